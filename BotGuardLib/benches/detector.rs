@@ -0,0 +1,57 @@
+// `cargo bench`: times single and batch checks, pattern edits and a large pattern set.
+//
+// `cargo bench -- <filter>` only runs the benchmarks whose name matches the filter.
+// `cargo bench -- --save-baseline main` on one build and `cargo bench -- --baseline main` on
+// another reports the benchmarks that changed beyond criterion's noise threshold.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+
+use BotGuardLib::bench::{check_corpus, pattern_entries, Corpus};
+use BotGuardLib::BotDetector;
+
+const GOOGLEBOT: &str = "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)";
+const CHROME: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+
+fn single_check(c: &mut Criterion) {
+    let detector = BotDetector::default();
+    let mut group = c.benchmark_group("single check");
+    group.bench_function("bot", |b| b.iter(|| detector.check(GOOGLEBOT)));
+    group.bench_function("human", |b| b.iter(|| detector.check(CHROME)));
+    group.finish();
+}
+
+fn batch_check(c: &mut Criterion) {
+    let detector = BotDetector::default();
+    let mut group = c.benchmark_group("batch check");
+    for len in [100, 1000, 10_000] {
+        let corpus = Corpus::synthetic(len);
+        group.throughput(Throughput::Elements(len as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &corpus, |b, corpus| b.iter(|| check_corpus(&detector, corpus)));
+    }
+    group.finish();
+}
+
+fn recompile(c: &mut Criterion) {
+    let detector = BotDetector::default();
+    let mut group = c.benchmark_group("recompile");
+    group.bench_function("append", |b| b.iter_batched(|| detector.clone(), |mut detector| detector.append(&["^benchappended/"]), BatchSize::SmallInput));
+    group.bench_function("remove", |b| b.iter_batched(|| detector.clone(), |mut detector| detector.remove(&["googlebot"]), BatchSize::SmallInput));
+    group.finish();
+}
+
+fn large_pattern_set(c: &mut Criterion) {
+    let corpus = Corpus::synthetic(1000);
+    let mut group = c.benchmark_group("large pattern set");
+    group.sample_size(20);
+    for len in [1000, 5000] {
+        let entries = pattern_entries(len);
+        group.bench_with_input(BenchmarkId::new("compile", len), &entries, |b, entries| b.iter(|| BotDetector::new(entries)));
+        let detector = BotDetector::new(&entries);
+        group.throughput(Throughput::Elements(corpus.len() as u64));
+        group.bench_with_input(BenchmarkId::new("batch check 1000", len), &corpus, |b, corpus| b.iter(|| check_corpus(&detector, corpus)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, single_check, batch_check, recompile, large_pattern_set);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+// Builds the `node` feature of the crate as a native addon and copies it to `botguard.node`.
+//
+//   node build.mjs [--debug]
+
+import { execFileSync } from "node:child_process";
+import { copyFileSync } from "node:fs";
+import { fileURLToPath } from "node:url";
+
+const release = !process.argv.includes("--debug");
+const manifest = fileURLToPath(new URL("../../Cargo.toml", import.meta.url));
+const args = ["rustc", "--lib", "--crate-type", "cdylib", "--features", "node", "--manifest-path", manifest];
+execFileSync("cargo", release ? [...args, "--release"] : args, { stdio: "inherit" });
+
+const library = { darwin: "libBotGuardLib.dylib", win32: "BotGuardLib.dll" }[process.platform] ?? "libBotGuardLib.so";
+const target = new URL(`../../target/${release ? "release" : "debug"}/${library}`, import.meta.url);
+copyFileSync(target, new URL("./botguard.node", import.meta.url));
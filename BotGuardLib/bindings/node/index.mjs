@@ -0,0 +1,52 @@
+// BotGuardLib for Node.js: the same compiled patterns and verdicts as the Rust crate, loaded as
+// the native addon of the `node` feature. Run `npm run build` first.
+//
+//   import express from "express";
+//   import { createBotGuard, expressMiddleware } from "botguard";
+//
+//   const guard = createBotGuard();
+//   const app = express();
+//   app.use(expressMiddleware(guard));
+//
+//   import Fastify from "fastify";
+//   const fastify = Fastify();
+//   fastify.addHook("onRequest", fastifyHook(guard, { block: ["bot", "suspicious"] }));
+
+import { createRequire } from "node:module";
+
+const { BotDetector } = createRequire(import.meta.url)("./botguard.node");
+
+export { BotDetector };
+
+/** A detector with the bundled patterns, or with newline-delimited `patterns` instead. */
+export function createBotGuard({ patterns } = {}) {
+  return new BotDetector(patterns);
+}
+
+function judge(guard, userAgent) {
+  const verdict = guard.check(userAgent ?? "");
+  return { verdict, category: verdict === "bot" ? guard.classify(userAgent) : null };
+}
+
+/** Express/Connect middleware answering 403 for the given verdicts and setting `req.botguard`. */
+export function expressMiddleware(guard, { block = ["bot"], status = 403 } = {}) {
+  return (req, res, next) => {
+    req.botguard = judge(guard, req.headers["user-agent"]);
+    if (block.includes(req.botguard.verdict)) {
+      res.statusCode = status;
+      res.end("Forbidden");
+      return;
+    }
+    next();
+  };
+}
+
+/** Fastify `onRequest` hook answering 403 for the given verdicts and setting `request.botguard`. */
+export function fastifyHook(guard, { block = ["bot"], status = 403 } = {}) {
+  return async (request, reply) => {
+    request.botguard = judge(guard, request.headers["user-agent"]);
+    if (block.includes(request.botguard.verdict)) {
+      return reply.code(status).send("Forbidden");
+    }
+  };
+}
@@ -0,0 +1,13 @@
+from typing import Iterable, List, Optional
+
+class BotDetector:
+    """Same patterns and verdicts as the Rust ``BotDetector``, bundled defaults unless given."""
+
+    def __init__(self, patterns: Optional[str] = None) -> None: ...
+    def check(self, user_agent: str) -> str: ...
+    def check_bot(self, user_agent: str) -> bool: ...
+    def classify(self, user_agent: str) -> Optional[str]: ...
+    def check_many(self, user_agents: Iterable[object]) -> List[Optional[str]]: ...
+    def classify_many(self, user_agents: Iterable[object]) -> List[Optional[str]]: ...
+    def append(self, patterns: str) -> None: ...
+    def __len__(self) -> int: ...
@@ -0,0 +1,67 @@
+// Thin wrapper around the raw exports of BotGuardLib.wasm, see src/wasm.rs.
+//
+// Cloudflare Workers:
+//
+//   import wasm from "./BotGuardLib.wasm";
+//   import { BotGuard } from "./botguard.mjs";
+//
+//   const guard = await BotGuard.instantiate(wasm);
+//   export default {
+//     async fetch(request) {
+//       if (guard.check(request.headers.get("user-agent") ?? "") === "bot") {
+//         return new Response("Forbidden", { status: 403 });
+//       }
+//       return fetch(request);
+//     },
+//   };
+
+const VERDICTS = ["human", "suspicious", "bot"];
+const encoder = new TextEncoder();
+const decoder = new TextDecoder();
+
+export class BotGuard {
+  /** Accepts a compiled `WebAssembly.Module` or the bytes of the module. */
+  static async instantiate(module) {
+    const result = await WebAssembly.instantiate(module, {});
+    return new BotGuard(result.instance ?? result);
+  }
+
+  constructor(instance) {
+    this.exports = instance.exports;
+  }
+
+  /** Replaces the patterns, throws if one of them is invalid. */
+  load(patterns) {
+    if (this.#withString(patterns, (ptr, len) => this.exports.botguard_wasm_load(ptr, len)) !== 0) {
+      throw new Error("invalid bot patterns");
+    }
+  }
+
+  /** Returns "human", "suspicious" or "bot". */
+  check(userAgent) {
+    return VERDICTS[this.#withString(userAgent, (ptr, len) => this.exports.botguard_wasm_check(ptr, len))];
+  }
+
+  /** Returns the pattern group of a bot, or null. */
+  classify(userAgent) {
+    const out = this.exports.botguard_wasm_alloc(64);
+    try {
+      const len = this.#withString(userAgent, (ptr, len) => this.exports.botguard_wasm_classify(ptr, len, out, 64));
+      return len < 0 ? null : decoder.decode(new Uint8Array(this.exports.memory.buffer, out, len));
+    } finally {
+      this.exports.botguard_wasm_dealloc(out, 64);
+    }
+  }
+
+  #withString(text, f) {
+    const bytes = encoder.encode(text);
+    const len = Math.max(bytes.length, 1);
+    const ptr = this.exports.botguard_wasm_alloc(len);
+    try {
+      new Uint8Array(this.exports.memory.buffer, ptr, bytes.length).set(bytes);
+      return f(ptr, bytes.length);
+    } finally {
+      this.exports.botguard_wasm_dealloc(ptr, len);
+    }
+  }
+}
@@ -0,0 +1,6 @@
+// Only the Node.js addon needs a build step, the link arguments napi-rs sets for the platform.
+
+fn main() {
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
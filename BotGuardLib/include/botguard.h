@@ -0,0 +1,57 @@
+/*
+ * C interface of BotGuardLib, built with
+ * `cargo rustc --release --lib --crate-type cdylib --features ffi` as
+ * libBotGuardLib.so / .dylib / BotGuardLib.dll. See src/ffi.rs.
+ *
+ * Ownership: botguard_new returns a handle owned by the caller until it is passed to
+ * botguard_free. String arguments are borrowed for the duration of the call only.
+ *
+ * Threads: a handle may be shared by any number of threads. Checks run concurrently, appends
+ * wait for running checks. Only botguard_free must not race with other calls on the handle.
+ */
+
+#ifndef BOTGUARD_H
+#define BOTGUARD_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct BotGuard BotGuard;
+
+#define BOTGUARD_HUMAN 0
+#define BOTGUARD_SUSPICIOUS 1
+#define BOTGUARD_BOT 2
+#define BOTGUARD_ERROR (-1)
+
+/* Compiles newline-delimited patterns, or the bundled defaults if patterns is NULL.
+ * Returns NULL if a pattern is invalid. */
+BotGuard *botguard_new(const char *patterns);
+
+/* Returns BOTGUARD_HUMAN, BOTGUARD_SUSPICIOUS or BOTGUARD_BOT, and BOTGUARD_ERROR if an
+ * argument is NULL or the user-agent is not UTF-8. */
+int botguard_check(const BotGuard *guard, const char *user_agent);
+
+/* Checks count user-agents under a single lock, writing the botguard_check codes to out.
+ * Returns 0, or BOTGUARD_ERROR if guard, user_agents or out is NULL. */
+int botguard_check_batch(const BotGuard *guard, const char *const *user_agents, size_t count, int *out);
+
+/* Writes the pattern group of a bot to out as NUL-terminated string, cut to out_len bytes.
+ * Returns the full length of the name, 0 if the user-agent is no bot and BOTGUARD_ERROR if an
+ * argument is NULL or the user-agent is not UTF-8. */
+int botguard_classify(const BotGuard *guard, const char *user_agent, char *out, size_t out_len);
+
+/* Appends newline-delimited patterns. Returns 0, or BOTGUARD_ERROR if an argument is NULL or a
+ * pattern is invalid, nothing is appended then. */
+int botguard_append(const BotGuard *guard, const char *patterns);
+
+/* Releases the handle, NULL is ignored. */
+void botguard_free(BotGuard *guard);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BOTGUARD_H */
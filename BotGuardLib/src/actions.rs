@@ -0,0 +1,618 @@
+// Ready-made responses for the actions a policy decides on: a block page carrying a reference ID
+// support can look the decision up by, a 429 with `Retry-After`, and a tarpit keeping a bot's
+// connection busy for as long as it is willing to wait.
+//
+// Responses are plain status, headers and body, so every framework can send them. The tarpit and
+// the trickling body do not depend on an async runtime, their pauses are timed by one timer
+// thread shared by all of them. The trickling body is an `http_body::Body` and comes with the
+// `http-body` feature.
+
+use std::cmp::Ordering as Order;
+use std::collections::hash_map::RandomState;
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, Weak};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "http-body")]
+use bytes::{Buf, Bytes};
+#[cfg(feature = "http-body")]
+use http_body::{Body, Frame};
+
+use crate::policy::Action;
+use crate::request::RequestVerdict;
+
+/// Placeholder of the block page template replaced with the reference ID.
+pub const REFERENCE_PLACEHOLDER: &str = "{reference}";
+
+const DEFAULT_BLOCK_PAGE: &str = "<!doctype html><html><head><meta charset=\"utf-8\"><title>Access denied</title></head>\
+<body><h1>Access denied</h1><p>Your request looks automated and was blocked.</p>\
+<p>If you think this is a mistake, contact us with the reference <code>{reference}</code>.</p></body></html>";
+
+/// An HTTP response, independent of any framework.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &str, body: String) -> Self {
+        Response { status, headers: vec![("Content-Type".to_string(), content_type.to_string())], body }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The first value of a header, compared case-insensitively.
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    /// Writes the response as HTTP/1.1 with a `Content-Length` and `Connection: close`.
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "HTTP/1.1 {} {}\r\n", self.status, reason_phrase(self.status))?;
+        for (name, value) in &self.headers {
+            write!(out, "{}: {}\r\n", name, value)?;
+        }
+        write!(out, "Content-Length: {}\r\nConnection: close\r\n\r\n{}", self.body.len(), self.body)?;
+        out.flush()
+    }
+}
+
+/// The reason phrase of the statuses the crate answers with.
+pub(crate) fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        429 => "Too Many Requests",
+        _ => "Internal Server Error",
+    }
+}
+
+/// A new reference ID of 16 hex digits, unique within the process and unlikely to repeat across
+/// processes.
+pub fn reference_id() -> String {
+    format!("{:016x}", random_u64())
+}
+
+/// A random number, unique within the process, for reference IDs and nonces.
+pub(crate) fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos());
+    hasher.finish()
+}
+
+/// The 403 page served to blocked requests.
+///
+/// ```
+/// use BotGuardLib::actions::BlockPage;
+///
+/// let page = BlockPage::new().template("<p>Blocked, reference {reference}</p>");
+/// let response = page.response("a1b2<c3>");
+/// assert_eq!(response.status, 403);
+/// assert_eq!(response.body, "<p>Blocked, reference a1b2&lt;c3&gt;</p>");
+/// assert_eq!(response.header_value("cache-control"), Some("no-store"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockPage {
+    template: String,
+}
+
+impl Default for BlockPage {
+    fn default() -> Self {
+        BlockPage { template: DEFAULT_BLOCK_PAGE.to_string() }
+    }
+}
+
+impl BlockPage {
+    /// A minimal page asking to quote the reference when contacting the site.
+    pub fn new() -> Self {
+        BlockPage::default()
+    }
+
+    /// Replaces the HTML, every [`REFERENCE_PLACEHOLDER`] is replaced with the escaped reference.
+    pub fn template(mut self, html: &str) -> Self {
+        self.template = html.to_string();
+        self
+    }
+
+    pub fn render(&self, reference: &str) -> String {
+        self.template.replace(REFERENCE_PLACEHOLDER, &escape_html(reference))
+    }
+
+    /// The 403 response, the reference is also sent in an `X-Reference-Id` header for logs.
+    pub fn response(&self, reference: &str) -> Response {
+        Response::new(403, "text/html; charset=utf-8", self.render(reference))
+            .header("Cache-Control", "no-store")
+            .header("X-Reference-Id", reference)
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A 429 response telling the client to retry after the given time, in whole seconds rounded up.
+///
+/// ```
+/// use std::time::Duration;
+/// use BotGuardLib::actions::too_many_requests;
+///
+/// let response = too_many_requests(Duration::from_millis(1500));
+/// assert_eq!((response.status, response.header_value("Retry-After")), (429, Some("2")));
+/// ```
+pub fn too_many_requests(retry_after: Duration) -> Response {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    Response::new(429, "text/plain; charset=utf-8", "Too many requests, slow down.\n".to_string())
+        .header("Retry-After", &seconds.max(1).to_string())
+        .header("Cache-Control", "no-store")
+}
+
+/// The response for the action of an evaluated request: the block page for [`Action::Block`] and
+/// a 429 for [`Action::RateLimit`], `None` for actions letting the request through or left to a
+/// [`crate::challenge::ChallengeFlow`]. For [`Action::Tarpit`] the application's own response is
+/// sent, its body wrapped in a `TrickleBody` of the `http-body` feature, and [`Action::Decoy`] is answered by
+/// [`crate::decoy::respond`].
+///
+/// The block page shows the signed [`RequestVerdict::reference`] when the guard issues
+/// references, `reference` otherwise.
+///
+/// ```
+/// use BotGuardLib::actions::{respond, BlockPage};
+/// use BotGuardLib::policy::{Action, PolicyEngine};
+/// use BotGuardLib::request::{BotGuard, RequestSnapshot};
+/// use BotGuardLib::BotDetector;
+///
+/// let guard = BotGuard::new(BotDetector::new("^sqlmap/")).policy(PolicyEngine::new(Action::Block));
+/// let verdict = guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("sqlmap/1.7"));
+/// let response = respond(&verdict, &BlockPage::new(), "ref-42").unwrap();
+/// assert!(response.body.contains("ref-42"));
+/// ```
+pub fn respond(verdict: &RequestVerdict, page: &BlockPage, reference: &str) -> Option<Response> {
+    match &verdict.action {
+        Action::Block => Some(page.response(verdict.reference.as_deref().unwrap_or(reference))),
+        Action::RateLimit { requests, per } => Some(too_many_requests(*per / (*requests).max(1))),
+        Action::Allow | Action::Challenge | Action::Tag(_) | Action::Tarpit { .. } | Action::Decoy => None,
+    }
+}
+
+/// Answers slowly: a few bytes at a time with pauses in between, so a bot waiting for the whole
+/// response ties up a connection for minutes while the server spends almost nothing on it.
+///
+/// Only worth it against clients already known to be bots, a browser would just hang.
+///
+/// ```
+/// use std::time::Duration;
+/// use BotGuardLib::actions::Tarpit;
+///
+/// let tarpit = Tarpit::new().chunk(4).interval(Duration::from_millis(1)).total(10);
+/// let mut received = Vec::new();
+/// tarpit.drip_blocking(&mut received).unwrap();
+/// assert_eq!(received.len(), 10);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tarpit {
+    chunk: usize,
+    interval: Duration,
+    total: usize,
+}
+
+impl Default for Tarpit {
+    fn default() -> Self {
+        Tarpit { chunk: 1, interval: Duration::from_secs(1), total: 600 }
+    }
+}
+
+impl Tarpit {
+    /// One byte per second for ten minutes.
+    pub fn new() -> Self {
+        Tarpit::default()
+    }
+
+    /// Bytes sent at a time.
+    pub fn chunk(mut self, bytes: usize) -> Self {
+        self.chunk = bytes.max(1);
+        self
+    }
+
+    /// Pause between two chunks.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Bytes sent in all before giving up.
+    pub fn total(mut self, bytes: usize) -> Self {
+        self.total = bytes;
+        self
+    }
+
+    /// Filler for the chunk after `sent` bytes, spaces as in a page still being generated.
+    fn filler(&self, sent: usize) -> Vec<u8> {
+        vec![b' '; self.chunk.min(self.total - sent)]
+    }
+
+    /// Passes the chunks to `send`, e.g. a closure writing to the framework's response body, and
+    /// waits between them. Stops at the first error, which usually means the client gave up, and
+    /// returns the number of bytes sent.
+    pub async fn drip<F, Fut>(&self, mut send: F) -> io::Result<usize>
+    where
+        F: FnMut(Vec<u8>) -> Fut,
+        Fut: Future<Output = io::Result<()>>,
+    {
+        let mut sent = 0;
+        while sent < self.total {
+            if sent > 0 {
+                Sleep::new(self.interval).await;
+            }
+            let chunk = self.filler(sent);
+            let len = chunk.len();
+            send(chunk).await?;
+            sent += len;
+        }
+        Ok(sent)
+    }
+
+    /// Same as [`Tarpit::drip`] for a blocking writer, e.g. in a thread per connection.
+    pub fn drip_blocking<W: Write>(&self, mut out: W) -> io::Result<usize> {
+        let mut sent = 0;
+        while sent < self.total {
+            if sent > 0 {
+                thread::sleep(self.interval);
+            }
+            let chunk = self.filler(sent);
+            out.write_all(&chunk)?;
+            out.flush()?;
+            sent += chunk.len();
+        }
+        Ok(sent)
+    }
+}
+
+/// Sends the response of a body at a trickle, for [`Action::Tarpit`]: scrapers get the real
+/// page, only at `bytes_per_second`, and every pause is randomly longer or shorter so the rate
+/// cannot be told from a slow server.
+///
+/// Wraps any [`http_body::Body`], e.g. a hyper or axum response body, and is one itself, so it
+/// is handed back to the framework in place of the original body. Trailers are passed on after
+/// the last chunk.
+///
+/// ```
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll, Waker};
+/// use http_body::Body;
+/// use BotGuardLib::actions::TrickleBody;
+///
+/// let mut body = TrickleBody::new("<html>catalog</html>".to_string(), 8);
+/// let mut cx = Context::from_waker(Waker::noop());
+/// let Poll::Ready(Some(Ok(first))) = Pin::new(&mut body).poll_frame(&mut cx) else { panic!() };
+/// assert_eq!(first.into_data().unwrap(), "<html>ca");
+/// // the next eight bytes come in about a second
+/// assert!(Pin::new(&mut body).poll_frame(&mut cx).is_pending());
+/// ```
+#[cfg(feature = "http-body")]
+#[derive(Debug)]
+pub struct TrickleBody<B> {
+    inner: B,
+    buffered: Bytes,
+    chunk: usize,
+    tick: Duration,
+    jitter: f32,
+    pause: Option<Sleep>,
+    rng: u64,
+    finished: bool,
+}
+
+#[cfg(feature = "http-body")]
+impl<B: Body + Unpin> TrickleBody<B> {
+    /// Sends a chunk every second with a jitter of half the pause.
+    pub fn new(inner: B, bytes_per_second: u32) -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(bytes_per_second);
+        TrickleBody {
+            inner,
+            buffered: Bytes::new(),
+            chunk: bytes_per_second.max(1) as usize,
+            tick: Duration::from_secs(1),
+            jitter: 0.5,
+            pause: None,
+            rng: hasher.finish() | 1,
+            finished: false,
+        }
+    }
+
+    /// Pause between two chunks, the chunks shrink with it to keep the rate.
+    pub fn tick(mut self, tick: Duration) -> Self {
+        let rate = self.chunk as f64 / self.tick.as_secs_f64();
+        self.chunk = ((rate * tick.as_secs_f64()).ceil() as usize).max(1);
+        self.tick = tick;
+        self
+    }
+
+    /// Share of the pause randomly added or taken away, between `0.0` and `1.0`.
+    pub fn jitter(mut self, jitter: f32) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// The next data chunk, for frameworks taking a stream of chunks rather than a [`Body`];
+    /// trailers are skipped.
+    pub async fn next_chunk(&mut self) -> Option<Result<Bytes, B::Error>> {
+        loop {
+            match std::future::poll_fn(|cx| Pin::new(&mut *self).poll_frame(cx)).await? {
+                Ok(frame) => match frame.into_data() {
+                    Ok(data) => return Some(Ok(data)),
+                    Err(_) => continue,
+                },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    fn next_pause(&mut self) -> Duration {
+        // xorshift, good enough to blur the timing
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let spread = (self.rng >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0;
+        self.tick.mul_f64((1.0 + f64::from(self.jitter) * spread).max(0.0))
+    }
+}
+
+#[cfg(feature = "http-body")]
+impl<B: Body + Unpin> Body for TrickleBody<B> {
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let this = self.get_mut();
+        if let Some(pause) = &mut this.pause {
+            if Pin::new(pause).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.pause = None;
+        }
+        while this.buffered.is_empty() && !this.finished {
+            match Pin::new(&mut this.inner).poll_frame(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(frame))) => match frame.map_data(|mut data| data.copy_to_bytes(data.remaining())).into_data() {
+                    Ok(data) => this.buffered = data,
+                    // trailers come after the data, nothing is buffered
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => this.finished = true,
+            }
+        }
+        if this.buffered.is_empty() {
+            return Poll::Ready(None);
+        }
+        let chunk = this.buffered.split_to(this.chunk.min(this.buffered.len()));
+        this.pause = Some(Sleep::new(this.next_pause()));
+        Poll::Ready(Some(Ok(Frame::data(chunk))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.finished && self.buffered.is_empty()
+    }
+}
+
+/// A timer future woken by the [`Timer`] at the deadline.
+#[derive(Debug)]
+struct Sleep {
+    deadline: Instant,
+    waker: Option<Arc<Mutex<Waker>>>,
+}
+
+impl Sleep {
+    fn new(duration: Duration) -> Self {
+        Sleep { deadline: Instant::now() + duration, waker: None }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        match &self.waker {
+            Some(waker) => waker.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone_from(cx.waker()),
+            None => {
+                // without a timer thread the pause is skipped rather than never ending
+                let Some(timer) = Timer::shared() else { return Poll::Ready(()) };
+                let waker = Arc::new(Mutex::new(cx.waker().clone()));
+                timer.schedule(self.deadline, &waker);
+                self.waker = Some(waker);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// One thread waking every pending [`Sleep`] at its deadline, earliest first.
+struct Timer {
+    pending: Mutex<BinaryHeap<Pending>>,
+    /// Signalled when a pause is due before the one the thread waits for.
+    earlier: Condvar,
+}
+
+/// A scheduled wake-up, dropped pauses are skipped.
+struct Pending {
+    deadline: Instant,
+    waker: Weak<Mutex<Waker>>,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Order> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    /// Reversed, the heap pops the earliest deadline.
+    fn cmp(&self, other: &Self) -> Order {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+impl Timer {
+    /// The timer of the process, started on first use. `None` if its thread cannot be spawned.
+    fn shared() -> Option<&'static Timer> {
+        static TIMER: OnceLock<Option<Arc<Timer>>> = OnceLock::new();
+        TIMER
+            .get_or_init(|| {
+                let timer = Arc::new(Timer { pending: Mutex::new(BinaryHeap::new()), earlier: Condvar::new() });
+                let worker = Arc::clone(&timer);
+                thread::Builder::new().name("botguard-timer".to_string()).spawn(move || worker.run()).ok().map(|_| timer)
+            })
+            .as_deref()
+    }
+
+    fn schedule(&self, deadline: Instant, waker: &Arc<Mutex<Waker>>) {
+        let mut pending = self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let earliest = pending.peek().is_none_or(|next| deadline < next.deadline);
+        pending.push(Pending { deadline, waker: Arc::downgrade(waker) });
+        if earliest {
+            self.earlier.notify_one();
+        }
+    }
+
+    fn run(&self) {
+        let mut pending = self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        loop {
+            let now = Instant::now();
+            let mut due = Vec::new();
+            while pending.peek().is_some_and(|next| next.deadline <= now) {
+                due.extend(pending.pop().and_then(|expired| expired.waker.upgrade()));
+            }
+            if !due.is_empty() {
+                // woken without the lock, a waker polling right away schedules its next pause
+                drop(pending);
+                for waker in due {
+                    waker.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).wake_by_ref();
+                }
+                pending = self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                continue;
+            }
+            pending = match pending.peek().map(|next| next.deadline - now) {
+                Some(wait) => self.earlier.wait_timeout(pending, wait).unwrap_or_else(|poisoned| poisoned.into_inner()).0,
+                None => self.earlier.wait(pending).unwrap_or_else(|poisoned| poisoned.into_inner()),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::block_on;
+
+    #[test]
+    fn builds_responses() {
+        let page = BlockPage::new().response(&reference_id());
+        let reference = page.header_value("x-reference-id").unwrap().to_string();
+        assert_eq!(reference.len(), 16);
+        assert!(page.body.contains(&reference));
+        assert_ne!(reference_id(), reference_id());
+
+        let mut raw = Vec::new();
+        too_many_requests(Duration::ZERO).write_to(&mut raw).unwrap();
+        let raw = String::from_utf8(raw).unwrap();
+        assert!(raw.starts_with("HTTP/1.1 429 Too Many Requests\r\nContent-Type: text/plain; charset=utf-8\r\nRetry-After: 1\r\n"), "{}", raw);
+        assert!(raw.ends_with("Connection: close\r\n\r\nToo many requests, slow down.\n"));
+    }
+
+    #[test]
+    fn drips_until_the_client_gives_up() {
+        let tarpit = Tarpit::new().chunk(3).interval(Duration::from_millis(5)).total(8);
+        let started = Instant::now();
+        let mut chunks = Vec::new();
+        let sent = block_on(tarpit.drip(|chunk| {
+            chunks.push(chunk.len());
+            async { Ok(()) }
+        }));
+        assert_eq!((sent.unwrap(), chunks), (8, vec![3, 3, 2]));
+        assert!(started.elapsed() >= Duration::from_millis(10));
+
+        let mut calls = 0;
+        let gone = block_on(tarpit.drip(|_| {
+            calls += 1;
+            async move { if calls == 2 { Err(io::Error::from(io::ErrorKind::BrokenPipe)) } else { Ok(()) } }
+        }));
+        assert_eq!(gone.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn times_concurrent_pauses_on_one_thread() {
+        let started = Instant::now();
+        // a dropped pause is skipped by the timer
+        let mut dropped = Box::pin(Sleep::new(Duration::from_millis(5)));
+        assert!(dropped.as_mut().poll(&mut Context::from_waker(Waker::noop())).is_pending());
+        drop(dropped);
+        let sleepers = (1..=8u64).rev().map(|i| thread::spawn(move || block_on(Sleep::new(Duration::from_millis(i * 5))))).collect::<Vec<_>>();
+        for sleeper in sleepers {
+            sleeper.join().unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[cfg(feature = "http-body")]
+    #[test]
+    fn trickles_the_inner_body() {
+        let mut body = TrickleBody::new("0123456789".to_string(), 800).tick(Duration::from_millis(5)).jitter(0.0);
+        let started = Instant::now();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = block_on(body.next_chunk()) {
+            chunks.push(String::from_utf8(chunk.unwrap().to_vec()).unwrap());
+        }
+        assert_eq!(chunks, ["0123", "4567", "89"]);
+        assert!(started.elapsed() >= Duration::from_millis(10));
+        assert!(body.is_end_stream());
+
+        let mut jittered = TrickleBody::new(String::new(), 1).jitter(1.0);
+        assert!((0..100).map(|_| jittered.next_pause()).all(|pause| pause <= Duration::from_secs(2)));
+        assert!(block_on(jittered.next_chunk()).is_none());
+    }
+}
@@ -0,0 +1,345 @@
+// Runtime administration of a running guard: reloading the configuration file, flipping the
+// policy into shadow mode, overriding the verdict of a client and reading statistics, without
+// restarting the service.
+//
+// `AdminHandle` works on the guard shared with the request handlers, a reload compiles the new
+// patterns before taking the write lock so requests only wait for the swap. The same operations
+// are offered as HTTP routes through `AdminHandle::handle`, for the application's router or the
+// `server` feature's `Server::admin`. A process wanting to reload on `SIGHUP` calls
+// `AdminHandle::reload` from its signal handling thread.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+use crate::actions::Response;
+use crate::config::BotGuardConfig;
+use crate::request::BotGuard;
+use crate::{crypto, json, BotGuardError, Verdict};
+
+/// How long an override set through [`AdminHandle::handle`] lasts unless the request says.
+const OVERRIDE_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// Outcome of [`AdminHandle::reload`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReloadSummary {
+    /// Version of the reloaded patterns.
+    pub version: String,
+    pub patterns: usize,
+    pub rules: usize,
+}
+
+/// See [`AdminHandle::stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminStats {
+    pub version: String,
+    pub patterns: usize,
+    pub rules: usize,
+    pub shadow: bool,
+    /// Entries of the reputation cache, `None` without one.
+    pub reputation_entries: Option<usize>,
+    pub reloads: u64,
+    pub reload_failures: u64,
+}
+
+impl AdminStats {
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"version\":");
+        json::push_str(&mut out, &self.version);
+        out.push_str(&format!(
+            ",\"patterns\":{},\"rules\":{},\"shadow\":{},\"reputation_entries\":{},\"reloads\":{},\"reload_failures\":{}}}",
+            self.patterns,
+            self.rules,
+            self.shadow,
+            self.reputation_entries.map_or("null".to_string(), |entries| entries.to_string()),
+            self.reloads,
+            self.reload_failures
+        ));
+        out
+    }
+}
+
+/// Administers a guard shared with the request handlers.
+///
+/// ```
+/// use std::sync::{Arc, RwLock};
+/// use BotGuardLib::admin::AdminHandle;
+/// use BotGuardLib::request::{BotGuard, RequestSnapshot};
+/// use BotGuardLib::BotDetector;
+///
+/// let guard = Arc::new(RwLock::new(BotGuard::new(BotDetector::new("^curl/"))));
+/// let admin = AdminHandle::new(Arc::clone(&guard)).token("a long random admin token");
+/// assert!(!admin.set_shadow(true));
+/// assert!(admin.stats().shadow);
+///
+/// let response = admin.handle("GET", "/admin/stats", Some("Bearer a long random admin token"), b"");
+/// assert_eq!(response.status, 200);
+/// assert!(response.body.contains("\"shadow\":true"));
+/// assert_eq!(admin.handle("GET", "/admin/stats", None, b"").status, 401);
+/// ```
+#[derive(Clone)]
+pub struct AdminHandle {
+    guard: Arc<RwLock<BotGuard>>,
+    config: Option<PathBuf>,
+    token: Option<String>,
+    reloads: Arc<AtomicU64>,
+    reload_failures: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for AdminHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminHandle").field("config", &self.config).field("token", &self.token.as_ref().map(|_| "..")).finish_non_exhaustive()
+    }
+}
+
+impl AdminHandle {
+    /// Without a configuration file and without a token, the HTTP routes are then open to
+    /// anyone who can reach them.
+    pub fn new(guard: Arc<RwLock<BotGuard>>) -> Self {
+        AdminHandle { guard, config: None, token: None, reloads: Arc::default(), reload_failures: Arc::default() }
+    }
+
+    /// The configuration file [`AdminHandle::reload`] reads.
+    pub fn config_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config = Some(path.into());
+        self
+    }
+
+    /// Requires `Authorization: Bearer <token>` on the HTTP routes.
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Checks the `Authorization` header against the token, the error is the 401 to answer.
+    pub(crate) fn authorize(&self, authorization: Option<&str>) -> Result<(), Response> {
+        let Some(token) = &self.token else { return Ok(()) };
+        let presented = authorization.and_then(|value| value.strip_prefix("Bearer ")).unwrap_or("");
+        if !crypto::mac_eq(presented.trim().as_bytes(), token.as_bytes()) {
+            return Err(error(401, "missing or wrong admin token").header("WWW-Authenticate", "Bearer"));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    pub fn guard(&self) -> &Arc<RwLock<BotGuard>> {
+        &self.guard
+    }
+
+    /// Re-reads the configuration file and replaces the patterns, allowlist, route policy, signal
+    /// gates, pipeline and exemptions of the guard. Everything else, like rate limits and feeds, is kept. On error the guard is
+    /// left unchanged.
+    pub fn reload(&self) -> Result<ReloadSummary, BotGuardError> {
+        let result = self.try_reload();
+        match &result {
+            Ok(_) => self.reloads.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.reload_failures.fetch_add(1, Ordering::Relaxed),
+        };
+        result
+    }
+
+    fn try_reload(&self) -> Result<ReloadSummary, BotGuardError> {
+        let path = self.config.as_ref().ok_or_else(|| BotGuardError::InvalidConfig { line: None, reason: "no configuration file set".to_string() })?;
+        let config = BotGuardConfig::from_path(path)?;
+        // compiled before locking, requests only wait for the swap
+        let detector = config.detector()?;
+        let mut guard = self.write();
+        guard.detector_mut().install(detector);
+        guard.set_allowlist(config.allowlist);
+        guard.set_policy(config.policy);
+        guard.set_sampling(config.sampling);
+        guard.set_pipeline(config.pipeline);
+        guard.set_exemptions(config.exemptions);
+        Ok(ReloadSummary { version: guard.detector().current_version().version.clone(), patterns: guard.detector().len(), rules: guard.policy_engine().rules().len() })
+    }
+
+    /// Switches shadow mode of the route policy, see [`crate::policy::PolicyEngine::shadow`].
+    /// Returns whether it was on before.
+    pub fn set_shadow(&self, shadow: bool) -> bool {
+        let mut guard = self.write();
+        let previous = guard.policy_engine().is_shadow();
+        let policy = guard.policy_engine().clone().shadow(shadow);
+        guard.set_policy(policy);
+        previous
+    }
+
+    /// Sets the verdict of a client, see [`crate::state::ClientOverrides`], `None` removes its
+    /// override. Fails with [`BotGuardError::InvalidConfig`] if the guard has no overrides.
+    pub fn set_override(&self, client: &str, verdict: Option<Verdict>, ttl: Duration) -> Result<(), BotGuardError> {
+        let guard = self.read();
+        let overrides = guard.client_overrides().ok_or_else(|| BotGuardError::InvalidConfig { line: None, reason: "the guard has no client overrides".to_string() })?;
+        match verdict {
+            Some(Verdict::Bot) => overrides.mark_bot(client, ttl),
+            Some(_) => overrides.mark_human(client, ttl),
+            None => overrides.clear(client),
+        }
+    }
+
+    pub fn stats(&self) -> AdminStats {
+        let guard = self.read();
+        AdminStats {
+            version: guard.detector().current_version().version.clone(),
+            patterns: guard.detector().len(),
+            rules: guard.policy_engine().rules().len(),
+            shadow: guard.policy_engine().is_shadow(),
+            reputation_entries: guard.reputation_cache().map(|cache| cache.len()),
+            reloads: self.reloads.load(Ordering::Relaxed),
+            reload_failures: self.reload_failures.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Answers the admin routes, `authorization` is the value of the request's header:
+    ///
+    /// - `GET /admin/stats`: the [`AdminStats`] as JSON
+    /// - `POST /admin/reload`: reloads the configuration, answers the [`ReloadSummary`]
+    /// - `POST /admin/shadow` with `{"enabled": true}`: switches shadow mode
+    /// - `POST /admin/override` with `{"client": "203.0.113.7", "verdict": "human", "ttl_seconds": 3600}`:
+    ///   overrides the verdict of a client for a day unless `ttl_seconds` is given, `"verdict": null`
+    ///   removes the override
+    pub fn handle(&self, method: &str, path: &str, authorization: Option<&str>, body: &[u8]) -> Response {
+        if let Err(response) = self.authorize(authorization) {
+            return response;
+        }
+        match (method, path) {
+            ("GET", "/admin/stats") => Response::new(200, "application/json", self.stats().to_json()),
+            ("POST", "/admin/reload") => match self.reload() {
+                Ok(summary) => {
+                    let mut out = String::from("{\"version\":");
+                    json::push_str(&mut out, &summary.version);
+                    out.push_str(&format!(",\"patterns\":{},\"rules\":{}}}", summary.patterns, summary.rules));
+                    Response::new(200, "application/json", out)
+                }
+                Err(e @ BotGuardError::InvalidConfig { line: None, .. }) if self.config.is_none() => error(409, &e.to_string()),
+                Err(e) => error(500, &e.to_string()),
+            },
+            ("POST", "/admin/shadow") => {
+                let enabled = std::str::from_utf8(body).ok().and_then(|body| json::parse(body).ok()).and_then(|request| request.get("enabled").and_then(json::Value::as_bool));
+                match enabled {
+                    Some(enabled) => {
+                        let previous = self.set_shadow(enabled);
+                        Response::new(200, "application/json", format!("{{\"shadow\":{},\"previous\":{}}}", enabled, previous))
+                    }
+                    None => error(400, "expected {\"enabled\": true|false}"),
+                }
+            }
+            ("POST", "/admin/override") => {
+                let Some(request) = std::str::from_utf8(body).ok().and_then(|body| json::parse(body).ok()) else {
+                    return error(400, "expected a JSON object");
+                };
+                let verdict = match request.get("verdict") {
+                    Some(json::Value::Null) => None,
+                    Some(verdict) => match verdict.as_str() {
+                        Some("human") => Some(Verdict::Human),
+                        Some("bot") => Some(Verdict::Bot),
+                        _ => return error(400, "verdict must be \"human\", \"bot\" or null"),
+                    },
+                    None => return error(400, "missing verdict"),
+                };
+                let Some(client) = request.get("client").and_then(json::Value::as_str) else {
+                    return error(400, "missing client");
+                };
+                let ttl = match request.get("ttl_seconds") {
+                    None => OVERRIDE_TTL,
+                    Some(ttl) => match ttl.as_f64().filter(|ttl| *ttl > 0.0) {
+                        Some(ttl) => Duration::from_secs_f64(ttl),
+                        None => return error(400, "ttl_seconds must be a positive number"),
+                    },
+                };
+                match self.set_override(client, verdict, ttl) {
+                    Ok(()) => Response::new(200, "application/json", "{\"ok\":true}".to_string()),
+                    Err(e @ BotGuardError::InvalidConfig { .. }) => error(409, &e.to_string()),
+                    Err(e) => error(500, &e.to_string()),
+                }
+            }
+            (_, "/admin/stats" | "/admin/reload" | "/admin/shadow" | "/admin/override") => error(405, "method not allowed"),
+            _ => error(404, "not found"),
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, BotGuard> {
+        self.guard.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, BotGuard> {
+        self.guard.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn error(status: u16, reason: &str) -> Response {
+    let mut out = String::from("{\"error\":");
+    json::push_str(&mut out, reason);
+    out.push('}');
+    Response::new(status, "application/json", out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{Action, PolicyEngine};
+    use crate::request::RequestSnapshot;
+    use crate::{BotDetector, Verdict};
+
+    #[test]
+    fn reloads_the_configuration() {
+        let path = std::env::temp_dir().join(format!("botguard-admin-{}.toml", std::process::id()));
+        std::fs::write(&path, "[detector]\ndefault_patterns = false\n\n[groups]\ntools = [\"^curl/\"]\n\n[policy]\ndefault = \"block\"\n").unwrap();
+        let guard = Arc::new(RwLock::new(BotGuard::new(BotDetector::new("^wget/"))));
+        let admin = AdminHandle::new(Arc::clone(&guard));
+        assert_eq!(admin.handle("POST", "/admin/reload", None, b"").status, 409);
+
+        let admin = admin.config_path(&path);
+        let summary = admin.reload().unwrap();
+        assert_eq!((summary.patterns, summary.rules), (1, 0));
+        let curl = RequestSnapshot::new("GET", "/").user_agent("curl/8.0");
+        let evaluated = guard.read().unwrap().evaluate(&curl);
+        assert_eq!((evaluated.verdict, evaluated.action), (Verdict::Bot, Action::Block));
+
+        std::fs::write(&path, "[detector]\ndefault_patterns = false\n\n[groups]\ntools = [\"(\"]\n").unwrap();
+        assert_eq!(admin.handle("POST", "/admin/reload", None, b"").status, 500);
+        assert_eq!(guard.read().unwrap().evaluate(&curl).verdict, Verdict::Bot);
+        let stats = admin.stats();
+        assert_eq!((stats.reloads, stats.reload_failures, stats.reputation_entries), (1, 2, None));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn overrides_client_verdicts() {
+        use crate::state::{ClientOverrides, MemoryStore};
+
+        let guard = BotGuard::new(BotDetector::new("^curl/")).policy(PolicyEngine::new(Action::Block)).overrides(ClientOverrides::new(Arc::new(MemoryStore::new())));
+        let guard = Arc::new(RwLock::new(guard));
+        let admin = AdminHandle::new(Arc::clone(&guard));
+        let customer = RequestSnapshot::new("GET", "/").user_agent("curl/8.0").client_ip("192.0.2.1".parse().unwrap());
+        let response = admin.handle("POST", "/admin/override", None, br#"{"client":"192.0.2.1","verdict":"human","ttl_seconds":60}"#);
+        assert_eq!((response.status, response.body.as_str()), (200, r#"{"ok":true}"#));
+        assert_eq!(guard.read().unwrap().evaluate(&customer).action, Action::Allow);
+
+        assert_eq!(admin.handle("POST", "/admin/override", None, br#"{"client":"192.0.2.1","verdict":null}"#).status, 200);
+        assert_eq!(guard.read().unwrap().evaluate(&customer).action, Action::Block);
+        assert_eq!(admin.handle("POST", "/admin/override", None, br#"{"client":"192.0.2.1","verdict":"maybe"}"#).status, 400);
+        assert_eq!(admin.handle("POST", "/admin/override", None, br#"{"verdict":"bot"}"#).status, 400);
+        assert_eq!(admin.handle("POST", "/admin/override", None, br#"{"client":"x","verdict":"bot","ttl_seconds":0}"#).status, 400);
+    }
+
+    #[test]
+    fn serves_admin_routes() {
+        let admin = AdminHandle::new(Arc::new(RwLock::new(BotGuard::new(BotDetector::new("^curl/"))))).token("secret");
+        let bearer = Some("Bearer secret");
+        let response = admin.handle("POST", "/admin/shadow", bearer, br#"{"enabled":true}"#);
+        assert_eq!(response.body, r#"{"shadow":true,"previous":false}"#);
+        assert_eq!(admin.handle("POST", "/admin/shadow", bearer, b"{}").status, 400);
+        assert_eq!(admin.handle("GET", "/admin/reload", bearer, b"").status, 405);
+        assert_eq!(admin.handle("GET", "/admin/nope", bearer, b"").status, 404);
+        let denied = admin.handle("GET", "/admin/stats", Some("Bearer secre"), b"");
+        assert_eq!((denied.status, denied.header_value("www-authenticate")), (401, Some("Bearer")));
+        assert_eq!(admin.handle("POST", "/admin/override", bearer, br#"{"client":"192.0.2.1","verdict":"human"}"#).status, 409);
+        let stats = admin.handle("GET", "/admin/stats", bearer, b"").body;
+        assert!(stats.starts_with("{\"version\":") && stats.ends_with(",\"patterns\":1,\"rules\":0,\"shadow\":true,\"reputation_entries\":null,\"reloads\":0,\"reload_failures\":0}"), "{}", stats);
+    }
+}
@@ -0,0 +1,272 @@
+// Tor exit nodes, open proxies and VPN endpoints, loaded from address feeds that are refreshed once
+// their time-to-live has passed. `request::BotGuard::anonymizers` adds the kind's weight to the
+// score of requests from a listed address.
+
+use std::net::IpAddr;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::http;
+use crate::ip::{IpNet, IpRangeSet};
+use crate::BotGuardError;
+
+/// Published list of the current Tor exit node addresses, one per line.
+pub const TOR_EXIT_LIST_URL: &str = "https://check.torproject.org/torbulkexitlist";
+
+/// The kind of anonymizing service an address belongs to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AnonymizerKind {
+    /// The address is on none of the feeds.
+    #[default]
+    None,
+    Vpn,
+    Proxy,
+    Tor,
+}
+
+impl AnonymizerKind {
+    /// Lowercase name, as in [`crate::request::Reason::to_json`].
+    pub fn name(self) -> &'static str {
+        match self {
+            AnonymizerKind::None => "none",
+            AnonymizerKind::Vpn => "vpn",
+            AnonymizerKind::Proxy => "proxy",
+            AnonymizerKind::Tor => "tor",
+        }
+    }
+
+    /// Default contribution of the kind to a composite bot score, between `0.0` and `1.0`.
+    pub fn weight(self) -> f32 {
+        match self {
+            AnonymizerKind::None => 0.0,
+            AnonymizerKind::Vpn => 0.2,
+            AnonymizerKind::Proxy => 0.5,
+            AnonymizerKind::Tor => 0.6,
+        }
+    }
+}
+
+/// An address feed, see [`AnonymizerLists::add_feed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnonymizerFeed {
+    pub name: String,
+    pub kind: AnonymizerKind,
+    /// `http://` or `https://` URL of the feed, `None` for feeds only filled with [`AnonymizerLists::load`].
+    pub url: Option<String>,
+    /// Age after which [`AnonymizerLists::refresh_expired`] downloads the feed again.
+    pub ttl: Duration,
+}
+
+impl AnonymizerFeed {
+    /// A feed refreshed every hour.
+    pub fn new(name: &str, kind: AnonymizerKind, url: Option<&str>) -> Self {
+        AnonymizerFeed {
+            name: name.to_string(),
+            kind,
+            url: url.map(str::to_string),
+            ttl: Duration::from_secs(3600),
+        }
+    }
+
+    /// The Tor exit node list of [`TOR_EXIT_LIST_URL`], refreshed every 30 minutes.
+    pub fn tor_exit_nodes() -> Self {
+        AnonymizerFeed {
+            ttl: Duration::from_secs(30 * 60),
+            ..AnonymizerFeed::new("tor", AnonymizerKind::Tor, Some(TOR_EXIT_LIST_URL))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LoadedFeed {
+    feed: AnonymizerFeed,
+    networks: IpRangeSet<()>,
+    loaded_at: Option<Instant>,
+}
+
+impl LoadedFeed {
+    fn is_expired(&self) -> bool {
+        self.loaded_at.is_none_or(|loaded_at| loaded_at.elapsed() >= self.feed.ttl)
+    }
+}
+
+/// Anonymizer feeds shared between threads, checked while they are refreshed.
+///
+/// Expired feeds keep answering with their last contents until a refresh succeeds.
+///
+/// ```
+/// use BotGuardLib::anonymizer::{AnonymizerFeed, AnonymizerKind, AnonymizerLists};
+///
+/// let lists = AnonymizerLists::new();
+/// lists.add_feed(AnonymizerFeed::new("corporate-vpn", AnonymizerKind::Vpn, None));
+/// lists.load("corporate-vpn", "# egress\n198.51.100.0/24\n").unwrap();
+///
+/// assert_eq!(lists.check_anonymizer("198.51.100.20".parse().unwrap()), AnonymizerKind::Vpn);
+/// assert_eq!(lists.check_anonymizer("192.0.2.1".parse().unwrap()), AnonymizerKind::None);
+/// ```
+#[derive(Debug)]
+pub struct AnonymizerLists {
+    feeds: RwLock<Vec<LoadedFeed>>,
+    timeout: Duration,
+}
+
+impl Default for AnonymizerLists {
+    fn default() -> Self {
+        AnonymizerLists { feeds: RwLock::new(Vec::new()), timeout: Duration::from_secs(30) }
+    }
+}
+
+impl AnonymizerLists {
+    pub fn new() -> Self {
+        AnonymizerLists::default()
+    }
+
+    /// Sets the download timeout of [`AnonymizerLists::refresh_expired`], 30 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Registers a feed, replacing the feed with the same name and its contents.
+    pub fn add_feed(&self, feed: AnonymizerFeed) {
+        let mut feeds = self.feeds.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        feeds.retain(|loaded| loaded.feed.name != feed.name);
+        feeds.push(LoadedFeed { feed, networks: IpRangeSet::new(), loaded_at: None });
+    }
+
+    /// Replaces the contents of a feed with a list of addresses or networks, one per line.
+    ///
+    /// `#` starts a comment, and the `ExitAddress` lines of the Tor exit list format are
+    /// understood as well. Returns the number of entries loaded.
+    pub fn load(&self, name: &str, list: &str) -> Result<usize, BotGuardError> {
+        let networks = parse_list(list).map_err(|reason| BotGuardError::InvalidFeed { feed: name.to_string(), reason })?;
+        let count = networks.len();
+        let mut feeds = self.feeds.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let loaded = feeds
+            .iter_mut()
+            .find(|loaded| loaded.feed.name == name)
+            .ok_or_else(|| BotGuardError::InvalidFeed { feed: name.to_string(), reason: "no feed with this name".to_string() })?;
+        loaded.networks = networks.into_iter().map(|net| (net, ())).collect();
+        loaded.loaded_at = Some(Instant::now());
+        Ok(count)
+    }
+
+    /// Downloads every feed with a URL whose time-to-live has passed.
+    ///
+    /// Returns the name and outcome of each attempted refresh. Downloads happen without holding the
+    /// lock, so checks are never blocked by a slow feed.
+    pub fn refresh_expired(&self) -> Vec<(String, Result<usize, BotGuardError>)> {
+        let due = {
+            let feeds = self.feeds.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+            feeds
+                .iter()
+                .filter(|loaded| loaded.is_expired())
+                .filter_map(|loaded| Some((loaded.feed.name.clone(), loaded.feed.url.clone()?)))
+                .collect::<Vec<_>>()
+        };
+        due.into_iter()
+            .map(|(name, url)| {
+                let result = http::get(&url, self.timeout)
+                    .map_err(|e| BotGuardError::FetchFailed { url: url.clone(), reason: e.to_string() })
+                    .and_then(|body| self.load(&name, &String::from_utf8_lossy(&body)));
+                (name, result)
+            })
+            .collect()
+    }
+
+    /// Returns the most anonymizing kind among the feeds listing the address.
+    pub fn check_anonymizer(&self, ip: IpAddr) -> AnonymizerKind {
+        let feeds = self.feeds.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        feeds
+            .iter()
+            .filter(|loaded| loaded.networks.contains(ip))
+            .map(|loaded| loaded.feed.kind)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Names of the feeds that were never loaded or whose time-to-live has passed.
+    pub fn expired_feeds(&self) -> Vec<String> {
+        let feeds = self.feeds.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        feeds.iter().filter(|loaded| loaded.is_expired()).map(|loaded| loaded.feed.name.clone()).collect()
+    }
+}
+
+fn parse_list(list: &str) -> Result<Vec<IpNet>, String> {
+    list.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let entry = match line.strip_prefix("ExitAddress ") {
+                Some(rest) => rest.split_whitespace().next()?,
+                None => line.split_whitespace().next()?,
+            };
+            // other keywords of the exit list format carry no address
+            if entry.chars().all(|c| c.is_ascii_alphabetic()) {
+                return None;
+            }
+            Some(entry.parse::<IpNet>().map_err(|e| format!("line {}: {}", i + 1, e)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn checks_loaded_feeds() {
+        let lists = AnonymizerLists::new();
+        lists.add_feed(AnonymizerFeed { url: None, ..AnonymizerFeed::tor_exit_nodes() });
+        lists.add_feed(AnonymizerFeed::new("proxies", AnonymizerKind::Proxy, None));
+        assert_eq!(lists.expired_feeds(), ["tor", "proxies"]);
+
+        let exit_list = "ExitNode 0011BD2485AD45D984EC4159C88FC066E5E3300E\nPublished 2024-01-01 00:00:00\n\
+                         ExitAddress 185.220.101.1 2024-01-01 00:10:00\n2001:db8::5\n";
+        assert_eq!(lists.load("tor", exit_list).unwrap(), 2);
+        assert_eq!(lists.load("proxies", "185.220.101.0/24 # also runs an open proxy\n203.0.113.9").unwrap(), 2);
+        assert_eq!(lists.expired_feeds(), Vec::<String>::new());
+
+        assert_eq!(lists.check_anonymizer(ip("185.220.101.1")), AnonymizerKind::Tor);
+        assert_eq!(lists.check_anonymizer(ip("185.220.101.2")), AnonymizerKind::Proxy);
+        assert_eq!(lists.check_anonymizer(ip("2001:db8::5")), AnonymizerKind::Tor);
+        assert_eq!(lists.check_anonymizer(ip("192.0.2.1")), AnonymizerKind::None);
+        assert!(AnonymizerKind::Tor.weight() > AnonymizerKind::None.weight());
+        assert_eq!(AnonymizerFeed::tor_exit_nodes().url.as_deref(), Some(TOR_EXIT_LIST_URL));
+
+        assert!(lists.load("proxies", "not-an-ip").is_err());
+        assert_eq!(lists.check_anonymizer(ip("203.0.113.9")), AnonymizerKind::Proxy);
+        assert!(lists.load("unknown", "192.0.2.1").is_err());
+    }
+
+    #[test]
+    fn refreshes_expired_feeds() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/exits", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\n192.0.2.77\r\n").unwrap();
+        });
+
+        let lists = AnonymizerLists::new();
+        lists.add_feed(AnonymizerFeed { url: Some(url), ..AnonymizerFeed::tor_exit_nodes() });
+        lists.add_feed(AnonymizerFeed::new("manual", AnonymizerKind::Vpn, None));
+        let refreshed = lists.refresh_expired();
+        server.join().unwrap();
+        assert_eq!(refreshed.len(), 1);
+        assert_eq!(refreshed[0], ("tor".to_string(), Ok(1)));
+        assert_eq!(lists.check_anonymizer(ip("192.0.2.77")), AnonymizerKind::Tor);
+
+        // fresh feeds are not downloaded again
+        assert!(lists.refresh_expired().is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+// Workloads for timing the detector, used by the criterion suite in `benches/` and by
+// applications measuring their own patterns and traffic before a redesign:
+//
+//     let corpus = Corpus::from_lines(&std::fs::read_to_string("user-agents.txt")?);
+//     c.bench_function("production traffic", |b| b.iter(|| check_corpus(&detector, &corpus)));
+//
+// `check_corpus` passes every user-agent and verdict through `black_box`, so the checks are not
+// optimized away whatever the harness.
+
+use std::hint::black_box;
+
+use crate::{BotDetector, Verdict};
+
+/// User-agents to check, in the order they are checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Corpus {
+    user_agents: Vec<String>,
+}
+
+const BROWSERS: [&str; 4] = [
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/{}.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/{}.0 Safari/605.1.15",
+    "Mozilla/5.0 (X11; Linux x86_64; rv:{}.0) Gecko/20100101 Firefox/{}.0",
+    "Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Mobile/15E148 Safari/604.1 ({})",
+];
+
+const BOTS: [&str; 4] = [
+    "Mozilla/5.0 (compatible; Googlebot/2.{}; +http://www.google.com/bot.html)",
+    "curl/8.{}.0",
+    "python-requests/2.{}",
+    "Mozilla/5.0 (compatible; AhrefsBot/7.{}; +http://ahrefs.com/robot/)",
+];
+
+impl Corpus {
+    /// One user-agent per non-empty line.
+    pub fn from_lines(lines: &str) -> Self {
+        Corpus { user_agents: lines.lines().map(str::trim).filter(|line| !line.is_empty()).map(str::to_string).collect() }
+    }
+
+    /// `len` user-agents, four browsers for every bot, with varying versions so every repetition
+    /// is a different string. The same `len` always gives the same corpus.
+    pub fn synthetic(len: usize) -> Self {
+        let user_agents = (0..len)
+            .map(|i| {
+                let template = if i % 5 == 4 { BOTS[(i / 5) % BOTS.len()] } else { BROWSERS[i % BROWSERS.len()] };
+                template.replace("{}", &(i % 97).to_string())
+            })
+            .collect();
+        Corpus { user_agents }
+    }
+
+    pub fn push(&mut self, user_agent: &str) {
+        self.user_agents.push(user_agent.to_string());
+    }
+
+    pub fn user_agents(&self) -> impl Iterator<Item = &str> {
+        self.user_agents.iter().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.user_agents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.user_agents.is_empty()
+    }
+}
+
+/// Checks every user-agent of the corpus, returns how many are bots so the work cannot be
+/// optimized away.
+pub fn check_corpus(detector: &BotDetector, corpus: &Corpus) -> usize {
+    corpus.user_agents().filter(|user_agent| black_box(detector.check(black_box(user_agent))) == Verdict::Bot).count()
+}
+
+/// Entries of `len` distinct patterns for a large list, half anchored literals and half with a
+/// version class, none matching browser user-agents.
+pub fn pattern_entries(len: usize) -> String {
+    (0..len).map(|i| if i % 2 == 0 { format!("^benchcrawler{}/", i) } else { format!("benchspider{}/[0-9]+", i) }).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synthetic_corpus_mixes_bots_and_browsers() {
+        let corpus = Corpus::synthetic(50);
+        assert_eq!(corpus, Corpus::synthetic(50));
+        assert_eq!(check_corpus(&BotDetector::default(), &corpus), 10);
+        let detector = BotDetector::new(&pattern_entries(100));
+        assert_eq!(detector.len(), 100);
+        assert_eq!(check_corpus(&detector, &corpus), 0);
+        assert!(detector.check_bot("benchspider7/12"));
+    }
+}
@@ -0,0 +1,67 @@
+// Runs the detection service of `BotGuardLib::server` as a sidecar.
+//
+// botguard-server [--listen ADDR] [--grpc ADDR] [--config FILE] [--patterns FILE] [--reload-interval SECONDS]
+//
+// `--grpc` serves the `Check` method of `proto/botguard.proto` as well, it needs the `grpc` feature.
+
+use std::net::TcpListener;
+use std::process::exit;
+use std::time::Duration;
+
+use BotGuardLib::config::BotGuardConfig;
+use BotGuardLib::server::Server;
+use BotGuardLib::source::FileSource;
+use BotGuardLib::BotDetector;
+
+const USAGE: &str = "usage: botguard-server [--listen ADDR] [--grpc ADDR] [--config FILE] [--patterns FILE] [--reload-interval SECONDS]";
+
+fn fail(message: &str) -> ! {
+    eprintln!("botguard-server: {}", message);
+    exit(2)
+}
+
+fn main() {
+    let mut listen = "127.0.0.1:8080".to_string();
+    let (mut grpc, mut config, mut patterns, mut interval) = (None::<String>, None, None, 60);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| fail(USAGE));
+        match arg.as_str() {
+            "--listen" => listen = value(),
+            "--grpc" => grpc = Some(value()),
+            "--config" => config = Some(value()),
+            "--patterns" => patterns = Some(value()),
+            "--reload-interval" => interval = value().parse().unwrap_or_else(|_| fail("--reload-interval must be a number of seconds")),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => fail(USAGE),
+        }
+    }
+
+    let mut server = match config {
+        Some(path) => BotGuardConfig::from_path(path)
+            .and_then(|config| Server::from_config(&config))
+            .unwrap_or_else(|e| fail(&e.to_string())),
+        None => Server::new(BotDetector::default()),
+    };
+    if let Some(path) = patterns {
+        server = server.reload_from(FileSource::new(path), Duration::from_secs(interval));
+    }
+    if let Some(grpc) = grpc {
+        #[cfg(feature = "grpc")]
+        {
+            let listener = TcpListener::bind(&grpc).unwrap_or_else(|e| fail(&format!("cannot listen on {}: {}", grpc, e)));
+            server = server.grpc(listener);
+            eprintln!("botguard-server: serving gRPC on {}", grpc);
+        }
+        #[cfg(not(feature = "grpc"))]
+        fail(&format!("cannot serve gRPC on {}, built without the grpc feature", grpc));
+    }
+    let listener = TcpListener::bind(&listen).unwrap_or_else(|e| fail(&format!("cannot listen on {}: {}", listen, e)));
+    eprintln!("botguard-server: listening on {}", listen);
+    if let Err(e) = server.serve(listener) {
+        fail(&e.to_string());
+    }
+}
@@ -0,0 +1,394 @@
+// Builder for detectors that need more than the newline-delimited patterns of `BotDetector::new`.
+
+use crate::config::Thresholds;
+use crate::normalize::Normalization;
+use crate::source::{BundleVersion, VerifyingKey};
+use crate::{BotDetector, BotGuardError, MatchMode, RegexLimits, Verdict};
+
+/// How user-agents that are empty or only whitespace are judged, before any pattern is matched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyUaPolicy {
+    TreatAsBot,
+    #[default]
+    TreatAsHuman,
+    Suspicious,
+}
+
+impl EmptyUaPolicy {
+    pub(crate) fn verdict(self) -> Verdict {
+        match self {
+            EmptyUaPolicy::TreatAsBot => Verdict::Bot,
+            EmptyUaPolicy::TreatAsHuman => Verdict::Human,
+            EmptyUaPolicy::Suspicious => Verdict::Suspicious,
+        }
+    }
+}
+
+/// How the entries given to [`BotDetectorBuilder::patterns`] are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PatternSyntax {
+    /// Regular expressions, as in [`BotDetector::new`].
+    #[default]
+    Regex,
+    /// Globs matching the whole user-agent, where `*` stands for any text and `?` for one
+    /// character, like `Googlebot*` or `*bingpreview*`.
+    Glob,
+    /// Plain text found anywhere in the user-agent.
+    Literal,
+}
+
+impl PatternSyntax {
+    /// Translates a pattern into the regular expression it stands for.
+    ///
+    /// ```
+    /// use BotGuardLib::PatternSyntax;
+    ///
+    /// assert_eq!(PatternSyntax::Glob.to_regex("Googlebot*"), "^Googlebot");
+    /// assert_eq!(PatternSyntax::Glob.to_regex("*bing?preview*"), "bing.preview");
+    /// assert_eq!(PatternSyntax::Glob.to_regex("curl/7.*.1"), "^curl/7\\..*\\.1$");
+    /// assert_eq!(PatternSyntax::Literal.to_regex("Yahoo! Slurp (+http"), "Yahoo! Slurp \\(\\+http");
+    /// ```
+    pub fn to_regex(self, pattern: &str) -> String {
+        match self {
+            PatternSyntax::Regex => pattern.to_string(),
+            PatternSyntax::Literal => regex::escape(pattern),
+            PatternSyntax::Glob => {
+                let inner = pattern.trim_start_matches('*').trim_end_matches('*');
+                if inner.is_empty() {
+                    return String::new();
+                }
+                let mut regex = String::with_capacity(pattern.len() + 2);
+                if !pattern.starts_with('*') {
+                    regex.push('^');
+                }
+                for c in inner.chars() {
+                    match c {
+                        '*' => regex.push_str(".*"),
+                        '?' => regex.push('.'),
+                        c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+                    }
+                }
+                if !pattern.ends_with('*') {
+                    regex.push('$');
+                }
+                regex
+            }
+        }
+    }
+}
+
+/// Matching options fixed when a detector is built.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DetectorOptions {
+    pub(crate) case_sensitive: bool,
+    pub(crate) match_mode: MatchMode,
+    pub(crate) limits: RegexLimits,
+    pub(crate) max_input_len: Option<usize>,
+    pub(crate) normalization: Normalization,
+    pub(crate) empty_ua_policy: EmptyUaPolicy,
+    pub(crate) verifying_key: Option<VerifyingKey>,
+    pub(crate) thresholds: Thresholds,
+    /// Only read while building, later reloads compile right away.
+    pub(crate) background_compilation: bool,
+    #[cfg(feature = "ua-parser")]
+    pub(crate) context_rules: crate::useragent::ContextRules,
+}
+
+/// Configures and builds a [`BotDetector`], created with [`BotDetector::builder`].
+///
+/// Starts out with the bundled default patterns and the same options as [`BotDetector::new`].
+///
+/// ```
+/// use BotGuardLib::BotDetector;
+///
+/// let BotDetector = BotDetector::builder()
+///     .patterns("B0T\n(?i)^curl/")
+///     .case_sensitive(true)
+///     .build()
+///     .unwrap();
+///
+/// assert!(BotDetector.check_bot("Mozilla/5.0 (ScanB0T)"));
+/// assert!(!BotDetector.check_bot("Mozilla/5.0 (Scanb0t)"));
+/// assert!(BotDetector.check_bot("CURL/8.4.0"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BotDetectorBuilder {
+    /// `None` keeps the bundled default patterns.
+    entries: Option<String>,
+    syntax: PatternSyntax,
+    options: DetectorOptions,
+}
+
+impl BotDetectorBuilder {
+    /// Replaces the bundled default patterns with newline-delimited entries, in the format of [`BotDetector::new`].
+    pub fn patterns(mut self, bot_entries: &str) -> Self {
+        self.entries = Some(bot_entries.to_string());
+        self
+    }
+
+    /// Reads the entries of [`BotDetectorBuilder::patterns`] as globs or plain text instead of
+    /// regular expressions, see [`PatternSyntax`]. Group headers and weights are written as
+    /// usual, the bundled default patterns are not affected.
+    ///
+    /// The patterns are stored as the translated regular expressions, which is what methods like
+    /// [`BotDetector::contains_pattern`] and [`BotDetector::export`] see afterwards.
+    ///
+    /// ```
+    /// use BotGuardLib::{BotDetector, PatternSyntax};
+    ///
+    /// let BotDetector = BotDetector::builder()
+    ///     .patterns("[search-engines]\nGooglebot*\n*bingpreview*\n[tools]\n0.6 python-requests/*")
+    ///     .pattern_syntax(PatternSyntax::Glob)
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(BotDetector.check_bot("Googlebot/2.1 (+http://www.google.com/bot.html)"));
+    /// assert!(!BotDetector.check_bot("Mozilla/5.0 (compatible; Googlebot/2.1)"));
+    /// assert!(BotDetector.check_bot("Mozilla/5.0 (Windows NT 6.1) BingPreview/1.0b"));
+    /// assert_eq!(BotDetector.score("python-requests/2.31"), 0.6);
+    /// assert!(BotDetector.contains_pattern("^googlebot"));
+    /// ```
+    pub fn pattern_syntax(mut self, syntax: PatternSyntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Matches the case of patterns as written instead of ignoring it. Patterns are never
+    /// rewritten, case-insensitive detectors compile them with the `i` flag, so capture names,
+    /// escapes like `\x41` and letters beyond ASCII keep their meaning.
+    ///
+    /// Individual patterns can still ignore case with a leading `(?i)`. The other way round does
+    /// not work, case-insensitive detectors also lowercase the ASCII letters of user-agents.
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.options.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Chooses where in the user-agent a pattern may match, see [`MatchMode`].
+    pub fn match_mode(mut self, match_mode: MatchMode) -> Self {
+        self.options.match_mode = match_mode;
+        self
+    }
+
+    /// Limits the compiled size of patterns, see [`RegexLimits`].
+    pub fn regex_limits(mut self, limits: RegexLimits) -> Self {
+        self.options.limits = limits;
+        self
+    }
+
+    /// Only the first `max` bytes of a user-agent are matched, longer ones are cut at the
+    /// preceding character boundary.
+    pub fn max_input_len(mut self, max: usize) -> Self {
+        self.options.max_input_len = Some(max);
+        self
+    }
+
+    /// Folds evasive spellings of user-agents before they are matched, see [`Normalization`].
+    ///
+    /// ```
+    /// use BotGuardLib::normalize::Normalization;
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let BotDetector = BotDetector::builder().patterns("^sqlmap/").normalization(Normalization::all()).build().unwrap();
+    /// assert!(BotDetector.check_bot(" ｓqlmаp/1.7"));
+    /// ```
+    pub fn normalization(mut self, normalization: Normalization) -> Self {
+        self.options.normalization = normalization;
+        self
+    }
+
+    /// Sets how empty user-agents are judged, [`EmptyUaPolicy::TreatAsHuman`] by default.
+    pub fn empty_ua_policy(mut self, policy: EmptyUaPolicy) -> Self {
+        self.options.empty_ua_policy = policy;
+        self
+    }
+
+    /// Sets the scores from which [`BotDetector::check`] reports suspicious clients and bots,
+    /// see [`BotDetector::score`]. Matches of unweighted patterns always score `1.0`.
+    ///
+    /// ```
+    /// use BotGuardLib::config::Thresholds;
+    /// use BotGuardLib::{BotDetector, Verdict};
+    ///
+    /// let BotDetector = BotDetector::builder()
+    ///     .patterns("[scanners]\n^sqlmap/\n[http-clients]\n0.6 python-requests/")
+    ///     .thresholds(Thresholds { suspicious: 0.4, bot: 0.8 })
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(BotDetector.check("sqlmap/1.7"), Verdict::Bot);
+    /// assert_eq!(BotDetector.check("python-requests/2.31"), Verdict::Suspicious);
+    /// assert_eq!(BotDetector.check("acme-uptime/1.0"), Verdict::Suspicious);
+    /// assert_eq!(BotDetector.check("Mozilla/5.0 (X11; Linux x86_64) Firefox/120.0"), Verdict::Human);
+    /// ```
+    pub fn thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.options.thresholds = thresholds;
+        self
+    }
+
+    /// Raises the verdict of user-agents matching rules on their parsed fields, see
+    /// [`crate::useragent::ContextRules`].
+    #[cfg(feature = "ua-parser")]
+    pub fn context_rules(mut self, rules: crate::useragent::ContextRules) -> Self {
+        self.options.context_rules = rules;
+        self
+    }
+
+    /// Returns from [`BotDetectorBuilder::build`] right after reading the patterns and compiles
+    /// them on a background thread, for cold starts where compiling a large pattern set would
+    /// delay the first response.
+    ///
+    /// Until every group is compiled only the plain-text patterns match, like `googlebot` or
+    /// `^curl/`, using string search. Invalid or oversized patterns fail
+    /// [`BotDetector::compile_now`] instead of the build, which also waits for the compilation.
+    /// Without thread support the patterns are compiled by `compile_now` or the first edit.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let BotDetector = BotDetector::builder().patterns("googlebot\nbingbot/\\d").background_compilation(true).build().unwrap();
+    /// assert!(BotDetector.check_bot("Googlebot/2.1"));
+    ///
+    /// BotDetector.compile_now().unwrap();
+    /// assert!(BotDetector.is_compiled());
+    /// assert!(BotDetector.check_bot("bingbot/2.0"));
+    /// ```
+    pub fn background_compilation(mut self, background: bool) -> Self {
+        self.options.background_compilation = background;
+        self
+    }
+
+    /// Only applies fetched bundles signed with this key, see [`crate::source::refresh`].
+    ///
+    /// Patterns passed directly, e.g. to [`BotDetector::reload`], are not checked.
+    pub fn verifying_key(mut self, key: VerifyingKey) -> Self {
+        self.options.verifying_key = Some(key);
+        self
+    }
+
+    /// Compiles the patterns, failing on the first entry that is invalid or exceeds the limits.
+    ///
+    /// ```
+    /// use BotGuardLib::{BotDetector, BotGuardError, RegexLimits};
+    ///
+    /// let limits = RegexLimits { pattern_size_limit: 10_000, ..RegexLimits::default() };
+    /// let error = BotDetector::builder().patterns("googlebot\n\\w{100}{100}").regex_limits(limits).build().unwrap_err();
+    /// assert!(matches!(error, BotGuardError::PatternTooLarge { ref pattern, .. } if pattern == "\\w{100}{100}"));
+    /// ```
+    pub fn build(self) -> Result<BotDetector, BotGuardError> {
+        match (self.entries, self.syntax) {
+            (None, _) => BotDetector::from_entries(crate::_PATTERNS, self.options),
+            (Some(entries), PatternSyntax::Regex) => BotDetector::from_entries(&entries, self.options),
+            (Some(entries), syntax) => {
+                let groups = BotDetector::parse_lines(&entries)
+                    .into_iter()
+                    .map(|(name, patterns)| (name, patterns.into_iter().map(|(p, weight)| (syntax.to_regex(p.trim()), weight)).collect()))
+                    .collect();
+                BotDetector::from_groups(groups, self.options, BundleVersion::of(&entries, None))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BotDetector, BotGuardError, MatchMode, PatternSyntax, RegexLimits};
+
+    #[test]
+    fn defaults_match_new() {
+        let built = BotDetector::builder().build().unwrap();
+        assert!(built.diff(&BotDetector::default()).is_empty());
+        assert!(built.check_bot("GOOGLEBOT"));
+    }
+
+    #[test]
+    fn case_sensitive_patterns_are_kept_verbatim() {
+        let mut BotDetector = BotDetector::builder().patterns("[Scanners]\nB0T\n\\D{3}X").case_sensitive(true).build().unwrap();
+        assert!(BotDetector.contains_pattern("B0T"));
+        assert!(!BotDetector.contains_pattern("b0t"));
+        assert_eq!(BotDetector.classify("abcX"), Some("scanners"));
+        assert!(!BotDetector.check_bot("abcx"));
+
+        BotDetector.append(&["Exact"]).unwrap();
+        assert!(BotDetector.check_bot("Exact/1.0"));
+        assert!(!BotDetector.check_bot("exact/1.0"));
+        BotDetector.remove(&["exact"]);
+        assert!(BotDetector.check_bot("Exact/1.0"));
+        BotDetector.remove(&["Exact"]);
+        assert!(!BotDetector.check_bot("Exact/1.0"));
+    }
+
+    #[test]
+    fn translates_globs_and_literals() {
+        let glob = |pattern: &str| PatternSyntax::Glob.to_regex(pattern);
+        assert_eq!(glob("*"), "");
+        assert_eq!(glob("**bot"), "bot$");
+        assert_eq!(glob("a*b?c"), "^a.*b.c$");
+        assert_eq!(glob("(+http://example.com)*"), "^\\(\\+http://example\\.com\\)");
+
+        let BotDetector = BotDetector::builder().patterns("  Googlebot* \n*.example.com)").pattern_syntax(PatternSyntax::Glob).build().unwrap();
+        assert!(BotDetector.check_bot("googlebot-image/1.0"));
+        assert!(BotDetector.check_bot("Crawler (+https://bot.example.com)"));
+        assert!(!BotDetector.check_bot("Crawler (+https://botxexample.com)"));
+
+        let literal = BotDetector::builder().patterns("[tools]\n0.3 curl/7.\nYahoo! Slurp").pattern_syntax(PatternSyntax::Literal).build().unwrap();
+        assert_eq!(literal.score("curl/7.88.1"), 0.3);
+        assert!(!literal.check_bot("curl/7088"));
+        assert_eq!(literal.classify("Mozilla/5.0 (compatible; Yahoo! Slurp)"), Some("tools"));
+        assert!(BotDetector::builder().pattern_syntax(PatternSyntax::Literal).build().unwrap().diff(&BotDetector::default()).is_empty());
+    }
+
+    #[test]
+    fn token_mode_requires_token_boundaries() {
+        let mut BotDetector = BotDetector::builder().patterns("me\ngooglebot").match_mode(MatchMode::Token).build().unwrap();
+        assert!(BotDetector.check_bot("Me"));
+        assert!(BotDetector.check_bot("Mozilla/5.0 (compatible; me; +http://example.com)"));
+        assert!(BotDetector.check_bot("Mozilla/5.0 (compatible; Googlebot/2.1)"));
+        assert!(!BotDetector.check_bot("Mozilla/5.0 (Linux; Android 13; SM-S918B) Mobile Safari Chrome/120 Awesome/1"));
+        assert!(!BotDetector.check_bot("NotGooglebot/1.0"));
+
+        BotDetector.append(&["^Special"]).unwrap();
+        assert!(BotDetector.check_bot("Special/1.0"));
+        assert!(!BotDetector.check_bot("Specialized/1.0"));
+    }
+
+    #[test]
+    fn rejects_invalid_and_oversized_patterns() {
+        let error = BotDetector::builder().patterns("[ok]\ngooglebot\n[broken]\n(unclosed").build().unwrap_err();
+        assert!(matches!(error, BotGuardError::InvalidPattern { ref pattern, .. } if pattern == "(unclosed"));
+
+        let limits = RegexLimits { nest_limit: 3, ..RegexLimits::default() };
+        let error = BotDetector::builder().patterns("((((deep))))").regex_limits(limits).build().unwrap_err();
+        assert!(matches!(error, BotGuardError::InvalidPattern { .. }));
+
+        let limits = RegexLimits { group_size_limit: 20_000, ..RegexLimits::default() };
+        let many = (0..200).map(|i| format!("bot{}x\\d+", i)).collect::<Vec<_>>().join("\n");
+        let error = BotDetector::builder().patterns(&many).regex_limits(limits).build().unwrap_err();
+        assert_eq!(error, BotGuardError::GroupTooLarge { group: "custom".to_string(), limit: 20_000 });
+    }
+
+    #[test]
+    fn appended_patterns_are_validated() {
+        let limits = RegexLimits { pattern_size_limit: 10_000, ..RegexLimits::default() };
+        let mut BotDetector = BotDetector::builder().patterns("googlebot").regex_limits(limits).build().unwrap();
+        let appended = BotDetector.append(&["\\w{50}{50}"]);
+        assert!(matches!(appended, Err(BotGuardError::PatternTooLarge { limit: 10_000, .. })));
+        assert!(!BotDetector.contains_pattern("\\w{50}{50}"));
+        assert!(BotDetector.check_bot("Googlebot"));
+    }
+
+    #[test]
+    fn caps_input_length() {
+        let BotDetector = BotDetector::builder().patterns("bot$").max_input_len(8).build().unwrap();
+        assert!(BotDetector.check_bot("abcd_bot"));
+        assert!(BotDetector.check_bot("abcd_bot and more"));
+        assert!(!BotDetector.check_bot("more abcd_bot"));
+        assert!(!BotDetector.check_bot("ééééébot"));
+    }
+
+    #[test]
+    fn anchored_mode_matches_the_whole_user_agent() {
+        let BotDetector = BotDetector::builder().patterns("googlebot/\\d\\.\\d").match_mode(MatchMode::Anchored).build().unwrap();
+        assert!(BotDetector.check_bot("Googlebot/2.1"));
+        assert!(!BotDetector.check_bot("Mozilla/5.0 (compatible; Googlebot/2.1)"));
+        assert!(!BotDetector.check_bot("Googlebot/2.1 extra"));
+    }
+}
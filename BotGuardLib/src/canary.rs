@@ -0,0 +1,236 @@
+// Canary rollout of pattern updates: a new bundle is compiled next to the patterns in use and
+// checked on a sample of the real traffic before it replaces them, so a list update matching
+// some common browser fails as a report of diverging verdicts instead of as blocked customers.
+//
+// The candidate only sees user-agents, the other signals do not depend on the patterns. Its
+// verdicts are never acted on. Once enough requests were sampled and the share on which the
+// two sets of patterns disagree is at most the allowed divergence, the candidate can be
+// promoted; the replaced patterns stay available to `BotDetector::rollback` as after any reload.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::sampling::uniform;
+use crate::source::{BundleVersion, PatternBundle};
+use crate::tester::verdict;
+use crate::{BotDetector, BotGuardError, Verdict};
+
+/// Diverging user-agents kept for the report, the oldest are dropped first.
+const RECENT_DIFFS: usize = 100;
+
+/// A user-agent the current and the candidate patterns judge differently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerdictDiff {
+    pub user_agent: String,
+    pub current: Verdict,
+    pub candidate: Verdict,
+}
+
+/// How the candidate did so far, see [`Canary::report`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanaryReport {
+    /// Version of the candidate patterns.
+    pub version: String,
+    pub samples: u64,
+    /// Samples the candidate judged differently.
+    pub diverged: u64,
+    /// The latest diverging user-agents, oldest first.
+    pub recent: Vec<VerdictDiff>,
+}
+
+impl CanaryReport {
+    /// Share of the samples the candidate judged differently, `0.0` before the first sample.
+    pub fn divergence(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.diverged as f64 / self.samples as f64
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    samples: u64,
+    diverged: u64,
+    recent: VecDeque<VerdictDiff>,
+}
+
+/// Candidate patterns checked alongside the current ones, see [`crate::request::BotGuard::canary`].
+///
+/// ```
+/// use BotGuardLib::canary::Canary;
+/// use BotGuardLib::source::PatternBundle;
+/// use BotGuardLib::{BotDetector, Verdict};
+///
+/// let mut detector = BotDetector::new("[scanners]\n^sqlmap/");
+/// // the update also matches every Chrome
+/// let bundle = PatternBundle::new("[scanners]\n^sqlmap/\nchrome".to_string(), "update").with_version("2024-07-01");
+/// let canary = Canary::from_bundle(&detector, &bundle).unwrap().sample(1.0).min_samples(2);
+///
+/// let chrome = "Mozilla/5.0 (Windows NT 10.0) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0 Safari/537.36";
+/// assert_eq!(canary.compare(&detector, chrome).unwrap().candidate, Verdict::Bot);
+/// assert_eq!(canary.compare(&detector, "sqlmap/1.7"), None);
+/// assert_eq!(canary.report().divergence(), 0.5);
+///
+/// // the default allows one diverging request in a thousand
+/// assert!(canary.promote(&mut detector).is_err());
+/// assert!(!detector.check_bot(chrome));
+/// ```
+pub struct Canary {
+    candidate: BotDetector,
+    rate: f64,
+    max_divergence: f64,
+    min_samples: u64,
+    requests: AtomicU64,
+    tally: Mutex<Tally>,
+}
+
+impl fmt::Debug for Canary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Canary")
+            .field("version", &self.candidate.current_version().version)
+            .field("rate", &self.rate)
+            .field("max_divergence", &self.max_divergence)
+            .field("min_samples", &self.min_samples)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Canary {
+    /// Checks `candidate` on one request in ten, it can be promoted after 1000 samples with at
+    /// most one diverging request in a thousand.
+    pub fn new(candidate: BotDetector) -> Self {
+        Canary { candidate, rate: 0.1, max_divergence: 0.001, min_samples: 1000, requests: AtomicU64::new(0), tally: Mutex::default() }
+    }
+
+    /// Compiles a bundle with the options of `current`, the groups disabled there are disabled
+    /// in the candidate as well.
+    pub fn from_bundle(current: &BotDetector, bundle: &PatternBundle) -> Result<Self, BotGuardError> {
+        let mut candidate = BotDetector::from_bundle(bundle, current.options.clone())?;
+        for group in current.groups().filter(|group| !current.is_group_enabled(group)) {
+            candidate.disable_group(group);
+        }
+        Ok(Canary::new(candidate))
+    }
+
+    /// Share of the requests the candidate is checked on, clamped to `0.0..=1.0`.
+    pub fn sample(mut self, rate: f64) -> Self {
+        self.rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Share of the samples the candidate may judge differently and still be promoted.
+    pub fn max_divergence(mut self, divergence: f64) -> Self {
+        self.max_divergence = divergence;
+        self
+    }
+
+    /// Samples needed before the candidate can be promoted.
+    pub fn min_samples(mut self, samples: u64) -> Self {
+        self.min_samples = samples;
+        self
+    }
+
+    pub fn version(&self) -> &BundleVersion {
+        self.candidate.current_version()
+    }
+
+    /// Checks the user-agent with the candidate if the request is sampled and returns the diff if
+    /// the verdicts differ. Detection hooks are not called, neither the candidate's nor those of
+    /// `current`.
+    pub fn compare(&self, current: &BotDetector, user_agent: &str) -> Option<VerdictDiff> {
+        self.compare_scored(|| current.score_untraced(user_agent), current, user_agent)
+    }
+
+    /// [`Canary::compare`] with the score of the current patterns already known, only computed
+    /// for sampled requests.
+    pub(crate) fn compare_scored(&self, score: impl FnOnce() -> f32, current: &BotDetector, user_agent: &str) -> Option<VerdictDiff> {
+        if self.rate < 1.0 && (self.rate <= 0.0 || uniform(self.requests.fetch_add(1, Ordering::Relaxed)) >= self.rate) {
+            return None;
+        }
+        let (current, candidate) = (current.options.thresholds.verdict(score()), verdict(&self.candidate, user_agent));
+        let mut tally = self.lock();
+        tally.samples += 1;
+        if current == candidate {
+            return None;
+        }
+        tally.diverged += 1;
+        let diff = VerdictDiff { user_agent: user_agent.to_string(), current, candidate };
+        if tally.recent.len() == RECENT_DIFFS {
+            tally.recent.pop_front();
+        }
+        tally.recent.push_back(diff.clone());
+        Some(diff)
+    }
+
+    pub fn report(&self) -> CanaryReport {
+        let tally = self.lock();
+        CanaryReport { version: self.version().version.clone(), samples: tally.samples, diverged: tally.diverged, recent: tally.recent.iter().cloned().collect() }
+    }
+
+    /// Whether enough requests were sampled and the divergence is within the allowed share.
+    pub fn is_ready(&self) -> bool {
+        let report = self.report();
+        report.samples >= self.min_samples && report.divergence() <= self.max_divergence
+    }
+
+    /// Replaces the patterns of `detector` with the candidate if it [`Canary::is_ready`], like
+    /// [`BotDetector::reload_bundle`]. Otherwise the candidate is dropped and the detector left
+    /// unchanged, check [`Canary::is_ready`] first to keep sampling.
+    pub fn promote(self, detector: &mut BotDetector) -> Result<(), BotGuardError> {
+        self.check_ready()?;
+        detector.install(self.candidate);
+        Ok(())
+    }
+
+    /// [`Canary::is_ready`] as an error saying why not.
+    pub(crate) fn check_ready(&self) -> Result<(), BotGuardError> {
+        if self.is_ready() {
+            return Ok(());
+        }
+        let report = self.report();
+        Err(BotGuardError::InvalidConfig { line: None, reason: format!("canary {} is not ready: {} of {} samples diverged", report.version, report.diverged, report.samples) })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Tally> {
+        self.tally.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn promotes_candidates_that_agree() {
+        let mut detector = BotDetector::new("[scanners]\n^sqlmap/\n[monitoring]\npingdom");
+        detector.disable_group("monitoring");
+        let bundle = PatternBundle::new("[scanners]\n^sqlmap/\nnikto\n[monitoring]\npingdom".to_string(), "update").with_version("v2");
+        let canary = Canary::from_bundle(&detector, &bundle).unwrap().sample(1.0).min_samples(3).max_divergence(0.0);
+        for user_agent in ["sqlmap/1.7", "Pingdom.com_bot_version_1.4", "Mozilla/5.0 (X11; Linux x86_64; rv:127.0) Gecko/20100101 Firefox/127.0"] {
+            assert_eq!(canary.compare(&detector, user_agent), None);
+        }
+        assert_eq!((canary.report().samples, canary.report().version.as_str()), (3, "v2"));
+        canary.promote(&mut detector).unwrap();
+        assert!(detector.check_bot("Nikto/2.5"));
+        assert!(!detector.is_group_enabled("monitoring"));
+        assert_eq!(detector.current_version().version, "v2");
+    }
+
+    #[test]
+    fn samples_and_keeps_the_latest_diffs() {
+        let detector = BotDetector::new("^sqlmap/");
+        let canary = Canary::new(BotDetector::new("^sqlmap/\ncurl")).sample(0.5);
+        let sampled = (0..1000).filter_map(|i| canary.compare(&detector, &format!("curl/{}", i))).count();
+        let report = canary.report();
+        assert_eq!((report.samples, report.diverged), (sampled as u64, sampled as u64));
+        assert!((400..600).contains(&sampled), "{}", sampled);
+        assert_eq!(report.recent.len(), RECENT_DIFFS);
+        assert_eq!(report.recent[0].current, Verdict::Human);
+        assert_eq!(Canary::new(BotDetector::new("x")).sample(0.0).compare(&detector, "curl/8"), None);
+        assert_eq!(Canary::new(BotDetector::new("x")).report().divergence(), 0.0);
+    }
+}
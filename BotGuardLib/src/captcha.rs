@@ -0,0 +1,265 @@
+// CAPTCHA verification: the token a widget hands to the page is checked with the provider's
+// siteverify endpoint, so a `challenge::ChallengeFlow` can escalate to a CAPTCHA rendered by a
+// custom `ChallengePage` and treat the provider's answer like a solved challenge.
+//
+// hCaptcha, Cloudflare Turnstile and reCAPTCHA share one protocol: a form POST with the secret,
+// the token and optionally the client address, answered with JSON. `SiteVerifier` posts it to
+// the provider's HTTPS endpoint with the crate's HTTP client. The client blocks, `SiteVerifier`
+// runs it on the shared pool of `nonblocking` so awaiting a verification never stalls the executor.
+
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::{http, json, nonblocking, BotGuardError};
+
+/// A CAPTCHA service with a siteverify endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CaptchaProvider {
+    HCaptcha,
+    Turnstile,
+    ReCaptcha,
+}
+
+impl CaptchaProvider {
+    /// The provider's public siteverify endpoint, used by [`SiteVerifier::new`].
+    pub fn endpoint(self) -> &'static str {
+        match self {
+            CaptchaProvider::HCaptcha => "https://api.hcaptcha.com/siteverify",
+            CaptchaProvider::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/siteverify",
+            CaptchaProvider::ReCaptcha => "https://www.google.com/recaptcha/api/siteverify",
+        }
+    }
+}
+
+/// The provider's answer, the fields a provider does not send are empty.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CaptchaOutcome {
+    /// Whether the token is valid and passed the checks of the verifier.
+    pub success: bool,
+    /// Site the widget was solved on.
+    pub hostname: Option<String>,
+    /// reCAPTCHA v3 and hCaptcha Enterprise risk score, `1.0` is a human for reCAPTCHA.
+    pub score: Option<f32>,
+    /// Action name the widget was rendered with.
+    pub action: Option<String>,
+    /// Error codes like `invalid-input-response` or `timeout-or-duplicate`.
+    pub error_codes: Vec<String>,
+}
+
+impl CaptchaOutcome {
+    /// Reads a siteverify JSON response.
+    pub fn from_json(response: &str) -> Result<Self, String> {
+        let value = json::parse(response)?;
+        let text = |key: &str| value.get(key).and_then(json::Value::as_str).map(str::to_string);
+        Ok(CaptchaOutcome {
+            success: value.get("success").and_then(json::Value::as_bool).ok_or("response without success")?,
+            hostname: text("hostname"),
+            score: value.get("score").and_then(json::Value::as_f64).map(|score| score as f32),
+            action: text("action"),
+            error_codes: value
+                .get("error-codes")
+                .and_then(json::Value::as_array)
+                .map(|codes| codes.iter().filter_map(json::Value::as_str).map(str::to_string).collect())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Checks CAPTCHA tokens, e.g. with a provider or a stub in tests.
+///
+/// ```
+/// use std::net::IpAddr;
+/// use BotGuardLib::captcha::{CaptchaOutcome, CaptchaVerifier};
+/// use BotGuardLib::BotGuardError;
+///
+/// struct AlwaysPass;
+///
+/// impl CaptchaVerifier for AlwaysPass {
+///     async fn verify(&self, _token: &str, _remote_ip: Option<IpAddr>) -> Result<CaptchaOutcome, BotGuardError> {
+///         Ok(CaptchaOutcome { success: true, ..CaptchaOutcome::default() })
+///     }
+/// }
+/// ```
+pub trait CaptchaVerifier {
+    fn verify(&self, token: &str, remote_ip: Option<IpAddr>) -> impl Future<Output = Result<CaptchaOutcome, BotGuardError>> + Send;
+}
+
+/// Verifies tokens with a provider's siteverify endpoint.
+///
+/// ```no_run
+/// use BotGuardLib::captcha::{CaptchaProvider, CaptchaVerifier, SiteVerifier};
+///
+/// # async fn check() -> Result<(), BotGuardLib::BotGuardError> {
+/// let verifier = SiteVerifier::new(CaptchaProvider::Turnstile, "0x4AAAAAAA-secret").hostname("example.com");
+/// let outcome = verifier.verify("token from the widget", "203.0.113.7".parse().ok()).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SiteVerifier {
+    provider: CaptchaProvider,
+    secret: String,
+    endpoint: String,
+    hostname: Option<String>,
+    min_score: Option<f32>,
+    timeout: Duration,
+}
+
+impl fmt::Debug for SiteVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SiteVerifier")
+            .field("provider", &self.provider)
+            .field("endpoint", &self.endpoint)
+            .field("hostname", &self.hostname)
+            .field("min_score", &self.min_score)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SiteVerifier {
+    /// A verifier sending the requests to the provider's [`CaptchaProvider::endpoint`], with a
+    /// 10 second timeout.
+    pub fn new(provider: CaptchaProvider, secret: &str) -> Self {
+        SiteVerifier {
+            provider,
+            secret: secret.to_string(),
+            endpoint: provider.endpoint().to_string(),
+            hostname: None,
+            min_score: None,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// A verifier sending the requests to another `http://` or `https://` endpoint of the same
+    /// protocol, e.g. an egress proxy or a stub. Other URLs fail with
+    /// [`BotGuardError::InvalidConfig`].
+    pub fn with_endpoint(provider: CaptchaProvider, secret: &str, endpoint: &str) -> Result<Self, BotGuardError> {
+        if let Err(e) = http::HttpUrl::parse(endpoint) {
+            return Err(BotGuardError::InvalidConfig { line: None, reason: format!("siteverify endpoint: {}", e) });
+        }
+        Ok(SiteVerifier { endpoint: endpoint.to_string(), ..SiteVerifier::new(provider, secret) })
+    }
+
+    /// Tokens solved on another site fail.
+    pub fn hostname(mut self, hostname: &str) -> Self {
+        self.hostname = Some(hostname.to_string());
+        self
+    }
+
+    /// Tokens with a lower score fail, for reCAPTCHA v3. Responses without a score are not affected.
+    pub fn min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn provider(&self) -> CaptchaProvider {
+        self.provider
+    }
+
+    fn check(&self, mut outcome: CaptchaOutcome) -> CaptchaOutcome {
+        if let Some(expected) = &self.hostname {
+            if outcome.hostname.as_deref().is_some_and(|hostname| !hostname.eq_ignore_ascii_case(expected)) {
+                outcome.success = false;
+                outcome.error_codes.push("hostname-mismatch".to_string());
+            }
+        }
+        if self.min_score.zip(outcome.score).is_some_and(|(min, score)| score < min) {
+            outcome.success = false;
+            outcome.error_codes.push("score-too-low".to_string());
+        }
+        outcome
+    }
+}
+
+impl CaptchaVerifier for SiteVerifier {
+    async fn verify(&self, token: &str, remote_ip: Option<IpAddr>) -> Result<CaptchaOutcome, BotGuardError> {
+        let failed = |reason: String| BotGuardError::FetchFailed { url: self.endpoint.clone(), reason };
+        let mut form = format!("secret={}&response={}", form_encode(&self.secret), form_encode(token));
+        if let Some(ip) = remote_ip {
+            form.push_str(&format!("&remoteip={}", ip));
+        }
+        let (endpoint, timeout) = (self.endpoint.clone(), self.timeout);
+        let body = nonblocking::spawn_blocking(move || http::post_for_body(&endpoint, "application/x-www-form-urlencoded", form.as_bytes(), timeout))
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+        let body = String::from_utf8(body).map_err(|_| failed("body is not UTF-8".to_string()))?;
+        Ok(self.check(CaptchaOutcome::from_json(&body).map_err(failed)?))
+    }
+}
+
+/// Percent-encodes a form value.
+fn form_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::block_on;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Answers one request with the JSON body and returns the request it received.
+    fn siteverify(response: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/siteverify", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !String::from_utf8_lossy(&request).contains("response=") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}", response.len(), response).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn posts_the_token_and_reads_the_answer() {
+        let (url, server) = siteverify(r#"{"success":true,"hostname":"example.com","challenge_ts":"2024-01-01T00:00:00Z"}"#);
+        let verifier = SiteVerifier::with_endpoint(CaptchaProvider::HCaptcha, "s3cret&=", &url).unwrap().hostname("Example.com");
+        let outcome = block_on(verifier.verify("tok en", Some("203.0.113.7".parse().unwrap()))).unwrap();
+        assert_eq!(outcome, CaptchaOutcome { success: true, hostname: Some("example.com".to_string()), ..CaptchaOutcome::default() });
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /siteverify HTTP/1.1\r\n"), "{}", request);
+        assert!(request.ends_with("\r\n\r\nsecret=s3cret%26%3D&response=tok%20en&remoteip=203.0.113.7"), "{}", request);
+
+        let unreachable = SiteVerifier::with_endpoint(CaptchaProvider::Turnstile, "s", "http://127.0.0.1:1/siteverify").unwrap();
+        assert!(matches!(block_on(unreachable.verify("t", None)), Err(BotGuardError::FetchFailed { .. })));
+        assert!(SiteVerifier::with_endpoint(CaptchaProvider::Turnstile, "s", "ftp://127.0.0.1/").is_err());
+        let turnstile = SiteVerifier::new(CaptchaProvider::Turnstile, "s");
+        assert_eq!(turnstile.endpoint, "https://challenges.cloudflare.com/turnstile/v0/siteverify");
+    }
+
+    #[test]
+    fn applies_hostname_and_score_checks() {
+        let outcome = CaptchaOutcome::from_json(r#"{"success":true,"hostname":"evil.example","score":0.3,"action":"login"}"#).unwrap();
+        assert_eq!((outcome.score, outcome.action.as_deref()), (Some(0.3), Some("login")));
+        let verifier = SiteVerifier::new(CaptchaProvider::ReCaptcha, "s").hostname("example.com").min_score(0.5);
+        let checked = verifier.check(outcome);
+        assert!(!checked.success);
+        assert_eq!(checked.error_codes, ["hostname-mismatch", "score-too-low"]);
+
+        let failed = CaptchaOutcome::from_json(r#"{"success":false,"error-codes":["invalid-input-response"]}"#).unwrap();
+        assert_eq!(verifier.check(failed.clone()), failed);
+        assert!(CaptchaOutcome::from_json("{}").is_err());
+    }
+}
@@ -0,0 +1,425 @@
+// Escalating challenges per client: the first suspicious request gets a JavaScript check setting
+// a cookie, a client coming back suspicious has to solve a proof of work, and one that keeps
+// failing is blocked for a while.
+//
+// Strikes, passes and blocks live in a `state::StateStore`, so instances sharing a Redis store
+// escalate together. Challenge tokens are `timing::PageTimer` tokens bound to the client and the
+// kind of challenge, the solution comes back in the `bg_challenge` cookie set by the page.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::captcha::CaptchaVerifier;
+use crate::state::{NonceStore, StateStore};
+use crate::timing::{PageTimer, TimingCheck};
+use crate::{cookie, crypto, BotGuardError, Verdict};
+
+/// Name of the cookie carrying the solution of a challenge.
+pub const CHALLENGE_COOKIE: &str = "bg_challenge";
+
+/// What a client has to do to get through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeKind {
+    /// Run a script that sets a cookie and reloads the page.
+    JsCookie,
+    /// Find a number `n` such that SHA-512 of `<token>:<n>` starts with `difficulty` zero bits.
+    ProofOfWork { difficulty: u8 },
+}
+
+/// A challenge to serve instead of the page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub kind: ChallengeKind,
+    /// Signed token the solution has to include, only valid for the client it was issued to.
+    pub token: String,
+}
+
+/// What to do with a request, see [`ChallengeFlow::step`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChallengeStep {
+    Allow,
+    /// Serve the challenge, e.g. rendered with [`ChallengeFlow::render`].
+    Challenge(Challenge),
+    /// The client failed too often and is blocked until the block expires.
+    Block,
+}
+
+/// Renders the page serving a challenge, replaceable with [`ChallengeFlow::pages`] to match the
+/// look of the site.
+pub trait ChallengePage: Send + Sync {
+    /// The HTML of the page, it has to set the [`CHALLENGE_COOKIE`] to the solution and reload.
+    fn render(&self, challenge: &Challenge) -> String;
+}
+
+/// Minimal pages solving the challenges in the browser.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultPages;
+
+impl ChallengePage for DefaultPages {
+    fn render(&self, challenge: &Challenge) -> String {
+        let script = match challenge.kind {
+            ChallengeKind::JsCookie => format!("document.cookie=\"{}={}; path=/; SameSite=Lax\";location.reload();", CHALLENGE_COOKIE, challenge.token),
+            ChallengeKind::ProofOfWork { difficulty } => format!(
+                "(async()=>{{const t=\"{}\",e=new TextEncoder();for(let n=0;;n++){{\
+                 const h=new Uint8Array(await crypto.subtle.digest(\"SHA-512\",e.encode(t+\":\"+n)));\
+                 let z=0;for(const b of h){{if(b){{z+=Math.clz32(b)-24;break}}z+=8}}\
+                 if(z>={}){{document.cookie=\"{}=\"+t+\":\"+n+\"; path=/; SameSite=Lax\";location.reload();return}}}}}})();",
+                challenge.token, difficulty, CHALLENGE_COOKIE
+            ),
+        };
+        format!(
+            "<!doctype html><html><head><meta charset=\"utf-8\"><title>Checking your browser</title></head>\
+             <body><p>Checking your browser, this takes a moment.</p>\
+             <noscript><p>Please enable JavaScript to continue.</p></noscript><script>{}</script></body></html>",
+            script
+        )
+    }
+}
+
+/// Per-client challenge escalation.
+///
+/// ```
+/// use std::sync::Arc;
+/// use BotGuardLib::challenge::{ChallengeFlow, ChallengeKind, ChallengeStep};
+/// use BotGuardLib::state::MemoryStore;
+/// use BotGuardLib::Verdict;
+///
+/// let flow = ChallengeFlow::new(Arc::new(MemoryStore::new()), b"a long random server secret").difficulty(4);
+/// let client = "203.0.113.7";
+/// assert_eq!(flow.step(client, Verdict::Human).unwrap(), ChallengeStep::Allow);
+///
+/// let ChallengeStep::Challenge(first) = flow.step(client, Verdict::Suspicious).unwrap() else { panic!() };
+/// assert_eq!(first.kind, ChallengeKind::JsCookie);
+/// assert!(flow.render(&first).contains("bg_challenge"));
+///
+/// // a browser solves the challenge and gets through from then on
+/// let cookie = format!("bg_challenge={}", first.token);
+/// assert!(flow.verify(client, &cookie).unwrap());
+/// assert_eq!(flow.step(client, Verdict::Suspicious).unwrap(), ChallengeStep::Allow);
+///
+/// // a client coming back without solving it has to do more
+/// let other = "198.51.100.4";
+/// assert!(matches!(flow.step(other, Verdict::Suspicious).unwrap(), ChallengeStep::Challenge(_)));
+/// let ChallengeStep::Challenge(second) = flow.step(other, Verdict::Suspicious).unwrap() else { panic!() };
+/// assert_eq!(second.kind, ChallengeKind::ProofOfWork { difficulty: 4 });
+/// ```
+pub struct ChallengeFlow {
+    store: Arc<dyn StateStore>,
+    timer: PageTimer,
+    pages: Box<dyn ChallengePage>,
+    proof_of_work_after: u64,
+    block_after: u64,
+    difficulty: u8,
+    block_ttl: Duration,
+    pass_ttl: Duration,
+    memory: Duration,
+    nonces: Option<NonceStore>,
+}
+
+impl fmt::Debug for ChallengeFlow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChallengeFlow")
+            .field("proof_of_work_after", &self.proof_of_work_after)
+            .field("block_after", &self.block_after)
+            .field("difficulty", &self.difficulty)
+            .field("block_ttl", &self.block_ttl)
+            .field("pass_ttl", &self.pass_ttl)
+            .field("nonces", &self.nonces)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ChallengeFlow {
+    /// The first strike gets a JavaScript check, the second and third a proof of work of 16
+    /// bits, the fourth blocks for an hour. Strikes are forgotten after an hour, a solved
+    /// challenge lets the client through for a day and a challenge has to be solved within 10
+    /// minutes. Every instance needs the same secret.
+    pub fn new(store: Arc<dyn StateStore>, secret: &[u8]) -> Self {
+        ChallengeFlow {
+            store,
+            timer: PageTimer::new(secret).human_floor(Duration::ZERO).max_age(Duration::from_secs(600)),
+            pages: Box::new(DefaultPages),
+            proof_of_work_after: 2,
+            block_after: 4,
+            difficulty: 16,
+            block_ttl: Duration::from_secs(3600),
+            pass_ttl: Duration::from_secs(24 * 60 * 60),
+            memory: Duration::from_secs(3600),
+            nonces: None,
+        }
+    }
+
+    /// The strike from which on the proof of work is served instead of the JavaScript check.
+    pub fn proof_of_work_after(mut self, strikes: u64) -> Self {
+        self.proof_of_work_after = strikes.max(1);
+        self
+    }
+
+    /// The strike that blocks the client.
+    pub fn block_after(mut self, strikes: u64) -> Self {
+        self.block_after = strikes.max(1);
+        self
+    }
+
+    /// Leading zero bits of the proof of work, each bit doubles the work of the client.
+    pub fn difficulty(mut self, bits: u8) -> Self {
+        self.difficulty = bits.min(64);
+        self
+    }
+
+    pub fn block_ttl(mut self, ttl: Duration) -> Self {
+        self.block_ttl = ttl;
+        self
+    }
+
+    /// How long a solved challenge lets the client through.
+    pub fn pass_ttl(mut self, ttl: Duration) -> Self {
+        self.pass_ttl = ttl;
+        self
+    }
+
+    /// How long strikes are remembered after the first one.
+    pub fn memory(mut self, memory: Duration) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    /// Accepts every challenge token only once, a replayed solution counts as a wrong one. The
+    /// nonces need to be remembered for at least the 10 minutes a challenge can be solved in.
+    /// Only requests [`ChallengeFlow::step`] did not let through should then be verified.
+    pub fn replay_protection(mut self, nonces: NonceStore) -> Self {
+        self.nonces = Some(nonces);
+        self
+    }
+
+    pub fn pages<P: ChallengePage + 'static>(mut self, pages: P) -> Self {
+        self.pages = Box::new(pages);
+        self
+    }
+
+    /// Decides about a request of the client judged `verdict`. Human requests are let through,
+    /// every other request counts as a strike unless the client solved a challenge recently.
+    pub fn step(&self, client: &str, verdict: Verdict) -> Result<ChallengeStep, BotGuardError> {
+        if self.store.get(&key("block", client))?.is_some() {
+            return Ok(ChallengeStep::Block);
+        }
+        if verdict == Verdict::Human || self.store.get(&key("pass", client))?.is_some() {
+            return Ok(ChallengeStep::Allow);
+        }
+        self.strike(client)
+    }
+
+    /// Renders the challenge with the [`ChallengePage`].
+    pub fn render(&self, challenge: &Challenge) -> String {
+        self.pages.render(challenge)
+    }
+
+    /// Checks the solution in the `Cookie` header of the client's request. Only the kind of
+    /// challenge last issued to the client is accepted, an escalated client cannot fall back to
+    /// the token of an earlier JavaScript check. A solved challenge lets the client through for
+    /// the pass duration, a wrong solution counts as a strike. Without the cookie nothing is
+    /// recorded.
+    pub fn verify(&self, client: &str, cookie_header: &str) -> Result<bool, BotGuardError> {
+        self.verify_at(client, cookie_header, SystemTime::now())
+    }
+
+    fn verify_at(&self, client: &str, cookie_header: &str, now: SystemTime) -> Result<bool, BotGuardError> {
+        let Some(solution) = cookie::value(cookie_header, CHALLENGE_COOKIE).filter(|solution| !solution.is_empty()) else { return Ok(false) };
+        let (token, solved) = match (solution.split_once(':'), self.issued(client)?) {
+            (None, Some(kind @ ChallengeKind::JsCookie)) => (solution, self.valid_token(client, kind, solution, now)),
+            (Some((token, nonce)), Some(kind @ ChallengeKind::ProofOfWork { difficulty })) => {
+                (token, self.valid_token(client, kind, token, now) && nonce.parse::<u64>().is_ok() && zero_bits(solution) >= u32::from(difficulty))
+            }
+            (None, _) => (solution, false),
+            (Some((token, _)), _) => (token, false),
+        };
+        let solved = match &self.nonces {
+            Some(nonces) if solved => nonces.redeem(token)?,
+            _ => solved,
+        };
+        if solved {
+            self.store.set(&key("pass", client), "1", self.pass_ttl)?;
+        } else {
+            self.strike(client)?;
+        }
+        Ok(solved)
+    }
+
+    /// Checks a CAPTCHA token of the client with the verifier, e.g. from a [`ChallengePage`]
+    /// rendering a CAPTCHA widget. Passing lets the client through like a solved challenge,
+    /// failing counts as a strike. A [`crate::captcha::SiteVerifier`] does its I/O on a pool of
+    /// its own, the strike is recorded in the store on the calling thread as with
+    /// [`ChallengeFlow::verify`].
+    pub async fn verify_captcha<V: CaptchaVerifier>(&self, client: &str, verifier: &V, token: &str, remote_ip: Option<IpAddr>) -> Result<bool, BotGuardError> {
+        let outcome = verifier.verify(token, remote_ip).await?;
+        if outcome.success {
+            self.store.set(&key("pass", client), "1", self.pass_ttl)?;
+        } else {
+            self.strike(client)?;
+        }
+        Ok(outcome.success)
+    }
+
+    fn strike(&self, client: &str) -> Result<ChallengeStep, BotGuardError> {
+        let strikes = self.store.increment(&key("strikes", client), self.memory)?;
+        if strikes >= self.block_after {
+            self.store.set(&key("block", client), "1", self.block_ttl)?;
+            return Ok(ChallengeStep::Block);
+        }
+        let kind = match strikes >= self.proof_of_work_after {
+            true => ChallengeKind::ProofOfWork { difficulty: self.difficulty },
+            false => ChallengeKind::JsCookie,
+        };
+        let issued = match kind {
+            ChallengeKind::JsCookie => "js".to_string(),
+            ChallengeKind::ProofOfWork { difficulty } => format!("pow{}", difficulty),
+        };
+        self.store.set(&key("issued", client), &issued, self.memory)?;
+        Ok(ChallengeStep::Challenge(Challenge { kind, token: self.timer.issue(&context(client, kind)) }))
+    }
+
+    /// The kind of challenge [`ChallengeFlow::strike`] last issued to the client.
+    fn issued(&self, client: &str) -> Result<Option<ChallengeKind>, BotGuardError> {
+        Ok(self.store.get(&key("issued", client))?.and_then(|issued| match issued.strip_prefix("pow") {
+            Some(difficulty) => difficulty.parse().ok().map(|difficulty| ChallengeKind::ProofOfWork { difficulty }),
+            None => (issued == "js").then_some(ChallengeKind::JsCookie),
+        }))
+    }
+
+    fn valid_token(&self, client: &str, kind: ChallengeKind, token: &str, now: SystemTime) -> bool {
+        matches!(self.timer.verify_at(&context(client, kind), token, now), TimingCheck::Passed(_) | TimingCheck::TooFast(_))
+    }
+}
+
+fn key(kind: &str, client: &str) -> String {
+    format!("botguard:challenge:{}:{}", kind, client)
+}
+
+/// What a token is bound to, a JavaScript check token cannot be passed off as a proof of work.
+fn context(client: &str, kind: ChallengeKind) -> String {
+    match kind {
+        ChallengeKind::JsCookie => format!("challenge\njs\n{}", client),
+        ChallengeKind::ProofOfWork { difficulty } => format!("challenge\npow{}\n{}", difficulty, client),
+    }
+}
+
+/// Leading zero bits of the SHA-512 of the solution.
+fn zero_bits(solution: &str) -> u32 {
+    let hash = crypto::sha512(solution.as_bytes());
+    let zero_bytes = hash.iter().take_while(|b| **b == 0).count();
+    (zero_bytes as u32 * 8) + hash.get(zero_bytes).map_or(0, |b| b.leading_zeros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::MemoryStore;
+
+    fn solve(token: &str, difficulty: u8) -> String {
+        (0u64..).map(|n| format!("{}:{}", token, n)).find(|solution| zero_bits(solution) >= u32::from(difficulty)).unwrap()
+    }
+
+    #[test]
+    fn escalates_to_a_block() {
+        let flow = ChallengeFlow::new(Arc::new(MemoryStore::new()), b"secret").difficulty(6).block_after(4);
+        let kinds = (0..3)
+            .map(|_| match flow.step("a", Verdict::Bot).unwrap() {
+                ChallengeStep::Challenge(challenge) => challenge.kind,
+                step => panic!("{:?}", step),
+            })
+            .collect::<Vec<_>>();
+        let pow = ChallengeKind::ProofOfWork { difficulty: 6 };
+        assert_eq!(kinds, [ChallengeKind::JsCookie, pow, pow]);
+
+        // a wrong solution is the fourth strike
+        assert!(!flow.verify("a", "bg_challenge=1.2:3").unwrap());
+        assert_eq!(flow.step("a", Verdict::Human).unwrap(), ChallengeStep::Block);
+        assert!(matches!(flow.step("b", Verdict::Suspicious).unwrap(), ChallengeStep::Challenge(_)));
+        // without a cookie nothing is counted
+        assert!(!flow.verify("b", "theme=dark").unwrap());
+        assert!(matches!(flow.step("b", Verdict::Suspicious).unwrap(), ChallengeStep::Challenge(Challenge { kind: ChallengeKind::ProofOfWork { .. }, .. })));
+    }
+
+    #[test]
+    fn verifies_solutions() {
+        let flow = ChallengeFlow::new(Arc::new(MemoryStore::new()), b"secret").difficulty(8).proof_of_work_after(1).block_after(10);
+        let ChallengeStep::Challenge(challenge) = flow.step("a", Verdict::Suspicious).unwrap() else { panic!() };
+        assert!(flow.render(&challenge).contains("crypto.subtle.digest(\"SHA-512\""));
+        let solution = solve(&challenge.token, 8);
+
+        // bound to the client and the kind of challenge
+        assert!(!flow.verify("b", &format!("bg_challenge={}", solution)).unwrap());
+        assert!(!flow.verify("a", &format!("bg_challenge={}", challenge.token)).unwrap());
+        let later = SystemTime::now() + Duration::from_secs(601);
+        assert!(!flow.verify_at("a", &format!("bg_challenge={}", solution), later).unwrap());
+        assert!(flow.verify("a", &format!("x=1; bg_challenge={}", solution)).unwrap());
+        assert_eq!(flow.step("a", Verdict::Bot).unwrap(), ChallengeStep::Allow);
+    }
+
+    #[test]
+    fn rejects_replayed_solutions() {
+        let nonces = NonceStore::new(Arc::new(MemoryStore::new()), Duration::from_secs(600));
+        let flow = ChallengeFlow::new(Arc::new(MemoryStore::new()), b"secret").difficulty(4).proof_of_work_after(1).replay_protection(nonces.clone());
+        let ChallengeStep::Challenge(challenge) = flow.step("a", Verdict::Suspicious).unwrap() else { panic!() };
+        let cookie = format!("bg_challenge={}", solve(&challenge.token, 4));
+        assert!(flow.verify("a", &cookie).unwrap());
+        assert!(!flow.verify("a", &cookie).unwrap());
+        // another solution of the same challenge is a replay too
+        let other = (0u64..).map(|n| format!("{}:{}", challenge.token, n)).filter(|solution| zero_bits(solution) >= 4).nth(1).unwrap();
+        assert!(!flow.verify("a", &format!("bg_challenge={}", other)).unwrap());
+        assert_eq!((nonces.stats().redeemed, nonces.stats().replays), (1, 2));
+    }
+
+    #[test]
+    fn escalated_clients_cannot_fall_back_to_the_javascript_check() {
+        let flow = ChallengeFlow::new(Arc::new(MemoryStore::new()), b"secret").difficulty(4).block_after(10);
+        let ChallengeStep::Challenge(js) = flow.step("a", Verdict::Suspicious).unwrap() else { panic!() };
+        assert_eq!(js.kind, ChallengeKind::JsCookie);
+        let ChallengeStep::Challenge(pow) = flow.step("a", Verdict::Suspicious).unwrap() else { panic!() };
+        assert_eq!(pow.kind, ChallengeKind::ProofOfWork { difficulty: 4 });
+
+        // the earlier token is still signed and fresh, but no longer the challenge of the client
+        assert!(!flow.verify("a", &format!("bg_challenge={}", js.token)).unwrap());
+        assert!(flow.verify("a", &format!("bg_challenge={}", solve(&pow.token, 4))).unwrap());
+
+        // nothing issued, nothing to solve
+        let ChallengeStep::Challenge(issued) = ChallengeFlow::new(Arc::new(MemoryStore::new()), b"secret").step("b", Verdict::Bot).unwrap() else { panic!() };
+        assert!(!flow.verify("b", &format!("bg_challenge={}", issued.token)).unwrap());
+    }
+
+    #[test]
+    fn hands_off_to_a_captcha() {
+        use crate::captcha::CaptchaOutcome;
+        use crate::source::block_on;
+
+        struct Answer(&'static str);
+        impl CaptchaVerifier for Answer {
+            async fn verify(&self, token: &str, _remote_ip: Option<IpAddr>) -> Result<CaptchaOutcome, BotGuardError> {
+                Ok(CaptchaOutcome { success: token == self.0, ..CaptchaOutcome::default() })
+            }
+        }
+        let flow = ChallengeFlow::new(Arc::new(MemoryStore::new()), b"secret").block_after(2);
+        flow.step("a", Verdict::Suspicious).unwrap();
+        assert!(!block_on(flow.verify_captcha("a", &Answer("right"), "wrong", None)).unwrap());
+        assert_eq!(flow.step("a", Verdict::Human).unwrap(), ChallengeStep::Block);
+        flow.step("b", Verdict::Suspicious).unwrap();
+        assert!(block_on(flow.verify_captcha("b", &Answer("right"), "right", None)).unwrap());
+        assert_eq!(flow.step("b", Verdict::Bot).unwrap(), ChallengeStep::Allow);
+    }
+
+    #[test]
+    fn renders_custom_pages() {
+        struct Branded;
+        impl ChallengePage for Branded {
+            fn render(&self, challenge: &Challenge) -> String {
+                format!("<h1>Acme</h1>{:?}", challenge.kind)
+            }
+        }
+        let flow = ChallengeFlow::new(Arc::new(MemoryStore::new()), b"secret").pages(Branded);
+        let challenge = Challenge { kind: ChallengeKind::JsCookie, token: "t".to_string() };
+        assert_eq!(flow.render(&challenge), "<h1>Acme</h1>JsCookie");
+        assert!(DefaultPages.render(&challenge).contains("document.cookie=\"bg_challenge=t; path=/; SameSite=Lax\""));
+    }
+}
@@ -0,0 +1,298 @@
+// A small logistic regression scoring user-agents no pattern knows, trained on labeled samples,
+// e.g. the bundled corpus plus the traffic of a site, as one more input to `request::BotGuard`.
+//
+// Features are the character trigrams and words of the lowercased user-agent and, for samples
+// with headers, the names of the headers sent and how many there are. They are hashed into a
+// fixed number of weights, so the model size does not grow with the vocabulary. Training is
+// deterministic: the same samples and settings always give the same model.
+//
+// Models are saved as text, one `index weight` line per weight that is not zero.
+
+use std::fs;
+use std::path::Path;
+
+use crate::request::RequestSnapshot;
+use crate::tester::Label;
+use crate::BotGuardError;
+
+/// First line of a saved model.
+const HEADER: &str = "# botguard classifier v1";
+
+/// A labeled user-agent, with the headers of its request if they are known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub user_agent: String,
+    pub headers: Vec<(String, String)>,
+    pub label: Label,
+}
+
+impl Sample {
+    pub fn new(user_agent: &str, label: Label) -> Self {
+        Sample { user_agent: user_agent.to_string(), headers: Vec::new(), label }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The user-agent and headers of a request.
+    pub fn from_request(request: &RequestSnapshot, label: Label) -> Self {
+        Sample { user_agent: request.effective_user_agent().to_string(), headers: request.headers.clone(), label }
+    }
+}
+
+/// Settings of [`Trainer::train`].
+///
+/// ```
+/// use BotGuardLib::classifier::{Sample, Trainer};
+/// use BotGuardLib::tester::Label;
+///
+/// let samples = [
+///     Sample::new("python-requests/2.31.0", Label::Bot),
+///     Sample::new("Go-http-client/1.1", Label::Bot),
+///     Sample::new("okhttp/4.9.0", Label::Bot),
+///     Sample::new("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36", Label::Human),
+///     Sample::new("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0", Label::Human),
+///     Sample::new("Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) AppleWebKit/605.1.15 Version/17.1 Mobile Safari/604.1", Label::Human),
+/// ];
+/// let classifier = Trainer::default().train(&samples);
+/// assert!(classifier.score("python-requests/2.28.1") > 0.5);
+/// assert!(classifier.score("Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0") < 0.5);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trainer {
+    buckets: usize,
+    epochs: usize,
+    learning_rate: f32,
+    regularization: f32,
+}
+
+impl Default for Trainer {
+    fn default() -> Self {
+        Trainer { buckets: 1 << 16, epochs: 30, learning_rate: 2.0, regularization: 1e-6 }
+    }
+}
+
+impl Trainer {
+    /// Number of weights the features are hashed into, `65536` by default.
+    pub fn buckets(mut self, buckets: usize) -> Self {
+        self.buckets = buckets.max(1);
+        self
+    }
+
+    /// Passes over the samples, `30` by default.
+    pub fn epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    /// Step size of the first pass, shrinking with every pass after it.
+    pub fn learning_rate(mut self, rate: f32) -> Self {
+        self.learning_rate = rate;
+        self
+    }
+
+    /// L2 penalty keeping weights of rare features small.
+    pub fn regularization(mut self, penalty: f32) -> Self {
+        self.regularization = penalty;
+        self
+    }
+
+    /// Fits a model to the samples by stochastic gradient descent. Without samples of one label,
+    /// the model learns to always give the other.
+    pub fn train(&self, samples: &[Sample]) -> Classifier {
+        let mut classifier = Classifier { weights: vec![0.0; self.buckets], bias: 0.0 };
+        let features = samples.iter().map(|sample| classifier.features(&sample.user_agent, Some(&sample.headers))).collect::<Vec<_>>();
+        let mut order = (0..samples.len()).collect::<Vec<_>>();
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        for epoch in 0..self.epochs {
+            // the same shuffle on every run, so training is reproducible
+            for i in (1..order.len()).rev() {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                order.swap(i, (seed % (i as u64 + 1)) as usize);
+            }
+            let rate = self.learning_rate / (1.0 + epoch as f32).sqrt();
+            for &i in &order {
+                let target = match samples[i].label {
+                    Label::Bot => 1.0,
+                    Label::Human => 0.0,
+                };
+                let gradient = classifier.predict(&features[i]) - target;
+                classifier.bias -= rate * gradient;
+                for &(bucket, value) in &features[i] {
+                    let weight = &mut classifier.weights[bucket];
+                    *weight -= rate * (gradient * value + self.regularization * *weight);
+                }
+            }
+        }
+        classifier
+    }
+}
+
+/// A trained model, see [`Trainer::train`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classifier {
+    weights: Vec<f32>,
+    bias: f32,
+}
+
+impl Classifier {
+    /// How likely the user-agent is a bot, between `0.0` and `1.0`, from the user-agent alone.
+    pub fn score(&self, user_agent: &str) -> f32 {
+        self.predict(&self.features(user_agent, None))
+    }
+
+    /// Same as [`Classifier::score`], with the header features of the request.
+    pub fn score_request(&self, request: &RequestSnapshot) -> f32 {
+        self.predict(&self.features(request.effective_user_agent(), Some(&request.headers)))
+    }
+
+    /// Number of weights the features are hashed into.
+    pub fn buckets(&self) -> usize {
+        self.weights.len()
+    }
+
+    /// Writes the model to a file, see [`Classifier::load`].
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BotGuardError> {
+        fs::write(&path, self.to_text()).map_err(|e| BotGuardError::IoError { path: path.as_ref().display().to_string(), reason: e.to_string() })
+    }
+
+    /// Reads a model written by [`Classifier::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BotGuardError> {
+        let text = fs::read_to_string(&path).map_err(|e| BotGuardError::IoError { path: path.as_ref().display().to_string(), reason: e.to_string() })?;
+        Classifier::from_text(&text)
+    }
+
+    /// The model in the format of [`Classifier::save`].
+    ///
+    /// ```
+    /// use BotGuardLib::classifier::{Classifier, Sample, Trainer};
+    /// use BotGuardLib::tester::Label;
+    ///
+    /// let classifier = Trainer::default().buckets(64).train(&[Sample::new("curl/8.4", Label::Bot)]);
+    /// let text = classifier.to_text();
+    /// assert!(text.starts_with("# botguard classifier v1\nbuckets 64\nbias "));
+    /// assert_eq!(Classifier::from_text(&text).unwrap(), classifier);
+    /// ```
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{}\nbuckets {}\nbias {}\n", HEADER, self.weights.len(), self.bias);
+        for (i, weight) in self.weights.iter().enumerate().filter(|(_, weight)| **weight != 0.0) {
+            out.push_str(&format!("{} {}\n", i, weight));
+        }
+        out
+    }
+
+    /// Parses a model in the format of [`Classifier::save`]. Fails with
+    /// [`BotGuardError::InvalidImport`] on the first line that does not belong there.
+    pub fn from_text(text: &str) -> Result<Self, BotGuardError> {
+        let error = |line: usize, reason: &str| BotGuardError::InvalidImport { format: "classifier".to_string(), line, reason: reason.to_string() };
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(error(1, "not a classifier model"));
+        }
+        let mut value = |key: &str| match lines.next() {
+            Some((number, line)) => line.strip_prefix(key).map(str::trim).ok_or_else(|| error(number, &format!("expected `{}`", key))).map(|value| (number, value)),
+            None => Err(error(0, &format!("missing `{}`", key))),
+        };
+        let (number, buckets) = value("buckets")?;
+        let buckets = buckets.parse::<usize>().ok().filter(|buckets| *buckets > 0).ok_or_else(|| error(number, "invalid bucket count"))?;
+        let (number, bias) = value("bias")?;
+        let bias = bias.parse::<f32>().map_err(|_| error(number, "invalid bias"))?;
+        let mut weights = vec![0.0; buckets];
+        for (number, line) in lines.filter(|(_, line)| !line.is_empty()) {
+            let (index, weight) = line.split_once(' ').ok_or_else(|| error(number, "expected `index weight`"))?;
+            let index = index.parse::<usize>().ok().filter(|index| *index < buckets).ok_or_else(|| error(number, "invalid weight index"))?;
+            weights[index] = weight.trim().parse::<f32>().map_err(|_| error(number, "invalid weight"))?;
+        }
+        Ok(Classifier { weights, bias })
+    }
+
+    fn predict(&self, features: &[(usize, f32)]) -> f32 {
+        let sum = self.bias + features.iter().map(|&(bucket, value)| self.weights[bucket] * value).sum::<f32>();
+        1.0 / (1.0 + (-sum).exp())
+    }
+
+    /// The hashed features with their values, scaled so that every user-agent weighs the same.
+    /// Without `headers` there are no header features at all, an empty list is a request
+    /// without headers.
+    fn features(&self, user_agent: &str, headers: Option<&[(String, String)]>) -> Vec<(usize, f32)> {
+        let user_agent = user_agent.trim().to_ascii_lowercase();
+        let mut hashed = Vec::new();
+        let padded = format!("^{}$", user_agent);
+        for gram in padded.as_bytes().windows(3) {
+            hashed.push(hash(b"3:", gram));
+        }
+        for word in user_agent.split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()) {
+            hashed.push(hash(b"w:", word.as_bytes()));
+        }
+        if let Some(headers) = headers {
+            for (name, _) in headers {
+                hashed.push(hash(b"h:", name.to_ascii_lowercase().as_bytes()));
+            }
+            hashed.push(hash(b"n:", &[headers.len().min(16) as u8]));
+        }
+        hashed.sort_unstable();
+        hashed.dedup();
+        let value = 1.0 / (hashed.len().max(1) as f32).sqrt();
+        hashed.into_iter().map(|hash| ((hash % self.weights.len() as u64) as usize, value)).collect()
+    }
+}
+
+/// FNV-1a of a feature kind and its bytes.
+fn hash(kind: &[u8], bytes: &[u8]) -> u64 {
+    kind.iter().chain(bytes).fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_features_tell_scripts_from_browsers() {
+        let browser = |user_agent: &str, label| Sample::new(user_agent, label).header("Accept", "text/html").header("Accept-Language", "en").header("Accept-Encoding", "gzip");
+        let user_agent = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36";
+        let safari = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 Version/17.1 Safari/605.1.15";
+        let samples = [
+            browser(user_agent, Label::Human),
+            browser(safari, Label::Human),
+            Sample::new(user_agent, Label::Bot),
+            Sample::new(safari, Label::Bot).header("Accept", "*/*"),
+        ];
+        let classifier = Trainer::default().buckets(1 << 10).train(&samples);
+        let headless = RequestSnapshot::new("GET", "/").user_agent(user_agent).header("Accept", "*/*");
+        let real = RequestSnapshot::new("GET", "/").user_agent(user_agent).header("Accept", "text/html").header("Accept-Language", "de").header("Accept-Encoding", "br");
+        assert!(classifier.score_request(&headless) > 0.5);
+        assert!(classifier.score_request(&real) < 0.5);
+        assert_eq!(Trainer::default().buckets(1 << 10).train(&samples), classifier);
+    }
+
+    #[test]
+    fn classified_requests_feed_the_guard() {
+        use crate::request::{BotGuard, Reason};
+        use crate::{BotDetector, Verdict};
+
+        let samples = [Sample::new("dataharvester/3.1", Label::Bot), Sample::new("Mozilla/5.0 (X11; Linux x86_64) Firefox/120.0", Label::Human)];
+        let guard = BotGuard::new(BotDetector::new("^curl/")).classifier(Trainer::default().train(&samples));
+        let evaluated = guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("DataHarvester/3.2"));
+        assert_eq!(evaluated.verdict, Verdict::Bot);
+        assert!(matches!(evaluated.reasons[..], [Reason::Heuristic, Reason::Classified { score }] if score > 0.8));
+        let matched = guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("curl/8.4"));
+        assert_eq!(matched.reasons.len(), 1);
+    }
+
+    #[test]
+    fn invalid_models_are_rejected() {
+        let invalid = |text: &str| match Classifier::from_text(text) {
+            Err(BotGuardError::InvalidImport { line, .. }) => line,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(invalid("buckets 4"), 1);
+        assert_eq!(invalid("# botguard classifier v1\nbuckets 0\nbias 0"), 2);
+        assert_eq!(invalid("# botguard classifier v1\nbuckets 4\nbias 0.5\n4 1.0"), 4);
+        let model = Classifier::from_text("# botguard classifier v1\nbuckets 4\nbias 0\n\n2 -1.5\n").unwrap();
+        assert_eq!((model.buckets(), model.weights[2]), (4, -1.5));
+    }
+}
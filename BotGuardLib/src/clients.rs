@@ -0,0 +1,120 @@
+// Programmatic HTTP clients: command line tools, language standard libraries and SDKs. They are
+// not crawlers, so deployments often allow crawlers but throttle these.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use regex::{RegexSet, RegexSetBuilder};
+
+/// Group of the bundled patterns holding the HTTP clients.
+pub const HTTP_CLIENTS_GROUP: &str = "http-clients";
+
+/// A recognized HTTP library or tool, see [`crate::BotDetector::check_http_client`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientLibrary {
+    Curl,
+    Wget,
+    PythonRequests,
+    PythonUrllib,
+    Httpx,
+    Aiohttp,
+    Scrapy,
+    GoHttpClient,
+    Java,
+    ApacheHttpClient,
+    OkHttp,
+    Axios,
+    NodeFetch,
+    Undici,
+    LibwwwPerl,
+    Guzzle,
+}
+
+/// Recognized libraries with their user-agent pattern, matched case-insensitively.
+const LIBRARIES: &[(ClientLibrary, &str)] = &[
+    (ClientLibrary::Curl, r"^curl/"),
+    (ClientLibrary::Wget, r"^wget/"),
+    (ClientLibrary::PythonRequests, r"python-requests/"),
+    (ClientLibrary::PythonUrllib, r"python-urllib/"),
+    (ClientLibrary::Httpx, r"python-httpx/"),
+    (ClientLibrary::Aiohttp, r"aiohttp/"),
+    (ClientLibrary::Scrapy, r"scrapy/"),
+    (ClientLibrary::GoHttpClient, r"go-http-client/"),
+    (ClientLibrary::Java, r"^java/"),
+    (ClientLibrary::ApacheHttpClient, r"apache-httpclient/"),
+    (ClientLibrary::OkHttp, r"okhttp/"),
+    (ClientLibrary::Axios, r"^axios/"),
+    (ClientLibrary::NodeFetch, r"node-fetch"),
+    (ClientLibrary::Undici, r"^undici"),
+    (ClientLibrary::LibwwwPerl, r"libwww-perl/"),
+    (ClientLibrary::Guzzle, r"guzzlehttp/"),
+];
+
+impl ClientLibrary {
+    /// The product name as it appears in user-agents.
+    pub fn name(self) -> &'static str {
+        match self {
+            ClientLibrary::Curl => "curl",
+            ClientLibrary::Wget => "Wget",
+            ClientLibrary::PythonRequests => "python-requests",
+            ClientLibrary::PythonUrllib => "Python-urllib",
+            ClientLibrary::Httpx => "python-httpx",
+            ClientLibrary::Aiohttp => "aiohttp",
+            ClientLibrary::Scrapy => "Scrapy",
+            ClientLibrary::GoHttpClient => "Go-http-client",
+            ClientLibrary::Java => "Java",
+            ClientLibrary::ApacheHttpClient => "Apache-HttpClient",
+            ClientLibrary::OkHttp => "okhttp",
+            ClientLibrary::Axios => "axios",
+            ClientLibrary::NodeFetch => "node-fetch",
+            ClientLibrary::Undici => "undici",
+            ClientLibrary::LibwwwPerl => "libwww-perl",
+            ClientLibrary::Guzzle => "GuzzleHttp",
+        }
+    }
+}
+
+impl fmt::Display for ClientLibrary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The first recognized library in the user-agent.
+pub(crate) fn http_client(user_agent: &str) -> Option<ClientLibrary> {
+    static SET: OnceLock<RegexSet> = OnceLock::new();
+    let set = SET.get_or_init(|| {
+        RegexSetBuilder::new(LIBRARIES.iter().map(|(_, pattern)| pattern))
+            .case_insensitive(true)
+            .build()
+            .expect("the library patterns are valid")
+    });
+    set.matches(user_agent).iter().next().map(|i| LIBRARIES[i].0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BotDetector;
+
+    #[test]
+    fn recognizes_libraries() {
+        assert_eq!(http_client("curl/8.4.0"), Some(ClientLibrary::Curl));
+        assert_eq!(http_client("Go-http-client/2.0"), Some(ClientLibrary::GoHttpClient));
+        assert_eq!(http_client("Java/17.0.2"), Some(ClientLibrary::Java));
+        assert_eq!(http_client("okhttp/4.12.0"), Some(ClientLibrary::OkHttp));
+        assert_eq!(http_client("Python/3.11 aiohttp/3.9.3"), Some(ClientLibrary::Aiohttp));
+        assert_eq!(http_client("Mozilla/5.0 (compatible; curl/8.0)"), None);
+        assert_eq!(http_client("JavaScript"), None);
+        assert_eq!(ClientLibrary::PythonRequests.to_string(), "python-requests");
+    }
+
+    #[test]
+    fn every_library_is_in_the_bundled_group() {
+        let detector = BotDetector::default();
+        for (library, _) in LIBRARIES {
+            let user_agent = format!("{}/1.0", library.name());
+            assert_eq!(detector.classify(&user_agent), Some(HTTP_CLIENTS_GROUP), "{}", user_agent);
+        }
+    }
+}
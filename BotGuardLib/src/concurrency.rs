@@ -0,0 +1,178 @@
+// Limits on requests in flight at the same time, per client key and over all clients. Some bots
+// open hundreds of parallel connections, far more than a browser, which stays below about six
+// per host.
+//
+// A request holds an `InFlight` permit while it is served, dropping the permit releases the slot,
+// so a request that panics or returns early cannot leak one.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+
+/// Why [`ConcurrencyGuard::acquire`] refused a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConcurrencyExceeded {
+    /// The client key already has `limit` requests in flight.
+    Key { limit: usize },
+    /// All clients together already have `limit` requests in flight.
+    Global { limit: usize },
+}
+
+impl fmt::Display for ConcurrencyExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConcurrencyExceeded::Key { limit } => write!(f, "more than {} parallel requests from the client", limit),
+            ConcurrencyExceeded::Global { limit } => write!(f, "more than {} parallel requests in total", limit),
+        }
+    }
+}
+
+/// Counts the requests in flight of each client key, e.g. an address, a session or an API key.
+///
+/// ```
+/// use BotGuardLib::concurrency::{ConcurrencyExceeded, ConcurrencyGuard};
+///
+/// let guard = ConcurrencyGuard::new(2).global_limit(3);
+/// let first = guard.acquire("203.0.113.7").unwrap();
+/// let _second = guard.acquire("203.0.113.7").unwrap();
+/// assert_eq!(guard.acquire("203.0.113.7").unwrap_err(), ConcurrencyExceeded::Key { limit: 2 });
+/// let _other = guard.acquire("198.51.100.1").unwrap();
+/// assert_eq!(guard.acquire("192.0.2.1").unwrap_err(), ConcurrencyExceeded::Global { limit: 3 });
+///
+/// drop(first);
+/// assert_eq!(guard.in_flight("203.0.113.7"), 1);
+/// assert!(guard.acquire("203.0.113.7").is_ok());
+/// ```
+#[derive(Debug)]
+pub struct ConcurrencyGuard {
+    in_flight: Mutex<Counts>,
+    per_key: usize,
+    global: Option<usize>,
+}
+
+impl ConcurrencyGuard {
+    /// Allows `per_key` requests in flight per client key and any number in total.
+    pub fn new(per_key: usize) -> Self {
+        ConcurrencyGuard { in_flight: Mutex::default(), per_key: per_key.max(1), global: None }
+    }
+
+    pub fn global_limit(mut self, limit: usize) -> Self {
+        self.global = Some(limit.max(1));
+        self
+    }
+
+    pub fn per_key_limit(&self) -> usize {
+        self.per_key
+    }
+
+    /// A permit for one more request of the key, refused if the key or all clients are at their
+    /// limit. A refused request is not counted, so a client blocked while flooding gets its
+    /// slots back as its earlier requests finish.
+    pub fn acquire(&self, key: &str) -> Result<InFlight<'_>, ConcurrencyExceeded> {
+        let mut in_flight = lock(&self.in_flight);
+        if in_flight.keys.get(key).is_some_and(|count| *count >= self.per_key) {
+            return Err(ConcurrencyExceeded::Key { limit: self.per_key });
+        }
+        if let Some(limit) = self.global.filter(|limit| in_flight.total >= *limit) {
+            return Err(ConcurrencyExceeded::Global { limit });
+        }
+        *in_flight.keys.entry(key.to_string()).or_default() += 1;
+        in_flight.total += 1;
+        Ok(InFlight { guard: self, key: key.to_string() })
+    }
+
+    /// Requests of the key in flight right now.
+    pub fn in_flight(&self, key: &str) -> usize {
+        lock(&self.in_flight).keys.get(key).copied().unwrap_or(0)
+    }
+
+    /// Requests of all clients in flight right now.
+    pub fn total(&self) -> usize {
+        lock(&self.in_flight).total
+    }
+
+    fn release(&self, key: &str) {
+        let mut in_flight = lock(&self.in_flight);
+        if let Some(count) = in_flight.keys.get_mut(key) {
+            *count -= 1;
+            // idle keys are forgotten so the map only holds clients with requests in flight
+            if *count == 0 {
+                in_flight.keys.remove(key);
+            }
+            in_flight.total -= 1;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    keys: HashMap<String, usize>,
+    total: usize,
+}
+
+/// A slot of [`ConcurrencyGuard`], released when dropped.
+#[derive(Debug)]
+pub struct InFlight<'a> {
+    guard: &'a ConcurrencyGuard,
+    key: String,
+}
+
+impl InFlight<'_> {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.guard.release(&self.key);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::thread;
+
+    #[test]
+    fn releases_slots_on_drop() {
+        let guard = ConcurrencyGuard::new(1);
+        {
+            let permit = guard.acquire("a").unwrap();
+            assert_eq!(permit.key(), "a");
+            assert_eq!(guard.acquire("a").unwrap_err(), ConcurrencyExceeded::Key { limit: 1 });
+            assert_eq!((guard.in_flight("a"), guard.total()), (1, 1));
+        }
+        assert_eq!((guard.in_flight("a"), guard.total()), (0, 0));
+        assert!(lock(&guard.in_flight).keys.is_empty());
+        assert_eq!(ConcurrencyGuard::new(0).per_key_limit(), 1);
+        assert_eq!(ConcurrencyExceeded::Global { limit: 8 }.to_string(), "more than 8 parallel requests in total");
+    }
+
+    #[test]
+    fn limits_parallel_threads() {
+        let guard = ConcurrencyGuard::new(4).global_limit(6);
+        let barrier = Barrier::new(16);
+        let admitted = thread::scope(|scope| {
+            let threads = (0..16)
+                .map(|i| {
+                    let (guard, barrier) = (&guard, &barrier);
+                    scope.spawn(move || {
+                        let permit = guard.acquire(if i % 2 == 0 { "even" } else { "odd" });
+                        // every thread holds its permit until all have tried
+                        barrier.wait();
+                        permit.is_ok()
+                    })
+                })
+                .collect::<Vec<_>>();
+            threads.into_iter().map(|thread| thread.join().unwrap()).filter(|admitted| *admitted).count()
+        });
+        assert_eq!(admitted, 6);
+        assert_eq!(guard.total(), 0);
+    }
+}
@@ -0,0 +1,780 @@
+// Declarative configuration of the whole anti-bot setup, loaded from a TOML file.
+//
+// ```toml
+// [detector]
+// default_patterns = true          # start from the bundled patterns
+// match_mode = "token"             # "substring", "token" or "anchored"
+// pattern_syntax = "glob"          # of [groups] patterns: "regex", "glob" or "literal"
+// empty_user_agent = "suspicious"  # "human", "bot" or "suspicious"
+// disabled_groups = ["monitoring"]
+// bundle_public_key = "A6EHv/POEL4dcN0Y50vAmWfk1jCbpQ1fHdyGZBJVMbg="  # Ed25519
+// bundle_key_encoding = "base64"   # of the key: "hex" or "base64", "base64" unless set
+//
+// [groups]
+// internal-scanners = ["^acme-scanner/"]
+//
+// [allowlist]
+// user_agents = ["^kube-probe/"]
+// networks = ["10.0.0.0/8"]
+//
+// [proxies]                        # read the client address forwarded by these, see `crate::proxy`
+// trusted = ["10.0.0.0/8"]
+// headers = ["x-forwarded-for"]    # "x-forwarded-for" unless set
+//
+// [exempt]                         # never analyzed, see `crate::exempt`
+// networks = ["10.0.0.0/8"]        # also user_agents
+// health_checks = true             # kube-probe, ELB-HealthChecker and others
+// token_secret = "a long random shared secret"
+// rate_limit = false               # whether the rate limit still counts them
+// log = false
+//
+// [references]                     # signed reference IDs on the block page, see `crate::reference`
+// secret = "another long random secret"
+//
+// [thresholds]                     # verdict tiers of the detector score
+// suspicious = 0.5
+// bot = 0.8
+//
+// [policy]
+// default = "allow"
+// shadow = true                    # only report decisions, see `PolicyEngine::shadow`
+//
+// [[policy.route]]
+// path = "/api/*"
+// action = "block"                 # "allow", "block", "challenge", "tag", "rate_limit", "tarpit" or "decoy"
+// min_score = 0.5
+// verified = false
+// enforce = true                   # applies even in shadow mode
+//
+// [[policy.route]]
+// path = "/signup"
+// action = "challenge"
+// not_countries = ["DE", "AT"]     # also countries, datacenter, asns and not_asns
+//
+// [policy.social_previews]         # link preview bots, see `PolicyEngine::social_previews`
+// private = ["/account/*"]         # also public, the only routes they may see
+// action = "block"                 # for the private routes, as in the routes
+//
+// [signals.fingerprint]            # when a costly signal runs, see `crate::sampling`
+// min_score = 0.5                  # only for requests already this suspicious
+// sample = 0.1                     # and one in ten of them
+// user_agents = ["^mozilla/"]      # also paths
+//
+// [pipeline]                       # see `crate::pipeline`
+// stages = ["allowlist", "memory", "user_agent", "ip", "headers", "behavior"]
+//
+// [pipeline.short_circuit]         # skip the later stages once the score reaches this
+// user_agent = 0.9
+// ```
+
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use regex::{Regex, RegexSet};
+
+use crate::exempt::Exemptions;
+use crate::ip::{IpNet, IpRangeSet};
+use crate::literal::group_header;
+use crate::pipeline::{Pipeline, Stage};
+use crate::policy::{Action, Condition, PolicyEngine, PolicyRule, SocialPreviews};
+use crate::proxy::{ClientIpExtractor, ForwardingHeader};
+use crate::reference::ReferenceSigner;
+use crate::sampling::{Sampling, Signal, SignalGate};
+use crate::source::{Encoding, VerifyingKey};
+pub use crate::score::Thresholds;
+use crate::toml::{self, Value};
+use crate::{BotDetector, BotDetectorBuilder, BotGuardError, EmptyUaPolicy, MatchMode, PatternSyntax};
+
+
+/// Clients that are never treated as bots.
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist {
+    user_agents: Vec<String>,
+    user_agent_set: Option<RegexSet>,
+    networks: IpRangeSet<()>,
+}
+
+impl Allowlist {
+    /// User-agent patterns are case-insensitive regexes.
+    pub fn new(user_agents: &[&str], networks: &[IpNet]) -> Result<Self, BotGuardError> {
+        let mut allowlist = Allowlist::default();
+        for pattern in user_agents {
+            Regex::new(pattern)
+                .map_err(|e| BotGuardError::InvalidPattern { pattern: pattern.to_string(), reason: e.to_string() })?;
+            allowlist.user_agents.push(pattern.to_string());
+        }
+        if !allowlist.user_agents.is_empty() {
+            let set = regex::RegexSetBuilder::new(&allowlist.user_agents)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| BotGuardError::InvalidPattern { pattern: allowlist.user_agents.join("|"), reason: e.to_string() })?;
+            allowlist.user_agent_set = Some(set);
+        }
+        allowlist.networks.extend(networks.iter().map(|&net| (net, ())));
+        Ok(allowlist)
+    }
+
+    pub fn user_agents(&self) -> &[String] {
+        &self.user_agents
+    }
+
+    pub fn networks(&self) -> impl Iterator<Item = &IpNet> {
+        self.networks.iter().map(|(net, _)| net)
+    }
+
+    pub fn allows_user_agent(&self, user_agent: &str) -> bool {
+        self.user_agent_set.as_ref().is_some_and(|set| set.is_match(user_agent))
+    }
+
+    pub fn allows_ip(&self, ip: IpAddr) -> bool {
+        self.networks.contains(ip)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.user_agents.is_empty() && self.networks.is_empty()
+    }
+}
+
+/// A loaded configuration file.
+///
+/// ```
+/// use BotGuardLib::config::BotGuardConfig;
+/// use BotGuardLib::policy::{Action, PolicyInput};
+///
+/// let config = BotGuardConfig::from_toml(r#"
+/// [detector]
+/// default_patterns = false
+/// [groups]
+/// scanners = ["^acme-scanner/"]
+/// [[policy.route]]
+/// path = "/api/*"
+/// action = "block"
+/// category = "scanners"
+/// "#).unwrap();
+///
+/// let detector = config.detector().unwrap();
+/// let category = detector.classify("ACME-Scanner/2.0");
+/// assert_eq!(category, Some("scanners"));
+/// let input = PolicyInput::new("/api/orders").category(category).score(1.0);
+/// assert_eq!(config.policy.evaluate(&input).action, Action::Block);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BotGuardConfig {
+    builder: BotDetectorBuilder,
+    disabled_groups: Vec<String>,
+    pub allowlist: Allowlist,
+    pub thresholds: Thresholds,
+    pub policy: PolicyEngine,
+    pub sampling: Sampling,
+    pub pipeline: Pipeline,
+    pub exemptions: Exemptions,
+    /// `None` without a `[proxies]` section.
+    pub proxies: Option<ClientIpExtractor>,
+    /// `None` without a `[references]` section.
+    pub references: Option<ReferenceSigner>,
+}
+
+impl BotGuardConfig {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, BotGuardError> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| BotGuardError::IoError { path: path.as_ref().display().to_string(), reason: e.to_string() })?;
+        BotGuardConfig::from_toml(&text)
+    }
+
+    /// Parses a configuration, failing on syntax errors, unknown keys and invalid values.
+    pub fn from_toml(text: &str) -> Result<Self, BotGuardError> {
+        let document = toml::parse(text).map_err(|e| BotGuardError::InvalidConfig { line: Some(e.line), reason: e.reason })?;
+        let root = Section::new("", &document)?;
+        root.only(&["detector", "groups", "allowlist", "thresholds", "policy", "signals", "pipeline", "exempt", "proxies", "references"])?;
+
+        let detector = root.section("detector")?;
+        detector.only(&["default_patterns", "case_sensitive", "match_mode", "pattern_syntax", "empty_user_agent", "max_input_len", "disabled_groups", "bundle_public_key", "bundle_key_encoding"])?;
+        let mut entries = if detector.bool("default_patterns")?.unwrap_or(true) { crate::_PATTERNS.to_string() } else { String::new() };
+        let syntax = match detector.string("pattern_syntax")?.as_deref() {
+            None | Some("regex") => PatternSyntax::Regex,
+            Some("glob") => PatternSyntax::Glob,
+            Some("literal") => PatternSyntax::Literal,
+            Some(other) => return Err(invalid(format!("unknown pattern_syntax {:?}", other))),
+        };
+        let groups = root.section("groups")?;
+        for (name, _) in groups.entries {
+            if group_header(&format!("[{}]", name)).is_none() {
+                return Err(invalid(format!("group name {:?} may only contain letters, digits, '-' and '_'", name)));
+            }
+            entries.push_str(&format!("\n[{}]\n", name));
+            for pattern in groups.strings(name)?.unwrap_or_default() {
+                if pattern.contains('\n') || group_header(&pattern).is_some() {
+                    return Err(invalid(format!("pattern {:?} of group {:?} looks like a group header or spans lines", pattern, name)));
+                }
+                entries.push_str(&syntax.to_regex(&pattern));
+                entries.push('\n');
+            }
+        }
+
+        let mut builder = BotDetector::builder().patterns(&entries);
+        if let Some(case_sensitive) = detector.bool("case_sensitive")? {
+            builder = builder.case_sensitive(case_sensitive);
+        }
+        if let Some(mode) = detector.string("match_mode")? {
+            builder = builder.match_mode(match mode.as_str() {
+                "substring" => MatchMode::Substring,
+                "token" => MatchMode::Token,
+                "anchored" => MatchMode::Anchored,
+                other => return Err(invalid(format!("unknown match_mode {:?}", other))),
+            });
+        }
+        if let Some(policy) = detector.string("empty_user_agent")? {
+            builder = builder.empty_ua_policy(match policy.as_str() {
+                "human" => EmptyUaPolicy::TreatAsHuman,
+                "bot" => EmptyUaPolicy::TreatAsBot,
+                "suspicious" => EmptyUaPolicy::Suspicious,
+                other => return Err(invalid(format!("unknown empty_user_agent policy {:?}", other))),
+            });
+        }
+        if let Some(max) = detector.number("max_input_len")? {
+            builder = builder.max_input_len(max as usize);
+        }
+        let encoding = match detector.string("bundle_key_encoding")?.as_deref() {
+            None | Some("base64") => Encoding::Base64,
+            Some("hex") => Encoding::Hex,
+            Some(other) => return Err(invalid(format!("unknown bundle_key_encoding {:?}", other))),
+        };
+        if let Some(key) = detector.string("bundle_public_key")? {
+            builder = builder.verifying_key(VerifyingKey::decode(key.as_bytes(), encoding).map_err(invalid)?);
+        }
+        let disabled_groups = detector.strings("disabled_groups")?.unwrap_or_default();
+
+        let allowlist_section = root.section("allowlist")?;
+        allowlist_section.only(&["user_agents", "networks"])?;
+        let user_agents = allowlist_section.strings("user_agents")?.unwrap_or_default();
+        let networks = allowlist_section
+            .strings("networks")?
+            .unwrap_or_default()
+            .iter()
+            .map(|net| net.parse::<IpNet>().map_err(invalid))
+            .collect::<Result<Vec<IpNet>, BotGuardError>>()?;
+        let allowlist = Allowlist::new(&user_agents.iter().map(String::as_str).collect::<Vec<&str>>(), &networks)?;
+        let exemptions = parse_exemptions(&root.section("exempt")?)?;
+        let proxies = root.get("proxies").map(|_| parse_proxies(&root.section("proxies")?)).transpose()?;
+        let references = root.get("references").map(|_| parse_references(&root.section("references")?)).transpose()?;
+
+        let thresholds_section = root.section("thresholds")?;
+        thresholds_section.only(&["suspicious", "bot"])?;
+        let mut thresholds = Thresholds::default();
+        if let Some(suspicious) = thresholds_section.number("suspicious")? {
+            thresholds.suspicious = suspicious as f32;
+        }
+        if let Some(bot) = thresholds_section.number("bot")? {
+            thresholds.bot = bot as f32;
+        }
+        if thresholds.suspicious > thresholds.bot {
+            return Err(invalid("the suspicious threshold is above the bot threshold".to_string()));
+        }
+        builder = builder.thresholds(thresholds);
+
+        let policy_section = root.section("policy")?;
+        policy_section.only(&["default", "shadow", "route", "social_previews"])?;
+        let default_action = match policy_section.string("default")? {
+            Some(action) => parse_action(&action, &policy_section)?,
+            None => Action::Allow,
+        };
+        let mut policy = PolicyEngine::new(default_action).shadow(policy_section.bool("shadow")?.unwrap_or(false));
+        for route in policy_section.tables("route")? {
+            policy.push(parse_route(&route)?);
+        }
+        if policy_section.get("social_previews").is_some() {
+            policy = policy.social_previews(parse_social_previews(&policy_section.section("social_previews")?)?);
+        }
+
+        let signals = root.section("signals")?;
+        let mut sampling = Sampling::new();
+        for (name, _) in signals.entries {
+            let signal = name.parse::<Signal>().map_err(|e| invalid(format!("{} in signals", e)))?;
+            sampling = sampling.gate(signal, parse_gate(&signals.section(name)?)?);
+        }
+
+        let pipeline = parse_pipeline(&root.section("pipeline")?)?;
+
+        Ok(BotGuardConfig { builder, disabled_groups, allowlist, thresholds, policy, sampling, pipeline, exemptions, proxies, references })
+    }
+
+    /// Builds the configured detector, with the disabled groups already turned off.
+    pub fn detector(&self) -> Result<BotDetector, BotGuardError> {
+        let mut detector = self.builder.clone().build()?;
+        for group in &self.disabled_groups {
+            if !detector.disable_group(group) {
+                return Err(invalid(format!("disabled group {:?} does not exist", group)));
+            }
+        }
+        Ok(detector)
+    }
+}
+
+fn invalid(reason: String) -> BotGuardError {
+    BotGuardError::InvalidConfig { line: None, reason }
+}
+
+fn parse_route(route: &Section<'_>) -> Result<PolicyRule, BotGuardError> {
+    route.only(&[
+        "path", "action", "tag", "requests", "per_seconds", "category", "unclassified", "min_score", "max_score", "verified", "countries", "not_countries",
+        "datacenter", "asns", "not_asns", "bytes_per_second", "enforce",
+    ])?;
+    let path = route.string("path")?.ok_or_else(|| invalid(format!("{} needs a path", route.name)))?;
+    let action = route.string("action")?.ok_or_else(|| invalid(format!("{} needs an action", route.name)))?;
+    let mut rule = PolicyRule::new(&path, parse_action(&action, route)?);
+    if let Some(category) = route.string("category")? {
+        rule = rule.when(Condition::Category(category));
+    }
+    if route.bool("unclassified")? == Some(true) {
+        rule = rule.when(Condition::Unclassified);
+    }
+    if let Some(min) = route.number("min_score")? {
+        rule = rule.when(Condition::MinScore(min as f32));
+    }
+    if let Some(max) = route.number("max_score")? {
+        rule = rule.when(Condition::MaxScore(max as f32));
+    }
+    if let Some(verified) = route.bool("verified")? {
+        rule = rule.when(Condition::Verified(verified));
+    }
+    if let Some(countries) = route.strings("countries")? {
+        rule = rule.when(Condition::Country(countries));
+    }
+    if let Some(countries) = route.strings("not_countries")? {
+        rule = rule.when(Condition::NotCountry(countries));
+    }
+    if let Some(datacenter) = route.bool("datacenter")? {
+        rule = rule.when(Condition::Datacenter(datacenter));
+    }
+    if let Some(asns) = route.asns("asns")? {
+        rule = rule.when(Condition::Asn(asns));
+    }
+    if let Some(asns) = route.asns("not_asns")? {
+        rule = rule.when(Condition::NotAsn(asns));
+    }
+    if route.bool("enforce")? == Some(true) {
+        rule = rule.enforced();
+    }
+    Ok(rule)
+}
+
+fn parse_proxies(section: &Section<'_>) -> Result<ClientIpExtractor, BotGuardError> {
+    section.only(&["trusted", "headers"])?;
+    let trusted = section.strings("trusted")?.unwrap_or_default().iter().map(|net| net.parse::<IpNet>().map_err(invalid)).collect::<Result<Vec<IpNet>, BotGuardError>>()?;
+    let mut extractor = ClientIpExtractor::new(&trusted);
+    if let Some(headers) = section.strings("headers")? {
+        let headers = headers.iter().map(|header| header.parse::<ForwardingHeader>().map_err(invalid)).collect::<Result<Vec<ForwardingHeader>, BotGuardError>>()?;
+        extractor = extractor.headers(&headers);
+    }
+    Ok(extractor)
+}
+
+fn parse_social_previews(section: &Section<'_>) -> Result<SocialPreviews, BotGuardError> {
+    section.only(&["public", "private", "action", "tag", "requests", "per_seconds", "bytes_per_second"])?;
+    let routes = |key| section.strings(key).map(Option::unwrap_or_default);
+    let mut previews = SocialPreviews::new().public(&routes("public")?.iter().map(String::as_str).collect::<Vec<&str>>()).private(&routes("private")?.iter().map(String::as_str).collect::<Vec<&str>>());
+    if let Some(action) = section.string("action")? {
+        previews = previews.private_action(parse_action(&action, section)?);
+    }
+    Ok(previews)
+}
+
+fn parse_references(section: &Section<'_>) -> Result<ReferenceSigner, BotGuardError> {
+    section.only(&["secret"])?;
+    let secret = section.string("secret")?.ok_or_else(|| invalid(format!("{} needs a secret", section.name)))?;
+    if secret.len() < 16 {
+        return Err(invalid(format!("{}.secret must be at least 16 characters", section.name)));
+    }
+    Ok(ReferenceSigner::new(secret.as_bytes()))
+}
+
+fn parse_exemptions(section: &Section<'_>) -> Result<Exemptions, BotGuardError> {
+    section.only(&["networks", "user_agents", "health_checks", "token_secret", "rate_limit", "log"])?;
+    let networks = section.strings("networks")?.unwrap_or_default().iter().map(|net| net.parse::<IpNet>().map_err(invalid)).collect::<Result<Vec<IpNet>, BotGuardError>>()?;
+    let mut exemptions = Exemptions::new().networks(&networks);
+    if let Some(user_agents) = section.strings("user_agents")? {
+        exemptions = exemptions.user_agents(&user_agents.iter().map(String::as_str).collect::<Vec<&str>>())?;
+    }
+    if section.bool("health_checks")? == Some(true) {
+        exemptions = exemptions.health_checks();
+    }
+    if let Some(secret) = section.string("token_secret")? {
+        if secret.len() < 16 {
+            return Err(invalid(format!("{}.token_secret must be at least 16 characters", section.name)));
+        }
+        exemptions = exemptions.tokens(secret.as_bytes());
+    }
+    Ok(exemptions.rate_limited(section.bool("rate_limit")?.unwrap_or(false)).logged(section.bool("log")?.unwrap_or(false)))
+}
+
+fn parse_pipeline(section: &Section<'_>) -> Result<Pipeline, BotGuardError> {
+    section.only(&["stages", "short_circuit"])?;
+    let mut pipeline = match section.strings("stages")? {
+        Some(names) => {
+            let mut stages = Vec::new();
+            for name in names {
+                let stage = name.parse::<Stage>().map_err(|e| invalid(format!("{} in {}.stages", e, section.name)))?;
+                if stages.contains(&stage) {
+                    return Err(invalid(format!("stage {:?} is listed twice in {}.stages", name, section.name)));
+                }
+                stages.push(stage);
+            }
+            Pipeline::new(stages)
+        }
+        None => Pipeline::default(),
+    };
+    let short_circuit = section.section("short_circuit")?;
+    for (name, _) in short_circuit.entries {
+        let stage = name.parse::<Stage>().map_err(|e| invalid(format!("{} in {}", e, short_circuit.name)))?;
+        if !pipeline.contains(stage) {
+            return Err(invalid(format!("{}.{} is not in the pipeline", short_circuit.name, name)));
+        }
+        let score = short_circuit.number(name)?.unwrap_or_default();
+        pipeline = pipeline.short_circuit(stage, score as f32);
+    }
+    Ok(pipeline)
+}
+
+fn parse_gate(section: &Section<'_>) -> Result<SignalGate, BotGuardError> {
+    section.only(&["sample", "min_score", "user_agents", "paths"])?;
+    let mut gate = SignalGate::new();
+    if let Some(rate) = section.number("sample")? {
+        if rate > 1.0 {
+            return Err(invalid(format!("{}.sample must be at most 1", section.name)));
+        }
+        gate = gate.sample(rate);
+    }
+    if let Some(min) = section.number("min_score")? {
+        gate = gate.min_score(min as f32);
+    }
+    if let Some(user_agents) = section.strings("user_agents")? {
+        gate = gate.user_agents(&user_agents.iter().map(String::as_str).collect::<Vec<&str>>())?;
+    }
+    if let Some(paths) = section.strings("paths")? {
+        gate = gate.paths(&paths.iter().map(String::as_str).collect::<Vec<&str>>());
+    }
+    Ok(gate)
+}
+
+fn parse_action(action: &str, section: &Section<'_>) -> Result<Action, BotGuardError> {
+    Ok(match action {
+        "allow" => Action::Allow,
+        "block" => Action::Block,
+        "challenge" => Action::Challenge,
+        "decoy" => Action::Decoy,
+        "tag" => Action::Tag(section.string("tag")?.ok_or_else(|| invalid(format!("{} needs a tag", section.name)))?),
+        "rate_limit" => {
+            let requests = section.number("requests")?.ok_or_else(|| invalid(format!("{} needs requests", section.name)))?;
+            let per = section.number("per_seconds")?.unwrap_or(60.0);
+            Action::RateLimit { requests: requests as u32, per: Duration::from_secs_f64(per) }
+        }
+        "tarpit" => Action::Tarpit { bytes_per_second: section.number("bytes_per_second")?.unwrap_or(16.0).max(1.0) as u32 },
+        other => return Err(invalid(format!("unknown action {:?} in {}", other, section.name))),
+    })
+}
+
+/// Typed access to the keys of a table, naming the table in errors.
+struct Section<'a> {
+    name: String,
+    entries: &'a [(String, Value)],
+}
+
+impl<'a> Section<'a> {
+    fn new(name: &str, value: &'a Value) -> Result<Self, BotGuardError> {
+        let entries = value.as_table().ok_or_else(|| invalid(format!("{} must be a table", name)))?;
+        Ok(Section { name: name.to_string(), entries })
+    }
+
+    fn get(&self, key: &str) -> Option<&'a Value> {
+        self.entries.iter().find(|(name, _)| name == key).map(|(_, value)| value)
+    }
+
+    fn path(&self, key: &str) -> String {
+        if self.name.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.name, key)
+        }
+    }
+
+    /// Fails on keys outside of `allowed`, catching typos that would silently change nothing.
+    fn only(&self, allowed: &[&str]) -> Result<(), BotGuardError> {
+        match self.entries.iter().find(|(key, _)| !allowed.contains(&key.as_str())) {
+            Some((key, _)) => Err(invalid(format!("unknown key {}", self.path(key)))),
+            None => Ok(()),
+        }
+    }
+
+    fn wrong_type(&self, key: &str, expected: &str, value: &Value) -> BotGuardError {
+        invalid(format!("{} must be a {}, not a {}", self.path(key), expected, value.type_name()))
+    }
+
+    /// A missing section is empty.
+    fn section(&self, key: &str) -> Result<Section<'a>, BotGuardError> {
+        match self.get(key) {
+            Some(value @ Value::Table(_)) => Section::new(&self.path(key), value),
+            Some(value) => Err(self.wrong_type(key, "table", value)),
+            None => Ok(Section { name: self.path(key), entries: &[] }),
+        }
+    }
+
+    fn tables(&self, key: &str) -> Result<Vec<Section<'a>>, BotGuardError> {
+        match self.get(key) {
+            Some(Value::Array(items)) => {
+                items.iter().enumerate().map(|(i, item)| Section::new(&format!("{}[{}]", self.path(key), i), item)).collect()
+            }
+            Some(value) => Err(self.wrong_type(key, "array of tables", value)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<Option<String>, BotGuardError> {
+        match self.get(key) {
+            Some(Value::String(s)) => Ok(Some(s.clone())),
+            Some(value) => Err(self.wrong_type(key, "string", value)),
+            None => Ok(None),
+        }
+    }
+
+    fn bool(&self, key: &str) -> Result<Option<bool>, BotGuardError> {
+        match self.get(key) {
+            Some(Value::Bool(b)) => Ok(Some(*b)),
+            Some(value) => Err(self.wrong_type(key, "boolean", value)),
+            None => Ok(None),
+        }
+    }
+
+    /// Integers and floats, negative numbers are rejected.
+    fn number(&self, key: &str) -> Result<Option<f64>, BotGuardError> {
+        let number = match self.get(key) {
+            Some(Value::Integer(n)) => *n as f64,
+            Some(Value::Float(n)) => *n,
+            Some(value) => return Err(self.wrong_type(key, "number", value)),
+            None => return Ok(None),
+        };
+        if number < 0.0 || !number.is_finite() {
+            return Err(invalid(format!("{} must not be negative", self.path(key))));
+        }
+        Ok(Some(number))
+    }
+
+    fn strings(&self, key: &str) -> Result<Option<Vec<String>>, BotGuardError> {
+        match self.get(key) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    Value::String(s) => Ok(s.clone()),
+                    other => Err(self.wrong_type(key, "list of strings", other)),
+                })
+                .collect::<Result<Vec<String>, BotGuardError>>()
+                .map(Some),
+            Some(value) => Err(self.wrong_type(key, "list of strings", value)),
+            None => Ok(None),
+        }
+    }
+
+    fn asns(&self, key: &str) -> Result<Option<Vec<u32>>, BotGuardError> {
+        match self.get(key) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    Value::Integer(n) => u32::try_from(*n).map_err(|_| invalid(format!("{} contains the invalid ASN {}", self.path(key), n))),
+                    other => Err(self.wrong_type(key, "list of integers", other)),
+                })
+                .collect::<Result<Vec<u32>, BotGuardError>>()
+                .map(Some),
+            Some(value) => Err(self.wrong_type(key, "list of integers", value)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::PolicyInput;
+    use crate::Verdict;
+
+    const CONFIG: &str = r#"
+[detector]
+default_patterns = true
+match_mode = "token"
+empty_user_agent = "suspicious"
+max_input_len = 512
+disabled_groups = ["monitoring"]
+
+[groups]
+internal-scanners = ["acme-scanner", "^probe/"]
+search-engines = ["examplebot"]
+
+[allowlist]
+user_agents = ["^kube-probe/"]
+networks = ["10.0.0.0/8", "fd00::/8"]
+
+[proxies]
+trusted = ["10.0.0.0/8"]
+headers = ["cf-connecting-ip"]
+
+[exempt]
+networks = ["192.168.0.0/16"]
+health_checks = true
+token_secret = "0123456789abcdef"
+log = true
+
+[thresholds]
+suspicious = 0.4
+bot = 0.9
+
+[policy]
+default = "allow"
+shadow = true
+
+[[policy.route]]
+path = "*"
+action = "tag"
+tag = "scored"
+min_score = 0.4
+
+[[policy.route]]
+path = "/api/*"
+action = "block"
+min_score = 0.4
+verified = false
+enforce = true
+
+[[policy.route]]
+path = "/blog/*"
+action = "rate_limit"
+requests = 30
+per_seconds = 60
+category = "search-engines"
+
+[[policy.route]]
+path = "/signup"
+action = "challenge"
+not_countries = ["DE", "AT"]
+datacenter = true
+not_asns = [15169]
+
+[[policy.route]]
+path = "/catalog/*"
+action = "tarpit"
+bytes_per_second = 32
+category = "scrapers"
+
+[policy.social_previews]
+private = ["/account/*"]
+action = "challenge"
+
+[signals.fingerprint]
+min_score = 0.4
+sample = 0.5
+
+[signals.reputation]
+user_agents = ["googlebot"]
+paths = ["/search/*"]
+
+[pipeline]
+stages = ["allowlist", "user_agent", "ip", "headers"]
+
+[pipeline.short_circuit]
+user_agent = 0.9
+"#;
+
+    #[test]
+    fn loads_a_complete_config() {
+        let config = BotGuardConfig::from_toml(CONFIG).unwrap();
+        let detector = config.detector().unwrap();
+        assert_eq!(detector.classify("Mozilla/5.0 (compatible; acme-scanner)"), Some("internal-scanners"));
+        assert_eq!(detector.classify("ExampleBot/1.0"), Some("search-engines"));
+        assert_eq!(detector.classify("Googlebot/2.1"), Some("search-engines"));
+        // token mode: the pattern has to be a whole token
+        assert!(!detector.check_bot("acme-scannerX"));
+        assert!(!detector.is_group_enabled("monitoring"));
+        assert_eq!(detector.check(""), Verdict::Suspicious);
+
+        assert!(config.allowlist.allows_user_agent("kube-probe/1.29"));
+        assert!(config.allowlist.allows_ip("10.1.2.3".parse().unwrap()));
+        assert!(!config.allowlist.allows_ip("192.0.2.1".parse().unwrap()));
+        assert_eq!(config.thresholds, Thresholds { suspicious: 0.4, bot: 0.9 });
+
+        let blocked = config.policy.evaluate(&PolicyInput::new("/api/orders").score(0.5));
+        assert_eq!((blocked.action, blocked.tags), (Action::Block, vec!["scored".to_string()]));
+        let crawler = PolicyInput::new("/blog/1").category(Some("search-engines")).score(1.0);
+        let shadowed = config.policy.evaluate(&crawler);
+        assert_eq!((shadowed.action, shadowed.shadowed), (Action::Allow, Some(Action::RateLimit { requests: 30, per: Duration::from_secs(60) })));
+        let cloud = PolicyInput::new("/signup").country(Some("US")).datacenter(true).asn(Some(16509));
+        assert_eq!(config.policy.evaluate(&cloud).shadowed, Some(Action::Challenge));
+        assert_eq!(config.policy.evaluate(&cloud.asn(Some(15169))).shadowed, None);
+        let scraper = PolicyInput::new("/catalog/shoes").category(Some("scrapers"));
+        assert_eq!(config.policy.evaluate(&scraper).shadowed, Some(Action::Tarpit { bytes_per_second: 32 }));
+        let preview = |path| PolicyInput::new(path).category(Some("social-previews")).score(1.0);
+        assert_eq!(config.policy.evaluate(&preview("/api/posts/1")).shadowed, None);
+        assert_eq!(config.policy.evaluate(&preview("/account/billing")).shadowed, Some(Action::Challenge));
+
+        assert_eq!(config.sampling.get(Signal::Fingerprint).map(SignalGate::rate), Some(0.5));
+        assert!(config.sampling.get(Signal::Classifier).is_none());
+        let claims_google = crate::request::RequestSnapshot::new("GET", "/search/q").user_agent("Googlebot/2.1");
+        assert!(config.sampling.runs(Signal::Reputation, &claims_google, 0.0));
+        assert!(!config.sampling.runs(Signal::Reputation, &claims_google.user_agent("curl/8.4.0"), 0.0));
+
+        let probe = crate::request::RequestSnapshot::new("GET", "/healthz").user_agent("ELB-HealthChecker/2.0");
+        assert!(config.exemptions.exempts(&probe).is_some());
+        assert_eq!((config.exemptions.is_logged(), config.exemptions.is_rate_limited()), (true, false));
+        assert!(config.exemptions.issue_token("deploy", Duration::from_secs(60)).is_some());
+
+        let proxies = config.proxies.as_ref().unwrap();
+        assert_eq!(proxies.extract("10.0.0.1".parse().unwrap(), [("CF-Connecting-IP", "192.0.2.1")]), "192.0.2.1".parse::<IpAddr>().unwrap());
+
+        let stages = config.pipeline.stages().collect::<Vec<_>>();
+        assert_eq!(stages, vec![(Stage::Allowlist, None), (Stage::UserAgent, Some(0.9)), (Stage::Ip, None), (Stage::Headers, None)]);
+    }
+
+    #[test]
+    fn reads_config_files() {
+        let path = std::env::temp_dir().join(format!("botguard-config-{}.toml", std::process::id()));
+        std::fs::write(&path, CONFIG).unwrap();
+        assert!(BotGuardConfig::from_path(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(BotGuardConfig::from_path(&path), Err(BotGuardError::IoError { .. })));
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        let error = |text: &str| BotGuardConfig::from_toml(text).unwrap_err().to_string();
+        assert_eq!(error("[detector]\nmatch_mode = \"fuzzy\""), "invalid configuration: unknown match_mode \"fuzzy\"");
+        assert_eq!(error("[detector]\ncase_sensitiv = true"), "invalid configuration: unknown key detector.case_sensitiv");
+        assert_eq!(error("[thresholds]\nbot = \"high\""), "invalid configuration: thresholds.bot must be a number, not a string");
+        assert_eq!(error("[groups]\nx = [\"a\"]\nx = [\"b\"]"), "invalid configuration on line 3: key \"x\" is defined twice");
+        assert!(error("[[policy.route]]\npath = \"/\"\naction = \"nuke\"").contains("unknown action"));
+        assert!(error("[[policy.route]]\naction = \"block\"").contains("policy.route[0] needs a path"));
+        assert!(error("[allowlist]\nnetworks = [\"10.0.0.0/40\"]").contains("prefix length"));
+        assert!(error("[groups]\n\"bad name\" = []").contains("group name"));
+        assert!(error("[groups]\nx = [\"[y]\"]").contains("group header"));
+        assert!(error("[detector]\nbundle_public_key = \"abcd\"").contains("32 byte key in base64"));
+        let hex_key = "bundle_public_key = \"03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8\"";
+        assert!(error(&format!("[detector]\n{}", hex_key)).contains("32 byte key in base64"));
+        assert!(BotGuardConfig::from_toml(&format!("[detector]\n{}\nbundle_key_encoding = \"hex\"", hex_key)).unwrap().detector().is_ok());
+        assert_eq!(error("[detector]\nbundle_key_encoding = \"pem\""), "invalid configuration: unknown bundle_key_encoding \"pem\"");
+        assert!(matches!(BotGuardConfig::from_toml("[groups]\nx = [\"(open\"]").unwrap().detector(), Err(BotGuardError::InvalidPattern { .. })));
+        assert_eq!(error("[detector]\npattern_syntax = \"wildcard\""), "invalid configuration: unknown pattern_syntax \"wildcard\"");
+        let literal = BotGuardConfig::from_toml("[detector]\ndefault_patterns = false\npattern_syntax = \"literal\"\n[groups]\nx = [\"(open\"]").unwrap();
+        assert!(literal.detector().unwrap().check_bot("Tool (open source)"));
+        let missing_group = BotGuardConfig::from_toml("[detector]\ndisabled_groups = [\"nope\"]").unwrap();
+        assert!(missing_group.detector().is_err());
+        assert_eq!(error("[signals.dns]\nsample = 0.1"), "invalid configuration: unknown signal \"dns\" in signals");
+        assert_eq!(error("[signals.classifier]\nsample = 2"), "invalid configuration: signals.classifier.sample must be at most 1");
+        assert_eq!(error("[pipeline]\nstages = [\"cache\"]"), "invalid configuration: unknown stage \"cache\" in pipeline.stages");
+        assert!(error("[pipeline]\nstages = [\"ip\", \"ip\"]").contains("listed twice"));
+        assert_eq!(error("[proxies]\nheaders = [\"x-client-ip\"]"), "invalid configuration: unknown forwarding header \"x-client-ip\"");
+        assert!(BotGuardConfig::from_toml("").unwrap().proxies.is_none());
+        assert_eq!(error("[references]\nsecret = \"short\""), "invalid configuration: references.secret must be at least 16 characters");
+        assert_eq!(error("[references]"), "invalid configuration: references needs a secret");
+        assert_eq!(error("[exempt]\ntoken_secret = \"short\""), "invalid configuration: exempt.token_secret must be at least 16 characters");
+        assert_eq!(
+            error("[pipeline]\nstages = [\"user_agent\"]\n[pipeline.short_circuit]\nip = 0.5"),
+            "invalid configuration: pipeline.short_circuit.ip is not in the pipeline"
+        );
+    }
+}
@@ -0,0 +1,227 @@
+// Returning visitor signal: the first response sets a signed "seen before" cookie, browsers send
+// it back on every later request. Most scrapers and scripted clients drop cookies, so an address
+// making request after request without ever returning the cookie stands out.
+//
+// The cookie value is a `timing::PageTimer` token with the time it was issued, so nothing has to
+// be stored to verify it. Only the streak of requests without a cookie is kept per address.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::timing::{PageTimer, TimingCheck};
+use crate::traffic::evict_oldest;
+
+/// Name of the cookie.
+pub const COOKIE_NAME: &str = "bg_seen";
+
+/// Context the cookie tokens are issued for, so page timing tokens are not accepted as cookies.
+const CONTEXT: &str = "cookie";
+
+/// Outcome of [`CookieSignal::evaluate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieCheck {
+    /// The client sent back a cookie issued this long ago.
+    Returning(Duration),
+    /// No cookie was sent, as on the first visit.
+    Missing,
+    /// The cookie was not issued with this secret or was tampered with.
+    Invalid,
+    /// The cookie is older than [`CookieSignal::max_age`].
+    Expired,
+}
+
+/// Issues and verifies the cookie and counts the requests of each address without it.
+///
+/// ```
+/// use BotGuardLib::cookie::{CookieCheck, CookieSignal};
+///
+/// let signal = CookieSignal::new(b"a long random server secret").suspicious_after(3);
+/// let set_cookie = signal.set_cookie();
+/// assert!(set_cookie.starts_with("bg_seen="));
+///
+/// // a browser returns the cookie
+/// let cookie = set_cookie.split(';').next().unwrap();
+/// assert!(matches!(signal.evaluate(&format!("theme=dark; {}", cookie)), CookieCheck::Returning(_)));
+/// assert_eq!(signal.evaluate("bg_seen=1700000000000.forged"), CookieCheck::Invalid);
+///
+/// // a script never does
+/// let ip = "203.0.113.7".parse().unwrap();
+/// for _ in 0..3 {
+///     signal.observe(ip, signal.evaluate(""));
+/// }
+/// assert_eq!(signal.observe(ip, CookieCheck::Missing), 4);
+/// assert!(signal.is_suspicious(4));
+/// ```
+pub struct CookieSignal {
+    timer: PageTimer,
+    max_age: Duration,
+    suspicious_after: u32,
+    streaks: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+    max_clients: usize,
+}
+
+impl fmt::Debug for CookieSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CookieSignal")
+            .field("max_age", &self.max_age)
+            .field("suspicious_after", &self.suspicious_after)
+            .field("max_clients", &self.max_clients)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CookieSignal {
+    /// Cookies last a year, more than 10 requests in a row without one are suspicious and up to
+    /// 100,000 addresses are tracked. Every instance verifying the cookies needs the same secret.
+    pub fn new(secret: &[u8]) -> Self {
+        let max_age = Duration::from_secs(365 * 24 * 60 * 60);
+        CookieSignal {
+            timer: PageTimer::new(secret).human_floor(Duration::ZERO).max_age(max_age),
+            max_age,
+            suspicious_after: 10,
+            streaks: Mutex::default(),
+            max_clients: 100_000,
+        }
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.timer = self.timer.max_age(max_age);
+        self.max_age = max_age;
+        self
+    }
+
+    /// How many requests in a row an address may make without the cookie, a browser needs one.
+    pub fn suspicious_after(mut self, requests: u32) -> Self {
+        self.suspicious_after = requests.max(1);
+        self
+    }
+
+    /// When full, the addresses seen least recently are forgotten to make room for new ones, a
+    /// sixteenth of them at a time.
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients.max(1);
+        self
+    }
+
+    /// A `Set-Cookie` header value with a cookie issued now.
+    pub fn set_cookie(&self) -> String {
+        self.set_cookie_at(SystemTime::now())
+    }
+
+    fn set_cookie_at(&self, issued: SystemTime) -> String {
+        format!("{}={}; Max-Age={}; Path=/; HttpOnly; SameSite=Lax", COOKIE_NAME, self.timer.issue_at(CONTEXT, issued), self.max_age.as_secs())
+    }
+
+    /// Checks the cookie in a `Cookie` request header, an empty header is [`CookieCheck::Missing`].
+    pub fn evaluate(&self, cookie_header: &str) -> CookieCheck {
+        self.evaluate_at(cookie_header, SystemTime::now())
+    }
+
+    fn evaluate_at(&self, cookie_header: &str, now: SystemTime) -> CookieCheck {
+        match self.timer.verify_at(CONTEXT, value(cookie_header, COOKIE_NAME).unwrap_or(""), now) {
+            TimingCheck::Passed(age) | TimingCheck::TooFast(age) => CookieCheck::Returning(age),
+            TimingCheck::Missing => CookieCheck::Missing,
+            TimingCheck::Invalid => CookieCheck::Invalid,
+            TimingCheck::Expired => CookieCheck::Expired,
+        }
+    }
+
+    /// Records the check of a request of the address and returns how many requests in a row it
+    /// made without a valid cookie, `0` once it returned one.
+    pub fn observe(&self, ip: IpAddr, check: CookieCheck) -> u32 {
+        self.observe_at(ip, check, Instant::now())
+    }
+
+    fn observe_at(&self, ip: IpAddr, check: CookieCheck, now: Instant) -> u32 {
+        let mut streaks = self.lock();
+        if let CookieCheck::Returning(_) = check {
+            streaks.remove(&ip);
+            return 0;
+        }
+        if !streaks.contains_key(&ip) && streaks.len() >= self.max_clients {
+            evict_oldest(&mut streaks, |(_, seen)| *seen);
+        }
+        let (requests, seen) = streaks.entry(ip).or_insert((0, now));
+        *requests = requests.saturating_add(1);
+        *seen = now;
+        *requests
+    }
+
+    /// Whether a streak returned by [`CookieSignal::observe`] is longer than a browser's.
+    pub fn is_suspicious(&self, requests: u32) -> bool {
+        requests > self.suspicious_after
+    }
+
+    /// Number of addresses with a streak of requests without the cookie.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<IpAddr, (u32, Instant)>> {
+        self.streaks.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The value of a cookie in a `Cookie` request header.
+pub(crate) fn value<'a>(cookie_header: &'a str, name: &str) -> Option<&'a str> {
+    cookie_header
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| key.trim() == name)
+        .map(|(_, value)| value.trim().trim_matches('"'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn verifies_returned_cookies() {
+        let signal = CookieSignal::new(b"secret").max_age(Duration::from_secs(3600));
+        let issued = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let set_cookie = signal.set_cookie_at(issued);
+        assert!(set_cookie.ends_with("; Max-Age=3600; Path=/; HttpOnly; SameSite=Lax"), "{}", set_cookie);
+        let cookie = set_cookie.split(';').next().unwrap();
+        let at = |header: &str, after: u64| signal.evaluate_at(header, issued + Duration::from_secs(after));
+
+        assert_eq!(at(cookie, 60), CookieCheck::Returning(Duration::from_secs(60)));
+        assert_eq!(at(&format!("a=1;  {} ; b=2", cookie), 0), CookieCheck::Returning(Duration::ZERO));
+        assert_eq!(at(cookie, 3601), CookieCheck::Expired);
+        assert_eq!(at("a=1; b=2", 60), CookieCheck::Missing);
+        assert_eq!(at(&cookie.replace("bg_seen=1", "bg_seen=2"), 60), CookieCheck::Invalid);
+        assert_eq!(CookieSignal::new(b"other").evaluate_at(cookie, issued), CookieCheck::Invalid);
+        // a page timing token of the same secret is no cookie
+        let page = PageTimer::new(b"secret").issue_at("checkout", issued);
+        assert_eq!(at(&format!("bg_seen={}", page), 60), CookieCheck::Invalid);
+    }
+
+    #[test]
+    fn counts_requests_without_the_cookie() {
+        let signal = CookieSignal::new(b"secret").suspicious_after(2).max_clients(2);
+        let start = Instant::now();
+        let ip = |last: u8| IpAddr::from([192, 0, 2, last]);
+
+        assert_eq!(signal.observe_at(ip(1), CookieCheck::Missing, start), 1);
+        assert_eq!(signal.observe_at(ip(1), CookieCheck::Invalid, start), 2);
+        assert!(!signal.is_suspicious(2));
+        assert_eq!(signal.observe_at(ip(1), CookieCheck::Expired, start), 3);
+        assert!(signal.is_suspicious(3));
+        assert_eq!(signal.observe_at(ip(1), CookieCheck::Returning(Duration::ZERO), start), 0);
+        assert!(signal.is_empty());
+
+        signal.observe_at(ip(1), CookieCheck::Missing, start);
+        signal.observe_at(ip(2), CookieCheck::Missing, start + Duration::from_secs(1));
+        signal.observe_at(ip(3), CookieCheck::Missing, start + Duration::from_secs(2));
+        assert_eq!(signal.len(), 2);
+        assert_eq!(signal.observe_at(ip(2), CookieCheck::Missing, start + Duration::from_secs(3)), 2);
+        assert_eq!(signal.observe_at(ip(1), CookieCheck::Missing, start + Duration::from_secs(4)), 1);
+    }
+}
@@ -0,0 +1,169 @@
+// SHA-512 and HMAC-SHA-512 from the RustCrypto crates and Ed25519 verification from
+// `ed25519-dalek`, used to check signed pattern bundles and the tokens handed out to clients.
+//
+// Signatures are verified with `verify_strict`: non-canonical scalars and points, small-order
+// keys and small-order `R` are rejected. MACs are compared in constant time with `mac_eq`.
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha512};
+
+/// SHA-512 of the data.
+pub(crate) fn sha512(data: &[u8]) -> [u8; 64] {
+    Sha512::digest(data).into()
+}
+
+pub(crate) fn hmac_sha512(key: &[u8], message: &[u8]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+/// Compares MACs without an early exit, so the time taken does not reveal the matching prefix.
+pub(crate) fn mac_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Lowercase hex digits of the bytes.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes a public key that is a canonically encoded curve point outside the small-order
+/// subgroup. `ed25519-dalek` accepts non-canonical encodings, so the key is compressed again.
+fn verifying_key(public_key: &[u8; 32]) -> Option<VerifyingKey> {
+    let key = VerifyingKey::from_bytes(public_key).ok()?;
+    (!key.is_weak() && key.to_edwards().compress().as_bytes() == public_key).then_some(key)
+}
+
+/// Whether a public key is a canonically encoded curve point outside the small-order subgroup.
+pub(crate) fn ed25519_valid_key(public_key: &[u8; 32]) -> bool {
+    verifying_key(public_key).is_some()
+}
+
+/// Checks an Ed25519 signature, rejecting non-canonical encodings and small-order points.
+pub(crate) fn ed25519_verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    verifying_key(public_key).is_some_and(|key| key.verify_strict(message, &Signature::from_bytes(signature)).is_ok())
+}
+
+/// Decodes hex digits in either case, surrounding whitespace is ignored.
+pub(crate) fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+    if text.len() % 2 != 0 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok()).collect()
+}
+
+/// Decodes standard base64 with optional padding, surrounding whitespace is ignored.
+pub(crate) fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+    let unpadded = text.trim_end_matches('=');
+    if text.len() - unpadded.len() > 2 || unpadded.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::new();
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for b in unpadded.bytes() {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = acc << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        decode_hex(text).unwrap()
+    }
+
+    #[test]
+    fn sha512_digests() {
+        assert_eq!(sha512(b"abc").to_vec(), hex("ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"));
+        assert_eq!(
+            hmac_sha512(b"Jefe", b"what do ya want for nothing?").to_vec(),
+            hex("164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737")
+        );
+        assert!(mac_eq(b"abc", b"abc") && !mac_eq(b"abc", b"abd") && !mac_eq(b"ab", b"abc"));
+    }
+
+    const PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+
+    #[test]
+    fn verifies_signatures() {
+        let public_key: [u8; 32] = hex(PUBLIC_KEY).try_into().unwrap();
+        let mut signature: [u8; 64] = hex(SIGNATURE).try_into().unwrap();
+        assert!(ed25519_valid_key(&public_key));
+        assert!(ed25519_verify(&public_key, b"", &signature));
+        assert!(!ed25519_verify(&public_key, b"other", &signature));
+        signature[40] ^= 1;
+        assert!(!ed25519_verify(&public_key, b"", &signature));
+    }
+
+    #[test]
+    fn rejects_non_canonical_scalars() {
+        // S + L is the same scalar modulo the group order, but not the canonical encoding
+        const L: [u8; 32] = [
+            0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+        ];
+        let public_key: [u8; 32] = hex(PUBLIC_KEY).try_into().unwrap();
+        let mut signature: [u8; 64] = hex(SIGNATURE).try_into().unwrap();
+        let mut carry = 0u16;
+        for (s, l) in signature[32..].iter_mut().zip(L) {
+            let sum = u16::from(*s) + u16::from(l) + carry;
+            *s = sum as u8;
+            carry = sum >> 8;
+        }
+        assert_eq!(carry, 0);
+        assert!(!ed25519_verify(&public_key, b"", &signature));
+    }
+
+    #[test]
+    fn rejects_small_order_and_non_canonical_points() {
+        let identity = hex("0100000000000000000000000000000000000000000000000000000000000000");
+        let order_eight = hex("c7176a703d4dd84fba3c0b760d10670f2a2053fa2c39ccc64ec7fd7792ac037a");
+        // y = p, a non-canonical encoding of y = 0
+        let non_canonical = hex("edffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f");
+        // y = 2^255 - 1, a non-canonical encoding of y = 18 that is a point of large order
+        let non_canonical_large = [0xff; 32].to_vec();
+        for key in [identity, order_eight, non_canonical, non_canonical_large] {
+            let key: [u8; 32] = key.try_into().unwrap();
+            assert!(!ed25519_valid_key(&key));
+        }
+
+        // the identity as key and R with S = 0 satisfies the plain verification equation
+        let identity: [u8; 32] = hex("0100000000000000000000000000000000000000000000000000000000000000").try_into().unwrap();
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&identity);
+        assert!(!ed25519_verify(&identity, b"any message", &signature));
+    }
+
+    #[test]
+    fn decodes_hex_and_base64() {
+        assert_eq!(decode_hex(" 00ff10\n"), Some(vec![0, 255, 16]));
+        assert_eq!(decode_hex("0ff"), None);
+        assert_eq!(decode_base64("AP8Q"), Some(vec![0, 255, 16]));
+        assert_eq!(decode_base64("aGk="), Some(b"hi".to_vec()));
+        // only hex digits, still read as base64
+        assert_eq!(decode_base64("00ff"), Some(vec![0xd3, 0x47, 0xdf]));
+        assert_eq!(decode_base64("not base64!"), None);
+        assert_eq!(decode_base64("a==="), None);
+    }
+}
@@ -0,0 +1,168 @@
+// Named bots with metadata, loaded from a JSON database instead of bare pattern lines.
+
+use std::sync::OnceLock;
+
+use regex::{RegexSet, RegexSetBuilder};
+
+use crate::json::{self, Value};
+use crate::BotGuardError;
+
+/// Bundled bot database, unless the default patterns are disabled.
+#[cfg(feature = "include-default-BotDetector")]
+const _DATABASE: &str = include_str!("bot_database.json");
+
+#[cfg(not(feature = "include-default-BotDetector"))]
+const _DATABASE: &str = "[]";
+
+/// Metadata of a known bot, see [`BotDatabase::lookup`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotInfo {
+    /// Canonical name, e.g. `"Googlebot"`.
+    pub name: String,
+    /// Company or project running the bot.
+    pub operator: String,
+    /// Homepage or documentation of the bot.
+    pub url: String,
+    /// Same names as the pattern groups of the default patterns, e.g. `"search-engines"`.
+    pub category: String,
+    pub respects_robots_txt: bool,
+    /// Case-insensitive regex identifying the bot's user-agent.
+    pub pattern: String,
+}
+
+/// A list of named bots, matched in the order of the database.
+///
+/// The database is a JSON array of objects with the fields of [`BotInfo`]:
+///
+/// ```
+/// use BotGuardLib::BotDatabase;
+///
+/// let database = BotDatabase::from_json(r#"[{
+///     "name": "ExampleBot", "pattern": "examplebot/", "operator": "Example Inc.",
+///     "url": "https://example.com/bot", "category": "seo-tools", "respects_robots_txt": true
+/// }]"#).unwrap();
+///
+/// let info = database.lookup("Mozilla/5.0 (compatible; ExampleBot/1.2)").unwrap();
+/// assert_eq!(info.operator, "Example Inc.");
+/// assert!(database.lookup("ExampleBot").is_none());
+/// ```
+#[derive(Debug, Clone)]
+pub struct BotDatabase {
+    bots: Vec<BotInfo>,
+    set: RegexSet,
+}
+
+impl BotDatabase {
+    /// Parses a database, failing on malformed JSON, missing fields and invalid patterns.
+    pub fn from_json(database: &str) -> Result<Self, BotGuardError> {
+        let invalid = |reason: String| BotGuardError::InvalidDatabase { reason };
+        let document = json::parse(database).map_err(invalid)?;
+        let entries = document.as_array().ok_or_else(|| invalid("expected an array of bots".to_string()))?;
+        let bots = entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let field = |name: &str| {
+                    entry
+                        .get(name)
+                        .and_then(Value::as_str)
+                        .map(str::to_string)
+                        .ok_or_else(|| invalid(format!("bot {} is missing the string field {:?}", i, name)))
+                };
+                Ok(BotInfo {
+                    name: field("name")?,
+                    operator: field("operator")?,
+                    url: field("url")?,
+                    category: field("category")?,
+                    respects_robots_txt: entry.get("respects_robots_txt").and_then(Value::as_bool).unwrap_or(false),
+                    pattern: field("pattern")?,
+                })
+            })
+            .collect::<Result<Vec<BotInfo>, BotGuardError>>()?;
+
+        let set = RegexSetBuilder::new(bots.iter().map(|bot| &bot.pattern))
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| {
+                // report the offending entry rather than the combined set
+                let pattern = bots
+                    .iter()
+                    .find(|bot| regex::Regex::new(&bot.pattern).is_err())
+                    .map_or_else(String::new, |bot| bot.pattern.clone());
+                BotGuardError::InvalidPattern { pattern, reason: e.to_string() }
+            })?;
+        Ok(BotDatabase { bots, set })
+    }
+
+    /// The database bundled with the crate, parsed on first use.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDatabase;
+    ///
+    /// let info = BotDatabase::bundled().lookup("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)").unwrap();
+    /// assert_eq!((info.name.as_str(), info.operator.as_str()), ("Googlebot", "Google"));
+    /// assert_eq!(info.category, "search-engines");
+    /// assert!(info.respects_robots_txt);
+    /// ```
+    pub fn bundled() -> &'static BotDatabase {
+        static BUNDLED: OnceLock<BotDatabase> = OnceLock::new();
+        BUNDLED.get_or_init(|| BotDatabase::from_json(_DATABASE).expect("the bundled bot database is valid"))
+    }
+
+    /// Returns the first bot of the database whose pattern matches the user-agent.
+    pub fn lookup(&self, user_agent: &str) -> Option<&BotInfo> {
+        self.set.matches(user_agent).iter().next().map(|i| &self.bots[i])
+    }
+
+    /// Iterates over the bots in database order.
+    pub fn bots(&self) -> impl Iterator<Item = &BotInfo> {
+        self.bots.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bots.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "include-default-BotDetector")]
+    fn bundled_database() {
+        let database = BotDatabase::bundled();
+        assert!(database.len() > 40);
+
+        let info = database.lookup("Mozilla/5.0 (compatible; AhrefsBot/7.0; +http://ahrefs.com/robot/)").unwrap();
+        assert_eq!(info.name, "AhrefsBot");
+        assert_eq!(info.category, "seo-tools");
+        assert_eq!(database.lookup("curl/8.4.0").map(|info| info.name.as_str()), Some("curl"));
+        assert_eq!(database.lookup("Mozilla/5.0 AppleWebKit/537.36 (KHTML, like Gecko; compatible; ClaudeBot/1.0)").unwrap().operator, "Anthropic");
+        assert!(database.lookup("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36").is_none());
+
+        // categories are the group names of the default patterns
+        let BotDetector = crate::BotDetector::default();
+        for bot in database.bots() {
+            assert!(
+                BotDetector.group_patterns(&bot.category).is_some(),
+                "{} has an unknown category {}",
+                bot.name,
+                bot.category
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_databases() {
+        assert!(matches!(BotDatabase::from_json("{}"), Err(BotGuardError::InvalidDatabase { .. })));
+        assert!(matches!(BotDatabase::from_json("[{\"name\": \"x\"}]"), Err(BotGuardError::InvalidDatabase { .. })));
+        let broken = r#"[{"name": "x", "pattern": "(x", "operator": "", "url": "", "category": ""}]"#;
+        assert!(matches!(BotDatabase::from_json(broken), Err(BotGuardError::InvalidPattern { ref pattern, .. }) if pattern == "(x"));
+        assert!(BotDatabase::from_json("[]").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,324 @@
+// Address ranges of cloud and hosting providers, traffic from them is rarely a human with a browser.
+//
+// A snapshot of the ranges is bundled with the `datacenter-ranges` feature, `DatacenterRanges::refresh`
+// replaces a provider's ranges with its current published feed.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::http;
+use crate::ip::{IpNet, IpRangeSet};
+use crate::json::{self, Value};
+use crate::BotGuardError;
+
+#[cfg(feature = "datacenter-ranges")]
+const _RANGES: &str = include_str!("datacenter_ranges.txt");
+
+/// A cloud or hosting provider whose address ranges are known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CloudProvider {
+    Aws,
+    Gcp,
+    Azure,
+    Ovh,
+    Hetzner,
+    DigitalOcean,
+}
+
+impl CloudProvider {
+    pub const ALL: [CloudProvider; 6] = [
+        CloudProvider::Aws,
+        CloudProvider::Gcp,
+        CloudProvider::Azure,
+        CloudProvider::Ovh,
+        CloudProvider::Hetzner,
+        CloudProvider::DigitalOcean,
+    ];
+
+    /// Lowercase name, also used as the section header of range lists.
+    pub fn name(self) -> &'static str {
+        match self {
+            CloudProvider::Aws => "aws",
+            CloudProvider::Gcp => "gcp",
+            CloudProvider::Azure => "azure",
+            CloudProvider::Ovh => "ovh",
+            CloudProvider::Hetzner => "hetzner",
+            CloudProvider::DigitalOcean => "digitalocean",
+        }
+    }
+
+    /// Where the provider publishes its ranges, if it has a stable URL.
+    ///
+    /// Azure publishes its service tags under a URL changing every week, OVH and Hetzner publish
+    /// no feed at all; their ranges can be exported from the RIR databases as a list of networks.
+    pub fn feed_url(self) -> Option<&'static str> {
+        match self {
+            CloudProvider::Aws => Some("https://ip-ranges.amazonaws.com/ip-ranges.json"),
+            CloudProvider::Gcp => Some("https://www.gstatic.com/ipranges/cloud.json"),
+            CloudProvider::DigitalOcean => Some("https://www.digitalocean.com/geo/google.csv"),
+            CloudProvider::Azure | CloudProvider::Ovh | CloudProvider::Hetzner => None,
+        }
+    }
+
+    /// Extracts the networks from the provider's feed format.
+    fn parse_feed(self, feed: &str) -> Result<Vec<IpNet>, String> {
+        let prefixes = match self {
+            CloudProvider::Aws => {
+                let document = json::parse(feed)?;
+                let mut prefixes = json_strings(&document, "prefixes", &["ip_prefix"]);
+                prefixes.extend(json_strings(&document, "ipv6_prefixes", &["ipv6_prefix"]));
+                prefixes
+            }
+            CloudProvider::Gcp => json_strings(&json::parse(feed)?, "prefixes", &["ipv4Prefix", "ipv6Prefix"]),
+            CloudProvider::Azure => json::parse(feed)?
+                .get("values")
+                .and_then(Value::as_array)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|value| value.get("properties")?.get("addressPrefixes")?.as_array())
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            // CSV rows start with the network, plain lists have nothing else on the line
+            CloudProvider::Ovh | CloudProvider::Hetzner | CloudProvider::DigitalOcean => feed
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.split(',').next().unwrap_or_default().to_string())
+                .collect(),
+        };
+        if prefixes.is_empty() {
+            return Err("feed contains no networks".to_string());
+        }
+        prefixes.iter().map(|prefix| prefix.parse::<IpNet>()).collect()
+    }
+}
+
+/// Collects `document[array][*][key]` for each of the keys.
+fn json_strings(document: &Value, array: &str, keys: &[&str]) -> Vec<String> {
+    document
+        .get(array)
+        .and_then(Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|entry| keys.iter().filter_map(|key| entry.get(key)?.as_str()))
+        .map(str::to_string)
+        .collect()
+}
+
+impl fmt::Display for CloudProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CloudProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CloudProvider::ALL
+            .into_iter()
+            .find(|provider| provider.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown cloud provider {:?}", s))
+    }
+}
+
+/// Address ranges per cloud provider.
+///
+/// ```
+/// use BotGuardLib::datacenter::{CloudProvider, DatacenterRanges};
+///
+/// let mut ranges = DatacenterRanges::from_lists("[aws]\n3.0.0.0/9\n[hetzner]\n2a01:4f8::/29").unwrap();
+/// assert_eq!(ranges.check_datacenter_ip("3.5.140.2".parse().unwrap()), Some(CloudProvider::Aws));
+/// assert_eq!(ranges.check_datacenter_ip("192.0.2.1".parse().unwrap()), None);
+///
+/// let feed = r#"{"prefixes": [{"ipv4Prefix": "34.64.0.0/10"}, {"ipv6Prefix": "2600:1900::/28"}]}"#;
+/// assert_eq!(ranges.refresh(CloudProvider::Gcp, feed).unwrap(), 2);
+/// assert_eq!(ranges.check_datacenter_ip("34.80.1.1".parse().unwrap()), Some(CloudProvider::Gcp));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DatacenterRanges {
+    ranges: IpRangeSet<CloudProvider>,
+}
+
+impl DatacenterRanges {
+    /// Creates an empty set, fill it with [`DatacenterRanges::refresh`].
+    pub fn new() -> Self {
+        DatacenterRanges::default()
+    }
+
+    /// The ranges bundled with the crate, a snapshot of the providers' feeds.
+    #[cfg(feature = "datacenter-ranges")]
+    pub fn bundled() -> Self {
+        DatacenterRanges::from_lists(_RANGES).expect("the bundled datacenter ranges are valid")
+    }
+
+    /// Parses networks listed one per line under a `[provider]` header, `#` starts a comment line.
+    pub fn from_lists(lists: &str) -> Result<Self, BotGuardError> {
+        let invalid = |reason: String| BotGuardError::InvalidFeed { feed: "datacenter ranges".to_string(), reason };
+        let mut provider = None;
+        let mut networks = Vec::new();
+        for (number, line) in lists.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                provider = Some(name.parse::<CloudProvider>().map_err(|e| invalid(format!("line {}: {}", number, e)))?);
+                continue;
+            }
+            let provider = provider.ok_or_else(|| invalid(format!("line {}: network outside of a [provider] section", number)))?;
+            let net = line.parse::<IpNet>().map_err(|e| invalid(format!("line {}: {}", number, e)))?;
+            networks.push((net, provider));
+        }
+        Ok(DatacenterRanges { ranges: networks.into_iter().collect() })
+    }
+
+    /// Returns the provider owning the address.
+    pub fn check_datacenter_ip(&self, ip: IpAddr) -> Option<CloudProvider> {
+        self.ranges.get(ip).copied()
+    }
+
+    /// Replaces the ranges of one provider with the networks of its published feed.
+    ///
+    /// The feed is the provider's own format: `ip-ranges.json` for AWS, `cloud.json` for GCP, the
+    /// service tags JSON for Azure, the geofeed CSV for DigitalOcean and a list of networks for
+    /// OVH and Hetzner. Returns the number of networks loaded, on error the old ranges stay.
+    pub fn refresh(&mut self, provider: CloudProvider, feed: &str) -> Result<usize, BotGuardError> {
+        let networks = provider
+            .parse_feed(feed)
+            .map_err(|reason| BotGuardError::InvalidFeed { feed: provider.to_string(), reason })?;
+        let count = networks.len();
+        self.ranges.retain(|_, owner| *owner != provider);
+        self.ranges.extend(networks.into_iter().map(|net| (net, provider)));
+        Ok(count)
+    }
+
+    /// Downloads the provider's published feed from [`CloudProvider::feed_url`] and applies it
+    /// with [`DatacenterRanges::refresh`], providers without a feed fail with
+    /// [`BotGuardError::InvalidFeed`].
+    pub fn refresh_from_feed(&mut self, provider: CloudProvider, timeout: Duration) -> Result<usize, BotGuardError> {
+        let url = provider
+            .feed_url()
+            .ok_or_else(|| BotGuardError::InvalidFeed { feed: provider.to_string(), reason: "the provider publishes no feed".to_string() })?;
+        self.refresh_from_url(provider, url, timeout)
+    }
+
+    /// Downloads a feed from an `http://` or `https://` URL, e.g. a mirror, and applies it with
+    /// [`DatacenterRanges::refresh`].
+    pub fn refresh_from_url(&mut self, provider: CloudProvider, url: &str, timeout: Duration) -> Result<usize, BotGuardError> {
+        let body = http::get(url, timeout).map_err(|e| BotGuardError::FetchFailed { url: url.to_string(), reason: e.to_string() })?;
+        self.refresh(provider, &String::from_utf8_lossy(&body))
+    }
+
+    /// Iterates over the networks of one provider.
+    pub fn networks(&self, provider: CloudProvider) -> impl Iterator<Item = &IpNet> {
+        self.ranges.iter().filter(move |(_, owner)| **owner == provider).map(|(net, _)| net)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+/// Checks an address against the bundled ranges, parsed on first use.
+///
+/// ```
+/// use BotGuardLib::datacenter::{check_datacenter_ip, CloudProvider};
+///
+/// assert_eq!(check_datacenter_ip("2a01:4f8:c17::1".parse().unwrap()), Some(CloudProvider::Hetzner));
+/// assert_eq!(check_datacenter_ip("192.0.2.1".parse().unwrap()), None);
+/// ```
+#[cfg(feature = "datacenter-ranges")]
+pub fn check_datacenter_ip(ip: IpAddr) -> Option<CloudProvider> {
+    static BUNDLED: std::sync::OnceLock<DatacenterRanges> = std::sync::OnceLock::new();
+    BUNDLED.get_or_init(DatacenterRanges::bundled).check_datacenter_ip(ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_provider_feeds() {
+        let mut ranges = DatacenterRanges::new();
+        let aws = r#"{"syncToken": "1", "prefixes": [{"ip_prefix": "3.0.0.0/9", "region": "us-east-1"}],
+            "ipv6_prefixes": [{"ipv6_prefix": "2600:1f00::/24"}]}"#;
+        assert_eq!(ranges.refresh(CloudProvider::Aws, aws).unwrap(), 2);
+        let azure = r#"{"values": [{"name": "AzureCloud", "properties": {"addressPrefixes": ["13.64.0.0/11", "2603:1000::/24"]}}]}"#;
+        assert_eq!(ranges.refresh(CloudProvider::Azure, azure).unwrap(), 2);
+        let digitalocean = "104.131.0.0/18,US,US-NY,New York,10011\n2604:a880::/48,US,US-NY,New York,10011\n";
+        assert_eq!(ranges.refresh(CloudProvider::DigitalOcean, digitalocean).unwrap(), 2);
+        assert_eq!(ranges.refresh(CloudProvider::Ovh, "# export\n51.68.0.0/16\n").unwrap(), 1);
+
+        assert_eq!(ranges.check_datacenter_ip(ip("3.80.0.1")), Some(CloudProvider::Aws));
+        assert_eq!(ranges.check_datacenter_ip(ip("2603:1000::9")), Some(CloudProvider::Azure));
+        assert_eq!(ranges.check_datacenter_ip(ip("104.131.1.1")), Some(CloudProvider::DigitalOcean));
+        assert_eq!(ranges.check_datacenter_ip(ip("51.68.3.3")), Some(CloudProvider::Ovh));
+        assert_eq!(ranges.len(), 7);
+
+        // a refresh replaces only the ranges of its provider
+        ranges.refresh(CloudProvider::Aws, r#"{"prefixes": [{"ip_prefix": "52.0.0.0/10"}]}"#).unwrap();
+        assert_eq!(ranges.check_datacenter_ip(ip("3.80.0.1")), None);
+        assert_eq!(ranges.networks(CloudProvider::Aws).count(), 1);
+        assert_eq!(ranges.check_datacenter_ip(ip("51.68.3.3")), Some(CloudProvider::Ovh));
+
+        // a broken feed keeps the old ranges
+        assert!(matches!(ranges.refresh(CloudProvider::Aws, "{}"), Err(BotGuardError::InvalidFeed { .. })));
+        assert!(ranges.refresh(CloudProvider::Aws, r#"{"prefixes": [{"ip_prefix": "52.0.0/10"}]}"#).is_err());
+        assert_eq!(ranges.check_datacenter_ip(ip("52.1.1.1")), Some(CloudProvider::Aws));
+    }
+
+    #[test]
+    fn rejects_malformed_lists() {
+        assert!(DatacenterRanges::from_lists("3.0.0.0/9").is_err());
+        assert!(DatacenterRanges::from_lists("[unknown]\n3.0.0.0/9").is_err());
+        assert!(DatacenterRanges::from_lists("[aws]\n3.0.0/9").is_err());
+    }
+
+    #[test]
+    fn refreshes_from_url() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/ranges.txt", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            write!(stream, "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nc\r\n5.9.0.0/16\r\n\r\n0\r\n\r\n").unwrap();
+        });
+        let mut ranges = DatacenterRanges::new();
+        assert_eq!(ranges.refresh_from_url(CloudProvider::Hetzner, &url, Duration::from_secs(5)).unwrap(), 1);
+        server.join().unwrap();
+        assert_eq!(ranges.check_datacenter_ip(ip("5.9.8.7")), Some(CloudProvider::Hetzner));
+
+        let error = ranges.refresh_from_url(CloudProvider::Hetzner, "http://127.0.0.1:1/", Duration::from_secs(1));
+        assert!(matches!(error, Err(BotGuardError::FetchFailed { .. })));
+        assert!(matches!(ranges.refresh_from_feed(CloudProvider::Hetzner, Duration::from_secs(1)), Err(BotGuardError::InvalidFeed { .. })));
+        assert!(CloudProvider::Aws.feed_url().unwrap().starts_with("https://"));
+    }
+
+    #[test]
+    #[cfg(feature = "datacenter-ranges")]
+    fn bundled_ranges() {
+        let ranges = DatacenterRanges::bundled();
+        for provider in CloudProvider::ALL {
+            assert!(ranges.networks(provider).count() > 0, "no bundled ranges for {}", provider);
+        }
+        assert_eq!(check_datacenter_ip(ip("35.192.0.1")), Some(CloudProvider::Gcp));
+        assert_eq!(check_datacenter_ip(ip("167.99.1.1")), Some(CloudProvider::DigitalOcean));
+        assert_eq!(check_datacenter_ip(ip("127.0.0.1")), None);
+    }
+}
@@ -0,0 +1,267 @@
+// Decoy responses for scrapers: a request the policy answers with `Action::Decoy` gets a page
+// that looks like the real thing, well-formed JSON or HTML with a plausible structure, filled with
+// generated junk. A scraper receiving an error knows it was detected and adapts, one receiving
+// junk keeps collecting it.
+//
+// The junk is derived from the path, so the same URL always shows the same data and comparing two
+// downloads gives nothing away. Decoys are sent with `Cache-Control: no-store`, a cache in front
+// of the site must never hand them to real visitors.
+
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+
+use crate::actions::Response;
+use crate::policy::{glob_match, Action};
+use crate::request::{RequestSnapshot, RequestVerdict};
+
+const WORDS: &[&str] = &[
+    "classic", "premium", "compact", "wireless", "organic", "vintage", "steel", "cotton", "travel", "smart", "outdoor", "modular", "portable",
+    "deluxe", "studio", "urban", "carbon", "eco", "ultra", "mini", "lamp", "kettle", "backpack", "jacket", "speaker", "router", "blender",
+    "chair", "monitor", "bottle", "sneaker", "watch", "charger", "desk", "camera", "mug", "helmet", "notebook", "blanket", "drill",
+];
+
+/// Produces the decoy response for a request.
+pub trait DecoyResponder: Send + Sync {
+    fn respond(&self, request: &RequestSnapshot) -> Response;
+}
+
+/// A body with placeholders replaced with generated values: `{word}`, `{words}` (three words),
+/// `{sentence}`, `{number}` (up to 10000), `{price}`, `{id}` (12 hex digits) and `{email}`. The
+/// values contain no characters needing escapes in JSON or HTML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecoyTemplate {
+    pub content_type: String,
+    pub body: String,
+}
+
+impl DecoyTemplate {
+    pub fn json(body: &str) -> Self {
+        DecoyTemplate { content_type: "application/json".to_string(), body: body.to_string() }
+    }
+
+    pub fn html(body: &str) -> Self {
+        DecoyTemplate { content_type: "text/html; charset=utf-8".to_string(), body: body.to_string() }
+    }
+
+    fn fill(&self, junk: &mut Junk) -> String {
+        let mut out = String::with_capacity(self.body.len());
+        let mut rest = self.body.as_str();
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let value = rest[start..].find('}').and_then(|end| Some((junk.placeholder(&rest[start + 1..start + end])?, end)));
+            match value {
+                Some((value, end)) => {
+                    out.push_str(&value);
+                    rest = &rest[start + end + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// Generated values, deterministic for a seed.
+struct Junk(u64);
+
+impl Junk {
+    fn next(&mut self) -> u64 {
+        // xorshift
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+
+    fn word(&mut self) -> &'static str {
+        WORDS[self.below(WORDS.len() as u64) as usize]
+    }
+
+    fn words(&mut self, n: usize) -> String {
+        (0..n).map(|_| self.word()).collect::<Vec<_>>().join(" ")
+    }
+
+    fn title(&mut self) -> String {
+        self.words(3).split(' ').map(capitalize).collect::<Vec<_>>().join(" ")
+    }
+
+    fn sentence(&mut self) -> String {
+        let len = 6 + self.below(8) as usize;
+        format!("{}.", capitalize(&self.words(len)))
+    }
+
+    fn price(&mut self) -> String {
+        format!("{}.{:02}", 1 + self.below(500), self.below(100))
+    }
+
+    fn id(&mut self) -> String {
+        format!("{:012x}", self.next() & 0xffff_ffff_ffff)
+    }
+
+    fn placeholder(&mut self, name: &str) -> Option<String> {
+        Some(match name {
+            "word" => self.word().to_string(),
+            "words" => self.words(3),
+            "sentence" => self.sentence(),
+            "number" => self.below(10_001).to_string(),
+            "price" => self.price(),
+            "id" => self.id(),
+            "email" => format!("{}.{}@example.com", self.word(), self.below(1000)),
+            _ => return None,
+        })
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+/// The default responder: a product listing as JSON for API-looking requests and as HTML
+/// otherwise, or the template of the first route glob matching the path.
+///
+/// ```
+/// use BotGuardLib::decoy::{DecoyResponder, DecoyTemplate, GeneratedDecoys};
+/// use BotGuardLib::request::RequestSnapshot;
+///
+/// let decoys = GeneratedDecoys::new(b"decoy seed")
+///     .template("/api/users/*", DecoyTemplate::json(r#"{"id":"{id}","email":"{email}","score":{number}}"#));
+///
+/// let user = decoys.respond(&RequestSnapshot::new("GET", "/api/users/42"));
+/// assert!(user.body.starts_with(r#"{"id":""#) && user.body.contains("@example.com"));
+/// assert_eq!(user.body, decoys.respond(&RequestSnapshot::new("GET", "/api/users/42")).body);
+///
+/// let listing = decoys.respond(&RequestSnapshot::new("GET", "/catalog/shoes"));
+/// assert_eq!(listing.header_value("content-type"), Some("text/html; charset=utf-8"));
+/// assert_eq!(listing.header_value("cache-control"), Some("no-store"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct GeneratedDecoys {
+    seed: u64,
+    templates: Vec<(String, DecoyTemplate)>,
+}
+
+impl Default for GeneratedDecoys {
+    /// Seeded randomly, the junk of a path changes when the process restarts.
+    fn default() -> Self {
+        GeneratedDecoys { seed: RandomState::new().build_hasher().finish(), templates: Vec::new() }
+    }
+}
+
+impl GeneratedDecoys {
+    /// Seeded with a secret, instances sharing it serve the same junk for a path.
+    pub fn new(secret: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        secret.hash(&mut hasher);
+        GeneratedDecoys { seed: hasher.finish(), templates: Vec::new() }
+    }
+
+    /// Serves the template to paths matching the glob, checked in the order they were added.
+    pub fn template(mut self, route: &str, template: DecoyTemplate) -> Self {
+        self.templates.push((route.to_string(), template));
+        self
+    }
+
+    fn junk(&self, path: &str) -> Junk {
+        let mut hasher = DefaultHasher::new();
+        (self.seed, path).hash(&mut hasher);
+        Junk(hasher.finish() | 1)
+    }
+}
+
+impl DecoyResponder for GeneratedDecoys {
+    fn respond(&self, request: &RequestSnapshot) -> Response {
+        let mut junk = self.junk(&request.path);
+        let (content_type, body) = match self.templates.iter().find(|(route, _)| glob_match(route, &request.path)) {
+            Some((_, template)) => (template.content_type.as_str(), template.fill(&mut junk)),
+            None if wants_json(request) => ("application/json", json_listing(&mut junk)),
+            None => ("text/html; charset=utf-8", html_listing(&mut junk, &request.path)),
+        };
+        Response::new(200, content_type, body).header("Cache-Control", "no-store")
+    }
+}
+
+fn wants_json(request: &RequestSnapshot) -> bool {
+    let accept = request.header_value("accept").unwrap_or_default();
+    request.path.starts_with("/api/") || request.path.ends_with(".json") || (accept.contains("json") && !accept.contains("html"))
+}
+
+fn json_listing(junk: &mut Junk) -> String {
+    let count = 10 + junk.below(20);
+    let items = (0..count)
+        .map(|_| {
+            format!(
+                "{{\"id\":\"{}\",\"name\":\"{}\",\"price\":{},\"stock\":{},\"description\":\"{}\"}}",
+                junk.id(),
+                junk.title(),
+                junk.price(),
+                junk.below(250),
+                junk.sentence()
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("{{\"items\":[{}],\"total\":{},\"page\":1}}", items.join(","), count + junk.below(5000))
+}
+
+fn html_listing(junk: &mut Junk, path: &str) -> String {
+    let title = junk.title();
+    let base = path.trim_end_matches('/');
+    let items = (0..10 + junk.below(20))
+        .map(|_| format!("<li><a href=\"{}/{}\">{}</a> <span class=\"price\">${}</span><p>{}</p></li>", base, junk.id(), junk.title(), junk.price(), junk.sentence()))
+        .collect::<String>();
+    format!("<!doctype html><html><head><meta charset=\"utf-8\"><title>{}</title></head><body><h1>{}</h1><ul>{}</ul></body></html>", title, title, items)
+}
+
+/// The decoy for a request the policy answered with [`Action::Decoy`], `None` for other actions.
+pub fn respond(verdict: &RequestVerdict, request: &RequestSnapshot, responder: &dyn DecoyResponder) -> Option<Response> {
+    (verdict.action == Action::Decoy).then(|| responder.respond(request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::policy::{Condition, PolicyEngine, PolicyRule};
+    use crate::request::BotGuard;
+    use crate::BotDetector;
+
+    #[test]
+    fn generates_valid_listings() {
+        let decoys = GeneratedDecoys::new(b"secret");
+        let api = decoys.respond(&RequestSnapshot::new("GET", "/api/products?page=2"));
+        let parsed = json::parse(&api.body).unwrap();
+        assert!(parsed.get("items").and_then(json::Value::as_array).is_some_and(|items| items.len() >= 10));
+        let accepts_json = RequestSnapshot::new("GET", "/products").header("Accept", "application/json");
+        assert_eq!(decoys.respond(&accepts_json).header_value("content-type"), Some("application/json"));
+
+        let page = decoys.respond(&RequestSnapshot::new("GET", "/catalog/"));
+        assert!(page.body.starts_with("<!doctype html>") && page.body.contains("<a href=\"/catalog/"));
+        assert_ne!(page.body, decoys.respond(&RequestSnapshot::new("GET", "/catalog/2")).body);
+        assert_ne!(page.body, GeneratedDecoys::new(b"other").respond(&RequestSnapshot::new("GET", "/catalog/")).body);
+
+        let template = DecoyTemplate::json("{\"a\":{number},\"b\":\"{unknown}\",\"c\":\"{words}\"");
+        let filled = template.fill(&mut Junk(7));
+        assert!(filled.starts_with("{\"a\":") && filled.contains("\"b\":\"{unknown}\""), "{}", filled);
+    }
+
+    #[test]
+    fn only_decoy_actions_get_decoys() {
+        let policy = PolicyEngine::new(Action::Allow).rule(PolicyRule::new("/catalog/*", Action::Decoy).when(Condition::Category("scrapers".to_string())));
+        let guard = BotGuard::new(BotDetector::new("[scrapers]\n^scrapy/")).policy(policy);
+        let decoys = GeneratedDecoys::default();
+
+        let scraper = RequestSnapshot::new("GET", "/catalog/shoes").user_agent("Scrapy/2.11");
+        let response = respond(&guard.evaluate(&scraper), &scraper, &decoys).unwrap();
+        assert_eq!(response.status, 200);
+        let visitor = RequestSnapshot::new("GET", "/catalog/shoes").user_agent("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0");
+        assert_eq!(respond(&guard.evaluate(&visitor), &visitor, &decoys), None);
+    }
+}
@@ -0,0 +1,388 @@
+// Stable wire types for verdicts, for services exposing them over their own REST or GraphQL APIs
+// instead of hand-rolling a JSON mapping of `RequestVerdict`, whose reasons gain variants and
+// fields as checks are added.
+//
+// The DTOs are plain owned data with public fields. A reason keeps its stable `code` and `detail`
+// text and flattens the fields of its variant into string key-value pairs, so a new reason is a
+// new code rather than a new shape. The JSON always carries `schema`, the `SCHEMA_VERSION` it was
+// written with. The version only changes when a field is removed or changes meaning, and parsing
+// refuses documents of a newer schema. With the `serde` feature the DTOs also implement `Serialize`
+// and `Deserialize`, in the same shape as their JSON, reason fields included.
+
+use std::str::FromStr;
+
+use crate::BotInfo;
+use crate::json::{self, Value};
+use crate::request::{Reason, RequestVerdict};
+use crate::review::action_name;
+use crate::Verdict;
+
+/// Version of the JSON written by [`VerdictDto::to_json`].
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A [`Reason`] of a verdict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReasonDto {
+    /// [`Reason::code`].
+    pub code: String,
+    /// Human readable, may change between releases.
+    pub detail: String,
+    /// The fields of the reason, e.g. `group` and `pattern` of a pattern match, numbers written
+    /// as text.
+    #[cfg_attr(feature = "serde", serde(with = "fields_object"))]
+    pub fields: Vec<(String, String)>,
+}
+
+impl From<&Reason> for ReasonDto {
+    fn from(reason: &Reason) -> Self {
+        let mut dto = ReasonDto { code: reason.code().to_string(), detail: reason.to_string(), fields: Vec::new() };
+        if let Ok(Value::Object(members)) = json::parse(&reason.to_json()) {
+            for (name, value) in members.into_iter().filter(|(name, _)| name != "code" && name != "detail") {
+                let value = match value {
+                    Value::String(text) => text,
+                    Value::Number(number) => number.to_string(),
+                    Value::Bool(flag) => flag.to_string(),
+                    _ => continue,
+                };
+                dto.fields.push((name, value));
+            }
+        }
+        dto
+    }
+}
+
+impl ReasonDto {
+    /// The value of a field.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    fn push_json(&self, out: &mut String) {
+        out.push_str("{\"code\":");
+        json::push_str(out, &self.code);
+        out.push_str(",\"detail\":");
+        json::push_str(out, &self.detail);
+        out.push_str(",\"fields\":{");
+        for (i, (name, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            json::push_str(out, name);
+            out.push(':');
+            json::push_str(out, value);
+        }
+        out.push_str("}}");
+    }
+
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let fields = match value.get("fields") {
+            Some(Value::Object(members)) => members
+                .iter()
+                .map(|(name, value)| value.as_str().map(|value| (name.clone(), value.to_string())).ok_or_else(|| format!("reason field {:?} must be a string", name)))
+                .collect::<Result<_, _>>()?,
+            _ => return Err("reason fields must be an object".to_string()),
+        };
+        Ok(ReasonDto { code: string(value, "code")?, detail: string(value, "detail")?, fields })
+    }
+}
+
+/// A known bot from the [`crate::BotDatabase`], without its pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BotInfoDto {
+    pub name: String,
+    pub operator: String,
+    pub url: String,
+    pub category: String,
+    pub respects_robots_txt: bool,
+}
+
+impl From<&BotInfo> for BotInfoDto {
+    fn from(info: &BotInfo) -> Self {
+        BotInfoDto {
+            name: info.name.clone(),
+            operator: info.operator.clone(),
+            url: info.url.clone(),
+            category: info.category.clone(),
+            respects_robots_txt: info.respects_robots_txt,
+        }
+    }
+}
+
+impl BotInfoDto {
+    fn push_json(&self, out: &mut String) {
+        out.push_str("{\"name\":");
+        json::push_str(out, &self.name);
+        out.push_str(",\"operator\":");
+        json::push_str(out, &self.operator);
+        out.push_str(",\"url\":");
+        json::push_str(out, &self.url);
+        out.push_str(",\"category\":");
+        json::push_str(out, &self.category);
+        out.push_str(&format!(",\"respects_robots_txt\":{}}}", self.respects_robots_txt));
+    }
+
+    fn from_value(value: &Value) -> Result<Self, String> {
+        Ok(BotInfoDto {
+            name: string(value, "name")?,
+            operator: string(value, "operator")?,
+            url: string(value, "url")?,
+            category: string(value, "category")?,
+            respects_robots_txt: value.get("respects_robots_txt").and_then(Value::as_bool).ok_or("respects_robots_txt must be a boolean")?,
+        })
+    }
+}
+
+/// A [`RequestVerdict`] on the wire.
+///
+/// ```
+/// use BotGuardLib::dto::{VerdictDto, SCHEMA_VERSION};
+/// use BotGuardLib::request::{BotGuard, RequestSnapshot};
+/// use BotGuardLib::{BotDatabase, BotDetector};
+///
+/// let guard = BotGuard::new(BotDetector::new("[search-engines]\ngooglebot"));
+/// let user_agent = "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)";
+/// let verdict = guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent(user_agent));
+/// let dto = VerdictDto::from(&verdict).bot(BotDatabase::bundled().lookup(user_agent));
+///
+/// let json = dto.to_json();
+/// assert!(json.starts_with(r#"{"schema":1,"verdict":"bot","score":1,"category":"search-engines","#));
+/// let parsed: VerdictDto = json.parse().unwrap();
+/// assert_eq!(parsed.reasons[0].field("pattern"), Some("googlebot"));
+/// assert_eq!(parsed.bot.map(|bot| bot.operator), Some("Google".to_string()));
+/// assert_eq!(parsed.schema, SCHEMA_VERSION);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VerdictDto {
+    /// [`SCHEMA_VERSION`] of the document, taken as is when parsing.
+    pub schema: u32,
+    /// `human`, `suspicious` or `bot`.
+    pub verdict: String,
+    /// Between `0.0` (human) and `1.0` (bot).
+    pub score: f32,
+    pub category: Option<String>,
+    /// `allow`, `block`, `challenge`, `decoy`, `tag:<tag>`, `rate_limit:<requests>/<millis>` or
+    /// `tarpit:<bytes per second>`.
+    pub action: String,
+    /// The action not taken in shadow mode, in the form of `action`.
+    pub shadowed: Option<String>,
+    pub tags: Vec<String>,
+    pub reasons: Vec<ReasonDto>,
+    /// The signed reference, see [`crate::reference`].
+    pub reference: Option<String>,
+    /// The bot the user-agent belongs to, if known, see [`VerdictDto::bot`].
+    pub bot: Option<BotInfoDto>,
+}
+
+impl From<&RequestVerdict> for VerdictDto {
+    fn from(verdict: &RequestVerdict) -> Self {
+        VerdictDto {
+            schema: SCHEMA_VERSION,
+            verdict: match verdict.verdict {
+                Verdict::Human => "human",
+                Verdict::Suspicious => "suspicious",
+                Verdict::Bot => "bot",
+            }
+            .to_string(),
+            score: verdict.score,
+            category: verdict.category.clone(),
+            action: action_name(&verdict.action),
+            shadowed: verdict.shadowed.as_ref().map(action_name),
+            tags: verdict.tags.clone(),
+            reasons: verdict.reasons.iter().map(ReasonDto::from).collect(),
+            reference: verdict.reference.clone(),
+            bot: None,
+        }
+    }
+}
+
+impl VerdictDto {
+    /// Adds the bot of a [`crate::BotDatabase::lookup`].
+    pub fn bot(mut self, info: Option<&BotInfo>) -> Self {
+        self.bot = info.map(BotInfoDto::from);
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = format!("{{\"schema\":{},\"verdict\":", self.schema);
+        json::push_str(&mut out, &self.verdict);
+        out.push_str(&format!(",\"score\":{},\"category\":", self.score));
+        json::push_opt_str(&mut out, self.category.as_deref());
+        out.push_str(",\"action\":");
+        json::push_str(&mut out, &self.action);
+        out.push_str(",\"shadowed\":");
+        json::push_opt_str(&mut out, self.shadowed.as_deref());
+        out.push_str(",\"tags\":[");
+        for (i, tag) in self.tags.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            json::push_str(&mut out, tag);
+        }
+        out.push_str("],\"reasons\":[");
+        for (i, reason) in self.reasons.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            reason.push_json(&mut out);
+        }
+        out.push_str("],\"reference\":");
+        json::push_opt_str(&mut out, self.reference.as_deref());
+        out.push_str(",\"bot\":");
+        match &self.bot {
+            Some(bot) => bot.push_json(&mut out),
+            None => out.push_str("null"),
+        }
+        out.push('}');
+        out
+    }
+}
+
+impl FromStr for VerdictDto {
+    type Err = String;
+
+    /// Parses the JSON of [`VerdictDto::to_json`], failing on documents of a newer schema.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let document = json::parse(s)?;
+        let schema = document.get("schema").and_then(Value::as_f64).filter(|schema| schema.fract() == 0.0 && *schema >= 1.0).ok_or("schema must be a positive integer")? as u32;
+        if schema > SCHEMA_VERSION {
+            return Err(format!("schema {} is newer than the supported {}", schema, SCHEMA_VERSION));
+        }
+        let list = |name: &str| document.get(name).and_then(Value::as_array).ok_or_else(|| format!("{} must be an array", name));
+        Ok(VerdictDto {
+            schema,
+            verdict: string(&document, "verdict")?,
+            score: document.get("score").and_then(Value::as_f64).ok_or("score must be a number")? as f32,
+            category: optional_string(&document, "category")?,
+            action: string(&document, "action")?,
+            shadowed: optional_string(&document, "shadowed")?,
+            tags: list("tags")?.iter().map(|tag| tag.as_str().map(str::to_string).ok_or("tags must be strings")).collect::<Result<_, _>>()?,
+            reasons: list("reasons")?.iter().map(ReasonDto::from_value).collect::<Result<_, _>>()?,
+            reference: optional_string(&document, "reference")?,
+            bot: match document.get("bot") {
+                None | Some(Value::Null) => None,
+                Some(bot) => Some(BotInfoDto::from_value(bot)?),
+            },
+        })
+    }
+}
+
+fn string(value: &Value, name: &str) -> Result<String, String> {
+    value.get(name).and_then(Value::as_str).map(str::to_string).ok_or_else(|| format!("{} must be a string", name))
+}
+
+/// A string member that may be `null` or missing.
+fn optional_string(value: &Value, name: &str) -> Result<Option<String>, String> {
+    match value.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(text)) => Ok(Some(text.clone())),
+        Some(_) => Err(format!("{} must be a string or null", name)),
+    }
+}
+
+/// The fields of a [`ReasonDto`] as a JSON object, in their order.
+#[cfg(feature = "serde")]
+mod fields_object {
+    use serde::de::{MapAccess, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(fields: &[(String, String)], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(fields.iter().map(|(name, value)| (name, value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(String, String)>, D::Error> {
+        struct Fields;
+
+        impl<'de> Visitor<'de> for Fields {
+            type Value = Vec<(String, String)>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an object of string fields")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(fields)
+            }
+        }
+
+        deserializer.deserialize_map(Fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Action;
+    use std::time::Duration;
+
+    #[test]
+    fn round_trips_verdicts() {
+        let verdict = RequestVerdict {
+            verdict: Verdict::Suspicious,
+            score: 0.65,
+            category: None,
+            reasons: vec![Reason::RateExceeded { limit: 10, per: Duration::from_secs(60) }, Reason::MissingHeader { name: "accept \"language\"".to_string() }],
+            action: Action::RateLimit { requests: 10, per: Duration::from_secs(60) },
+            shadowed: Some(Action::Block),
+            tags: vec!["api".to_string()],
+            log: true,
+            reference: Some("abc".to_string()),
+        };
+        let dto = VerdictDto::from(&verdict);
+        assert_eq!(dto.reasons[0].fields, vec![("limit".to_string(), "10".to_string()), ("per_ms".to_string(), "60000".to_string())]);
+        assert_eq!((dto.action.as_str(), dto.shadowed.as_deref()), ("rate_limit:10/60000", Some("block")));
+        assert_eq!(dto.to_json().parse::<VerdictDto>(), Ok(dto.clone()));
+        assert!(dto.to_json().contains(r#"{"code":"missing_header","detail":"browser request without accept \"language\" header","fields":{"name":"accept \"language\""}}"#));
+    }
+
+    #[test]
+    fn rejects_newer_and_malformed_documents() {
+        let json = VerdictDto::from(&RequestVerdict {
+            verdict: Verdict::Human,
+            score: 0.0,
+            category: None,
+            reasons: Vec::new(),
+            action: Action::Allow,
+            shadowed: None,
+            tags: Vec::new(),
+            log: true,
+            reference: None,
+        })
+        .to_json();
+        assert_eq!(json, r#"{"schema":1,"verdict":"human","score":0,"category":null,"action":"allow","shadowed":null,"tags":[],"reasons":[],"reference":null,"bot":null}"#);
+        assert_eq!(json.replace("\"schema\":1", "\"schema\":2").parse::<VerdictDto>(), Err("schema 2 is newer than the supported 1".to_string()));
+        assert!(json.replace("\"schema\":1,", "").parse::<VerdictDto>().is_err());
+        assert_eq!(json.replace("\"tags\":[]", "\"tags\":[1]").parse::<VerdictDto>(), Err("tags must be strings".to_string()));
+        assert!(json.replace("\"reasons\":[]", "\"reasons\":[{\"code\":\"x\"}]").parse::<VerdictDto>().is_err());
+        // fields added by later versions of the same schema are ignored
+        assert!(json.replace("\"bot\":null", "\"bot\":null,\"extra\":true").parse::<VerdictDto>().is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_as_the_json() {
+        let verdict = RequestVerdict {
+            verdict: Verdict::Bot,
+            score: 0.75,
+            category: Some("scrapers".to_string()),
+            reasons: vec![Reason::MissingHeader { name: "accept".to_string() }],
+            action: Action::Block,
+            shadowed: None,
+            tags: Vec::new(),
+            log: true,
+            reference: None,
+        };
+        let dto = VerdictDto::from(&verdict);
+        let json = serde_json::to_string(&dto).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), serde_json::from_str::<serde_json::Value>(&dto.to_json()).unwrap());
+        assert_eq!(serde_json::from_str::<VerdictDto>(&dto.to_json()).unwrap(), dto);
+        assert_eq!(json.parse::<VerdictDto>(), Ok(dto));
+    }
+}
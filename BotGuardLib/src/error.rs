@@ -0,0 +1,63 @@
+// Error type shared by every fallible operation of the crate.
+
+use alloc::string::String;
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BotGuardError {
+    /// A pattern is not a valid regular expression.
+    InvalidPattern { pattern: String, reason: String },
+    /// A single pattern compiles to more than the configured size limit.
+    PatternTooLarge { pattern: String, limit: usize },
+    /// Every pattern of a group is fine on its own, but the combined regex exceeds the size limit.
+    GroupTooLarge { group: String, limit: usize },
+    /// A bot database is not valid JSON or an entry misses a field.
+    InvalidDatabase { reason: String },
+    /// A downloaded or loaded list cannot be parsed.
+    InvalidFeed { feed: String, reason: String },
+    /// A remote resource could not be downloaded.
+    FetchFailed { url: String, reason: String },
+    /// A file could not be read or written.
+    IoError { path: String, reason: String },
+    /// A configuration file is malformed or contains an invalid setting.
+    InvalidConfig { line: Option<usize>, reason: String },
+    /// A CSV or YAML pattern list cannot be read, `line` is the row or line in the file.
+    InvalidImport { format: String, line: usize, reason: String },
+    /// A pattern bundle is unsigned or its signature does not verify with the configured key.
+    SignatureInvalid { origin: String, reason: String },
+    /// A shared state backend such as Redis cannot be reached or answered with an error.
+    StateUnavailable { backend: String, reason: String },
+}
+
+impl fmt::Display for BotGuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BotGuardError::InvalidPattern { pattern, reason } => {
+                write!(f, "invalid bot pattern {:?}: {}", pattern, reason)
+            }
+            BotGuardError::PatternTooLarge { pattern, limit } => {
+                write!(f, "bot pattern {:?} exceeds the compiled size limit of {} bytes", pattern, limit)
+            }
+            BotGuardError::GroupTooLarge { group, limit } => {
+                write!(f, "pattern group {:?} exceeds the compiled size limit of {} bytes", group, limit)
+            }
+            BotGuardError::InvalidDatabase { reason } => write!(f, "invalid bot database: {}", reason),
+            BotGuardError::InvalidFeed { feed, reason } => write!(f, "invalid {} feed: {}", feed, reason),
+            BotGuardError::FetchFailed { url, reason } => write!(f, "failed to fetch {}: {}", url, reason),
+            BotGuardError::IoError { path, reason } => write!(f, "failed to access {}: {}", path, reason),
+            BotGuardError::InvalidConfig { line: Some(line), reason } => {
+                write!(f, "invalid configuration on line {}: {}", line, reason)
+            }
+            BotGuardError::InvalidConfig { line: None, reason } => write!(f, "invalid configuration: {}", reason),
+            BotGuardError::InvalidImport { format, line, reason } => {
+                write!(f, "invalid {} pattern list on line {}: {}", format, line, reason)
+            }
+            BotGuardError::SignatureInvalid { origin, reason } => {
+                write!(f, "rejected pattern bundle from {}: {}", origin, reason)
+            }
+            BotGuardError::StateUnavailable { backend, reason } => write!(f, "state backend {} is unavailable: {}", backend, reason),
+        }
+    }
+}
+
+impl core::error::Error for BotGuardError {}
@@ -0,0 +1,607 @@
+// Export of detection events to an observability pipeline: JSON Lines appended to a file that a
+// log shipper tails, OpenTelemetry log records posted to an OTLP/HTTP collector, or any
+// `EventPublisher` of the application. The `kafka` feature adds a publisher producing to a Kafka
+// topic with librdkafka, and `kafka-rest` adds one that goes through the REST Proxy instead and
+// needs no native client library.
+//
+// Events are written from a background thread in batches. The queue between the detector and the
+// thread is bounded: by default events beyond it are dropped and counted so a stalled disk or
+// collector never slows down request handling, `Backpressure::Block` makes the detector wait
+// instead for pipelines where losing an event is worse than latency.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::events::BotEvent;
+use crate::{http, json};
+
+/// Where [`EventExporter`] writes events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventSink {
+    /// Appends one [`BotEvent::to_json`] object per line, creating the file if needed.
+    JsonLines(PathBuf),
+    /// Posts OTLP/HTTP JSON log records to a collector's `http://` or `https://` logs endpoint, usually
+    /// `http://collector:4318/v1/logs`.
+    Otlp { endpoint: String, service_name: String },
+}
+
+/// What happens to an event when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// The event is dropped and counted in [`EventExporter::dropped`].
+    #[default]
+    Drop,
+    /// The detector waits until there is room.
+    Block,
+}
+
+impl EventSink {
+    pub fn json_lines<P: Into<PathBuf>>(path: P) -> Self {
+        EventSink::JsonLines(path.into())
+    }
+
+    /// [`EventSink::Otlp`] with the service name `botguard`.
+    pub fn otlp(endpoint: &str) -> Self {
+        EventSink::Otlp { endpoint: endpoint.to_string(), service_name: "botguard".to_string() }
+    }
+}
+
+/// Batching settings for [`EventExporter`].
+#[derive(Debug, Clone)]
+pub struct EventLogConfig {
+    /// Maximum number of events per write or POST.
+    pub batch_size: usize,
+    /// A partial batch is written once it is this old.
+    pub flush_interval: Duration,
+    pub queue_capacity: usize,
+    pub backpressure: Backpressure,
+    /// Retries of a failed write or POST before the batch is dropped, with the delay doubling
+    /// from `retry_backoff`.
+    pub max_retries: u32,
+    pub retry_backoff: Duration,
+    /// Timeout of a single POST to the collector.
+    pub timeout: Duration,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        EventLogConfig {
+            batch_size: 500,
+            flush_interval: Duration::from_secs(1),
+            queue_capacity: 10_000,
+            backpressure: Backpressure::Drop,
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(200),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+}
+
+enum Message {
+    Event(BotEvent),
+    Shutdown,
+}
+
+/// Exports detection events from a background thread, queued events are written when the
+/// exporter is dropped.
+///
+/// ```
+/// use BotGuardLib::eventlog::{EventExporter, EventLogConfig, EventSink};
+/// use BotGuardLib::BotDetector;
+///
+/// let path = std::env::temp_dir().join(format!("botguard-doc-events-{}.jsonl", std::process::id()));
+/// let exporter = EventExporter::spawn(&EventSink::json_lines(&path), EventLogConfig::default()).unwrap();
+/// let mut BotDetector = BotDetector::default();
+/// BotDetector.on_detection(exporter.hook());
+/// assert!(BotDetector.check_bot("curl/8.4.0"));
+/// drop(exporter);
+///
+/// let lines = std::fs::read_to_string(&path).unwrap();
+/// assert!(lines.starts_with("{\"user_agent\":\"curl/8.4.0\"") && lines.ends_with("}\n"));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct EventExporter {
+    sender: SyncSender<Message>,
+    worker: Option<JoinHandle<()>>,
+    backpressure: Backpressure,
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    exported: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl EventExporter {
+    /// Starts the export thread. Fails if the file cannot be opened for appending or the
+    /// endpoint is not a valid `http://` URL.
+    pub fn spawn(sink: &EventSink, config: EventLogConfig) -> io::Result<Self> {
+        let writer = Writer::open(sink, &config)?;
+        EventExporter::start(Box::new(writer), config)
+    }
+
+    /// Exports to a publisher of the application instead of one of the [`EventSink`]s.
+    pub fn spawn_publisher<P: EventPublisher + 'static>(publisher: P, config: EventLogConfig) -> io::Result<Self> {
+        EventExporter::start(Box::new(publisher), config)
+    }
+
+    fn start(mut publisher: Box<dyn EventPublisher>, config: EventLogConfig) -> io::Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel(config.queue_capacity.max(1));
+        let counters = Arc::new(Counters::default());
+        let worker_counters = Arc::clone(&counters);
+        let backpressure = config.backpressure;
+        let worker = thread::Builder::new().name("botguard-eventlog".to_string()).spawn(move || {
+            let mut batch = Vec::new();
+            let mut deadline = Instant::now() + config.flush_interval;
+            loop {
+                let shutdown = match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(Message::Event(event)) => {
+                        batch.push(event);
+                        false
+                    }
+                    Ok(Message::Shutdown) | Err(RecvTimeoutError::Disconnected) => true,
+                    Err(RecvTimeoutError::Timeout) => false,
+                };
+                if batch.len() >= config.batch_size.max(1) || Instant::now() >= deadline || shutdown {
+                    if !batch.is_empty() {
+                        let counter = match publish_retrying(publisher.as_mut(), &config, &batch) {
+                            Ok(()) => &worker_counters.exported,
+                            Err(_) => &worker_counters.dropped,
+                        };
+                        counter.fetch_add(batch.len() as u64, Ordering::Relaxed);
+                        batch.clear();
+                    }
+                    deadline = Instant::now() + config.flush_interval;
+                }
+                if shutdown {
+                    return;
+                }
+            }
+        })?;
+        Ok(EventExporter { sender, worker: Some(worker), backpressure, counters })
+    }
+
+    /// Queues an event, returns `false` if it was dropped. Only waits with [`Backpressure::Block`].
+    pub fn export(&self, event: &BotEvent) -> bool {
+        enqueue(&self.sender, self.backpressure, &self.counters, event)
+    }
+
+    /// A hook for [`crate::BotDetector::on_detection`] queueing every event on this exporter.
+    pub fn hook(&self) -> impl FnMut(&BotEvent) + Send + 'static {
+        let (sender, backpressure, counters) = (self.sender.clone(), self.backpressure, Arc::clone(&self.counters));
+        move |event| {
+            enqueue(&sender, backpressure, &counters, event);
+        }
+    }
+
+    /// Events written to the sink so far.
+    pub fn exported(&self) -> u64 {
+        self.counters.exported.load(Ordering::Relaxed)
+    }
+
+    /// Events lost to a full queue or to failed writes.
+    pub fn dropped(&self) -> u64 {
+        self.counters.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for EventExporter {
+    fn drop(&mut self) {
+        let _ = self.sender.send(Message::Shutdown);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn enqueue(sender: &SyncSender<Message>, backpressure: Backpressure, counters: &Counters, event: &BotEvent) -> bool {
+    let sent = match backpressure {
+        Backpressure::Drop => match sender.try_send(Message::Event(event.clone())) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => false,
+        },
+        Backpressure::Block => sender.send(Message::Event(event.clone())).is_ok(),
+    };
+    if !sent {
+        counters.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+    sent
+}
+
+/// Receives the batches of an [`EventExporter`] on its thread, for sinks of the application like
+/// a message queue, see [`EventExporter::spawn_publisher`].
+pub trait EventPublisher: Send {
+    /// Delivers the whole batch. A failed batch is retried as configured, then dropped.
+    fn publish(&mut self, batch: &[BotEvent]) -> io::Result<()>;
+}
+
+/// The publishers of the [`EventSink`]s.
+enum Writer {
+    File(File),
+    Otlp { endpoint: String, service_name: String, timeout: Duration },
+}
+
+impl Writer {
+    fn open(sink: &EventSink, config: &EventLogConfig) -> io::Result<Self> {
+        match sink {
+            EventSink::JsonLines(path) => OpenOptions::new().create(true).append(true).open(path).map(Writer::File),
+            EventSink::Otlp { endpoint, service_name } => {
+                http::HttpUrl::parse(endpoint)?;
+                Ok(Writer::Otlp { endpoint: endpoint.clone(), service_name: service_name.clone(), timeout: config.timeout })
+            }
+        }
+    }
+}
+
+impl EventPublisher for Writer {
+    fn publish(&mut self, batch: &[BotEvent]) -> io::Result<()> {
+        match self {
+            Writer::File(file) => {
+                // one write per batch, so lines of concurrent writers do not interleave
+                let lines = batch.iter().map(|event| event.to_json() + "\n").collect::<String>();
+                file.write_all(lines.as_bytes())?;
+                file.flush()
+            }
+            Writer::Otlp { endpoint, service_name, timeout } => post(endpoint, "application/json", otlp_logs(service_name, batch), *timeout),
+        }
+    }
+}
+
+fn publish_retrying(publisher: &mut dyn EventPublisher, config: &EventLogConfig, batch: &[BotEvent]) -> io::Result<()> {
+    let mut backoff = config.retry_backoff;
+    let mut result = publisher.publish(batch);
+    for _ in 0..config.max_retries {
+        if result.is_ok() {
+            break;
+        }
+        thread::sleep(backoff);
+        backoff *= 2;
+        result = publisher.publish(batch);
+    }
+    result
+}
+
+/// Posts a payload, statuses other than `2xx` are errors.
+fn post(url: &str, content_type: &str, payload: String, timeout: Duration) -> io::Result<()> {
+    match http::post(url, content_type, payload.as_bytes(), timeout)? {
+        200..=299 => Ok(()),
+        status => Err(io::Error::other(format!("{} returned status {}", url, status))),
+    }
+}
+
+/// Fails unless the topic name is one Kafka accepts.
+#[cfg(any(feature = "kafka", feature = "kafka-rest"))]
+fn check_topic(topic: &str) -> io::Result<()> {
+    let valid = !topic.is_empty() && topic.len() <= 249 && topic.bytes().all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b));
+    if !valid {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid Kafka topic: {}", topic)));
+    }
+    Ok(())
+}
+
+/// Produces events to a Kafka topic with librdkafka, one record per event with the client
+/// address as key, so the events of a client keep their order within a partition.
+///
+/// The values are the [`BotEvent::to_json`] objects. A batch is published once all of its
+/// records are acknowledged; if one is not, the batch is retried as a whole, so records may be
+/// delivered twice but are not lost before the retries run out.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use BotGuardLib::eventlog::{EventExporter, EventLogConfig, KafkaPublisher};
+/// use BotGuardLib::BotDetector;
+///
+/// let kafka = KafkaPublisher::new("kafka-1.internal:9092,kafka-2.internal:9092", "bot-verdicts").unwrap();
+/// let config = EventLogConfig { batch_size: 1000, flush_interval: Duration::from_millis(200), ..EventLogConfig::default() };
+/// let exporter = EventExporter::spawn_publisher(kafka, config).unwrap();
+/// let mut detector = BotDetector::default();
+/// detector.on_detection(exporter.hook());
+/// ```
+#[cfg(feature = "kafka")]
+pub struct KafkaPublisher {
+    producer: rdkafka::producer::BaseProducer<Deliveries>,
+    topic: String,
+    timeout: Duration,
+}
+
+/// Counts the records of the current batch the brokers did not acknowledge, late reports of
+/// earlier batches are ignored.
+#[cfg(feature = "kafka")]
+#[derive(Default)]
+struct Deliveries {
+    batch: std::sync::atomic::AtomicUsize,
+    failed: AtomicU64,
+}
+
+#[cfg(feature = "kafka")]
+impl rdkafka::ClientContext for Deliveries {}
+
+#[cfg(feature = "kafka")]
+impl rdkafka::producer::ProducerContext for Deliveries {
+    type DeliveryOpaque = usize;
+
+    fn delivery(&self, result: &rdkafka::producer::DeliveryResult<'_>, batch: usize) {
+        if result.is_err() && batch == self.batch.load(Ordering::Relaxed) {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaPublisher {
+    /// Produces to the comma-separated `host:port` bootstrap brokers. Fails if the topic name is
+    /// not one Kafka accepts.
+    pub fn new(brokers: &str, topic: &str) -> io::Result<Self> {
+        let mut config = rdkafka::ClientConfig::new();
+        config.set("bootstrap.servers", brokers);
+        KafkaPublisher::from_config(config, topic)
+    }
+
+    /// Produces with librdkafka settings of the application, e.g. for TLS or SASL.
+    pub fn from_config(mut config: rdkafka::ClientConfig, topic: &str) -> io::Result<Self> {
+        check_topic(topic)?;
+        if config.get("message.timeout.ms").is_none() {
+            config.set("message.timeout.ms", "10000");
+        }
+        let producer = config.create_with_context(Deliveries::default()).map_err(io::Error::other)?;
+        Ok(KafkaPublisher { producer, topic: topic.to_string(), timeout: Duration::from_secs(10) })
+    }
+
+    /// Time a batch may take to be acknowledged, 10 seconds unless set.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl std::fmt::Debug for KafkaPublisher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaPublisher").field("topic", &self.topic).field("timeout", &self.timeout).finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl EventPublisher for KafkaPublisher {
+    fn publish(&mut self, batch: &[BotEvent]) -> io::Result<()> {
+        use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+        use rdkafka::producer::{BaseRecord, Producer, PurgeConfig};
+
+        let deadline = Instant::now() + self.timeout;
+        let deliveries = self.producer.context();
+        let generation = deliveries.batch.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+        deliveries.failed.store(0, Ordering::Relaxed);
+        for event in batch {
+            let key = event.ip.map(|ip| ip.to_string());
+            let payload = event.to_json();
+            loop {
+                let mut record = BaseRecord::<str, str, usize>::with_opaque_to(&self.topic, generation).payload(&payload);
+                record.key = key.as_deref();
+                match self.producer.send(record) {
+                    Ok(()) => break,
+                    // wait for acknowledgements to make room in librdkafka's queue
+                    Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) if Instant::now() < deadline => {
+                        self.producer.poll(Duration::from_millis(100));
+                    }
+                    Err((e, _)) => return Err(io::Error::other(e)),
+                }
+            }
+        }
+        if let Err(e) = self.producer.flush(deadline.saturating_duration_since(Instant::now())) {
+            // drop what is left of the batch, a retry produces all of it again
+            self.producer.purge(PurgeConfig::default().queue().inflight());
+            return Err(io::Error::new(io::ErrorKind::TimedOut, format!("Kafka did not acknowledge the records in time: {}", e)));
+        }
+        match self.producer.context().failed.load(Ordering::Relaxed) {
+            0 => Ok(()),
+            lost => Err(io::Error::other(format!("{} records were not acknowledged by Kafka", lost))),
+        }
+    }
+}
+
+/// Publishes events to a Kafka topic through a Kafka REST Proxy, keyed and encoded like the
+/// records of `KafkaPublisher`, for deployments without librdkafka.
+///
+/// ```no_run
+/// use BotGuardLib::eventlog::{EventExporter, EventLogConfig, KafkaRestPublisher};
+///
+/// let kafka = KafkaRestPublisher::new("http://kafka-rest.internal:8082", "bot-verdicts").unwrap();
+/// let exporter = EventExporter::spawn_publisher(kafka, EventLogConfig::default()).unwrap();
+/// ```
+#[cfg(feature = "kafka-rest")]
+#[derive(Debug, Clone)]
+pub struct KafkaRestPublisher {
+    url: String,
+    timeout: Duration,
+}
+
+#[cfg(feature = "kafka-rest")]
+impl KafkaRestPublisher {
+    /// Fails if the proxy is not a valid `http://` URL or the topic name is not one Kafka accepts.
+    pub fn new(rest_proxy: &str, topic: &str) -> io::Result<Self> {
+        check_topic(topic)?;
+        let url = format!("{}/topics/{}", rest_proxy.trim_end_matches('/'), topic);
+        http::HttpUrl::parse(&url)?;
+        Ok(KafkaRestPublisher { url, timeout: Duration::from_secs(10) })
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[cfg(feature = "kafka-rest")]
+impl EventPublisher for KafkaRestPublisher {
+    fn publish(&mut self, batch: &[BotEvent]) -> io::Result<()> {
+        let mut out = String::from("{\"records\":[");
+        for (i, event) in batch.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"key\":");
+            json::push_opt_str(&mut out, event.ip.map(|ip| ip.to_string()).as_deref());
+            out.push_str(",\"value\":");
+            out.push_str(&event.to_json());
+            out.push('}');
+        }
+        out.push_str("]}");
+        post(&self.url, "application/vnd.kafka.json.v2+json", out, self.timeout)
+    }
+}
+
+/// An OTLP `ExportLogsServiceRequest` in its JSON encoding, one `WARN` record per event.
+fn otlp_logs(service_name: &str, batch: &[BotEvent]) -> String {
+    let mut out = String::from("{\"resourceLogs\":[{\"resource\":{\"attributes\":[");
+    push_attribute(&mut out, "service.name", service_name);
+    out.push_str("]},\"scopeLogs\":[{\"scope\":{\"name\":\"botguard\"},\"logRecords\":[");
+    for (i, event) in batch.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let nanos = event.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        out.push_str(&format!("{{\"timeUnixNano\":\"{}\",\"severityNumber\":13,\"severityText\":\"WARN\",\"body\":{{\"stringValue\":\"bot detected\"}},\"attributes\":[", nanos));
+        push_attribute(&mut out, "user_agent.original", &event.user_agent);
+        let optional = [("client.address", event.ip.map(|ip| ip.to_string())), ("botguard.pattern", event.matched_pattern.clone()), ("botguard.category", event.category.clone())];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                out.push(',');
+                push_attribute(&mut out, key, &value);
+            }
+        }
+        out.push_str("]}");
+    }
+    out.push_str("]}]}]}");
+    out
+}
+
+fn push_attribute(out: &mut String, key: &str, value: &str) {
+    out.push_str("{\"key\":");
+    json::push_str(out, key);
+    out.push_str(",\"value\":{\"stringValue\":");
+    json::push_str(out, value);
+    out.push_str("}}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn event(user_agent: &str) -> BotEvent {
+        BotEvent { user_agent: user_agent.to_string(), ip: Some("203.0.113.7".parse().unwrap()), matched_pattern: Some("^curl/".to_string()), category: None, timestamp: UNIX_EPOCH + Duration::from_millis(1500) }
+    }
+
+    /// Accepts one POST to `path`, answers `200` and returns the request head and body.
+    fn collector(path: &str) -> (String, JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}{}", listener.local_addr().unwrap(), path);
+        let collector = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut raw = Vec::new();
+            let mut buf = [0; 4096];
+            let request = loop {
+                let n = stream.read(&mut buf).unwrap();
+                raw.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&raw).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head.lines().find_map(|l| l.strip_prefix("Content-Length: ")).map_or(0, |l| l.parse::<usize>().unwrap());
+                    if body.len() >= length {
+                        break (head.to_string(), body.to_string());
+                    }
+                }
+            };
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            request
+        });
+        (url, collector)
+    }
+
+    #[test]
+    fn appends_json_lines_in_batches() {
+        let path = std::env::temp_dir().join(format!("botguard-eventlog-{}.jsonl", std::process::id()));
+        let config = EventLogConfig { batch_size: 2, ..EventLogConfig::default() };
+        let exporter = EventExporter::spawn(&EventSink::json_lines(&path), config).unwrap();
+        for user_agent in ["a", "b", "c"] {
+            assert!(exporter.export(&event(user_agent)));
+        }
+        drop(exporter);
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(lines.lines().count(), 3);
+        assert_eq!(lines.lines().next().unwrap(), event("a").to_json());
+        std::fs::remove_file(&path).unwrap();
+        let missing = EventSink::json_lines(std::env::temp_dir().join("missing-dir/events.jsonl"));
+        assert!(EventExporter::spawn(&missing, EventLogConfig::default()).is_err());
+    }
+
+    #[test]
+    fn posts_otlp_log_records() {
+        let (endpoint, collector) = collector("/v1/logs");
+        let exporter = EventExporter::spawn(&EventSink::otlp(&endpoint), EventLogConfig::default()).unwrap();
+        exporter.export(&event("curl/8.0"));
+        drop(exporter);
+
+        let request = json::parse(&collector.join().unwrap().1).unwrap();
+        let resource = &request.get("resourceLogs").and_then(json::Value::as_array).unwrap()[0];
+        let records = resource.get("scopeLogs").and_then(json::Value::as_array).unwrap()[0].get("logRecords").and_then(json::Value::as_array).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].get("timeUnixNano").and_then(json::Value::as_str), Some("1500000000"));
+        let attributes = records[0].get("attributes").and_then(json::Value::as_array).unwrap();
+        assert_eq!(attributes.len(), 3);
+        assert_eq!(attributes[0].get("value").and_then(|value| value.get("stringValue")).and_then(json::Value::as_str), Some("curl/8.0"));
+        assert!(EventExporter::spawn(&EventSink::otlp("grpc://collector:4317"), EventLogConfig::default()).is_err());
+    }
+
+    #[test]
+    fn drops_events_beyond_the_queue() {
+        let config = EventLogConfig { queue_capacity: 1, max_retries: 0, timeout: Duration::from_millis(50), ..EventLogConfig::default() };
+        let exporter = EventExporter::spawn(&EventSink::otlp("http://127.0.0.1:1/v1/logs"), config).unwrap();
+        let queued = (0..1000).filter(|_| exporter.export(&event("curl/8.0"))).count();
+        assert!(queued < 1000);
+        let counters = Arc::clone(&exporter.counters);
+        drop(exporter);
+        // the queued events are dropped by the failed POSTs
+        assert_eq!((counters.exported.load(Ordering::Relaxed), counters.dropped.load(Ordering::Relaxed)), (0, 1000));
+    }
+
+    #[cfg(feature = "kafka")]
+    #[test]
+    fn reports_records_kafka_did_not_acknowledge() {
+        assert!(KafkaPublisher::new("127.0.0.1:9092", "bad topic").is_err());
+        // nothing listens on the port, the records time out unacknowledged
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut kafka = KafkaPublisher::new(&format!("127.0.0.1:{}", port), "bot-verdicts").unwrap().timeout(Duration::from_millis(300));
+        let error = kafka.publish(&[event("curl/8.0")]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut, "{}", error);
+        kafka.publish(&[]).unwrap();
+    }
+
+    #[cfg(feature = "kafka-rest")]
+    #[test]
+    fn publishes_records_to_kafka() {
+        let (proxy, collector) = collector("");
+        let kafka = KafkaRestPublisher::new(&format!("{}/", proxy), "bot-verdicts").unwrap();
+        let exporter = EventExporter::spawn_publisher(kafka, EventLogConfig::default()).unwrap();
+        exporter.export(&event("curl/8.0"));
+        exporter.export(&BotEvent { ip: None, ..event("wget/1.21") });
+        drop(exporter);
+
+        let (head, body) = collector.join().unwrap();
+        assert!(head.starts_with("POST /topics/bot-verdicts ") && head.contains("application/vnd.kafka.json.v2+json"), "{}", head);
+        let records = json::parse(&body).unwrap();
+        let records = records.get("records").and_then(json::Value::as_array).unwrap();
+        assert_eq!(records[0].get("key").and_then(json::Value::as_str), Some("203.0.113.7"));
+        assert_eq!((records[1].get("key"), records.len()), (Some(&json::Value::Null), 2));
+        assert!(KafkaRestPublisher::new(&proxy, "bad topic").is_err());
+    }
+}
@@ -0,0 +1,319 @@
+// Detection events, the hooks receiving them and a webhook notifier forwarding them to a SIEM.
+
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{http, json};
+
+/// A user-agent that was detected as a bot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BotEvent {
+    /// The user-agent as received, before lowercasing.
+    pub user_agent: String,
+    /// Client address, when the check was made with [`crate::BotDetector::check_bot_from`].
+    pub ip: Option<IpAddr>,
+    /// The pattern responsible for the match.
+    pub matched_pattern: Option<String>,
+    pub category: Option<String>,
+    pub timestamp: SystemTime,
+}
+
+impl BotEvent {
+    /// Serializes the event as a JSON object, the timestamp is in unix milliseconds.
+    ///
+    /// ```
+    /// use BotGuardLib::events::BotEvent;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let event = BotEvent {
+    ///     user_agent: "curl/8.0".to_string(),
+    ///     ip: Some("203.0.113.7".parse().unwrap()),
+    ///     matched_pattern: Some("^curl/".to_string()),
+    ///     category: None,
+    ///     timestamp: UNIX_EPOCH + Duration::from_millis(1500),
+    /// };
+    /// assert_eq!(
+    ///     event.to_json(),
+    ///     r#"{"user_agent":"curl/8.0","ip":"203.0.113.7","matched_pattern":"^curl/","category":null,"timestamp":1500}"#
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"user_agent\":");
+        json::push_str(&mut out, &self.user_agent);
+        out.push_str(",\"ip\":");
+        json::push_opt_str(&mut out, self.ip.map(|ip| ip.to_string()).as_deref());
+        out.push_str(",\"matched_pattern\":");
+        json::push_opt_str(&mut out, self.matched_pattern.as_deref());
+        out.push_str(",\"category\":");
+        json::push_opt_str(&mut out, self.category.as_deref());
+        let millis = self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        out.push_str(&format!(",\"timestamp\":{}}}", millis));
+        out
+    }
+}
+
+type HookFn = Box<dyn FnMut(&BotEvent) + Send>;
+
+/// A callback registered with [`crate::BotDetector::on_detection`], shared by clones of the detector.
+#[derive(Clone)]
+pub(crate) struct DetectionHook(Arc<Mutex<HookFn>>);
+
+impl DetectionHook {
+    pub(crate) fn new<F: FnMut(&BotEvent) + Send + 'static>(hook: F) -> Self {
+        DetectionHook(Arc::new(Mutex::new(Box::new(hook))))
+    }
+
+    pub(crate) fn call(&self, event: &BotEvent) {
+        // a hook that panicked once is still called, the event itself cannot be inconsistent
+        let mut hook = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        hook(event)
+    }
+}
+
+impl fmt::Debug for DetectionHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DetectionHook")
+    }
+}
+
+/// Delivery settings for [`WebhookNotifier`].
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// `http://` or `https://` endpoint receiving a JSON array of events per POST.
+    pub url: String,
+    /// Maximum number of events per POST.
+    pub batch_size: usize,
+    /// A partial batch is sent once it is this old.
+    pub flush_interval: Duration,
+    /// Number of retries after a failed POST before the batch is dropped.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further retry.
+    pub retry_backoff: Duration,
+    /// Connect, read and write timeout of a single POST.
+    pub timeout: Duration,
+    /// Events queued beyond this are dropped instead of blocking the detector.
+    pub queue_capacity: usize,
+}
+
+impl WebhookConfig {
+    pub fn new(url: &str) -> Self {
+        WebhookConfig {
+            url: url.to_string(),
+            batch_size: 100,
+            flush_interval: Duration::from_secs(5),
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(10),
+            queue_capacity: 10_000,
+        }
+    }
+}
+
+enum Message {
+    Event(BotEvent),
+    Shutdown,
+}
+
+/// Forwards detection events to a webhook from a background thread, with batching and retry.
+///
+/// Queued events are flushed when the notifier is dropped.
+///
+/// ```no_run
+/// use BotGuardLib::BotDetector;
+/// use BotGuardLib::events::{WebhookConfig, WebhookNotifier};
+///
+/// let notifier = WebhookNotifier::spawn(WebhookConfig::new("http://siem.internal:8080/bots")).unwrap();
+/// let mut detector = BotDetector::default();
+/// detector.on_detection(notifier.hook());
+/// ```
+#[derive(Debug)]
+pub struct WebhookNotifier {
+    sender: SyncSender<Message>,
+    worker: Option<JoinHandle<()>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl WebhookNotifier {
+    /// Starts the delivery thread, fails if the URL is not a valid `http://` or `https://` URL.
+    pub fn spawn(config: WebhookConfig) -> io::Result<Self> {
+        http::HttpUrl::parse(&config.url)?;
+        let (sender, receiver) = mpsc::sync_channel(config.queue_capacity.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let worker_dropped = Arc::clone(&dropped);
+        let worker = thread::Builder::new()
+            .name("botguard-webhook".to_string())
+            .spawn(move || {
+                let mut batch = Vec::new();
+                let mut deadline = Instant::now() + config.flush_interval;
+                loop {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    let shutdown = match receiver.recv_timeout(timeout) {
+                        Ok(Message::Event(event)) => {
+                            batch.push(event);
+                            false
+                        }
+                        Ok(Message::Shutdown) | Err(RecvTimeoutError::Disconnected) => true,
+                        Err(RecvTimeoutError::Timeout) => false,
+                    };
+                    if batch.len() >= config.batch_size.max(1) || Instant::now() >= deadline || shutdown {
+                        if !batch.is_empty() && !deliver(&config, &batch) {
+                            worker_dropped.fetch_add(batch.len() as u64, Ordering::Relaxed);
+                        }
+                        batch.clear();
+                        deadline = Instant::now() + config.flush_interval;
+                    }
+                    if shutdown {
+                        return;
+                    }
+                }
+            })?;
+        Ok(WebhookNotifier { sender, worker: Some(worker), dropped })
+    }
+
+    /// Queues an event without blocking, returns `false` if it was dropped because the queue is full.
+    pub fn notify(&self, event: &BotEvent) -> bool {
+        enqueue(&self.sender, &self.dropped, event)
+    }
+
+    /// A hook for [`crate::BotDetector::on_detection`] queueing every event on this notifier.
+    pub fn hook(&self) -> impl FnMut(&BotEvent) + Send + 'static {
+        let sender = self.sender.clone();
+        let dropped = Arc::clone(&self.dropped);
+        move |event| {
+            enqueue(&sender, &dropped, event);
+        }
+    }
+
+    /// Number of events lost to a full queue or to failed deliveries.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for WebhookNotifier {
+    fn drop(&mut self) {
+        let _ = self.sender.send(Message::Shutdown);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn enqueue(sender: &SyncSender<Message>, dropped: &AtomicU64, event: &BotEvent) -> bool {
+    match sender.try_send(Message::Event(event.clone())) {
+        Ok(()) => true,
+        Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+            dropped.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+}
+
+fn deliver(config: &WebhookConfig, batch: &[BotEvent]) -> bool {
+    let payload = format!("[{}]", batch.iter().map(BotEvent::to_json).collect::<Vec<_>>().join(","));
+    let mut backoff = config.retry_backoff;
+    for attempt in 0..=config.max_retries {
+        if attempt > 0 {
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+        match http::post(&config.url, "application/json", payload.as_bytes(), config.timeout) {
+            Ok(status) if (200..300).contains(&status) => return true,
+            _ => continue,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Answers each request with the next status and returns the received bodies.
+    fn serve(statuses: Vec<u16>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut raw = Vec::new();
+                let mut buf = [0; 4096];
+                let body = loop {
+                    let n = stream.read(&mut buf).unwrap();
+                    raw.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&raw).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|l| l.strip_prefix("Content-Length: "))
+                            .map_or(0, |l| l.parse::<usize>().unwrap());
+                        if body.len() >= length {
+                            break body.to_string();
+                        }
+                    }
+                };
+                bodies.push(body);
+                write!(stream, "HTTP/1.1 {} X\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+            }
+            bodies
+        });
+        (url, server)
+    }
+
+    fn event(user_agent: &str) -> BotEvent {
+        BotEvent {
+            user_agent: user_agent.to_string(),
+            ip: None,
+            matched_pattern: None,
+            category: None,
+            timestamp: UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn batches_events_and_flushes_on_drop() {
+        let (url, server) = serve(vec![200, 200]);
+        let mut config = WebhookConfig::new(&url);
+        config.batch_size = 2;
+        let notifier = WebhookNotifier::spawn(config).unwrap();
+        let mut hook = notifier.hook();
+        hook(&event("a"));
+        hook(&event("b"));
+        assert!(notifier.notify(&event("c")));
+        drop(notifier);
+
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert!(bodies[0].starts_with("[{\"user_agent\":\"a\"") && bodies[0].contains("\"user_agent\":\"b\""));
+        assert!(bodies[1].starts_with("[{\"user_agent\":\"c\""));
+    }
+
+    #[test]
+    fn retries_failed_deliveries() {
+        let (url, server) = serve(vec![503, 200]);
+        let mut config = WebhookConfig::new(&url);
+        config.retry_backoff = Duration::from_millis(1);
+        let notifier = WebhookNotifier::spawn(config).unwrap();
+        notifier.notify(&event("retried"));
+        drop(notifier);
+
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0], bodies[1]);
+    }
+
+    #[test]
+    fn rejects_unsupported_urls() {
+        assert!(WebhookNotifier::spawn(WebhookConfig::new("ftp://siem.example/")).is_err());
+        assert!(WebhookNotifier::spawn(WebhookConfig::new("https://siem.example/")).is_ok());
+    }
+}
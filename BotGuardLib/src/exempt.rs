@@ -0,0 +1,236 @@
+// "Never analyze" rules for traffic that is our own: internal networks, load balancer and
+// orchestrator health checks, and internal services sending a signed token. Unlike the
+// allowlist, which is a stage of the pipeline and still goes through the route policy, an
+// exempt request is recognized before any stage runs and allowed without a policy decision.
+//
+// Exempt requests are not rate limited and marked as not to be logged unless configured
+// otherwise, so a probe every second neither fills the access log nor a rate limit bucket.
+//
+// Tokens are `<service>.<expires unix seconds>.<HMAC-SHA-512 prefix>` in the `INTERNAL_HEADER`,
+// issued with the shared secret by the calling service or a deploy script. Nothing is stored.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use regex::{RegexSet, RegexSetBuilder};
+
+use crate::ip::{IpNet, IpRangeSet};
+use crate::request::RequestSnapshot;
+use crate::{crypto, BotGuardError};
+
+/// Header carrying an internal token, see [`Exemptions::issue_token`].
+pub const INTERNAL_HEADER: &str = "X-BotGuard-Internal";
+
+/// User-agents of common health checkers: Kubernetes, AWS load balancers and Route 53, Google Cloud
+/// load balancers, Consul, Envoy and HAProxy.
+pub const HEALTH_CHECKS: &[&str] = &[
+    "^kube-probe/",
+    "^ELB-HealthChecker/",
+    "^Amazon-Route53-Health-Check-Service",
+    "^GoogleHC/",
+    "^Consul Health Check",
+    "^Envoy/HC",
+    "^HAProxy",
+];
+
+/// Hex digits of the MAC in tokens.
+const MAC_DIGITS: usize = 32;
+
+/// Why a request is exempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exemption {
+    /// The client address is in the internal network.
+    Network(IpNet),
+    /// The user-agent matches the pattern, e.g. of [`HEALTH_CHECKS`].
+    UserAgent(String),
+    /// The request carries a valid token of the service.
+    Token { service: String },
+}
+
+impl fmt::Display for Exemption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exemption::Network(net) => write!(f, "internal network {}", net),
+            Exemption::UserAgent(pattern) => write!(f, "user-agent matches {:?}", pattern),
+            Exemption::Token { service } => write!(f, "internal token of {}", service),
+        }
+    }
+}
+
+/// Requests that are never analyzed, see [`crate::request::BotGuard::exemptions`].
+///
+/// ```
+/// use std::time::Duration;
+/// use BotGuardLib::exempt::{Exemption, Exemptions, INTERNAL_HEADER};
+/// use BotGuardLib::request::RequestSnapshot;
+///
+/// let exemptions = Exemptions::new()
+///     .networks(&["10.0.0.0/8".parse().unwrap()])
+///     .health_checks()
+///     .tokens(b"a long random shared secret");
+///
+/// let probe = RequestSnapshot::new("GET", "/healthz").user_agent("kube-probe/1.29");
+/// assert_eq!(exemptions.exempts(&probe), Some(Exemption::UserAgent("^kube-probe/".to_string())));
+///
+/// let token = exemptions.issue_token("billing", Duration::from_secs(3600)).unwrap();
+/// let call = RequestSnapshot::new("POST", "/api/invoices").header(INTERNAL_HEADER, &token);
+/// assert_eq!(exemptions.exempts(&call), Some(Exemption::Token { service: "billing".to_string() }));
+/// assert_eq!(exemptions.exempts(&RequestSnapshot::new("GET", "/").user_agent("curl/8.4.0")), None);
+/// ```
+#[derive(Clone, Default)]
+pub struct Exemptions {
+    networks: IpRangeSet<()>,
+    user_agents: Vec<String>,
+    user_agent_set: Option<RegexSet>,
+    secret: Option<Vec<u8>>,
+    rate_limited: bool,
+    logged: bool,
+}
+
+impl fmt::Debug for Exemptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Exemptions")
+            .field("networks", &self.networks.len())
+            .field("user_agents", &self.user_agents)
+            .field("tokens", &self.secret.is_some())
+            .field("rate_limited", &self.rate_limited)
+            .field("logged", &self.logged)
+            .finish()
+    }
+}
+
+impl Exemptions {
+    /// Exempts nothing.
+    pub fn new() -> Self {
+        Exemptions::default()
+    }
+
+    /// Exempts clients in the networks.
+    pub fn networks(mut self, networks: &[IpNet]) -> Self {
+        self.networks.extend(networks.iter().map(|&net| (net, ())));
+        self
+    }
+
+    /// Exempts user-agents matching one of the case-insensitive regexes.
+    pub fn user_agents(mut self, patterns: &[&str]) -> Result<Self, BotGuardError> {
+        self.user_agents.extend(patterns.iter().map(|pattern| pattern.to_string()));
+        let set = RegexSetBuilder::new(&self.user_agents)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| BotGuardError::InvalidPattern { pattern: patterns.join("|"), reason: e.to_string() })?;
+        self.user_agent_set = Some(set);
+        Ok(self)
+    }
+
+    /// Exempts the [`HEALTH_CHECKS`].
+    pub fn health_checks(self) -> Self {
+        self.user_agents(HEALTH_CHECKS).expect("the health check patterns are valid")
+    }
+
+    /// Exempts requests with a valid token issued with `secret`.
+    pub fn tokens(mut self, secret: &[u8]) -> Self {
+        self.secret = Some(secret.to_vec());
+        self
+    }
+
+    /// Whether the rate limit still counts exempt requests, `false` by default.
+    pub fn rate_limited(mut self, rate_limited: bool) -> Self {
+        self.rate_limited = rate_limited;
+        self
+    }
+
+    /// Whether exempt requests are logged, `false` by default, see
+    /// [`crate::request::RequestVerdict::log`].
+    pub fn logged(mut self, logged: bool) -> Self {
+        self.logged = logged;
+        self
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        self.rate_limited
+    }
+
+    pub fn is_logged(&self) -> bool {
+        self.logged
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty() && self.user_agents.is_empty() && self.secret.is_none()
+    }
+
+    /// Why the request is exempt, `None` if it is analyzed.
+    pub fn exempts(&self, request: &RequestSnapshot) -> Option<Exemption> {
+        self.exempts_at(request, SystemTime::now())
+    }
+
+    fn exempts_at(&self, request: &RequestSnapshot, now: SystemTime) -> Option<Exemption> {
+        if let Some((net, _)) = request.client_ip.and_then(|ip: IpAddr| self.networks.get_net(ip)) {
+            return Some(Exemption::Network(*net));
+        }
+        let user_agent = request.effective_user_agent();
+        if let Some(index) = self.user_agent_set.as_ref().and_then(|set| set.matches(user_agent).into_iter().next()) {
+            return Some(Exemption::UserAgent(self.user_agents[index].clone()));
+        }
+        let token = request.header_value(INTERNAL_HEADER)?;
+        self.verify_at(token, now).map(|service| Exemption::Token { service })
+    }
+
+    /// A token for `service` valid for `valid_for`, `None` without a secret. The service name
+    /// may not contain dots.
+    pub fn issue_token(&self, service: &str, valid_for: Duration) -> Option<String> {
+        self.issue_token_at(service, SystemTime::now() + valid_for)
+    }
+
+    fn issue_token_at(&self, service: &str, expires: SystemTime) -> Option<String> {
+        let secret = self.secret.as_deref().filter(|_| !service.is_empty() && !service.contains('.'))?;
+        let expires = expires.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        Some(format!("{}.{}.{}", service, expires, mac(secret, service, expires)))
+    }
+
+    /// The service of a valid and unexpired token.
+    fn verify_at(&self, token: &str, now: SystemTime) -> Option<String> {
+        let secret = self.secret.as_deref()?;
+        let mut parts = token.trim().splitn(3, '.');
+        let (service, expires, sent) = (parts.next()?, parts.next()?.parse::<u64>().ok()?, parts.next()?);
+        let valid = crypto::mac_eq(sent.as_bytes(), mac(secret, service, expires).as_bytes());
+        let unexpired = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() < expires;
+        (valid && unexpired).then(|| service.to_string())
+    }
+}
+
+fn mac(secret: &[u8], service: &str, expires: u64) -> String {
+    let message = format!("internal\n{}\n{}", service, expires);
+    crypto::hex(&crypto::hmac_sha512(secret, message.as_bytes()))[..MAC_DIGITS].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_internal_tokens() {
+        let exemptions = Exemptions::new().tokens(b"secret");
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let token = exemptions.issue_token_at("billing", now + Duration::from_secs(60)).unwrap();
+        assert_eq!(exemptions.verify_at(&token, now), Some("billing".to_string()));
+        assert_eq!(exemptions.verify_at(&token, now + Duration::from_secs(60)), None);
+        assert_eq!(Exemptions::new().tokens(b"other").verify_at(&token, now), None);
+        assert_eq!(exemptions.verify_at(&token.replacen("billing", "search", 1), now), None);
+        assert_eq!(exemptions.verify_at("billing.x.y", now), None);
+        assert_eq!(exemptions.issue_token_at("a.b", now), None);
+        assert_eq!(Exemptions::new().issue_token_at("billing", now), None);
+    }
+
+    #[test]
+    fn recognizes_networks_and_health_checks() {
+        let exemptions = Exemptions::new().networks(&["10.0.0.0/8".parse().unwrap()]).health_checks();
+        let internal = RequestSnapshot::new("GET", "/").user_agent("curl/8.4.0").client_ip("10.1.2.3".parse().unwrap());
+        assert_eq!(exemptions.exempts(&internal), Some(Exemption::Network("10.0.0.0/8".parse().unwrap())));
+        let elb = RequestSnapshot::new("GET", "/").header("User-Agent", "ELB-HealthChecker/2.0");
+        assert_eq!(exemptions.exempts(&elb).map(|exemption| exemption.to_string()).as_deref(), Some("user-agent matches \"^ELB-HealthChecker/\""));
+        assert_eq!(exemptions.exempts(&RequestSnapshot::new("GET", "/").header(INTERNAL_HEADER, "a.1.b")), None);
+        assert!(Exemptions::new().user_agents(&["("]).is_err());
+        assert!(Exemptions::new().is_empty());
+    }
+}
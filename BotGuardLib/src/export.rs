@@ -0,0 +1,217 @@
+// Rendering of the active pattern set for layers that do not run the crate, like nginx or HAProxy
+// in front of the application, or tools keeping the curated list in JSON or YAML.
+//
+// Only enabled groups are exported, patterns are sorted within a group so exports of the same
+// patterns compare equal.
+
+use std::fmt::Write as _;
+
+use crate::{json, BotDetector};
+
+/// Output format of [`BotDetector::export`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// The entry format of [`BotDetector::new`], with `[group]` headers and weights.
+    Regex,
+    /// An object with the version, the number of patterns and the groups with weighted patterns.
+    Json,
+    /// The same document as [`ExportFormat::Json`] in YAML.
+    Yaml,
+    /// An nginx `map` setting `$bot_category` to the group of the matching pattern.
+    NginxMap,
+    /// nginx `if` blocks answering 403 to matching user-agents, one per group.
+    NginxIf,
+    /// HAProxy `acl` lines named `botguard_<group>`.
+    HaproxyAcl,
+}
+
+struct ExportGroup<'a> {
+    name: &'a str,
+    /// Sorted patterns with their weights.
+    patterns: Vec<(&'a str, f32)>,
+}
+
+pub(crate) fn export(detector: &BotDetector, format: ExportFormat) -> String {
+    let groups = detector
+        .groups
+        .iter()
+        .filter(|group| group.is_enabled())
+        .map(|group| {
+            let mut patterns = group
+                .patterns()
+                .iter()
+                .map(|pattern| (pattern.as_str(), group.weights().get(pattern).copied().unwrap_or(1.0)))
+                .collect::<Vec<_>>();
+            patterns.sort_by(|a, b| a.0.cmp(b.0));
+            ExportGroup { name: group.name(), patterns }
+        })
+        .collect::<Vec<_>>();
+    match format {
+        ExportFormat::Regex => regex_entries(&groups),
+        ExportFormat::Json => json_document(detector, &groups),
+        ExportFormat::Yaml => yaml_document(detector, &groups),
+        ExportFormat::NginxMap | ExportFormat::NginxIf | ExportFormat::HaproxyAcl => {
+            // these layers cannot add up scores, so only patterns making a bot by themselves are kept
+            let bot = detector.options.thresholds.bot;
+            let groups = groups
+                .into_iter()
+                .map(|group| ExportGroup { patterns: group.patterns.into_iter().filter(|(_, weight)| *weight >= bot).collect(), ..group })
+                .filter(|group| !group.patterns.is_empty())
+                .collect::<Vec<_>>();
+            let case_insensitive = !detector.options.case_sensitive;
+            match format {
+                ExportFormat::NginxMap => nginx_map(&groups, case_insensitive),
+                ExportFormat::NginxIf => nginx_if(&groups, case_insensitive),
+                _ => haproxy_acl(&groups, case_insensitive),
+            }
+        }
+    }
+}
+
+fn regex_entries(groups: &[ExportGroup<'_>]) -> String {
+    let mut out = String::new();
+    for group in groups {
+        let _ = writeln!(out, "[{}]", group.name);
+        for (pattern, weight) in &group.patterns {
+            if *weight < 1.0 {
+                let _ = write!(out, "{:?} ", weight);
+            }
+            let _ = writeln!(out, "{}", pattern);
+        }
+    }
+    out
+}
+
+fn json_document(detector: &BotDetector, groups: &[ExportGroup<'_>]) -> String {
+    let mut out = String::from("{\"version\":");
+    json::push_str(&mut out, &detector.current_version().version);
+    let _ = write!(out, ",\"case_sensitive\":{},\"pattern_count\":{},\"groups\":[", detector.options.case_sensitive, count(groups));
+    for (i, group) in groups.iter().enumerate() {
+        out.push_str(if i == 0 { "{\"name\":" } else { ",{\"name\":" });
+        json::push_str(&mut out, group.name);
+        out.push_str(",\"patterns\":[");
+        for (j, (pattern, weight)) in group.patterns.iter().enumerate() {
+            out.push_str(if j == 0 { "{\"pattern\":" } else { ",{\"pattern\":" });
+            json::push_str(&mut out, pattern);
+            let _ = write!(out, ",\"weight\":{}}}", weight);
+        }
+        out.push_str("]}");
+    }
+    out.push_str("]}");
+    out
+}
+
+fn yaml_document(detector: &BotDetector, groups: &[ExportGroup<'_>]) -> String {
+    // JSON strings are valid double-quoted YAML scalars
+    let quoted = |value: &str| {
+        let mut out = String::new();
+        json::push_str(&mut out, value);
+        out
+    };
+    let mut out = format!(
+        "version: {}\ncase_sensitive: {}\npattern_count: {}\ngroups:\n",
+        quoted(&detector.current_version().version),
+        detector.options.case_sensitive,
+        count(groups)
+    );
+    for group in groups {
+        let _ = writeln!(out, "  - name: {}\n    patterns:", quoted(group.name));
+        for (pattern, weight) in &group.patterns {
+            let _ = writeln!(out, "      - pattern: {}\n        weight: {}", quoted(pattern), weight);
+        }
+    }
+    out
+}
+
+fn nginx_map(groups: &[ExportGroup<'_>], case_insensitive: bool) -> String {
+    let operator = if case_insensitive { "~*" } else { "~" };
+    let mut out = String::from("map $http_user_agent $bot_category {\n    default \"\";\n");
+    for group in groups {
+        for (pattern, _) in &group.patterns {
+            let _ = writeln!(out, "    \"{}{}\" \"{}\";", operator, nginx_quote(pattern), nginx_quote(group.name));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn nginx_if(groups: &[ExportGroup<'_>], case_insensitive: bool) -> String {
+    let operator = if case_insensitive { "~*" } else { "~" };
+    let mut out = String::new();
+    for group in groups {
+        let alternation = group.patterns.iter().map(|(pattern, _)| format!("(?:{})", pattern)).collect::<Vec<_>>().join("|");
+        let _ = writeln!(out, "# {}\nif ($http_user_agent {} \"{}\") {{\n    return 403;\n}}", group.name, operator, nginx_quote(&alternation));
+    }
+    out
+}
+
+fn haproxy_acl(groups: &[ExportGroup<'_>], case_insensitive: bool) -> String {
+    let flag = if case_insensitive { " -i" } else { "" };
+    let mut out = String::new();
+    for group in groups {
+        let name = group.name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect::<String>();
+        for (pattern, _) in &group.patterns {
+            // HAProxy splits arguments at unescaped whitespace
+            let pattern = pattern.replace('\\', "\\\\").replace(' ', "\\ ");
+            let _ = writeln!(out, "acl botguard_{} req.hdr(user-agent) -m reg{} {}", name, flag, pattern);
+        }
+    }
+    out
+}
+
+fn nginx_quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn count(groups: &[ExportGroup<'_>]) -> usize {
+    groups.iter().map(|group| group.patterns.len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> BotDetector {
+        let mut detector = BotDetector::new("[crawlers]\ngooglebot\n0.6 python-requests/\n[tools]\n^curl/\nfoo \"bar\\d\n[off]\nnever");
+        detector.disable_group("off");
+        detector
+    }
+
+    #[test]
+    fn exports_documents() {
+        let detector = detector();
+        let entries = detector.export(ExportFormat::Regex);
+        assert_eq!(entries, "[crawlers]\ngooglebot\n0.6 python-requests/\n[tools]\n^curl/\nfoo \"bar\\d\n");
+        assert_eq!(BotDetector::new(&entries).export(ExportFormat::Regex), entries);
+
+        let json = detector.export(ExportFormat::Json);
+        let value = json::parse(&json).unwrap();
+        assert_eq!(value.get("pattern_count").and_then(json::Value::as_f64), Some(4.0));
+        assert!(json.contains(r#"{"name":"crawlers","patterns":[{"pattern":"googlebot","weight":1},{"pattern":"python-requests/","weight":0.6}]}"#), "{}", json);
+
+        let yaml = detector.export(ExportFormat::Yaml);
+        assert!(yaml.starts_with("version: \""), "{}", yaml);
+        assert!(yaml.contains("\ngroups:\n  - name: \"crawlers\"\n    patterns:\n      - pattern: \"googlebot\"\n        weight: 1\n"), "{}", yaml);
+        assert!(yaml.contains("      - pattern: \"foo \\\"bar\\\\d\"\n"), "{}", yaml);
+    }
+
+    #[test]
+    fn exports_proxy_snippets() {
+        let detector = detector();
+        assert_eq!(
+            detector.export(ExportFormat::NginxMap),
+            "map $http_user_agent $bot_category {\n    default \"\";\n    \"~*googlebot\" \"crawlers\";\n    \"~*^curl/\" \"tools\";\n    \"~*foo \\\"bar\\\\d\" \"tools\";\n}\n"
+        );
+        assert_eq!(
+            detector.export(ExportFormat::NginxIf),
+            "# crawlers\nif ($http_user_agent ~* \"(?:googlebot)\") {\n    return 403;\n}\n# tools\nif ($http_user_agent ~* \"(?:^curl/)|(?:foo \\\"bar\\\\d)\") {\n    return 403;\n}\n"
+        );
+        assert_eq!(
+            detector.export(ExportFormat::HaproxyAcl),
+            "acl botguard_crawlers req.hdr(user-agent) -m reg -i googlebot\nacl botguard_tools req.hdr(user-agent) -m reg -i ^curl/\nacl botguard_tools req.hdr(user-agent) -m reg -i foo\\ \"bar\\\\d\n"
+        );
+        let sensitive = BotDetector::builder().patterns("[a-b]\nBot").case_sensitive(true).build().unwrap();
+        assert_eq!(sensitive.export(ExportFormat::HaproxyAcl), "acl botguard_a_b req.hdr(user-agent) -m reg Bot\n");
+        assert!(sensitive.export(ExportFormat::NginxMap).contains("\"~Bot\" \"a-b\""));
+    }
+}
@@ -0,0 +1,206 @@
+// In-process fast path for gateways checking every request: most traffic repeats a small set of
+// user-agents, so the verdict of a normalized user-agent is remembered in a sharded map and the
+// regex engine only runs on misses.
+//
+// Entries are keyed by the normalized user-agent, hashed with `ahash` under random keys per
+// cache, which picks the shard as well. A hit costs a hash, a short lock and a comparison with
+// the stored user-agent, so two user-agents with the same hash never share a verdict. Every
+// shard keeps two generations of entries, the recent one moves to the old one when full and the
+// old one is dropped, which keeps the user-agents seen over and over without tracking their
+// order. Entries are dropped as soon as the detector's patterns change, see
+// `BotDetector::generation`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+use ahash::RandomState;
+
+use crate::{BotDetector, Verdict, INLINE_USER_AGENT};
+
+#[derive(Default)]
+struct Shard {
+    generation: u64,
+    recent: HashMap<Box<str>, Verdict, RandomState>,
+    older: HashMap<Box<str>, Verdict, RandomState>,
+}
+
+impl Shard {
+    /// Forgets the entries of older patterns, returns `false` if the shard already holds those
+    /// of newer ones.
+    fn sync(&mut self, generation: u64) -> bool {
+        if self.generation < generation {
+            self.recent.clear();
+            self.older.clear();
+            self.generation = generation;
+        }
+        self.generation == generation
+    }
+}
+
+/// See [`ShardedVerdictCache::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// Verdicts of [`BotDetector::check`] by user-agent, shared by every request handler.
+///
+/// Detection hooks and the decisions recorded with the `tracing` feature only run on misses. Verdicts
+/// depending on the client address, like those of [`BotDetector::check_bot_from`], are not
+/// cached.
+///
+/// ```
+/// use BotGuardLib::fastpath::ShardedVerdictCache;
+/// use BotGuardLib::{BotDetector, Verdict};
+///
+/// let mut BotDetector = BotDetector::new("^curl/");
+/// let cache = ShardedVerdictCache::new(10_000);
+/// assert_eq!(cache.check(&BotDetector, "curl/8.4.0"), Verdict::Bot);
+/// assert_eq!(cache.check(&BotDetector, "curl/8.4.0"), Verdict::Bot);
+/// assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+///
+/// BotDetector.remove(&["^curl/"]);
+/// assert_eq!(cache.check(&BotDetector, "curl/8.4.0"), Verdict::Human);
+/// ```
+pub struct ShardedVerdictCache {
+    shards: Box<[Mutex<Shard>]>,
+    /// Entries per generation of a shard.
+    per_shard: usize,
+    /// Picks the shard of a user-agent.
+    hasher: RandomState,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl fmt::Debug for ShardedVerdictCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedVerdictCache").field("shards", &self.shards.len()).field("stats", &self.stats()).finish_non_exhaustive()
+    }
+}
+
+impl ShardedVerdictCache {
+    /// Holds about `capacity` user-agents, in four shards per core.
+    pub fn new(capacity: usize) -> Self {
+        let cores = thread::available_parallelism().map_or(4, |cores| cores.get());
+        ShardedVerdictCache::with_shards(capacity, cores * 4)
+    }
+
+    /// Spreads `capacity` over `shards` locks, rounded up to a power of two.
+    pub fn with_shards(capacity: usize, shards: usize) -> Self {
+        let shards = shards.max(1).next_power_of_two();
+        ShardedVerdictCache {
+            shards: (0..shards).map(|_| Mutex::default()).collect(),
+            per_shard: (capacity / shards / 2).max(1),
+            hasher: RandomState::new(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the verdict cached for the user-agent, or checks it with the detector and caches
+    /// the verdict.
+    pub fn check(&self, detector: &BotDetector, user_agent: &str) -> Verdict {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        let key = detector.verdict_key(user_agent, &mut buffer);
+        let key: &str = &key;
+        let generation = detector.generation();
+        {
+            let mut shard = self.shard(key);
+            if !shard.sync(generation) {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return detector.check(user_agent);
+            }
+            if let Some(&verdict) = shard.recent.get(key) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return verdict;
+            }
+            if let Some((key, verdict)) = shard.older.remove_entry(key) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                self.insert(&mut shard, key, verdict);
+                return verdict;
+            }
+        }
+        // the patterns run without holding the lock
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let verdict = detector.check(user_agent);
+        let mut shard = self.shard(key);
+        if shard.sync(generation) {
+            self.insert(&mut shard, key.into(), verdict);
+        }
+        verdict
+    }
+
+    fn insert(&self, shard: &mut Shard, key: Box<str>, verdict: Verdict) {
+        if shard.recent.len() >= self.per_shard {
+            shard.older = std::mem::take(&mut shard.recent);
+        }
+        shard.recent.insert(key, verdict);
+    }
+
+    fn shard(&self, key: &str) -> MutexGuard<'_, Shard> {
+        let index = self.hasher.hash_one(key) as usize & (self.shards.len() - 1);
+        self.shards[index].lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.shards.iter().map(|shard| {
+            let shard = shard.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            shard.recent.len() + shard.older.len()
+        });
+        CacheStats { hits: self.hits.load(Ordering::Relaxed), misses: self.misses.load(Ordering::Relaxed), entries: entries.sum() }
+    }
+
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            let mut shard = shard.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            shard.recent.clear();
+            shard.older.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_normalized_user_agents() {
+        let detector = BotDetector::new("^curl/");
+        let cache = ShardedVerdictCache::with_shards(100, 3);
+        assert_eq!(cache.shards.len(), 4);
+        assert_eq!(cache.check(&detector, "curl/8.0"), Verdict::Bot);
+        assert_eq!(cache.check(&detector, "CURL/8.0"), Verdict::Bot);
+        assert_eq!(cache.check(&detector, "Mozilla/5.0 (X11; Linux x86_64) Firefox/120.0"), Verdict::Human);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2, entries: 2 });
+
+        // a clone shares the entries until it is edited
+        let mut edited = detector.clone();
+        assert_eq!(cache.check(&edited, "curl/8.0"), Verdict::Bot);
+        edited.disable_group("custom");
+        assert_eq!(cache.check(&edited, "curl/8.0"), Verdict::Human);
+        assert_eq!(cache.stats().misses, 3);
+        cache.clear();
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn keeps_repeated_user_agents_within_capacity() {
+        let detector = BotDetector::new("^curl/");
+        let cache = ShardedVerdictCache::with_shards(8, 1);
+        for i in 0..100 {
+            cache.check(&detector, "curl/8.0");
+            cache.check(&detector, &format!("client/{}", i));
+        }
+        let stats = cache.stats();
+        assert!(stats.entries <= 8, "{:?}", stats);
+        assert_eq!((stats.hits, stats.misses), (99, 101));
+        // entries are matched by the user-agent itself, not by its hash
+        let shard = cache.shards[0].lock().unwrap();
+        assert_eq!(shard.recent.get("curl/8.0").or(shard.older.get("curl/8.0")), Some(&Verdict::Bot));
+    }
+}
@@ -0,0 +1,156 @@
+// Feed readers and podcast apps. They poll the same `/feed.xml` scrapers hammer, but every poll
+// stands for readers or listeners, so a feed policy wants to tell them apart rather than rate
+// limit the endpoint as a whole.
+//
+// Hosted readers fetch a feed once for all their users and say how many there are, e.g.
+// `Feedly/1.0 (+http://www.feedly.com/fetcher.html; 523 subscribers; like FeedFetcher-Google)`.
+// The count is taken from the user-agent as sent, so it is as trustworthy as the user-agent
+// itself: fine for statistics, not for granting anything.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use regex::{Regex, RegexSet, RegexSetBuilder};
+
+/// Group of the bundled patterns holding feed readers and podcast apps.
+pub const FEED_READERS_GROUP: &str = "feed-readers";
+
+/// A recognized feed reader or podcast app, see [`crate::BotDetector::check_feed_reader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeedClient {
+    Feedly,
+    Inoreader,
+    NewsBlur,
+    Feedbin,
+    Miniflux,
+    FreshRss,
+    TinyTinyRss,
+    TheOldReader,
+    NetNewsWire,
+    ApplePodcasts,
+    Overcast,
+    PocketCasts,
+    Castro,
+    AntennaPod,
+    PodcastAddict,
+    PlayerFm,
+    Castbox,
+}
+
+/// Recognized clients with their user-agent pattern, matched case-insensitively.
+const CLIENTS: &[(FeedClient, &str)] = &[
+    (FeedClient::Feedly, r"feedly"),
+    (FeedClient::Inoreader, r"inoreader"),
+    (FeedClient::NewsBlur, r"newsblur"),
+    (FeedClient::Feedbin, r"feedbin"),
+    (FeedClient::Miniflux, r"miniflux"),
+    (FeedClient::FreshRss, r"freshrss"),
+    (FeedClient::TinyTinyRss, r"tiny tiny rss"),
+    (FeedClient::TheOldReader, r"theoldreader"),
+    (FeedClient::NetNewsWire, r"netnewswire"),
+    (FeedClient::ApplePodcasts, r"^podcasts/"),
+    (FeedClient::Overcast, r"overcast/"),
+    (FeedClient::PocketCasts, r"pocketcasts"),
+    (FeedClient::Castro, r"^castro"),
+    (FeedClient::AntennaPod, r"antennapod"),
+    (FeedClient::PodcastAddict, r"podcastaddict"),
+    (FeedClient::PlayerFm, r"player fm"),
+    (FeedClient::Castbox, r"castbox"),
+];
+
+impl FeedClient {
+    /// The product name as it appears in user-agents.
+    pub fn name(self) -> &'static str {
+        match self {
+            FeedClient::Feedly => "Feedly",
+            FeedClient::Inoreader => "Inoreader",
+            FeedClient::NewsBlur => "NewsBlur",
+            FeedClient::Feedbin => "Feedbin",
+            FeedClient::Miniflux => "Miniflux",
+            FeedClient::FreshRss => "FreshRSS",
+            FeedClient::TinyTinyRss => "Tiny Tiny RSS",
+            FeedClient::TheOldReader => "TheOldReader",
+            FeedClient::NetNewsWire => "NetNewsWire",
+            FeedClient::ApplePodcasts => "Podcasts",
+            FeedClient::Overcast => "Overcast",
+            FeedClient::PocketCasts => "PocketCasts",
+            FeedClient::Castro => "Castro",
+            FeedClient::AntennaPod => "AntennaPod",
+            FeedClient::PodcastAddict => "PodcastAddict",
+            FeedClient::PlayerFm => "Player FM",
+            FeedClient::Castbox => "CastBox",
+        }
+    }
+
+    /// Whether the client is a podcast app rather than a feed reader.
+    pub fn is_podcast_app(self) -> bool {
+        matches!(
+            self,
+            FeedClient::ApplePodcasts | FeedClient::Overcast | FeedClient::PocketCasts | FeedClient::Castro | FeedClient::AntennaPod | FeedClient::PodcastAddict | FeedClient::PlayerFm | FeedClient::Castbox
+        )
+    }
+}
+
+impl fmt::Display for FeedClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A feed reader request, see [`crate::BotDetector::check_feed_reader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeedReader {
+    pub client: FeedClient,
+    /// Subscribers the reader fetches the feed for, if the user-agent says.
+    pub subscribers: Option<u32>,
+}
+
+/// The first recognized client in the user-agent, with the subscriber count it reports.
+pub(crate) fn feed_reader(user_agent: &str) -> Option<FeedReader> {
+    static SET: OnceLock<RegexSet> = OnceLock::new();
+    let set = SET.get_or_init(|| {
+        RegexSetBuilder::new(CLIENTS.iter().map(|(_, pattern)| pattern))
+            .case_insensitive(true)
+            .build()
+            .expect("the feed client patterns are valid")
+    });
+    let client = set.matches(user_agent).iter().next().map(|i| CLIENTS[i].0)?;
+    Some(FeedReader { client, subscribers: subscribers(user_agent) })
+}
+
+/// The `<n> subscribers` count of a user-agent, as hosted readers and aggregators send it.
+pub fn subscribers(user_agent: &str) -> Option<u32> {
+    static COUNT: OnceLock<Regex> = OnceLock::new();
+    let count = COUNT.get_or_init(|| Regex::new(r"(?i)\b(\d{1,9}) subscribers?\b").expect("the subscriber pattern is valid"));
+    count.captures(user_agent)?[1].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BotDetector;
+
+    #[test]
+    fn recognizes_readers_and_their_subscribers() {
+        let feedly = feed_reader("Feedly/1.0 (+http://www.feedly.com/fetcher.html; 523 subscribers; like FeedFetcher-Google)").unwrap();
+        assert_eq!(feedly, FeedReader { client: FeedClient::Feedly, subscribers: Some(523) });
+        assert_eq!(feed_reader("NewsBlur Feed Fetcher - 1 subscriber - https://www.newsblur.com/site/42/example").unwrap().subscribers, Some(1));
+        assert_eq!(feed_reader("Miniflux/2.1.0 (https://miniflux.app)"), Some(FeedReader { client: FeedClient::Miniflux, subscribers: None }));
+        let podcasts = feed_reader("Podcasts/1555.2.1 CFNetwork/1399 Darwin/22.1.0").unwrap();
+        assert!(podcasts.client.is_podcast_app() && !FeedClient::Feedly.is_podcast_app());
+        assert_eq!(feed_reader("Mozilla/5.0 (compatible; overcastle)"), None);
+        assert_eq!(subscribers("Inoreader/1.0 (+http://www.inoreader.com/feed-fetcher; 99999999999 subscribers)"), None);
+        assert_eq!(FeedClient::TinyTinyRss.to_string(), "Tiny Tiny RSS");
+    }
+
+    #[test]
+    fn every_client_is_in_the_bundled_group() {
+        let detector = BotDetector::default();
+        for (client, _) in CLIENTS {
+            let user_agent = format!("{}/1.0", client.name());
+            assert_eq!(detector.classify(&user_agent), Some(FEED_READERS_GROUP), "{}", user_agent);
+        }
+        // before the search engines, whose feedfetcher-google Feedly claims to be like
+        assert_eq!(detector.classify("Feedly/1.0 (+http://www.feedly.com/fetcher.html; 523 subscribers; like FeedFetcher-Google)"), Some(FEED_READERS_GROUP));
+    }
+}
@@ -0,0 +1,217 @@
+// C ABI over the detector for nginx modules and C/C++ services, declared in `include/botguard.h`.
+// The shared library is built with `cargo rustc --release --lib --crate-type cdylib --features ffi`.
+//
+// Ownership: `botguard_new` returns a handle owned by the caller until it is passed to
+// `botguard_free`, strings are only borrowed for the duration of a call. Threads: a handle may be
+// shared by any number of threads, checks run concurrently and appends wait for them, only
+// `botguard_free` must not race with other calls on the same handle. Panics never cross the
+// boundary, they are reported like any other failure.
+
+use std::ffi::{c_char, c_int, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::RwLock;
+
+use crate::{BotDetector, Verdict, CUSTOM_GROUP};
+
+/// Opaque detector handle.
+pub struct BotGuard(RwLock<BotDetector>);
+
+/// # Safety
+///
+/// `ptr` must be null or a NUL-terminated string.
+unsafe fn borrow_str<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok()
+}
+
+/// Compiles newline-delimited patterns in the format of [`BotDetector::new`], or the bundled
+/// defaults if `patterns` is null. Returns null if a pattern is invalid.
+///
+/// # Safety
+///
+/// `patterns` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn botguard_new(patterns: *const c_char) -> *mut BotGuard {
+    let detector = if patterns.is_null() {
+        catch_unwind(BotDetector::default).ok()
+    } else {
+        borrow_str(patterns).and_then(|patterns| BotDetector::builder().patterns(patterns).build().ok())
+    };
+    match detector {
+        Some(detector) => Box::into_raw(Box::new(BotGuard(RwLock::new(detector)))),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Returns 0 for humans, 1 for suspicious clients and 2 for bots, see [`BotDetector::check`],
+/// and -1 if an argument is null or the user-agent is not UTF-8.
+///
+/// # Safety
+///
+/// `guard` must be null or a live handle, `user_agent` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn botguard_check(guard: *const BotGuard, user_agent: *const c_char) -> c_int {
+    let (Some(guard), Some(user_agent)) = (guard.as_ref(), borrow_str(user_agent)) else { return -1 };
+    let verdict = catch_unwind(AssertUnwindSafe(|| {
+        guard.0.read().unwrap_or_else(|poisoned| poisoned.into_inner()).check(user_agent)
+    }));
+    verdict.map_or(-1, verdict_code)
+}
+
+fn verdict_code(verdict: Verdict) -> c_int {
+    match verdict {
+        Verdict::Human => 0,
+        Verdict::Suspicious => 1,
+        Verdict::Bot => 2,
+    }
+}
+
+/// Checks `count` user-agents under a single lock, writing the [`botguard_check`] codes to `out`.
+/// Returns 0, or -1 if `guard`, `user_agents` or `out` is null.
+///
+/// # Safety
+///
+/// `guard` must be null or a live handle, `user_agents` must point to `count` entries that are
+/// null or NUL-terminated strings, `out` to `count` writable ints.
+#[no_mangle]
+pub unsafe extern "C" fn botguard_check_batch(
+    guard: *const BotGuard,
+    user_agents: *const *const c_char,
+    count: usize,
+    out: *mut c_int,
+) -> c_int {
+    let Some(guard) = guard.as_ref() else { return -1 };
+    if user_agents.is_null() || out.is_null() {
+        return -1;
+    }
+    let checked = catch_unwind(AssertUnwindSafe(|| {
+        let detector = guard.0.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for i in 0..count {
+            *out.add(i) = borrow_str(*user_agents.add(i)).map_or(-1, |user_agent| verdict_code(detector.check(user_agent)));
+        }
+    }));
+    checked.map_or(-1, |()| 0)
+}
+
+/// Writes the group of the matching pattern to `out` as a NUL-terminated string, see
+/// [`BotDetector::classify`], cut to fit `out_len` bytes.
+///
+/// Returns the full length of the name without the NUL, 0 if the user-agent is no bot and -1 if
+/// an argument is null or the user-agent is not UTF-8.
+///
+/// # Safety
+///
+/// `guard` must be null or a live handle, `user_agent` null or a NUL-terminated string and `out`
+/// must point to `out_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn botguard_classify(guard: *const BotGuard, user_agent: *const c_char, out: *mut c_char, out_len: usize) -> c_int {
+    let (Some(guard), Some(user_agent)) = (guard.as_ref(), borrow_str(user_agent)) else { return -1 };
+    if out.is_null() || out_len == 0 {
+        return -1;
+    }
+    let classified = catch_unwind(AssertUnwindSafe(|| {
+        let detector = guard.0.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let group = detector.classify(user_agent).unwrap_or("");
+        let written = group.len().min(out_len - 1);
+        std::ptr::copy_nonoverlapping(group.as_ptr().cast(), out, written);
+        *out.add(written) = 0;
+        group.len() as c_int
+    }));
+    classified.unwrap_or(-1)
+}
+
+/// Appends newline-delimited patterns to the [`CUSTOM_GROUP`]. Returns 0 on success and -1 if an
+/// argument is null or a pattern is invalid, no pattern is added then.
+///
+/// # Safety
+///
+/// `guard` must be null or a live handle, `patterns` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn botguard_append(guard: *const BotGuard, patterns: *const c_char) -> c_int {
+    let (Some(guard), Some(patterns)) = (guard.as_ref(), borrow_str(patterns)) else { return -1 };
+    let patterns = patterns.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<&str>>();
+    let appended = catch_unwind(AssertUnwindSafe(|| {
+        guard
+            .0
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .append_to_group(CUSTOM_GROUP, &patterns)
+    }));
+    match appended {
+        Ok(Ok(())) => 0,
+        _ => -1,
+    }
+}
+
+/// Releases a handle, null is ignored.
+///
+/// # Safety
+///
+/// `guard` must be null or a live handle no other thread is using, it is dangling afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn botguard_free(guard: *mut BotGuard) {
+    if !guard.is_null() {
+        drop(Box::from_raw(guard));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn handles_check_and_append() {
+        let c = |s: &str| CString::new(s).unwrap();
+        unsafe {
+            let guard = botguard_new(c("googlebot").as_ptr());
+            assert!(!guard.is_null());
+            assert_eq!(botguard_check(guard, c("Googlebot/2.1").as_ptr()), 2);
+            assert_eq!(botguard_check(guard, c("Mozilla/5.0").as_ptr()), 0);
+            assert_eq!(botguard_check(guard, std::ptr::null()), -1);
+            assert_eq!(botguard_check(std::ptr::null(), c("x").as_ptr()), -1);
+            assert_eq!(botguard_check(guard, [0xffu8, 0].as_ptr().cast()), -1);
+
+            let user_agents = [c("Googlebot/2.1"), c("Mozilla/5.0")];
+            let mut pointers = user_agents.iter().map(|ua| ua.as_ptr()).collect::<Vec<_>>();
+            pointers.push(std::ptr::null());
+            let mut codes = [9; 3];
+            assert_eq!(botguard_check_batch(guard, pointers.as_ptr(), 3, codes.as_mut_ptr()), 0);
+            assert_eq!(codes, [2, 0, -1]);
+
+            let mut name = [1 as c_char; 4];
+            assert_eq!(botguard_classify(guard, c("Googlebot").as_ptr(), name.as_mut_ptr(), 4), 6);
+            assert_eq!(CStr::from_ptr(name.as_ptr()).to_str(), Ok("cus"));
+            assert_eq!(botguard_classify(guard, c("Mozilla").as_ptr(), name.as_mut_ptr(), 4), 0);
+            assert_eq!(name[0], 0);
+
+            assert_eq!(botguard_append(guard, c("^mozilla/\n\nexamplebot").as_ptr()), 0);
+            assert_eq!(botguard_check(guard, c("Mozilla/5.0").as_ptr()), 2);
+            assert_eq!(botguard_append(guard, c("newbot\n(open").as_ptr()), -1);
+            assert_eq!(botguard_check(guard, c("newbot").as_ptr()), 0);
+            botguard_free(guard);
+
+            assert!(botguard_new(c("(open").as_ptr()).is_null());
+            let defaults = botguard_new(std::ptr::null());
+            assert_eq!(botguard_check(defaults, c("").as_ptr()), 0);
+            botguard_free(defaults);
+            botguard_free(std::ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn header_declares_every_export() {
+        let header = include_str!("../include/botguard.h");
+        let exports = include_str!("ffi.rs")
+            .lines()
+            .filter_map(|line| line.strip_prefix("pub unsafe extern \"C\" fn "))
+            .map(|rest| &rest[..rest.find('(').unwrap()])
+            .collect::<Vec<&str>>();
+        assert_eq!(exports.len(), 6);
+        for export in exports {
+            assert!(header.contains(&format!("{}(", export)), "{} is missing from botguard.h", export);
+        }
+    }
+}
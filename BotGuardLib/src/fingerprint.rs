@@ -0,0 +1,382 @@
+// Browser fingerprints collected client-side: a script served by `collector_script` reads what
+// the page can see of its environment and posts it as JSON to an endpoint of the application,
+// which parses it with `FingerprintPayload::from_json` and attaches it to the request snapshots
+// of that client.
+//
+// Headless browsers and emulators give themselves away in details a user-agent cannot hide: the
+// `navigator.webdriver` flag, the 800x600 screen of headless Chrome, desktop browsers without
+// their built-in PDF plugins or a canvas rendered by a software rasterizer. The payload is
+// written by the client and a careful bot sends what a browser would, so the signals only ever
+// add to the score of a request, a clean payload vouches for nothing.
+
+use std::fmt;
+
+use crate::json;
+use crate::request::RequestSnapshot;
+
+/// Longest canvas hash accepted, a SHA-512 in hex.
+const MAX_CANVAS_HASH: usize = 128;
+
+/// Largest screen side accepted, in CSS pixels.
+const MAX_SCREEN_SIDE: f64 = 16384.0;
+
+/// Screens of headless browsers left at their defaults.
+const HEADLESS_SCREENS: &[(u32, u32)] = &[(800, 600), (0, 0)];
+
+/// The screen reported by `window.screen`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Screen {
+    pub width: u32,
+    pub height: u32,
+    pub color_depth: u32,
+}
+
+/// What the collector script reports about the browser.
+///
+/// ```
+/// use BotGuardLib::fingerprint::FingerprintPayload;
+///
+/// let payload = FingerprintPayload::from_json(
+///     r#"{"canvasHash":"9f2c41d07be8a3e5","timezone":"Europe/Berlin","screen":{"width":1920,"height":1080,"colorDepth":24},"webdriver":false,"plugins":5}"#,
+/// )
+/// .unwrap();
+/// assert_eq!((payload.screen.unwrap().width, payload.plugins), (1920, 5));
+/// assert!(FingerprintPayload::from_json(r#"{"plugins":-1}"#).is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FingerprintPayload {
+    /// Hash of an image drawn on a canvas, in hex.
+    pub canvas_hash: Option<String>,
+    /// IANA time zone from `Intl.DateTimeFormat`, like `Europe/Berlin`.
+    pub timezone: Option<String>,
+    pub screen: Option<Screen>,
+    /// `navigator.webdriver`, set by browsers under automation.
+    pub webdriver: bool,
+    /// Length of `navigator.plugins`.
+    pub plugins: u32,
+    /// `navigator.userAgent`, compared with the header by [`FingerprintAnalyzer::evaluate_request`].
+    pub user_agent: Option<String>,
+    /// Random value of the collector script, redeemed with a [`crate::state::NonceStore`] so a
+    /// captured payload cannot be sent again.
+    pub nonce: Option<String>,
+}
+
+impl FingerprintPayload {
+    /// Reads and validates a payload posted by the collector script, unknown fields are ignored.
+    pub fn from_json(payload: &str) -> Result<Self, String> {
+        let value = json::parse(payload)?;
+        if !matches!(value, json::Value::Object(_)) {
+            return Err("payload is not an object".to_string());
+        }
+        let text = |key: &str| match value.get(key) {
+            None | Some(json::Value::Null) => Ok(None),
+            Some(field) => field.as_str().map(|text| Some(text.to_string())).ok_or(format!("{} is not a string", key)),
+        };
+        let canvas_hash = text("canvasHash")?;
+        if let Some(hash) = &canvas_hash {
+            if hash.is_empty() || hash.len() > MAX_CANVAS_HASH || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(format!("canvasHash {:?} is not a hex digest", hash));
+            }
+        }
+        let nonce = text("nonce")?;
+        if nonce.as_ref().is_some_and(|nonce| nonce.is_empty() || nonce.len() > 128) {
+            return Err("nonce is empty or longer than 128 bytes".to_string());
+        }
+        let timezone = text("timezone")?;
+        if let Some(timezone) = &timezone {
+            if timezone.is_empty() || timezone.len() > 64 || !timezone.bytes().all(|b| b.is_ascii_alphanumeric() || b"/_+-".contains(&b)) {
+                return Err(format!("timezone {:?} is not a time zone name", timezone));
+            }
+        }
+        let screen = match value.get("screen") {
+            None | Some(json::Value::Null) => None,
+            Some(screen) => Some(Screen {
+                width: integer(screen.get("width"), "screen.width", MAX_SCREEN_SIDE)?.ok_or("screen without width")?,
+                height: integer(screen.get("height"), "screen.height", MAX_SCREEN_SIDE)?.ok_or("screen without height")?,
+                color_depth: integer(screen.get("colorDepth"), "screen.colorDepth", 64.0)?.unwrap_or(24),
+            }),
+        };
+        let webdriver = match value.get("webdriver") {
+            None | Some(json::Value::Null) => false,
+            Some(flag) => flag.as_bool().ok_or("webdriver is not a boolean")?,
+        };
+        Ok(FingerprintPayload {
+            canvas_hash: canvas_hash.map(|hash| hash.to_ascii_lowercase()),
+            timezone,
+            screen,
+            webdriver,
+            plugins: integer(value.get("plugins"), "plugins", 1000.0)?.unwrap_or(0),
+            user_agent: text("userAgent")?,
+            nonce,
+        })
+    }
+}
+
+fn integer(value: Option<&json::Value>, name: &str, max: f64) -> Result<Option<u32>, String> {
+    match value {
+        None | Some(json::Value::Null) => Ok(None),
+        Some(value) => match value.as_f64() {
+            Some(n) if n.fract() == 0.0 && (0.0..=max).contains(&n) => Ok(Some(n as u32)),
+            _ => Err(format!("{} is not an integer between 0 and {}", name, max)),
+        },
+    }
+}
+
+/// JavaScript posting the [`FingerprintPayload`] of the browser to `endpoint` as JSON, to be
+/// embedded in a `<script>` of the pages.
+pub fn collector_script(endpoint: &str) -> String {
+    let mut url = String::new();
+    json::push_str(&mut url, endpoint);
+    format!(
+        "(async()=>{{let h=null;try{{const c=document.createElement(\"canvas\"),x=c.getContext(\"2d\");\
+         x.textBaseline=\"top\";x.font=\"14px Arial\";x.fillStyle=\"#f60\";x.fillRect(2,2,120,20);\
+         x.fillStyle=\"#069\";x.fillText(\"BotGuard \\u{{1f916}}\",4,4);\
+         h=Array.from(new Uint8Array(await crypto.subtle.digest(\"SHA-256\",new TextEncoder().encode(c.toDataURL()))),\
+         b=>b.toString(16).padStart(2,\"0\")).join(\"\")}}catch(e){{}}\
+         const s=window.screen;navigator.sendBeacon({},JSON.stringify({{canvasHash:h,\
+         timezone:Intl.DateTimeFormat().resolvedOptions().timeZone||null,\
+         screen:{{width:s.width,height:s.height,colorDepth:s.colorDepth}},webdriver:!!navigator.webdriver,\
+         plugins:navigator.plugins?navigator.plugins.length:0,userAgent:navigator.userAgent,\
+         nonce:crypto.randomUUID()}}))}})()",
+        url
+    )
+}
+
+/// Something about a browser fingerprint no regular browser shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FingerprintSignal {
+    /// `navigator.webdriver` is set.
+    Webdriver,
+    /// The screen has the size a headless browser defaults to.
+    HeadlessScreen { width: u32, height: u32 },
+    /// A desktop browser without plugins, every current one ships a PDF viewer.
+    NoPlugins,
+    /// The canvas renders like a known headless or emulated environment.
+    KnownCanvas { client: String },
+    /// The canvas could not be drawn or hashed.
+    MissingCanvas,
+    /// The time zone is UTC, the default of servers and containers.
+    UtcTimezone,
+    /// The script saw another user-agent than the request header carries.
+    UserAgentMismatch,
+}
+
+impl fmt::Display for FingerprintSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FingerprintSignal::Webdriver => f.write_str("browser under automation"),
+            FingerprintSignal::HeadlessScreen { width, height } => write!(f, "{}x{} screen of a headless browser", width, height),
+            FingerprintSignal::NoPlugins => f.write_str("desktop browser without plugins"),
+            FingerprintSignal::KnownCanvas { client } => write!(f, "canvas rendered like {}", client),
+            FingerprintSignal::MissingCanvas => f.write_str("no canvas fingerprint"),
+            FingerprintSignal::UtcTimezone => f.write_str("UTC time zone"),
+            FingerprintSignal::UserAgentMismatch => f.write_str("script and header user-agents differ"),
+        }
+    }
+}
+
+/// Outcome of [`FingerprintAnalyzer::evaluate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FingerprintReport {
+    pub signals: Vec<FingerprintSignal>,
+    /// Between `0.0` and `1.0`, the weights of the signals combined like independent chances.
+    pub score: f32,
+}
+
+/// Weighs the signals of fingerprint payloads.
+///
+/// ```
+/// use BotGuardLib::fingerprint::{FingerprintAnalyzer, FingerprintPayload, FingerprintSignal};
+///
+/// let analyzer = FingerprintAnalyzer::default().known_canvas("5e1d8c0a", "headless chrome (swiftshader)");
+/// let payload = FingerprintPayload::from_json(r#"{"canvasHash":"5E1D8C0A","screen":{"width":800,"height":600},"plugins":5}"#).unwrap();
+/// let report = analyzer.evaluate(&payload);
+/// assert_eq!(report.signals[0], FingerprintSignal::HeadlessScreen { width: 800, height: 600 });
+/// assert!(report.score > 0.9);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FingerprintAnalyzer {
+    webdriver: f32,
+    headless_screen: f32,
+    no_plugins: f32,
+    known_canvas: f32,
+    missing_canvas: f32,
+    utc_timezone: f32,
+    user_agent_mismatch: f32,
+    canvases: Vec<(String, String)>,
+}
+
+impl Default for FingerprintAnalyzer {
+    /// The webdriver flag alone makes a bot, a known canvas or a changed user-agent nearly so,
+    /// the other signals only add up. No canvas hashes are known.
+    fn default() -> Self {
+        FingerprintAnalyzer {
+            webdriver: 1.0,
+            headless_screen: 0.6,
+            no_plugins: 0.4,
+            known_canvas: 0.9,
+            missing_canvas: 0.3,
+            utc_timezone: 0.2,
+            user_agent_mismatch: 0.8,
+            canvases: Vec::new(),
+        }
+    }
+}
+
+impl FingerprintAnalyzer {
+    /// Recognizes the canvas hash of a headless or emulated environment.
+    pub fn known_canvas(mut self, hash: &str, client: &str) -> Self {
+        self.canvases.push((hash.to_ascii_lowercase(), client.to_string()));
+        self
+    }
+
+    pub fn webdriver_weight(mut self, weight: f32) -> Self {
+        self.webdriver = weight;
+        self
+    }
+
+    pub fn headless_screen_weight(mut self, weight: f32) -> Self {
+        self.headless_screen = weight;
+        self
+    }
+
+    pub fn no_plugins_weight(mut self, weight: f32) -> Self {
+        self.no_plugins = weight;
+        self
+    }
+
+    pub fn known_canvas_weight(mut self, weight: f32) -> Self {
+        self.known_canvas = weight;
+        self
+    }
+
+    pub fn missing_canvas_weight(mut self, weight: f32) -> Self {
+        self.missing_canvas = weight;
+        self
+    }
+
+    pub fn utc_timezone_weight(mut self, weight: f32) -> Self {
+        self.utc_timezone = weight;
+        self
+    }
+
+    pub fn user_agent_mismatch_weight(mut self, weight: f32) -> Self {
+        self.user_agent_mismatch = weight;
+        self
+    }
+
+    /// The signals of a payload, a weight of `0.0` disables a signal.
+    pub fn evaluate(&self, payload: &FingerprintPayload) -> FingerprintReport {
+        let mut signals = Vec::new();
+        if payload.webdriver {
+            signals.push(FingerprintSignal::Webdriver);
+        }
+        if let Some(screen) = payload.screen.filter(|screen| HEADLESS_SCREENS.contains(&(screen.width, screen.height))) {
+            signals.push(FingerprintSignal::HeadlessScreen { width: screen.width, height: screen.height });
+        }
+        if payload.plugins == 0 && payload.user_agent.as_deref().is_some_and(is_desktop_browser) {
+            signals.push(FingerprintSignal::NoPlugins);
+        }
+        match &payload.canvas_hash {
+            Some(hash) => {
+                if let Some((_, client)) = self.canvases.iter().find(|(known, _)| known == hash) {
+                    signals.push(FingerprintSignal::KnownCanvas { client: client.clone() });
+                }
+            }
+            None => signals.push(FingerprintSignal::MissingCanvas),
+        }
+        if payload.timezone.as_deref().is_some_and(|timezone| matches!(timezone, "UTC" | "Etc/UTC" | "Etc/GMT" | "GMT")) {
+            signals.push(FingerprintSignal::UtcTimezone);
+        }
+        self.report(signals)
+    }
+
+    /// The signals of the payload attached to a request, with [`FingerprintSignal::UserAgentMismatch`]
+    /// if the script saw another user-agent. `None` without a payload.
+    pub fn evaluate_request(&self, request: &RequestSnapshot) -> Option<FingerprintReport> {
+        let payload = request.fingerprint.as_ref()?;
+        let mut signals = self.evaluate(payload).signals;
+        if payload.user_agent.as_deref().is_some_and(|seen| seen != request.effective_user_agent()) {
+            signals.push(FingerprintSignal::UserAgentMismatch);
+        }
+        Some(self.report(signals))
+    }
+
+    pub fn weight(&self, signal: &FingerprintSignal) -> f32 {
+        match signal {
+            FingerprintSignal::Webdriver => self.webdriver,
+            FingerprintSignal::HeadlessScreen { .. } => self.headless_screen,
+            FingerprintSignal::NoPlugins => self.no_plugins,
+            FingerprintSignal::KnownCanvas { .. } => self.known_canvas,
+            FingerprintSignal::MissingCanvas => self.missing_canvas,
+            FingerprintSignal::UtcTimezone => self.utc_timezone,
+            FingerprintSignal::UserAgentMismatch => self.user_agent_mismatch,
+        }
+    }
+
+    fn report(&self, mut signals: Vec<FingerprintSignal>) -> FingerprintReport {
+        signals.retain(|signal| self.weight(signal) > 0.0);
+        let score = 1.0 - signals.iter().fold(1.0, |human, signal| human * (1.0 - self.weight(signal)));
+        FingerprintReport { signals, score }
+    }
+}
+
+/// Whether a user-agent claims a desktop browser, which always has plugins.
+fn is_desktop_browser(user_agent: &str) -> bool {
+    let ua = user_agent.to_ascii_lowercase();
+    ua.starts_with("mozilla/") && !["mobile", "android", "iphone", "ipad"].iter().any(|token| ua.contains(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_payloads() {
+        let payload = FingerprintPayload::from_json(r#"{"canvasHash":null,"screen":{"width":390,"height":844},"unknown":[1,2]}"#).unwrap();
+        assert_eq!(payload.screen, Some(Screen { width: 390, height: 844, color_depth: 24 }));
+        assert_eq!((payload.canvas_hash, payload.webdriver, payload.plugins), (None, false, 0));
+
+        for invalid in [
+            r#"{"canvasHash":"not hex"}"#,
+            r#"{"timezone":"<script>"}"#,
+            r#"{"screen":{"width":1.5,"height":2}}"#,
+            r#"{"screen":{"height":2}}"#,
+            r#"{"webdriver":"false"}"#,
+            r#"{"plugins":100000}"#,
+            "[]",
+        ] {
+            assert!(FingerprintPayload::from_json(invalid).is_err(), "{}", invalid);
+        }
+        let script = collector_script("/_bg/fp?x=\"1\"");
+        assert!(script.contains(r#"navigator.sendBeacon("/_bg/fp?x=\"1\"","#), "{}", script);
+        assert!(FingerprintPayload::from_json(r#"{"nonce":""}"#).is_err());
+    }
+
+    #[test]
+    fn flags_headless_environments() {
+        let analyzer = FingerprintAnalyzer::default();
+        let chrome = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) HeadlessChrome/122.0.0.0 Safari/537.36";
+        let headless = FingerprintPayload {
+            webdriver: true,
+            timezone: Some("Etc/UTC".to_string()),
+            screen: Some(Screen { width: 800, height: 600, color_depth: 24 }),
+            user_agent: Some(chrome.to_string()),
+            ..FingerprintPayload::default()
+        };
+        let report = analyzer.evaluate(&headless);
+        assert_eq!(report.signals.len(), 5);
+        assert_eq!(report.score, 1.0);
+
+        let phone = FingerprintPayload {
+            canvas_hash: Some("ab12".to_string()),
+            user_agent: Some("Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) Mobile/15E148".to_string()),
+            ..FingerprintPayload::default()
+        };
+        assert_eq!(analyzer.evaluate(&phone), FingerprintReport { signals: vec![], score: 0.0 });
+        let request = RequestSnapshot::new("GET", "/").user_agent("Mozilla/5.0 (Windows NT 10.0)").fingerprint(phone);
+        let report = analyzer.clone().user_agent_mismatch_weight(0.0).evaluate_request(&request).unwrap();
+        assert!(report.signals.is_empty());
+        assert_eq!(analyzer.evaluate_request(&request).unwrap().signals, vec![FingerprintSignal::UserAgentMismatch]);
+    }
+}
@@ -0,0 +1,384 @@
+// IP to ASN and country resolution from MaxMind databases (GeoLite2/GeoIP2 `.mmdb` files), enabled
+// with the `geoip` feature, and rules turning the result into findings for the scoring.
+//
+// The databases are read by the `maxminddb` crate, records are decoded into its `geoip2` types.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+use maxminddb::{geoip2, Reader};
+use serde::Deserialize;
+
+use crate::BotGuardError;
+
+/// A MaxMind DB file loaded into memory, cheap to clone.
+#[derive(Clone)]
+pub struct MaxMindDb {
+    reader: Arc<Reader<Vec<u8>>>,
+}
+
+impl fmt::Debug for MaxMindDb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MaxMindDb").field("database_type", &self.database_type()).finish_non_exhaustive()
+    }
+}
+
+impl MaxMindDb {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, BotGuardError> {
+        let data = std::fs::read(path.as_ref())
+            .map_err(|e| BotGuardError::IoError { path: path.as_ref().display().to_string(), reason: e.to_string() })?;
+        MaxMindDb::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, BotGuardError> {
+        Ok(MaxMindDb { reader: Arc::new(Reader::from_source(data).map_err(corrupt)?) })
+    }
+
+    /// The `database_type` of the metadata, e.g. `"GeoLite2-ASN"`.
+    pub fn database_type(&self) -> &str {
+        &self.reader.metadata().database_type
+    }
+
+    /// Decodes the record of the address, `None` for addresses the database does not cover,
+    /// including IPv6 addresses in an IPv4 database.
+    fn lookup<'a, T: Deserialize<'a>>(&'a self, ip: IpAddr) -> Result<Option<T>, BotGuardError> {
+        if ip.is_ipv6() && self.reader.metadata().ip_version == 4 {
+            return Ok(None);
+        }
+        self.reader.lookup(ip).and_then(|result| result.decode()).map_err(corrupt)
+    }
+}
+
+fn corrupt(error: maxminddb::MaxMindDbError) -> BotGuardError {
+    BotGuardError::InvalidDatabase { reason: format!("corrupt MaxMind database: {}", error) }
+}
+
+/// What the databases know about an address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeoInfo {
+    /// Autonomous system number, from an ASN database.
+    pub asn: Option<u32>,
+    /// Organization operating the autonomous system.
+    pub organization: Option<String>,
+    /// ISO 3166-1 alpha-2 country code, from a country or city database.
+    pub country: Option<String>,
+}
+
+/// Resolves addresses with an ASN database, a country database, or both.
+///
+/// ```no_run
+/// use BotGuardLib::geoip::{GeoIp, MaxMindDb};
+///
+/// let geoip = GeoIp::new()
+///     .with_asn_db(MaxMindDb::open("/var/lib/GeoIP/GeoLite2-ASN.mmdb").unwrap())
+///     .with_country_db(MaxMindDb::open("/var/lib/GeoIP/GeoLite2-Country.mmdb").unwrap());
+/// let info = geoip.lookup("203.0.113.7".parse().unwrap()).unwrap();
+/// println!("AS{:?} {:?} in {:?}", info.asn, info.organization, info.country);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GeoIp {
+    asn_db: Option<MaxMindDb>,
+    country_db: Option<MaxMindDb>,
+}
+
+impl GeoIp {
+    pub fn new() -> Self {
+        GeoIp::default()
+    }
+
+    pub fn with_asn_db(mut self, db: MaxMindDb) -> Self {
+        self.asn_db = Some(db);
+        self
+    }
+
+    pub fn with_country_db(mut self, db: MaxMindDb) -> Self {
+        self.country_db = Some(db);
+        self
+    }
+
+    /// Looks the address up in every configured database, unknown fields stay `None`.
+    pub fn lookup(&self, ip: IpAddr) -> Result<GeoInfo, BotGuardError> {
+        let mut info = GeoInfo::default();
+        if let Some(record) = self.asn_db.as_ref().map(|db| db.lookup::<geoip2::Asn>(ip)).transpose()?.flatten() {
+            info.asn = record.autonomous_system_number;
+            info.organization = record.autonomous_system_organization.map(str::to_string);
+        }
+        if let Some(record) = self.country_db.as_ref().map(|db| db.lookup::<geoip2::Country>(ip)).transpose()?.flatten() {
+            info.country = record.country.iso_code.or(record.registered_country.iso_code).map(str::to_string);
+        }
+        Ok(info)
+    }
+}
+
+/// A policy rule evaluated against the [`GeoInfo`] of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeoRule {
+    /// Flags traffic from these autonomous systems, e.g. hosting and cloud providers.
+    FlagAsns(HashSet<u32>),
+    /// Flags autonomous systems whose organization contains one of the words, ignoring case.
+    FlagOrganizations(Vec<String>),
+    /// Blocks a country on every path starting with the route prefix, `"/"` for the whole site.
+    BlockCountry { country: String, route_prefix: String },
+}
+
+/// A rule that applied to a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeoFinding {
+    HostingAsn { asn: u32, organization: Option<String> },
+    BlockedCountry { country: String, route_prefix: String },
+}
+
+impl GeoFinding {
+    /// Default contribution of the finding to a composite bot score, between `0.0` and `1.0`.
+    pub fn weight(&self) -> f32 {
+        match self {
+            GeoFinding::HostingAsn { .. } => 0.4,
+            GeoFinding::BlockedCountry { .. } => 1.0,
+        }
+    }
+}
+
+/// Rules evaluated in order, every matching rule adds a finding.
+///
+/// ```
+/// use BotGuardLib::geoip::{GeoFinding, GeoInfo, GeoPolicy, GeoRule};
+///
+/// let policy = GeoPolicy::new(vec![
+///     GeoRule::FlagOrganizations(vec!["hosting".to_string()]),
+///     GeoRule::BlockCountry { country: "XX".to_string(), route_prefix: "/signup".to_string() },
+/// ]);
+/// let info = GeoInfo { asn: Some(64500), organization: Some("Example Hosting GmbH".to_string()), country: Some("XX".to_string()) };
+///
+/// assert_eq!(policy.evaluate(&info, "/blog/post").len(), 1);
+/// assert!(matches!(policy.evaluate(&info, "/signup")[1], GeoFinding::BlockedCountry { .. }));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeoPolicy {
+    rules: Vec<GeoRule>,
+}
+
+impl GeoPolicy {
+    pub fn new(rules: Vec<GeoRule>) -> Self {
+        GeoPolicy { rules }
+    }
+
+    pub fn rules(&self) -> &[GeoRule] {
+        &self.rules
+    }
+
+    pub fn evaluate(&self, info: &GeoInfo, path: &str) -> Vec<GeoFinding> {
+        let mut findings = Vec::new();
+        let hosting = |findings: &mut Vec<GeoFinding>, asn: u32| {
+            if !findings.iter().any(|finding| matches!(finding, GeoFinding::HostingAsn { .. })) {
+                findings.push(GeoFinding::HostingAsn { asn, organization: info.organization.clone() });
+            }
+        };
+        for rule in &self.rules {
+            match rule {
+                GeoRule::FlagAsns(asns) => {
+                    if let Some(asn) = info.asn.filter(|asn| asns.contains(asn)) {
+                        hosting(&mut findings, asn);
+                    }
+                }
+                GeoRule::FlagOrganizations(words) => {
+                    let organization = info.organization.as_deref().unwrap_or_default().to_lowercase();
+                    if let Some(asn) = info.asn {
+                        if words.iter().any(|word| organization.contains(&word.to_lowercase())) {
+                            hosting(&mut findings, asn);
+                        }
+                    }
+                }
+                GeoRule::BlockCountry { country, route_prefix } => {
+                    let same_country = info.country.as_deref().is_some_and(|c| c.eq_ignore_ascii_case(country));
+                    if same_country && path.starts_with(route_prefix.as_str()) {
+                        findings.push(GeoFinding::BlockedCountry { country: country.clone(), route_prefix: route_prefix.clone() });
+                    }
+                }
+            }
+        }
+        findings
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Encodes the values used by the tests in the data section format.
+    pub(crate) enum Enc<'a> {
+        Str(&'a str),
+        U16(u16),
+        U32(u32),
+        U64(u64),
+        Map(Vec<(&'a str, Enc<'a>)>),
+        Array(Vec<Enc<'a>>),
+    }
+
+    pub(crate) fn encode(value: &Enc<'_>, out: &mut Vec<u8>) {
+        match value {
+            Enc::Str(s) => {
+                if s.len() < 29 {
+                    out.push(2 << 5 | s.len() as u8);
+                } else {
+                    out.extend_from_slice(&[2 << 5 | 29, (s.len() - 29) as u8]);
+                }
+                out.extend_from_slice(s.as_bytes());
+            }
+            Enc::U16(n) => {
+                out.push(5 << 5 | 2);
+                out.extend_from_slice(&n.to_be_bytes());
+            }
+            Enc::U32(n) => {
+                out.push(6 << 5 | 4);
+                out.extend_from_slice(&n.to_be_bytes());
+            }
+            Enc::U64(n) => {
+                // the extended type 9
+                out.extend_from_slice(&[8, 9 - 7]);
+                out.extend_from_slice(&n.to_be_bytes());
+            }
+            Enc::Map(entries) => {
+                out.push(7 << 5 | entries.len() as u8);
+                for (key, value) in entries {
+                    encode(&Enc::Str(key), out);
+                    encode(value, out);
+                }
+            }
+            Enc::Array(items) => {
+                // arrays are of the extended type 11
+                out.extend_from_slice(&[items.len() as u8, 11 - 7]);
+                for item in items {
+                    encode(item, out);
+                }
+            }
+        }
+    }
+
+    /// Builds an IPv4 database with 24-bit records holding one record per network.
+    pub(crate) fn build_db(database_type: &str, networks: &[(&str, Enc<'_>)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut leaves = Vec::new();
+        for (net, value) in networks {
+            let (addr, prefix) = net.split_once('/').unwrap();
+            let addr = u32::from(addr.parse::<std::net::Ipv4Addr>().unwrap());
+            leaves.push((addr, prefix.parse::<u32>().unwrap(), data.len()));
+            encode(value, &mut data);
+        }
+
+        // one node per prefix bit of every network, records start out empty
+        let mut nodes: Vec<[usize; 2]> = vec![[usize::MAX; 2]];
+        for &(addr, prefix, data_offset) in &leaves {
+            let mut node = 0;
+            for i in 0..prefix {
+                let bit = (addr >> (31 - i) & 1) as usize;
+                if i + 1 == prefix {
+                    nodes[node][bit] = usize::MAX - 1 - data_offset;
+                } else {
+                    if nodes[node][bit] == usize::MAX {
+                        nodes.push([usize::MAX; 2]);
+                        nodes[node][bit] = nodes.len() - 1;
+                    }
+                    node = nodes[node][bit];
+                }
+            }
+        }
+        let node_count = nodes.len();
+        let mut out = Vec::new();
+        for node in &nodes {
+            for &record in node {
+                let value = match record {
+                    usize::MAX => node_count,
+                    record if record > usize::MAX / 2 => node_count + 16 + (usize::MAX - 1 - record),
+                    record => record,
+                };
+                out.extend_from_slice(&(value as u32).to_be_bytes()[1..]);
+            }
+        }
+        out.extend_from_slice(&[0; 16]);
+        out.extend_from_slice(&data);
+        out.extend_from_slice(b"\xab\xcd\xefMaxMind.com");
+        encode(
+            &Enc::Map(vec![
+                ("binary_format_major_version", Enc::U16(2)),
+                ("binary_format_minor_version", Enc::U16(0)),
+                ("build_epoch", Enc::U64(1_700_000_000)),
+                ("database_type", Enc::Str(database_type)),
+                ("description", Enc::Map(Vec::new())),
+                ("ip_version", Enc::U16(4)),
+                ("languages", Enc::Array(vec![Enc::Str("en")])),
+                ("node_count", Enc::U32(node_count as u32)),
+                ("record_size", Enc::U16(24)),
+            ]),
+            &mut out,
+        );
+        out
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    pub(crate) fn test_geoip() -> GeoIp {
+        let asn = build_db(
+            "GeoLite2-ASN",
+            &[
+                ("203.0.113.0/24", Enc::Map(vec![("autonomous_system_number", Enc::U32(64500)), ("autonomous_system_organization", Enc::Str("Example Hosting"))])),
+                ("198.51.100.128/25", Enc::Map(vec![("autonomous_system_number", Enc::U32(64501))])),
+            ],
+        );
+        let country = build_db(
+            "GeoLite2-Country",
+            &[("203.0.113.0/25", Enc::Map(vec![("country", Enc::Map(vec![("iso_code", Enc::Str("DE"))]))]))],
+        );
+        GeoIp::new()
+            .with_asn_db(MaxMindDb::from_bytes(asn).unwrap())
+            .with_country_db(MaxMindDb::from_bytes(country).unwrap())
+    }
+
+    #[test]
+    fn looks_up_asn_and_country() {
+        let geoip = test_geoip();
+        assert_eq!(
+            geoip.lookup(ip("203.0.113.9")).unwrap(),
+            GeoInfo { asn: Some(64500), organization: Some("Example Hosting".to_string()), country: Some("DE".to_string()) }
+        );
+        assert_eq!(geoip.lookup(ip("203.0.113.200")).unwrap().country, None);
+        assert_eq!(geoip.lookup(ip("198.51.100.200")).unwrap().asn, Some(64501));
+        assert_eq!(geoip.lookup(ip("198.51.100.1")).unwrap(), GeoInfo::default());
+        assert_eq!(geoip.lookup(ip("2001:db8::1")).unwrap(), GeoInfo::default());
+        assert_eq!(geoip.asn_db.as_ref().unwrap().database_type(), "GeoLite2-ASN");
+    }
+
+    #[test]
+    fn rejects_corrupt_databases() {
+        assert!(matches!(MaxMindDb::from_bytes(b"not a database".to_vec()), Err(BotGuardError::InvalidDatabase { .. })));
+        let mut truncated = build_db("x", &[("10.0.0.0/8", Enc::U32(1))]);
+        truncated.drain(..20);
+        assert!(MaxMindDb::from_bytes(truncated).is_err());
+        assert!(matches!(MaxMindDb::open("/nonexistent/GeoLite2-ASN.mmdb"), Err(BotGuardError::IoError { .. })));
+    }
+
+    #[test]
+    fn evaluates_rules() {
+        let geoip = test_geoip();
+        let policy = GeoPolicy::new(vec![
+            GeoRule::FlagAsns([64501].into_iter().collect()),
+            GeoRule::FlagOrganizations(vec!["HOSTING".to_string()]),
+            GeoRule::BlockCountry { country: "de".to_string(), route_prefix: "/api/".to_string() },
+        ]);
+        let hosting = policy.evaluate(&geoip.lookup(ip("203.0.113.9")).unwrap(), "/api/orders");
+        assert_eq!(
+            hosting,
+            [
+                GeoFinding::HostingAsn { asn: 64500, organization: Some("Example Hosting".to_string()) },
+                GeoFinding::BlockedCountry { country: "de".to_string(), route_prefix: "/api/".to_string() },
+            ]
+        );
+        assert_eq!(policy.evaluate(&geoip.lookup(ip("198.51.100.200")).unwrap(), "/api/orders").len(), 1);
+        assert!(policy.evaluate(&geoip.lookup(ip("198.51.100.1")).unwrap(), "/api/orders").is_empty());
+        assert_eq!(policy.evaluate(&geoip.lookup(ip("203.0.113.9")).unwrap(), "/blog").len(), 1);
+    }
+}
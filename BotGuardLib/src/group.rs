@@ -0,0 +1,807 @@
+// A named set of patterns compiled into its own regexes, so toggling or editing one group never
+// recompiles the others. Within a group the patterns are split into shards of `SHARD_SIZE`, an
+// edit recompiles the shard it touches rather than thousands of patterns under the write lock.
+// With the `lite` feature plain-text patterns skip the regexes and are found with string search.
+//
+// Patterns keep the order they were added in, which is also the order they are joined into the
+// regexes, so the same entries always compile to the same matcher.
+
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+
+use crate::builder::DetectorOptions;
+use crate::literal::{Literal, MatchMode};
+use crate::BotGuardError;
+
+/// Compilation limits applied to every pattern and to the combined regexes of every group.
+///
+/// The regex engine never backtracks, so matching stays linear in the input, but a single entry
+/// like `\w{500}{500}` can still compile to a huge program. Entries exceeding the limits are
+/// rejected with [`BotGuardError::PatternTooLarge`] when they are loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegexLimits {
+    /// Maximum compiled size in bytes of a single pattern.
+    pub pattern_size_limit: usize,
+    /// Maximum compiled size in bytes of the combined regex of one group, checked per shard of up to
+    /// 256 patterns.
+    pub group_size_limit: usize,
+    /// Maximum cache size in bytes of the lazy DFA of one shard; a smaller cache only costs speed.
+    pub dfa_size_limit: usize,
+    /// Maximum nesting depth of a pattern.
+    pub nest_limit: u32,
+}
+
+impl Default for RegexLimits {
+    /// The defaults of the `regex` crate, with single patterns limited to 1 MiB.
+    fn default() -> Self {
+        RegexLimits {
+            pattern_size_limit: 1 << 20,
+            group_size_limit: 10 << 20,
+            dfa_size_limit: 2 << 20,
+            nest_limit: 250,
+        }
+    }
+}
+
+impl MatchMode {
+    pub(crate) fn wrap(self, index: usize, entry: &str) -> String {
+        match self {
+            MatchMode::Substring => format!("(?P<__bg{}>{})", index, entry),
+            MatchMode::Token => format!(r"(?:^|[\s;,()])(?P<__bg{}>{})(?:$|[\s;,()/])", index, entry),
+            MatchMode::Anchored => format!("^(?P<__bg{}>{})$", index, entry),
+        }
+    }
+}
+
+/// Most patterns compiled into one regex. Groups are split into shards of this size, so adding or
+/// removing a pattern only recompiles the shard it belongs to instead of the whole group.
+pub(crate) const SHARD_SIZE: usize = 256;
+
+/// Up to [`SHARD_SIZE`] patterns of a group joined into one regex.
+#[derive(Debug, Clone)]
+struct Shard {
+    /// Patterns in the order they were joined into `regex`, entry `i` is the capture group named `__bg{i}`.
+    patterns: Vec<String>,
+    regex: Regex,
+    /// The `patterns` as a set for finding every matching pattern, only compiled while the shard
+    /// holds a weighted pattern.
+    weighted: Option<RegexSet>,
+}
+
+impl Shard {
+    /// The start of the leftmost match in an already normalized user-agent and its pattern.
+    fn leftmost(&self, user_agent: &str) -> Option<(usize, &str)> {
+        let captures = self.regex.captures(user_agent)?;
+        let start = captures.get(0)?.start();
+        self.regex.capture_names().zip(captures.iter()).find_map(|(name, group)| {
+            let index = name?.strip_prefix("__bg")?.parse::<usize>().ok()?;
+            group.map(|_| (start, self.patterns[index].as_str()))
+        })
+    }
+}
+
+/// Shards being compiled in the background, with the plain-text patterns matched until then.
+#[derive(Debug, Clone)]
+struct Pending {
+    compiled: Arc<OnceLock<Result<Vec<Shard>, BotGuardError>>>,
+    literals: Vec<Literal>,
+}
+
+/// Patterns as written in the order they were added, looked up by their [`pattern_key`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Patterns {
+    order: Vec<String>,
+    /// The key of every pattern with the pattern as written.
+    keys: HashMap<String, String>,
+    case_sensitive: bool,
+}
+
+impl Patterns {
+    /// Keeps the first of duplicate patterns.
+    fn new(patterns: Vec<String>, case_sensitive: bool) -> Self {
+        let mut deduplicated = Patterns { case_sensitive, ..Patterns::default() };
+        deduplicated.extend(patterns);
+        deduplicated
+    }
+
+    pub(crate) fn contains(&self, pattern: &str) -> bool {
+        self.keys.contains_key(&*pattern_key(pattern, self.case_sensitive))
+    }
+
+    /// The pattern as written when it was added.
+    pub(crate) fn get(&self, pattern: &str) -> Option<&str> {
+        self.keys.get(&*pattern_key(pattern, self.case_sensitive)).map(String::as_str)
+    }
+
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, String> {
+        self.order.iter()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    fn extend<I: IntoIterator<Item = String>>(&mut self, patterns: I) {
+        for pattern in patterns {
+            if let Entry::Vacant(vacant) = self.keys.entry(pattern_key(&pattern, self.case_sensitive).into_owned()) {
+                vacant.insert(pattern.clone());
+                self.order.push(pattern);
+            }
+        }
+    }
+
+    /// Removes the patterns, returning those that were there as they were written.
+    fn remove(&mut self, patterns: &[String]) -> HashSet<String> {
+        let removed = patterns.iter().filter_map(|pattern| self.keys.remove(&*pattern_key(pattern, self.case_sensitive))).collect::<HashSet<String>>();
+        if !removed.is_empty() {
+            self.order.retain(|pattern| !removed.contains(pattern));
+        }
+        removed
+    }
+}
+
+impl PartialEq for Patterns {
+    fn eq(&self, other: &Self) -> bool {
+        self.order == other.order
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct PatternGroup {
+    name: String,
+    /// In priority order: among matches starting at the same position the earlier pattern wins.
+    patterns: Patterns,
+    enabled: bool,
+    match_mode: MatchMode,
+    /// Whether the regexes and literals match the case as written, see
+    /// [`crate::builder::BotDetectorBuilder::case_sensitive`]. The patterns are kept as written
+    /// either way.
+    case_sensitive: bool,
+    limits: RegexLimits,
+    /// The compiled `patterns`, none of the shards is empty. Empty while `pending`.
+    shards: Vec<Shard>,
+    /// With the `lite` feature the plain-text patterns, found with string search instead of
+    /// being compiled into `shards` and ranked after them at the same position. Always empty
+    /// without it.
+    literals: Vec<Literal>,
+    /// Set until the shards of a group built with [`PatternGroup::deferred`] are taken over.
+    pending: Option<Pending>,
+    /// Weights of the patterns scoring below `1.0`, see [`crate::BotDetector::score`].
+    weights: HashMap<String, f32>,
+}
+
+impl PartialEq for PatternGroup {
+    /// Compares the patterns, weights and settings, not how far they are compiled.
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.patterns == other.patterns
+            && self.weights == other.weights
+            && self.enabled == other.enabled
+            && self.match_mode == other.match_mode
+            && self.case_sensitive == other.case_sensitive
+    }
+}
+
+impl PatternGroup {
+    pub(crate) fn new(name: String, patterns: Vec<String>, options: &DetectorOptions) -> Result<Self, BotGuardError> {
+        let patterns = Patterns::new(patterns, options.case_sensitive);
+        let mut group = PatternGroup {
+            name,
+            patterns: Patterns { case_sensitive: options.case_sensitive, ..Patterns::default() },
+            enabled: true,
+            match_mode: options.match_mode,
+            case_sensitive: options.case_sensitive,
+            limits: options.limits,
+            shards: Vec::new(),
+            literals: Vec::new(),
+            pending: None,
+            weights: HashMap::new(),
+        };
+        for pattern in patterns.iter() {
+            group.validate(pattern)?;
+        }
+        group.literals = patterns.iter().filter_map(|pattern| group.lite_literal(pattern)).collect();
+        group.patterns = patterns;
+        group.shards = group.compile_all()?;
+        Ok(group)
+    }
+
+    /// A group whose patterns are validated and compiled on a background thread, or on the
+    /// first call needing the regexes if no thread can be spawned. Only the plain-text patterns
+    /// match until then, see [`PatternGroup::compile_now`].
+    pub(crate) fn deferred(name: String, patterns: Vec<String>, weights: Vec<(String, f32)>, options: &DetectorOptions) -> Self {
+        let mut group = PatternGroup {
+            name,
+            patterns: Patterns::new(patterns, options.case_sensitive),
+            enabled: true,
+            match_mode: options.match_mode,
+            case_sensitive: options.case_sensitive,
+            limits: options.limits,
+            shards: Vec::new(),
+            literals: Vec::new(),
+            pending: None,
+            weights: HashMap::new(),
+        };
+        group.literals = group.patterns.iter().filter_map(|pattern| group.lite_literal(pattern)).collect();
+        for (pattern, weight) in weights {
+            if let Some(pattern) = group.patterns.get(&pattern).filter(|_| weight < 1.0) {
+                group.weights.insert(pattern.to_string(), weight.max(0.0));
+            }
+        }
+        group.defer();
+        let compiled = Arc::clone(&group.pending.as_ref().expect("just deferred").compiled);
+        let (name, patterns, weights, options) = (group.name.clone(), group.patterns.order.clone(), group.weights.clone(), options.clone());
+        // without threads, e.g. on wasm32-unknown-unknown, the first call needing the regexes compiles them
+        let _ = std::thread::Builder::new()
+            .name("botguard-compile".to_string())
+            .spawn(move || compiled.get_or_init(|| PatternGroup::compile_detached(name, patterns, weights, &options)).is_ok());
+        group
+    }
+
+    /// Recompiles the patterns as [`PatternGroup::new`] does, for deferred groups.
+    fn compile_detached(
+        name: String,
+        patterns: Vec<String>,
+        weights: HashMap<String, f32>,
+        options: &DetectorOptions,
+    ) -> Result<Vec<Shard>, BotGuardError> {
+        let mut group = PatternGroup::new(name, patterns, options)?;
+        group.set_weights(weights);
+        Ok(group.shards)
+    }
+
+    /// The pattern as a literal if the `lite` feature keeps it out of the regexes.
+    fn lite_literal(&self, pattern: &str) -> Option<Literal> {
+        cfg!(feature = "lite").then(|| Literal::parse_folded(pattern, self.case_sensitive)).flatten()
+    }
+
+    /// Starts over with uncompiled shards, matching the plain-text patterns.
+    fn defer(&mut self) {
+        let literals = self.patterns.iter().filter_map(|pattern| Literal::parse_folded(pattern, self.case_sensitive)).collect();
+        self.shards.clear();
+        self.pending = Some(Pending { compiled: Arc::new(OnceLock::new()), literals });
+    }
+
+    /// Waits for the regexes of a deferred group, compiling them on this thread unless the
+    /// background thread has started. Returns the error of an invalid or oversized pattern.
+    pub(crate) fn compile_now(&self) -> Result<(), BotGuardError> {
+        self.compiled().map(drop)
+    }
+
+    /// Whether the regexes are compiled, rather than only the plain-text patterns matched.
+    pub(crate) fn is_compiled(&self) -> bool {
+        self.pending.as_ref().is_none_or(|pending| matches!(pending.compiled.get(), Some(Ok(_))))
+    }
+
+    /// The shards, waiting for them if they are compiled in the background.
+    fn compiled(&self) -> Result<&[Shard], BotGuardError> {
+        let Some(pending) = &self.pending else { return Ok(&self.shards) };
+        let compile = || {
+            let options = DetectorOptions { match_mode: self.match_mode, case_sensitive: self.case_sensitive, limits: self.limits, ..DetectorOptions::default() };
+            PatternGroup::compile_detached(self.name.clone(), self.patterns.order.clone(), self.weights.clone(), &options)
+        };
+        match pending.compiled.get_or_init(compile) {
+            Ok(shards) => Ok(shards),
+            Err(e) => Err(e.clone()),
+        }
+    }
+
+    /// The compiled shards and the literals, or only every plain-text pattern while the shards
+    /// are not ready.
+    fn matchers(&self) -> (&[Shard], &[Literal]) {
+        match &self.pending {
+            None => (&self.shards, &self.literals),
+            Some(pending) => match pending.compiled.get() {
+                Some(Ok(shards)) => (shards, &self.literals),
+                _ => (&[], &pending.literals),
+            },
+        }
+    }
+
+    /// Takes the shards of a deferred group over before an edit. On error the group stays
+    /// deferred with the error for [`PatternGroup::compile_now`].
+    fn take_compiled(&mut self) -> Result<(), BotGuardError> {
+        let shards = self.compiled()?.to_vec();
+        self.shards = shards;
+        self.pending = None;
+        Ok(())
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn patterns(&self) -> &Patterns {
+        &self.patterns
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns whether the enabled state changed.
+    pub(crate) fn set_enabled(&mut self, enabled: bool) -> bool {
+        std::mem::replace(&mut self.enabled, enabled) != enabled
+    }
+
+    /// Adds the patterns, filling up the last shard and compiling new ones for the rest, so the
+    /// shards holding the existing patterns are not recompiled.
+    ///
+    /// Returns whether any pattern was new, on error the group is left unchanged.
+    pub(crate) fn insert<I: IntoIterator<Item = String>>(&mut self, patterns: I) -> Result<bool, BotGuardError> {
+        let mut added = Patterns::new(patterns.into_iter().filter(|pattern| !self.patterns.contains(pattern)).collect(), self.case_sensitive).order;
+        if added.is_empty() {
+            return Ok(false);
+        }
+        for pattern in &added {
+            self.validate(pattern)?;
+        }
+        self.take_compiled()?;
+        let mut literals = Vec::new();
+        added.retain(|pattern| match self.lite_literal(pattern) {
+            Some(literal) => {
+                literals.push(literal);
+                false
+            }
+            None => true,
+        });
+        let mut compiled = Vec::new();
+        let mut refilled = false;
+        if let Some(last) = self.shards.last().filter(|last| last.patterns.len() < SHARD_SIZE) {
+            let room = (SHARD_SIZE - last.patterns.len()).min(added.len());
+            let mut patterns = last.patterns.clone();
+            patterns.extend(added.drain(..room));
+            compiled.push(self.compile_shard(patterns)?);
+            refilled = true;
+        }
+        for chunk in added.chunks(SHARD_SIZE) {
+            compiled.push(self.compile_shard(chunk.to_vec())?);
+        }
+        if refilled {
+            self.shards.pop();
+        }
+        for shard in compiled {
+            self.patterns.extend(shard.patterns.iter().cloned());
+            self.shards.push(shard);
+        }
+        self.patterns.extend(literals.iter().map(|literal| literal.pattern.clone()));
+        self.literals.extend(literals);
+        Ok(true)
+    }
+
+    /// Removes the patterns, recompiling only the shards that held them.
+    ///
+    /// Once removals leave more than twice the shards the patterns need, the group is compacted
+    /// into full shards again. Returns whether the group held any of the patterns.
+    pub(crate) fn remove(&mut self, patterns: &[String]) -> bool {
+        let removed = self.patterns.remove(patterns);
+        for pattern in &removed {
+            self.weights.remove(pattern);
+        }
+        self.literals.retain(|literal| !removed.contains(literal.pattern.as_str()));
+        if self.take_compiled().is_err() {
+            // the remaining patterns might compile now
+            self.defer();
+            return !removed.is_empty();
+        }
+        if removed.is_empty() {
+            self.refresh_sets();
+            return false;
+        }
+        // a subset of patterns that compiled before always compiles again
+        let expect = "removing patterns cannot exceed the regex limits";
+        if self.shards.len() > 2 * self.patterns.len().div_ceil(SHARD_SIZE) + 1 {
+            self.shards = self.compile_all().expect(expect);
+            return true;
+        }
+        let shards = std::mem::take(&mut self.shards);
+        for shard in shards {
+            if !shard.patterns.iter().any(|pattern| removed.contains(pattern.as_str())) {
+                self.shards.push(shard);
+                continue;
+            }
+            let kept = shard.patterns.into_iter().filter(|pattern| !removed.contains(pattern.as_str())).collect::<Vec<String>>();
+            if !kept.is_empty() {
+                let shard = self.compile_shard(kept).expect(expect);
+                self.shards.push(shard);
+            }
+        }
+        self.refresh_sets();
+        true
+    }
+
+    /// Weights of the patterns scoring below `1.0`.
+    pub(crate) fn weights(&self) -> &HashMap<String, f32> {
+        &self.weights
+    }
+
+    /// Sets the weights of patterns, clamped to `0.0..=1.0`, compiling the sets of the shards
+    /// that gain their first weighted pattern. Patterns the group does not contain are skipped,
+    /// returns whether any weight was set.
+    pub(crate) fn set_weights<I: IntoIterator<Item = (String, f32)>>(&mut self, weights: I) -> bool {
+        let mut found = false;
+        for (pattern, weight) in weights {
+            let Some(pattern) = self.patterns.get(&pattern).map(str::to_string) else { continue };
+            found = true;
+            let weight = weight.clamp(0.0, 1.0);
+            if weight < 1.0 {
+                self.weights.insert(pattern, weight);
+            } else {
+                self.weights.remove(&pattern);
+            }
+        }
+        if found && self.take_compiled().is_ok() {
+            self.refresh_sets();
+        }
+        found
+    }
+
+    /// The highest weight of the patterns matching an already normalized user-agent, `None`
+    /// without a match.
+    pub(crate) fn best_weight(&self, user_agent: &str) -> Option<f32> {
+        let (shards, literals) = self.matchers();
+        if !literals.is_empty() {
+            return self.best_match(user_agent).map(|(_, weight)| weight);
+        }
+        let weight = |shard: &Shard, i: usize| self.weights.get(&shard.patterns[i]).copied().unwrap_or(1.0);
+        let mut best = None;
+        for shard in shards.iter().filter(|shard| shard.regex.is_match(user_agent)) {
+            let found = match &shard.weighted {
+                Some(weighted) => weighted.matches(user_agent).iter().map(|i| weight(shard, i)).fold(0.0, f32::max),
+                None => return Some(1.0),
+            };
+            best = Some(best.map_or(found, |best: f32| best.max(found)));
+        }
+        best
+    }
+
+    /// The matching pattern with the highest weight in an already normalized user-agent, the
+    /// leftmost one among equal weights.
+    pub(crate) fn best_match(&self, user_agent: &str) -> Option<(&str, f32)> {
+        let weight = |pattern: &str| self.weights.get(pattern).copied().unwrap_or(1.0);
+        let leftmost = self.matched_pattern(user_agent)?;
+        let mut best = (leftmost, weight(leftmost));
+        let (shards, literals) = self.matchers();
+        let found = literals.iter().filter(|literal| literal.find(user_agent, self.match_mode).is_some()).map(|literal| literal.pattern.as_str());
+        let candidates = shards.iter().flat_map(|shard| match &shard.weighted {
+            Some(weighted) => weighted.matches(user_agent).iter().map(|i| shard.patterns[i].as_str()).collect(),
+            // every pattern of the shard weighs 1.0
+            None => shard.leftmost(user_agent).map(|(_, pattern)| pattern).into_iter().collect::<Vec<_>>(),
+        });
+        for pattern in candidates.chain(found) {
+            if weight(pattern) > best.1 {
+                best = (pattern, weight(pattern));
+            }
+        }
+        Some(best)
+    }
+
+    pub(crate) fn is_match(&self, user_agent: &str) -> bool {
+        let (shards, literals) = self.matchers();
+        literals.iter().any(|literal| literal.find(user_agent, self.match_mode).is_some()) || shards.iter().any(|shard| shard.regex.is_match(user_agent))
+    }
+
+    /// Returns the pattern responsible for the leftmost match in an already normalized user-agent.
+    pub(crate) fn matched_pattern(&self, user_agent: &str) -> Option<&str> {
+        let (shards, literals) = self.matchers();
+        let found = literals.iter().filter_map(|literal| Some((literal.find(user_agent, self.match_mode)?, literal.pattern.as_str())));
+        shards.iter().filter_map(|shard| shard.leftmost(user_agent)).chain(found).min_by_key(|(start, _)| *start).map(|(_, pattern)| pattern)
+    }
+
+    /// Compiles all patterns into full shards, except the literals of the `lite` feature.
+    fn compile_all(&self) -> Result<Vec<Shard>, BotGuardError> {
+        let patterns = self.patterns.iter().filter(|pattern| self.lite_literal(pattern).is_none()).cloned().collect::<Vec<String>>();
+        patterns.chunks(SHARD_SIZE).map(|chunk| self.compile_shard(chunk.to_vec())).collect()
+    }
+
+    /// Joins the entries into one alternation, each wrapped in its own named group so inline
+    /// flags like `(?i)` cannot leak into the following entries, with the anchors of the match mode.
+    fn compile_shard(&self, patterns: Vec<String>) -> Result<Shard, BotGuardError> {
+        let pattern = patterns
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| self.match_mode.wrap(i, &strip_capture_names(entry)))
+            .collect::<Vec<String>>()
+            .join("|");
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .size_limit(self.limits.group_size_limit)
+            .dfa_size_limit(self.limits.dfa_size_limit)
+            // the wrapping group adds one level of nesting
+            .nest_limit(self.limits.nest_limit.saturating_add(1))
+            .build()
+            .map_err(|e| self.combine_error(&pattern, e))?;
+        let weighted = match patterns.iter().any(|pattern| self.weights.contains_key(pattern)) {
+            true => Some(self.compile_set(&patterns)?),
+            false => None,
+        };
+        Ok(Shard { patterns, regex, weighted })
+    }
+
+    /// Compiles the sets of shards that gained a weighted pattern and drops those of shards that lost all.
+    fn refresh_sets(&mut self) {
+        for i in 0..self.shards.len() {
+            let shard = &self.shards[i];
+            let weighted = shard.patterns.iter().any(|pattern| self.weights.contains_key(pattern));
+            if weighted && shard.weighted.is_none() {
+                // the set holds the same patterns as the regex that compiled within the limits
+                let set = self.compile_set(&shard.patterns).expect("weighting patterns cannot exceed the regex limits");
+                self.shards[i].weighted = Some(set);
+            } else if !weighted {
+                self.shards[i].weighted = None;
+            }
+        }
+    }
+
+    fn compile_set(&self, patterns: &[String]) -> Result<RegexSet, BotGuardError> {
+        let wrapped = patterns.iter().enumerate().map(|(i, entry)| self.match_mode.wrap(i, &strip_capture_names(entry))).collect::<Vec<String>>();
+        RegexSetBuilder::new(&wrapped)
+            .case_insensitive(!self.case_sensitive)
+            .size_limit(self.limits.group_size_limit)
+            .dfa_size_limit(self.limits.dfa_size_limit)
+            .nest_limit(self.limits.nest_limit.saturating_add(1))
+            .build()
+            .map_err(|e| self.combine_error(&wrapped.join("|"), e))
+    }
+
+    /// Only the size can fail once every entry compiled on its own, anything else is reported
+    /// as it is rather than hidden behind the size limit.
+    fn combine_error(&self, combined: &str, error: regex::Error) -> BotGuardError {
+        match error {
+            regex::Error::CompiledTooBig(_) => BotGuardError::GroupTooLarge { group: self.name.clone(), limit: self.limits.group_size_limit },
+            e => BotGuardError::InvalidPattern { pattern: combined.to_string(), reason: format!("combined into group {:?}: {}", self.name, e) },
+        }
+    }
+
+    /// Every pattern on its own in one set, indexed like [`PatternGroup::compiled_patterns`], for
+    /// finding all patterns matching a user-agent rather than the leftmost one.
+    pub(crate) fn pattern_set(&self) -> Result<RegexSet, BotGuardError> {
+        let shards = self.compiled()?;
+        let patterns = shards.iter().flat_map(|shard| shard.patterns.iter()).chain(self.literals.iter().map(|literal| &literal.pattern));
+        self.compile_set(&patterns.cloned().collect::<Vec<String>>())
+    }
+
+    /// The patterns in shard order followed by the literals, empty while a deferred group does not compile.
+    pub(crate) fn compiled_patterns(&self) -> Vec<&str> {
+        let shards = self.compiled().unwrap_or_default();
+        let patterns = shards.iter().flat_map(|shard| shard.patterns.iter()).chain(self.literals.iter().map(|literal| &literal.pattern));
+        patterns.map(String::as_str).collect()
+    }
+
+    /// Compiles a single entry on its own against the pattern limits. The `__bg` capture names
+    /// are reserved for the wrapping groups.
+    fn validate(&self, pattern: &str) -> Result<(), BotGuardError> {
+        if pattern.contains("(?P<__bg") || pattern.contains("(?<__bg") {
+            return Err(BotGuardError::InvalidPattern { pattern: pattern.to_string(), reason: "capture names starting with __bg are reserved".to_string() });
+        }
+        let compiled = RegexBuilder::new(pattern)
+            .case_insensitive(!self.case_sensitive)
+            .size_limit(self.limits.pattern_size_limit)
+            .nest_limit(self.limits.nest_limit)
+            .build();
+        match compiled {
+            Ok(_) => Ok(()),
+            Err(regex::Error::CompiledTooBig(limit)) => Err(BotGuardError::PatternTooLarge { pattern: pattern.to_string(), limit }),
+            Err(e) => Err(BotGuardError::InvalidPattern { pattern: pattern.to_string(), reason: e.to_string() }),
+        }
+    }
+}
+
+/// What patterns are told apart by: as written in case-sensitive detectors, otherwise lowercased
+/// outside of escapes and capture names, so `GoogleBot` and `googlebot` are one pattern while
+/// `\D` and `\d` or `(?P<Version>` and `(?P<version>` stay two. Only for lookups, the patterns
+/// are compiled as written.
+pub(crate) fn pattern_key(pattern: &str, case_sensitive: bool) -> Cow<'_, str> {
+    if case_sensitive || !pattern.bytes().any(|b| b.is_ascii_uppercase()) {
+        return Cow::Borrowed(pattern);
+    }
+    let mut key = String::with_capacity(pattern.len());
+    let (mut escaped, mut name) = (false, false);
+    for c in pattern.chars() {
+        if name {
+            name = c != '>';
+            key.push(c);
+            continue;
+        }
+        key.push(if escaped { c } else { c.to_ascii_lowercase() });
+        name = !escaped && (key.ends_with("(?p<") || key.ends_with("(?<"));
+        escaped = !escaped && c == '\\';
+    }
+    Cow::Owned(key)
+}
+
+/// Turns the named captures of an entry into non-capturing groups before it is combined with
+/// others, two entries may use the same name. [`crate::span::find`] compiles the matching entry
+/// on its own again to read its captures.
+fn strip_capture_names(entry: &str) -> Cow<'_, str> {
+    if !entry.contains("(?P<") && !entry.contains("(?<") {
+        return Cow::Borrowed(entry);
+    }
+    let mut out = String::with_capacity(entry.len());
+    let (mut i, mut class) = (0, 0usize);
+    while let Some(c) = entry[i..].chars().next() {
+        let rest = &entry[i..];
+        match c {
+            '\\' => {
+                let len = 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+                out.push_str(&rest[..len]);
+                i += len;
+                continue;
+            }
+            '[' => {
+                class += 1;
+                // a `]` right after the opening bracket is a literal
+                let literal = ["[^]", "[]"].iter().find(|open| rest.starts_with(*open)).map_or(1, |open| open.len());
+                out.push_str(&rest[..literal]);
+                i += literal;
+                continue;
+            }
+            ']' if class > 0 => class -= 1,
+            '(' if class == 0 => {
+                let open = if rest.starts_with("(?P<") {
+                    4
+                } else if rest.starts_with("(?<") && !rest.starts_with("(?<=") && !rest.starts_with("(?<!") {
+                    3
+                } else {
+                    0
+                };
+                if let Some(end) = Some(open).filter(|&open| open > 0).and_then(|open| rest[open..].find('>')) {
+                    out.push_str("(?:");
+                    i += open + end + 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        out.push(c);
+        i += c.len_utf8();
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BotDetector;
+
+    fn group(patterns: impl IntoIterator<Item = String>) -> PatternGroup {
+        PatternGroup::new("test".to_string(), patterns.into_iter().collect(), &DetectorOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn entries_may_share_capture_names() {
+        let detector = BotDetector::builder().patterns("curl/(?P<version>\\d+)\nwget/(?P<version>\\d+)").build().unwrap();
+        assert_eq!(detector.find("Wget/1.21").and_then(|found| found.capture("version")).map(|span| span.text), Some("1"));
+        let mut detector = BotDetector::new("foo");
+        detector.append(&["x(?P<v>\\d)", "y(?P<v>\\d)"]).unwrap();
+        assert!(detector.check_bot("y1"));
+
+        let reserved = BotDetector::builder().patterns("(?P<__bg1>zz)\nfoo").build().unwrap_err();
+        assert!(matches!(reserved, BotGuardError::InvalidPattern { .. }), "{}", reserved);
+        assert_eq!(strip_capture_names(r"\(?P<a>x)[(?P<b>](?P<c>y)"), r"\(?P<a>x)[(?P<b>](?:y)");
+        assert_eq!(strip_capture_names("[]]|(?P<d>z)|(?:w)"), "[]]|(?:z)|(?:w)");
+    }
+
+    #[test]
+    fn edits_only_recompile_their_shard() {
+        let mut group = group((0..600).map(|i| format!("bot{}x+", i)));
+        assert_eq!(group.shards.iter().map(|shard| shard.patterns.len()).collect::<Vec<_>>(), [256, 256, 88]);
+        let first = group.shards[0].patterns.clone();
+
+        group.insert(["newbot+".to_string(), "bot1x+".to_string()]).unwrap();
+        assert_eq!(group.shards.len(), 3);
+        assert_eq!(group.shards[0].patterns, first);
+        assert_eq!(group.shards[2].patterns.last().map(String::as_str), Some("newbot+"));
+        assert_eq!(group.matched_pattern("a newbot"), Some("newbot+"));
+
+        let removed = first[..10].to_vec();
+        group.remove(&removed);
+        assert_eq!(group.shards[0].patterns.len(), 246);
+        assert!(!group.is_match(removed[0].trim_end_matches('+')));
+        assert_eq!(group.patterns().len(), 591);
+        assert_eq!(group.compiled_patterns().len(), 591);
+
+        assert!(group.insert(["(broken".to_string()]).is_err());
+        assert_eq!(group.patterns().len(), 591);
+    }
+
+    #[test]
+    fn patterns_keep_their_order() {
+        let patterns = ["zz\\d", "bot\\d*", "aa\\d", "bot\\d+", "zz\\d"].map(String::from);
+        let mut forward = group(patterns.clone());
+        assert_eq!(forward.compiled_patterns(), vec!["zz\\d", "bot\\d*", "aa\\d", "bot\\d+"]);
+        assert_eq!(forward.matched_pattern("bot1"), Some("bot\\d*"));
+        let mut backward = group(patterns.into_iter().rev());
+        assert_eq!(backward.matched_pattern("bot1"), Some("bot\\d+"));
+
+        forward.insert(["new\\d".to_string(), "bot\\d*".to_string()]).unwrap();
+        forward.remove(&["aa\\d".to_string()]);
+        assert_eq!(forward.patterns().iter().collect::<Vec<_>>(), vec!["zz\\d", "bot\\d*", "bot\\d+", "new\\d"]);
+        assert_eq!(forward.compiled_patterns(), vec!["zz\\d", "bot\\d*", "bot\\d+", "new\\d"]);
+        backward.remove(&["bot\\d+".to_string()]);
+        assert_eq!(backward.matched_pattern("bot1"), Some("bot\\d*"));
+    }
+
+    #[test]
+    fn matches_across_shards() {
+        let crawler = "crawler".to_string();
+        let mut group = group((0..300).map(|i| format!("^agent{:03}/\\d", i)).chain([crawler.clone()]));
+        let other = group.shards.iter().find(|shard| !shard.patterns.contains(&crawler)).unwrap().patterns[0].clone();
+        let ua = format!("{}1.0 crawler", other[1..].trim_end_matches("\\d"));
+        assert_eq!(group.matched_pattern(&ua), Some(other.as_str()));
+        assert_eq!(group.best_weight(&ua), Some(1.0));
+
+        group.set_weights([(other.clone(), 0.2), ("crawler".to_string(), 0.4)]);
+        assert_eq!(group.best_weight(&ua), Some(0.4));
+        assert_eq!(group.best_match(&ua), Some(("crawler", 0.4)));
+        assert_eq!(group.best_match("a crawler"), Some(("crawler", 0.4)));
+        group.set_weights([(other, 1.0), (crawler, 1.0)]);
+        assert!(group.shards.iter().all(|shard| shard.weighted.is_none()));
+
+        // removing most patterns compacts the leftover shards
+        let mut group = self::group((0..1024).map(|i| format!("p{}q+", i)));
+        let shard_heads = group.shards.iter().map(|shard| shard.patterns[..250].to_vec()).collect::<Vec<_>>();
+        for head in &shard_heads {
+            group.remove(head);
+        }
+        assert_eq!((group.shards.len(), group.patterns().len()), (1, 24));
+    }
+
+    #[test]
+    fn lite_builds_only_compile_regex_patterns() {
+        let mut group = group(["googlebot", "^curl/", "bot\\d"].map(String::from));
+        let compiled = |group: &PatternGroup| group.shards.iter().map(|shard| shard.patterns.len()).sum::<usize>();
+        assert_eq!(compiled(&group), if cfg!(feature = "lite") { 1 } else { 3 });
+        assert_eq!(group.matched_pattern("curl/8 bot1 googlebot"), Some("^curl/"));
+        assert_eq!(group.matched_pattern("x bot1 googlebot"), Some("bot\\d"));
+        group.set_weights([("googlebot".to_string(), 0.4)]);
+        assert_eq!(group.best_match("googlebot"), Some(("googlebot", 0.4)));
+        assert_eq!(group.best_weight("googlebot curl/"), Some(0.4));
+
+        group.insert(["wget/".to_string()]).unwrap();
+        group.remove(&["googlebot".to_string()]);
+        assert!(group.is_match("wget/1.21") && !group.is_match("googlebot"));
+        assert_eq!(group.compiled_patterns().len(), 3);
+        assert_eq!(group.pattern_set().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn deferred_groups_match_plain_text_until_compiled() {
+        let mut group = group(["googlebot", "^curl/", "bot$", "bing\\.com", "a.b"].map(String::from));
+        group.set_weights([("^curl/".to_string(), 0.5)]);
+        group.defer();
+        assert!(!group.is_compiled());
+        assert_eq!(group.matched_pattern("x (+bing.com) googlebot"), Some("bing\\.com"));
+        assert_eq!(group.best_match("curl/8 googlebot/2"), Some(("googlebot", 1.0)));
+        assert_eq!(group.best_weight("curl/8.0"), Some(0.5));
+        assert!(group.is_match("my bot") && !group.is_match("my bots") && !group.is_match("a curl/"));
+        assert!(!group.is_match("axb"));
+
+        group.compile_now().unwrap();
+        assert!(group.is_compiled());
+        assert!(group.is_match("axb"));
+        group.insert(["new".to_string()]).unwrap();
+        assert!(group.pending.is_none() && group.is_match("new"));
+
+        let token = Literal::parse("googlebot").unwrap();
+        assert_eq!(token.find("notgooglebot (googlebot/2.1)", MatchMode::Token), Some(14));
+        assert_eq!(token.find("googlebots", MatchMode::Token), None);
+        assert!(Literal::parse("\\d+").is_none() && Literal::parse("(?i)x").is_none());
+
+        let options = DetectorOptions::default();
+        let mut broken = PatternGroup::deferred("x".to_string(), ["ok".to_string(), "(open".to_string()].into(), Vec::new(), &options);
+        assert!(matches!(broken.compile_now(), Err(BotGuardError::InvalidPattern { .. })));
+        assert!(broken.is_match("ok"));
+        broken.remove(&["(open".to_string()]);
+        broken.compile_now().unwrap();
+        assert!(broken.is_compiled());
+    }
+}
@@ -0,0 +1,255 @@
+// The gRPC endpoint of the detection service, the `botguard.v1.BotGuard/Check` method of
+// `proto/botguard.proto`, enabled with the `grpc` feature and started by `Server::grpc`.
+//
+// The messages and the service are what `tonic-build` would generate from the proto file,
+// written out so that building the crate needs no `protoc`. A check is decided exactly like a
+// `POST /check` and counted in the same metrics. The endpoint runs on a Tokio runtime of its
+// own; calls are answered within `Server::request_timeout` and at most 32 run at the same time
+// on one connection.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{empty_body, http, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService, UnaryService};
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status};
+
+use crate::server::{Check, Decision, Server};
+
+/// Path of the `Check` method.
+pub const CHECK_PATH: &str = "/botguard.v1.BotGuard/Check";
+/// Calls served at the same time on one connection.
+const CALLS_PER_CONNECTION: usize = 32;
+
+/// Arguments of `Check`, the same as the JSON body of `POST /check`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CheckRequest {
+    /// Falls back to the `user-agent` entry of `headers`.
+    #[prost(string, optional, tag = "1")]
+    pub user_agent: Option<String>,
+    /// IPv4 or IPv6 address of the client, empty for none.
+    #[prost(string, tag = "2")]
+    pub ip: String,
+    /// Falls back to the `referer` entry of `headers`.
+    #[prost(string, optional, tag = "3")]
+    pub referrer: Option<String>,
+    /// Request headers, names compared case-insensitively.
+    #[prost(map = "string, string", tag = "4")]
+    pub headers: HashMap<String, String>,
+}
+
+/// [`crate::Verdict`] on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Verdict {
+    Unspecified = 0,
+    Human = 1,
+    Suspicious = 2,
+    Bot = 3,
+}
+
+/// Answer of `Check`, the same fields as the JSON answer of `POST /check`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CheckResponse {
+    #[prost(enumeration = "Verdict", tag = "1")]
+    pub verdict: i32,
+    #[prost(bool, tag = "2")]
+    pub is_bot: bool,
+    #[prost(bool, tag = "3")]
+    pub allowlisted: bool,
+    /// Group of the matching pattern.
+    #[prost(string, optional, tag = "4")]
+    pub category: Option<String>,
+    /// Version of the pattern set.
+    #[prost(string, tag = "5")]
+    pub version: String,
+    /// Why the referrer is spam, unset if it is not.
+    #[prost(string, optional, tag = "6")]
+    pub referrer_spam: Option<String>,
+}
+
+impl From<Decision> for CheckResponse {
+    fn from(decision: Decision) -> Self {
+        let verdict = match decision.verdict {
+            crate::Verdict::Human => Verdict::Human,
+            crate::Verdict::Suspicious => Verdict::Suspicious,
+            crate::Verdict::Bot => Verdict::Bot,
+        };
+        CheckResponse {
+            verdict: verdict as i32,
+            is_bot: decision.verdict.is_bot(),
+            allowlisted: decision.allowlisted,
+            category: decision.category,
+            version: decision.version,
+            referrer_spam: decision.referrer_spam,
+        }
+    }
+}
+
+/// Decides a `Check` call, the error is the reason the arguments are invalid.
+fn check(server: &Server, request: &CheckRequest) -> Result<CheckResponse, String> {
+    let header = |name: &str| request.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str());
+    let ip = match request.ip.as_str() {
+        "" => None,
+        ip => match ip.parse() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                server.count_bad_request();
+                return Err(format!("invalid ip {:?}", ip));
+            }
+        },
+    };
+    let user_agent = request.user_agent.as_deref().or_else(|| header("user-agent")).unwrap_or("");
+    let referrer = request.referrer.as_deref().or_else(|| header("referer"));
+    Ok(server.decide(&Check { user_agent, ip, referrer }).into())
+}
+
+/// The `botguard.v1.BotGuard` service.
+#[derive(Debug, Clone)]
+struct BotGuardService {
+    server: Arc<Server>,
+}
+
+impl NamedService for BotGuardService {
+    const NAME: &'static str = "botguard.v1.BotGuard";
+}
+
+struct CheckMethod(Arc<Server>);
+
+impl UnaryService<CheckRequest> for CheckMethod {
+    type Response = CheckResponse;
+    type Future = BoxFuture<Response<CheckResponse>, Status>;
+
+    fn call(&mut self, request: Request<CheckRequest>) -> Self::Future {
+        let server = Arc::clone(&self.0);
+        Box::pin(async move { check(&server, request.get_ref()).map(Response::new).map_err(Status::invalid_argument) })
+    }
+}
+
+impl<B> Service<http::Request<B>> for BotGuardService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if request.uri().path() == CHECK_PATH {
+            let method = CheckMethod(Arc::clone(&self.server));
+            return Box::pin(async move { Ok(Grpc::new(ProstCodec::default()).unary(method, request).await) });
+        }
+        Box::pin(async move {
+            let mut response = http::Response::new(empty_body());
+            let headers = response.headers_mut();
+            headers.insert(Status::GRPC_STATUS, (Code::Unimplemented as i32).into());
+            headers.insert(http::header::CONTENT_TYPE, tonic::metadata::GRPC_CONTENT_TYPE);
+            Ok(response)
+        })
+    }
+}
+
+/// A bound gRPC listener with the runtime that serves it.
+pub(crate) struct Endpoint {
+    runtime: Runtime,
+    listener: tokio::net::TcpListener,
+}
+
+impl Endpoint {
+    pub(crate) fn bind(listener: &TcpListener) -> io::Result<Self> {
+        let listener = listener.try_clone()?;
+        listener.set_nonblocking(true)?;
+        let runtime = tokio::runtime::Builder::new_multi_thread().thread_name("botguard-grpc").enable_all().build()?;
+        let listener = {
+            let _context = runtime.enter();
+            tokio::net::TcpListener::from_std(listener)?
+        };
+        Ok(Endpoint { runtime, listener })
+    }
+
+    /// Serves `Check` on a thread of its own until the returned sender is dropped.
+    pub(crate) fn spawn(self, server: Arc<Server>, timeout: Duration) -> io::Result<oneshot::Sender<()>> {
+        let incoming = TcpIncoming::from_listener(self.listener, true, None).map_err(io::Error::other)?;
+        let router = tonic::transport::Server::builder()
+            .timeout(timeout)
+            .concurrency_limit_per_connection(CALLS_PER_CONNECTION)
+            .add_service(BotGuardService { server });
+        let (stop, stopped) = oneshot::channel::<()>();
+        let runtime = self.runtime;
+        thread::Builder::new().name("botguard-grpc".to_string()).spawn(move || {
+            let _ = runtime.block_on(router.serve_with_incoming_shutdown(incoming, async {
+                let _ = stopped.await;
+            }));
+        })?;
+        Ok(stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::codegen::http::uri::PathAndQuery;
+    use tonic::transport::Channel;
+
+    use crate::config::Allowlist;
+    use crate::BotDetector;
+
+    #[test]
+    fn answers_checks_over_grpc() {
+        let rest = TcpListener::bind("127.0.0.1:0").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let allowlist = Allowlist::new(&["^kube-probe/"], &[]).unwrap();
+        let server = Server::new(BotDetector::new("[scrapers]\ncurl/")).allowlist(allowlist).grpc(listener);
+        thread::spawn(move || server.serve(rest));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let channel = Channel::from_shared(format!("http://{}", addr)).unwrap().connect().await.unwrap();
+            let mut client = tonic::client::Grpc::new(channel);
+            let check = |request: CheckRequest| {
+                let mut client = client.clone();
+                async move {
+                    client.ready().await.unwrap();
+                    let path = PathAndQuery::from_static(CHECK_PATH);
+                    client.unary::<_, CheckResponse, _>(Request::new(request), path, ProstCodec::default()).await.map(Response::into_inner)
+                }
+            };
+
+            let bot = check(CheckRequest { user_agent: Some("curl/8.0".to_string()), ip: "203.0.113.7".to_string(), ..Default::default() }).await.unwrap();
+            assert_eq!(bot.verdict(), Verdict::Bot);
+            assert!(bot.is_bot && !bot.allowlisted);
+            assert_eq!(bot.category.as_deref(), Some("scrapers"));
+            assert_eq!(bot.referrer_spam, None);
+
+            let headers = HashMap::from([("User-Agent".to_string(), "Kube-Probe/1.29".to_string()), ("Referer".to_string(), "http://semalt.com/".to_string())]);
+            let probe = check(CheckRequest { headers, ..Default::default() }).await.unwrap();
+            assert_eq!(probe.verdict(), Verdict::Human);
+            assert!(probe.allowlisted);
+            assert_eq!(probe.referrer_spam.as_deref(), Some("referrer spam domain semalt.com"));
+
+            let invalid = check(CheckRequest { ip: "nope".to_string(), ..Default::default() }).await.unwrap_err();
+            assert_eq!(invalid.code(), Code::InvalidArgument);
+
+            client.ready().await.unwrap();
+            let other = client
+                .unary::<_, CheckResponse, _>(Request::new(CheckRequest::default()), PathAndQuery::from_static("/botguard.v1.BotGuard/Other"), ProstCodec::default())
+                .await;
+            assert_eq!(other.unwrap_err().code(), Code::Unimplemented);
+        });
+    }
+}
@@ -0,0 +1,166 @@
+// Header order fingerprints. Browsers and HTTP libraries each send their headers in an order of
+// their own, which scripts rarely bother to imitate when they copy a browser user-agent.
+//
+// Headers that proxies and CDNs add on the way, like `X-Forwarded-For` or `Via`, are left out
+// of the fingerprint, so it stays the same behind any number of them.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{RwLock, RwLockReadGuard};
+
+use crate::request::RequestSnapshot;
+
+/// Header orders of automation clients as sent by their recent releases.
+const BUNDLED: &[(&str, &str)] = &[
+    ("host,user-agent,accept", "curl"),
+    ("user-agent,accept,accept-encoding,host,connection", "wget"),
+    ("host,user-agent,accept-encoding,accept,connection", "python-requests"),
+    ("host,user-agent,accept-encoding", "go-http-client"),
+    ("host,connection,accept-encoding,user-agent", "okhttp"),
+    ("accept,user-agent,host,connection", "axios"),
+    ("user-agent,host,connection", "java-httpclient"),
+];
+
+/// Header names added by proxies and CDNs, and prefixes of such names.
+const FORWARDING: &[&str] = &["via", "forwarded", "true-client-ip", "x-", "cf-"];
+
+/// The names of a request's headers in the order they were sent, lowercased and separated by
+/// commas, like `host,user-agent,accept`. Repeated headers count once, where they first appeared.
+///
+/// ```
+/// use BotGuardLib::headers::HeaderOrderFingerprint;
+/// use BotGuardLib::request::RequestSnapshot;
+///
+/// let request = RequestSnapshot::new("GET", "/")
+///     .header("Host", "example.com")
+///     .header("User-Agent", "curl/8.4.0")
+///     .header("X-Forwarded-For", "203.0.113.7")
+///     .header("Accept", "*/*");
+/// assert_eq!(HeaderOrderFingerprint::from_request(&request).as_str(), "host,user-agent,accept");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct HeaderOrderFingerprint(String);
+
+impl HeaderOrderFingerprint {
+    pub fn from_names<'a, I: IntoIterator<Item = &'a str>>(names: I) -> Self {
+        let mut seen: Vec<String> = Vec::new();
+        for name in names {
+            let name = name.trim().to_ascii_lowercase();
+            let forwarded = FORWARDING.iter().any(|prefix| if prefix.ends_with('-') { name.starts_with(prefix) } else { name == *prefix });
+            if !name.is_empty() && !forwarded && !seen.contains(&name) {
+                seen.push(name);
+            }
+        }
+        HeaderOrderFingerprint(seen.join(","))
+    }
+
+    pub fn from_request(request: &RequestSnapshot) -> Self {
+        HeaderOrderFingerprint::from_names(request.headers.iter().map(|(name, _)| name.as_str()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns `true` for a request without headers, which nothing is known about.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for HeaderOrderFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Known header orders of automation clients, with the client each one identifies.
+///
+/// Fingerprints can be registered while checks are running, e.g. from an admin endpoint.
+///
+/// ```
+/// use BotGuardLib::headers::{HeaderFingerprints, HeaderOrderFingerprint};
+///
+/// let fingerprints = HeaderFingerprints::bundled();
+/// let curl = HeaderOrderFingerprint::from_names(["Host", "User-Agent", "Accept"]);
+/// assert_eq!(fingerprints.identify(&curl).as_deref(), Some("curl"));
+///
+/// let scraper = HeaderOrderFingerprint::from_names(["Accept", "Host", "User-Agent", "Accept-Language"]);
+/// assert_eq!(fingerprints.identify(&scraper), None);
+/// fingerprints.register(scraper.clone(), "acme-scraper");
+/// assert_eq!(fingerprints.identify(&scraper).as_deref(), Some("acme-scraper"));
+/// ```
+#[derive(Debug, Default)]
+pub struct HeaderFingerprints {
+    known: RwLock<HashMap<HeaderOrderFingerprint, String>>,
+}
+
+impl HeaderFingerprints {
+    /// No fingerprints at all.
+    pub fn new() -> Self {
+        HeaderFingerprints::default()
+    }
+
+    /// The fingerprints of common HTTP libraries and command line tools.
+    pub fn bundled() -> Self {
+        let fingerprints = HeaderFingerprints::new();
+        for (order, client) in BUNDLED {
+            fingerprints.register(HeaderOrderFingerprint::from_names(order.split(',')), client);
+        }
+        fingerprints
+    }
+
+    /// Adds a fingerprint or replaces the client it identifies.
+    pub fn register(&self, fingerprint: HeaderOrderFingerprint, client: &str) {
+        if !fingerprint.is_empty() {
+            self.known.write().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(fingerprint, client.to_string());
+        }
+    }
+
+    /// Returns `false` if the fingerprint was not known.
+    pub fn remove(&self, fingerprint: &HeaderOrderFingerprint) -> bool {
+        self.known.write().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(fingerprint).is_some()
+    }
+
+    /// The client a header order belongs to, if it is a known one.
+    pub fn identify(&self, fingerprint: &HeaderOrderFingerprint) -> Option<String> {
+        self.known().get(fingerprint).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.known().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.known().is_empty()
+    }
+
+    fn known(&self) -> RwLockReadGuard<'_, HashMap<HeaderOrderFingerprint, String>> {
+        self.known.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarding_headers_and_repeats_are_left_out() {
+        let fingerprint = HeaderOrderFingerprint::from_names(["Via", "Host", "CF-Connecting-IP", "Accept", "accept", "Forwarded", "X-Real-IP", "Xylophone"]);
+        assert_eq!(fingerprint.to_string(), "host,accept,xylophone");
+        assert!(HeaderOrderFingerprint::from_names(["X-Request-Id"]).is_empty());
+    }
+
+    #[test]
+    fn bundled_fingerprints_identify_libraries() {
+        let fingerprints = HeaderFingerprints::bundled();
+        assert_eq!(fingerprints.len(), BUNDLED.len());
+        let requests = HeaderOrderFingerprint::from_names(["Host", "User-Agent", "Accept-Encoding", "Accept", "Connection"]);
+        assert_eq!(fingerprints.identify(&requests).as_deref(), Some("python-requests"));
+        let chrome = HeaderOrderFingerprint::from_names(["Host", "Connection", "sec-ch-ua", "User-Agent", "Accept", "Accept-Encoding", "Accept-Language"]);
+        assert_eq!(fingerprints.identify(&chrome), None);
+        assert!(fingerprints.remove(&requests) && !fingerprints.remove(&requests));
+        fingerprints.register(HeaderOrderFingerprint::default(), "nothing");
+        assert_eq!(fingerprints.len(), BUNDLED.len() - 1);
+    }
+}
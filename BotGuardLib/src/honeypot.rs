@@ -0,0 +1,193 @@
+// Hidden form fields catching form spam that user-agent patterns never see: the form carries a
+// field humans cannot see and a signed token with the time it was served. Bots filling every
+// field or posting the moment the page arrived give themselves away.
+//
+// The field name is derived from a secret and the session with HMAC-SHA-512 and the token is a
+// `timing::PageTimer` token for the session, so nothing has to be stored on the server between
+// serving the form and checking the submission.
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::timing::{PageTimer, TimingCheck};
+use crate::{crypto, Verdict};
+
+/// Name of the form field holding the token.
+pub const TOKEN_FIELD: &str = "bg_form_token";
+
+/// Hex digits of the MAC in field names.
+const NAME_DIGITS: usize = 8;
+
+/// The fields to add to a form, see [`FormHoneypot::issue`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HoneypotField {
+    /// Name of the field that has to stay empty, it differs between sessions.
+    pub name: String,
+    /// Value of the [`TOKEN_FIELD`].
+    pub token: String,
+}
+
+impl HoneypotField {
+    /// Both fields as HTML, the honeypot is moved off-screen rather than `type="hidden"`, which bots skip.
+    pub fn to_html(&self) -> String {
+        format!(
+            "<input type=\"hidden\" name=\"{}\" value=\"{}\">\
+             <div style=\"position:absolute;left:-10000px\" aria-hidden=\"true\">\
+             <input type=\"text\" name=\"{}\" tabindex=\"-1\" autocomplete=\"off\"></div>",
+            TOKEN_FIELD, self.token, self.name
+        )
+    }
+}
+
+/// Outcome of [`FormHoneypot::verify`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormCheck {
+    Passed,
+    /// The submission has no token, e.g. the form was posted without loading the page.
+    MissingToken,
+    /// The token was not issued for this session or was tampered with.
+    InvalidToken,
+    /// The token is older than [`FormHoneypot::max_age`].
+    Expired,
+    /// The hidden field has a value.
+    FilledHoneypot,
+    /// The form was submitted this soon after it was served, faster than
+    /// [`FormHoneypot::min_fill_time`].
+    TooFast(Duration),
+}
+
+impl FormCheck {
+    /// A filled honeypot, a too fast submission or a forged token are bots. Missing or expired
+    /// tokens are suspicious, they also happen to humans with stale tabs or blocked scripts.
+    pub fn verdict(self) -> Verdict {
+        match self {
+            FormCheck::Passed => Verdict::Human,
+            FormCheck::MissingToken | FormCheck::Expired => Verdict::Suspicious,
+            FormCheck::InvalidToken | FormCheck::FilledHoneypot | FormCheck::TooFast(_) => Verdict::Bot,
+        }
+    }
+}
+
+/// Issues and verifies honeypot fields.
+///
+/// ```
+/// use BotGuardLib::honeypot::{FormCheck, FormHoneypot, TOKEN_FIELD};
+///
+/// let honeypot = FormHoneypot::new(b"a long random server secret");
+/// let field = honeypot.issue("session-42");
+/// assert!(field.to_html().contains(&field.name));
+///
+/// // a bot posting right away and filling every field
+/// let form = [(TOKEN_FIELD, field.token.as_str()), (field.name.as_str(), "http://spam.example"), ("comment", "hi")];
+/// assert_eq!(honeypot.verify("session-42", form), FormCheck::FilledHoneypot);
+/// let form = [(TOKEN_FIELD, field.token.as_str()), (field.name.as_str(), ""), ("comment", "hi")];
+/// assert!(matches!(honeypot.verify("session-42", form), FormCheck::TooFast(_)));
+/// assert_eq!(honeypot.verify("session-7", form), FormCheck::InvalidToken);
+/// ```
+#[derive(Clone)]
+pub struct FormHoneypot {
+    secret: Vec<u8>,
+    timer: PageTimer,
+}
+
+impl fmt::Debug for FormHoneypot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FormHoneypot").field("timer", &self.timer).finish_non_exhaustive()
+    }
+}
+
+impl FormHoneypot {
+    /// Submissions within 2 seconds of serving the form are too fast, tokens expire after a day.
+    /// Every instance verifying the forms needs the same secret.
+    pub fn new(secret: &[u8]) -> Self {
+        FormHoneypot { secret: secret.to_vec(), timer: PageTimer::new(secret) }
+    }
+
+    pub fn min_fill_time(mut self, min_fill_time: Duration) -> Self {
+        self.timer = self.timer.human_floor(min_fill_time);
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.timer = self.timer.max_age(max_age);
+        self
+    }
+
+    /// The fields for a form served now to the session.
+    pub fn issue(&self, session: &str) -> HoneypotField {
+        self.issue_at(session, SystemTime::now())
+    }
+
+    fn issue_at(&self, session: &str, served: SystemTime) -> HoneypotField {
+        HoneypotField { name: self.field_name(session), token: self.timer.issue_at(session, served) }
+    }
+
+    /// The name of the session's honeypot field, a plausible one so form fillers do not skip it.
+    pub fn field_name(&self, session: &str) -> String {
+        let mac = crypto::hmac_sha512(&self.secret, format!("field\n{}", session).as_bytes());
+        format!("website_{}", &crypto::hex(&mac)[..NAME_DIGITS])
+    }
+
+    /// Checks the submitted form fields of the session.
+    pub fn verify<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(&self, session: &str, fields: I) -> FormCheck {
+        self.verify_at(session, fields, SystemTime::now())
+    }
+
+    fn verify_at<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(&self, session: &str, fields: I, now: SystemTime) -> FormCheck {
+        let honeypot = self.field_name(session);
+        let mut token = None;
+        for (name, value) in fields {
+            if name == honeypot && !value.trim().is_empty() {
+                return FormCheck::FilledHoneypot;
+            }
+            if name == TOKEN_FIELD {
+                token = Some(value);
+            }
+        }
+        match self.timer.verify_at(session, token.unwrap_or(""), now) {
+            TimingCheck::Passed(_) => FormCheck::Passed,
+            TimingCheck::TooFast(elapsed) => FormCheck::TooFast(elapsed),
+            TimingCheck::Missing => FormCheck::MissingToken,
+            TimingCheck::Invalid => FormCheck::InvalidToken,
+            TimingCheck::Expired => FormCheck::Expired,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn accepts_humans_and_rejects_tampering() {
+        let honeypot = FormHoneypot::new(b"secret").min_fill_time(Duration::from_secs(3)).max_age(Duration::from_secs(3600));
+        let served = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let field = honeypot.issue_at("s1", served);
+        let form = |token: &str| vec![(TOKEN_FIELD.to_string(), token.to_string()), (field.name.clone(), String::new())];
+        let verify = |token: &str, after: u64| {
+            let form = form(token);
+            honeypot.verify_at("s1", form.iter().map(|(n, v)| (n.as_str(), v.as_str())), served + Duration::from_secs(after))
+        };
+
+        assert_eq!(verify(&field.token, 10), FormCheck::Passed);
+        assert_eq!(verify(&field.token, 1), FormCheck::TooFast(Duration::from_secs(1)));
+        assert_eq!(verify(&field.token, 3601), FormCheck::Expired);
+        assert_eq!(verify("", 10), FormCheck::MissingToken);
+        let (time, mac) = field.token.split_once('.').unwrap();
+        assert_eq!(verify(&format!("{}.{}", time.parse::<u64>().unwrap() - 60, mac), 10), FormCheck::InvalidToken);
+        assert_eq!(verify("garbage", 10), FormCheck::InvalidToken);
+        assert_eq!(FormHoneypot::new(b"other").verify_at("s1", [(TOKEN_FIELD, field.token.as_str())], served + Duration::from_secs(10)), FormCheck::InvalidToken);
+    }
+
+    #[test]
+    fn field_names_depend_on_session_and_secret() {
+        let honeypot = FormHoneypot::new(b"secret");
+        assert_eq!(honeypot.field_name("a"), honeypot.field_name("a"));
+        assert_ne!(honeypot.field_name("a"), honeypot.field_name("b"));
+        assert_ne!(honeypot.field_name("a"), FormHoneypot::new(b"other").field_name("a"));
+        assert_eq!(honeypot.verify("a", [(TOKEN_FIELD, "1.x"), (honeypot.field_name("a").as_str(), " ")]), FormCheck::InvalidToken);
+        assert_eq!(FormCheck::TooFast(Duration::ZERO).verdict(), Verdict::Bot);
+        assert_eq!(FormCheck::Expired.verdict(), Verdict::Suspicious);
+    }
+}
@@ -0,0 +1,187 @@
+// Minimal blocking HTTP client used by the webhook notifier, the feed refreshers and the
+// CAPTCHA verifiers, built on `ureq`.
+//
+// `http://` and `https://` URLs are supported, TLS is done by rustls with the Mozilla root
+// certificates bundled, so no system TLS library or local proxy is needed. A request may take
+// the timeout as a whole and its response at most `MAX_RESPONSE` bytes: a slow or endless
+// response ends with an error rather than holding the caller's thread. Redirects are not
+// followed.
+
+use std::error::Error as _;
+use std::io::{self, Read};
+use std::time::Duration;
+
+/// Largest response body read.
+const MAX_RESPONSE: usize = 16 * 1024 * 1024;
+
+/// A parsed `http://` or `https://host[:port]/path` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HttpUrl {
+    pub https: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl HttpUrl {
+    pub(crate) fn parse(url: &str) -> io::Result<Self> {
+        let (https, rest) = match (url.strip_prefix("https://"), url.strip_prefix("http://")) {
+            (Some(rest), _) => (true, rest),
+            (None, Some(rest)) => (false, rest),
+            (None, None) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("only http:// and https:// URLs are supported: {}", url)));
+            }
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port
+                    .parse()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid port in URL: {}", url)))?;
+                (host, port)
+            }
+            None => (authority, if https { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("missing host in URL: {}", url)));
+        }
+        Ok(HttpUrl { https, host: host.to_string(), port, path: path.to_string() })
+    }
+}
+
+/// Sends a POST request and returns the response status code.
+pub(crate) fn post(url: &str, content_type: &str, body: &[u8], timeout: Duration) -> io::Result<u16> {
+    request("POST", url, Some((content_type, body)), timeout).map(|(status, _)| status)
+}
+
+/// Sends a POST request and returns the body of a `2xx` response, other statuses are errors.
+pub(crate) fn post_for_body(url: &str, content_type: &str, body: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+    let response = request("POST", url, Some((content_type, body)), timeout)?;
+    success_body("POST", url, response)
+}
+
+/// Sends a GET request and returns the body of a `2xx` response, other statuses are errors.
+pub(crate) fn get(url: &str, timeout: Duration) -> io::Result<Vec<u8>> {
+    let response = request("GET", url, None, timeout)?;
+    success_body("GET", url, response)
+}
+
+fn success_body(method: &str, url: &str, (status, body): (u16, Vec<u8>)) -> io::Result<Vec<u8>> {
+    if !(200..300).contains(&status) {
+        return Err(io::Error::other(format!("{} {} returned status {}", method, url, status)));
+    }
+    Ok(body)
+}
+
+/// Sends a request and returns the status and body of the response, `timeout` bounds the whole
+/// exchange.
+fn request(method: &str, url: &str, body: Option<(&str, &[u8])>, timeout: Duration) -> io::Result<(u16, Vec<u8>)> {
+    HttpUrl::parse(url)?;
+    let agent = ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .redirects(0)
+        .user_agent(&format!("BotGuardLib/{}", env!("CARGO_PKG_VERSION")))
+        .build();
+    let request = agent.request(method, url);
+    let response = match body {
+        Some((content_type, body)) => request.set("Content-Type", content_type).send_bytes(body),
+        None => request.call(),
+    };
+    let response = match response {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(ureq::Error::Transport(transport)) => return Err(transport_error(transport)),
+    };
+    let status = response.status();
+    if response.header("content-length").and_then(|length| length.trim().parse::<usize>().ok()).is_some_and(|length| length > MAX_RESPONSE) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    response.into_reader().take(MAX_RESPONSE as u64 + 1).read_to_end(&mut body)?;
+    if body.len() > MAX_RESPONSE {
+        return Err(too_large());
+    }
+    Ok((status, body))
+}
+
+/// Keeps the kind of the IO error behind a failed request, e.g. `TimedOut`.
+fn transport_error(transport: ureq::Transport) -> io::Error {
+    let kind = transport.source().and_then(|source| source.downcast_ref::<io::Error>()).map_or(io::ErrorKind::Other, io::Error::kind);
+    io::Error::new(kind, transport.to_string())
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("HTTP response exceeds {} bytes", MAX_RESPONSE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::time::Instant;
+
+    #[test]
+    fn parse_urls() {
+        assert_eq!(
+            HttpUrl::parse("http://siem.local:8080/hooks/bots").unwrap(),
+            HttpUrl { https: false, host: "siem.local".into(), port: 8080, path: "/hooks/bots".into() }
+        );
+        assert_eq!(HttpUrl::parse("http://example.com").unwrap().path, "/");
+        assert_eq!(HttpUrl::parse("https://check.torproject.org/torbulkexitlist").unwrap().port, 443);
+        assert!(HttpUrl::parse("ftp://example.com/").is_err());
+        assert!(HttpUrl::parse("https://:443/").is_err());
+    }
+
+    /// Serves `response` to one client in pieces, `pause` apart, and keeps the connection open.
+    fn serve(response: Vec<u8>, piece: usize, pause: Duration) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]);
+            for part in response.chunks(piece) {
+                if stream.write_all(part).is_err() {
+                    return;
+                }
+                std::thread::sleep(pause);
+            }
+            std::thread::sleep(Duration::from_secs(5));
+        });
+        url
+    }
+
+    #[test]
+    fn reads_statuses_and_bodies() {
+        let url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello, ignored".to_vec(), 1024, Duration::ZERO);
+        assert_eq!(get(&url, Duration::from_secs(2)).unwrap(), b"hello");
+        let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\n\r\n";
+        let url = serve(chunked.to_vec(), 1024, Duration::ZERO);
+        assert_eq!(post_for_body(&url, "text/plain", b"hi", Duration::from_secs(2)).unwrap(), b"hello, world");
+        let url = serve(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n".to_vec(), 1024, Duration::ZERO);
+        assert_eq!(post(&url, "text/plain", b"hi", Duration::from_secs(2)).unwrap(), 503);
+        let url = serve(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(), 1024, Duration::ZERO);
+        assert!(get(&url, Duration::from_secs(2)).unwrap_err().to_string().contains("returned status 404"));
+    }
+
+    #[test]
+    fn bounds_responses() {
+        let url = serve(format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", MAX_RESPONSE + 1).into_bytes(), 1024, Duration::ZERO);
+        assert_eq!(get(&url, Duration::from_secs(2)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // a trickle that never stalls long enough for a read timeout still ends at the deadline
+        let started = Instant::now();
+        let url = serve([b"HTTP/1.1 200 OK\r\n\r\n".as_slice(), &[b'x'; 100]].concat(), 1, Duration::from_millis(20));
+        assert_eq!(get(&url, Duration::from_millis(300)).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn speaks_tls_to_https_urls() {
+        // a plain HTTP server answers the TLS handshake with garbage
+        let url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello".to_vec(), 1024, Duration::ZERO).replace("http://", "https://");
+        let error = get(&url, Duration::from_secs(2)).unwrap_err();
+        assert!(error.to_string().contains("tls connection init failed"), "{}", error);
+    }
+}
@@ -0,0 +1,204 @@
+// HTTP/2 connection fingerprints in the format popularized by Akamai: the SETTINGS of the
+// client's first frame, its WINDOW_UPDATE increment, the PRIORITY frames it sends and the order
+// of its pseudo-headers, like `1:65536;2:0;4:6291456;6:262144|15663105|0|m,a,s,p` for Chrome.
+//
+// They are computed by whatever terminates HTTP/2, this module only compares them with known
+// clients. Copying a browser user-agent is one line of code, sending a browser's HTTP/2 frames
+// takes a different HTTP stack.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{RwLock, RwLockReadGuard};
+
+/// Fingerprints of recent releases, priority frames left out.
+const BUNDLED: &[(&str, &str, ProfileKind)] = &[
+    ("1:65536;2:0;4:6291456;6:262144|15663105|0|m,a,s,p", "chrome", ProfileKind::Browser),
+    ("1:65536;2:0;4:131072;5:16384|12517377|0|m,p,a,s", "firefox", ProfileKind::Browser),
+    ("2:0;4:4194304;3:100|10485760|0|m,s,p,a", "safari", ProfileKind::Browser),
+    ("2:0;3:100;4:2097152;9:1|10420225|0|m,s,a,p", "safari", ProfileKind::Browser),
+    ("2:0;4:4194304;6:10485760|1073741824|0|m,a,s,p", "go-http-client", ProfileKind::Automation),
+    ("3:100;4:10485760;2:0|1048576000|0|m,p,s,a", "curl", ProfileKind::Automation),
+    ("4:16777216|16711681|0|m,p,a,s", "okhttp", ProfileKind::Automation),
+];
+
+/// A parsed HTTP/2 fingerprint.
+///
+/// ```
+/// use BotGuardLib::http2::Http2Fingerprint;
+///
+/// let chrome: Http2Fingerprint = "1:65536;2:0;4:6291456;6:262144|15663105|0|m,a,s,p".parse().unwrap();
+/// assert_eq!(chrome.settings[2], (4, 6291456));
+/// assert_eq!((chrome.window_update, chrome.pseudo_headers.as_str()), (15663105, "m,a,s,p"));
+/// assert!("1:65536|x|0|m,a,s,p".parse::<Http2Fingerprint>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http2Fingerprint {
+    /// Identifier and value of each setting, in the order they were sent.
+    pub settings: Vec<(u16, u32)>,
+    /// Increment of the connection window, `0` if the client sent no WINDOW_UPDATE.
+    pub window_update: u32,
+    /// The PRIORITY frames as written in the fingerprint, `0` if there were none.
+    pub priority: String,
+    /// Order of the `:method`, `:authority`, `:scheme` and `:path` pseudo-headers, like `m,a,s,p`.
+    pub pseudo_headers: String,
+}
+
+impl Http2Fingerprint {
+    /// Whether both come from the same HTTP/2 stack. Priority frames are not compared, browsers
+    /// changed them between releases without changing anything else.
+    pub fn same_client(&self, other: &Http2Fingerprint) -> bool {
+        self.settings == other.settings && self.window_update == other.window_update && self.pseudo_headers == other.pseudo_headers
+    }
+}
+
+impl FromStr for Http2Fingerprint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.trim().split('|').collect::<Vec<_>>();
+        let [settings, window_update, priority, pseudo_headers] = parts[..] else {
+            return Err(format!("expected 4 parts separated by `|` in {:?}", s));
+        };
+        let settings = settings
+            .split([';', ','])
+            .filter(|setting| !setting.is_empty())
+            .map(|setting| {
+                let (id, value) = setting.split_once(':').ok_or_else(|| format!("setting {:?} is not `id:value`", setting))?;
+                match (id.parse(), value.parse()) {
+                    (Ok(id), Ok(value)) => Ok((id, value)),
+                    _ => Err(format!("invalid setting {:?}", setting)),
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let window_update = window_update.parse().map_err(|_| format!("invalid window update {:?}", window_update))?;
+        if pseudo_headers.split(',').any(|header| !matches!(header, "m" | "a" | "s" | "p")) {
+            return Err(format!("invalid pseudo-header order {:?}", pseudo_headers));
+        }
+        Ok(Http2Fingerprint { settings, window_update, priority: priority.to_string(), pseudo_headers: pseudo_headers.to_string() })
+    }
+}
+
+impl fmt::Display for Http2Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let settings = self.settings.iter().map(|(id, value)| format!("{}:{}", id, value)).collect::<Vec<_>>();
+        write!(f, "{}|{}|{}|{}", settings.join(";"), self.window_update, self.priority, self.pseudo_headers)
+    }
+}
+
+/// What kind of client a profile belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProfileKind {
+    Browser,
+    /// An HTTP library or tool, never a browser.
+    Automation,
+}
+
+/// The client a fingerprint was recognized as, see [`Http2Profiles::identify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http2Profile {
+    /// Like `chrome` or `curl`.
+    pub client: String,
+    pub kind: ProfileKind,
+}
+
+/// Known HTTP/2 fingerprints of browsers and automation clients.
+///
+/// ```
+/// use BotGuardLib::http2::{Http2Profiles, ProfileKind};
+///
+/// let profiles = Http2Profiles::bundled();
+/// let go = profiles.identify(&"2:0;4:4194304;6:10485760|1073741824|0|m,a,s,p".parse().unwrap()).unwrap();
+/// assert_eq!((go.client.as_str(), go.kind), ("go-http-client", ProfileKind::Automation));
+///
+/// let headless = "1:65536;2:0;4:6291456;6:262144|15663105|0|m,s,a,p".parse().unwrap();
+/// assert_eq!(profiles.identify(&headless), None);
+/// profiles.register(headless.clone(), "acme-headless", ProfileKind::Automation);
+/// assert_eq!(profiles.identify(&headless).unwrap().client, "acme-headless");
+/// ```
+#[derive(Debug, Default)]
+pub struct Http2Profiles {
+    known: RwLock<Vec<(Http2Fingerprint, Http2Profile)>>,
+}
+
+impl Http2Profiles {
+    /// No profiles at all.
+    pub fn new() -> Self {
+        Http2Profiles::default()
+    }
+
+    /// The profiles of current browsers and common HTTP libraries.
+    pub fn bundled() -> Self {
+        let profiles = Http2Profiles::new();
+        for (fingerprint, client, kind) in BUNDLED {
+            if let Ok(fingerprint) = fingerprint.parse() {
+                profiles.register(fingerprint, client, *kind);
+            }
+        }
+        profiles
+    }
+
+    /// Adds a profile, replacing the one of the same client stack.
+    pub fn register(&self, fingerprint: Http2Fingerprint, client: &str, kind: ProfileKind) {
+        let mut known = self.known.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        known.retain(|(existing, _)| !existing.same_client(&fingerprint));
+        known.push((fingerprint, Http2Profile { client: client.to_string(), kind }));
+    }
+
+    /// The profile of the same client stack, if one is known.
+    pub fn identify(&self, fingerprint: &Http2Fingerprint) -> Option<Http2Profile> {
+        self.known().iter().find(|(known, _)| known.same_client(fingerprint)).map(|(_, profile)| profile.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.known().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.known().is_empty()
+    }
+
+    fn known(&self) -> RwLockReadGuard<'_, Vec<(Http2Fingerprint, Http2Profile)>> {
+        self.known.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The browser family a user-agent claims, named like the bundled browser profiles.
+pub(crate) fn claimed_browser(user_agent: &str) -> Option<&'static str> {
+    let ua = user_agent.to_ascii_lowercase();
+    if !ua.starts_with("mozilla/") {
+        None
+    } else if ua.contains("firefox/") {
+        Some("firefox")
+    } else if ua.contains("chrome/") {
+        Some("chrome")
+    } else if ua.contains("safari/") {
+        Some("safari")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprints_round_trip() {
+        let firefox = "1:65536;2:0;4:131072;5:16384|12517377|3:0:0:201,5:0:0:101|m,p,a,s";
+        let parsed = firefox.parse::<Http2Fingerprint>().unwrap();
+        assert_eq!(parsed.to_string(), firefox);
+        assert_eq!(Http2Profiles::bundled().identify(&parsed).map(|profile| profile.client), Some("firefox".to_string()));
+        assert!("1:65536|0|0".parse::<Http2Fingerprint>().is_err());
+        assert!("1:65536|0|0|m,x".parse::<Http2Fingerprint>().is_err());
+        assert!("1=65536|0|0|m,a,s,p".parse::<Http2Fingerprint>().is_err());
+    }
+
+    #[test]
+    fn claimed_browsers() {
+        assert_eq!(claimed_browser("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0"), Some("firefox"));
+        assert_eq!(claimed_browser("Mozilla/5.0 (Macintosh) AppleWebKit/605.1.15 Version/17.4 Safari/605.1.15"), Some("safari"));
+        assert_eq!(claimed_browser("Mozilla/5.0 (Windows NT 10.0) AppleWebKit/537.36 Chrome/122.0 Safari/537.36 Edg/122.0"), Some("chrome"));
+        assert_eq!(claimed_browser("curl/8.4.0"), None);
+        assert_eq!(Http2Profiles::bundled().len(), BUNDLED.len());
+    }
+}
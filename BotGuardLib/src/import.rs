@@ -0,0 +1,446 @@
+// Pattern lists kept outside the entry format: CSV exported from a spreadsheet and YAML from a
+// config repository, see `BotDetector::from_csv` and `BotDetector::from_yaml`.
+//
+// The YAML reader covers the block style such lists are written in: mappings, sequences, plain
+// and quoted scalars and comments. Flow collections, anchors and multi-line scalars are
+// rejected with the line they appear on instead of being misread.
+
+use std::collections::HashMap;
+
+use crate::{BotDetector, BotGuardError, BundleVersion, DetectorOptions, EntryGroup, CUSTOM_GROUP};
+
+/// A line number and what is wrong on it.
+type ParseError = (usize, String);
+
+/// The groups of a list in declaration order, remembering the line of every pattern.
+pub(crate) struct Imported {
+    format: &'static str,
+    groups: Vec<EntryGroup>,
+    lines: HashMap<String, usize>,
+}
+
+impl Imported {
+    fn new(format: &'static str) -> Self {
+        Imported { format, groups: Vec::new(), lines: HashMap::new() }
+    }
+
+    /// Compiles the patterns, errors of a pattern point at its line.
+    pub(crate) fn into_detector(mut self, version: BundleVersion) -> Result<BotDetector, BotGuardError> {
+        let groups = std::mem::take(&mut self.groups);
+        BotDetector::from_groups(groups, DetectorOptions::default(), version).map_err(|e| self.locate(e))
+    }
+
+    fn error(&self, line: usize, reason: impl Into<String>) -> BotGuardError {
+        BotGuardError::InvalidImport { format: self.format.to_string(), line, reason: reason.into() }
+    }
+
+    fn add(&mut self, line: usize, group: &str, pattern: &str, weight: Option<f32>) -> Result<(), BotGuardError> {
+        let group = if group.is_empty() { CUSTOM_GROUP.to_string() } else { group.to_ascii_lowercase() };
+        if !group.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+            return Err(self.error(line, format!("invalid category {:?}, use letters, digits, - and _", group)));
+        }
+        if pattern.is_empty() {
+            return Err(self.error(line, "empty pattern"));
+        }
+        if let Some(weight) = weight.filter(|weight| !(0.0..=1.0).contains(weight)) {
+            return Err(self.error(line, format!("weight {} is not between 0.0 and 1.0", weight)));
+        }
+        let index = match self.groups.iter().position(|(name, _)| *name == group) {
+            Some(index) => index,
+            None => {
+                self.groups.push((group, Vec::new()));
+                self.groups.len() - 1
+            }
+        };
+        self.groups[index].1.push((pattern.to_string(), weight));
+        self.lines.entry(pattern.to_ascii_lowercase()).or_insert(line);
+        Ok(())
+    }
+
+    fn weight(&self, line: usize, weight: &str) -> Result<Option<f32>, BotGuardError> {
+        match weight.trim() {
+            "" => Ok(None),
+            weight => weight.parse().map(Some).map_err(|_| self.error(line, format!("invalid weight {:?}", weight))),
+        }
+    }
+
+    fn locate(&self, error: BotGuardError) -> BotGuardError {
+        let pattern = match &error {
+            BotGuardError::InvalidPattern { pattern, .. } | BotGuardError::PatternTooLarge { pattern, .. } => pattern,
+            _ => return error,
+        };
+        match self.lines.get(&pattern.to_ascii_lowercase()) {
+            Some(line) => self.error(*line, error.to_string()),
+            None => error,
+        }
+    }
+}
+
+/// Reads `pattern,category,weight` rows. A first row naming the columns may list them in any
+/// order, without it the columns are taken in this order. Only `pattern` is required.
+pub(crate) fn csv(text: &str) -> Result<Imported, BotGuardError> {
+    let mut list = Imported::new("CSV");
+    let mut columns = None;
+    for (line, record) in csv_records(text).map_err(|(line, reason)| list.error(line, reason))? {
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let (pattern, category, weight) = *columns.get_or_insert_with(|| {
+            let names = record.iter().map(|field| field.trim().to_ascii_lowercase()).collect::<Vec<_>>();
+            let find = |name: &str| names.iter().position(|field| field == name);
+            match find("pattern") {
+                Some(pattern) => (pattern, find("category"), find("weight")),
+                None => (0, Some(1), Some(2)),
+            }
+        });
+        // the header row itself
+        if record.get(pattern).is_some_and(|field| field.trim().eq_ignore_ascii_case("pattern")) {
+            continue;
+        }
+        let field = |index: Option<usize>| index.and_then(|index| record.get(index)).map_or("", |field| field.trim());
+        let weight = list.weight(line, field(weight))?;
+        list.add(line, field(category), field(Some(pattern)), weight)?;
+    }
+    Ok(list)
+}
+
+/// Splits RFC 4180 CSV into records with the line each starts on.
+fn csv_records(text: &str) -> Result<Vec<(usize, Vec<String>)>, ParseError> {
+    let mut records = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut fields = Vec::new();
+        let mut chars = line.chars().peekable();
+        loop {
+            let mut field = String::new();
+            while chars.peek().is_some_and(|c| *c == ' ' || *c == '\t') {
+                field.push(chars.next().unwrap_or(' '));
+            }
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                field.clear();
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => field.push(c),
+                        None => return Err((line_number, "unterminated quoted field, patterns cannot span lines".to_string())),
+                    }
+                }
+                while chars.peek().is_some_and(|c| *c == ' ' || *c == '\t') {
+                    chars.next();
+                }
+                if chars.peek().is_some_and(|c| *c != ',') {
+                    return Err((line_number, "unexpected text after a quoted field".to_string()));
+                }
+            } else {
+                while let Some(c) = chars.next_if(|c| *c != ',') {
+                    field.push(c);
+                }
+            }
+            fields.push(field);
+            if chars.next().is_none() {
+                break;
+            }
+        }
+        records.push((line_number, fields));
+    }
+    Ok(records)
+}
+
+/// A YAML node with the line it starts on.
+#[derive(Debug)]
+enum Yaml {
+    Scalar(usize, String),
+    Seq(usize, Vec<Yaml>),
+    Map(usize, Vec<(String, Yaml)>),
+}
+
+impl Yaml {
+    fn line(&self) -> usize {
+        match self {
+            Yaml::Scalar(line, _) | Yaml::Seq(line, _) | Yaml::Map(line, _) => *line,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Yaml> {
+        match self {
+            Yaml::Map(_, entries) => entries.iter().find(|(k, _)| k == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+/// Reads either the document of `ExportFormat::Yaml`, a `groups` sequence of `name` and
+/// `patterns`, or a mapping from category to patterns. Patterns are strings or mappings with a
+/// `pattern` and a `weight`.
+pub(crate) fn yaml(text: &str) -> Result<Imported, BotGuardError> {
+    let mut list = Imported::new("YAML");
+    let mut lines = yaml_lines(text).map_err(|(line, reason)| list.error(line, reason))?;
+    if lines.is_empty() {
+        return Ok(list);
+    }
+    let mut i = 0;
+    let indent = lines[0].1;
+    let root = parse_node(&mut lines, &mut i, indent).map_err(|(line, reason)| list.error(line, reason))?;
+    if let Some((line, _, _)) = lines.get(i) {
+        return Err(list.error(*line, "unexpected indentation"));
+    }
+
+    let groups = match (&root, root.get("groups")) {
+        (_, Some(Yaml::Seq(_, groups))) => groups
+            .iter()
+            .map(|group| match (group.get("name"), group.get("patterns")) {
+                (Some(Yaml::Scalar(_, name)), Some(patterns)) => Ok((name.as_str(), patterns)),
+                _ => Err(list.error(group.line(), "a group needs a name and patterns")),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        (Yaml::Map(_, entries), None) => entries.iter().map(|(name, patterns)| (name.as_str(), patterns)).collect(),
+        _ => return Err(list.error(root.line(), "expected a mapping of categories to patterns or a groups sequence")),
+    };
+    for (group, patterns) in groups {
+        let Yaml::Seq(_, patterns) = patterns else { return Err(list.error(patterns.line(), "patterns must be a sequence")) };
+        for entry in patterns {
+            match entry {
+                Yaml::Scalar(line, pattern) => list.add(*line, group, pattern, None)?,
+                Yaml::Map(line, _) => {
+                    let Some(Yaml::Scalar(_, pattern)) = entry.get("pattern") else { return Err(list.error(*line, "a pattern entry needs a pattern")) };
+                    let weight = match entry.get("weight") {
+                        Some(Yaml::Scalar(line, weight)) => list.weight(*line, weight)?,
+                        Some(other) => return Err(list.error(other.line(), "weight must be a number")),
+                        None => None,
+                    };
+                    list.add(*line, group, pattern, weight)?;
+                }
+                Yaml::Seq(line, _) => return Err(list.error(*line, "a pattern cannot be a sequence")),
+            }
+        }
+    }
+    Ok(list)
+}
+
+/// Non-empty lines as line number, indentation and content without comments.
+fn yaml_lines(text: &str) -> Result<Vec<(usize, usize, String)>, ParseError> {
+    let mut lines = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        if line.starts_with('\t') || line.trim_start_matches(' ').starts_with('\t') {
+            return Err((line_number, "tabs cannot indent YAML".to_string()));
+        }
+        let content = strip_comment(line).trim_end();
+        let indent = content.len() - content.trim_start().len();
+        let content = content.trim_start();
+        if content.is_empty() || content == "---" {
+            continue;
+        }
+        if content == "..." {
+            break;
+        }
+        lines.push((line_number, indent, content.to_string()));
+    }
+    Ok(lines)
+}
+
+/// A line without its comment, `#` only starts one outside of quotes and after whitespace.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') if previous == ' ' || previous == '-' || previous == ':' => quote = Some(c),
+            (None, '#') if previous.is_whitespace() => return &line[..i],
+            (Some('"'), '\\') if previous == '\\' => {
+                previous = ' ';
+                continue;
+            }
+            (Some(q), c) if c == q && !(q == '"' && previous == '\\') => quote = None,
+            _ => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+fn parse_node(lines: &mut [(usize, usize, String)], i: &mut usize, indent: usize) -> Result<Yaml, ParseError> {
+    let (line, _, content) = lines[*i].clone();
+    if content == "-" || content.starts_with("- ") {
+        let mut items = Vec::new();
+        while let Some((item_line, item_indent, item)) = lines.get(*i).cloned() {
+            if item_indent != indent || !(item == "-" || item.starts_with("- ")) {
+                break;
+            }
+            let rest = item[1..].trim_start();
+            if rest.is_empty() {
+                *i += 1;
+                match lines.get(*i) {
+                    Some((_, child, _)) if *child > indent => {
+                        let child = *child;
+                        items.push(parse_node(lines, i, child)?);
+                    }
+                    _ => items.push(Yaml::Scalar(item_line, String::new())),
+                }
+            } else {
+                // the item continues as a node indented to where its content starts
+                let offset = item.len() - rest.len();
+                lines[*i] = (item_line, indent + offset, rest.to_string());
+                items.push(parse_node(lines, i, indent + offset)?);
+            }
+        }
+        return Ok(Yaml::Seq(line, items));
+    }
+    if split_key(&content).is_some() {
+        let mut entries = Vec::new();
+        while let Some((entry_line, entry_indent, entry)) = lines.get(*i).cloned() {
+            if entry_indent != indent {
+                if entry_indent > indent {
+                    return Err((entry_line, "unexpected indentation".to_string()));
+                }
+                break;
+            }
+            let Some((key, value)) = split_key(&entry) else { return Err((entry_line, "expected a key followed by a colon".to_string())) };
+            let key = scalar(entry_line, key)?;
+            if entries.iter().any(|(existing, _)| *existing == key) {
+                return Err((entry_line, format!("duplicate key {:?}", key)));
+            }
+            *i += 1;
+            let value = if !value.is_empty() {
+                Yaml::Scalar(entry_line, scalar(entry_line, value)?)
+            } else {
+                match lines.get(*i) {
+                    Some((_, child, _)) if *child > indent => {
+                        let child = *child;
+                        parse_node(lines, i, child)?
+                    }
+                    // a sequence may sit at the indentation of its key
+                    Some((_, child, next)) if *child == indent && (next == "-" || next.starts_with("- ")) => parse_node(lines, i, indent)?,
+                    _ => Yaml::Scalar(entry_line, String::new()),
+                }
+            };
+            entries.push((key, value));
+        }
+        return Ok(Yaml::Map(line, entries));
+    }
+    *i += 1;
+    Ok(Yaml::Scalar(line, scalar(line, &content)?))
+}
+
+/// Splits `key: value` at the first colon outside of quotes followed by a space or the end.
+fn split_key(content: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in content.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') if i == 0 => quote = Some(c),
+            (None, ':') if content[i + 1..].is_empty() || content[i + 1..].starts_with(' ') => {
+                return Some((content[..i].trim_end(), content[i + 1..].trim()));
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+    None
+}
+
+/// The value of a plain, single-quoted or double-quoted scalar.
+fn scalar(line: usize, text: &str) -> Result<String, ParseError> {
+    let unsupported = |what: &str| Err((line, format!("{} are not supported", what)));
+    match text.chars().next() {
+        Some('[' | '{') => return unsupported("flow collections"),
+        Some('&' | '*') => return unsupported("anchors and aliases"),
+        Some('|' | '>') => return unsupported("block scalars"),
+        _ => {}
+    }
+    if let Some(inner) = text.strip_prefix('\'') {
+        let inner = inner.strip_suffix('\'').ok_or((line, "unterminated single-quoted string".to_string()))?;
+        return Ok(inner.replace("''", "'"));
+    }
+    let Some(inner) = text.strip_prefix('"') else { return Ok(text.to_string()) };
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if chars.as_str().trim().is_empty() => return Ok(out),
+            '"' => return Err((line, "unexpected text after a quoted string".to_string())),
+            '\\' => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('u') => {
+                    let hex = chars.by_ref().take(4).collect::<String>();
+                    let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or((line, format!("invalid escape \\u{}", hex)))?;
+                    out.push(c);
+                }
+                Some(c @ ('"' | '\\' | '/')) => out.push(c),
+                _ => return Err((line, "invalid escape in double-quoted string".to_string())),
+            },
+            c => out.push(c),
+        }
+    }
+    Err((line, "unterminated double-quoted string".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::ExportFormat;
+    use crate::BotDetector;
+
+    #[test]
+    fn reads_csv_rows() {
+        let csv = "weight,pattern,category\n\n1.0,googlebot,search\n0.3,\"python-requests/\",http-clients\n,\"^foo, \"\"bar\"\"\",\n";
+        let detector = BotDetector::from_csv(csv).unwrap();
+        assert_eq!(detector.classify("Googlebot/2.1"), Some("search"));
+        assert_eq!(detector.score("python-requests/2.31"), 0.3);
+        assert!(detector.contains_pattern("^foo, \"bar\""));
+        assert_eq!(detector.groups().collect::<Vec<_>>(), ["search", "http-clients", "custom"]);
+
+        let headerless = BotDetector::from_csv("# exported 2024-05-01\ncurl/,tools\nwget/").unwrap();
+        assert_eq!(headerless.classify("curl/8.0"), Some("tools"));
+        assert_eq!(headerless.classify("Wget/1.21"), Some("custom"));
+    }
+
+    #[test]
+    fn reports_csv_rows_in_errors() {
+        let error = |csv: &str| BotDetector::from_csv(csv).unwrap_err().to_string();
+        assert_eq!(error("pattern,weight\nbot,1.0\ncurl/,high"), "invalid CSV pattern list on line 3: invalid weight \"high\"");
+        assert_eq!(error("a,b,2.0"), "invalid CSV pattern list on line 1: weight 2 is not between 0.0 and 1.0");
+        assert_eq!(error("x\n\"unterminated,a"), "invalid CSV pattern list on line 2: unterminated quoted field, patterns cannot span lines");
+        assert_eq!(error("ok,my group"), "invalid CSV pattern list on line 1: invalid category \"my group\", use letters, digits, - and _");
+        assert!(error("fine\n(unclosed,a").starts_with("invalid CSV pattern list on line 2: invalid bot pattern \"(unclosed\""), "{}", error("fine\n(unclosed,a"));
+    }
+
+    #[test]
+    fn reads_yaml_lists() {
+        let yaml = "# bots\nsearch:\n  - googlebot   # Google\n  - 'bing''s bot'\nhttp-clients:\n- pattern: \"python-requests/\"\n  weight: 0.3\n- \"^curl/ #1\"\n";
+        let detector = BotDetector::from_yaml(yaml).unwrap();
+        assert_eq!(detector.classify("Googlebot/2.1"), Some("search"));
+        assert!(detector.contains_pattern("bing's bot"));
+        assert!(detector.contains_pattern("^curl/ #1"));
+        assert_eq!(detector.score("python-requests/2.31"), 0.3);
+
+        // the export format reads back into the same patterns
+        let exported = detector.export(ExportFormat::Yaml);
+        assert_eq!(BotDetector::from_yaml(&exported).unwrap().export(ExportFormat::Regex), detector.export(ExportFormat::Regex));
+        assert!(BotDetector::from_yaml("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_yaml_lines_in_errors() {
+        let error = |yaml: &str| BotDetector::from_yaml(yaml).unwrap_err().to_string();
+        assert_eq!(error("search:\n  - googlebot\n    - nested"), "invalid YAML pattern list on line 3: unexpected indentation");
+        assert_eq!(error("search: [googlebot]"), "invalid YAML pattern list on line 1: flow collections are not supported");
+        assert_eq!(error("search:\n  - pattern: x\n    weight: lots"), "invalid YAML pattern list on line 3: invalid weight \"lots\"");
+        assert_eq!(error("- googlebot"), "invalid YAML pattern list on line 1: expected a mapping of categories to patterns or a groups sequence");
+        assert_eq!(error("a:\n  - \"x\\q\""), "invalid YAML pattern list on line 2: invalid escape in double-quoted string");
+        assert_eq!(error("a:\n  - ok\nb:\n  - (bad"), "invalid YAML pattern list on line 4: invalid bot pattern \"(bad\": regex parse error:\n    (bad\n    ^\nerror: unclosed group");
+    }
+}
@@ -0,0 +1,289 @@
+// CIDR networks and an interval set for looking up which listed network an address falls into.
+//
+// Shared by every IP-based signal: datacenter ranges, anonymizer feeds, reputation lists.
+// IPv4-mapped IPv6 addresses, as dual-stack listeners report IPv4 clients, are looked up as IPv4.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An IPv4 or IPv6 network in CIDR notation, e.g. `203.0.113.0/24` or `2001:db8::/32`.
+///
+/// A bare address is accepted as a single-host network.
+///
+/// ```
+/// use BotGuardLib::ip::IpNet;
+///
+/// let net: IpNet = "203.0.113.0/24".parse().unwrap();
+/// assert!(net.contains("203.0.113.77".parse().unwrap()));
+/// assert!(!net.contains("203.0.114.1".parse().unwrap()));
+/// assert_eq!("10.1.2.3".parse::<IpNet>().unwrap().to_string(), "10.1.2.3/32");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNet {
+    /// Returns `None` if the prefix length is longer than the address.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        (prefix_len <= max_prefix_len(addr)).then_some(IpNet { addr, prefix_len })
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Returns `true` if the address is inside the network, IPv4 and IPv6 never contain each other.
+    /// IPv4-mapped IPv6 addresses count as IPv4.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        let (start, end) = self.bounds();
+        match (self.addr, ip) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => (start..=end).contains(&to_u128(ip)),
+            _ => false,
+        }
+    }
+
+    /// First and last address of the network, as integers.
+    fn bounds(&self) -> (u128, u128) {
+        let host_bits = u32::from(max_prefix_len(self.addr) - self.prefix_len);
+        let host_mask = if host_bits == 0 { 0 } else { u128::MAX >> (128 - host_bits) };
+        let start = to_u128(self.addr) & !host_mask;
+        (start, start | host_mask)
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+        let addr = addr.parse::<IpAddr>().map_err(|_| format!("invalid network {:?}", s))?;
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse::<u8>().map_err(|_| format!("invalid prefix length in {:?}", s))?,
+            None => max_prefix_len(addr),
+        };
+        IpNet::new(addr, prefix_len).ok_or_else(|| format!("prefix length out of range in {:?}", s))
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+fn max_prefix_len(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// Maps an address to an integer, callers canonicalize IPv4-mapped IPv6 addresses first.
+fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u128::from(u32::from(v4)),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+/// Networks with a value each, looked up by address with a binary search.
+///
+/// When networks overlap, the most specific one wins. Lookups also scan the networks overlapping
+/// the candidate, which stays short for provider and blocklist feeds.
+///
+/// ```
+/// use BotGuardLib::ip::IpRangeSet;
+///
+/// let set = IpRangeSet::from_iter([
+///     ("10.0.0.0/8".parse().unwrap(), "internal"),
+///     ("10.9.0.0/16".parse().unwrap(), "lab"),
+/// ]);
+/// assert_eq!(set.get("10.9.1.1".parse().unwrap()), Some(&"lab"));
+/// assert_eq!(set.get("10.1.1.1".parse().unwrap()), Some(&"internal"));
+/// assert_eq!(set.get("::ffff:10.1.1.1".parse().unwrap()), Some(&"internal"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct IpRangeSet<T> {
+    v4: Ranges<T>,
+    v6: Ranges<T>,
+}
+
+#[derive(Debug, Clone)]
+struct Ranges<T> {
+    /// `(start, end, prefix_len, net, value)` sorted by start.
+    entries: Vec<(u128, u128, u8, IpNet, T)>,
+    /// `max_end[i]` is the largest end among `entries[..=i]`, bounding the backwards scan of lookups.
+    max_end: Vec<u128>,
+}
+
+impl<T> Default for Ranges<T> {
+    fn default() -> Self {
+        Ranges { entries: Vec::new(), max_end: Vec::new() }
+    }
+}
+
+impl<T> Ranges<T> {
+    fn rebuild(&mut self) {
+        self.entries.sort_by_key(|&(start, end, ..)| (start, end));
+        let mut max = 0;
+        self.max_end = self
+            .entries
+            .iter()
+            .map(|&(_, end, ..)| {
+                max = max.max(end);
+                max
+            })
+            .collect();
+    }
+
+    fn get(&self, ip: u128) -> Option<(&IpNet, &T)> {
+        let candidates = self.entries.partition_point(|&(start, ..)| start <= ip);
+        let mut best: Option<&(u128, u128, u8, IpNet, T)> = None;
+        for i in (0..candidates).rev() {
+            if self.max_end[i] < ip {
+                break;
+            }
+            let entry = &self.entries[i];
+            if entry.1 >= ip && best.is_none_or(|best| entry.2 > best.2) {
+                best = Some(entry);
+            }
+        }
+        best.map(|(_, _, _, net, value)| (net, value))
+    }
+}
+
+impl<T> Default for IpRangeSet<T> {
+    fn default() -> Self {
+        IpRangeSet { v4: Ranges::default(), v6: Ranges::default() }
+    }
+}
+
+impl<T> IpRangeSet<T> {
+    pub fn new() -> Self {
+        IpRangeSet::default()
+    }
+
+    /// Adds several networks at once, sorting only once.
+    pub fn extend<I: IntoIterator<Item = (IpNet, T)>>(&mut self, networks: I) {
+        for (net, value) in networks {
+            let (start, end) = net.bounds();
+            let ranges = match net.addr {
+                IpAddr::V4(_) => &mut self.v4,
+                IpAddr::V6(_) => &mut self.v6,
+            };
+            ranges.entries.push((start, end, net.prefix_len, net, value));
+        }
+        self.v4.rebuild();
+        self.v6.rebuild();
+    }
+
+    pub fn insert(&mut self, net: IpNet, value: T) {
+        self.extend([(net, value)]);
+    }
+
+    /// Removes every network whose value does not satisfy the predicate.
+    pub fn retain<F: FnMut(&IpNet, &T) -> bool>(&mut self, mut keep: F) {
+        self.v4.entries.retain(|(_, _, _, net, value)| keep(net, value));
+        self.v6.entries.retain(|(_, _, _, net, value)| keep(net, value));
+        self.v4.rebuild();
+        self.v6.rebuild();
+    }
+
+    /// Returns the value of the most specific network containing the address.
+    pub fn get(&self, ip: IpAddr) -> Option<&T> {
+        self.get_net(ip).map(|(_, value)| value)
+    }
+
+    /// Same as [`IpRangeSet::get`], together with the matching network.
+    pub fn get_net(&self, ip: IpAddr) -> Option<(&IpNet, &T)> {
+        let ip = ip.to_canonical();
+        match ip {
+            IpAddr::V4(_) => self.v4.get(to_u128(ip)),
+            IpAddr::V6(_) => self.v6.get(to_u128(ip)),
+        }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.get_net(ip).is_some()
+    }
+
+    /// Iterates over the networks, IPv4 first, each family in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (&IpNet, &T)> {
+        self.v4.entries.iter().chain(&self.v6.entries).map(|(_, _, _, net, value)| (net, value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.v4.entries.len() + self.v6.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> FromIterator<(IpNet, T)> for IpRangeSet<T> {
+    fn from_iter<I: IntoIterator<Item = (IpNet, T)>>(networks: I) -> Self {
+        let mut set = IpRangeSet::new();
+        set.extend(networks);
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_networks() {
+        let net: IpNet = "2001:db8::/32".parse().unwrap();
+        assert!(net.contains(ip("2001:db8:ffff::1")));
+        assert!(!net.contains(ip("2001:db9::1")));
+        assert!(!net.contains(ip("32.1.13.184")));
+        assert!("10.0.0.0/8".parse::<IpNet>().unwrap().contains(ip("::ffff:10.1.2.3")));
+        assert!("0.0.0.0/0".parse::<IpNet>().unwrap().contains(ip("255.255.255.255")));
+        assert!("::/0".parse::<IpNet>().unwrap().contains(ip("::1")));
+        assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+        assert!("10.0.0/8".parse::<IpNet>().is_err());
+        // host bits are ignored for matching
+        assert!("10.1.2.3/8".parse::<IpNet>().unwrap().contains(ip("10.200.0.1")));
+    }
+
+    #[test]
+    fn looks_up_overlapping_ranges() {
+        let mut set = IpRangeSet::new();
+        set.extend([
+            ("10.0.0.0/8".parse().unwrap(), 1),
+            ("10.0.0.0/16".parse().unwrap(), 2),
+            ("10.0.5.0/24".parse().unwrap(), 3),
+            ("11.0.0.0/8".parse().unwrap(), 4),
+            ("2001:db8::/32".parse().unwrap(), 5),
+        ]);
+        assert_eq!(set.get(ip("10.0.5.9")), Some(&3));
+        assert_eq!(set.get(ip("10.0.6.9")), Some(&2));
+        assert_eq!(set.get(ip("10.3.0.1")), Some(&1));
+        assert_eq!(set.get(ip("11.255.255.255")), Some(&4));
+        assert_eq!(set.get(ip("12.0.0.0")), None);
+        assert_eq!(set.get(ip("9.255.255.255")), None);
+        assert_eq!(set.get(ip("2001:db8::1")), Some(&5));
+        assert_eq!(set.len(), 5);
+
+        set.retain(|_, value| *value != 2);
+        assert_eq!(set.get(ip("10.0.6.9")), Some(&1));
+    }
+}
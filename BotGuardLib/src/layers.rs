@@ -0,0 +1,246 @@
+// A read-only base detector shared by many variants, each adding patterns of its own and hiding
+// base patterns it does not want, without copying or recompiling the base.
+//
+// Suppressed patterns stay in the base regexes. Only when the pattern the base reports is
+// suppressed are all matching base patterns looked up, with sets compiled on first need and shared
+// by the variants of the same base.
+
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
+
+use regex::RegexSet;
+
+use crate::group::PatternGroup;
+use crate::{score, BotDetector, BotGuardError, PatternMatch, Verdict, CUSTOM_GROUP, INLINE_USER_AGENT};
+
+/// Each base group as a set of its patterns, with the patterns in set order.
+type GroupSets = Vec<(RegexSet, Vec<String>)>;
+
+/// A shared base detector with an overlay of added patterns and suppressed base patterns.
+///
+/// Overlay groups are matched before the base groups and win ties, and the overlay uses the
+/// options of the base, like case sensitivity, thresholds and the match mode. No detection hooks
+/// are called.
+///
+/// ```
+/// use std::sync::Arc;
+/// use BotGuardLib::layers::LayeredDetector;
+/// use BotGuardLib::{BotDetector, Verdict};
+///
+/// let base = Arc::new(BotDetector::new("[crawlers]\ngooglebot\nbingbot\n[tools]\n^curl/"));
+/// let mut partner = LayeredDetector::new(Arc::clone(&base));
+/// partner.add_to_group("scrapers", &["^scrapy/"]).unwrap();
+/// assert_eq!(partner.suppress(&["^curl/", "unknown"]), 1);
+///
+/// assert_eq!(partner.check("curl/8.4"), Verdict::Human);
+/// assert_eq!(partner.classify("Scrapy/2.11"), Some("scrapers"));
+/// assert!(partner.check_bot("Googlebot/2.1"));
+/// assert!(base.check_bot("curl/8.4") && !base.check_bot("Scrapy/2.11"));
+///
+/// let mut other = partner.variant();
+/// assert!(other.check_bot("curl/8.4"));
+/// other.suppress(&["googlebot"]);
+/// assert!(!other.check_bot("Googlebot/2.1") && partner.check_bot("Googlebot/2.1"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LayeredDetector {
+    base: Arc<BotDetector>,
+    /// Shared by the variants of `base`.
+    sets: Arc<OnceLock<Result<GroupSets, BotGuardError>>>,
+    overlay: BotDetector,
+    suppressed: HashSet<String>,
+}
+
+impl LayeredDetector {
+    /// An empty overlay on the base.
+    pub fn new(base: Arc<BotDetector>) -> Self {
+        let overlay = base.empty_like();
+        LayeredDetector { base, sets: Arc::default(), overlay, suppressed: HashSet::new() }
+    }
+
+    /// Another empty overlay on the same base, sharing what was compiled for it.
+    pub fn variant(&self) -> Self {
+        LayeredDetector { base: Arc::clone(&self.base), sets: Arc::clone(&self.sets), overlay: self.base.empty_like(), suppressed: HashSet::new() }
+    }
+
+    pub fn base(&self) -> &Arc<BotDetector> {
+        &self.base
+    }
+
+    /// The patterns added by the overlay.
+    pub fn overlay(&self) -> &BotDetector {
+        &self.overlay
+    }
+
+    /// Adds patterns to the [`CUSTOM_GROUP`] of the overlay, see [`BotDetector::append`].
+    pub fn add(&mut self, patterns: &[&str]) -> Result<(), BotGuardError> {
+        self.add_to_group(CUSTOM_GROUP, patterns)
+    }
+
+    /// Adds patterns to a group of the overlay, only that group is compiled.
+    pub fn add_to_group(&mut self, group: &str, patterns: &[&str]) -> Result<(), BotGuardError> {
+        self.overlay.append_to_group(group, patterns)
+    }
+
+    /// Removes patterns the overlay added.
+    pub fn remove(&mut self, patterns: &[&str]) {
+        self.overlay.remove(patterns)
+    }
+
+    /// Hides base patterns from this detector, returns how many of them the base contains.
+    pub fn suppress(&mut self, patterns: &[&str]) -> usize {
+        let mut found = 0;
+        for pattern in patterns {
+            if self.base.contains_pattern(pattern) {
+                found += 1;
+                self.suppressed.insert(self.base.pattern_key(pattern).into_owned());
+            }
+        }
+        found
+    }
+
+    /// Makes suppressed base patterns match again.
+    pub fn unsuppress(&mut self, patterns: &[&str]) {
+        for pattern in patterns {
+            self.suppressed.remove(&*self.base.pattern_key(pattern));
+        }
+    }
+
+    pub fn is_suppressed(&self, pattern: &str) -> bool {
+        self.suppressed.contains(&*self.base.pattern_key(pattern))
+    }
+
+    /// The patterns in effect: those of the overlay, then the base patterns not suppressed.
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        let base = self.base.patterns().filter(|pattern| !self.is_suppressed(pattern) && !self.overlay.contains_pattern(pattern));
+        self.overlay.patterns().chain(base)
+    }
+
+    pub fn check(&self, user_agent: &str) -> Verdict {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        let normalized_user_agent = self.base.normalize_user_agent_in(user_agent, &mut buffer);
+        if normalized_user_agent.trim().is_empty() {
+            return self.base.options.empty_ua_policy.verdict();
+        }
+        self.base.options.thresholds.verdict(self.score(user_agent))
+    }
+
+    pub fn check_bot(&self, user_agent: &str) -> bool {
+        self.check(user_agent).is_bot()
+    }
+
+    /// Same as [`BotDetector::score`] over the patterns in effect.
+    pub fn score(&self, user_agent: &str) -> f32 {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        let normalized_user_agent = self.base.normalize_user_agent_in(user_agent, &mut buffer);
+        if normalized_user_agent.trim().is_empty() {
+            return self.base.score(user_agent);
+        }
+        match self.best_match(&normalized_user_agent) {
+            Some(found) => found.weight,
+            None => score::heuristic(&normalized_user_agent),
+        }
+    }
+
+    /// Same as [`BotDetector::find_match`], overlay patterns win ties with base patterns.
+    pub fn find_match(&self, user_agent: &str) -> Option<PatternMatch<'_>> {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        self.best_match(&self.base.normalize_user_agent_in(user_agent, &mut buffer))
+    }
+
+    /// The first overlay group matching the user-agent, otherwise the first base group matching
+    /// with a pattern that is not suppressed.
+    pub fn classify(&self, user_agent: &str) -> Option<&str> {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        let normalized_user_agent = self.base.normalize_user_agent_in(user_agent, &mut buffer);
+        if let Some((name, _)) = self.overlay.matching_group(&normalized_user_agent) {
+            return Some(name);
+        }
+        let groups = self.base.groups.iter().enumerate().filter(|(_, group)| group.is_enabled() && group.is_match(&normalized_user_agent));
+        for (i, group) in groups {
+            let leftmost = group.matched_pattern(&normalized_user_agent);
+            if leftmost.is_some_and(|pattern| !self.is_suppressed(pattern)) || self.unsuppressed_matches(i, &normalized_user_agent).next().is_some() {
+                return Some(group.name());
+            }
+        }
+        None
+    }
+
+    fn best_match(&self, normalized_user_agent: &str) -> Option<PatternMatch<'_>> {
+        let overlay = self.overlay.best_match(normalized_user_agent);
+        let base = match self.base.best_match(normalized_user_agent) {
+            Some(found) if self.is_suppressed(found.pattern) => self.unsuppressed_best(normalized_user_agent),
+            found => found,
+        };
+        match (overlay, base) {
+            (Some(overlay), Some(base)) if base.weight > overlay.weight => Some(base),
+            (None, base) => base,
+            (overlay, _) => overlay,
+        }
+    }
+
+    /// The best base match among the patterns that are not suppressed.
+    fn unsuppressed_best(&self, normalized_user_agent: &str) -> Option<PatternMatch<'_>> {
+        let mut best: Option<PatternMatch<'_>> = None;
+        for (i, group) in self.base.groups.iter().enumerate().filter(|(_, group)| group.is_enabled()) {
+            for pattern in self.unsuppressed_matches(i, normalized_user_agent) {
+                let weight = group.weights().get(pattern).copied().unwrap_or(1.0);
+                if best.is_none_or(|best| weight > best.weight) {
+                    best = Some(PatternMatch { group: group.name(), pattern, weight });
+                }
+            }
+        }
+        best
+    }
+
+    /// The patterns of a base group matching the user-agent in priority order, without the suppressed ones.
+    fn unsuppressed_matches<'a>(&'a self, group: usize, normalized_user_agent: &str) -> impl Iterator<Item = &'a str> {
+        let sets = self.sets.get_or_init(|| {
+            let set = |group: &PatternGroup| Ok((group.pattern_set()?, group.compiled_patterns().into_iter().map(str::to_string).collect()));
+            self.base.groups.iter().map(set).collect()
+        });
+        // the base patterns compiled before, so their sets do as well
+        let found = match sets.as_ref().ok().and_then(|sets| sets.get(group)) {
+            Some((set, patterns)) => set.matches(normalized_user_agent).into_iter().map(|i| patterns[i].as_str()).collect(),
+            None => Vec::new(),
+        };
+        found.into_iter().filter(|pattern| !self.is_suppressed(pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suppressed_patterns_fall_back_to_other_matches() {
+        let base = Arc::new(BotDetector::new("[crawlers]\ngooglebot\n0.6 google\n[tools]\n0.3 bot"));
+        let mut layered = LayeredDetector::new(Arc::clone(&base));
+        assert_eq!(layered.find_match("Googlebot/2.1").map(|found| found.pattern), Some("googlebot"));
+        layered.suppress(&["GoogleBot"]);
+        assert!(layered.is_suppressed("googlebot"));
+        let found = layered.find_match("Googlebot/2.1").unwrap();
+        assert_eq!((found.group, found.pattern, found.weight), ("crawlers", "google", 0.6));
+        assert_eq!(layered.check("Googlebot/2.1"), Verdict::Suspicious);
+        layered.suppress(&["google"]);
+        assert_eq!(layered.classify("Googlebot/2.1"), Some("tools"));
+        assert_eq!(layered.score("Googlebot/2.1"), 0.3);
+        layered.unsuppress(&["googlebot"]);
+        assert!(layered.check_bot("Googlebot/2.1"));
+        assert_eq!(layered.patterns().collect::<Vec<_>>(), vec!["googlebot", "bot"]);
+    }
+
+    #[test]
+    fn overlays_win_ties_and_keep_the_base_options() {
+        let base = Arc::new(BotDetector::builder().patterns("[crawlers]\nGooglebot").case_sensitive(true).build().unwrap());
+        let mut layered = LayeredDetector::new(Arc::clone(&base));
+        layered.add_to_group("mine", &["Googlebot", "Scrapy"]).unwrap();
+        assert_eq!(layered.find_match("Googlebot/2.1").map(|found| found.group), Some("mine"));
+        assert!(!layered.check_bot("scrapy/2.11") && layered.check_bot("Scrapy/2.11"));
+        assert!(layered.add(&["(unclosed"]).is_err());
+        layered.remove(&["Googlebot"]);
+        assert_eq!(layered.classify("Googlebot/2.1"), Some("crawlers"));
+        assert_eq!(layered.overlay().len(), 1);
+        assert_eq!(base.len(), 1);
+    }
+}
@@ -1,41 +1,336 @@
 // This is the BotDetector/anti-bot helper module that help to identify  and prevent bots based on a set of customizable regex patterns
+#![allow(non_snake_case)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use std::{collections::HashSet, fmt::Debug};
-use regex::Regex;
+extern crate alloc;
 
-#[derive(Debug)]
+/// Marks items needing the standard library, without the `std` feature only the plain-text
+/// matching of [`literal`] is built.
+macro_rules! with_std {
+    ($($item:item)*) => {
+        $(
+            #[cfg(feature = "std")]
+            $item
+        )*
+    };
+}
+
+mod error;
+pub mod literal;
+// outside `with_std!`, napi-rs pairs a class with its methods only in items it expands in order
+#[cfg(feature = "node")]
+pub mod node;
+mod score;
+
+pub use error::BotGuardError;
+pub use literal::{MatchMode, CUSTOM_GROUP};
+
+with_std! {
+    use std::{borrow::Cow, collections::HashSet, fmt::Debug, net::IpAddr, time::{Duration, Instant, SystemTime}};
+
+    pub mod actions;
+    pub mod admin;
+    pub mod anonymizer;
+    pub mod bench;
+    mod builder;
+    pub mod captcha;
+    pub mod canary;
+    pub mod challenge;
+    #[cfg(feature = "classifier")]
+    pub mod classifier;
+    pub mod clients;
+    pub mod concurrency;
+    pub mod config;
+    pub mod cookie;
+    mod crypto;
+    mod database;
+    pub mod datacenter;
+    pub mod dto;
+    pub mod decoy;
+    pub mod eventlog;
+    pub mod events;
+    pub mod exempt;
+    pub mod export;
+    pub mod fastpath;
+    pub mod feeds;
+    pub mod fingerprint;
+    #[cfg(feature = "grpc")]
+    pub mod grpc;
+    #[cfg(feature = "ffi")]
+    pub mod ffi;
+    #[cfg(feature = "geoip")]
+    pub mod geoip;
+    mod group;
+    pub mod headers;
+    pub mod honeypot;
+    mod http;
+    pub mod http2;
+    mod import;
+    pub mod ip;
+    pub mod layers;
+    pub mod locale;
+    pub mod maintenance;
+    mod json;
+    pub mod nonblocking;
+    pub mod normalize;
+    pub mod partners;
+    pub mod pipeline;
+    pub mod policy;
+    pub mod proxy;
+    #[cfg(feature = "python")]
+    pub mod python;
+    pub mod reference;
+    pub mod referrer;
+    pub mod registry;
+    pub mod reputation;
+    pub mod request;
+    #[cfg(feature = "redis")]
+    mod redis;
+    pub mod review;
+    pub mod robots;
+    pub mod sampling;
+    #[cfg(feature = "server")]
+    pub mod server;
+    pub mod source;
+    pub mod spam;
+    mod span;
+    #[cfg(feature = "sqlite")]
+    mod sqlite;
+    pub mod spoof;
+    pub mod state;
+    pub mod structure;
+    pub mod suggest;
+    pub mod tester;
+    pub mod testing;
+    pub mod timing;
+    mod toml;
+    #[cfg(feature = "tracing")]
+    pub mod trace;
+    pub mod traffic;
+    mod ttl;
+    #[cfg(feature = "ua-parser")]
+    pub mod useragent;
+    #[cfg(feature = "wasm")]
+    pub mod wasm;
+
+    use builder::DetectorOptions;
+    pub use builder::{BotDetectorBuilder, EmptyUaPolicy, PatternSyntax};
+    pub use database::{BotDatabase, BotInfo};
+    use events::{BotEvent, DetectionHook};
+    pub use group::RegexLimits;
+    use group::PatternGroup;
+    use literal::{group_header, weighted_entry};
+    use source::{BundleVersion, PatternBundle};
+    pub use span::{Capture, Match, Span};
+    pub use structure::ua_anomaly_score;
+    pub use ttl::TEMPORARY_GROUP;
+}
+
+/// Outcome of [`BotDetector::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Verdict {
+    Human,
+    /// Not confirmed as a bot, but worth a challenge rather than a free pass.
+    Suspicious,
+    Bot,
+}
+
+impl Verdict {
+    pub fn is_bot(self) -> bool {
+        self == Verdict::Bot
+    }
+
+    /// Orders verdicts from `Human` to `Bot`, for picking the strictest one.
+    #[cfg(feature = "std")]
+    pub(crate) fn rank(self) -> u8 {
+        match self {
+            Verdict::Human => 0,
+            Verdict::Suspicious => 1,
+            Verdict::Bot => 2,
+        }
+    }
+}
+
+/// How much a match by itself says about the client, derived from the pattern weight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Weight below `0.4`, e.g. a generic HTTP library also used by legitimate apps.
+    Low,
+    /// Weight below `0.7`.
+    Medium,
+    High,
+}
+
+/// The pattern responsible for a match, see [`BotDetector::find_match`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PatternMatch<'a> {
+    pub group: &'a str,
+    pub pattern: &'a str,
+    /// Between `0.0` and `1.0`, `1.0` unless the pattern was weighted lower.
+    pub weight: f32,
+}
+
+impl PatternMatch<'_> {
+    pub fn severity(&self) -> Severity {
+        if self.weight >= 0.7 {
+            Severity::High
+        } else if self.weight >= 0.4 {
+            Severity::Medium
+        } else {
+            Severity::Low
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+/// Pattern-level difference between two detectors, see [`BotDetector::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatternDiff {
+    /// Patterns only the other detector contains, sorted.
+    pub added: Vec<String>,
+    /// Patterns only this detector contains, sorted.
+    pub removed: Vec<String>,
+}
+
+#[cfg(feature = "std")]
+impl PatternDiff {
+    /// Returns `true` if both detectors contain the same patterns.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// The pattern groups and version of a detector, taken with [`BotDetector::snapshot`] and put
+/// back with [`BotDetector::restore`].
+///
+/// Holds the compiled groups, so restoring never recompiles. Snapshots compare equal when their
+/// patterns do, like detectors.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct DetectorSnapshot {
+    groups: Vec<PatternGroup>,
+    version: BundleVersion,
+}
+
+#[cfg(feature = "std")]
+impl DetectorSnapshot {
+    /// The version of the patterns when the snapshot was taken.
+    pub fn version(&self) -> &BundleVersion {
+        &self.version
+    }
+}
+
+#[cfg(feature = "std")]
+impl PartialEq for DetectorSnapshot {
+    fn eq(&self, other: &Self) -> bool {
+        same_groups(&self.groups, &other.groups)
+    }
+}
+
+/// Compares groups in order, skipping empty ones like the group left behind by removing its patterns.
+#[cfg(feature = "std")]
+fn same_groups(groups: &[PatternGroup], other: &[PatternGroup]) -> bool {
+    let non_empty = |group: &&PatternGroup| !group.patterns().is_empty();
+    groups.iter().filter(non_empty).eq(other.iter().filter(non_empty))
+}
+
+/// User-agents up to this many bytes are checked without allocating, longer ones are rare enough
+/// to lowercase into a `String`.
+#[cfg(feature = "std")]
+pub(crate) const INLINE_USER_AGENT: usize = 512;
+
+#[cfg(feature = "std")]
+pub(crate) type UserAgentBuffer = [u8; INLINE_USER_AGENT];
+
+/// A group name with its patterns and their optional weights, in the order they were written.
+#[cfg(feature = "std")]
+pub(crate) type EntryGroup = (String, Vec<(String, Option<f32>)>);
+
+/// Cloning copies the compiled patterns, the clone shares the detection hooks with the original.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
 pub struct BotDetector {
-    user_agents_regex: Regex,
-    user_agent_patterns: HashSet<String>,
+    /// Pattern groups in declaration order, which is also their matching priority.
+    groups: Vec<PatternGroup>,
+    detection_hooks: Vec<DetectionHook>,
+    options: DetectorOptions,
+    version: BundleVersion,
+    /// Groups and version replaced by the last reload, restored by [`BotDetector::rollback`].
+    previous: Option<Box<(Vec<PatternGroup>, BundleVersion)>>,
+    /// Expiry of the temporary patterns and blocked addresses.
+    expiries: ttl::Expiries,
+    /// Changes with every edit of the patterns, see [`BotDetector::generation`].
+    generation: u64,
+    /// Number of distinct patterns, counted again with every new generation.
+    len: usize,
+}
+
+/// Source of [`BotDetector::generation`], unique across detectors so a cache shared by several
+/// never mistakes one for another.
+#[cfg(feature = "std")]
+static GENERATIONS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
 
+#[cfg(feature = "std")]
+fn next_generation() -> u64 {
+    GENERATIONS.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1
+}
+
+#[cfg(feature = "std")]
+impl PartialEq for BotDetector {
+    /// Detectors are equal if they have the same non-empty groups in the same order, with the
+    /// same patterns, weights and enabled state. Options, versions and hooks are not compared.
+    fn eq(&self, other: &Self) -> bool {
+        same_groups(&self.groups, &other.groups)
+    }
 }
 
 /// Load default bot user-agent regular expressions from a local file, unless the feature is disabled
 #[cfg(feature = "include-default-BotDetector")]
-const _PATTERNS: &str = include_str!("pathtoRegexPatternsFile.rgx"); // it does not exist now but we can add it if we need more patterns, another way would be reading that from our server so that we can add or remove the patterns dynamically
+const _PATTERNS: &str = include_str!("bot_patterns.rgx"); // another way would be reading that from our server so that we can add or remove the patterns dynamically
 
 /// Do not load any default user-agent strings into the compiled library if feature is not enabled
 #[cfg(not(feature = "include-default-BotDetector"))]
 const _PATTERNS: &str = "";
 
+#[cfg(feature = "std")]
 impl Default for BotDetector {
     /// Constructs a new instance with default user-agent patterns.
-
-
     fn default() -> Self {
         BotDetector::new(_PATTERNS)
     }
 }
 
+/// Returns `true` if the user-agent is a known bot according to [`BotDetector::global`], for
+/// code that has no detector at hand.
+///
+/// ```
+/// assert!(BotGuardLib::is_bot("Mozilla/5.0 (compatible; bingbot/2.0; +http://www.bing.com/bingbot.htm)"));
+/// assert!(!BotGuardLib::is_bot("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36"));
+/// ```
+#[cfg(feature = "std")]
+pub fn is_bot(user_agent: &str) -> bool {
+    BotDetector::global().check_bot(user_agent)
+}
+
+#[cfg(feature = "std")]
 impl BotDetector {
     /// Constructs a new instance with bot user-agent regular expression entries delimited by a newline
     ///
-    /// All user-agent regular expressions are converted to lowercase.
+    /// All user-agent regular expressions ignore case, use [`BotDetector::builder`] for
+    /// case-sensitive matching. A `[name]` line starts a pattern group, entries before the first
+    /// header belong to the [`CUSTOM_GROUP`]. An entry may start with a weight between `0.0` and
+    /// `1.0` written with a decimal point and followed by whitespace, like `0.3 python-requests/`,
+    /// see [`BotDetector::score`].
+    ///
+    /// Groups and the patterns within them keep the order they are written in, which is their
+    /// priority: the earlier group wins, and within a group the earlier of two patterns matching at
+    /// the same position is reported. Appended patterns come after the existing ones, so the same
+    /// entries and edits always compile to the same matcher.
     ///
     /// # Example code
     ///
     /// ```
-    /// use checkbot::BotDetector;
+    /// use BotGuardLib::BotDetector;
     ///
     /// let custom_user_agent_patterns = r#"
     /// ^Googlebot-Image/
@@ -45,47 +340,270 @@ impl BotDetector {
     /// assert!(BotDetector.check_bot("Googlebot-Image/1.0"));
     /// assert!(BotDetector.check_bot("Mozilla/5.0 (Windows NT 6.1; WOW64) AppleWebKit/534+ (KHTML, like Gecko) BingPreview/1.0b"));
     /// assert!(!BotDetector.check_bot("Googlebot"));
+    ///
+    /// let grouped_patterns = "[search-engines]\ngooglebot\n[seo-tools]\nahrefsbot";
+    /// let BotDetector = BotDetector::new(grouped_patterns);
+    /// assert_eq!(BotDetector.classify("AhrefsBot/7.0"), Some("seo-tools"));
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if an entry is not a valid regular expression.
     pub fn new(bot_entries: &str) -> Self {
-        let user_agent_patterns = BotDetector::parse_lines(&bot_entries.to_ascii_lowercase());
-        let combined_user_agent_regex = BotDetector::to_regex(&user_agent_patterns);
-        BotDetector {
-            user_agent_patterns,
-            user_agents_regex: combined_user_agent_regex,
-        }
+        BotDetector::from_entries(bot_entries, DetectorOptions::default()).unwrap_or_else(|e| panic!("{}", e))
     }
 
-    /// Appends bot user-agent regular expressions patterns.
+    /// Constructs a new instance from CSV rows of `pattern,category,weight`, like a bot list kept
+    /// in a spreadsheet.
     ///
-    /// Duplicates are ignored.
+    /// A first row naming the columns may order them freely, only `pattern` is required. An empty
+    /// category puts the pattern in the [`CUSTOM_GROUP`], an empty weight counts as `1.0`. Fields
+    /// containing commas are quoted with `"`, lines starting with `#` are comments.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let csv = "pattern,category,weight\ngooglebot,search-engines,\n\"python-requests/\",http-clients,0.3";
+    /// let BotDetector = BotDetector::from_csv(csv).unwrap();
+    /// assert_eq!(BotDetector.classify("Googlebot/2.1"), Some("search-engines"));
+    /// assert_eq!(BotDetector.score("python-requests/2.31"), 0.3);
+    ///
+    /// let error = BotDetector::from_csv("googlebot\n(unclosed").unwrap_err();
+    /// assert!(error.to_string().starts_with("invalid CSV pattern list on line 2: "));
+    /// ```
+    pub fn from_csv(csv: &str) -> Result<Self, BotGuardError> {
+        import::csv(csv)?.into_detector(BundleVersion::of(csv, None))
+    }
+
+    /// Constructs a new instance from a YAML pattern list, either a mapping from category to
+    /// patterns or the document of [`export::ExportFormat::Yaml`].
+    ///
+    /// A pattern is a string or a mapping with `pattern` and `weight`. Errors name the line in
+    /// the document; flow collections, anchors and block scalars are not supported.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let yaml = "
+    /// search-engines:
+    ///   - googlebot
+    /// http-clients:
+    ///   - pattern: 'python-requests/'
+    ///     weight: 0.3
+    /// ";
+    /// let BotDetector = BotDetector::from_yaml(yaml).unwrap();
+    /// assert_eq!(BotDetector.classify("Googlebot/2.1"), Some("search-engines"));
+    /// assert_eq!(BotDetector.score("python-requests/2.31"), 0.3);
+    /// ```
+    pub fn from_yaml(yaml: &str) -> Result<Self, BotGuardError> {
+        import::yaml(yaml)?.into_detector(BundleVersion::of(yaml, None))
+    }
+
+    /// Starts building a detector with non-default options, see [`BotDetectorBuilder`].
+    pub fn builder() -> BotDetectorBuilder {
+        BotDetectorBuilder::default()
+    }
+
+    /// A detector with the default patterns shared by the whole process, built on first use.
+    ///
+    /// Middlewares and libraries can check user-agents through it, or through [`is_bot`], without
+    /// passing a detector through every layer. It cannot be changed, applications reloading or
+    /// editing patterns keep their own detector.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// assert!(BotDetector::global().check_bot("Googlebot/2.1 (+http://www.google.com/bot.html)"));
+    /// assert!(std::ptr::eq(BotDetector::global(), BotDetector::global()));
+    /// ```
+    pub fn global() -> &'static BotDetector {
+        static GLOBAL: std::sync::OnceLock<BotDetector> = std::sync::OnceLock::new();
+        GLOBAL.get_or_init(BotDetector::default)
+    }
+
+    /// Finishes compiling the patterns of a detector built with
+    /// [`BotDetectorBuilder::background_compilation`], waiting for the background thread. Returns
+    /// the first invalid or oversized pattern, the detector keeps matching plain-text patterns
+    /// only in that case. Detectors compiled when they were built return right away.
+    pub fn compile_now(&self) -> Result<(), BotGuardError> {
+        self.groups.iter().try_for_each(PatternGroup::compile_now)
+    }
+
+    /// Whether all patterns are compiled, rather than only the plain-text ones matched while
+    /// compiling in the background.
+    pub fn is_compiled(&self) -> bool {
+        self.groups.iter().all(PatternGroup::is_compiled)
+    }
+
+    pub(crate) fn from_entries(bot_entries: &str, options: DetectorOptions) -> Result<Self, BotGuardError> {
+        BotDetector::from_groups(BotDetector::parse_lines(bot_entries), options, BundleVersion::of(bot_entries, None))
+    }
+
+    /// Builds a detector from groups of patterns with their optional weights, in declaration order.
+    ///
+    /// Duplicate patterns keep the position of the first and the weight of the last occurrence.
+    pub(crate) fn from_groups(
+        groups: Vec<EntryGroup>,
+        options: DetectorOptions,
+        version: BundleVersion,
+    ) -> Result<Self, BotGuardError> {
+        let mut BotDetector = BotDetector { groups: Vec::new(), detection_hooks: Vec::new(), options, version, previous: None, expiries: ttl::Expiries::default(), generation: next_generation(), len: 0 };
+        BotDetector.groups = groups
+            .into_iter()
+            .map(|(name, patterns)| {
+                let weights = patterns.iter().map(|(p, weight)| (p.clone(), weight.unwrap_or(1.0))).collect::<Vec<(String, f32)>>();
+                let patterns = patterns.into_iter().map(|(p, _)| p).collect::<Vec<String>>();
+                if BotDetector.options.background_compilation {
+                    return Ok(PatternGroup::deferred(name, patterns, weights, &BotDetector.options));
+                }
+                let mut group = PatternGroup::new(name, patterns, &BotDetector.options)?;
+                group.set_weights(weights);
+                Ok(group)
+            })
+            .collect::<Result<Vec<PatternGroup>, BotGuardError>>()?;
+        BotDetector.options.background_compilation = false;
+        BotDetector.len = BotDetector.patterns().count();
+        Ok(BotDetector)
+    }
+
+    /// A detector without patterns or hooks, with the options of this one.
+    pub(crate) fn empty_like(&self) -> BotDetector {
+        BotDetector { groups: Vec::new(), detection_hooks: Vec::new(), options: self.options.clone(), version: BundleVersion::of("", None), previous: None, expiries: ttl::Expiries::default(), generation: next_generation(), len: 0 }
+    }
+
+    /// Appends bot user-agent regular expressions patterns to the [`CUSTOM_GROUP`].
+    ///
+    /// Duplicates are ignored. Fails like [`BotDetector::append_to_group`].
     ///
     /// # Example code
     ///
     /// ```
+    /// use BotGuardLib::BotDetector;
     ///
     /// let mut BotDetector = BotDetector::default();
     /// assert!(!BotDetector.check_bot("Mozilla/5.0 (CustomNewTestB0T /1.2)"));
-    /// BotDetector.append(&[r"CustomNewTestB0T\s/\d\.\d"]);
+    /// BotDetector.append(&[r"CustomNewTestB0T\s/\d\.\d"]).unwrap();
     /// assert!(BotDetector.check_bot("Mozilla/5.0 (CustomNewTestB0T /1.2)"));
     ///
     /// let new__PATTERNS = vec!["GoogleMetaverse", "^Special/"];
-    /// BotDetector.append(&new__PATTERNS);
+    /// BotDetector.append(&new__PATTERNS).unwrap();
     /// assert!(BotDetector.check_bot("Mozilla/5.0 (GoogleMetaverse/1.0)"));
+    /// assert!(BotDetector.append(&["(unclosed"]).is_err());
+    /// ```
+    pub fn append(&mut self, BotDetector: &[&str]) -> Result<(), BotGuardError> {
+        self.append_to_group(CUSTOM_GROUP, BotDetector)
+    }
+
+    /// Appends patterns to the named group, creating it enabled if it does not exist yet.
+    ///
+    /// Only that group is recompiled. Fails with [`BotGuardError::InvalidPattern`] or
+    /// [`BotGuardError::PatternTooLarge`] if a pattern is not a valid regular expression or exceeds
+    /// the configured limits, the group is left unchanged then.
+    ///
+    /// ```
+    /// use BotGuardLib::{BotDetector, BotGuardError};
+    ///
+    /// let mut BotDetector = BotDetector::new("");
+    /// BotDetector.append_to_group("partners", &["^PartnerMonitor/"]).unwrap();
+    /// assert_eq!(BotDetector.classify("PartnerMonitor/2.0"), Some("partners"));
+    ///
+    /// let error = BotDetector.append_to_group("partners", &["^Other/", "[a-"]).unwrap_err();
+    /// assert!(matches!(error, BotGuardError::InvalidPattern { pattern, .. } if pattern == "[a-"));
+    /// assert!(!BotDetector.check_bot("Other/1.0"));
+    /// ```
+    pub fn append_to_group(&mut self, group: &str, patterns: &[&str]) -> Result<(), BotGuardError> {
+        self.prune_expired();
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<String>>();
+        let name = group.to_ascii_lowercase();
+        let changed = match self.group_mut(&name) {
+            Some(existing) => existing.insert(patterns)?,
+            None => {
+                self.groups.push(PatternGroup::new(name, patterns.into_iter().collect(), &self.options)?);
+                true
+            }
+        };
+        if changed {
+            self.edited();
+        }
+        Ok(())
+    }
+
+    /// Appends a pattern to the [`TEMPORARY_GROUP`] until `ttl` has passed, appending it again
+    /// sets a new expiry. Fails like [`BotDetector::append_to_group`].
+    ///
+    /// Expired patterns keep matching until they are pruned, by [`BotDetector::prune_expired`],
+    /// before any other edit of the detector or on every run of a [`source::PatternRefresher`].
+    /// Reloads, rollbacks and restores keep the temporary patterns.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let mut BotDetector = BotDetector::new("googlebot");
+    /// BotDetector.append_ttl("^scrapy/", Duration::from_secs(24 * 60 * 60)).unwrap();
+    /// assert!(BotDetector.check_bot("Scrapy/2.11"));
+    /// assert!(BotDetector.pattern_expiry("^scrapy/").is_some());
+    ///
+    /// BotDetector.append_ttl("^Nutch/", Duration::ZERO).unwrap();
+    /// assert_eq!(BotDetector.prune_expired(), 1);
+    /// assert!(!BotDetector.check_bot("Nutch/1.19"));
+    /// ```
+    pub fn append_ttl(&mut self, pattern: &str, ttl: Duration) -> Result<(), BotGuardError> {
+        self.append_to_group(TEMPORARY_GROUP, &[pattern])?;
+        self.expiries.set_pattern(self.pattern_key(pattern).into_owned(), Instant::now() + ttl);
+        Ok(())
+    }
+
+    /// When a pattern appended with [`BotDetector::append_ttl`] expires.
+    pub fn pattern_expiry(&self, pattern: &str) -> Option<Instant> {
+        self.expiries.pattern(&self.pattern_key(pattern))
+    }
+
+    /// Treats every check from the address as a bot until `ttl` has passed, whatever the
+    /// user-agent, see [`BotDetector::check_bot_from`]. Blocking it again sets a new expiry.
+    ///
     /// ```
-    pub fn append(&mut self, BotDetector: &[&str]) {
-        for bot in BotDetector {
-            self.user_agent_patterns.insert(bot.to_ascii_lowercase());
+    /// use std::time::Duration;
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let mut BotDetector = BotDetector::default();
+    /// let scraper = "203.0.113.7".parse().unwrap();
+    /// BotDetector.block_ip_ttl(scraper, Duration::from_secs(60 * 60));
+    /// assert!(BotDetector.check_bot_from("Mozilla/5.0 (X11; Linux x86_64)", scraper));
+    /// assert!(!BotDetector.check_bot_from("Mozilla/5.0 (X11; Linux x86_64)", "198.51.100.1".parse().unwrap()));
+    /// ```
+    pub fn block_ip_ttl(&mut self, ip: IpAddr, ttl: Duration) {
+        self.prune_expired();
+        self.expiries.block_ip(ip, Instant::now() + ttl);
+    }
+
+    /// Lifts a block of [`BotDetector::block_ip_ttl`], returns `false` if the address was not blocked.
+    pub fn unblock_ip(&mut self, ip: IpAddr) -> bool {
+        self.expiries.unblock_ip(ip)
+    }
+
+    /// Returns `true` if the address is blocked and the block has not expired yet.
+    pub fn is_ip_blocked(&self, ip: IpAddr) -> bool {
+        self.expiries.is_blocked(ip, Instant::now())
+    }
+
+    /// Removes the expired temporary patterns and blocked addresses, returns how many were
+    /// removed. Only the [`TEMPORARY_GROUP`] is recompiled, and only if a pattern expired.
+    pub fn prune_expired(&mut self) -> usize {
+        let (patterns, ips) = self.expiries.take_expired(Instant::now());
+        if !patterns.is_empty() && self.group_mut(TEMPORARY_GROUP).is_some_and(|group| group.remove(&patterns)) {
+            self.edited();
         }
-        self.update_regex()
+        patterns.len() + ips
     }
 
 
-      /// Removes bot user-agent regular expressions.
+      /// Removes bot user-agent regular expressions from every group containing them.
     ///
     /// # Example code
     ///
     /// ```
-    /// use checkbot::BotDetector;
+    /// use BotGuardLib::BotDetector;
     ///
     /// let mut BotDetector = BotDetector::default();
     ///
@@ -100,16 +618,387 @@ impl BotDetector {
     /// assert!(!BotDetector.check_bot("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/49.0.2623.75 Safari/537.36 Google Favicon"));
     /// ```
     pub fn remove(&mut self, BotDetector: &[&str]) {
-        for bot in BotDetector {
-            self.user_agent_patterns.remove(&bot.to_ascii_lowercase());
+        self.prune_expired();
+        let patterns = BotDetector.iter().map(|p| self.pattern_key(p).into_owned()).collect::<Vec<String>>();
+        let mut removed = false;
+        for group in &mut self.groups {
+            removed |= group.remove(&patterns);
+        }
+        if removed {
+            self.edited();
+        }
+        self.expiries.forget_patterns(&patterns);
+    }
+
+    /// Enables a pattern group, returns `false` if there is no group with that name.
+    ///
+    /// Toggling a group does not recompile anything.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let mut BotDetector = BotDetector::default();
+    /// assert!(BotDetector.check_bot("AhrefsBot/7.0"));
+    /// BotDetector.disable_group("seo-tools");
+    /// assert!(!BotDetector.check_bot("AhrefsBot/7.0"));
+    /// BotDetector.enable_group("seo-tools");
+    /// assert!(BotDetector.check_bot("AhrefsBot/7.0"));
+    /// ```
+    pub fn enable_group(&mut self, group: &str) -> bool {
+        self.set_group_enabled(group, true)
+    }
+
+    /// Disables a pattern group, returns `false` if there is no group with that name.
+    pub fn disable_group(&mut self, group: &str) -> bool {
+        self.set_group_enabled(group, false)
+    }
+
+    /// Returns `true` if the group exists and is enabled.
+    pub fn is_group_enabled(&self, group: &str) -> bool {
+        let name = group.to_ascii_lowercase();
+        self.groups.iter().any(|group| group.name() == name && group.is_enabled())
+    }
+
+    /// Names of all pattern groups, enabled or not, in declaration order.
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.groups.iter().map(PatternGroup::name)
+    }
+
+    fn set_group_enabled(&mut self, group: &str, enabled: bool) -> bool {
+        let Some(group) = self.group_mut(&group.to_ascii_lowercase()) else { return false };
+        if group.set_enabled(enabled) {
+            self.edited();
+        }
+        true
+    }
+
+    /// The group of that name, edits through it have to be followed by [`BotDetector::edited`].
+    fn group_mut(&mut self, name: &str) -> Option<&mut PatternGroup> {
+        self.groups.iter_mut().find(|group| group.name() == name)
+    }
+
+    /// Moves to a new [`BotDetector::generation`] after the patterns changed and counts them again.
+    fn edited(&mut self) {
+        self.generation = next_generation();
+        self.len = self.patterns().count();
+    }
+
+    /// Iterates over every loaded pattern once, including those of disabled groups.
+    ///
+    /// Patterns are yielded as first written, a later spelling in other case is the same pattern
+    /// unless the detector is case-sensitive, and in priority order, see [`BotDetector::new`].
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let BotDetector = BotDetector::new("[a]\nFooBot\nbarbot\n[b]\nfoobot\nbazbot");
+    /// assert_eq!(BotDetector.patterns().collect::<Vec<_>>(), vec!["FooBot", "barbot", "bazbot"]);
+    /// assert_eq!(BotDetector.len(), 3);
+    /// ```
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        let mut seen = HashSet::new();
+        self.groups.iter().flat_map(|group| group.patterns().iter().map(String::as_str)).filter(move |pattern| seen.insert(self.pattern_key(pattern)))
+    }
+
+    /// Iterates over the patterns of one group in priority order, `None` if there is no group with that name.
+    pub fn group_patterns(&self, group: &str) -> Option<impl Iterator<Item = &str>> {
+        let name = group.to_ascii_lowercase();
+        let group = self.groups.iter().find(|group| group.name() == name)?;
+        Some(group.patterns().iter().map(String::as_str))
+    }
+
+    /// Returns `true` if any group contains the pattern, compared in lowercase outside of escapes
+    /// and capture names unless the detector is case-sensitive.
+    pub fn contains_pattern(&self, pattern: &str) -> bool {
+        self.groups.iter().any(|group| group.patterns().contains(pattern))
+    }
+
+    /// Sets the weight a pattern scores with in [`BotDetector::score`] in every group containing
+    /// it, clamped to `0.0..=1.0`. Returns `false` if no group contains the pattern.
+    ///
+    /// [`BotDetector::check`] compares the weight with the [`config::Thresholds`], so a pattern
+    /// weighted below the bot threshold only makes clients suspicious. Reloading the patterns
+    /// replaces the weights with those written in the entries, see [`BotDetector::new`].
+    pub fn set_weight(&mut self, pattern: &str, weight: f32) -> bool {
+        let mut found = false;
+        for group in &mut self.groups {
+            found |= group.set_weights([(pattern.to_string(), weight)]);
+        }
+        if found {
+            self.edited();
+        }
+        found
+    }
+
+    /// Adds every pattern of `other` to the group of the same name.
+    ///
+    /// Groups missing here are created with the enabled state they have in `other`, existing groups
+    /// keep their state. Only groups that gained patterns are recompiled. The patterns are matched
+    /// with the options of this detector.
+    ///
+    /// Fails if a merged group exceeds the regex limits of this detector, the groups merged before
+    /// it keep their new patterns.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let mut corporate = BotDetector::new("[search-engines]\ngooglebot");
+    /// let service = BotDetector::new("[search-engines]\nbingbot\n[internal]\n^healthcheck/");
+    /// corporate.merge(&service).unwrap();
+    /// assert_eq!(corporate.classify("bingbot/2.0"), Some("search-engines"));
+    /// assert_eq!(corporate.classify("HealthCheck/1.0"), Some("internal"));
+    /// assert!(corporate.diff(&service).added.is_empty());
+    /// ```
+    pub fn merge(&mut self, other: &BotDetector) -> Result<(), BotGuardError> {
+        for group in &other.groups {
+            let weights = group.weights().iter().map(|(pattern, weight)| (pattern.clone(), *weight));
+            let changed = match self.group_mut(group.name()) {
+                Some(existing) => {
+                    let weights = weights.filter(|(pattern, weight)| existing.weights().get(pattern) != Some(weight)).collect::<Vec<_>>();
+                    existing.insert(group.patterns().iter().cloned())? | existing.set_weights(weights)
+                }
+                None => {
+                    let mut merged = PatternGroup::new(group.name().to_string(), group.patterns().iter().cloned().collect(), &self.options)?;
+                    merged.set_enabled(group.is_enabled());
+                    merged.set_weights(weights);
+                    self.groups.push(merged);
+                    true
+                }
+            };
+            if changed {
+                self.edited();
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares the pattern sets of both detectors, ignoring groups and their enabled state.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let before = BotDetector::new("googlebot\nbingbot");
+    /// let after = BotDetector::new("googlebot\nduckduckbot");
+    /// let diff = before.diff(&after);
+    /// assert_eq!(diff.added, vec!["duckduckbot".to_string()]);
+    /// assert_eq!(diff.removed, vec!["bingbot".to_string()]);
+    /// ```
+    pub fn diff(&self, other: &BotDetector) -> PatternDiff {
+        let mut added = other
+            .patterns()
+            .filter(|p| !self.contains_pattern(p))
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+        let mut removed = self
+            .patterns()
+            .filter(|p| !other.contains_pattern(p))
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+        added.sort();
+        removed.sort();
+        PatternDiff { added, removed }
+    }
+
+    /// Renders the patterns of the enabled groups for deployment to other layers, see
+    /// [`export::ExportFormat`].
+    ///
+    /// The nginx and HAProxy formats only carry patterns weighted at least the bot threshold,
+    /// these layers cannot add up scores.
+    ///
+    /// ```
+    /// use BotGuardLib::export::ExportFormat;
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let BotDetector = BotDetector::new("[scanners]\n^sqlmap/\nnikto");
+    /// assert_eq!(BotDetector.export(ExportFormat::Regex), "[scanners]\n^sqlmap/\nnikto\n");
+    /// assert_eq!(
+    ///     BotDetector.export(ExportFormat::NginxMap),
+    ///     "map $http_user_agent $bot_category {\n    default \"\";\n    \"~*^sqlmap/\" \"scanners\";\n    \"~*nikto\" \"scanners\";\n}\n"
+    /// );
+    /// ```
+    pub fn export(&self, format: export::ExportFormat) -> String {
+        export::export(self, format)
+    }
+
+    /// Runs this and the `other` detector over a corpus of user-agents, one per line, and lists
+    /// where their verdicts differ and which patterns caused it, see [`tester::compare`].
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let current = BotDetector::new("^curl/");
+    /// let report = current.compare(&BotDetector::new("^curl/\n^wget/"), "curl/8.4.0\nWget/1.21");
+    /// assert_eq!(report.agreement(), 0.5);
+    /// ```
+    pub fn compare(&self, other: &BotDetector, corpus: &str) -> tester::ComparisonReport {
+        tester::compare(self, other, corpus)
+    }
+
+    /// Replaces all patterns with newline-delimited entries, in the format of [`BotDetector::new`].
+    ///
+    /// The options and detection hooks stay, groups that still exist keep being enabled or disabled.
+    /// On error the current patterns stay in place. The replaced patterns are kept for
+    /// [`BotDetector::rollback`], the new version is a hash of the entries.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let mut BotDetector = BotDetector::new("[search-engines]\ngooglebot\n[monitoring]\npingdom");
+    /// BotDetector.disable_group("monitoring");
+    /// BotDetector.reload("[search-engines]\nbingbot\n[monitoring]\npingdom").unwrap();
+    /// assert!(BotDetector.check_bot("bingbot/2.0"));
+    /// assert!(!BotDetector.check_bot("Googlebot"));
+    /// assert!(!BotDetector.is_group_enabled("monitoring"));
+    /// assert!(BotDetector.reload("(unclosed").is_err());
+    /// ```
+    pub fn reload(&mut self, bot_entries: &str) -> Result<(), BotGuardError> {
+        let reloaded = BotDetector::from_entries(bot_entries, self.options.clone())?;
+        self.install(reloaded);
+        Ok(())
+    }
+
+    /// Reloads the patterns of a bundle like [`BotDetector::reload`], taking its version if it has one.
+    ///
+    /// The signature is not checked, see [`source::refresh`] for that.
+    pub fn reload_bundle(&mut self, bundle: &PatternBundle) -> Result<(), BotGuardError> {
+        let reloaded = BotDetector::from_bundle(bundle, self.options.clone())?;
+        self.install(reloaded);
+        Ok(())
+    }
+
+    pub(crate) fn from_bundle(bundle: &PatternBundle, options: DetectorOptions) -> Result<Self, BotGuardError> {
+        let mut BotDetector = BotDetector::from_entries(&bundle.patterns, options)?;
+        BotDetector.version = BundleVersion::of(&bundle.patterns, bundle.version.as_deref());
+        Ok(BotDetector)
+    }
+
+    /// Takes over the groups and version of a detector compiled with the same options.
+    pub(crate) fn install(&mut self, reloaded: BotDetector) {
+        self.replace_groups(reloaded.groups, reloaded.version);
+    }
+
+    /// Version of the patterns loaded at construction or by the latest reload.
+    ///
+    /// Patterns appended or removed since then do not change it.
+    pub fn current_version(&self) -> &BundleVersion {
+        &self.version
+    }
+
+    /// Version [`BotDetector::rollback`] would restore.
+    pub fn previous_version(&self) -> Option<&BundleVersion> {
+        self.previous.as_ref().map(|previous| &previous.1)
+    }
+
+    /// Restores the patterns replaced by the latest reload without recompiling them.
+    ///
+    /// Only one step is kept, returns `false` if there is nothing to roll back to.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let mut BotDetector = BotDetector::new("googlebot");
+    /// let original = BotDetector.current_version().clone();
+    /// BotDetector.reload(".").unwrap();
+    /// assert!(BotDetector.check_bot("Mozilla/5.0"));
+    ///
+    /// assert!(BotDetector.rollback());
+    /// assert!(!BotDetector.check_bot("Mozilla/5.0"));
+    /// assert_eq!(BotDetector.current_version(), &original);
+    /// assert!(!BotDetector.rollback());
+    /// ```
+    pub fn rollback(&mut self) -> bool {
+        match self.previous.take() {
+            Some(previous) => {
+                let (groups, version) = *previous;
+                self.replace_groups(groups, version);
+                self.previous = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Captures the current patterns, including which groups are enabled, to
+    /// [`BotDetector::restore`] them after experimenting with edits.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let mut BotDetector = BotDetector::new("[crawlers]\ngooglebot\n[tools]\ncurl/");
+    /// let snapshot = BotDetector.snapshot();
+    /// BotDetector.append(&["wget/"]).unwrap();
+    /// BotDetector.disable_group("tools");
+    /// assert!(BotDetector.snapshot() != snapshot);
+    ///
+    /// BotDetector.restore(snapshot.clone());
+    /// assert!(!BotDetector.check_bot("Wget/1.21") && BotDetector.check_bot("curl/8.4"));
+    /// assert!(BotDetector.snapshot() == snapshot);
+    /// ```
+    pub fn snapshot(&self) -> DetectorSnapshot {
+        DetectorSnapshot { groups: self.groups.clone(), version: self.version.clone() }
+    }
+
+    /// Replaces the patterns and version with a [`DetectorSnapshot`]. Options and hooks stay, the
+    /// replaced patterns can be brought back with [`BotDetector::rollback`].
+    pub fn restore(&mut self, snapshot: DetectorSnapshot) {
+        let mut groups = snapshot.groups;
+        self.keep_temporary(&mut groups);
+        self.edited();
+        let replaced = std::mem::replace(&mut self.groups, groups);
+        let replaced_version = std::mem::replace(&mut self.version, snapshot.version);
+        self.previous = Some(Box::new((replaced, replaced_version)));
+    }
+
+    /// Swaps in new groups, carrying over the enabled state of groups that keep their name.
+    fn replace_groups(&mut self, mut groups: Vec<PatternGroup>, version: BundleVersion) {
+        self.keep_temporary(&mut groups);
+        for group in &mut groups {
+            if let Some(current) = self.groups.iter().find(|current| current.name() == group.name()) {
+                group.set_enabled(current.is_enabled());
+            }
+        }
+        self.edited();
+        let replaced = std::mem::replace(&mut self.groups, groups);
+        let replaced_version = std::mem::replace(&mut self.version, version);
+        self.previous = Some(Box::new((replaced, replaced_version)));
+    }
+
+    /// Carries the current [`TEMPORARY_GROUP`] over to groups replacing the current ones.
+    fn keep_temporary(&self, groups: &mut Vec<PatternGroup>) {
+        groups.retain(|group| group.name() != TEMPORARY_GROUP);
+        if let Some(temporary) = self.groups.iter().find(|group| group.name() == TEMPORARY_GROUP) {
+            groups.push(temporary.clone());
         }
-        self.update_regex()
+    }
+
+    /// Identifies the current patterns: it changes with every append, removal, reload or other
+    /// edit that can change a verdict, and clones share it until one of them is edited. Caches of
+    /// verdicts, like [`fastpath::ShardedVerdictCache`], compare it to notice stale entries.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Number of distinct loaded patterns.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no pattern is loaded at all.
+    pub fn is_empty(&self) -> bool {
+        self.groups.iter().all(|group| group.patterns().is_empty())
     }
 
 
     /// Returns `true` the user-agent is a known bot.
     ///
-    /// The user-agent comparison is done using lowercase.
+    /// The user-agent comparison is done using lowercase. Empty user-agents are handled by the
+    /// [`EmptyUaPolicy`] and only count as bots with [`EmptyUaPolicy::TreatAsBot`].
+    ///
+    /// Checks do not allocate for user-agents of up to 512 bytes, unless the
+    /// [`normalize::Normalization`] folds them or detection hooks run.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
     ///
     /// let BotDetector = BotDetector::default();
     ///
@@ -117,44 +1006,500 @@ impl BotDetector {
     /// assert!(!BotDetector.check_bot("Dalvik/2.1.0 (Linux; U; Android 8.0.0; SM-G930F Build/R16NW)"));
     /// ```    
     pub fn check_bot(&self, user_agent: &str) -> bool {
-        self.user_agents_regex
-            .is_match(&user_agent.to_ascii_lowercase())
+        self.detect(user_agent, None).is_bot()
     }
 
-    
-  
+    /// Same as [`BotDetector::check_bot`], the client address is passed on to the detection hooks.
+    ///
+    /// Addresses blocked with [`BotDetector::block_ip_ttl`] are bots whatever the user-agent.
+    pub fn check_bot_from(&self, user_agent: &str, ip: IpAddr) -> bool {
+        self.detect(user_agent, Some(ip)).is_bot()
+    }
+
+    /// Returns the [`Verdict`] for a user-agent.
+    ///
+    /// ```
+    /// use BotGuardLib::{BotDetector, EmptyUaPolicy, Verdict};
+    ///
+    /// let BotDetector = BotDetector::builder().empty_ua_policy(EmptyUaPolicy::Suspicious).build().unwrap();
+    /// assert_eq!(BotDetector.check("Googlebot/2.1"), Verdict::Bot);
+    /// assert_eq!(BotDetector.check(""), Verdict::Suspicious);
+    /// assert_eq!(BotDetector.check("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0"), Verdict::Human);
+    /// ```
+    pub fn check(&self, user_agent: &str) -> Verdict {
+        self.detect(user_agent, None)
+    }
+
+    /// Returns how likely the user-agent is a bot, between `0.0` and `1.0`.
+    ///
+    /// A matching pattern scores its weight, `1.0` unless set with [`BotDetector::set_weight`],
+    /// the highest weight wins if several match. User-agents no pattern matches get a partial
+    /// score of at most `0.4` if they look like a tool or a bare `Mozilla/5.0`. Empty user-agents
+    /// score `1.0`, `0.5` or `0.0` following the [`EmptyUaPolicy`]. No detection hooks are called.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let mut BotDetector = BotDetector::new("^sqlmap/\npython-requests/");
+    /// BotDetector.set_weight("python-requests/", 0.3);
+    /// assert_eq!(BotDetector.score("sqlmap/1.7"), 1.0);
+    /// assert_eq!(BotDetector.score("python-requests/2.31"), 0.3);
+    /// assert!(BotDetector.score("acme-uptime/1.0") < 0.5);
+    /// assert_eq!(BotDetector.score("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) Safari/605.1.15"), 0.0);
+    /// ```
+    pub fn score(&self, user_agent: &str) -> f32 {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        let normalized_user_agent = self.normalize_user_agent_in(user_agent, &mut buffer);
+        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+        let (score, found) = self.weigh(&normalized_user_agent);
+
+        #[cfg(feature = "tracing")]
+        trace::decision(
+            "score",
+            &normalized_user_agent,
+            found.map(|found| found.pattern),
+            found.map(|found| found.group),
+            self.options.thresholds.verdict(score).is_bot(),
+            Some(score),
+        );
+
+        score
+    }
+
+    /// [`BotDetector::score`] without recording a decision, for comparing detectors.
+    pub(crate) fn score_untraced(&self, user_agent: &str) -> f32 {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        self.weigh(&self.normalize_user_agent_in(user_agent, &mut buffer)).0
+    }
+
+    /// The score of an already normalized user-agent with the match it is based on.
+    fn weigh(&self, normalized_user_agent: &str) -> (f32, Option<PatternMatch<'_>>) {
+        if normalized_user_agent.trim().is_empty() {
+            return (self.empty_ua_score(), None);
+        }
+        let found = self.best_match(normalized_user_agent);
+        (found.map_or_else(|| score::heuristic(normalized_user_agent), |found| found.weight), found)
+    }
+
+    /// Returns the matching pattern with the highest weight, the one [`BotDetector::score`] is
+    /// based on. Among equal weights the earliest group, then the leftmost match, then the earlier
+    /// pattern win.
+    ///
+    /// ```
+    /// use BotGuardLib::{BotDetector, Severity};
+    ///
+    /// let BotDetector = BotDetector::new("[scanners]\n0.9 ^sqlmap/\n[http-clients]\n0.3 python-requests/");
+    /// let found = BotDetector.find_match("python-requests/2.31").unwrap();
+    /// assert_eq!((found.group, found.pattern, found.weight), ("http-clients", "python-requests/", 0.3));
+    /// assert_eq!(found.severity(), Severity::Low);
+    /// assert_eq!(BotDetector.find_match("sqlmap/1.7").unwrap().severity(), Severity::High);
+    /// assert_eq!(BotDetector.find_match("Mozilla/5.0"), None);
+    /// ```
+    pub fn find_match(&self, user_agent: &str) -> Option<PatternMatch<'_>> {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        self.best_match(&self.normalize_user_agent_in(user_agent, &mut buffer))
+    }
+
+    /// Same as [`BotDetector::find_match`], together with where the pattern matched the user-agent
+    /// and its capture groups.
+    ///
+    /// The offsets point into the user-agent as it was passed in, even where folding by the
+    /// [`normalize::Normalization`] changed the length of the text the patterns ran on.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let BotDetector = BotDetector::new("python-requests/(?P<version>[\\d.]+)");
+    /// let found = BotDetector.find("Python-Requests/2.31.0").unwrap();
+    /// assert_eq!((found.span.start, found.span.end, found.span.text), (0, 22, "Python-Requests/2.31.0"));
+    /// assert_eq!(found.capture("version").unwrap().text, "2.31.0");
+    /// ```
+    pub fn find<'a, 'u>(&'a self, user_agent: &'u str) -> Option<Match<'a, 'u>> {
+        span::find(self, user_agent)
+    }
+
+    fn best_match(&self, normalized_user_agent: &str) -> Option<PatternMatch<'_>> {
+        let (group, _) = self.best_group(normalized_user_agent)?;
+        let (pattern, weight) = group.best_match(normalized_user_agent)?;
+        Some(PatternMatch { group: group.name(), pattern, weight })
+    }
+
+    /// Finds the enabled group with the highest weighted match, without resolving the pattern.
+    fn best_group(&self, normalized_user_agent: &str) -> Option<(&PatternGroup, f32)> {
+        let mut best: Option<(&PatternGroup, f32)> = None;
+        for group in self.groups.iter().filter(|group| group.is_enabled()) {
+            if let Some(weight) = group.best_weight(normalized_user_agent) {
+                if best.is_none_or(|(_, best)| weight > best) {
+                    best = Some((group, weight));
+                }
+                if weight >= 1.0 {
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    /// Same as [`BotDetector::check`], together with the parsed user-agent the
+    /// [`useragent::ContextRules`] were evaluated on.
+    #[cfg(feature = "ua-parser")]
+    pub fn check_parsed(&self, user_agent: &str) -> useragent::ParsedVerdict {
+        useragent::ParsedVerdict { verdict: self.check(user_agent), user_agent: useragent::UserAgent::parse(user_agent) }
+    }
+
+    /// Raises the verdict to the strictest one of the context rules holding for the user-agent.
+    #[cfg(feature = "ua-parser")]
+    fn contextual_verdict(&self, user_agent: &str, verdict: Verdict, parsed: &useragent::UserAgent) -> Verdict {
+        match self.options.context_rules.evaluate(parsed, user_agent) {
+            Some(contextual) if contextual.rank() > verdict.rank() => contextual,
+            _ => verdict,
+        }
+    }
+
+    /// Registers a hook called with every user-agent detected as a bot.
+    ///
+    /// Hooks run synchronously inside the check, slow consumers should queue the event, like
+    /// [`events::WebhookNotifier::hook`] does.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let seen = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = Arc::clone(&seen);
+    /// let mut BotDetector = BotDetector::default();
+    /// BotDetector.on_detection(move |event| sink.lock().unwrap().push(event.user_agent.clone()));
+    ///
+    /// BotDetector.check_bot_from("Googlebot/2.1", "66.249.66.1".parse().unwrap());
+    /// BotDetector.check_bot("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7)");
+    /// assert_eq!(*seen.lock().unwrap(), vec!["Googlebot/2.1".to_string()]);
+    /// ```
+    pub fn on_detection<F: FnMut(&BotEvent) + Send + 'static>(&mut self, hook: F) {
+        self.detection_hooks.push(DetectionHook::new(hook));
+    }
+
+    /// Recognizes programmatic HTTP clients like curl, python-requests or okhttp.
+    ///
+    /// Independent of the loaded patterns, so crawlers and HTTP clients can be told apart even
+    /// with the [`clients::HTTP_CLIENTS_GROUP`] disabled, e.g. to throttle scripts instead of
+    /// blocking them.
+    ///
+    /// ```
+    /// use BotGuardLib::clients::{ClientLibrary, HTTP_CLIENTS_GROUP};
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let mut BotDetector = BotDetector::default();
+    /// BotDetector.disable_group(HTTP_CLIENTS_GROUP);
+    /// assert!(!BotDetector.check_bot("python-requests/2.31.0"));
+    /// assert_eq!(BotDetector.check_http_client("python-requests/2.31.0"), Some(ClientLibrary::PythonRequests));
+    /// assert_eq!(BotDetector.check_http_client("Googlebot/2.1"), None);
+    /// ```
+    pub fn check_http_client(&self, user_agent: &str) -> Option<clients::ClientLibrary> {
+        clients::http_client(user_agent)
+    }
+
+    /// Returns `true` if the user-agent is an email harvester or comment spam bot of the bundled
+    /// [`spam::SPAM_BOTS_GROUP`], whether or not the group is enabled or loaded in this detector.
+    /// Spam bots with a browser user-agent are only found by their posts, see
+    /// [`spam::ContentAnalyzer`].
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let BotDetector = BotDetector::new("[search-engines]\ngooglebot");
+    /// assert!(BotDetector.check_spam_bot("Mozilla/4.0 (compatible; EmailSiphon)"));
+    /// assert!(!BotDetector.check_spam_bot("Googlebot/2.1"));
+    /// assert_eq!(BotDetector::default().classify("XRumer 7.0"), Some("spam-bots"));
+    /// ```
+    pub fn check_spam_bot(&self, user_agent: &str) -> bool {
+        spam::is_spam_bot(user_agent)
+    }
+
+    /// Recognizes feed readers and podcast apps, with the subscriber count hosted readers report.
+    /// They are classified into the [`feeds::FEED_READERS_GROUP`], for feed policies telling
+    /// them apart from scrapers of the same endpoints.
+    ///
+    /// ```
+    /// use BotGuardLib::feeds::{FeedClient, FEED_READERS_GROUP};
+    /// use BotGuardLib::policy::{Action, Condition, PolicyEngine, PolicyInput, PolicyRule};
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let BotDetector = BotDetector::default();
+    /// let feedly = "Feedly/1.0 (+http://www.feedly.com/fetcher.html; 523 subscribers; like FeedFetcher-Google)";
+    /// let reader = BotDetector.check_feed_reader(feedly).unwrap();
+    /// assert_eq!((reader.client, reader.subscribers), (FeedClient::Feedly, Some(523)));
+    /// assert_eq!(BotDetector.check_feed_reader("python-requests/2.31"), None);
+    ///
+    /// let feeds = PolicyEngine::new(Action::Allow)
+    ///     .rule(PolicyRule::new("/feed.xml", Action::Allow).when(Condition::Category(FEED_READERS_GROUP.to_string())))
+    ///     .rule(PolicyRule::new("/feed.xml", Action::Block).when(Condition::MinScore(0.8)));
+    /// let fetch = |user_agent| PolicyInput::new("/feed.xml").category(BotDetector.classify(user_agent)).score(1.0);
+    /// assert_eq!(feeds.evaluate(&fetch(feedly)).action, Action::Allow);
+    /// assert_eq!(feeds.evaluate(&fetch("python-requests/2.31")).action, Action::Block);
+    /// ```
+    pub fn check_feed_reader(&self, user_agent: &str) -> Option<feeds::FeedReader> {
+        feeds::feed_reader(user_agent)
+    }
+
+    /// Checks a `Referer` header against the bundled referrer spam domains and patterns, see
+    /// [`referrer::ReferrerFilter`] for custom lists.
+    ///
+    /// ```
+    /// use BotGuardLib::referrer::ReferrerIssue;
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let BotDetector = BotDetector::default();
+    /// assert_eq!(BotDetector.check_referrer("https://duckduckgo.com/"), None);
+    /// assert_eq!(BotDetector.check_referrer("http://semalt.com/"), Some(ReferrerIssue::SpamDomain("semalt.com".to_string())));
+    /// ```
+    pub fn check_referrer(&self, referrer: &str) -> Option<referrer::ReferrerIssue> {
+        referrer::bundled().check(referrer)
+    }
+
+    /// Returns the name of the first enabled group matching the user-agent, in declaration order.
+    ///
+    /// ```
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let BotDetector = BotDetector::default();
+    /// assert_eq!(BotDetector.classify("Mozilla/5.0 (compatible; bingbot/2.0)"), Some("search-engines"));
+    /// assert_eq!(BotDetector.classify("python-requests/2.31"), Some("http-clients"));
+    /// assert_eq!(BotDetector.classify("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7)"), None);
+    /// ```
+    pub fn classify(&self, user_agent: &str) -> Option<&str> {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        let normalized_user_agent = self.normalize_user_agent_in(user_agent, &mut buffer);
+        let found = self.matching_group(&normalized_user_agent);
+
+        #[cfg(feature = "tracing")]
+        trace::decision(
+            "classify",
+            &normalized_user_agent,
+            found.and_then(|(_, group)| group.matched_pattern(&normalized_user_agent)),
+            found.map(|(name, _)| name),
+            found.is_some(),
+            None,
+        );
+
+        found.map(|(name, _)| name)
+    }
+
+    /// [`BotDetector::classify`] without recording a decision, for callers recording their own.
+    #[cfg(feature = "server")]
+    pub(crate) fn classify_untraced(&self, user_agent: &str) -> Option<&str> {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        self.matching_group(&self.normalize_user_agent_in(user_agent, &mut buffer)).map(|(name, _)| name)
+    }
+
+    /// [`BotDetector::classify`] with the [`policy::recommended_action`] for the group, for sites
+    /// without a policy of their own.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use BotGuardLib::policy::Action;
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let BotDetector = BotDetector::default();
+    /// let ahrefs = "Mozilla/5.0 (compatible; AhrefsBot/7.0; +http://ahrefs.com/robot/)";
+    /// assert_eq!(BotDetector.classify_action(ahrefs), Some(("seo-tools", Action::RateLimit { requests: 30, per: Duration::from_secs(60) })));
+    /// assert_eq!(BotDetector.classify_action("MJ12bot/v1.4.8").map(|(group, _)| group), Some("seo-tools"));
+    /// assert_eq!(BotDetector.classify_action("Mozilla/5.0 (compatible; bingbot/2.0)"), Some(("search-engines", Action::Allow)));
+    /// assert_eq!(BotDetector.classify_action("python-requests/2.31").map(|(_, action)| action), Some(Action::Block));
+    /// ```
+    pub fn classify_action(&self, user_agent: &str) -> Option<(&str, policy::Action)> {
+        self.classify(user_agent).map(|group| (group, policy::recommended_action(group)))
+    }
+
+    fn detect(&self, user_agent: &str, ip: Option<IpAddr>) -> Verdict {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        let normalized_user_agent = self.normalize_user_agent_in(user_agent, &mut buffer);
+        let empty = normalized_user_agent.trim().is_empty();
+        let found = if empty { None } else { self.best_group(&normalized_user_agent) };
+        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+        let (verdict, score) = self.judge(user_agent, &normalized_user_agent, found.map(|(_, weight)| weight));
+        let resolve = || found.and_then(|(group, _)| group.best_match(&normalized_user_agent).map(|(pattern, weight)| PatternMatch { group: group.name(), pattern, weight }));
+        let verdict = self.conclude(user_agent, ip, verdict, resolve);
+
+        #[cfg(feature = "tracing")]
+        trace::decision(
+            "check_bot",
+            &normalized_user_agent,
+            resolve().map(|found| found.pattern),
+            found.map(|(group, _)| group.name()),
+            verdict.is_bot(),
+            (!empty).then_some(score),
+        );
+
+        verdict
+    }
+
+    /// [`BotDetector::check_bot_from`] together with the score and the pattern they are based on,
+    /// found in a single pass over the patterns, for [`request::BotGuard::evaluate`].
+    pub(crate) fn assess(&self, user_agent: &str, ip: Option<IpAddr>) -> (Verdict, f32, Option<PatternMatch<'_>>) {
+        let mut buffer = [0; INLINE_USER_AGENT];
+        let normalized_user_agent = self.normalize_user_agent_in(user_agent, &mut buffer);
+        let found = if normalized_user_agent.trim().is_empty() { None } else { self.best_match(&normalized_user_agent) };
+        let (verdict, score) = self.judge(user_agent, &normalized_user_agent, found.map(|found| found.weight));
+        (self.conclude(user_agent, ip, verdict, || found), score, found)
+    }
 
-    fn update_regex(&mut self) {
-        self.user_agents_regex = BotDetector::to_regex(&self.user_agent_patterns)
+    /// The verdict and score of a user-agent whose best match weighs `weight`, before blocked
+    /// addresses are taken into account. Empty user-agents are judged by the [`EmptyUaPolicy`].
+    #[cfg_attr(not(feature = "ua-parser"), allow(unused_variables))]
+    fn judge(&self, user_agent: &str, normalized_user_agent: &str, weight: Option<f32>) -> (Verdict, f32) {
+        if normalized_user_agent.trim().is_empty() {
+            return (self.options.empty_ua_policy.verdict(), self.empty_ua_score());
+        }
+        let score = weight.unwrap_or_else(|| score::heuristic(normalized_user_agent));
+        let verdict = self.options.thresholds.verdict(score);
+        #[cfg(feature = "ua-parser")]
+        let verdict = if self.options.context_rules.is_empty() {
+            verdict
+        } else {
+            self.contextual_verdict(user_agent, verdict, &useragent::UserAgent::parse(user_agent))
+        };
+        (verdict, score)
     }
 
-    fn parse_lines(bot_regex_entries: &str) -> HashSet<String> {
-        HashSet::from_iter(
-            bot_regex_entries
-                .lines()
-                .filter(|l| !l.trim().is_empty())
-                .map(ToString::to_string),
-        )
+    /// The score of empty user-agents, by the verdict of the [`EmptyUaPolicy`].
+    fn empty_ua_score(&self) -> f32 {
+        match self.options.empty_ua_policy.verdict() {
+            Verdict::Bot => 1.0,
+            Verdict::Suspicious => 0.5,
+            Verdict::Human => 0.0,
+        }
     }
 
-    fn to_regex(regex_entries: &HashSet<String>) -> Regex {
-        let pattern = regex_entries
+    /// Raises the verdict of blocked addresses and calls the detection hooks for bots, with the
+    /// pattern `found` resolves to.
+    fn conclude<'a>(&'a self, user_agent: &str, ip: Option<IpAddr>, verdict: Verdict, found: impl FnOnce() -> Option<PatternMatch<'a>>) -> Verdict {
+        let verdict = match ip {
+            Some(ip) if self.expiries.is_blocked(ip, Instant::now()) => Verdict::Bot,
+            _ => verdict,
+        };
+        if verdict.is_bot() && !self.detection_hooks.is_empty() {
+            let found = found();
+            let event = BotEvent {
+                user_agent: user_agent.to_string(),
+                ip,
+                matched_pattern: found.map(|found| found.pattern.to_string()),
+                category: found.map(|found| found.group.to_string()),
+                timestamp: SystemTime::now(),
+            };
+            for hook in &self.detection_hooks {
+                hook.call(&event);
+            }
+        }
+        verdict
+    }
+
+    /// Finds the first enabled group matching an already normalized user-agent.
+    fn matching_group(&self, normalized_user_agent: &str) -> Option<(&str, &PatternGroup)> {
+        self.groups
             .iter()
-            .cloned()
-            .collect::<Vec<String>>()
-            .join("|");
+            .find(|group| group.is_enabled() && group.is_match(normalized_user_agent))
+            .map(|group| (group.name(), group))
+    }
+
+    /// Returns the pattern responsible for the match in an already normalized user-agent.
+    #[cfg(test)]
+    pub(crate) fn matched_pattern(&self, normalized_user_agent: &str) -> Option<&str> {
+        let (_, group) = self.matching_group(normalized_user_agent)?;
+        group.matched_pattern(normalized_user_agent)
+    }
+
+    /// What the verdict of [`BotDetector::check`] depends on: the normalized user-agent, or the
+    /// user-agent as sent if context rules look at its unnormalized form.
+    pub(crate) fn verdict_key<'a>(&self, user_agent: &'a str, buffer: &'a mut UserAgentBuffer) -> Cow<'a, str> {
+        #[cfg(feature = "ua-parser")]
+        if !self.options.context_rules.is_empty() {
+            return Cow::Borrowed(user_agent);
+        }
+        self.normalize_user_agent_in(user_agent, buffer)
+    }
+
+    /// Applies the input length cap and the [`normalize::Normalization`], then lowercases the
+    /// user-agent unless the detector is case-sensitive. The regexes ignore case on their own,
+    /// the lowercasing is for the string search of plain-text patterns and for cache keys.
+    fn normalize_user_agent<'a>(&self, user_agent: &'a str) -> Cow<'a, str> {
+        self.lowercase_user_agent(self.options.normalization.apply(self.truncate_user_agent(user_agent)), None)
+    }
+
+    /// Same as [`BotDetector::normalize_user_agent`] without allocating for user-agents up to
+    /// [`INLINE_USER_AGENT`] bytes: they are lowercased into `buffer` on the caller's stack.
+    fn normalize_user_agent_in<'a>(&self, user_agent: &'a str, buffer: &'a mut UserAgentBuffer) -> Cow<'a, str> {
+        self.lowercase_user_agent(self.options.normalization.apply(self.truncate_user_agent(user_agent)), Some(buffer))
+    }
 
-        if pattern.is_empty() {
-            return Regex::new("^$").unwrap();
+    /// Lowercases in place where it can: user-agents without uppercase letters are passed through,
+    /// folded ones are already owned, short ones are copied into the buffer.
+    fn lowercase_user_agent<'a>(&self, user_agent: Cow<'a, str>, buffer: Option<&'a mut UserAgentBuffer>) -> Cow<'a, str> {
+        if self.options.case_sensitive || !user_agent.bytes().any(|b| b.is_ascii_uppercase()) {
+            return user_agent;
+        }
+        match (user_agent, buffer) {
+            (Cow::Owned(mut user_agent), _) => {
+                user_agent.make_ascii_lowercase();
+                Cow::Owned(user_agent)
+            }
+            (Cow::Borrowed(user_agent), Some(buffer)) if user_agent.len() <= buffer.len() => {
+                let copy = &mut buffer[..user_agent.len()];
+                copy.copy_from_slice(user_agent.as_bytes());
+                let copy = std::str::from_utf8_mut(copy).expect("copied from a str");
+                copy.make_ascii_lowercase();
+                Cow::Borrowed(copy)
+            }
+            (Cow::Borrowed(user_agent), _) => Cow::Owned(user_agent.to_ascii_lowercase()),
         }
+    }
+
+    /// Cuts the user-agent to the configured maximum input length.
+    fn truncate_user_agent<'a>(&self, user_agent: &'a str) -> &'a str {
+        match self.options.max_input_len {
+            Some(max) if user_agent.len() > max => &user_agent[..(0..=max).rev().find(|&end| user_agent.is_char_boundary(end)).unwrap_or(0)],
+            _ => user_agent,
+        }
+    }
+
+    /// What patterns are looked up by, see [`group::pattern_key`].
+    pub(crate) fn pattern_key<'a>(&self, pattern: &'a str) -> Cow<'a, str> {
+        group::pattern_key(pattern, self.options.case_sensitive)
+    }
 
-        Regex::new(&pattern).unwrap()
+    /// Splits the entries into groups named by the preceding `[name]` header, in order of first appearance.
+    ///
+    /// Group names are lowercased, the patterns are returned as written and in order with their
+    /// optional weight.
+    fn parse_lines(bot_regex_entries: &str) -> Vec<EntryGroup> {
+        let mut groups = Vec::<(String, Vec<(String, Option<f32>)>)>::new();
+        let mut current = CUSTOM_GROUP.to_string();
+        for line in bot_regex_entries.lines().filter(|l| !l.trim().is_empty()) {
+            let header = group_header(line);
+            if let Some(name) = header {
+                current = name.to_ascii_lowercase();
+            }
+            let index = match groups.iter().position(|(name, _)| *name == current) {
+                Some(index) => index,
+                None => {
+                    groups.push((current.clone(), Vec::new()));
+                    groups.len() - 1
+                }
+            };
+            if header.is_none() {
+                let (pattern, weight) = weighted_entry(line);
+                groups[index].1.push((pattern.to_string(), weight));
+            }
+        }
+        groups
     }
+
 }
 
+#[cfg(feature = "std")]
 #[cfg(test)]
+#[allow(non_upper_case_globals)]
 mod tests_BotDetector {
-    use crate::BotDetector;
+    use crate::{BotDetector, EmptyUaPolicy, Verdict, CUSTOM_GROUP};
 
     static G_BotDetector: [&str; 7] = [
         "Googlebot",
@@ -197,11 +1542,29 @@ mod tests_BotDetector {
     fn empty_user_agent_patterns() {
         let empty_user_agent_patterns = "";
         let BotDetector = BotDetector::new(empty_user_agent_patterns);
-        assert!(BotDetector.check_bot(""));
+        assert!(!BotDetector.check_bot(""));
         assert!(!BotDetector.check_bot("1"));
         assert!(!BotDetector.check_bot("Googlebot"));
     }
 
+    #[test]
+    fn empty_user_agent_policy() {
+        for patterns in ["", "googlebot"] {
+            let treat_as_bot =
+                BotDetector::builder().patterns(patterns).empty_ua_policy(EmptyUaPolicy::TreatAsBot).build().unwrap();
+            assert!(treat_as_bot.check_bot(""));
+            assert!(treat_as_bot.check_bot("  "));
+            assert!(!treat_as_bot.check_bot("1"));
+
+            let suspicious =
+                BotDetector::builder().patterns(patterns).empty_ua_policy(EmptyUaPolicy::Suspicious).build().unwrap();
+            assert_eq!(suspicious.check(""), Verdict::Suspicious);
+            assert!(!suspicious.check_bot(""));
+
+            assert_eq!(BotDetector::new(patterns).check(""), Verdict::Human);
+        }
+    }
+
     #[test]
     fn single_user_agent_patterns() {
         let single_user_agent_patterns = "me";
@@ -215,7 +1578,7 @@ mod tests_BotDetector {
     fn add_pattern() {
         let mut BotDetector = BotDetector::default();
         assert!(!BotDetector.check_bot("Mozilla/5.0 (FancyNewTestB0T /1.2)"));
-        BotDetector.append(&[r"TestCatalyzeBot\s/\d\.\d"]);
+        BotDetector.append(&[r"FancyNewTestB0T\s/\d\.\d"]).unwrap();
         assert!(BotDetector.check_bot("Mozilla/5.0 (FancyNewTestB0T /1.2)"));
     }
 
@@ -226,8 +1589,8 @@ mod tests_BotDetector {
         assert!(!BotDetector.check_bot("Special/1.0"));
         assert!(!BotDetector.check_bot("GoogleMetaverse/2.1 (experimental)"));
 
-        let new__PATTERNS = vec!["TestCatalyzeBot", "^GoogleMetaverse", "^Special/"];
-        BotDetector.append(&new__PATTERNS);
+        let new__PATTERNS = vec!["FancyNewTestB0T", "^GoogleMetaverse", "^Special/"];
+        BotDetector.append(&new__PATTERNS).unwrap();
 
         assert!(BotDetector.check_bot("Mozilla/5.0 (FancyNewTestB0T /1.2)"));
         assert!(BotDetector.check_bot("Special/1.0"));
@@ -280,4 +1643,301 @@ mod tests_BotDetector {
     
 
     }
+
+    #[test]
+    fn matched_pattern() {
+        let BotDetector = BotDetector::new("(?i)zzz\nspecialbot/\\d");
+        assert_eq!(BotDetector.matched_pattern("mozilla/5.0 specialbot/2"), Some("specialbot/\\d"));
+        assert_eq!(BotDetector.matched_pattern("mozilla/5.0"), None);
+    }
+
+    #[test]
+    fn pattern_groups() {
+        let mut BotDetector = BotDetector::new("^legacybot\n[ai-bots]\ngptbot\n[search-engines]\ngooglebot\n[empty]");
+        assert_eq!(BotDetector.groups().collect::<Vec<_>>(), vec![CUSTOM_GROUP, "ai-bots", "search-engines", "empty"]);
+        assert_eq!(BotDetector.classify("LegacyBot/1.0"), Some(CUSTOM_GROUP));
+        assert_eq!(BotDetector.classify("GPTBot/1.0"), Some("ai-bots"));
+
+        assert!(BotDetector.disable_group("AI-Bots"));
+        assert!(!BotDetector.is_group_enabled("ai-bots"));
+        assert!(!BotDetector.check_bot("GPTBot/1.0"));
+        assert!(BotDetector.check_bot("Googlebot"));
+        assert!(!BotDetector.disable_group("missing"));
+
+        BotDetector.append_to_group("ai-bots", &["ClaudeBot"]).unwrap();
+        assert!(!BotDetector.check_bot("ClaudeBot/1.0"));
+        assert!(BotDetector.enable_group("ai-bots"));
+        assert!(BotDetector.check_bot("ClaudeBot/1.0"));
+
+        BotDetector.remove(&["googlebot", "^legacybot"]);
+        assert_eq!(BotDetector.classify("Googlebot"), None);
+        assert_eq!(BotDetector.classify("LegacyBot/1.0"), None);
+    }
+
+    #[test]
+    fn inspect_patterns() {
+        let mut BotDetector = BotDetector::new("");
+        assert!(BotDetector.is_empty());
+        assert_eq!(BotDetector.len(), 0);
+
+        BotDetector.append(&["CustomBot", "otherbot"]).unwrap();
+        BotDetector.append_to_group("extra", &["custombot"]).unwrap();
+        assert!(BotDetector.contains_pattern("CUSTOMBOT"));
+        assert!(!BotDetector.contains_pattern("missing"));
+        assert_eq!(BotDetector.len(), 2);
+        assert_eq!(BotDetector.group_patterns("extra").unwrap().collect::<Vec<_>>(), vec!["custombot"]);
+        assert!(BotDetector.group_patterns("missing").is_none());
+
+        BotDetector.disable_group(CUSTOM_GROUP);
+        assert_eq!(BotDetector.len(), 2);
+        BotDetector.remove(&["custombot", "otherbot"]);
+        assert!(BotDetector.is_empty());
+    }
+
+    #[test]
+    fn merge_and_diff() {
+        let mut base = BotDetector::new("[search-engines]\ngooglebot\n[ai-bots]\ngptbot");
+        let mut overrides = BotDetector::new("[search-engines]\nbingbot\n[scrapers]\nscrapy");
+        overrides.disable_group("scrapers");
+        base.disable_group("ai-bots");
+
+        let diff = base.diff(&overrides);
+        assert_eq!(diff.added, vec!["bingbot", "scrapy"]);
+        assert_eq!(diff.removed, vec!["googlebot", "gptbot"]);
+        assert!(!diff.is_empty());
+
+        base.merge(&overrides).unwrap();
+        assert!(base.check_bot("bingbot"));
+        assert!(!base.is_group_enabled("scrapers"));
+        assert!(!base.is_group_enabled("ai-bots"));
+        assert_eq!(base.diff(&overrides).added, Vec::<String>::new());
+        assert!(base.diff(&base).is_empty());
+    }
+
+    #[test]
+    fn escapes_survive_lowercasing() {
+        let BotDetector = BotDetector::new("^Build\\D+$\nversion\\S");
+        assert!(BotDetector.check_bot("build-x"));
+        assert!(!BotDetector.check_bot("build7"));
+        assert!(BotDetector.check_bot("Version7"));
+        assert!(BotDetector.contains_pattern("VERSION\\S"));
+    }
+
+    #[test]
+    fn patterns_are_compiled_as_written() {
+        let BotDetector = BotDetector::new("[tools]\nScanner/(?P<Version>[\\d.]+)\n\\x41crawler\nÉditeurBot");
+        let found = BotDetector.find("scanner/2.1").unwrap();
+        assert_eq!((found.pattern, found.capture("Version").unwrap().text), ("Scanner/(?P<Version>[\\d.]+)", "2.1"));
+        assert!(BotDetector.check_bot("ACrawler/1.0") && BotDetector.check_bot("acrawler/1.0"));
+        assert!(BotDetector.check_bot("éditeurbot/3"));
+        assert!(BotDetector.contains_pattern("scanner/(?P<Version>[\\d.]+)") && !BotDetector.contains_pattern("scanner/(?P<version>[\\d.]+)"));
+        assert_eq!(BotDetector.group_patterns("tools").unwrap().next(), Some("Scanner/(?P<Version>[\\d.]+)"));
+    }
+
+    #[test]
+    fn pattern_like_headers_stay_patterns() {
+        let BotDetector = BotDetector::new("[0-9]{12}");
+        assert!(BotDetector.check_bot("123456789012"));
+        assert_eq!(BotDetector.groups().collect::<Vec<_>>(), vec![CUSTOM_GROUP]);
+    }
+
+    #[test]
+    fn bundle_versions_and_rollback() {
+        let mut BotDetector = BotDetector::new("[search-engines]\ngooglebot\n[monitoring]\npingdom");
+        let initial = BotDetector.current_version().clone();
+        assert_eq!(initial.version.len(), 16);
+        assert_eq!(BotDetector.previous_version(), None);
+
+        let bundle = crate::source::PatternBundle::new("[monitoring]\npingdom\nuptimerobot".to_string(), "test").with_version("2024-06-01");
+        BotDetector.reload_bundle(&bundle).unwrap();
+        assert_eq!(BotDetector.current_version().version, "2024-06-01");
+        assert_eq!(BotDetector.previous_version(), Some(&initial));
+        BotDetector.disable_group("monitoring");
+
+        assert!(BotDetector.rollback());
+        assert_eq!(BotDetector.current_version(), &initial);
+        assert!(BotDetector.check_bot("Googlebot"));
+        assert!(!BotDetector.check_bot("Pingdom.com_bot"));
+        assert!(!BotDetector.rollback());
+    }
+
+    #[test]
+    fn weighted_scores() {
+        let mut BotDetector = BotDetector::new("[scanners]\n^sqlmap\n[http-clients]\npython-requests\nrequests/");
+        assert!(BotDetector.set_weight("Python-Requests", 0.3));
+        assert!(!BotDetector.set_weight("missing", 0.3));
+        assert_eq!(BotDetector.score("python-requests/2.31"), 1.0);
+        BotDetector.set_weight("requests/", 0.6);
+        assert_eq!(BotDetector.score("python-requests/2.31"), 0.6);
+        assert_eq!(BotDetector.check("python-requests/2.31"), Verdict::Suspicious);
+
+        BotDetector.remove(&["requests/"]);
+        assert_eq!(BotDetector.score("python-requests/2.31"), 0.3);
+        BotDetector.set_weight("python-requests", 7.0);
+        assert_eq!(BotDetector.score("python-requests/2.31"), 1.0);
+        BotDetector.disable_group("http-clients");
+        assert_eq!(BotDetector.score("python-requests/2.31"), 0.4);
+        assert_eq!(BotDetector.score(" "), 0.0);
+    }
+
+    #[test]
+    fn weighted_patterns_feed_the_suspicious_tier() {
+        use std::sync::{Arc, Mutex};
+
+        let mut BotDetector = BotDetector::new("[http-clients]\n0.6 python-requests\n[scanners]\nsqlmap");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        BotDetector.on_detection(move |event| sink.lock().unwrap().push(event.category.clone()));
+        assert_eq!(BotDetector.check("python-requests/2.31"), Verdict::Suspicious);
+        assert_eq!(BotDetector.check("python-requests/2.31 sqlmap"), Verdict::Bot);
+        assert_eq!(BotDetector.check("okhttp/4.9"), Verdict::Human);
+        assert_eq!(*seen.lock().unwrap(), vec![Some("scanners".to_string())]);
+    }
+
+    #[test]
+    fn weights_in_entries() {
+        let mut BotDetector = BotDetector::new("0.3 python-requests\n0.5\t^Go-http-client\n1 2\n0.25.1 x\n2.0 y\n0.8 \n^sqlmap");
+        assert_eq!(BotDetector.score("python-requests/2.31"), 0.3);
+        assert_eq!(BotDetector.score("Go-http-client/1.1"), 0.5);
+        assert!(BotDetector.contains_pattern("1 2"));
+        assert!(BotDetector.contains_pattern("0.25.1 x"));
+        assert!(BotDetector.contains_pattern("2.0 y"));
+        assert!(BotDetector.contains_pattern("0.8 "));
+        assert_eq!(BotDetector.find_match("sqlmap and python-requests").map(|found| found.pattern), Some("^sqlmap"));
+
+        let mut merged = BotDetector::new("python-requests");
+        merged.merge(&BotDetector).unwrap();
+        assert_eq!(merged.score("python-requests/2.31"), 0.3);
+        BotDetector.reload("python-requests").unwrap();
+        assert_eq!(BotDetector.score("python-requests/2.31"), 1.0);
+    }
+
+    #[test]
+    fn clones_compare_by_patterns() {
+        let hits = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut original = BotDetector::new("[crawlers]\ngooglebot\n0.4 ^curl/");
+        let counter = hits.clone();
+        original.on_detection(move |_| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        });
+        let mut copy = original.clone();
+        assert!(copy == original && copy.diff(&original).is_empty());
+
+        copy.append(&["wget/"]).unwrap();
+        assert!(copy != original && copy.check_bot("Wget/1.21") && !original.check_bot("Wget/1.21"));
+        assert_eq!(copy.len(), original.len() + 1);
+        assert_eq!(hits.load(std::sync::atomic::Ordering::Relaxed), 1);
+        copy.remove(&["wget/"]);
+        assert!(copy == original && copy.len() == original.len());
+        copy.set_weight("^curl/", 0.3);
+        assert!(copy != original);
+        copy.restore(original.snapshot());
+        assert!(copy == original && copy.rollback() && copy != original);
+        assert!(BotDetector::new("googlebot") != BotDetector::new("[crawlers]\ngooglebot"));
+    }
+
+    #[test]
+    fn keeps_the_generation_without_changes() {
+        let mut BotDetector = BotDetector::new("[crawlers]\ngooglebot\n0.4 ^curl/");
+        let generation = BotDetector.generation();
+        assert!(!BotDetector.disable_group("missing") && BotDetector.enable_group("crawlers"));
+        BotDetector.append_to_group("crawlers", &["googlebot"]).unwrap();
+        assert!(BotDetector.append_to_group("crawlers", &["(broken"]).is_err());
+        BotDetector.remove(&["bingbot"]);
+        assert!(!BotDetector.set_weight("bingbot", 0.5));
+        BotDetector.merge(&BotDetector::new("[crawlers]\n0.4 ^curl/")).unwrap();
+        assert_eq!(BotDetector.prune_expired(), 0);
+        assert_eq!(BotDetector.generation(), generation);
+
+        BotDetector.disable_group("crawlers");
+        assert_ne!(BotDetector.generation(), generation);
+        let generation = BotDetector.generation();
+        BotDetector.merge(&BotDetector::new("[crawlers]\n0.2 ^curl/")).unwrap();
+        assert_ne!(BotDetector.generation(), generation);
+    }
+
+    #[test]
+    fn lowercases_short_user_agents_on_the_stack() {
+        use std::borrow::Cow;
+
+        let BotDetector = BotDetector::new("^curl/");
+        let mut buffer = [0; crate::INLINE_USER_AGENT];
+        let normalized = BotDetector.normalize_user_agent_in("Mozilla/5.0 (X11; Linux x86_64)", &mut buffer);
+        assert!(matches!(normalized, Cow::Borrowed("mozilla/5.0 (x11; linux x86_64)")));
+        let long = format!("curl/8.0 {}", "X".repeat(crate::INLINE_USER_AGENT));
+        assert!(matches!(BotDetector.normalize_user_agent_in(&long, &mut buffer), Cow::Owned(_)));
+        assert!(BotDetector.check_bot(&long) && BotDetector.check_bot("CURL/8.0"));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn traced_decision() {
+        use crate::trace;
+        use std::fmt::{Debug, Write};
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{field::Field, Event, Metadata, Subscriber};
+
+        /// Keeps the fields of every event as a line, and the names of the spans it was in.
+        #[derive(Default, Clone)]
+        struct Recorder {
+            events: Arc<Mutex<Vec<String>>>,
+            spans: Arc<Mutex<Vec<&'static str>>>,
+            entered: Arc<Mutex<Vec<u64>>>,
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut spans = self.spans.lock().unwrap();
+                spans.push(span.metadata().name());
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event<'_>) {
+                let mut line = String::new();
+                for id in self.entered.lock().unwrap().iter() {
+                    let _ = write!(line, "{}: ", self.spans.lock().unwrap()[*id as usize - 1]);
+                }
+                event.record(&mut |field: &Field, value: &dyn Debug| {
+                    let _ = write!(line, "{}={:?} ", field.name(), value);
+                });
+                self.events.lock().unwrap().push(line.trim_end().to_string());
+            }
+            fn enter(&self, span: &Id) {
+                self.entered.lock().unwrap().push(span.into_u64());
+            }
+            fn exit(&self, _: &Id) {
+                self.entered.lock().unwrap().pop();
+            }
+        }
+
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            assert!(BotDetector::new("tracedbot").check_bot("TracedBot/1.0"));
+            assert_eq!(BotDetector::new("0.5 scorebot").score("ScoreBot/2.0"), 0.5);
+            assert_eq!(BotDetector::new("[crawlers]\ngooglebot").classify("Googlebot/2.1"), Some("crawlers"));
+            // the canary compares every request without recording decisions of its own
+            let canary = crate::canary::Canary::new(BotDetector::new("^guardedbot/")).sample(1.0);
+            let guard = crate::request::BotGuard::new(BotDetector::new("[scanners]\n^guardedbot/")).canary(canary);
+            assert!(guard.evaluate(&crate::request::RequestSnapshot::new("GET", "/").user_agent("GuardedBot/1.0")).verdict.is_bot());
+        });
+
+        let hash = |user_agent| format!("{:016x}", trace::ua_hash(user_agent));
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            [
+                format!("message=bot detection decision decision=\"check_bot\" ua_hash={} pattern=\"tracedbot\" category=\"custom\" is_bot=true score=1.0", hash("tracedbot/1.0")),
+                format!("message=bot detection decision decision=\"score\" ua_hash={} pattern=\"scorebot\" category=\"custom\" is_bot=false score=0.5", hash("scorebot/2.0")),
+                format!("message=bot detection decision decision=\"classify\" ua_hash={} pattern=\"googlebot\" category=\"crawlers\" is_bot=true", hash("googlebot/2.1")),
+                format!(
+                    "botguard.evaluate: message=bot detection decision decision=\"evaluate\" ua_hash={} pattern=\"^guardedbot/\" category=\"scanners\" is_bot=true score=1.0",
+                    hash("guardedbot/1.0")
+                ),
+            ]
+        );
+    }
 }
@@ -0,0 +1,324 @@
+// The part of matching that only needs `core` and `alloc`: the entry format, plain-text patterns
+// found with string search and a detector built from them, for targets without the standard
+// library. `BotDetector` reads its entries and matches plain-text patterns with the same code.
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+pub use crate::score::Thresholds;
+use crate::{score, BotGuardError, PatternMatch, Verdict};
+
+/// Group receiving patterns that are not listed under a `[group]` header or are appended without one.
+pub const CUSTOM_GROUP: &str = "custom";
+
+/// Where in the user-agent a pattern is allowed to match.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Anywhere, a plain regex search over the whole user-agent.
+    #[default]
+    Substring,
+    /// Only whole tokens: the match has to start and end at the user-agent start or end, whitespace,
+    /// `;`, `,` or a parenthesis. It may also end at a `/`, so `googlebot` matches `Googlebot/2.1`.
+    Token,
+    /// Only the whole user-agent, as if every pattern was written as `^(?:pattern)$`.
+    Anchored,
+}
+
+/// The name of a `[name]` group header line.
+pub(crate) fn group_header(line: &str) -> Option<&str> {
+    let name = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let valid = !name.is_empty()
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    valid.then_some(name)
+}
+
+/// Splits a leading weight like the `0.3` of `0.3 python-requests/` off an entry.
+pub(crate) fn weighted_entry(line: &str) -> (&str, Option<f32>) {
+    let weighted = line.split_once(char::is_whitespace).and_then(|(weight, pattern)| {
+        let valid = weight.contains('.') && weight.bytes().all(|b| b.is_ascii_digit() || b == b'.');
+        let weight = weight.parse::<f32>().ok().filter(|weight| valid && (0.0..=1.0).contains(weight))?;
+        let pattern = pattern.trim_start();
+        (!pattern.is_empty()).then_some((pattern, Some(weight)))
+    });
+    weighted.unwrap_or((line, None))
+}
+
+/// A plain-text pattern, optionally anchored with `^` and `$`, found with string search.
+#[derive(Debug, Clone)]
+pub(crate) struct Literal {
+    pub(crate) pattern: String,
+    text: String,
+    start: bool,
+    end: bool,
+}
+
+impl Literal {
+    /// `None` unless the pattern only consists of text and escaped punctuation.
+    pub(crate) fn parse(pattern: &str) -> Option<Literal> {
+        let (start, rest) = match pattern.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut text = String::with_capacity(rest.len());
+        let mut end = false;
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => text.push(chars.next().filter(char::is_ascii_punctuation)?),
+                '$' if chars.as_str().is_empty() => end = true,
+                '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' => return None,
+                c => text.push(c),
+            }
+        }
+        (!text.is_empty()).then(|| Literal { pattern: pattern.to_string(), text, start, end })
+    }
+
+    /// [`Literal::parse`] for user-agents lowercased unless `case_sensitive`, with the text
+    /// lowercased as well. Text with other than ASCII characters is left to the regexes, they
+    /// fold its case where lowercasing ASCII does not.
+    #[cfg(feature = "std")]
+    pub(crate) fn parse_folded(pattern: &str, case_sensitive: bool) -> Option<Literal> {
+        let mut literal = Literal::parse(pattern)?;
+        if !case_sensitive {
+            if !literal.text.is_ascii() {
+                return None;
+            }
+            literal.text.make_ascii_lowercase();
+        }
+        Some(literal)
+    }
+
+    /// The start of the leftmost match in an already normalized user-agent.
+    pub(crate) fn find(&self, user_agent: &str, match_mode: MatchMode) -> Option<usize> {
+        let before = |i: usize| user_agent[..i].chars().next_back();
+        let after = |i: usize| user_agent[i + self.text.len()..].chars().next();
+        let token = |c: Option<char>, slash: bool| c.is_none_or(|c| c.is_whitespace() || ";,()".contains(c) || (slash && c == '/'));
+        user_agent.match_indices(&self.text).map(|(i, _)| i).find(|&i| {
+            let anchored = (!self.start || i == 0) && (!self.end || i + self.text.len() == user_agent.len());
+            anchored
+                && match match_mode {
+                    MatchMode::Substring => true,
+                    MatchMode::Token => token(before(i), false) && token(after(i), true),
+                    MatchMode::Anchored => i == 0 && self.text.len() == user_agent.len(),
+                }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LiteralGroup {
+    name: String,
+    /// Patterns with their weights, in the order they were written.
+    patterns: Vec<(Literal, f32)>,
+}
+
+/// Detects bots by plain-text patterns using only `core` and `alloc`, for builds without the
+/// `std` feature such as embedded API gateways.
+///
+/// Reads the entry format of `BotDetector::new`, with `[group]` headers and weights, but only
+/// plain-text patterns like `googlebot` or `^curl/` with `\` escaping punctuation; all bundled
+/// patterns are plain text. Patterns and user-agents are compared ignoring ASCII case, matches
+/// score the pattern weight and user-agents no pattern matches get the same partial score for
+/// tool-like names as `BotDetector::score`. Empty user-agents are human.
+///
+/// ```
+/// use BotGuardLib::literal::{LiteralDetector, Thresholds};
+/// use BotGuardLib::Verdict;
+///
+/// let detector = LiteralDetector::new("[search-engines]\nGooglebot\n[http-clients]\n0.6 ^python-requests/").unwrap();
+/// assert_eq!(detector.check("Mozilla/5.0 (compatible; Googlebot/2.1)"), Verdict::Bot);
+/// assert_eq!(detector.check("python-requests/2.31"), Verdict::Suspicious);
+/// assert_eq!(detector.classify("python-requests/2.31"), Some("http-clients"));
+/// assert_eq!(detector.check("Mozilla/5.0 (X11; Linux x86_64) Firefox/120.0"), Verdict::Human);
+///
+/// let strict = detector.thresholds(Thresholds { suspicious: 0.3, bot: 0.5 });
+/// assert_eq!(strict.check("python-requests/2.31"), Verdict::Bot);
+/// assert!(LiteralDetector::new("bot\\d+").is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct LiteralDetector {
+    groups: Vec<LiteralGroup>,
+    case_sensitive: bool,
+    match_mode: MatchMode,
+    thresholds: Thresholds,
+}
+
+impl Default for LiteralDetector {
+    /// Constructs a new instance with the bundled patterns, empty without the
+    /// `include-default-BotDetector` feature.
+    fn default() -> Self {
+        LiteralDetector::new(crate::_PATTERNS).expect("the bundled patterns are plain text")
+    }
+}
+
+impl LiteralDetector {
+    /// Reads newline-delimited entries, failing on the first pattern that is not plain text.
+    pub fn new(entries: &str) -> Result<Self, BotGuardError> {
+        let mut groups = Vec::<LiteralGroup>::new();
+        let mut current = CUSTOM_GROUP.to_string();
+        for line in entries.lines().filter(|line| !line.trim().is_empty()) {
+            if let Some(name) = group_header(line) {
+                current = name.to_ascii_lowercase();
+                continue;
+            }
+            let (pattern, weight) = weighted_entry(line);
+            let literal = Literal::parse(pattern).ok_or_else(|| BotGuardError::InvalidPattern {
+                pattern: pattern.to_string(),
+                reason: "only plain-text patterns, optionally anchored with ^ and $, are supported".to_string(),
+            })?;
+            let index = match groups.iter().position(|group| group.name == current) {
+                Some(index) => index,
+                None => {
+                    groups.push(LiteralGroup { name: current.clone(), patterns: Vec::new() });
+                    groups.len() - 1
+                }
+            };
+            let patterns = &mut groups[index].patterns;
+            if !patterns.iter().any(|(existing, _)| existing.pattern == literal.pattern) {
+                patterns.push((literal, weight.unwrap_or(1.0)));
+            }
+        }
+        let mut detector = LiteralDetector { groups, case_sensitive: true, match_mode: MatchMode::default(), thresholds: Thresholds::default() };
+        detector = detector.case_sensitive(false);
+        Ok(detector)
+    }
+
+    /// Matches patterns with their case as written instead of ignoring ASCII case.
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        if case_sensitive != self.case_sensitive {
+            for (literal, _) in self.groups.iter_mut().flat_map(|group| group.patterns.iter_mut()) {
+                let parsed = Literal::parse(&literal.pattern).expect("parsed before");
+                literal.text = if case_sensitive { parsed.text } else { parsed.text.to_ascii_lowercase() };
+            }
+            self.case_sensitive = case_sensitive;
+        }
+        self
+    }
+
+    /// Chooses where in the user-agent a pattern may match, see [`MatchMode`].
+    pub fn match_mode(mut self, match_mode: MatchMode) -> Self {
+        self.match_mode = match_mode;
+        self
+    }
+
+    /// Sets the scores from which [`LiteralDetector::check`] reports suspicious clients and bots.
+    pub fn thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// The number of patterns in all groups.
+    pub fn len(&self) -> usize {
+        self.groups.iter().map(|group| group.patterns.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Group names in declaration order.
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.groups.iter().map(|group| group.name.as_str())
+    }
+
+    pub fn check(&self, user_agent: &str) -> Verdict {
+        if user_agent.trim().is_empty() {
+            return Verdict::Human;
+        }
+        self.thresholds.verdict(self.score(user_agent))
+    }
+
+    pub fn check_bot(&self, user_agent: &str) -> bool {
+        self.check(user_agent).is_bot()
+    }
+
+    /// The weight of the best matching pattern, or a partial score for tool-like user-agents.
+    pub fn score(&self, user_agent: &str) -> f32 {
+        if user_agent.trim().is_empty() {
+            return 0.0;
+        }
+        let user_agent = self.normalize(user_agent);
+        match self.best_match(&user_agent) {
+            Some(found) => found.weight,
+            None => score::heuristic(&user_agent),
+        }
+    }
+
+    /// The matching pattern with the highest weight. Among equal weights the earliest group and
+    /// the leftmost match win.
+    pub fn find_match(&self, user_agent: &str) -> Option<PatternMatch<'_>> {
+        self.best_match(&self.normalize(user_agent))
+    }
+
+    /// The name of the first group matching the user-agent, in declaration order.
+    pub fn classify(&self, user_agent: &str) -> Option<&str> {
+        let user_agent = self.normalize(user_agent);
+        let matches = |group: &&LiteralGroup| group.patterns.iter().any(|(literal, _)| literal.find(&user_agent, self.match_mode).is_some());
+        self.groups.iter().find(matches).map(|group| group.name.as_str())
+    }
+
+    fn best_match(&self, user_agent: &str) -> Option<PatternMatch<'_>> {
+        let mut best: Option<PatternMatch<'_>> = None;
+        for group in &self.groups {
+            let mut group_best: Option<(usize, &Literal, f32)> = None;
+            for (literal, weight) in &group.patterns {
+                let Some(start) = literal.find(user_agent, self.match_mode) else { continue };
+                if group_best.is_none_or(|(best_start, _, best)| *weight > best || (*weight == best && start < best_start)) {
+                    group_best = Some((start, literal, *weight));
+                }
+            }
+            if let Some((_, literal, weight)) = group_best {
+                if best.is_none_or(|best| weight > best.weight) {
+                    best = Some(PatternMatch { group: &group.name, pattern: &literal.pattern, weight });
+                }
+            }
+        }
+        best
+    }
+
+    fn normalize<'a>(&self, user_agent: &'a str) -> Cow<'a, str> {
+        match self.case_sensitive {
+            true => Cow::Borrowed(user_agent),
+            false => Cow::Owned(user_agent.to_ascii_lowercase()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BotDetector;
+
+    #[test]
+    fn matches_like_the_regex_detector() {
+        let entries = "[search-engines]\ngooglebot\n^duckduckbot/\n[tools]\n0.4 \\(java\\)\n0.6 ^curl/\nwget/";
+        let literal = LiteralDetector::new(entries).unwrap();
+        let regex = BotDetector::new(entries);
+        for user_agent in ["Googlebot/2.1", "x duckduckbot/1.0", "DuckDuckBot/1.0", "curl/8.4", "curl/8 (Java)", "Wget/1.21", "okhttp/4", "Mozilla/5.0 (X11)", ""] {
+            assert_eq!(literal.check(user_agent), regex.check(user_agent), "{}", user_agent);
+            assert_eq!(literal.score(user_agent), regex.score(user_agent), "{}", user_agent);
+            assert_eq!(literal.classify(user_agent), regex.classify(user_agent), "{}", user_agent);
+        }
+        let found = literal.find_match("curl/8 (Java)").unwrap();
+        assert_eq!((found.group, found.pattern, found.weight), ("tools", "^curl/", 0.6));
+        assert_eq!(literal.find_match("wget/1 curl/8").map(|found| found.pattern), Some("wget/"));
+
+        let default = LiteralDetector::default();
+        assert_eq!(default.len(), BotDetector::default().len());
+        assert!(default.check_bot("Mozilla/5.0 (compatible; bingbot/2.0)"));
+    }
+
+    #[test]
+    fn applies_options() {
+        let detector = LiteralDetector::new("bot\n[crawlers]\nBingPreview\nbingpreview").unwrap();
+        assert_eq!((detector.len(), detector.groups().collect::<Vec<_>>()), (3, alloc::vec!["custom", "crawlers"]));
+        let token = detector.clone().match_mode(MatchMode::Token);
+        assert!(detector.check_bot("robots") && !token.check_bot("robots"));
+        assert!(token.check_bot("a bot/1.0"));
+        let sensitive = detector.case_sensitive(true);
+        assert_eq!(sensitive.find_match("BINGPREVIEW BingPreview").map(|found| found.pattern), Some("BingPreview"));
+        assert!(!sensitive.case_sensitive(true).check_bot("BOT"));
+        assert!(matches!(LiteralDetector::new("a.b"), Err(BotGuardError::InvalidPattern { .. })));
+    }
+}
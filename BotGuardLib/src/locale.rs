@@ -0,0 +1,288 @@
+// Plausibility of the `Accept-Language` header. Every browser sends one with the languages of its
+// user interface, scripts often send none, an `*` no settings screen produces, or a value typed
+// by hand that breaks the grammar of RFC 9110.
+//
+// None of these is proof of a bot, a privacy extension strips the header and travellers browse in
+// their own language, so each issue only adds a weight to the score. The country is optional and
+// comes from wherever the application resolves it, a `geoip` lookup or a CDN header like
+// `CF-IPCountry`.
+
+use std::fmt;
+
+use crate::request::RequestSnapshot;
+
+/// Languages commonly used in a country, by ISO 3166-1 alpha-2 code. Countries not listed are
+/// never compared, English is accepted everywhere.
+const COUNTRY_LANGUAGES: &[(&str, &[&str])] = &[
+    ("AE", &["ar"]),
+    ("AR", &["es"]),
+    ("AT", &["de"]),
+    ("BD", &["bn"]),
+    ("BE", &["nl", "fr", "de"]),
+    ("BG", &["bg"]),
+    ("BR", &["pt", "es"]),
+    ("BY", &["be", "ru"]),
+    ("CA", &["fr"]),
+    ("CH", &["de", "fr", "it", "rm"]),
+    ("CL", &["es"]),
+    ("CN", &["zh"]),
+    ("CO", &["es"]),
+    ("CZ", &["cs", "sk"]),
+    ("DE", &["de"]),
+    ("DK", &["da"]),
+    ("EG", &["ar"]),
+    ("ES", &["es", "ca", "eu", "gl"]),
+    ("FI", &["fi", "sv"]),
+    ("FR", &["fr"]),
+    ("GR", &["el"]),
+    ("HK", &["zh"]),
+    ("HU", &["hu"]),
+    ("ID", &["id", "jv"]),
+    ("IL", &["he", "ar", "ru"]),
+    ("IN", &["hi", "bn", "te", "mr", "ta", "ur", "gu", "kn", "ml", "pa", "or"]),
+    ("IR", &["fa"]),
+    ("IT", &["it"]),
+    ("JP", &["ja"]),
+    ("KR", &["ko"]),
+    ("KZ", &["kk", "ru"]),
+    ("MX", &["es"]),
+    ("MY", &["ms", "zh", "ta"]),
+    ("NL", &["nl", "fy"]),
+    ("NO", &["no", "nb", "nn"]),
+    ("PK", &["ur"]),
+    ("PL", &["pl"]),
+    ("PT", &["pt"]),
+    ("RO", &["ro", "hu"]),
+    ("RU", &["ru"]),
+    ("SA", &["ar"]),
+    ("SE", &["sv"]),
+    ("SK", &["sk", "cs"]),
+    ("TH", &["th"]),
+    ("TR", &["tr", "ku"]),
+    ("TW", &["zh"]),
+    ("UA", &["uk", "ru"]),
+    ("VN", &["vi"]),
+];
+
+/// Something implausible about the `Accept-Language` header of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocaleIssue {
+    /// The header is missing or empty.
+    Missing,
+    /// Only `*`, which no browser sends without a language before it.
+    WildcardOnly,
+    /// The value is not a list of language ranges with quality values.
+    Malformed(String),
+    /// None of the preferred languages, English aside, is used in the country of the address.
+    CountryMismatch { country: String, language: String },
+}
+
+impl fmt::Display for LocaleIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocaleIssue::Missing => f.write_str("no Accept-Language header"),
+            LocaleIssue::WildcardOnly => f.write_str("Accept-Language of only *"),
+            LocaleIssue::Malformed(value) => write!(f, "malformed Accept-Language {:?}", value),
+            LocaleIssue::CountryMismatch { country, language } => write!(f, "Accept-Language {} from {}", language, country),
+        }
+    }
+}
+
+/// Which locale checks run and how much each issue weighs, a weight of `0.0` turns a check off.
+///
+/// ```
+/// use BotGuardLib::locale::{LocaleIssue, LocaleRules};
+///
+/// let rules = LocaleRules::new();
+/// assert!(rules.check(Some("de-DE,de;q=0.9,en;q=0.8"), Some("DE")).is_empty());
+/// assert!(rules.check(Some("en-US,en;q=0.9"), Some("JP")).is_empty());
+/// assert_eq!(rules.check(Some("*"), None), vec![LocaleIssue::WildcardOnly]);
+///
+/// let issues = rules.check(Some("ja-JP,ja;q=0.9"), Some("BR"));
+/// assert_eq!(issues, vec![LocaleIssue::CountryMismatch { country: "BR".to_string(), language: "ja-JP".to_string() }]);
+/// assert_eq!(rules.score(&issues), 0.3);
+///
+/// let lenient = LocaleRules::new().missing_weight(0.0);
+/// assert!(lenient.check(None, Some("DE")).is_empty());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocaleRules {
+    missing: f32,
+    wildcard: f32,
+    malformed: f32,
+    country: f32,
+    country_threshold: f32,
+}
+
+impl Default for LocaleRules {
+    fn default() -> Self {
+        LocaleRules { missing: 0.4, wildcard: 0.6, malformed: 0.5, country: 0.3, country_threshold: 1.0 }
+    }
+}
+
+impl LocaleRules {
+    /// Weights `0.4` for a missing header, `0.6` for `*` alone, `0.5` for a malformed one and
+    /// `0.3` for a country none of the languages fit.
+    pub fn new() -> Self {
+        LocaleRules::default()
+    }
+
+    pub fn missing_weight(mut self, weight: f32) -> Self {
+        self.missing = weight.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn wildcard_weight(mut self, weight: f32) -> Self {
+        self.wildcard = weight.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn malformed_weight(mut self, weight: f32) -> Self {
+        self.malformed = weight.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn country_weight(mut self, weight: f32) -> Self {
+        self.country = weight.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Share of the quality values going to languages foreign to the country from which
+    /// [`LocaleIssue::CountryMismatch`] is reported, `1.0` by default: none of them fits.
+    pub fn country_threshold(mut self, threshold: f32) -> Self {
+        self.country_threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Checks the value of an `Accept-Language` header, `None` if the request has none, and the
+    /// country of the client's address if it is known.
+    pub fn check(&self, accept_language: Option<&str>, country: Option<&str>) -> Vec<LocaleIssue> {
+        let mut found = Vec::new();
+        let value = accept_language.map(str::trim).unwrap_or_default();
+        if value.is_empty() {
+            if self.missing > 0.0 {
+                found.push(LocaleIssue::Missing);
+            }
+            return found;
+        }
+        let Some(ranges) = parse(value) else {
+            if self.malformed > 0.0 {
+                found.push(LocaleIssue::Malformed(value.to_string()));
+            }
+            return found;
+        };
+        if ranges.iter().all(|(range, _)| range == "*") {
+            if self.wildcard > 0.0 {
+                found.push(LocaleIssue::WildcardOnly);
+            }
+            return found;
+        }
+        let languages = country.filter(|_| self.country > 0.0).and_then(|country| {
+            COUNTRY_LANGUAGES.iter().find(|(code, _)| code.eq_ignore_ascii_case(country.trim())).map(|(code, languages)| (*code, *languages))
+        });
+        if let Some((code, languages)) = languages {
+            let compared = ranges.iter().filter(|(range, q)| *q > 0.0 && range != "*" && primary(range) != "en").collect::<Vec<_>>();
+            let total = ranges.iter().filter(|(range, q)| *q > 0.0 && range != "*").map(|(_, q)| q).sum::<f32>();
+            let foreign = compared.iter().filter(|(range, _)| !languages.contains(&primary(range).as_str())).map(|(_, q)| q).sum::<f32>();
+            if let Some((preferred, _)) = compared.first().filter(|_| total > 0.0 && foreign / total >= self.country_threshold) {
+                found.push(LocaleIssue::CountryMismatch { country: code.to_string(), language: preferred.clone() });
+            }
+        }
+        found
+    }
+
+    /// Checks the `Accept-Language` header of a request, see [`LocaleRules::check`].
+    pub fn check_request(&self, request: &RequestSnapshot) -> Vec<LocaleIssue> {
+        self.check(request.header_value("accept-language"), request.country.as_deref())
+    }
+
+    pub fn weight(&self, issue: &LocaleIssue) -> f32 {
+        match issue {
+            LocaleIssue::Missing => self.missing,
+            LocaleIssue::WildcardOnly => self.wildcard,
+            LocaleIssue::Malformed(_) => self.malformed,
+            LocaleIssue::CountryMismatch { .. } => self.country,
+        }
+    }
+
+    /// The combined weight of the issues, between `0.0` and `1.0`, adding up like independent
+    /// chances as in [`crate::ua_anomaly_score`].
+    pub fn score(&self, issues: &[LocaleIssue]) -> f32 {
+        1.0 - issues.iter().fold(1.0, |human, issue| human * (1.0 - self.weight(issue)))
+    }
+}
+
+/// The language ranges of a header value with their quality values, `None` if it is malformed.
+fn parse(value: &str) -> Option<Vec<(String, f32)>> {
+    let mut ranges = Vec::new();
+    for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let mut parts = entry.split(';').map(str::trim);
+        let range = parts.next().unwrap_or_default();
+        let valid_range = range == "*"
+            || range.split('-').enumerate().all(|(i, subtag)| {
+                (1..=8).contains(&subtag.len()) && if i == 0 { subtag.bytes().all(|b| b.is_ascii_alphabetic()) } else { subtag.bytes().all(|b| b.is_ascii_alphanumeric()) }
+            });
+        if !valid_range {
+            return None;
+        }
+        let mut q = 1.0;
+        for parameter in parts {
+            let (name, value) = parameter.split_once('=')?;
+            if !name.trim().eq_ignore_ascii_case("q") {
+                return None;
+            }
+            let value = value.trim();
+            if value.len() > 5 || !value.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+                return None;
+            }
+            q = value.parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?;
+        }
+        ranges.push((range.to_string(), q));
+    }
+    if ranges.is_empty() {
+        None
+    } else {
+        Some(ranges)
+    }
+}
+
+/// The primary language subtag of a range, lowercased.
+fn primary(range: &str) -> String {
+    range.split('-').next().unwrap_or_default().to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_browser_values() {
+        let firefox = parse("fr-FR, fr;q=0.8, en-US;q=0.5, en;q=0.3").unwrap();
+        assert_eq!(firefox.len(), 4);
+        assert_eq!(firefox[2], ("en-US".to_string(), 0.5));
+        assert_eq!(parse("zh-Hant-TW,zh;q=0.9,*;q=0.1").unwrap()[0].0, "zh-Hant-TW");
+        for malformed in ["en_US", "en;q=2", "en;level=1", "english-language-preferred-x", "de;q=0.5.1", ","] {
+            assert_eq!(parse(malformed), None, "{}", malformed);
+        }
+        let rules = LocaleRules::new();
+        assert_eq!(rules.check(Some("en_US"), None), vec![LocaleIssue::Malformed("en_US".to_string())]);
+        assert_eq!(rules.check(Some("  "), None), vec![LocaleIssue::Missing]);
+        assert_eq!(rules.check(Some("*;q=0.5, *"), None), vec![LocaleIssue::WildcardOnly]);
+    }
+
+    #[test]
+    fn country_threshold_counts_quality_values() {
+        let rules = LocaleRules::new();
+        assert!(rules.check(Some("pt-BR,ja;q=0.5"), Some("BR")).is_empty());
+        assert!(rules.check(Some("ru"), Some("ZZ")).is_empty());
+        assert!(rules.check(Some("ru"), None).is_empty());
+        let strict = LocaleRules::new().country_threshold(0.6);
+        assert_eq!(
+            strict.check(Some("ja,pt-BR;q=0.5"), Some("br")),
+            vec![LocaleIssue::CountryMismatch { country: "BR".to_string(), language: "ja".to_string() }]
+        );
+        assert!(strict.check(Some("ja,pt-BR;q=0.9"), Some("BR")).is_empty());
+        assert!(LocaleRules::new().country_weight(0.0).check(Some("ja"), Some("BR")).is_empty());
+        assert!((rules.score(&[LocaleIssue::Missing, LocaleIssue::WildcardOnly]) - 0.76).abs() < 1e-6);
+    }
+}
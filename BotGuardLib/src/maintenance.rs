@@ -0,0 +1,402 @@
+// Periodic upkeep on one background thread: pattern refreshes, pruning of expired temporary
+// patterns and reputation entries, feed downloads and whatever else an application wants to run
+// on a timer, like flushing statistics.
+//
+// Jobs run one after the other, so a slow download delays the next job instead of piling up
+// threads. The feed downloads already release their locks while waiting, checks running on other
+// threads are never blocked by the worker. With the `tokio` feature the worker can also be a task
+// of the application's runtime, running each job on its blocking threads.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::anonymizer::AnonymizerLists;
+use crate::request::BotGuard;
+use crate::source::{block_on, refresh, PatternBundle, PatternSource};
+use crate::{BotDetector, BotGuardError};
+
+type Task = Box<dyn FnMut() -> Result<(), BotGuardError> + Send>;
+
+/// The [`JobStatus`] of every job by name.
+type Statuses = Mutex<HashMap<String, JobStatus>>;
+
+struct Job {
+    name: String,
+    interval: Duration,
+    task: Task,
+}
+
+/// What happened to a job so far, see [`MaintenanceHandle::status`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobStatus {
+    pub interval: Duration,
+    pub runs: u64,
+    pub failures: u64,
+    /// Error of the latest run, cleared by the next successful one.
+    pub last_error: Option<BotGuardError>,
+}
+
+/// The jobs to run, started with [`MaintenanceWorker::start`].
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::{Arc, RwLock};
+/// use std::time::Duration;
+/// use BotGuardLib::maintenance::MaintenanceWorker;
+/// use BotGuardLib::BotDetector;
+///
+/// let detector = Arc::new(RwLock::new(BotDetector::default()));
+/// let flushed = Arc::new(AtomicU64::new(0));
+/// let counter = Arc::clone(&flushed);
+/// let handle = MaintenanceWorker::new()
+///     .ttl_pruning(Arc::clone(&detector), Duration::from_secs(60))
+///     .job("stats", Duration::from_secs(10), move || {
+///         counter.fetch_add(1, Ordering::SeqCst);
+///         Ok(())
+///     })
+///     .start()
+///     .unwrap();
+/// assert!(handle.set_interval("stats", Duration::from_secs(30)));
+/// assert!(!handle.set_interval("nope", Duration::from_secs(30)));
+/// handle.stop();
+/// assert_eq!(flushed.load(Ordering::SeqCst), 1);
+/// ```
+#[derive(Default)]
+pub struct MaintenanceWorker {
+    jobs: Vec<Job>,
+}
+
+impl fmt::Debug for MaintenanceWorker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.jobs.iter().map(|job| (&job.name, job.interval))).finish()
+    }
+}
+
+impl MaintenanceWorker {
+    pub fn new() -> Self {
+        MaintenanceWorker::default()
+    }
+
+    /// Runs `task` every `interval`, replacing the job of the same name.
+    pub fn job<F>(mut self, name: &str, interval: Duration, task: F) -> Self
+    where
+        F: FnMut() -> Result<(), BotGuardError> + Send + 'static,
+    {
+        self.jobs.retain(|job| job.name != name);
+        self.jobs.push(Job { name: name.to_string(), interval, task: Box::new(task) });
+        self
+    }
+
+    /// Job `patterns`: reloads the detector from the source whenever the patterns changed, like
+    /// [`crate::source::PatternRefresher`].
+    pub fn pattern_refresh<S>(self, source: S, detector: Arc<RwLock<BotDetector>>, interval: Duration) -> Self
+    where
+        S: PatternSource + Send + 'static,
+    {
+        let mut current: Option<PatternBundle> = None;
+        self.job("patterns", interval, move || {
+            let (_, bundle) = block_on(refresh(&source, &detector, current.as_ref()))?;
+            current = Some(bundle);
+            Ok(())
+        })
+    }
+
+    /// Job `ttl`: removes expired temporary patterns and blocked addresses, see
+    /// [`BotDetector::prune_expired`].
+    pub fn ttl_pruning(self, detector: Arc<RwLock<BotDetector>>, interval: Duration) -> Self {
+        self.job("ttl", interval, move || {
+            detector.write().unwrap_or_else(|poisoned| poisoned.into_inner()).prune_expired();
+            Ok(())
+        })
+    }
+
+    /// Job `reputation`: downloads the expired feeds of the guard's reputation cache and evicts
+    /// the entries past their time-to-live. Fails with the first failed download.
+    pub fn reputation_feeds(self, guard: Arc<BotGuard>, interval: Duration) -> Self {
+        self.job("reputation", interval, move || {
+            let Some(cache) = guard.reputation_cache() else { return Ok(()) };
+            let refreshed = cache.refresh_expired();
+            cache.prune_expired();
+            refreshed.into_iter().try_for_each(|(_, result)| result.map(drop))
+        })
+    }
+
+    /// Job `anonymizers`: downloads the expired anonymizer feeds. Fails with the first failed
+    /// download.
+    pub fn anonymizer_feeds(self, lists: Arc<AnonymizerLists>, interval: Duration) -> Self {
+        self.job("anonymizers", interval, move || lists.refresh_expired().into_iter().try_for_each(|(_, result)| result.map(drop)))
+    }
+
+    /// Starts the thread, every job runs right away and then at its interval.
+    pub fn start(self) -> io::Result<MaintenanceHandle> {
+        let (control, commands) = mpsc::channel();
+        let (status, mut jobs) = self.schedule();
+        let worker_status = Arc::clone(&status);
+        let worker = thread::Builder::new().name("botguard-maintenance".to_string()).spawn(move || loop {
+            let now = Instant::now();
+            for (due, job) in jobs.iter_mut().filter(|(due, _)| *due <= now) {
+                let result = (job.task)();
+                *due = Instant::now() + job.interval;
+                record(&worker_status, &job.name, result);
+            }
+            let command = match next_due(&jobs) {
+                Some(next) => commands.recv_timeout(next.saturating_duration_since(Instant::now())),
+                None => commands.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match command {
+                Ok(command) if apply(&mut jobs, &command) => {}
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(_) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+        })?;
+        Ok(MaintenanceHandle { control: Control::Thread(control), worker: Some(worker), status })
+    }
+
+    /// Same as [`MaintenanceWorker::start`] as a task of the current tokio runtime, every job
+    /// runs on its blocking threads. Panics outside of a runtime, like `tokio::spawn`.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use BotGuardLib::maintenance::MaintenanceWorker;
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let handle = MaintenanceWorker::new().job("stats", Duration::from_secs(10), || Ok(())).spawn();
+    /// assert!(handle.run_now("stats"));
+    /// # });
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub fn spawn(self) -> MaintenanceHandle {
+        let (control, mut commands) = tokio::sync::mpsc::unbounded_channel();
+        let (status, mut jobs) = self.schedule();
+        let worker_status = Arc::clone(&status);
+        tokio::spawn(async move {
+            loop {
+                let now = Instant::now();
+                for (due, job) in jobs.iter_mut().filter(|(due, _)| *due <= now) {
+                    // the task moves to a blocking thread for the run and comes back with the result
+                    let mut task = std::mem::replace(&mut job.task, Box::new(|| Ok(())));
+                    let Ok((result, task)) = tokio::task::spawn_blocking(move || (task(), task)).await else { return };
+                    job.task = task;
+                    *due = Instant::now() + job.interval;
+                    record(&worker_status, &job.name, result);
+                }
+                let command = match next_due(&jobs) {
+                    Some(next) => match tokio::time::timeout_at(next.into(), commands.recv()).await {
+                        Ok(command) => command,
+                        Err(_) => continue,
+                    },
+                    None => commands.recv().await,
+                };
+                match command {
+                    Some(command) if apply(&mut jobs, &command) => {}
+                    _ => return,
+                }
+            }
+        });
+        MaintenanceHandle { control: Control::Task(control), worker: None, status }
+    }
+
+    /// The status of every job and the jobs, all due right away.
+    fn schedule(self) -> (Arc<Statuses>, Vec<(Instant, Job)>) {
+        let status = self.jobs.iter().map(|job| (job.name.clone(), JobStatus { interval: job.interval, ..JobStatus::default() })).collect();
+        (Arc::new(Mutex::new(status)), self.jobs.into_iter().map(|job| (Instant::now(), job)).collect())
+    }
+}
+
+fn record(status: &Statuses, name: &str, result: Result<(), BotGuardError>) {
+    if let Some(status) = lock(status).get_mut(name) {
+        status.runs += 1;
+        status.failures += u64::from(result.is_err());
+        status.last_error = result.err();
+    }
+}
+
+fn next_due(jobs: &[(Instant, Job)]) -> Option<Instant> {
+    jobs.iter().map(|(due, _)| *due).min()
+}
+
+/// Carries out a command of the handle, `false` once the worker is to stop.
+fn apply(jobs: &mut [(Instant, Job)], command: &Command) -> bool {
+    match command {
+        Command::Interval(name, interval) => {
+            if let Some((due, job)) = jobs.iter_mut().find(|(_, job)| job.name == *name) {
+                *due = due.checked_sub(job.interval).map_or(*due, |previous| previous + *interval);
+                job.interval = *interval;
+            }
+        }
+        Command::RunNow(name) => {
+            if let Some((due, _)) = jobs.iter_mut().find(|(_, job)| job.name == *name) {
+                *due = Instant::now();
+            }
+        }
+        Command::Stop => return false,
+    }
+    true
+}
+
+enum Command {
+    Interval(String, Duration),
+    RunNow(String),
+    Stop,
+}
+
+enum Control {
+    Thread(Sender<Command>),
+    #[cfg(feature = "tokio")]
+    Task(tokio::sync::mpsc::UnboundedSender<Command>),
+}
+
+impl Control {
+    fn send(&self, command: Command) -> bool {
+        match self {
+            Control::Thread(control) => control.send(command).is_ok(),
+            #[cfg(feature = "tokio")]
+            Control::Task(control) => control.send(command).is_ok(),
+        }
+    }
+}
+
+fn lock(status: &Statuses) -> MutexGuard<'_, HashMap<String, JobStatus>> {
+    status.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Controls the running worker, dropping it stops the worker after the job in progress.
+pub struct MaintenanceHandle {
+    control: Control,
+    /// The thread of [`MaintenanceWorker::start`], a task of [`MaintenanceWorker::spawn`] is
+    /// not waited for.
+    worker: Option<JoinHandle<()>>,
+    status: Arc<Statuses>,
+}
+
+impl fmt::Debug for MaintenanceHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MaintenanceHandle").field("status", &*lock(&self.status)).finish_non_exhaustive()
+    }
+}
+
+impl MaintenanceHandle {
+    /// Changes the interval of a job, counted from its previous run. Returns `false` if there is
+    /// no such job.
+    pub fn set_interval(&self, name: &str, interval: Duration) -> bool {
+        let mut status = lock(&self.status);
+        let Some(job) = status.get_mut(name) else { return false };
+        job.interval = interval;
+        self.control.send(Command::Interval(name.to_string(), interval))
+    }
+
+    /// Runs a job as soon as the worker is idle. Returns `false` if there is no such job.
+    pub fn run_now(&self, name: &str) -> bool {
+        lock(&self.status).contains_key(name) && self.control.send(Command::RunNow(name.to_string()))
+    }
+
+    pub fn status(&self, name: &str) -> Option<JobStatus> {
+        lock(&self.status).get(name).cloned()
+    }
+
+    /// Stops the worker and waits for the job in progress. A worker of
+    /// [`MaintenanceWorker::spawn`] finishes that job on its own, without blocking the caller.
+    pub fn stop(self) {}
+}
+
+impl Drop for MaintenanceHandle {
+    fn drop(&mut self) {
+        self.control.send(Command::Stop);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anonymizer::{AnonymizerFeed, AnonymizerKind};
+    use crate::reputation::ReputationCache;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn wait_for(handle: &MaintenanceHandle, name: &str, runs: u64) -> JobStatus {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let status = handle.status(name).unwrap();
+            if status.runs >= runs || Instant::now() > deadline {
+                return status;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn runs_jobs_at_their_intervals() {
+        let count = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&count);
+        let handle = MaintenanceWorker::new()
+            .job("fast", Duration::from_millis(10), move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .job("slow", Duration::from_secs(3600), || Err(BotGuardError::StateUnavailable { backend: "test".to_string(), reason: "down".to_string() }))
+            .start()
+            .unwrap();
+        assert!(wait_for(&handle, "fast", 3).runs >= 3);
+        let slow = wait_for(&handle, "slow", 1);
+        assert_eq!((slow.runs, slow.failures), (1, 1));
+        assert!(slow.last_error.is_some());
+
+        assert!(handle.run_now("slow"));
+        assert_eq!(wait_for(&handle, "slow", 2).runs, 2);
+        assert!(handle.set_interval("fast", Duration::from_secs(3600)));
+        thread::sleep(Duration::from_millis(30));
+        let settled = count.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(count.load(Ordering::SeqCst), settled);
+        assert_eq!(handle.status("fast").unwrap().interval, Duration::from_secs(3600));
+        assert_eq!(handle.status("nope"), None);
+    }
+
+    #[test]
+    fn maintains_feeds() {
+        let guard = Arc::new(BotGuard::new(BotDetector::new("^curl/")).ip_reputation(ReputationCache::new()));
+        let lists = Arc::new(AnonymizerLists::new());
+        lists.add_feed(AnonymizerFeed::new("vpn", AnonymizerKind::Vpn, Some("http://127.0.0.1:1/vpn.txt")));
+        let handle = MaintenanceWorker::new()
+            .reputation_feeds(guard, Duration::from_secs(3600))
+            .anonymizer_feeds(lists, Duration::from_secs(3600))
+            .start()
+            .unwrap();
+        assert_eq!(wait_for(&handle, "reputation", 1).last_error, None);
+        let anonymizers = wait_for(&handle, "anonymizers", 1);
+        assert!(matches!(anonymizers.last_error, Some(BotGuardError::FetchFailed { .. })), "{:?}", anonymizers);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn runs_jobs_as_a_tokio_task() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _entered = runtime.enter();
+        let count = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&count);
+        let handle = MaintenanceWorker::new()
+            .job("fast", Duration::from_millis(10), move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .job("slow", Duration::from_secs(3600), || Err(BotGuardError::StateUnavailable { backend: "test".to_string(), reason: "down".to_string() }))
+            .spawn();
+        assert!(wait_for(&handle, "fast", 3).runs >= 3);
+        assert_eq!(wait_for(&handle, "slow", 1).failures, 1);
+        assert!(handle.run_now("slow"));
+        assert_eq!(wait_for(&handle, "slow", 2).runs, 2);
+
+        handle.stop();
+        thread::sleep(Duration::from_millis(30));
+        let stopped = count.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(count.load(Ordering::SeqCst), stopped);
+    }
+}
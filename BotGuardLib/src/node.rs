@@ -0,0 +1,108 @@
+// Node.js bindings built with napi-rs, enabled with the `node` feature: a native addon with a
+// `BotDetector` class that compiles the same patterns and gives the same verdicts as the Rust
+// one, so Express and Fastify middleware needs no regex list of its own.
+//
+// `npm run build` in `bindings/node` builds the crate as a `cdylib` and copies it to
+// `botguard.node`, `bindings/node/index.mjs` loads it and adds the middleware. Node-API symbols
+// are looked up when the addon is loaded, so the crate links without Node.js as well.
+
+use std::sync::{RwLock, RwLockReadGuard};
+
+use napi::{Error, Result};
+use napi_derive::napi;
+
+use crate::{BotDetector, Verdict, CUSTOM_GROUP};
+
+/// `new BotDetector(patterns?)` in JavaScript, with the bundled patterns unless newline-delimited
+/// patterns in the format of [`BotDetector::new`] are given. Invalid patterns throw.
+#[napi(js_name = "BotDetector")]
+pub struct NodeBotDetector(RwLock<BotDetector>);
+
+fn verdict_name(verdict: Verdict) -> String {
+    match verdict {
+        Verdict::Human => "human",
+        Verdict::Suspicious => "suspicious",
+        Verdict::Bot => "bot",
+    }
+    .to_string()
+}
+
+impl NodeBotDetector {
+    fn detector(&self) -> RwLockReadGuard<'_, BotDetector> {
+        self.0.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[napi]
+impl NodeBotDetector {
+    #[napi(constructor)]
+    pub fn new(patterns: Option<String>) -> Result<Self> {
+        let detector = match patterns {
+            Some(patterns) => BotDetector::builder().patterns(&patterns).build().map_err(|e| Error::from_reason(e.to_string()))?,
+            None => BotDetector::default(),
+        };
+        Ok(NodeBotDetector(RwLock::new(detector)))
+    }
+
+    /// Returns "human", "suspicious" or "bot".
+    #[napi]
+    pub fn check(&self, user_agent: String) -> String {
+        verdict_name(self.detector().check(&user_agent))
+    }
+
+    #[napi]
+    pub fn check_bot(&self, user_agent: String) -> bool {
+        self.detector().check_bot(&user_agent)
+    }
+
+    /// Returns the pattern group of a bot, or null.
+    #[napi]
+    pub fn classify(&self, user_agent: String) -> Option<String> {
+        self.detector().classify(&user_agent).map(str::to_string)
+    }
+
+    /// Checks an array of user-agents under a single lock.
+    #[napi]
+    pub fn check_many(&self, user_agents: Vec<String>) -> Vec<String> {
+        let detector = self.detector();
+        user_agents.iter().map(|user_agent| verdict_name(detector.check(user_agent))).collect()
+    }
+
+    /// Appends newline-delimited patterns to the custom group, throws and adds none if one is
+    /// invalid.
+    #[napi]
+    pub fn append(&self, patterns: String) -> Result<()> {
+        let patterns = patterns.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<&str>>();
+        self.0
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .append_to_group(CUSTOM_GROUP, &patterns)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Number of patterns.
+    #[napi(getter)]
+    pub fn length(&self) -> u32 {
+        self.detector().len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_like_the_detector() {
+        let detector = NodeBotDetector::new(Some("[scrapers]\ncurl/".to_string())).unwrap();
+        assert_eq!(detector.check("curl/8.0".to_string()), "bot");
+        assert!(detector.check_bot("curl/8.0".to_string()) && !detector.check_bot("Mozilla/5.0".to_string()));
+        assert_eq!(detector.classify("curl/8.0".to_string()).as_deref(), Some("scrapers"));
+        assert_eq!(detector.check_many(vec!["curl/8.0".to_string(), "Mozilla/5.0".to_string()]), ["bot", "human"]);
+
+        detector.append("^wget/\n\n".to_string()).unwrap();
+        assert!(detector.append("(open".to_string()).is_err());
+        assert_eq!((detector.check("Wget/1.21".to_string()).as_str(), detector.length()), ("bot", 2));
+        assert!(NodeBotDetector::new(Some("(open".to_string())).is_err());
+        assert!(NodeBotDetector::new(None).unwrap().check_bot("Googlebot/2.1".to_string()));
+    }
+}
@@ -0,0 +1,240 @@
+// Async entry point for web frameworks built on an executor. The checks themselves are
+// synchronous: matching patterns takes microseconds and is fine to run on the executor, but a
+// guard sharing state through Redis or downloading feeds does network I/O that must not stall
+// the executor's threads.
+//
+// `AsyncBotGuard` runs that work on a small pool of its own threads and hands the result back
+// through a future, so it does not depend on any particular runtime. The crate's other async
+// APIs doing blocking I/O, like CAPTCHA verification and pattern downloads, share one such pool
+// through `spawn_blocking`.
+
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::request::{BotGuard, RequestSnapshot, RequestVerdict};
+use crate::{BotGuardError, Verdict};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads running blocking jobs, stopped when the pool is dropped.
+struct Pool {
+    jobs: Option<mpsc::Sender<Job>>,
+    workers: usize,
+}
+
+impl Pool {
+    fn new(workers: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let spawned = (0..workers.max(1))
+            .filter(|_| {
+                let receiver = Arc::clone(&receiver);
+                let worker = thread::Builder::new().name("botguard-blocking".to_string()).spawn(move || loop {
+                    let job = receiver.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                });
+                worker.is_ok()
+            })
+            .count();
+        // without a single worker, jobs run on the calling thread
+        Pool { jobs: (spawned > 0).then_some(sender), workers: spawned }
+    }
+
+    fn spawn<T: Send + 'static>(&self, work: impl FnOnce() -> T + Send + 'static) -> Blocking<T> {
+        let slot = Arc::new(Mutex::new(Slot { result: None, waker: None }));
+        let filled = Arc::clone(&slot);
+        let job: Job = Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(work));
+            let mut slot = filled.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            slot.result = Some(result);
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        });
+        match &self.jobs {
+            Some(jobs) => {
+                if let Err(mpsc::SendError(job)) = jobs.send(job) {
+                    job();
+                }
+            }
+            None => job(),
+        }
+        Blocking { slot }
+    }
+}
+
+/// Runs blocking work of the crate's async APIs on a pool shared by them, started on first use
+/// with a thread per core.
+pub(crate) fn spawn_blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> impl Future<Output = T> {
+    static SHARED: OnceLock<Pool> = OnceLock::new();
+    SHARED.get_or_init(|| Pool::new(thread::available_parallelism().map_or(4, |cores| cores.get()))).spawn(work)
+}
+
+struct Slot<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// Completes when the job finished on the pool, re-raising its panic.
+struct Blocking<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.slot.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match slot.result.take() {
+            Some(Ok(output)) => Poll::Ready(output),
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A [`BotGuard`] for async applications.
+///
+/// Requests are evaluated on the blocking pool if the guard keeps state in a
+/// [`crate::state::StateStore`], a rate limit or a client memory, and right away otherwise, see
+/// [`AsyncBotGuard::offload`]. Clones share the guard and the pool.
+///
+/// ```
+/// use BotGuardLib::nonblocking::AsyncBotGuard;
+/// use BotGuardLib::request::{BotGuard, RequestSnapshot};
+/// use BotGuardLib::{BotDetector, Verdict};
+///
+/// async fn handle(guard: &AsyncBotGuard, user_agent: &str) -> bool {
+///     let verdict = guard.evaluate(RequestSnapshot::new("GET", "/").user_agent(user_agent)).await;
+///     verdict.verdict != Verdict::Bot
+/// }
+///
+/// let guard = AsyncBotGuard::new(BotGuard::new(BotDetector::default())).workers(2);
+/// assert_eq!(guard.guard().detector().check("curl/8.4.0"), Verdict::Bot);
+/// # let _ = handle;
+/// ```
+#[derive(Clone)]
+pub struct AsyncBotGuard {
+    guard: Arc<BotGuard>,
+    pool: Arc<Pool>,
+    offload: Option<bool>,
+}
+
+impl fmt::Debug for AsyncBotGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncBotGuard").field("guard", &self.guard).field("workers", &self.pool.workers).field("offload", &self.offload).finish()
+    }
+}
+
+impl AsyncBotGuard {
+    /// A pool with a thread per core.
+    pub fn new(guard: BotGuard) -> Self {
+        AsyncBotGuard::from_shared(Arc::new(guard))
+    }
+
+    /// Shares a guard with synchronous code.
+    pub fn from_shared(guard: Arc<BotGuard>) -> Self {
+        let workers = thread::available_parallelism().map_or(4, |cores| cores.get());
+        AsyncBotGuard { guard, pool: Arc::new(Pool::new(workers)), offload: None }
+    }
+
+    /// Replaces the pool with one of `workers` threads.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.pool = Arc::new(Pool::new(workers));
+        self
+    }
+
+    /// Whether [`AsyncBotGuard::evaluate`] runs on the pool, instead of deciding by the
+    /// components of the guard. A custom [`crate::state::StateStore`] that never blocks can
+    /// turn it off, slow custom patterns turn it on.
+    pub fn offload(mut self, offload: bool) -> Self {
+        self.offload = Some(offload);
+        self
+    }
+
+    pub fn guard(&self) -> &Arc<BotGuard> {
+        &self.guard
+    }
+
+    /// [`BotGuard::evaluate`].
+    pub async fn evaluate(&self, request: RequestSnapshot) -> RequestVerdict {
+        if !self.offload.unwrap_or_else(|| self.guard.uses_state()) {
+            return self.guard.evaluate(&request);
+        }
+        let guard = Arc::clone(&self.guard);
+        self.pool.spawn(move || guard.evaluate(&request)).await
+    }
+
+    /// [`crate::BotDetector::check`] of the guard's detector, never offloaded.
+    pub async fn check(&self, user_agent: &str) -> Verdict {
+        self.guard.detector().check(user_agent)
+    }
+
+    /// Downloads the expired feeds of the reputation cache, see
+    /// [`crate::reputation::ReputationCache::refresh_expired`]. Empty without a cache.
+    pub async fn refresh_reputation(&self) -> Vec<(String, Result<usize, BotGuardError>)> {
+        self.run(|guard| guard.reputation_cache().map(|cache| cache.refresh_expired()).unwrap_or_default()).await
+    }
+
+    /// Runs any other blocking work with the guard on the pool, e.g. writing a response with a
+    /// [`crate::actions::Tarpit`] or verifying a challenge against a Redis store.
+    pub async fn run<T: Send + 'static>(&self, work: impl FnOnce(&BotGuard) -> T + Send + 'static) -> T {
+        let guard = Arc::clone(&self.guard);
+        self.pool.spawn(move || work(&guard)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::block_on;
+    use crate::state::{ClientOverrides, MemoryStore, RateLimiter};
+    use crate::BotDetector;
+    use std::time::Duration;
+
+    #[test]
+    fn evaluates_on_the_pool() {
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let guard = AsyncBotGuard::new(BotGuard::new(BotDetector::new("^curl/")).rate_limit(limiter, 100, Duration::from_secs(60))).workers(2);
+        assert!(guard.guard().uses_state());
+        assert!(!BotGuard::new(BotDetector::new("^curl/")).uses_state());
+        assert!(BotGuard::new(BotDetector::new("^curl/")).overrides(ClientOverrides::new(Arc::new(MemoryStore::new()))).uses_state());
+        let requests = (0..8).map(|i| RequestSnapshot::new("GET", "/").user_agent(if i % 2 == 0 { "curl/8.0" } else { "Mozilla/5.0" }));
+        let verdicts = requests.map(|request| block_on(guard.evaluate(request)).verdict).collect::<Vec<_>>();
+        assert_eq!(verdicts.iter().filter(|verdict| **verdict == Verdict::Bot).count(), 4);
+        assert_eq!(block_on(guard.check("curl/8.0")), Verdict::Bot);
+
+        let inline = AsyncBotGuard::from_shared(Arc::clone(guard.guard())).offload(false);
+        assert_eq!(block_on(inline.evaluate(RequestSnapshot::new("GET", "/").user_agent("curl/8.0"))).verdict, Verdict::Bot);
+        assert!(block_on(inline.refresh_reputation()).is_empty());
+    }
+
+    #[test]
+    fn shares_a_pool_for_blocking_io() {
+        let names = (0..3).map(|_| spawn_blocking(|| thread::current().name().map(str::to_string))).map(block_on).collect::<Vec<_>>();
+        assert!(names.iter().all(|name| name.as_deref() == Some("botguard-blocking")));
+    }
+
+    #[test]
+    fn runs_jobs_and_reraises_panics() {
+        let guard = AsyncBotGuard::new(BotGuard::new(BotDetector::new("^curl/"))).workers(1);
+        let names = (0..3).map(|_| guard.run(|_| thread::current().name().map(str::to_string))).map(block_on).collect::<Vec<_>>();
+        assert!(names.iter().all(|name| name.as_deref() == Some("botguard-blocking")));
+
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| block_on(guard.run(|_| -> u8 { panic!("boom") }))));
+        assert!(panicked.is_err());
+        // the worker survives the panic
+        assert_eq!(block_on(guard.run(|guard| guard.detector().check("curl/8.0"))), Verdict::Bot);
+    }
+}
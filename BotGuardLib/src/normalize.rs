@@ -0,0 +1,215 @@
+// Folding of the tricks scrapers use to slip past substring patterns: fullwidth and mathematical
+// letters, lookalike letters from other scripts, invisible characters and padded whitespace.
+//
+// The compatibility folding is Unicode NFKC by the `unicode-normalization` crate, enabled with the
+// feature of the same name. It runs on each character together with the combining marks following
+// it, so every folded character still points back to the bytes it came from.
+
+use std::borrow::Cow;
+
+#[cfg(feature = "unicode-normalization")]
+use unicode_normalization::UnicodeNormalization;
+
+/// Cyrillic and Greek letters drawn like a Latin one, with that Latin letter.
+const HOMOGLYPHS: &[(char, char)] = &[
+    ('а', 'a'), ('в', 'b'), ('е', 'e'), ('к', 'k'), ('м', 'm'), ('н', 'h'), ('о', 'o'), ('р', 'p'),
+    ('с', 'c'), ('т', 't'), ('у', 'y'), ('х', 'x'), ('ѕ', 's'), ('і', 'i'), ('ј', 'j'), ('һ', 'h'),
+    ('ԁ', 'd'), ('ӏ', 'l'), ('А', 'A'), ('В', 'B'), ('Е', 'E'), ('К', 'K'), ('М', 'M'), ('Н', 'H'),
+    ('О', 'O'), ('Р', 'P'), ('С', 'C'), ('Т', 'T'), ('Х', 'X'), ('Ѕ', 'S'), ('І', 'I'), ('Ј', 'J'),
+    ('α', 'a'), ('ε', 'e'), ('ι', 'i'), ('κ', 'k'), ('ν', 'v'), ('ο', 'o'), ('ρ', 'p'), ('υ', 'u'),
+    ('Α', 'A'), ('Β', 'B'), ('Ε', 'E'), ('Ζ', 'Z'), ('Η', 'H'), ('Ι', 'I'), ('Κ', 'K'), ('Μ', 'M'),
+    ('Ν', 'N'), ('Ο', 'O'), ('Ρ', 'P'), ('Τ', 'T'), ('Υ', 'Y'), ('Χ', 'X'), ('ı', 'i'), ('ɡ', 'g'),
+];
+
+/// Which folding steps run on user-agents before they are matched, see
+/// [`crate::BotDetectorBuilder::normalization`]. All steps are off by default.
+///
+/// Patterns are matched against the folded user-agent, so they should be written in plain ASCII.
+///
+/// ```
+/// use BotGuardLib::normalize::Normalization;
+///
+/// let folded = Normalization::all().apply("Ｇооglebot\u{200b}/2.1   (compatible)");
+/// assert_eq!(folded, "Googlebot/2.1 (compatible)");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalization {
+    /// Unicode NFKC, folding fullwidth forms, mathematical letters and digits and ligatures like
+    /// `ﬁ` to ASCII and unusual spaces to a plain space, with invisible characters like zero-width
+    /// spaces removed. Needs the `unicode-normalization` feature.
+    #[cfg(feature = "unicode-normalization")]
+    pub compatibility: bool,
+    /// Cyrillic and Greek lookalikes to the Latin letter they imitate, e.g. `о` (U+043E) to `o`.
+    pub homoglyphs: bool,
+    /// Runs of whitespace to one space, leading and trailing whitespace removed.
+    pub whitespace: bool,
+}
+
+impl Normalization {
+    pub fn all() -> Self {
+        Normalization {
+            #[cfg(feature = "unicode-normalization")]
+            compatibility: true,
+            homoglyphs: true,
+            whitespace: true,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.compatible() || self.homoglyphs || self.whitespace
+    }
+
+    fn compatible(&self) -> bool {
+        #[cfg(feature = "unicode-normalization")]
+        return self.compatibility;
+        #[cfg(not(feature = "unicode-normalization"))]
+        false
+    }
+
+    /// Runs the enabled steps, borrowing the input if nothing changes.
+    pub fn apply<'a>(&self, user_agent: &'a str) -> Cow<'a, str> {
+        match self.changes(user_agent) {
+            true => Cow::Owned(self.fold(user_agent, None)),
+            false => Cow::Borrowed(user_agent),
+        }
+    }
+
+    /// Same as [`Normalization::apply`], with the byte range in the input each byte of a changed
+    /// output was folded from. `None` if the input is borrowed unchanged.
+    pub(crate) fn apply_with_offsets<'a>(&self, user_agent: &'a str) -> (Cow<'a, str>, Option<Vec<(usize, usize)>>) {
+        if !self.changes(user_agent) {
+            return (Cow::Borrowed(user_agent), None);
+        }
+        let mut offsets = Vec::with_capacity(user_agent.len());
+        let folded = self.fold(user_agent, Some(&mut offsets));
+        (Cow::Owned(folded), Some(offsets))
+    }
+
+    fn changes(&self, user_agent: &str) -> bool {
+        let untouched = user_agent.is_ascii() && !(self.whitespace && needs_collapsing(user_agent));
+        self.is_enabled() && !untouched
+    }
+
+    fn fold(&self, user_agent: &str, mut offsets: Option<&mut Vec<(usize, usize)>>) -> String {
+        let mut folded = String::with_capacity(user_agent.len());
+        // the range of the whitespace a collapsed space stands for
+        let mut pending_space = None;
+        let mut push = |c: char, range: (usize, usize)| {
+            let c = if self.homoglyphs { homoglyph(c) } else { c };
+            if self.whitespace && c.is_whitespace() {
+                if !folded.is_empty() {
+                    pending_space = pending_space.or(Some(range));
+                }
+                return;
+            }
+            let mut emit = |c: char, range: (usize, usize)| {
+                folded.push(c);
+                if let Some(offsets) = offsets.as_deref_mut() {
+                    offsets.extend(std::iter::repeat_n(range, c.len_utf8()));
+                }
+            };
+            if let Some(space) = pending_space.take() {
+                emit(' ', space);
+            }
+            emit(c, range);
+        };
+        #[cfg(feature = "unicode-normalization")]
+        if self.compatibility {
+            for (start, end) in clusters(user_agent) {
+                let cluster = user_agent[start..end].chars().filter(|&c| !invisible(c));
+                cluster.nfkc().for_each(|c| push(c, (start, end)));
+            }
+            return folded;
+        }
+        for (i, c) in user_agent.char_indices() {
+            push(c, (i, i + c.len_utf8()));
+        }
+        folded
+    }
+}
+
+/// Whether an ASCII user-agent has whitespace other than single spaces between words.
+fn needs_collapsing(user_agent: &str) -> bool {
+    let bytes = user_agent.as_bytes();
+    let space = |b: &u8| *b == b' ' || (b'\t'..=b'\r').contains(b);
+    bytes.first().is_some_and(space)
+        || bytes.last().is_some_and(space)
+        || bytes.iter().any(|b| space(b) && *b != b' ')
+        || bytes.windows(2).any(|pair| pair == b"  ")
+}
+
+fn homoglyph(c: char) -> char {
+    if c.is_ascii() {
+        return c;
+    }
+    HOMOGLYPHS.iter().find(|(glyph, _)| *glyph == c).map_or(c, |&(_, latin)| latin)
+}
+
+/// Format characters drawn as nothing, which NFKC keeps.
+#[cfg(feature = "unicode-normalization")]
+fn invisible(c: char) -> bool {
+    matches!(c, '\u{ad}' | '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}')
+}
+
+/// Byte ranges of the characters of a user-agent, each with the combining marks and Hangul vowel
+/// and final jamo following it, the only characters NFKC composes with the one before.
+#[cfg(feature = "unicode-normalization")]
+fn clusters(user_agent: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let attaches = |c: char| unicode_normalization::char::is_combining_mark(c) || ('\u{1160}'..='\u{11ff}').contains(&c);
+    let mut chars = user_agent.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, c) = chars.next()?;
+        let mut end = start + c.len_utf8();
+        while let Some((i, c)) = chars.next_if(|&(_, c)| attaches(c)) {
+            end = i + c.len_utf8();
+        }
+        Some((start, end))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BotDetector;
+
+    #[test]
+    fn folds_each_step_on_its_own() {
+        let only = |normalization: Normalization, input: &str| normalization.apply(input).into_owned();
+        let homoglyphs = Normalization { homoglyphs: true, ..Normalization::default() };
+        let whitespace = Normalization { whitespace: true, ..Normalization::default() };
+
+        assert_eq!(only(homoglyphs, "ѕqlmар ΒΟΤ"), "sqlmap BOT");
+        assert_eq!(only(whitespace, " \tcurl/8.0 \n (x) "), "curl/8.0 (x)");
+        assert!(matches!(Normalization::all().apply("Mozilla/5.0 (X11)"), Cow::Borrowed(_)));
+        assert!(matches!(whitespace.apply("a\tb"), Cow::Owned(_)));
+        assert_eq!(Normalization::default().apply("  ｃ  "), "  ｃ  ");
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn folds_compatibility_forms_with_nfkc() {
+        let compatibility = Normalization { compatibility: true, ..Normalization::default() };
+        let only = |input: &str| compatibility.apply(input).into_owned();
+
+        assert_eq!(only("ｃｕｒｌ/８.0\u{00a0}x\u{feff}"), "curl/8.0 x");
+        assert_eq!(only("𝐬𝐪𝐥𝐦𝐚𝐩/𝟏.𝟕 ﬁle"), "sqlmap/1.7 file");
+        assert_eq!(only("ⓒᵘʳˡ/8.0\u{2003}cafe\u{301}"), "curl/8.0 caf\u{e9}");
+        assert_eq!(only("ѕqlmap"), "ѕqlmap");
+        // a folded character points back to the character and marks it came from
+        let (folded, offsets) = compatibility.apply_with_offsets("ﬁe\u{301}x");
+        assert_eq!(folded, "fiéx");
+        assert_eq!(offsets.unwrap(), [(0, 3), (0, 3), (3, 6), (3, 6), (6, 7)]);
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn detector_matches_folded_user_agents() {
+        let plain = BotDetector::new("googlebot\n^curl/\nbad bot");
+        let folded = BotDetector::builder().patterns("googlebot\n^curl/\nbad bot").normalization(Normalization::all()).build().unwrap();
+        for evasive in ["Mozilla/5.0 (compatible; Gооglеbot/2.1)", "  ｃｕｒｌ/8.4", "Bad\u{3000}\u{3000}Bot"] {
+            assert!(!plain.check_bot(evasive), "{}", evasive);
+            assert!(folded.check_bot(evasive), "{}", evasive);
+        }
+        assert!(!folded.check_bot("Mozilla/5.0 (X11; Linux x86_64) Firefox/120.0"));
+    }
+}
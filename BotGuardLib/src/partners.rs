@@ -0,0 +1,201 @@
+// Shared-secret tokens of partners whose automated traffic is wanted: uptime monitors, load-test
+// vendors, synthetic transaction checks. A request sending a registered token in the `PARTNER_HEADER`
+// is analyzed as usual but never blocked, and its verdict is tagged with the partner so metrics
+// and dashboards can tell the partner's traffic apart.
+//
+// Only the SHA-512 digest of a token is kept. Tokens can expire, and rotating a partner's token
+// keeps the previous ones valid for a grace period so the partner can switch over without
+// failed checks.
+
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, SystemTime};
+
+use crate::request::RequestSnapshot;
+use crate::{crypto, BotGuardError};
+
+/// Header carrying a partner token.
+pub const PARTNER_HEADER: &str = "X-BotGuard-Partner";
+
+/// Shorter tokens are rejected, they could be guessed.
+const MIN_TOKEN_LEN: usize = 16;
+
+type Digest = [u8; 32];
+
+/// A registered token, without the token itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartnerToken {
+    pub partner: String,
+    /// `None` for tokens valid until revoked.
+    pub expires: Option<SystemTime>,
+}
+
+/// The tokens of every partner, shared by the request handlers and updated while in use.
+///
+/// ```
+/// use std::time::Duration;
+/// use BotGuardLib::partners::{PartnerTokens, PARTNER_HEADER};
+/// use BotGuardLib::request::RequestSnapshot;
+///
+/// let partners = PartnerTokens::new();
+/// partners.register("uptime-monitor", "3f1b9c0e8a7d45f2b6c1", None).unwrap();
+/// let check = RequestSnapshot::new("GET", "/").header(PARTNER_HEADER, "3f1b9c0e8a7d45f2b6c1");
+/// assert_eq!(partners.verify_request(&check).as_deref(), Some("uptime-monitor"));
+///
+/// // the old token stays valid for an hour
+/// partners.rotate("uptime-monitor", "9d2e7a61c4b84f03a5e8", Duration::from_secs(3600)).unwrap();
+/// assert_eq!(partners.verify("3f1b9c0e8a7d45f2b6c1").as_deref(), Some("uptime-monitor"));
+/// assert_eq!(partners.verify("9d2e7a61c4b84f03a5e8").as_deref(), Some("uptime-monitor"));
+/// ```
+#[derive(Debug, Default)]
+pub struct PartnerTokens {
+    tokens: RwLock<HashMap<Digest, PartnerToken>>,
+}
+
+impl PartnerTokens {
+    pub fn new() -> Self {
+        PartnerTokens::default()
+    }
+
+    /// Accepts `token` for `partner` until `expires`, or until revoked. Fails on tokens shorter
+    /// than 16 characters and on tokens already registered for another partner.
+    pub fn register(&self, partner: &str, token: &str, expires: Option<SystemTime>) -> Result<(), BotGuardError> {
+        let digest = digest(token)?;
+        let mut tokens = self.write();
+        match tokens.get(&digest) {
+            Some(registered) if registered.partner != partner => {
+                Err(invalid(format!("the token of {} is already registered for {}", partner, registered.partner)))
+            }
+            _ => {
+                tokens.insert(digest, PartnerToken { partner: partner.to_string(), expires });
+                Ok(())
+            }
+        }
+    }
+
+    /// Registers `token` for `partner`, valid until revoked, and lets the partner's other tokens
+    /// expire within `grace`.
+    pub fn rotate(&self, partner: &str, token: &str, grace: Duration) -> Result<(), BotGuardError> {
+        self.rotate_at(partner, token, grace, SystemTime::now())
+    }
+
+    fn rotate_at(&self, partner: &str, token: &str, grace: Duration, now: SystemTime) -> Result<(), BotGuardError> {
+        let new = digest(token)?;
+        self.register(partner, token, None)?;
+        let deadline = now + grace;
+        for (_, registered) in self.write().iter_mut().filter(|(digest, registered)| **digest != new && registered.partner == partner) {
+            registered.expires = Some(registered.expires.map_or(deadline, |expires| expires.min(deadline)));
+        }
+        Ok(())
+    }
+
+    /// Removes every token of the partner, returns how many there were.
+    pub fn revoke(&self, partner: &str) -> usize {
+        let mut tokens = self.write();
+        let before = tokens.len();
+        tokens.retain(|_, registered| registered.partner != partner);
+        before - tokens.len()
+    }
+
+    /// Forgets expired tokens, returns how many.
+    pub fn remove_expired(&self) -> usize {
+        let now = SystemTime::now();
+        let mut tokens = self.write();
+        let before = tokens.len();
+        tokens.retain(|_, registered| registered.expires.is_none_or(|expires| now < expires));
+        before - tokens.len()
+    }
+
+    /// The partner of a registered and unexpired token.
+    pub fn verify(&self, token: &str) -> Option<String> {
+        self.verify_at(token, SystemTime::now())
+    }
+
+    /// The partner of the token in the [`PARTNER_HEADER`].
+    pub fn verify_request(&self, request: &RequestSnapshot) -> Option<String> {
+        self.verify(request.header_value(PARTNER_HEADER)?)
+    }
+
+    fn verify_at(&self, token: &str, now: SystemTime) -> Option<String> {
+        let digest = digest(token).ok()?;
+        let tokens = self.read();
+        let registered = tokens.get(&digest).filter(|registered| registered.expires.is_none_or(|expires| now < expires))?;
+        Some(registered.partner.clone())
+    }
+
+    /// The registered tokens, for listing them in an admin interface.
+    pub fn tokens(&self) -> Vec<PartnerToken> {
+        let mut tokens = self.read().values().cloned().collect::<Vec<_>>();
+        tokens.sort_by(|a, b| a.partner.cmp(&b.partner).then(a.expires.cmp(&b.expires)));
+        tokens
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<Digest, PartnerToken>> {
+        self.tokens.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<Digest, PartnerToken>> {
+        self.tokens.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn digest(token: &str) -> Result<Digest, BotGuardError> {
+    let token = token.trim();
+    if token.len() < MIN_TOKEN_LEN {
+        return Err(invalid(format!("partner tokens must be at least {} characters", MIN_TOKEN_LEN)));
+    }
+    let mut digest = [0; 32];
+    digest.copy_from_slice(&crypto::sha512(token.as_bytes())[..32]);
+    Ok(digest)
+}
+
+fn invalid(reason: String) -> BotGuardError {
+    BotGuardError::InvalidConfig { line: None, reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "aaaaaaaaaaaaaaaa";
+    const B: &str = "bbbbbbbbbbbbbbbb";
+
+    #[test]
+    fn expires_rotated_tokens_after_the_grace_period() {
+        let partners = PartnerTokens::new();
+        let now = SystemTime::now();
+        partners.register("monitor", A, None).unwrap();
+        partners.rotate_at("monitor", B, Duration::from_secs(60), now).unwrap();
+        assert_eq!(partners.verify_at(A, now + Duration::from_secs(59)).as_deref(), Some("monitor"));
+        assert_eq!(partners.verify_at(A, now + Duration::from_secs(60)), None);
+        assert_eq!(partners.verify_at(B, now + Duration::from_secs(3600)).as_deref(), Some("monitor"));
+        assert_eq!(partners.tokens(), vec![PartnerToken { partner: "monitor".to_string(), expires: None }, PartnerToken { partner: "monitor".to_string(), expires: Some(now + Duration::from_secs(60)) }]);
+
+        // rotating again keeps the earlier deadline
+        partners.rotate_at("monitor", "cccccccccccccccc", Duration::from_secs(600), now).unwrap();
+        assert_eq!(partners.verify_at(A, now + Duration::from_secs(61)), None);
+        assert_eq!(partners.verify_at(B, now + Duration::from_secs(599)).as_deref(), Some("monitor"));
+        assert_eq!(partners.revoke("monitor"), 3);
+        assert!(partners.is_empty());
+    }
+
+    #[test]
+    fn rejects_weak_and_shared_tokens() {
+        let partners = PartnerTokens::new();
+        assert!(partners.register("monitor", "short", None).is_err());
+        partners.register("monitor", A, Some(SystemTime::now() - Duration::from_secs(1))).unwrap();
+        assert!(partners.register("load-test", A, None).is_err());
+        assert_eq!(partners.verify(A), None);
+        assert_eq!(partners.verify(" "), None);
+        assert_eq!(partners.remove_expired(), 1);
+        assert_eq!(partners.len(), 0);
+    }
+}
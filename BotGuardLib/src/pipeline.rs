@@ -0,0 +1,130 @@
+// Order of the stages of `BotGuard::evaluate` and when a stage ends the evaluation early.
+//
+// By default the stages run cheapest first: the allowlist and the client memory are lookups, the
+// user-agent stage runs the patterns, the IP stage looks the address up in ranges and feeds, the
+// header stage compares headers and transport fingerprints, and the behavior stage keeps state
+// across requests. A stage with a short-circuit score ends the evaluation once the score reaches
+// it, the costlier stages after it are skipped and `Reason::ShortCircuited` names the stage. The
+// allowlist and the client memory end the evaluation whenever they apply. Stages left out of a
+// pipeline do not run.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A group of checks of [`crate::request::BotGuard::evaluate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// The allowlist, ends the evaluation with a human verdict.
+    Allowlist,
+    /// The client memory, ends the evaluation for clients remembered as bots.
+    Memory,
+    /// The patterns, the classifier, spoofed browser details and malformed user-agents.
+    UserAgent,
+    /// Datacenter ranges and IP reputation.
+    Ip,
+    /// The referrer, missing browser headers, locale rules, header order and HTTP/2 fingerprint.
+    Headers,
+    /// The browser fingerprint, the rate limit and the cookie signal.
+    Behavior,
+}
+
+impl Stage {
+    /// Every stage, in the default order.
+    pub const ALL: [Stage; 6] = [Stage::Allowlist, Stage::Memory, Stage::UserAgent, Stage::Ip, Stage::Headers, Stage::Behavior];
+
+    /// The name in configuration files and reasons.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Allowlist => "allowlist",
+            Stage::Memory => "memory",
+            Stage::UserAgent => "user_agent",
+            Stage::Ip => "ip",
+            Stage::Headers => "headers",
+            Stage::Behavior => "behavior",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Stage::ALL.into_iter().find(|stage| stage.name() == s).ok_or_else(|| format!("unknown stage {:?}", s))
+    }
+}
+
+/// The stages to run, in order, see [`crate::request::BotGuard::pipeline`].
+///
+/// ```
+/// use BotGuardLib::pipeline::{Pipeline, Stage};
+///
+/// // a pattern match of weight 0.9 or more skips the IP, header and behavior checks
+/// let pipeline = Pipeline::default().short_circuit(Stage::UserAgent, 0.9);
+/// assert_eq!(pipeline.stages().count(), 6);
+///
+/// let without_memory = Pipeline::new([Stage::Allowlist, Stage::UserAgent, Stage::Headers]);
+/// assert!(!without_memory.contains(Stage::Memory));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    stages: Vec<(Stage, Option<f32>)>,
+}
+
+impl Default for Pipeline {
+    /// Every stage in the order of [`Stage::ALL`], none short-circuiting.
+    fn default() -> Self {
+        Pipeline::new(Stage::ALL)
+    }
+}
+
+impl Pipeline {
+    /// Runs `stages` in order, a stage listed twice only runs the first time.
+    pub fn new(stages: impl IntoIterator<Item = Stage>) -> Self {
+        let mut pipeline = Pipeline { stages: Vec::new() };
+        for stage in stages {
+            if !pipeline.contains(stage) {
+                pipeline.stages.push((stage, None));
+            }
+        }
+        pipeline
+    }
+
+    /// Ends the evaluation after `stage` once the score reaches `score`. Does nothing for a
+    /// stage the pipeline does not run.
+    pub fn short_circuit(mut self, stage: Stage, score: f32) -> Self {
+        if let Some((_, short_circuit)) = self.stages.iter_mut().find(|(listed, _)| *listed == stage) {
+            *short_circuit = Some(score);
+        }
+        self
+    }
+
+    /// The stages in order, with their short-circuit score.
+    pub fn stages(&self) -> impl Iterator<Item = (Stage, Option<f32>)> + '_ {
+        self.stages.iter().copied()
+    }
+
+    pub fn contains(&self, stage: Stage) -> bool {
+        self.stages.iter().any(|(listed, _)| *listed == stage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_stages_once() {
+        let pipeline = Pipeline::new([Stage::UserAgent, Stage::Allowlist, Stage::UserAgent]).short_circuit(Stage::UserAgent, 0.9).short_circuit(Stage::Ip, 0.5);
+        assert_eq!(pipeline.stages().collect::<Vec<_>>(), vec![(Stage::UserAgent, Some(0.9)), (Stage::Allowlist, None)]);
+        assert_eq!(Pipeline::default().stages().map(|(stage, _)| stage).collect::<Vec<_>>(), Stage::ALL);
+        assert_eq!("user_agent".parse::<Stage>(), Ok(Stage::UserAgent));
+        assert_eq!(Stage::Behavior.to_string(), "behavior");
+        assert!("cache".parse::<Stage>().is_err());
+    }
+}
@@ -0,0 +1,665 @@
+// Per-route policies mapping what is known about a request to the action to take.
+//
+// Rules are checked in order: `Tag` rules add their tag and evaluation continues, the first rule
+// with any other action decides. Routes are globs where `*` matches any run of characters,
+// `/` included, and `?` a single character.
+//
+// Routes are matched against the path as the application routes it, not as it was sent: the
+// query and fragment are cut off, escaped unreserved characters decoded, repeated slashes merged
+// and dot segments resolved, so `/%6Cogin?x=1` or `//./login/` cannot slip past a rule on
+// `/login`. A trailing slash does not matter, `/login/` matches `/login` and `/api` `/api/*`.
+//
+// In shadow mode decisions are computed and reported but every request is allowed, except by
+// rules marked as enforced. That way a new policy can be measured against real traffic first.
+//
+// Link preview bots get a policy of their own on top of the rules: they have to reach every public
+// page a link to which may be shared, or the shared link shows up without a title and image, but
+// have no business in the authenticated areas. They are recognized by their user-agent, which
+// anyone can send, so the rules still apply to them and the preview policy only decides what the
+// rules leave open.
+
+use std::time::Duration;
+
+/// What to do with a request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Allow,
+    Block,
+    /// Serve a challenge, e.g. a CAPTCHA or a JavaScript check, instead of the page.
+    Challenge,
+    /// Only label the request, evaluation continues with the next rule.
+    Tag(String),
+    /// Allow at most `requests` per `per` from the client.
+    RateLimit { requests: u32, per: Duration },
+    /// Serve the response, but at a trickle, see `actions::TrickleBody` with the `http-body`
+    /// feature or [`crate::actions::Tarpit`].
+    Tarpit { bytes_per_second: u32 },
+    /// Serve generated junk looking like the real page, see [`crate::decoy`].
+    Decoy,
+}
+
+/// Group of the bundled patterns holding SEO crawlers, e.g. AhrefsBot, SemrushBot, MJ12bot and DotBot.
+pub const SEO_TOOLS_GROUP: &str = "seo-tools";
+
+/// Group of the bundled patterns holding link preview bots, e.g. facebookexternalhit,
+/// Twitterbot and Slackbot.
+pub const SOCIAL_PREVIEWS_GROUP: &str = "social-previews";
+
+/// Groups of the bundled patterns with crawlers most sites want.
+const WANTED_GROUPS: &[&str] = &["feed-readers", "search-engines", "archivers", "social-previews", "monitoring", "advertising"];
+
+/// The default action for bots of a pattern group, see [`crate::BotDetector::classify_action`].
+///
+/// Feed readers, search engines, archivers, link previews, monitoring and ad verification are
+/// allowed. SEO crawlers are throttled to 30 requests a minute rather than blocked: blocked, they
+/// stop listing the site as a referring domain in the backlink tools, at full speed they cost
+/// capacity. Every other group, custom groups included, is blocked.
+///
+/// ```
+/// use std::time::Duration;
+/// use BotGuardLib::policy::{recommended_action, Action, SEO_TOOLS_GROUP};
+///
+/// assert_eq!(recommended_action(SEO_TOOLS_GROUP), Action::RateLimit { requests: 30, per: Duration::from_secs(60) });
+/// assert_eq!(recommended_action("Search-Engines"), Action::Allow);
+/// assert_eq!(recommended_action("spam-bots"), Action::Block);
+/// ```
+pub fn recommended_action(category: &str) -> Action {
+    if category.eq_ignore_ascii_case(SEO_TOOLS_GROUP) {
+        Action::RateLimit { requests: 30, per: Duration::from_secs(60) }
+    } else if WANTED_GROUPS.iter().any(|group| group.eq_ignore_ascii_case(category)) {
+        Action::Allow
+    } else {
+        Action::Block
+    }
+}
+
+/// The facts about a request a rule can test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyInput<'a> {
+    pub path: &'a str,
+    /// Pattern group of the matched bot, see [`crate::BotDetector::classify`].
+    pub category: Option<&'a str>,
+    /// Bot score between `0.0` (human) and `1.0` (bot).
+    pub score: f32,
+    /// Whether the client was verified to be who it claims, e.g. a crawler confirmed by reverse DNS.
+    /// [`crate::request::BotGuard`] sets it for requests with a partner token.
+    pub verified: bool,
+    /// ISO 3166-1 alpha-2 country of the client's address, if known.
+    pub country: Option<&'a str>,
+    /// Autonomous system of the client's address, if known.
+    pub asn: Option<u32>,
+    /// Whether the address belongs to a cloud provider, see [`crate::datacenter`].
+    pub datacenter: bool,
+}
+
+impl<'a> PolicyInput<'a> {
+    /// An unclassified, unverified request with a score of `0.0`.
+    pub fn new(path: &'a str) -> Self {
+        PolicyInput { path, category: None, score: 0.0, verified: false, country: None, asn: None, datacenter: false }
+    }
+
+    pub fn category(mut self, category: Option<&'a str>) -> Self {
+        self.category = category;
+        self
+    }
+
+    pub fn score(mut self, score: f32) -> Self {
+        self.score = score;
+        self
+    }
+
+    pub fn verified(mut self, verified: bool) -> Self {
+        self.verified = verified;
+        self
+    }
+
+    pub fn country(mut self, country: Option<&'a str>) -> Self {
+        self.country = country;
+        self
+    }
+
+    pub fn asn(mut self, asn: Option<u32>) -> Self {
+        self.asn = asn;
+        self
+    }
+
+    pub fn datacenter(mut self, datacenter: bool) -> Self {
+        self.datacenter = datacenter;
+        self
+    }
+}
+
+/// A condition of a [`PolicyRule`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// The request was classified into this category.
+    Category(String),
+    /// The request was not classified at all.
+    Unclassified,
+    /// The score is at least this value.
+    MinScore(f32),
+    /// The score is below this value.
+    MaxScore(f32),
+    Verified(bool),
+    /// The client's country is one of these.
+    Country(Vec<String>),
+    /// The client's country is known and none of these, e.g. the countries a shop serves.
+    NotCountry(Vec<String>),
+    /// The address does or does not belong to a cloud provider.
+    Datacenter(bool),
+    /// The address belongs to one of these autonomous systems.
+    Asn(Vec<u32>),
+    /// The autonomous system of the address is known and none of these.
+    NotAsn(Vec<u32>),
+}
+
+impl Condition {
+    /// Whether the condition tests where the request comes from rather than what it is.
+    pub(crate) fn is_geo(&self) -> bool {
+        matches!(self, Condition::Country(_) | Condition::NotCountry(_) | Condition::Datacenter(_) | Condition::Asn(_) | Condition::NotAsn(_))
+    }
+
+    fn holds(&self, input: &PolicyInput<'_>) -> bool {
+        match self {
+            Condition::Category(category) => input.category.is_some_and(|c| c.eq_ignore_ascii_case(category)),
+            Condition::Unclassified => input.category.is_none(),
+            Condition::MinScore(min) => input.score >= *min,
+            Condition::MaxScore(max) => input.score < *max,
+            Condition::Verified(verified) => input.verified == *verified,
+            Condition::Country(countries) => input.country.is_some_and(|country| countries.iter().any(|c| c.eq_ignore_ascii_case(country))),
+            Condition::NotCountry(countries) => input.country.is_some_and(|country| !countries.iter().any(|c| c.eq_ignore_ascii_case(country))),
+            Condition::Datacenter(datacenter) => input.datacenter == *datacenter,
+            Condition::Asn(asns) => input.asn.is_some_and(|asn| asns.contains(&asn)),
+            Condition::NotAsn(asns) => input.asn.is_some_and(|asn| !asns.contains(&asn)),
+        }
+    }
+}
+
+/// Applies an action to the requests of a route meeting all conditions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyRule {
+    route: String,
+    conditions: Vec<Condition>,
+    action: Action,
+    enforced: bool,
+}
+
+impl PolicyRule {
+    /// A rule applying to every request of the route, narrowed down with [`PolicyRule::when`].
+    pub fn new(route: &str, action: Action) -> Self {
+        PolicyRule { route: route.to_string(), conditions: Vec::new(), action, enforced: false }
+    }
+
+    /// Applies the rule's action even when the engine is in shadow mode.
+    pub fn enforced(mut self) -> Self {
+        self.enforced = true;
+        self
+    }
+
+    pub fn is_enforced(&self) -> bool {
+        self.enforced
+    }
+
+    pub fn when(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn route(&self) -> &str {
+        &self.route
+    }
+
+    pub fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+
+    pub fn action(&self) -> &Action {
+        &self.action
+    }
+
+    /// Whether the route matches the path of the request, see [`normalize_path`], and every
+    /// condition holds.
+    pub fn matches(&self, input: &PolicyInput<'_>) -> bool {
+        self.applies(&normalize_path(input.path), input)
+    }
+
+    /// [`PolicyRule::matches`] with the path already normalized.
+    fn applies(&self, path: &str, input: &PolicyInput<'_>) -> bool {
+        route_match(&self.route, path) && self.conditions.iter().all(|condition| condition.holds(input))
+    }
+}
+
+/// Where link preview bots of the [`SOCIAL_PREVIEWS_GROUP`] may go, see
+/// [`PolicyEngine::social_previews`].
+///
+/// ```
+/// use BotGuardLib::policy::{Action, Condition, PolicyEngine, PolicyInput, PolicyRule, SocialPreviews, SOCIAL_PREVIEWS_GROUP};
+///
+/// let engine = PolicyEngine::new(Action::Challenge)
+///     .rule(PolicyRule::new("*", Action::Block).when(Condition::Datacenter(true)))
+///     .social_previews(SocialPreviews::new().private(&["/account/*", "/admin/*"]));
+///
+/// let preview = |path| PolicyInput::new(path).category(Some(SOCIAL_PREVIEWS_GROUP)).score(1.0);
+/// // allowed where the rules leave the default action, kept out of the private routes
+/// assert_eq!(engine.evaluate(&preview("/blog/launch")).action, Action::Allow);
+/// assert_eq!(engine.evaluate(&preview("/account/settings")).action, Action::Block);
+/// assert_eq!(engine.evaluate(&preview("/account")).action, Action::Block);
+/// // the rules apply to previews as to any other request
+/// assert_eq!(engine.evaluate(&preview("/blog/launch").datacenter(true)).action, Action::Block);
+/// assert_eq!(engine.evaluate(&PolicyInput::new("/blog/launch").score(1.0)).action, Action::Challenge);
+///
+/// // only the listed pages are public
+/// let strict = PolicyEngine::default().social_previews(SocialPreviews::new().public(&["/", "/blog/*"]).private_action(Action::Challenge));
+/// assert_eq!(strict.evaluate(&preview("/blog/launch")).action, Action::Allow);
+/// assert_eq!(strict.evaluate(&preview("/checkout")).action, Action::Challenge);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SocialPreviews {
+    public: Vec<String>,
+    private: Vec<String>,
+    private_action: Action,
+}
+
+impl Default for SocialPreviews {
+    fn default() -> Self {
+        SocialPreviews::new()
+    }
+}
+
+impl SocialPreviews {
+    /// Allows the previews everywhere, narrowed down with [`SocialPreviews::public`] and
+    /// [`SocialPreviews::private`].
+    pub fn new() -> Self {
+        SocialPreviews { public: Vec::new(), private: Vec::new(), private_action: Action::Block }
+    }
+
+    /// Allows the previews only on these routes. A route ending in `/*` covers the bare prefix
+    /// too, `/blog/*` covers `/blog`.
+    pub fn public(mut self, routes: &[&str]) -> Self {
+        self.public.extend(routes.iter().map(|route| route.to_string()));
+        self
+    }
+
+    /// Keeps the previews out of these routes, even where they match a public one. A route
+    /// ending in `/*` covers the bare prefix too, `/account/*` covers `/account`.
+    pub fn private(mut self, routes: &[&str]) -> Self {
+        self.private.extend(routes.iter().map(|route| route.to_string()));
+        self
+    }
+
+    /// What happens to previews of private routes, [`Action::Block`] unless set. A
+    /// [`Action::Tag`] is treated as [`Action::Allow`].
+    pub fn private_action(mut self, action: Action) -> Self {
+        self.private_action = match action {
+            Action::Tag(_) => Action::Allow,
+            action => action,
+        };
+        self
+    }
+
+    /// The action for a request of a preview bot to `path`, before the rules are applied.
+    pub fn action(&self, path: &str) -> &Action {
+        if self.is_private(&normalize_path(path)) {
+            &self.private_action
+        } else {
+            &Action::Allow
+        }
+    }
+
+    /// Whether the previews are kept out of an already normalized path.
+    fn is_private(&self, path: &str) -> bool {
+        let covers = |route: &String| route_match(route, path);
+        self.private.iter().any(covers) || (!self.public.is_empty() && !self.public.iter().any(covers))
+    }
+}
+
+/// Outcome of [`PolicyEngine::evaluate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    /// Never [`Action::Tag`], tags are collected in `tags`.
+    pub action: Action,
+    pub tags: Vec<String>,
+    /// Index of the rule that decided, `None` if the default action or the
+    /// [`SocialPreviews`] applied.
+    pub rule: Option<usize>,
+    /// In shadow mode, the action that would have been taken in place of `action`.
+    pub shadowed: Option<Action>,
+}
+
+/// An ordered list of rules with a default action for requests no rule decides.
+///
+/// ```
+/// use std::time::Duration;
+/// use BotGuardLib::policy::{Action, Condition, PolicyEngine, PolicyInput, PolicyRule};
+///
+/// let engine = PolicyEngine::new(Action::Allow)
+///     .rule(PolicyRule::new("/api/*", Action::Block).when(Condition::MinScore(0.5)).when(Condition::Verified(false)))
+///     .rule(PolicyRule::new("/blog/*", Action::RateLimit { requests: 60, per: Duration::from_secs(60) }).when(Condition::MinScore(0.9)));
+///
+/// let scraper = |path| PolicyInput::new(path).category(Some("http-clients")).score(0.95);
+/// assert_eq!(engine.evaluate(&scraper("/api/v1/orders")).action, Action::Block);
+/// assert!(matches!(engine.evaluate(&scraper("/blog/post-1")).action, Action::RateLimit { .. }));
+/// assert_eq!(engine.evaluate(&scraper("/about")).action, Action::Allow);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyEngine {
+    rules: Vec<PolicyRule>,
+    default_action: Action,
+    shadow: bool,
+    social_previews: Option<SocialPreviews>,
+}
+
+impl Default for PolicyEngine {
+    /// Allows everything.
+    fn default() -> Self {
+        PolicyEngine::new(Action::Allow)
+    }
+}
+
+impl PolicyEngine {
+    /// An engine without rules, a [`Action::Tag`] default is treated as [`Action::Allow`].
+    pub fn new(default_action: Action) -> Self {
+        let default_action = match default_action {
+            Action::Tag(_) => Action::Allow,
+            action => action,
+        };
+        PolicyEngine { rules: Vec::new(), default_action, shadow: false, social_previews: None }
+    }
+
+    /// Turns shadow mode on or off, see the [module documentation](self).
+    ///
+    /// ```
+    /// use BotGuardLib::policy::{Action, Condition, PolicyEngine, PolicyInput, PolicyRule};
+    ///
+    /// let engine = PolicyEngine::new(Action::Allow)
+    ///     .rule(PolicyRule::new("/login", Action::Block).when(Condition::MinScore(0.8)).enforced())
+    ///     .rule(PolicyRule::new("*", Action::Challenge).when(Condition::MinScore(0.5)))
+    ///     .shadow(true);
+    ///
+    /// let measured = engine.evaluate(&PolicyInput::new("/search").score(0.9));
+    /// assert_eq!((measured.action, measured.shadowed), (Action::Allow, Some(Action::Challenge)));
+    /// assert_eq!(engine.evaluate(&PolicyInput::new("/login").score(0.9)).action, Action::Block);
+    /// ```
+    pub fn shadow(mut self, shadow: bool) -> Self {
+        self.shadow = shadow;
+        self
+    }
+
+    pub fn is_shadow(&self) -> bool {
+        self.shadow
+    }
+
+    /// Appends a rule, rules are checked in the order they were added.
+    pub fn rule(mut self, rule: PolicyRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn push(&mut self, rule: PolicyRule) {
+        self.rules.push(rule);
+    }
+
+    pub fn rules(&self) -> &[PolicyRule] {
+        &self.rules
+    }
+
+    pub fn default_action(&self) -> &Action {
+        &self.default_action
+    }
+
+    /// Decides the requests of link preview bots the rules leave open with `previews`: they are
+    /// allowed instead of getting the default action, and get the private action on private
+    /// routes unless a rule already decided on something other than [`Action::Allow`]. The rules
+    /// apply to them as to every other request, their tags included. The preview decisions are
+    /// shadowed in shadow mode like those of the rules that are not enforced.
+    pub fn social_previews(mut self, previews: SocialPreviews) -> Self {
+        self.social_previews = Some(previews);
+        self
+    }
+
+    pub fn social_preview_policy(&self) -> Option<&SocialPreviews> {
+        self.social_previews.as_ref()
+    }
+
+    pub fn evaluate(&self, input: &PolicyInput<'_>) -> Decision {
+        let path = normalize_path(input.path);
+        let mut tags = Vec::new();
+        let mut decided = None;
+        for (i, rule) in self.rules.iter().enumerate().filter(|(_, rule)| rule.applies(&path, input)) {
+            match &rule.action {
+                Action::Tag(tag) => tags.push(tag.clone()),
+                action => {
+                    decided = Some((action, i, rule.enforced));
+                    break;
+                }
+            }
+        }
+        let previews = self.social_previews.as_ref().filter(|_| input.category.is_some_and(|category| category.eq_ignore_ascii_case(SOCIAL_PREVIEWS_GROUP)));
+        match (decided, previews) {
+            (decided, Some(previews)) if previews.is_private(&path) && decided.is_none_or(|(action, _, _)| *action == Action::Allow) => {
+                self.decision(&previews.private_action, tags, None, false)
+            }
+            (Some((action, i, enforced)), _) => self.decision(action, tags, Some(i), enforced),
+            (None, Some(_)) => self.decision(&Action::Allow, tags, None, false),
+            (None, None) => self.decision(&self.default_action, tags, None, false),
+        }
+    }
+
+    fn decision(&self, action: &Action, tags: Vec<String>, rule: Option<usize>, enforced: bool) -> Decision {
+        if self.shadow && !enforced && *action != Action::Allow {
+            Decision { action: Action::Allow, tags, rule, shadowed: Some(action.clone()) }
+        } else {
+            Decision { action: action.clone(), tags, rule, shadowed: None }
+        }
+    }
+}
+
+/// The path a request is routed by: without query and fragment, with escaped unreserved
+/// characters decoded and other escapes in uppercase, without repeated slashes, dot segments and
+/// a trailing slash.
+///
+/// ```
+/// use BotGuardLib::policy::normalize_path;
+///
+/// assert_eq!(normalize_path("/login?next=/admin"), "/login");
+/// assert_eq!(normalize_path("//static/./css/../%6Cogin/"), "/static/login");
+/// assert_eq!(normalize_path("/files/a%2fb"), "/files/a%2Fb");
+/// assert_eq!(normalize_path("/../"), "/");
+/// ```
+pub fn normalize_path(path: &str) -> String {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = match tail {
+            [high, low, ..] if byte == b'%' => hex_digit(*high).zip(hex_digit(*low)).map(|(high, low)| high << 4 | low),
+            _ => None,
+        };
+        match escaped {
+            Some(value) if value.is_ascii_alphanumeric() || b"-._~".contains(&value) => decoded.push(value),
+            Some(_) => decoded.extend([b'%', tail[0].to_ascii_uppercase(), tail[1].to_ascii_uppercase()]),
+            None => {
+                decoded.push(byte);
+                rest = tail;
+                continue;
+            }
+        }
+        rest = &tail[2..];
+    }
+    // only whole escapes of ASCII characters were replaced
+    let decoded = String::from_utf8(decoded).expect("decoding unreserved characters keeps UTF-8");
+    let mut segments = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    char::from(byte).to_digit(16).map(|digit| digit as u8)
+}
+
+/// Matches a route against a normalized path, with or without a trailing slash.
+fn route_match(route: &str, path: &str) -> bool {
+    glob_match(route, path) || (path != "/" && glob_match(route, &format!("{}/", path)))
+}
+
+/// Matches `*` (any run of characters) and `?` (one character) against the whole text.
+pub(crate) fn glob_match(glob: &str, text: &str) -> bool {
+    let glob = glob.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+    let (mut g, mut t) = (0, 0);
+    // position of the last `*` and the text position it currently absorbs up to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, t));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_g, star_t)) => {
+                    g = star_g + 1;
+                    t = star_t + 1;
+                    star = Some((star_g, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_match("/api/*", "/api/v1/orders"));
+        assert!(glob_match("/api/*", "/api/"));
+        assert!(!glob_match("/api/*", "/api"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("/*/admin", "/eu/admin"));
+        assert!(glob_match("/*.xml", "/feeds/rss.xml"));
+        assert!(!glob_match("/*.xml", "/feeds/rss.xml.bak"));
+        assert!(glob_match("/v?/*", "/v2/users"));
+        assert!(!glob_match("/v?/*", "/v10/users"));
+        assert!(glob_match("/a*b*c", "/aXbYbZc"));
+        assert!(!glob_match("/login", "/login/"));
+    }
+
+    #[test]
+    fn normalizes_paths_before_matching() {
+        let engine = PolicyEngine::new(Action::Allow).rule(PolicyRule::new("/login", Action::Block)).rule(PolicyRule::new("/api/*", Action::Challenge));
+        let action = |path| engine.evaluate(&PolicyInput::new(path)).action;
+        assert_eq!(action("/login"), Action::Block);
+        assert_eq!(action("/login?x=1"), Action::Block);
+        assert_eq!(action("/login#top"), Action::Block);
+        assert_eq!(action("//login"), Action::Block);
+        assert_eq!(action("/%6Cogin"), Action::Block);
+        assert_eq!(action("/%6c%6F%67%69%6e"), Action::Block);
+        assert_eq!(action("/login/"), Action::Block);
+        assert_eq!(action("/./login"), Action::Block);
+        assert_eq!(action("/static/../login"), Action::Block);
+        assert_eq!(action("/%2E/login"), Action::Block);
+        assert_eq!(action("/logins"), Action::Allow);
+        assert_eq!(action("/api"), Action::Challenge);
+        assert_eq!(action("/api//v1/"), Action::Challenge);
+        // an escaped slash is not a separator
+        assert_eq!(action("/x%2F..%2Flogin"), Action::Allow);
+        assert!(PolicyRule::new("/login", Action::Block).matches(&PolicyInput::new("//login/?x=1")));
+    }
+
+    #[test]
+    fn first_deciding_rule_wins() {
+        let engine = PolicyEngine::new(Action::Tag("ignored".to_string()))
+            .rule(PolicyRule::new("*", Action::Tag("seen".to_string())))
+            .rule(PolicyRule::new("/api/*", Action::Allow).when(Condition::Category("search-engines".to_string())).when(Condition::Verified(true)))
+            .rule(PolicyRule::new("/api/*", Action::Tag("api".to_string())))
+            .rule(PolicyRule::new("/api/*", Action::Challenge).when(Condition::Unclassified).when(Condition::MinScore(0.3)))
+            .rule(PolicyRule::new("/api/*", Action::Block).when(Condition::MinScore(0.3)));
+        assert_eq!(engine.default_action(), &Action::Allow);
+
+        let crawler = PolicyInput::new("/api/items").category(Some("Search-Engines")).score(1.0);
+        assert_eq!(engine.evaluate(&crawler.clone().verified(true)), Decision { action: Action::Allow, tags: vec!["seen".to_string()], rule: Some(1), shadowed: None });
+        let spoofed = engine.evaluate(&crawler);
+        assert_eq!((spoofed.action, spoofed.tags.len(), spoofed.rule), (Action::Block, 2, Some(4)));
+
+        assert_eq!(engine.evaluate(&PolicyInput::new("/api/items").score(0.4)).action, Action::Challenge);
+        let human = engine.evaluate(&PolicyInput::new("/api/items").score(0.1));
+        assert_eq!((human.action, human.rule), (Action::Allow, None));
+        assert!(PolicyRule::new("/api/*", Action::Block).when(Condition::MaxScore(0.5)).matches(&PolicyInput::new("/api/x")));
+    }
+
+    #[test]
+    fn shadow_mode_only_enforces_marked_rules() {
+        let engine = PolicyEngine::new(Action::Challenge)
+            .rule(PolicyRule::new("/api/*", Action::Block).when(Condition::MinScore(0.9)).enforced())
+            .rule(PolicyRule::new("/api/*", Action::RateLimit { requests: 10, per: Duration::from_secs(1) }))
+            .rule(PolicyRule::new("/health", Action::Allow))
+            .shadow(true);
+
+        assert_eq!(engine.evaluate(&PolicyInput::new("/api/x").score(0.95)).action, Action::Block);
+        let limited = engine.evaluate(&PolicyInput::new("/api/x").score(0.5));
+        assert_eq!((limited.action, limited.rule), (Action::Allow, Some(1)));
+        assert!(matches!(limited.shadowed, Some(Action::RateLimit { requests: 10, .. })));
+        assert_eq!(engine.evaluate(&PolicyInput::new("/health")).shadowed, None);
+        assert_eq!(engine.evaluate(&PolicyInput::new("/")).shadowed, Some(Action::Challenge));
+        assert_eq!(engine.clone().shadow(false).evaluate(&PolicyInput::new("/")).action, Action::Challenge);
+    }
+
+    #[test]
+    fn geo_conditions_need_known_locations() {
+        let served = vec!["DE".to_string(), "AT".to_string()];
+        let engine = PolicyEngine::new(Action::Allow)
+            .rule(PolicyRule::new("/signup", Action::Challenge).when(Condition::NotCountry(served.clone())))
+            .rule(PolicyRule::new("*", Action::Block).when(Condition::Datacenter(true)).when(Condition::NotAsn(vec![15169])))
+            .rule(PolicyRule::new("/eu/*", Action::Tag("dach".to_string())).when(Condition::Country(served)));
+
+        let signup = |country| PolicyInput::new("/signup").country(country);
+        assert_eq!(engine.evaluate(&signup(Some("us"))).action, Action::Challenge);
+        assert_eq!(engine.evaluate(&signup(Some("de"))).action, Action::Allow);
+        assert_eq!(engine.evaluate(&signup(None)).action, Action::Allow);
+
+        let cloud = |asn| PolicyInput::new("/").datacenter(true).asn(asn);
+        assert_eq!(engine.evaluate(&cloud(Some(16509))).action, Action::Block);
+        assert_eq!(engine.evaluate(&cloud(Some(15169))).action, Action::Allow);
+        assert_eq!(engine.evaluate(&cloud(None)).action, Action::Allow);
+        assert_eq!(engine.evaluate(&PolicyInput::new("/eu/shop").country(Some("AT"))).tags, ["dach"]);
+        assert!(Condition::Asn(vec![1]).is_geo() && !Condition::MinScore(0.5).is_geo());
+    }
+
+    #[test]
+    fn previews_are_held_to_every_rule() {
+        let engine = PolicyEngine::new(Action::Challenge)
+            .rule(PolicyRule::new("*", Action::Tag("seen".to_string())))
+            .rule(PolicyRule::new("/api/*", Action::Block).when(Condition::MinScore(0.9)))
+            .rule(PolicyRule::new("*", Action::RateLimit { requests: 10, per: Duration::from_secs(1) }).when(Condition::Datacenter(true)))
+            .rule(PolicyRule::new("/blog/drafts/*", Action::Allow).when(Condition::Verified(true)))
+            .social_previews(SocialPreviews::new().public(&["/", "/blog/*", "/api/*"]).private(&["/blog/drafts/*"]));
+
+        let preview = |path| PolicyInput::new(path).category(Some(SOCIAL_PREVIEWS_GROUP));
+        let seen = vec!["seen".to_string()];
+        assert_eq!(engine.evaluate(&preview("/blog")), Decision { action: Action::Allow, tags: seen.clone(), rule: None, shadowed: None });
+        // a spoofed preview user-agent does not get around the score and datacenter rules
+        assert_eq!(engine.evaluate(&preview("/api/posts").score(0.95)), Decision { action: Action::Block, tags: seen.clone(), rule: Some(1), shadowed: None });
+        assert!(matches!(engine.evaluate(&preview("/blog").datacenter(true)).action, Action::RateLimit { .. }));
+        // private routes stay private, even where a rule allows
+        assert_eq!(engine.evaluate(&preview("/blog/drafts").verified(true)), Decision { action: Action::Block, tags: seen.clone(), rule: None, shadowed: None });
+        assert!(matches!(engine.evaluate(&preview("/blog/drafts").datacenter(true)).action, Action::RateLimit { .. }));
+        assert_eq!(engine.evaluate(&preview("/blogroll")).action, Action::Block);
+        assert_eq!(engine.clone().shadow(true).evaluate(&preview("/blogroll")).shadowed, Some(Action::Block));
+        assert_eq!(engine.evaluate(&PolicyInput::new("/blog")).action, Action::Challenge);
+    }
+}
@@ -0,0 +1,217 @@
+// Client addresses behind reverse proxies, load balancers and CDNs.
+//
+// Forwarding headers are only as trustworthy as whoever set them: a client talking to us directly
+// can send any `X-Forwarded-For` it likes. The headers are therefore only read when the
+// connection comes from a trusted proxy, and address chains are walked from the right, skipping
+// the trusted proxies that appended to them, until the first address that is not one. Everything
+// to the left of that address was written by the client and is ignored. An entry that is not an
+// address ends the walk at the last address that could be vouched for.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use crate::ip::{IpNet, IpRangeSet};
+use crate::request::RequestSnapshot;
+
+/// A header a proxy passes the client address in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ForwardingHeader {
+    /// `Forwarded: for=192.0.2.60;proto=https, for="[2001:db8::17]:4711"`, RFC 7239.
+    Forwarded,
+    /// `X-Forwarded-For: 192.0.2.60, 198.51.100.17`, each proxy appending its peer.
+    XForwardedFor,
+    /// `X-Real-IP`, a single address as set by nginx.
+    XRealIp,
+    /// `CF-Connecting-IP`, a single address as set by Cloudflare.
+    CfConnectingIp,
+    /// `True-Client-IP`, a single address as set by Akamai and Cloudflare Enterprise.
+    TrueClientIp,
+}
+
+impl ForwardingHeader {
+    pub const ALL: [ForwardingHeader; 5] =
+        [ForwardingHeader::Forwarded, ForwardingHeader::XForwardedFor, ForwardingHeader::XRealIp, ForwardingHeader::CfConnectingIp, ForwardingHeader::TrueClientIp];
+
+    /// The lowercase header name.
+    pub fn name(self) -> &'static str {
+        match self {
+            ForwardingHeader::Forwarded => "forwarded",
+            ForwardingHeader::XForwardedFor => "x-forwarded-for",
+            ForwardingHeader::XRealIp => "x-real-ip",
+            ForwardingHeader::CfConnectingIp => "cf-connecting-ip",
+            ForwardingHeader::TrueClientIp => "true-client-ip",
+        }
+    }
+
+    /// Whether every proxy appends to the header, instead of the first one setting it.
+    fn is_chain(self) -> bool {
+        matches!(self, ForwardingHeader::Forwarded | ForwardingHeader::XForwardedFor)
+    }
+}
+
+impl fmt::Display for ForwardingHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ForwardingHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ForwardingHeader::ALL.into_iter().find(|header| header.name().eq_ignore_ascii_case(s)).ok_or_else(|| format!("unknown forwarding header {:?}", s))
+    }
+}
+
+/// Finds the client address of a request that may have passed through trusted proxies.
+///
+/// ```
+/// use std::net::IpAddr;
+/// use BotGuardLib::proxy::{ClientIpExtractor, ForwardingHeader};
+///
+/// let ip = |text: &str| text.parse::<IpAddr>().unwrap();
+/// let extractor = ClientIpExtractor::new(&["10.0.0.0/8".parse().unwrap()]);
+/// let headers = [("X-Forwarded-For", "198.51.100.1, 203.0.113.7, 10.0.0.3")];
+///
+/// // the load balancer at 10.0.0.2 got the request from 10.0.0.3, which got it from 203.0.113.7
+/// assert_eq!(extractor.extract(ip("10.0.0.2"), headers), ip("203.0.113.7"));
+/// // a client sending the header itself is not believed
+/// assert_eq!(extractor.extract(ip("192.0.2.9"), headers), ip("192.0.2.9"));
+///
+/// let cloudflare = ClientIpExtractor::new(&["173.245.48.0/20".parse().unwrap()]).headers(&[ForwardingHeader::CfConnectingIp]);
+/// assert_eq!(cloudflare.extract(ip("173.245.48.1"), [("CF-Connecting-IP", "203.0.113.7")]), ip("203.0.113.7"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientIpExtractor {
+    trusted: IpRangeSet<()>,
+    headers: Vec<ForwardingHeader>,
+}
+
+impl ClientIpExtractor {
+    /// Trusts the proxies in `trusted`, reading `X-Forwarded-For` only. Proxies appending to it,
+    /// like nginx and AWS load balancers, pass a `Forwarded` header of the client on unchanged,
+    /// so reading that one as well would let any client pick its own address.
+    pub fn new(trusted: &[IpNet]) -> Self {
+        ClientIpExtractor { trusted: trusted.iter().map(|&net| (net, ())).collect(), headers: vec![ForwardingHeader::XForwardedFor] }
+    }
+
+    /// Reads these headers instead, the first one the request has is used. List only the
+    /// headers the proxies set, or overwrite, any other one comes from the client.
+    pub fn headers(mut self, headers: &[ForwardingHeader]) -> Self {
+        self.headers = headers.to_vec();
+        self
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.contains(ip.to_canonical())
+    }
+
+    /// The client address of a request received from `peer`, `peer` itself unless it is a
+    /// trusted proxy that forwarded an address.
+    pub fn extract<'a>(&self, peer: IpAddr, headers: impl IntoIterator<Item = (&'a str, &'a str)> + Clone) -> IpAddr {
+        let peer = peer.to_canonical();
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+        for &header in &self.headers {
+            let mut values = headers.clone().into_iter().filter(|(name, _)| name.eq_ignore_ascii_case(header.name())).map(|(_, value)| value).peekable();
+            if values.peek().is_none() {
+                continue;
+            }
+            if !header.is_chain() {
+                return values.next().and_then(parse_address).unwrap_or(peer);
+            }
+            // several headers of the same name form one list, in the order they were added
+            let hops = values.flat_map(|value| value.split(',')).map(|hop| if header == ForwardingHeader::Forwarded { forwarded_for(hop) } else { parse_address(hop) });
+            return self.walk(peer, hops.collect());
+        }
+        peer
+    }
+
+    /// The client address of a request whose [`RequestSnapshot::client_ip`] is the peer of the
+    /// connection.
+    pub fn extract_request(&self, request: &RequestSnapshot) -> Option<IpAddr> {
+        let peer = request.client_ip?;
+        Some(self.extract(peer, request.headers.iter().map(|(name, value)| (name.as_str(), value.as_str()))))
+    }
+
+    /// Takes the closest hop from the right that is not a trusted proxy.
+    fn walk(&self, peer: IpAddr, hops: Vec<Option<IpAddr>>) -> IpAddr {
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            let Some(hop) = hop else { break };
+            client = hop;
+            if !self.is_trusted(hop) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// An address, optionally with a port or in brackets.
+fn parse_address(text: &str) -> Option<IpAddr> {
+    let text = text.trim().trim_matches('"');
+    if let Ok(ip) = text.parse::<IpAddr>() {
+        return Some(ip.to_canonical());
+    }
+    if let Ok(socket) = text.parse::<SocketAddr>() {
+        return Some(socket.ip().to_canonical());
+    }
+    text.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')).and_then(|ip| ip.parse::<IpAddr>().ok()).map(|ip| ip.to_canonical())
+}
+
+/// The `for` parameter of one element of a `Forwarded` header, `None` if it is missing, `unknown`
+/// or an obfuscated identifier.
+fn forwarded_for(element: &str) -> Option<IpAddr> {
+    element.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        key.trim().eq_ignore_ascii_case("for").then(|| parse_address(value)).flatten()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn walks_forwarding_chains_from_the_right() {
+        let extractor = ClientIpExtractor::new(&["10.0.0.0/8".parse().unwrap(), "2001:db8::/32".parse().unwrap()]).headers(&[ForwardingHeader::Forwarded, ForwardingHeader::XForwardedFor]);
+        let forwarded = [("Forwarded", "for=198.51.100.1"), ("Forwarded", "for=\"[2001:db8:cafe::17]:4711\";proto=https, For=\"203.0.113.7:8080\";by=10.0.0.1")];
+        assert_eq!(extractor.extract(ip("10.0.0.1"), forwarded), ip("203.0.113.7"));
+        // every hop trusted: the leftmost is the client
+        assert_eq!(extractor.extract(ip("10.0.0.1"), [("X-Forwarded-For", "10.1.1.1, 10.2.2.2")]), ip("10.1.1.1"));
+        // a hop that is not an address ends the walk at the last trusted one
+        assert_eq!(extractor.extract(ip("10.0.0.1"), [("X-Forwarded-For", "203.0.113.7, garbage, 10.2.2.2")]), ip("10.2.2.2"));
+        assert_eq!(extractor.extract(ip("10.0.0.1"), [("Forwarded", "for=unknown")]), ip("10.0.0.1"));
+        // the first header listed that the request has is used, X-Real-IP is not read unless configured
+        assert_eq!(extractor.extract(ip("10.0.0.1"), [("X-Forwarded-For", "192.0.2.1"), ("Forwarded", "for=192.0.2.2")]), ip("192.0.2.2"));
+        assert_eq!(extractor.extract(ip("10.0.0.1"), [("X-Real-IP", "192.0.2.3")]), ip("10.0.0.1"));
+        assert_eq!(extractor.extract(ip("::ffff:10.0.0.1"), [("X-Forwarded-For", "192.0.2.4:1234")]), ip("192.0.2.4"));
+    }
+
+    #[test]
+    fn reads_only_x_forwarded_for_unless_configured() {
+        // the proxy appended the real peer to X-Forwarded-For and passed the client's Forwarded on
+        let extractor = ClientIpExtractor::new(&["10.0.0.0/8".parse().unwrap()]);
+        let headers = [("Forwarded", "for=1.2.3.4"), ("X-Forwarded-For", "203.0.113.7")];
+        assert_eq!(extractor.extract(ip("10.0.0.1"), headers), ip("203.0.113.7"));
+        assert_eq!(extractor.extract(ip("10.0.0.1"), [("Forwarded", "for=1.2.3.4")]), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn reads_single_address_headers_from_trusted_peers() {
+        let extractor = ClientIpExtractor::new(&["10.0.0.0/8".parse().unwrap()]).headers(&[ForwardingHeader::CfConnectingIp, ForwardingHeader::XRealIp]);
+        let request = RequestSnapshot::new("GET", "/").header("X-Real-IP", "192.0.2.5").header("CF-Connecting-IP", "192.0.2.6").client_ip(ip("10.0.0.1"));
+        assert_eq!(extractor.extract_request(&request), Some(ip("192.0.2.6")));
+        assert_eq!(extractor.extract(ip("10.0.0.1"), [("cf-connecting-ip", "nonsense")]), ip("10.0.0.1"));
+        assert_eq!(extractor.extract_request(&RequestSnapshot::new("GET", "/")), None);
+        assert_eq!("X-Forwarded-For".parse::<ForwardingHeader>(), Ok(ForwardingHeader::XForwardedFor));
+        assert!("x-client-ip".parse::<ForwardingHeader>().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+// Python bindings built with PyO3, enabled with the `python` feature: the `botguard` extension
+// module with a `BotDetector` that has the patterns and verdicts of the Rust one.
+//
+// `maturin build --release` in `bindings/python` builds the wheel, it adds
+// `pyo3/extension-module` and builds the crate as a `cdylib`. The batch methods release the GIL
+// while they check, so other Python threads keep running over a large traffic dump:
+//
+//     import pandas as pd
+//     from botguard import BotDetector
+//
+//     detector = BotDetector()
+//     traffic = pd.read_csv("access.csv")
+//     traffic["verdict"] = detector.check_many(traffic["user_agent"])
+//     traffic["category"] = detector.classify_many(traffic["user_agent"])
+
+use std::sync::{RwLock, RwLockReadGuard};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::{BotDetector, Verdict, CUSTOM_GROUP};
+
+/// `BotDetector(patterns=None)` in Python, with the bundled patterns unless newline-delimited
+/// patterns in the format of [`BotDetector::new`] are given. Invalid patterns raise `ValueError`.
+#[pyclass(name = "BotDetector", module = "botguard", frozen)]
+pub struct PyBotDetector(RwLock<BotDetector>);
+
+fn verdict_name(verdict: Verdict) -> &'static str {
+    match verdict {
+        Verdict::Human => "human",
+        Verdict::Suspicious => "suspicious",
+        Verdict::Bot => "bot",
+    }
+}
+
+/// The entries of an iterable such as a list or a pandas `Series`, `None` for those that are no
+/// strings.
+fn user_agents(values: &Bound<'_, PyAny>) -> PyResult<Vec<Option<String>>> {
+    values.try_iter()?.map(|value| Ok(value?.extract::<String>().ok())).collect()
+}
+
+impl PyBotDetector {
+    fn detector(&self) -> RwLockReadGuard<'_, BotDetector> {
+        self.0.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[pymethods]
+impl PyBotDetector {
+    #[new]
+    #[pyo3(signature = (patterns = None))]
+    fn new(patterns: Option<&str>) -> PyResult<Self> {
+        let detector = match patterns {
+            Some(patterns) => BotDetector::builder().patterns(patterns).build().map_err(|e| PyValueError::new_err(e.to_string()))?,
+            None => BotDetector::default(),
+        };
+        Ok(PyBotDetector(RwLock::new(detector)))
+    }
+
+    /// Returns "human", "suspicious" or "bot".
+    fn check(&self, user_agent: &str) -> &'static str {
+        verdict_name(self.detector().check(user_agent))
+    }
+
+    fn check_bot(&self, user_agent: &str) -> bool {
+        self.detector().check_bot(user_agent)
+    }
+
+    /// Returns the pattern group of a bot, or None.
+    fn classify(&self, user_agent: &str) -> Option<String> {
+        self.detector().classify(user_agent).map(str::to_string)
+    }
+
+    /// Checks an iterable of user-agents with the GIL released, entries that are no strings
+    /// give None.
+    fn check_many(&self, py: Python<'_>, user_agents: &Bound<'_, PyAny>) -> PyResult<Vec<Option<&'static str>>> {
+        let user_agents = self::user_agents(user_agents)?;
+        Ok(py.allow_threads(|| {
+            let detector = self.detector();
+            user_agents.iter().map(|user_agent| user_agent.as_deref().map(|user_agent| verdict_name(detector.check(user_agent)))).collect()
+        }))
+    }
+
+    /// Classifies an iterable of user-agents with the GIL released, entries that are no strings
+    /// give None.
+    fn classify_many(&self, py: Python<'_>, user_agents: &Bound<'_, PyAny>) -> PyResult<Vec<Option<String>>> {
+        let user_agents = self::user_agents(user_agents)?;
+        Ok(py.allow_threads(|| {
+            let detector = self.detector();
+            user_agents.iter().map(|user_agent| detector.classify(user_agent.as_deref()?).map(str::to_string)).collect()
+        }))
+    }
+
+    /// Appends newline-delimited patterns to the custom group, raises ValueError and adds none
+    /// if one is invalid.
+    fn append(&self, patterns: &str) -> PyResult<()> {
+        let patterns = patterns.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<&str>>();
+        self.0
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .append_to_group(CUSTOM_GROUP, &patterns)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn __len__(&self) -> usize {
+        self.detector().len()
+    }
+}
+
+/// The `botguard` module.
+#[pymodule]
+fn botguard(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyBotDetector>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn checks_from_python() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("BotDetector", py.get_type::<PyBotDetector>()).unwrap();
+            let script = cr#"
+detector = BotDetector("[scrapers]\ncurl/")
+assert detector.check("curl/8.0") == "bot"
+assert detector.check_bot("curl/8.0") and not detector.check_bot("Mozilla/5.0")
+assert detector.classify("curl/8.0") == "scrapers"
+assert detector.classify("Mozilla/5.0") is None
+assert detector.check_many(("curl/8.0", "Mozilla/5.0", None, 7)) == ["bot", "human", None, None]
+assert detector.classify_many(iter(["curl/8.0", b"curl/8.0"])) == ["scrapers", None]
+
+detector.append("^wget/\n\n")
+assert detector.check("Wget/1.21") == "bot" and len(detector) == 2
+for call in (lambda: detector.append("(open"), lambda: BotDetector("(open")):
+    try:
+        call()
+        raise AssertionError("accepted an invalid pattern")
+    except ValueError:
+        pass
+assert len(detector) == 2
+assert BotDetector().check_bot("Googlebot/2.1")
+"#;
+            if let Err(e) = py.run(script, Some(&globals), None) {
+                panic!("{}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,298 @@
+// Redis backend of `state::StateStore`, speaking RESP over a single pipelined connection.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::state::StateStore;
+use crate::BotGuardError;
+
+/// A reply of the server, error replies are turned into errors.
+#[derive(Debug, Clone, PartialEq)]
+enum Reply {
+    Status(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+/// Shares state through a Redis server, see [`crate::state`].
+///
+/// Connects on first use and reconnects after errors. Wrap it in a
+/// [`crate::state::FallbackStore`] to keep working while Redis is unreachable.
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use BotGuardLib::state::{FallbackStore, RateLimiter, RedisStore};
+///
+/// let store = FallbackStore::new(RedisStore::open("redis://:secret@redis.internal:6379/2").unwrap());
+/// let limiter = RateLimiter::new(Arc::new(store));
+/// let decision = limiter.check("203.0.113.7", 100, Duration::from_secs(60)).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RedisStore {
+    addr: String,
+    password: Option<String>,
+    db: Option<u32>,
+    timeout: Duration,
+    connection: Mutex<Option<BufReader<TcpStream>>>,
+}
+
+impl RedisStore {
+    /// Parses `redis://[:password@]host[:port][/db]`, the connection is opened on first use.
+    pub fn open(url: &str) -> Result<Self, BotGuardError> {
+        let invalid = |reason: &str| BotGuardError::InvalidConfig { line: None, reason: format!("invalid Redis URL {:?}: {}", url, reason) };
+        let rest = url.strip_prefix("redis://").ok_or_else(|| invalid("expected redis://"))?;
+        let (credentials, rest) = match rest.rsplit_once('@') {
+            Some((credentials, rest)) => (Some(credentials), rest),
+            None => (None, rest),
+        };
+        let password = credentials.map(|credentials| match credentials.split_once(':') {
+            Some((_, password)) => password.to_string(),
+            None => credentials.to_string(),
+        });
+        let (host, db) = match rest.split_once('/') {
+            Some((host, "")) => (host, None),
+            Some((host, db)) => (host, Some(db.parse().map_err(|_| invalid("invalid database number"))?)),
+            None => (rest, None),
+        };
+        if host.is_empty() {
+            return Err(invalid("missing host"));
+        }
+        let addr = if host.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+            host.to_string()
+        } else {
+            format!("{}:6379", host)
+        };
+        Ok(RedisStore { addr, password, db, timeout: Duration::from_secs(1), connection: Mutex::new(None) })
+    }
+
+    /// Timeout for connecting and for each round trip, 1 second by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn unavailable(&self, reason: impl ToString) -> BotGuardError {
+        BotGuardError::StateUnavailable { backend: format!("redis://{}", self.addr), reason: reason.to_string() }
+    }
+
+    fn connect(&self) -> io::Result<BufReader<TcpStream>> {
+        let addr = std::net::ToSocketAddrs::to_socket_addrs(&self.addr)?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+        let stream = TcpStream::connect_timeout(&addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.set_nodelay(true)?;
+        Ok(BufReader::new(stream))
+    }
+
+    /// Sends the commands in one write and reads one reply per command.
+    fn pipeline(&self, commands: &[&[&[u8]]]) -> Result<Vec<Reply>, BotGuardError> {
+        let mut connection = self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut setup = Vec::new();
+        if connection.is_none() {
+            *connection = Some(self.connect().map_err(|e| self.unavailable(e))?);
+            if let Some(password) = &self.password {
+                setup.push(vec![b"AUTH".to_vec(), password.as_bytes().to_vec()]);
+            }
+            if let Some(db) = self.db {
+                setup.push(vec![b"SELECT".to_vec(), db.to_string().into_bytes()]);
+            }
+        }
+        let mut request = Vec::new();
+        for command in setup.iter().map(|c| c.iter().map(Vec::as_slice).collect::<Vec<&[u8]>>()).chain(commands.iter().map(|c| c.to_vec())) {
+            encode(&mut request, &command);
+        }
+        let stream = connection.as_mut().expect("connected above");
+        let result = stream
+            .get_mut()
+            .write_all(&request)
+            .and_then(|()| (0..setup.len() + commands.len()).map(|_| read_reply(stream)).collect::<io::Result<Vec<_>>>());
+        match result {
+            Ok(mut replies) => {
+                let errors = replies.iter().filter_map(|reply| reply.as_ref().err()).next().cloned();
+                if let Some(error) = errors {
+                    // setup failures leave the connection unusable
+                    if replies[..setup.len()].iter().any(Result::is_err) {
+                        *connection = None;
+                    }
+                    return Err(self.unavailable(error));
+                }
+                Ok(replies.drain(setup.len()..).map(|reply| reply.expect("errors handled above")).collect())
+            }
+            Err(e) => {
+                *connection = None;
+                Err(self.unavailable(e))
+            }
+        }
+    }
+}
+
+fn encode(out: &mut Vec<u8>, command: &[&[u8]]) {
+    out.extend_from_slice(format!("*{}\r\n", command.len()).as_bytes());
+    for arg in command {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Reads one reply, the inner result holds error replies of the server.
+fn read_reply<R: BufRead>(reader: &mut R) -> io::Result<Result<Reply, String>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed reply");
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line)?;
+    let line = line.strip_suffix(b"\r\n").ok_or_else(invalid)?;
+    let (&kind, rest) = line.split_first().ok_or_else(invalid)?;
+    let text = std::str::from_utf8(rest).map_err(|_| invalid())?;
+    let number = || text.parse::<i64>().map_err(|_| invalid());
+    Ok(Ok(match kind {
+        b'+' => Reply::Status(text.to_string()),
+        b'-' => return Ok(Err(text.to_string())),
+        b':' => Reply::Integer(number()?),
+        b'$' => match usize::try_from(number()?) {
+            Ok(len) => {
+                let mut data = vec![0; len + 2];
+                reader.read_exact(&mut data)?;
+                data.truncate(len);
+                Reply::Bulk(Some(data))
+            }
+            Err(_) => Reply::Bulk(None),
+        },
+        b'*' => {
+            let mut items = Vec::new();
+            for _ in 0..number()?.max(0) {
+                match read_reply(reader)? {
+                    Ok(item) => items.push(item),
+                    Err(error) => return Ok(Err(error)),
+                }
+            }
+            Reply::Array(items)
+        }
+        _ => return Err(invalid()),
+    }))
+}
+
+fn millis(ttl: Duration) -> Vec<u8> {
+    ttl.as_millis().max(1).to_string().into_bytes()
+}
+
+impl StateStore for RedisStore {
+    fn increment(&self, key: &str, ttl: Duration) -> Result<u64, BotGuardError> {
+        // INCR keeps the expiry set by the first SET
+        let ttl = millis(ttl);
+        let replies = self.pipeline(&[&[b"SET", key.as_bytes(), b"0", b"PX", &ttl, b"NX"], &[b"INCR", key.as_bytes()]])?;
+        match replies.last() {
+            Some(Reply::Integer(count)) => Ok(*count as u64),
+            other => Err(self.unavailable(format!("unexpected reply {:?}", other))),
+        }
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, BotGuardError> {
+        match self.pipeline(&[&[b"GET", key.as_bytes()]])?.pop() {
+            Some(Reply::Bulk(value)) => Ok(value.map(|value| String::from_utf8_lossy(&value).into_owned())),
+            other => Err(self.unavailable(format!("unexpected reply {:?}", other))),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), BotGuardError> {
+        self.pipeline(&[&[b"SET", key.as_bytes(), value.as_bytes(), b"PX", &millis(ttl)]]).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+
+    /// Serves AUTH, SELECT, SET, INCR and GET without expiry for one connection.
+    fn fake_redis(password: &'static str) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut values = HashMap::<Vec<u8>, Vec<u8>>::new();
+            let mut log = Vec::new();
+            while let Ok(Ok(Reply::Array(args))) = read_reply(&mut reader) {
+                let args = args.into_iter().map(|arg| match arg {
+                    Reply::Bulk(Some(arg)) => arg,
+                    other => panic!("{:?}", other),
+                }).collect::<Vec<_>>();
+                log.push(String::from_utf8_lossy(&args.join(&b' ')).into_owned());
+                let reply = match args[0].as_slice() {
+                    b"AUTH" if args[1] == password.as_bytes() => "+OK\r\n".to_string(),
+                    b"AUTH" => "-WRONGPASS invalid password\r\n".to_string(),
+                    b"SELECT" => "+OK\r\n".to_string(),
+                    b"SET" if args.last().unwrap() == b"NX" && values.contains_key(&args[1]) => "$-1\r\n".to_string(),
+                    b"SET" => {
+                        values.insert(args[1].clone(), args[2].clone());
+                        "+OK\r\n".to_string()
+                    }
+                    b"INCR" => {
+                        let value = values.entry(args[1].clone()).or_insert(b"0".to_vec());
+                        let count = String::from_utf8_lossy(value).parse::<i64>().unwrap() + 1;
+                        *value = count.to_string().into_bytes();
+                        format!(":{}\r\n", count)
+                    }
+                    b"GET" => match values.get(&args[1]) {
+                        Some(value) => format!("${}\r\n{}\r\n", value.len(), String::from_utf8_lossy(value)),
+                        None => "$-1\r\n".to_string(),
+                    },
+                    _ => "-ERR unknown command\r\n".to_string(),
+                };
+                writer.write_all(reply.as_bytes()).unwrap();
+            }
+            log
+        });
+        (addr, server)
+    }
+
+    #[test]
+    fn parses_urls() {
+        let store = RedisStore::open("redis://:secret@cache.internal/3").unwrap();
+        assert_eq!((store.addr.as_str(), store.password.as_deref(), store.db), ("cache.internal:6379", Some("secret"), Some(3)));
+        let store = RedisStore::open("redis://127.0.0.1:6380").unwrap();
+        assert_eq!((store.addr.as_str(), store.password, store.db), ("127.0.0.1:6380", None, None));
+        assert!(RedisStore::open("http://cache.internal").is_err());
+        assert!(RedisStore::open("redis://cache.internal/x").is_err());
+        let unreachable = RedisStore::open("redis://127.0.0.1:1").unwrap().with_timeout(Duration::from_millis(200));
+        assert!(matches!(unreachable.get("k"), Err(BotGuardError::StateUnavailable { .. })));
+    }
+
+    #[test]
+    fn speaks_resp() {
+        let (addr, server) = fake_redis("secret");
+        let store = RedisStore::open(&format!("redis://:secret@{}/2", addr)).unwrap();
+        assert_eq!(store.increment("botguard:rate:a", Duration::from_secs(60)).unwrap(), 1);
+        assert_eq!(store.increment("botguard:rate:a", Duration::from_secs(60)).unwrap(), 2);
+        store.set("botguard:verdict:x", "bot", Duration::from_millis(1500)).unwrap();
+        assert_eq!(store.get("botguard:verdict:x").unwrap().as_deref(), Some("bot"));
+        assert_eq!(store.get("missing").unwrap(), None);
+        drop(store);
+        assert_eq!(
+            server.join().unwrap(),
+            vec![
+                "AUTH secret",
+                "SELECT 2",
+                "SET botguard:rate:a 0 PX 60000 NX",
+                "INCR botguard:rate:a",
+                "SET botguard:rate:a 0 PX 60000 NX",
+                "INCR botguard:rate:a",
+                "SET botguard:verdict:x bot PX 1500",
+                "GET botguard:verdict:x",
+                "GET missing",
+            ]
+        );
+
+        let (addr, _server) = fake_redis("secret");
+        let store = RedisStore::open(&format!("redis://:wrong@{}", addr)).unwrap();
+        assert!(matches!(store.get("k"), Err(BotGuardError::StateUnavailable { ref reason, .. }) if reason.contains("WRONGPASS")));
+    }
+}
@@ -0,0 +1,251 @@
+// Signed reference IDs for the block page, so support can investigate an "I was blocked"
+// complaint from the reference the user quotes instead of searching the logs for it.
+//
+// A reference carries the decision itself: when it was made, the verdict, score, category,
+// action and reason codes, the client address and the path. Nothing is stored, any instance with
+// the secret decodes the reference of any other. The details are encrypted, so a bot reading its
+// own reference learns nothing about which signals caught it: the keystream is HMAC-SHA-512 of a
+// random nonce and a block counter, and a MAC over nonce and ciphertext rejects forged or
+// mistyped references. The result is base64url without padding, safe in URLs, headers and HTML.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::actions::random_u64;
+use crate::policy::Action;
+use crate::request::{RequestSnapshot, RequestVerdict};
+use crate::review::{action_name, parse_action};
+use crate::{crypto, Verdict};
+
+/// Bytes of the nonce and of the MAC in a reference.
+const NONCE_LEN: usize = 8;
+const MAC_LEN: usize = 16;
+
+/// Longer paths are cut, the rest of a long query rarely matters to support.
+const MAX_PATH_LEN: usize = 256;
+
+/// The decision a reference was issued for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    /// When the request was evaluated, to the second.
+    pub issued: SystemTime,
+    pub verdict: Verdict,
+    pub score: f32,
+    pub category: Option<String>,
+    pub action: Action,
+    /// The codes of the reasons, see [`crate::request::Reason::code`].
+    pub reasons: Vec<String>,
+    pub client_ip: Option<IpAddr>,
+    /// The path of the request, at most 256 bytes of it.
+    pub path: String,
+}
+
+/// Issues and decodes references, see [`crate::request::BotGuard::references`].
+///
+/// ```
+/// use BotGuardLib::policy::{Action, PolicyEngine};
+/// use BotGuardLib::reference::ReferenceSigner;
+/// use BotGuardLib::request::{BotGuard, RequestSnapshot};
+/// use BotGuardLib::BotDetector;
+///
+/// let signer = ReferenceSigner::new(b"a long random shared secret");
+/// let guard = BotGuard::new(BotDetector::new("[scanners]\n^sqlmap/"))
+///     .policy(PolicyEngine::new(Action::Block))
+///     .references(signer.clone());
+/// let verdict = guard.evaluate(&RequestSnapshot::new("GET", "/login").user_agent("sqlmap/1.7"));
+///
+/// // the user quotes the reference from the block page
+/// let details = signer.decode_reference(verdict.reference.as_deref().unwrap()).unwrap();
+/// assert_eq!((details.action, details.category.as_deref(), details.path.as_str()), (Action::Block, Some("scanners"), "/login"));
+/// assert_eq!(details.reasons, vec!["ua_pattern_match".to_string()]);
+/// assert_eq!(signer.decode_reference("not-a-reference"), None);
+/// ```
+#[derive(Clone)]
+pub struct ReferenceSigner {
+    cipher_key: [u8; 64],
+    mac_key: [u8; 64],
+}
+
+impl fmt::Debug for ReferenceSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReferenceSigner").finish_non_exhaustive()
+    }
+}
+
+impl ReferenceSigner {
+    /// Signs with `secret`, which every instance decoding the references needs as well.
+    pub fn new(secret: &[u8]) -> Self {
+        ReferenceSigner { cipher_key: crypto::hmac_sha512(secret, b"reference cipher"), mac_key: crypto::hmac_sha512(secret, b"reference mac") }
+    }
+
+    /// A reference for the verdict of `request`, the request as evaluated.
+    pub fn issue(&self, verdict: &RequestVerdict, request: &RequestSnapshot) -> String {
+        self.issue_at(verdict, request, SystemTime::now(), random_u64().to_be_bytes())
+    }
+
+    fn issue_at(&self, verdict: &RequestVerdict, request: &RequestSnapshot, now: SystemTime, nonce: [u8; NONCE_LEN]) -> String {
+        let mut path = request.path.as_str();
+        if path.len() > MAX_PATH_LEN {
+            let end = (0..=MAX_PATH_LEN).rev().find(|&i| path.is_char_boundary(i)).unwrap_or(0);
+            path = &path[..end];
+        }
+        let reasons = verdict.reasons.iter().map(|reason| reason.code()).collect::<Vec<_>>().join(",");
+        // the path goes last, it is the only field that may contain the separator
+        let details = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
+            now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            verdict_name(verdict.verdict),
+            verdict.score,
+            action_name(&verdict.action),
+            verdict.category.as_deref().unwrap_or(""),
+            reasons,
+            request.client_ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            path,
+        );
+        let mut sealed = nonce.to_vec();
+        sealed.extend(self.apply_keystream(&nonce, details.as_bytes()));
+        let mac = self.mac(&sealed);
+        sealed.extend_from_slice(&mac);
+        base64url(&sealed)
+    }
+
+    /// The decision of a reference issued with the same secret, `None` for anything else.
+    pub fn decode_reference(&self, reference: &str) -> Option<Reference> {
+        let sealed = from_base64url(reference.trim())?;
+        if sealed.len() < NONCE_LEN + MAC_LEN {
+            return None;
+        }
+        let (body, mac) = sealed.split_at(sealed.len() - MAC_LEN);
+        if !crypto::mac_eq(mac, &self.mac(body)) {
+            return None;
+        }
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+        let details = String::from_utf8(self.apply_keystream(nonce, ciphertext)).ok()?;
+        let mut fields = details.splitn(8, '\n');
+        let mut next = || fields.next();
+        Some(Reference {
+            issued: UNIX_EPOCH + Duration::from_secs(next()?.parse().ok()?),
+            verdict: parse_verdict(next()?)?,
+            score: next()?.parse().ok()?,
+            action: parse_action(next()?)?,
+            category: Some(next()?).filter(|category| !category.is_empty()).map(str::to_string),
+            reasons: next()?.split(',').filter(|code| !code.is_empty()).map(str::to_string).collect(),
+            client_ip: match next()? {
+                "" => None,
+                ip => Some(ip.parse().ok()?),
+            },
+            path: next()?.to_string(),
+        })
+    }
+
+    fn apply_keystream(&self, nonce: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        for (counter, chunk) in data.chunks(64).enumerate() {
+            let mut block = nonce.to_vec();
+            block.extend_from_slice(&(counter as u32).to_be_bytes());
+            let stream = crypto::hmac_sha512(&self.cipher_key, &block);
+            out.extend(chunk.iter().zip(stream).map(|(byte, key)| byte ^ key));
+        }
+        out
+    }
+
+    fn mac(&self, data: &[u8]) -> [u8; MAC_LEN] {
+        let mut mac = [0; MAC_LEN];
+        mac.copy_from_slice(&crypto::hmac_sha512(&self.mac_key, data)[..MAC_LEN]);
+        mac
+    }
+}
+
+fn verdict_name(verdict: Verdict) -> &'static str {
+    match verdict {
+        Verdict::Human => "human",
+        Verdict::Suspicious => "suspicious",
+        Verdict::Bot => "bot",
+    }
+}
+
+fn parse_verdict(name: &str) -> Option<Verdict> {
+    [Verdict::Human, Verdict::Suspicious, Verdict::Bot].into_iter().find(|&verdict| verdict_name(verdict) == name)
+}
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn base64url(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let word = chunk.iter().enumerate().fold(0u32, |word, (i, &byte)| word | u32::from(byte) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(char::from(BASE64URL[(word >> (18 - 6 * i) & 0x3f) as usize]));
+        }
+    }
+    out
+}
+
+fn from_base64url(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for b in text.bytes() {
+        let value = BASE64URL.iter().position(|&c| c == b)? as u32;
+        acc = (acc << 6 | value) & 0xffff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Reason;
+
+    fn verdict() -> RequestVerdict {
+        RequestVerdict {
+            verdict: Verdict::Suspicious,
+            score: 0.65,
+            category: None,
+            reasons: vec![Reason::Heuristic, Reason::EmptyUserAgent],
+            action: Action::RateLimit { requests: 10, per: Duration::from_secs(60) },
+            shadowed: None,
+            tags: Vec::new(),
+            log: true,
+            reference: None,
+        }
+    }
+
+    #[test]
+    fn decodes_what_it_issued() {
+        let signer = ReferenceSigner::new(b"secret");
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let long_path = format!("/search?q={}", "é".repeat(200));
+        let request = RequestSnapshot::new("GET", &long_path).client_ip("2001:db8::1".parse().unwrap());
+        let reference = signer.issue_at(&verdict(), &request, now, [7; NONCE_LEN]);
+        assert!(reference.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+
+        let decoded = signer.decode_reference(&reference).unwrap();
+        assert_eq!((decoded.issued, decoded.verdict, decoded.score), (now, Verdict::Suspicious, 0.65));
+        assert_eq!((decoded.action, decoded.category), (verdict().action, None));
+        assert_eq!(decoded.reasons, vec!["heuristic".to_string(), "empty_user_agent".to_string()]);
+        assert_eq!(decoded.client_ip, Some("2001:db8::1".parse().unwrap()));
+        assert!(long_path.starts_with(&decoded.path) && decoded.path.len() <= MAX_PATH_LEN && decoded.path.len() > 200);
+        // nothing readable without the secret
+        assert!(!String::from_utf8_lossy(&from_base64url(&reference).unwrap()).contains("heuristic"));
+    }
+
+    #[test]
+    fn rejects_foreign_and_altered_references() {
+        let signer = ReferenceSigner::new(b"secret");
+        let reference = signer.issue(&verdict(), &RequestSnapshot::new("GET", "/"));
+        assert_eq!(ReferenceSigner::new(b"other").decode_reference(&reference), None);
+        let mut altered = reference.into_bytes();
+        altered[12] = if altered[12] == b'A' { b'B' } else { b'A' };
+        assert_eq!(signer.decode_reference(&String::from_utf8(altered).unwrap()), None);
+        assert_eq!(signer.decode_reference(""), None);
+        assert_eq!(signer.decode_reference("a+b/"), None);
+        assert_eq!(from_base64url(&base64url(b"any bytes!")).unwrap(), b"any bytes!");
+    }
+}
@@ -0,0 +1,180 @@
+// Referrer spam: requests sent only to plant a domain in the referrer reports of analytics tools,
+// usually with a perfectly normal browser user-agent.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use regex::{RegexSet, RegexSetBuilder};
+
+use crate::BotGuardError;
+
+/// Domains known for referrer spam, subdomains included.
+const SPAM_DOMAINS: &[&str] = &[
+    "semalt.com",
+    "buttons-for-website.com",
+    "buttons-for-your-website.com",
+    "darodar.com",
+    "ilovevitaly.com",
+    "priceg.com",
+    "blackhatworth.com",
+    "hulfingtonpost.com",
+    "best-seo-offer.com",
+    "best-seo-solution.com",
+    "free-share-buttons.com",
+    "social-buttons.com",
+    "floating-share-buttons.com",
+    "get-free-traffic-now.com",
+    "trafficmonetize.org",
+    "4webmasters.org",
+    "7makemoneyonline.com",
+    "o-o-6-o-o.com",
+];
+
+/// Spam host names following the usual naming schemes, matched case-insensitively.
+const SPAM_PATTERNS: &[&str] = &[
+    r"semalt",
+    r"buttons-for-",
+    r"share-buttons",
+    r"(?:best|free|cheap)-?seo-",
+    r"free-.*traffic",
+    r"make-?money-?online",
+    r"^o-o-\d+-o-o\.",
+];
+
+/// Why a referrer was flagged, see [`ReferrerFilter::check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReferrerIssue {
+    /// The host is or belongs to a listed spam domain.
+    SpamDomain(String),
+    /// The host matches a spam pattern.
+    SpamPattern(String),
+    /// A `data:` URL, which browsers never send as the referrer.
+    DataUrl,
+    /// An `http`/`https` referrer without a valid host.
+    Malformed,
+}
+
+impl fmt::Display for ReferrerIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReferrerIssue::SpamDomain(domain) => write!(f, "referrer spam domain {}", domain),
+            ReferrerIssue::SpamPattern(pattern) => write!(f, "referrer host matches spam pattern {:?}", pattern),
+            ReferrerIssue::DataUrl => f.write_str("data: URL as referrer"),
+            ReferrerIssue::Malformed => f.write_str("malformed referrer"),
+        }
+    }
+}
+
+/// Spam domains and host patterns checked against referrers.
+///
+/// ```
+/// use BotGuardLib::referrer::{ReferrerFilter, ReferrerIssue};
+///
+/// let filter = ReferrerFilter::default().with_domains(&["spam.example"]);
+/// assert_eq!(filter.check("https://www.google.com/"), None);
+/// assert_eq!(filter.check("http://news.spam.example/page"), Some(ReferrerIssue::SpamDomain("spam.example".to_string())));
+/// assert_eq!(filter.check("data:text/html,hello"), Some(ReferrerIssue::DataUrl));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReferrerFilter {
+    domains: Vec<String>,
+    patterns: Vec<String>,
+    set: RegexSet,
+}
+
+impl Default for ReferrerFilter {
+    /// The bundled spam domains and patterns.
+    fn default() -> Self {
+        ReferrerFilter::new(SPAM_DOMAINS, SPAM_PATTERNS).expect("the bundled referrer patterns are valid")
+    }
+}
+
+impl ReferrerFilter {
+    /// A filter with only these domains and host patterns.
+    pub fn new(domains: &[&str], patterns: &[&str]) -> Result<Self, BotGuardError> {
+        let set = RegexSetBuilder::new(patterns)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| BotGuardError::InvalidPattern { pattern: patterns.join("|"), reason: e.to_string() })?;
+        Ok(ReferrerFilter {
+            domains: domains.iter().map(|domain| domain.trim_start_matches('.').to_ascii_lowercase()).collect(),
+            patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+            set,
+        })
+    }
+
+    /// Adds spam domains.
+    pub fn with_domains(mut self, domains: &[&str]) -> Self {
+        self.domains.extend(domains.iter().map(|domain| domain.trim_start_matches('.').to_ascii_lowercase()));
+        self
+    }
+
+    /// Returns why the referrer is spam or implausible, `None` if it looks fine. Referrers of
+    /// other schemes, like `android-app://`, are not checked.
+    pub fn check(&self, referrer: &str) -> Option<ReferrerIssue> {
+        let referrer = referrer.trim();
+        let scheme_end = referrer.find(':')?;
+        let scheme = referrer[..scheme_end].to_ascii_lowercase();
+        if scheme == "data" {
+            return Some(ReferrerIssue::DataUrl);
+        }
+        if scheme != "http" && scheme != "https" {
+            return None;
+        }
+        let Some(host) = host(&referrer[scheme_end + 1..]) else { return Some(ReferrerIssue::Malformed) };
+        if let Some(domain) = self.domains.iter().find(|domain| host == **domain || host.strip_suffix(domain.as_str()).is_some_and(|sub| sub.ends_with('.'))) {
+            return Some(ReferrerIssue::SpamDomain(domain.clone()));
+        }
+        self.set.matches(&host).iter().next().map(|i| ReferrerIssue::SpamPattern(self.patterns[i].clone()))
+    }
+}
+
+/// The lowercased host of the part of a URL following `scheme:`.
+fn host(rest: &str) -> Option<String> {
+    let authority = rest.strip_prefix("//")?.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = match host_port.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next()?,
+        None => host_port.split(':').next()?,
+    };
+    let valid = !host.is_empty() && host.bytes().all(|b| b.is_ascii_alphanumeric() || b"-.:".contains(&b));
+    valid.then(|| host.trim_end_matches('.').to_ascii_lowercase())
+}
+
+/// The filter used by [`crate::BotDetector::check_referrer`].
+pub(crate) fn bundled() -> &'static ReferrerFilter {
+    static BUNDLED: OnceLock<ReferrerFilter> = OnceLock::new();
+    BUNDLED.get_or_init(ReferrerFilter::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_spam_referrers() {
+        let filter = ReferrerFilter::default();
+        assert_eq!(filter.check("http://semalt.com/crawler.php?u=x"), Some(ReferrerIssue::SpamDomain("semalt.com".to_string())));
+        assert_eq!(filter.check("https://WWW.Darodar.com."), Some(ReferrerIssue::SpamDomain("darodar.com".to_string())));
+        assert_eq!(filter.check("http://semalt.semalt.net/"), Some(ReferrerIssue::SpamPattern("semalt".to_string())));
+        assert_eq!(filter.check("http://cheap-seo-deals.example/"), Some(ReferrerIssue::SpamPattern(r"(?:best|free|cheap)-?seo-".to_string())));
+        assert_eq!(filter.check("DATA:text/html;base64,PGgxPg=="), Some(ReferrerIssue::DataUrl));
+        assert_eq!(filter.check("http:///path"), Some(ReferrerIssue::Malformed));
+        assert_eq!(filter.check("https://bad host/"), Some(ReferrerIssue::Malformed));
+    }
+
+    #[test]
+    fn passes_ordinary_referrers() {
+        let filter = ReferrerFilter::default();
+        for referrer in [
+            "https://www.google.com/",
+            "https://user:pw@example.org:8443/a?b#c",
+            "http://[2001:db8::1]:8080/",
+            "android-app://com.google.android.gm/",
+            "notsemalt.com",
+            "https://news.ycombinator.com/item?id=1",
+        ] {
+            assert_eq!(filter.check(referrer), None, "{}", referrer);
+        }
+    }
+}
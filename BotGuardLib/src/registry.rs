@@ -0,0 +1,259 @@
+// Detectors of many tenants sharing one set of base patterns, each with its own allow and block
+// lists on top.
+//
+// Tenants without block patterns use the base detector itself. A tenant with block patterns gets
+// a clone of the base, which copies the compiled groups, plus one group of its own, so only that
+// group is compiled per tenant. Replacing the base rebuilds the overlays before anything is swapped.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::config::Allowlist;
+use crate::{BotDetector, BotGuardError, Verdict};
+
+/// Group the block patterns of a tenant are compiled into.
+pub const TENANT_GROUP: &str = "tenant";
+
+/// The allow and block lists a tenant adds to the base patterns.
+#[derive(Debug, Clone, Default)]
+pub struct TenantOverlay {
+    allowlist: Allowlist,
+    block: Vec<String>,
+}
+
+impl TenantOverlay {
+    /// Clients the tenant never wants treated as bots, even if a base pattern matches.
+    pub fn allow(mut self, allowlist: Allowlist) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    /// Extra bot patterns of the tenant, matched after the base groups in the [`TENANT_GROUP`].
+    pub fn block(mut self, patterns: &[&str]) -> Self {
+        self.block.extend(patterns.iter().map(|pattern| pattern.to_string()));
+        self
+    }
+
+    pub fn allowlist(&self) -> &Allowlist {
+        &self.allowlist
+    }
+
+    pub fn blocked_patterns(&self) -> &[String] {
+        &self.block
+    }
+
+    /// The base detector itself, or a copy of it with the block patterns appended.
+    fn detector(&self, base: &Arc<BotDetector>) -> Result<Arc<BotDetector>, BotGuardError> {
+        if self.block.is_empty() {
+            return Ok(Arc::clone(base));
+        }
+        let mut detector = BotDetector::clone(base);
+        detector.append_to_group(TENANT_GROUP, &self.block.iter().map(String::as_str).collect::<Vec<_>>())?;
+        Ok(Arc::new(detector))
+    }
+}
+
+/// Checks of one tenant, see [`DetectorRegistry::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantStats {
+    pub checks: u64,
+    pub bots: u64,
+    pub suspicious: u64,
+    /// Checks the tenant allowlist let through.
+    pub allowed: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    checks: AtomicU64,
+    bots: AtomicU64,
+    suspicious: AtomicU64,
+    allowed: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Tenant {
+    overlay: TenantOverlay,
+    detector: Arc<BotDetector>,
+    counters: Arc<Counters>,
+}
+
+/// Detectors keyed by tenant ID, all built from shared base patterns.
+///
+/// Checks of unknown tenants use the base detector and are not counted.
+///
+/// ```
+/// use BotGuardLib::config::Allowlist;
+/// use BotGuardLib::registry::{DetectorRegistry, TenantOverlay};
+/// use BotGuardLib::{BotDetector, Verdict};
+///
+/// let registry = DetectorRegistry::new(BotDetector::new("[crawlers]\ngooglebot\n[tools]\n^curl/"));
+/// let monitoring = Allowlist::new(&["^curl/.*acme-monitor"], &[]).unwrap();
+/// registry.set_tenant("acme", TenantOverlay::default().allow(monitoring).block(&["^scrapy/"])).unwrap();
+///
+/// assert_eq!(registry.check("acme", "Scrapy/2.11"), Verdict::Bot);
+/// assert_eq!(registry.check("acme", "curl/8.4 acme-monitor"), Verdict::Human);
+/// assert_eq!(registry.check("globex", "Scrapy/2.11"), Verdict::Human);
+/// assert_eq!(registry.check("globex", "curl/8.4 acme-monitor"), Verdict::Bot);
+///
+/// let stats = registry.stats("acme").unwrap();
+/// assert_eq!((stats.checks, stats.bots, stats.allowed), (2, 1, 1));
+/// assert!(registry.stats("globex").is_none());
+/// ```
+#[derive(Debug)]
+pub struct DetectorRegistry {
+    base: RwLock<Arc<BotDetector>>,
+    tenants: RwLock<HashMap<String, Tenant>>,
+}
+
+impl DetectorRegistry {
+    pub fn new(base: BotDetector) -> Self {
+        DetectorRegistry { base: RwLock::new(Arc::new(base)), tenants: RwLock::default() }
+    }
+
+    /// Adds a tenant or replaces its overlay, keeping its statistics. Fails if a block pattern is
+    /// invalid, the previous overlay stays in place then.
+    pub fn set_tenant(&self, tenant: &str, overlay: TenantOverlay) -> Result<(), BotGuardError> {
+        // locked before reading the base, so a concurrent `set_base` cannot be missed
+        let mut tenants = self.tenants_mut();
+        let detector = overlay.detector(&self.base())?;
+        let counters = tenants.get(tenant).map(|existing| Arc::clone(&existing.counters)).unwrap_or_default();
+        tenants.insert(tenant.to_string(), Tenant { overlay, detector, counters });
+        Ok(())
+    }
+
+    /// Returns `false` if there is no such tenant.
+    pub fn remove_tenant(&self, tenant: &str) -> bool {
+        self.tenants_mut().remove(tenant).is_some()
+    }
+
+    /// Replaces the base patterns of every tenant. Fails if a block pattern of a tenant does not
+    /// compile together with the new base, no tenant is changed then.
+    pub fn set_base(&self, base: BotDetector) -> Result<(), BotGuardError> {
+        let base = Arc::new(base);
+        let mut tenants = self.tenants_mut();
+        let detectors = tenants
+            .iter()
+            .map(|(name, tenant)| Ok((name.clone(), tenant.overlay.detector(&base)?)))
+            .collect::<Result<Vec<_>, BotGuardError>>()?;
+        for (name, detector) in detectors {
+            if let Some(tenant) = tenants.get_mut(&name) {
+                tenant.detector = detector;
+            }
+        }
+        *self.base.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = base;
+        Ok(())
+    }
+
+    /// The base detector shared by all tenants.
+    pub fn base(&self) -> Arc<BotDetector> {
+        Arc::clone(&self.base.read().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+
+    /// The detector of the tenant with its block patterns, the base detector for unknown tenants.
+    /// The tenant allowlist is only applied by [`DetectorRegistry::check`].
+    pub fn detector(&self, tenant: &str) -> Arc<BotDetector> {
+        match self.tenants().get(tenant) {
+            Some(tenant) => Arc::clone(&tenant.detector),
+            None => self.base(),
+        }
+    }
+
+    /// The verdict for a user-agent under the tenant's lists, `Human` if its allowlist matches.
+    pub fn check(&self, tenant: &str, user_agent: &str) -> Verdict {
+        self.check_with(tenant, user_agent, None)
+    }
+
+    /// Same as [`DetectorRegistry::check`], the address is also compared with the networks of the
+    /// tenant allowlist and passed on to the detection hooks.
+    pub fn check_from(&self, tenant: &str, user_agent: &str, ip: IpAddr) -> Verdict {
+        self.check_with(tenant, user_agent, Some(ip))
+    }
+
+    fn check_with(&self, tenant: &str, user_agent: &str, ip: Option<IpAddr>) -> Verdict {
+        let found = self.tenants().get(tenant).map(|tenant| {
+            let allowlist = &tenant.overlay.allowlist;
+            let allowed = allowlist.allows_user_agent(user_agent) || ip.is_some_and(|ip| allowlist.allows_ip(ip));
+            (Arc::clone(&tenant.detector), Arc::clone(&tenant.counters), allowed)
+        });
+        let Some((detector, counters, allowed)) = found else {
+            return self.base().detect(user_agent, ip);
+        };
+        counters.checks.fetch_add(1, Ordering::Relaxed);
+        if allowed {
+            counters.allowed.fetch_add(1, Ordering::Relaxed);
+            return Verdict::Human;
+        }
+        let verdict = detector.detect(user_agent, ip);
+        match verdict {
+            Verdict::Bot => counters.bots.fetch_add(1, Ordering::Relaxed),
+            Verdict::Suspicious => counters.suspicious.fetch_add(1, Ordering::Relaxed),
+            Verdict::Human => 0,
+        };
+        verdict
+    }
+
+    /// The checks of a tenant since it was added, `None` for unknown tenants.
+    pub fn stats(&self, tenant: &str) -> Option<TenantStats> {
+        let tenants = self.tenants();
+        let counters = &tenants.get(tenant)?.counters;
+        Some(TenantStats {
+            checks: counters.checks.load(Ordering::Relaxed),
+            bots: counters.bots.load(Ordering::Relaxed),
+            suspicious: counters.suspicious.load(Ordering::Relaxed),
+            allowed: counters.allowed.load(Ordering::Relaxed),
+        })
+    }
+
+    /// The IDs of all tenants, sorted.
+    pub fn tenant_ids(&self) -> Vec<String> {
+        let mut ids = self.tenants().keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    fn tenants(&self) -> RwLockReadGuard<'_, HashMap<String, Tenant>> {
+        self.tenants.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn tenants_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, Tenant>> {
+        self.tenants.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tenants_share_the_base_until_they_block() {
+        let registry = DetectorRegistry::new(BotDetector::new("googlebot"));
+        registry.set_tenant("allow-only", TenantOverlay::default().allow(Allowlist::new(&["googlebot"], &[]).unwrap())).unwrap();
+        registry.set_tenant("blocking", TenantOverlay::default().block(&["^scrapy/"])).unwrap();
+        assert!(Arc::ptr_eq(&registry.detector("allow-only"), &registry.base()));
+        assert!(!Arc::ptr_eq(&registry.detector("blocking"), &registry.base()));
+        assert_eq!(registry.detector("blocking").group_patterns(TENANT_GROUP).unwrap().collect::<Vec<_>>(), vec!["^scrapy/"]);
+        assert_eq!(registry.check("allow-only", "Googlebot/2.1"), Verdict::Human);
+        assert_eq!(registry.check("blocking", "Googlebot/2.1"), Verdict::Bot);
+
+        let invalid = registry.set_tenant("blocking", TenantOverlay::default().block(&["(unclosed"]));
+        assert!(matches!(invalid, Err(BotGuardError::InvalidPattern { .. })));
+        assert_eq!(registry.check("blocking", "scrapy/2.11"), Verdict::Bot);
+        assert_eq!(registry.stats("blocking").map(|stats| (stats.checks, stats.bots)), Some((2, 2)));
+        assert_eq!(registry.tenant_ids(), vec!["allow-only", "blocking"]);
+        assert!(registry.remove_tenant("allow-only") && !registry.remove_tenant("allow-only"));
+    }
+
+    #[test]
+    fn replacing_the_base_keeps_overlays() {
+        let registry = DetectorRegistry::new(BotDetector::new("googlebot"));
+        registry.set_tenant("acme", TenantOverlay::default().block(&["^scrapy/"])).unwrap();
+        registry.set_base(BotDetector::new("bingbot")).unwrap();
+        assert_eq!(registry.check("acme", "Googlebot/2.1"), Verdict::Human);
+        assert_eq!(registry.check("acme", "bingbot/2.0"), Verdict::Bot);
+        assert_eq!(registry.check("acme", "Scrapy/2.11"), Verdict::Bot);
+        assert_eq!(registry.check("other", "bingbot/2.0"), Verdict::Bot);
+    }
+}
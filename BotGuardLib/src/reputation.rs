@@ -0,0 +1,327 @@
+// IP reputation from external blocklists: firehol netsets, Spamhaus DROP style lists and CSV
+// exports of report databases like AbuseIPDB, refreshed when their interval has passed.
+//
+// Blocklists churn, an address reported yesterday may be missing from today's download even if
+// it is still abusive. Every entry is kept for its own time-to-live after it was last seen in a
+// download, so a short gap in a feed does not unblock anybody, and addresses leave the cache on
+// their own once no feed lists them any more.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::http;
+use crate::ip::{IpNet, IpRangeSet};
+use crate::BotGuardError;
+
+/// How the body of a feed is laid out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FeedFormat {
+    /// One address or network per line, `#` and `;` start comments, as in firehol netsets.
+    #[default]
+    Plain,
+    /// Comma separated values with the address in the first column, or the `ipAddress` column of
+    /// a header row, and an optional `abuseConfidenceScore` or `score` column between `0` and `100`.
+    Csv,
+}
+
+/// A blocklist, see [`ReputationCache::add_feed`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReputationFeed {
+    pub name: String,
+    /// `http://` or `https://` URL of the feed, `None` for feeds only filled with [`ReputationCache::load`].
+    pub url: Option<String>,
+    pub format: FeedFormat,
+    /// Age of the last download after which [`ReputationCache::refresh_expired`] downloads again.
+    pub refresh: Duration,
+    /// How long an entry stays in the cache after it was last seen in the feed.
+    pub entry_ttl: Duration,
+    /// Score of entries without one of their own, between `0.0` and `1.0`.
+    pub score: f32,
+}
+
+impl ReputationFeed {
+    /// A feed refreshed every hour whose entries are kept for a day, scoring `0.6`.
+    pub fn new(name: &str, url: Option<&str>, format: FeedFormat) -> Self {
+        ReputationFeed {
+            name: name.to_string(),
+            url: url.map(str::to_string),
+            format,
+            refresh: Duration::from_secs(3600),
+            entry_ttl: Duration::from_secs(24 * 3600),
+            score: 0.6,
+        }
+    }
+
+    pub fn refresh(mut self, refresh: Duration) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    pub fn entry_ttl(mut self, ttl: Duration) -> Self {
+        self.entry_ttl = ttl;
+        self
+    }
+
+    pub fn score(mut self, score: f32) -> Self {
+        self.score = score.clamp(0.0, 1.0);
+        self
+    }
+}
+
+/// What a feed says about an address, see [`ReputationCache::reputation`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReputationEntry {
+    /// Name of the feed listing the address.
+    pub feed: String,
+    /// The listed network containing the address.
+    pub network: IpNet,
+    /// Between `0.0` and `1.0`, from the feed's confidence column or its default score.
+    pub score: f32,
+    /// When the entry leaves the cache unless the feed lists it again.
+    pub expires_at: Instant,
+}
+
+#[derive(Debug)]
+struct LoadedFeed {
+    feed: ReputationFeed,
+    entries: HashMap<IpNet, (f32, Instant)>,
+    networks: IpRangeSet<(f32, Instant)>,
+    loaded_at: Option<Instant>,
+}
+
+impl LoadedFeed {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.loaded_at.is_none_or(|loaded_at| now.saturating_duration_since(loaded_at) >= self.feed.refresh)
+    }
+
+    /// Drops the entries expired at `now` and rebuilds the lookup set, returns how many were dropped.
+    fn rebuild(&mut self, now: Instant) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, (_, expires_at)| *expires_at > now);
+        self.networks = self.entries.iter().map(|(net, entry)| (*net, *entry)).collect();
+        before - self.entries.len()
+    }
+}
+
+/// Blocklist entries shared between threads, looked up while feeds are refreshed.
+///
+/// ```
+/// use std::time::Duration;
+/// use BotGuardLib::reputation::{FeedFormat, ReputationCache, ReputationFeed};
+///
+/// let cache = ReputationCache::new();
+/// cache.add_feed(ReputationFeed::new("abuse", None, FeedFormat::Csv).entry_ttl(Duration::from_secs(3600)));
+/// cache.load("abuse", "ipAddress,countryCode,abuseConfidenceScore\n203.0.113.7,NL,100\n198.51.100.4,US,40\n").unwrap();
+///
+/// assert_eq!(cache.reputation("203.0.113.7".parse().unwrap()).unwrap().score, 1.0);
+/// assert_eq!(cache.reputation("198.51.100.4".parse().unwrap()).unwrap().feed, "abuse");
+/// assert_eq!(cache.reputation("192.0.2.1".parse().unwrap()), None);
+/// ```
+#[derive(Debug)]
+pub struct ReputationCache {
+    feeds: RwLock<Vec<LoadedFeed>>,
+    timeout: Duration,
+}
+
+impl Default for ReputationCache {
+    fn default() -> Self {
+        ReputationCache { feeds: RwLock::new(Vec::new()), timeout: Duration::from_secs(30) }
+    }
+}
+
+impl ReputationCache {
+    pub fn new() -> Self {
+        ReputationCache::default()
+    }
+
+    /// Sets the download timeout of [`ReputationCache::refresh_expired`], 30 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Registers a feed, replacing the feed with the same name and its entries.
+    pub fn add_feed(&self, feed: ReputationFeed) {
+        let mut feeds = self.feeds.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        feeds.retain(|loaded| loaded.feed.name != feed.name);
+        feeds.push(LoadedFeed { feed, entries: HashMap::new(), networks: IpRangeSet::new(), loaded_at: None });
+    }
+
+    /// Ingests the body of a feed. Listed entries get a fresh time-to-live, entries missing from
+    /// the body are kept until theirs has passed. Returns the number of entries in the body.
+    pub fn load(&self, name: &str, body: &str) -> Result<usize, BotGuardError> {
+        self.load_at(name, body, Instant::now())
+    }
+
+    fn load_at(&self, name: &str, body: &str, now: Instant) -> Result<usize, BotGuardError> {
+        let mut feeds = self.feeds.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let loaded = feeds
+            .iter_mut()
+            .find(|loaded| loaded.feed.name == name)
+            .ok_or_else(|| BotGuardError::InvalidFeed { feed: name.to_string(), reason: "no feed with this name".to_string() })?;
+        let parsed = match loaded.feed.format {
+            FeedFormat::Plain => parse_plain(body),
+            FeedFormat::Csv => parse_csv(body),
+        }
+        .map_err(|reason| BotGuardError::InvalidFeed { feed: name.to_string(), reason })?;
+        let count = parsed.len();
+        let expires_at = now + loaded.feed.entry_ttl;
+        for (net, score) in parsed {
+            loaded.entries.insert(net, (score.unwrap_or(loaded.feed.score), expires_at));
+        }
+        loaded.rebuild(now);
+        loaded.loaded_at = Some(now);
+        Ok(count)
+    }
+
+    /// Downloads every feed with a URL whose refresh interval has passed, meant to be called
+    /// from a timer. Returns the name and outcome of each attempted refresh. Downloads happen
+    /// without holding the lock, so lookups are never blocked by a slow feed.
+    pub fn refresh_expired(&self) -> Vec<(String, Result<usize, BotGuardError>)> {
+        let now = Instant::now();
+        let due = {
+            let feeds = self.feeds.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+            feeds
+                .iter()
+                .filter(|loaded| loaded.is_expired(now))
+                .filter_map(|loaded| Some((loaded.feed.name.clone(), loaded.feed.url.clone()?)))
+                .collect::<Vec<_>>()
+        };
+        due.into_iter()
+            .map(|(name, url)| {
+                let result = http::get(&url, self.timeout)
+                    .map_err(|e| BotGuardError::FetchFailed { url: url.clone(), reason: e.to_string() })
+                    .and_then(|body| self.load(&name, &String::from_utf8_lossy(&body)));
+                (name, result)
+            })
+            .collect()
+    }
+
+    /// The entry with the highest score among the feeds listing the address, expired entries
+    /// excluded.
+    pub fn reputation(&self, ip: IpAddr) -> Option<ReputationEntry> {
+        let now = Instant::now();
+        let feeds = self.feeds.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        feeds
+            .iter()
+            .filter_map(|loaded| {
+                let (net, (score, expires_at)) = loaded.networks.get_net(ip)?;
+                (*expires_at > now).then(|| ReputationEntry { feed: loaded.feed.name.clone(), network: *net, score: *score, expires_at: *expires_at })
+            })
+            .max_by(|a, b| a.score.total_cmp(&b.score))
+    }
+
+    /// Drops the expired entries of every feed, returns how many were dropped.
+    pub fn prune_expired(&self) -> usize {
+        let now = Instant::now();
+        let mut feeds = self.feeds.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        feeds.iter_mut().map(|loaded| loaded.rebuild(now)).sum()
+    }
+
+    /// Names of the feeds that were never loaded or whose refresh interval has passed.
+    pub fn expired_feeds(&self) -> Vec<String> {
+        let now = Instant::now();
+        let feeds = self.feeds.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        feeds.iter().filter(|loaded| loaded.is_expired(now)).map(|loaded| loaded.feed.name.clone()).collect()
+    }
+
+    /// Number of cached entries over all feeds, expired ones included until they are pruned.
+    pub fn len(&self) -> usize {
+        let feeds = self.feeds.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        feeds.iter().map(|loaded| loaded.entries.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn parse_plain(body: &str) -> Result<Vec<(IpNet, Option<f32>)>, String> {
+    body.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let entry = line.split(['#', ';']).next().unwrap_or_default().split_whitespace().next()?;
+            Some(entry.parse::<IpNet>().map(|net| (net, None)).map_err(|e| format!("line {}: {}", i + 1, e)))
+        })
+        .collect()
+}
+
+fn parse_csv(body: &str) -> Result<Vec<(IpNet, Option<f32>)>, String> {
+    let mut columns = (0, None);
+    let mut entries = Vec::new();
+    for (i, line) in body.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.split(',').map(|field| field.trim().trim_matches('"')).collect::<Vec<_>>();
+        if entries.is_empty() && fields[0].parse::<IpNet>().is_err() {
+            let find = |names: &[&str]| fields.iter().position(|field| names.iter().any(|name| field.eq_ignore_ascii_case(name)));
+            let Some(address) = find(&["ipAddress", "ip", "address"]) else {
+                return Err(format!("line {}: no address column in header", i + 1));
+            };
+            columns = (address, find(&["abuseConfidenceScore", "confidence", "score"]));
+            continue;
+        }
+        let (address, score) = columns;
+        let net = fields.get(address).unwrap_or(&"").parse::<IpNet>().map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let score = match score.and_then(|column| fields.get(column)).filter(|field| !field.is_empty()) {
+            Some(field) => Some(field.parse::<f32>().map_err(|_| format!("line {}: invalid score {:?}", i + 1, field))?.clamp(0.0, 100.0) / 100.0),
+            None => None,
+        };
+        entries.push((net, score));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_feed_formats() {
+        let netset = "# firehol_level1\n1.10.16.0/20\n203.0.113.9 ; SBL000001\n\n2001:db8::/32 # documentation\n";
+        assert_eq!(parse_plain(netset).unwrap().len(), 3);
+        assert!(parse_plain("1.2.3.4\nnot-an-ip\n").unwrap_err().starts_with("line 2"));
+
+        let csv = "ipAddress,countryCode,abuseConfidenceScore,lastReportedAt\n\"192.0.2.1\",US,75,2024-01-01\n2001:db8::1,,,\n";
+        let parsed = parse_csv(csv).unwrap();
+        assert_eq!(parsed[0], ("192.0.2.1".parse().unwrap(), Some(0.75)));
+        assert_eq!(parsed[1].1, None);
+        assert_eq!(parse_csv("192.0.2.1,42\n192.0.2.2\n").unwrap()[1].1, None);
+        assert!(parse_csv("country,score\nUS,1\n").is_err());
+        assert!(parse_csv("ip,score\n192.0.2.1,high\n").is_err());
+    }
+
+    #[test]
+    fn entries_expire_on_their_own() {
+        let cache = ReputationCache::new();
+        cache.add_feed(ReputationFeed::new("firehol", None, FeedFormat::Plain).entry_ttl(Duration::from_secs(60)).score(0.8));
+        cache.add_feed(ReputationFeed::new("abuse", None, FeedFormat::Csv).entry_ttl(Duration::from_secs(600)));
+        assert_eq!(cache.expired_feeds(), ["firehol", "abuse"]);
+
+        let start = Instant::now();
+        cache.load_at("firehol", "198.51.100.0/24\n203.0.113.9\n", start).unwrap();
+        cache.load_at("abuse", "ip,score\n198.51.100.7,100\n", start).unwrap();
+        let entry = cache.reputation(ip("198.51.100.7")).unwrap();
+        assert_eq!((entry.feed.as_str(), entry.score), ("abuse", 1.0));
+        assert_eq!(cache.reputation(ip("198.51.100.8")).unwrap().network.to_string(), "198.51.100.0/24");
+
+        // dropped from the download, still cached until its time-to-live has passed
+        cache.load_at("firehol", "198.51.100.0/24\n", start + Duration::from_secs(30)).unwrap();
+        assert_eq!(cache.reputation(ip("203.0.113.9")).unwrap().score, 0.8);
+        cache.load_at("firehol", "198.51.100.0/24\n", start + Duration::from_secs(70)).unwrap();
+        assert_eq!(cache.reputation(ip("203.0.113.9")), None);
+        assert_eq!(cache.len(), 2);
+
+        assert!(cache.load("abuse", "ip\nnonsense").is_err());
+        assert!(cache.load("unknown", "192.0.2.1").is_err());
+        assert_eq!(cache.prune_expired(), 0);
+    }
+}
@@ -0,0 +1,1580 @@
+// Request-level evaluation: one call running every check that applies to an HTTP request, from
+// the allowlist over the pattern groups, browser consistency and the referrer to the route policy.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::anonymizer::{AnonymizerKind, AnonymizerLists};
+use crate::canary::{Canary, CanaryReport};
+use crate::config::{Allowlist, BotGuardConfig};
+use crate::cookie::{CookieCheck, CookieSignal};
+use crate::datacenter::{CloudProvider, DatacenterRanges};
+use crate::exempt::{Exemption, Exemptions};
+use crate::fingerprint::{FingerprintAnalyzer, FingerprintPayload, FingerprintSignal};
+use crate::headers::{HeaderFingerprints, HeaderOrderFingerprint};
+use crate::http2::{self, Http2Fingerprint, Http2Profiles, ProfileKind};
+use crate::json;
+use crate::locale::{LocaleIssue, LocaleRules};
+use crate::partners::PartnerTokens;
+use crate::pipeline::{Pipeline, Stage};
+use crate::policy::{Action, Condition, PolicyEngine, PolicyInput};
+use crate::proxy::ClientIpExtractor;
+use crate::reference::ReferenceSigner;
+use crate::referrer::{ReferrerFilter, ReferrerIssue};
+use crate::reputation::ReputationCache;
+use crate::review::action_name;
+use crate::sampling::{Sampling, Signal};
+use crate::source::BundleVersion;
+use crate::spoof::{self, Anomaly};
+use crate::structure::{self, StructuralIssue};
+use crate::state::{ClientMemory, ClientOverrides, RateLimiter};
+use crate::{BotDetector, BotGuardError, Verdict};
+
+/// Score of a client sending a forged seen-before cookie or never returning it, suspicious with
+/// the default thresholds.
+const COOKIE_SCORE: f32 = 0.6;
+
+/// Score of a browser user-agent with impossible details or without the headers every browser
+/// sends, suspicious with the default thresholds.
+const ANOMALY_SCORE: f32 = 0.6;
+
+/// Score of a request from a cloud provider's network, human with the default thresholds unless
+/// another signal adds up.
+const DATACENTER_SCORE: f32 = 0.4;
+
+/// Score of a client over its rate limit, suspicious with the default thresholds.
+const RATE_EXCEEDED_SCORE: f32 = 0.7;
+
+/// Score of headers sent in the order of a known automation client, suspicious with the default
+/// thresholds.
+const HEADER_ORDER_SCORE: f32 = 0.7;
+
+/// Score of an HTTP/2 fingerprint of an automation client, a bot with the default thresholds:
+/// unlike the user-agent, it takes another HTTP stack to change.
+const HTTP2_AUTOMATION_SCORE: f32 = 0.8;
+
+/// Score of an HTTP/2 fingerprint of another browser than the user-agent claims, suspicious
+/// with the default thresholds.
+const HTTP2_MISMATCH_SCORE: f32 = 0.7;
+
+/// Headers every browser sends, checked for user-agents claiming to be one.
+const BROWSER_HEADERS: &[&str] = &["accept", "accept-language"];
+
+/// Score of a request with a spam referrer, a bot with the default thresholds.
+const REFERRER_SPAM_SCORE: f32 = 0.9;
+
+/// What is known about a request, borrowed from the application's request type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestSnapshot {
+    pub method: String,
+    pub path: String,
+    /// Taken from the `User-Agent` header if empty.
+    pub user_agent: String,
+    /// Header names are compared case-insensitively.
+    pub headers: Vec<(String, String)>,
+    pub client_ip: Option<IpAddr>,
+    /// A TLS client fingerprint like JA3 or JA4, as computed by the TLS terminator.
+    pub tls_fingerprint: Option<String>,
+    /// An HTTP/2 fingerprint in the Akamai format, as computed by the HTTP/2 terminator, see
+    /// [`crate::http2`].
+    pub http2_fingerprint: Option<String>,
+    /// ISO 3166-1 alpha-2 country of the client's address, from a [`crate::geoip`] lookup or a
+    /// CDN header like `CF-IPCountry`.
+    pub country: Option<String>,
+    /// What the collector script reported about the client's browser, see [`crate::fingerprint`].
+    pub fingerprint: Option<FingerprintPayload>,
+}
+
+impl RequestSnapshot {
+    pub fn new(method: &str, path: &str) -> Self {
+        RequestSnapshot { method: method.to_string(), path: path.to_string(), ..RequestSnapshot::default() }
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn client_ip(mut self, ip: IpAddr) -> Self {
+        self.client_ip = Some(ip);
+        self
+    }
+
+    pub fn tls_fingerprint(mut self, fingerprint: &str) -> Self {
+        self.tls_fingerprint = Some(fingerprint.to_string());
+        self
+    }
+
+    pub fn http2_fingerprint(mut self, fingerprint: &str) -> Self {
+        self.http2_fingerprint = Some(fingerprint.to_string());
+        self
+    }
+
+    pub fn country(mut self, country: &str) -> Self {
+        self.country = Some(country.to_string());
+        self
+    }
+
+    pub fn fingerprint(mut self, payload: FingerprintPayload) -> Self {
+        self.fingerprint = Some(payload);
+        self
+    }
+
+    /// The first value of a header.
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    /// The address, or else `tls:<fingerprint>`, the key of the client in the
+    /// [`ClientOverrides`]. A TLS fingerprint is shared by every client of the same browser
+    /// build, so the client memory only keys by address.
+    pub fn client_key(&self) -> Option<String> {
+        self.client_ip.map(|ip| ip.to_string()).or_else(|| self.tls_fingerprint.as_ref().map(|fingerprint| format!("tls:{}", fingerprint)))
+    }
+
+    pub(crate) fn effective_user_agent(&self) -> &str {
+        if self.user_agent.is_empty() {
+            self.header_value("user-agent").unwrap_or("")
+        } else {
+            &self.user_agent
+        }
+    }
+}
+
+/// Why a request was judged the way it was.
+///
+/// [`Reason::code`] is a stable identifier for logs, filters and appeal workflows, the
+/// [`fmt::Display`] output is meant for humans and may change.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    /// The user-agent or address is allowlisted, no other check ran.
+    Allowlisted,
+    /// The user-agent is empty, judged by the [`crate::EmptyUaPolicy`].
+    EmptyUserAgent,
+    /// A pattern matched, see [`BotDetector::find_match`].
+    UaPatternMatch { group: String, pattern: String, weight: f32 },
+    /// No pattern matched, but the user-agent looks like a tool or a bare browser token.
+    Heuristic,
+    /// The user-agent claims to be a browser but is inconsistent, see [`crate::spoof`].
+    Anomaly(Anomaly),
+    /// The user-agent is structurally odd, see [`crate::structure`].
+    Malformed(StructuralIssue),
+    /// The user-agent claims to be a browser, but a header every browser sends is missing.
+    MissingHeader { name: String },
+    /// The `Accept-Language` header is implausible, see [`BotGuard::locale_rules`].
+    Locale(LocaleIssue),
+    /// The headers were sent in the order of an automation client, see [`crate::headers`].
+    AutomationHeaderOrder { client: String },
+    /// The HTTP/2 fingerprint is the one of an automation client, see [`crate::http2`].
+    Http2Automation { client: String },
+    /// The HTTP/2 fingerprint is the one of another browser than the user-agent claims.
+    Http2Mismatch { claimed: String, client: String },
+    /// The browser fingerprint shows a headless or emulated environment, see
+    /// [`BotGuard::fingerprint_analyzer`].
+    Fingerprint(FingerprintSignal),
+    /// The referrer is spam, see [`crate::referrer`].
+    SpamReferrer(ReferrerIssue),
+    /// The address belongs to a cloud provider, see [`crate::datacenter`].
+    DatacenterIp { provider: CloudProvider },
+    /// The address is on a blocklist, see [`BotGuard::ip_reputation`].
+    BadReputation { feed: String, score: f32 },
+    /// The address is a Tor exit node, an open proxy or a VPN endpoint, see
+    /// [`BotGuard::anonymizers`]. The request scores at least [`AnonymizerKind::weight`].
+    Anonymizer { kind: AnonymizerKind },
+    /// The route policy decided with a rule on the client's location, see [`Condition::Country`].
+    GeoPolicy { route: String, country: Option<String>, asn: Option<u32> },
+    /// The client was recently confirmed as a bot and is still remembered with `score`, no other
+    /// check ran, see [`BotGuard::client_memory`].
+    Remembered { score: f32 },
+    /// The client made more than `limit` requests in `per`, see [`BotGuard::rate_limit`].
+    RateExceeded { limit: u32, per: Duration },
+    /// The seen-before cookie was not issued by us, see [`crate::cookie`].
+    InvalidCookie,
+    /// The client made `requests` requests in a row without returning the seen-before cookie,
+    /// see [`BotGuard::cookie_signal`].
+    CookiesIgnored { requests: u32 },
+    /// No pattern matched, but the classifier scores the request as suspicious, see
+    /// [`BotGuard::classifier`].
+    #[cfg(feature = "classifier")]
+    Classified { score: f32 },
+    /// The score reached the short-circuit score of the stage, the later stages did not run, see
+    /// [`BotGuard::pipeline`].
+    ShortCircuited { stage: Stage },
+    /// The request carries a token of the partner and is allowed whatever its verdict, see
+    /// [`BotGuard::partner_tokens`].
+    Partner { partner: String },
+    /// The request was not analyzed, see [`BotGuard::exemptions`].
+    Exempt(Exemption),
+    /// Support set the verdict of the client, no check ran, see [`BotGuard::overrides`].
+    Overridden { verdict: Verdict },
+}
+
+impl Reason {
+    /// Stable snake case identifier of the kind of reason.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Reason::Allowlisted => "allowlisted",
+            Reason::EmptyUserAgent => "empty_user_agent",
+            Reason::UaPatternMatch { .. } => "ua_pattern_match",
+            Reason::Heuristic => "heuristic",
+            Reason::Anomaly(_) => "ua_anomaly",
+            Reason::Malformed(_) => "malformed_user_agent",
+            Reason::MissingHeader { .. } => "missing_header",
+            Reason::Locale(_) => "locale",
+            Reason::AutomationHeaderOrder { .. } => "automation_header_order",
+            Reason::Http2Automation { .. } => "http2_automation",
+            Reason::Http2Mismatch { .. } => "http2_mismatch",
+            Reason::Fingerprint(_) => "browser_fingerprint",
+            Reason::SpamReferrer(_) => "spam_referrer",
+            Reason::DatacenterIp { .. } => "datacenter_ip",
+            Reason::BadReputation { .. } => "ip_reputation",
+            Reason::Anonymizer { .. } => "anonymizer",
+            Reason::GeoPolicy { .. } => "geo_policy",
+            Reason::Remembered { .. } => "client_memory",
+            Reason::RateExceeded { .. } => "rate_exceeded",
+            Reason::InvalidCookie => "invalid_cookie",
+            Reason::CookiesIgnored { .. } => "cookies_ignored",
+            #[cfg(feature = "classifier")]
+            Reason::Classified { .. } => "classifier",
+            Reason::ShortCircuited { .. } => "short_circuited",
+            Reason::Exempt(_) => "exempt",
+            Reason::Partner { .. } => "partner",
+            Reason::Overridden { .. } => "overridden",
+        }
+    }
+
+    /// The reason as a JSON object with its `code`, the fields of the variant and a `detail` text.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"code\":");
+        json::push_str(&mut out, self.code());
+        match self {
+            Reason::UaPatternMatch { group, pattern, weight } => {
+                out.push_str(",\"group\":");
+                json::push_str(&mut out, group);
+                out.push_str(",\"pattern\":");
+                json::push_str(&mut out, pattern);
+                out.push_str(&format!(",\"weight\":{}", weight));
+            }
+            Reason::MissingHeader { name } => {
+                out.push_str(",\"name\":");
+                json::push_str(&mut out, name);
+            }
+            Reason::AutomationHeaderOrder { client } | Reason::Http2Automation { client } => {
+                out.push_str(",\"client\":");
+                json::push_str(&mut out, client);
+            }
+            Reason::Http2Mismatch { claimed, client } => {
+                out.push_str(",\"claimed\":");
+                json::push_str(&mut out, claimed);
+                out.push_str(",\"client\":");
+                json::push_str(&mut out, client);
+            }
+            Reason::DatacenterIp { provider } => {
+                out.push_str(",\"provider\":");
+                json::push_str(&mut out, provider.name());
+            }
+            Reason::BadReputation { feed, score } => {
+                out.push_str(",\"feed\":");
+                json::push_str(&mut out, feed);
+                out.push_str(&format!(",\"score\":{}", score));
+            }
+            Reason::Anonymizer { kind } => {
+                out.push_str(",\"kind\":");
+                json::push_str(&mut out, kind.name());
+            }
+            Reason::GeoPolicy { route, country, asn } => {
+                out.push_str(",\"route\":");
+                json::push_str(&mut out, route);
+                out.push_str(",\"country\":");
+                json::push_opt_str(&mut out, country.as_deref());
+                out.push_str(&format!(",\"asn\":{}", asn.map_or("null".to_string(), |asn| asn.to_string())));
+            }
+            Reason::RateExceeded { limit, per } => out.push_str(&format!(",\"limit\":{},\"per_ms\":{}", limit, per.as_millis())),
+            Reason::CookiesIgnored { requests } => out.push_str(&format!(",\"requests\":{}", requests)),
+            Reason::Remembered { score } => out.push_str(&format!(",\"score\":{}", score)),
+            #[cfg(feature = "classifier")]
+            Reason::Classified { score } => out.push_str(&format!(",\"score\":{}", score)),
+            Reason::Partner { partner } => {
+                out.push_str(",\"partner\":");
+                json::push_str(&mut out, partner);
+            }
+            Reason::ShortCircuited { stage } => {
+                out.push_str(",\"stage\":");
+                json::push_str(&mut out, stage.name());
+            }
+            Reason::Overridden { verdict } => {
+                out.push_str(",\"verdict\":");
+                json::push_str(&mut out, if verdict.is_bot() { "bot" } else { "human" });
+            }
+            _ => {}
+        }
+        out.push_str(",\"detail\":");
+        json::push_str(&mut out, &self.to_string());
+        out.push('}');
+        out
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Allowlisted => f.write_str("allowlisted"),
+            Reason::EmptyUserAgent => f.write_str("empty user-agent"),
+            Reason::UaPatternMatch { group, pattern, weight } => write!(f, "user-agent matches {:?} of group {} with weight {}", pattern, group, weight),
+            Reason::Heuristic => f.write_str("user-agent looks automated"),
+            Reason::Anomaly(anomaly) => anomaly.fmt(f),
+            Reason::Malformed(issue) => issue.fmt(f),
+            Reason::MissingHeader { name } => write!(f, "browser request without {} header", name),
+            Reason::Locale(issue) => issue.fmt(f),
+            Reason::AutomationHeaderOrder { client } => write!(f, "headers in the order {} sends them", client),
+            Reason::Http2Automation { client } => write!(f, "HTTP/2 fingerprint of {}", client),
+            Reason::Http2Mismatch { claimed, client } => write!(f, "{} user-agent with the HTTP/2 fingerprint of {}", claimed, client),
+            Reason::Fingerprint(signal) => signal.fmt(f),
+            Reason::SpamReferrer(issue) => issue.fmt(f),
+            Reason::DatacenterIp { provider } => write!(f, "address of {}", provider),
+            Reason::BadReputation { feed, score } => write!(f, "address listed by {} with score {}", feed, score),
+            Reason::Anonymizer { kind } => write!(f, "{} address", kind.name()),
+            Reason::GeoPolicy { route, country, asn } => {
+                write!(f, "location rule of {} for {}", route, country.as_deref().unwrap_or("unknown country"))?;
+                asn.map_or(Ok(()), |asn| write!(f, " AS{}", asn))
+            }
+            Reason::Remembered { score } => write!(f, "client remembered as a bot with score {}", score),
+            Reason::RateExceeded { limit, per } => write!(f, "more than {} requests in {:?}", limit, per),
+            Reason::InvalidCookie => f.write_str("forged seen-before cookie"),
+            Reason::CookiesIgnored { requests } => write!(f, "{} requests in a row without the seen-before cookie", requests),
+            #[cfg(feature = "classifier")]
+            Reason::Classified { score } => write!(f, "classifier scores the request {}", score),
+            Reason::ShortCircuited { stage } => write!(f, "confident after the {} stage, later stages skipped", stage),
+            Reason::Exempt(exemption) => write!(f, "exempt: {}", exemption),
+            Reason::Partner { partner } => write!(f, "token of partner {}", partner),
+            Reason::Overridden { verdict } => write!(f, "marked {} by hand", if verdict.is_bot() { "a bot" } else { "human" }),
+        }
+    }
+}
+
+/// Outcome of [`BotGuard::evaluate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestVerdict {
+    pub verdict: Verdict,
+    /// Between `0.0` (human) and `1.0` (bot).
+    pub score: f32,
+    /// Group of the matched pattern.
+    pub category: Option<String>,
+    /// Every signal that contributed, in the order the checks ran.
+    pub reasons: Vec<Reason>,
+    /// What the route policy recommends, never [`Action::Tag`].
+    pub action: Action,
+    /// The action not taken because the policy is in shadow mode, see [`PolicyEngine::shadow`].
+    pub shadowed: Option<Action>,
+    /// Tags added by the route policy.
+    pub tags: Vec<String>,
+    /// Whether the request should be logged, `false` for exempt requests unless
+    /// [`Exemptions::logged`].
+    pub log: bool,
+    /// Signed reference of a request not allowed, for the block page and the log, see
+    /// [`BotGuard::references`].
+    pub reference: Option<String>,
+}
+
+impl RequestVerdict {
+    /// ```
+    /// use BotGuardLib::request::{BotGuard, RequestSnapshot};
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let guard = BotGuard::new(BotDetector::new("[scanners]\n^sqlmap/"));
+    /// let verdict = guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("sqlmap/1.7"));
+    /// assert_eq!(
+    ///     verdict.to_json(),
+    ///     r#"{"verdict":"bot","score":1,"category":"scanners","action":"allow","shadowed":null,"tags":[],"reasons":[{"code":"ua_pattern_match","group":"scanners","pattern":"^sqlmap/","weight":1,"detail":"user-agent matches \"^sqlmap/\" of group scanners with weight 1"}]}"#
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        let verdict = match self.verdict {
+            Verdict::Human => "human",
+            Verdict::Suspicious => "suspicious",
+            Verdict::Bot => "bot",
+        };
+        let mut out = format!("{{\"verdict\":\"{}\",\"score\":{},\"category\":", verdict, self.score);
+        json::push_opt_str(&mut out, self.category.as_deref());
+        out.push_str(",\"action\":");
+        json::push_str(&mut out, &action_name(&self.action));
+        out.push_str(",\"shadowed\":");
+        json::push_opt_str(&mut out, self.shadowed.as_ref().map(action_name).as_deref());
+        if let Some(reference) = &self.reference {
+            out.push_str(",\"reference\":");
+            json::push_str(&mut out, reference);
+        }
+        out.push_str(",\"tags\":[");
+        for (i, tag) in self.tags.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            json::push_str(&mut out, tag);
+        }
+        out.push_str("],\"reasons\":[");
+        for (i, reason) in self.reasons.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&reason.to_json());
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+/// What the stages of [`BotGuard::evaluate`] found so far.
+struct Evaluation<'a> {
+    score: f32,
+    /// The detector's own verdict.
+    verdict: Verdict,
+    category: Option<String>,
+    reasons: Vec<Reason>,
+    /// Where to remember the client if it turns out a bot.
+    memory: Option<(&'a ClientMemory, String)>,
+    /// The rate limit the client is over, counted before the pipeline ran.
+    rate_exceeded: Option<(u32, Duration)>,
+    /// Whether the client presented a partner token, see [`crate::policy::PolicyInput::verified`].
+    verified: bool,
+}
+
+impl Evaluation<'_> {
+    /// Signals do not add up, the score is the one of the strongest.
+    fn add(&mut self, score: f32) {
+        self.score = self.score.max(score);
+    }
+}
+
+/// A detector together with the allowlist, referrer filter and route policy it is used with.
+///
+/// ```
+/// use BotGuardLib::policy::{Action, Condition, PolicyEngine, PolicyRule};
+/// use BotGuardLib::request::{BotGuard, Reason, RequestSnapshot};
+/// use BotGuardLib::{BotDetector, Verdict};
+///
+/// let policy = PolicyEngine::new(Action::Allow).rule(PolicyRule::new("/api/*", Action::Block).when(Condition::MinScore(0.8)));
+/// let guard = BotGuard::new(BotDetector::default()).policy(policy);
+///
+/// let request = RequestSnapshot::new("GET", "/api/orders")
+///     .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0.0.0 Safari/537.36")
+///     .header("Referer", "http://semalt.com/")
+///     .client_ip("203.0.113.7".parse().unwrap());
+/// let evaluated = guard.evaluate(&request);
+/// assert_eq!(evaluated.verdict, Verdict::Bot);
+/// assert_eq!(evaluated.action, Action::Block);
+/// assert!(matches!(evaluated.reasons[0], Reason::SpamReferrer(_)));
+/// ```
+#[derive(Debug)]
+pub struct BotGuard {
+    detector: BotDetector,
+    allowlist: Allowlist,
+    referrers: ReferrerFilter,
+    policy: PolicyEngine,
+    datacenters: Option<DatacenterRanges>,
+    reputation: Option<ReputationCache>,
+    anonymizers: Option<Arc<AnonymizerLists>>,
+    rate_limit: Option<(RateLimiter, u32, Duration)>,
+    memory: Option<ClientMemory>,
+    overrides: Option<ClientOverrides>,
+    cookies: Option<CookieSignal>,
+    header_orders: HeaderFingerprints,
+    http2_profiles: Http2Profiles,
+    locale: Option<LocaleRules>,
+    fingerprints: Option<FingerprintAnalyzer>,
+    sampling: Sampling,
+    pipeline: Pipeline,
+    exemptions: Exemptions,
+    partners: Option<PartnerTokens>,
+    proxies: Option<ClientIpExtractor>,
+    references: Option<ReferenceSigner>,
+    canary: Option<Canary>,
+    #[cfg(feature = "geoip")]
+    geoip: Option<crate::geoip::GeoIp>,
+    #[cfg(feature = "classifier")]
+    classifier: Option<crate::classifier::Classifier>,
+}
+
+impl BotGuard {
+    /// Uses the bundled referrer filter, datacenter ranges, header orders and HTTP/2 profiles, an
+    /// empty allowlist, a policy allowing everything, no rate limit and no cookie signal.
+    pub fn new(detector: BotDetector) -> Self {
+        BotGuard {
+            detector,
+            allowlist: Allowlist::default(),
+            referrers: ReferrerFilter::default(),
+            policy: PolicyEngine::default(),
+            datacenters: None,
+            reputation: None,
+            anonymizers: None,
+            rate_limit: None,
+            memory: None,
+            overrides: None,
+            cookies: None,
+            header_orders: HeaderFingerprints::bundled(),
+            http2_profiles: Http2Profiles::bundled(),
+            locale: None,
+            fingerprints: None,
+            sampling: Sampling::default(),
+            pipeline: Pipeline::default(),
+            exemptions: Exemptions::default(),
+            partners: None,
+            proxies: None,
+            references: None,
+            canary: None,
+            #[cfg(feature = "geoip")]
+            geoip: None,
+            #[cfg(feature = "classifier")]
+            classifier: None,
+        }
+    }
+
+    /// Builds the detector, allowlist and policy of a configuration.
+    pub fn from_config(config: &BotGuardConfig) -> Result<Self, BotGuardError> {
+        let mut guard = BotGuard::new(config.detector()?)
+            .allowlist(config.allowlist.clone())
+            .policy(config.policy.clone())
+            .sampling(config.sampling.clone())
+            .pipeline(config.pipeline.clone())
+            .exemptions(config.exemptions.clone());
+        if let Some(proxies) = &config.proxies {
+            guard = guard.trusted_proxies(proxies.clone());
+        }
+        if let Some(signer) = &config.references {
+            guard = guard.references(signer.clone());
+        }
+        Ok(guard)
+    }
+
+    pub fn allowlist(mut self, allowlist: Allowlist) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    pub fn referrer_filter(mut self, referrers: ReferrerFilter) -> Self {
+        self.referrers = referrers;
+        self
+    }
+
+    pub fn policy(mut self, policy: PolicyEngine) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Replaces the bundled datacenter ranges, without the `datacenter-ranges` feature no
+    /// addresses are checked unless ranges are set.
+    pub fn datacenter_ranges(mut self, ranges: DatacenterRanges) -> Self {
+        self.datacenters = Some(ranges);
+        self
+    }
+
+    /// Resolves client addresses to their country and autonomous system for the location
+    /// conditions of the route policy, a country set on the request takes precedence.
+    #[cfg(feature = "geoip")]
+    pub fn geoip(mut self, geoip: crate::geoip::GeoIp) -> Self {
+        self.geoip = Some(geoip);
+        self
+    }
+
+    /// Looks every client address up in the blocklists of the cache, a listed address gets
+    /// [`Reason::BadReputation`] and the entry's score.
+    pub fn ip_reputation(mut self, cache: ReputationCache) -> Self {
+        self.reputation = Some(cache);
+        self
+    }
+
+    /// Looks every client address up in the anonymizer feeds, a listed address gets
+    /// [`Reason::Anonymizer`]. The lists are shared, e.g. with
+    /// [`crate::maintenance::MaintenanceWorker::anonymizer_feeds`] refreshing them.
+    pub fn anonymizers(mut self, lists: Arc<AnonymizerLists>) -> Self {
+        self.anonymizers = Some(lists);
+        self
+    }
+
+    /// The blocklist cache, for refreshing its feeds while requests are evaluated.
+    pub fn reputation_cache(&self) -> Option<&ReputationCache> {
+        self.reputation.as_ref()
+    }
+
+    /// Counts the requests of every client address, a client making more than `requests` in
+    /// `per` gets [`Reason::RateExceeded`] from [`Stage::Behavior`]. Requests are counted before
+    /// the pipeline runs, also those decided by an earlier stage. Requests are let through if the
+    /// limiter's store fails.
+    pub fn rate_limit(mut self, limiter: RateLimiter, requests: u32, per: Duration) -> Self {
+        self.rate_limit = Some((limiter, requests, per));
+        self
+    }
+
+    /// Remembers the score of every client judged a bot, by address. While the decaying score
+    /// reaches the bot threshold, its requests are judged bots with [`Reason::Remembered`] without
+    /// running the other checks. Requests without an address are neither remembered nor recalled:
+    /// keyed by TLS fingerprint, one remembered bot would take down every user of its browser
+    /// build. Requests are evaluated in full if the memory's store fails.
+    pub fn client_memory(mut self, memory: ClientMemory) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Looks every client up in `overrides` before the pipeline runs. A client marked human gets
+    /// [`Reason::Overridden`] and is allowed, one marked a bot is judged a bot with score `1.0`
+    /// and left to the route policy. Requests are evaluated in full if the store fails.
+    pub fn overrides(mut self, overrides: ClientOverrides) -> Self {
+        self.overrides = Some(overrides);
+        self
+    }
+
+    /// Checks the seen-before cookie of every request, a forged cookie gets
+    /// [`Reason::InvalidCookie`] and an address that keeps coming back without one
+    /// [`Reason::CookiesIgnored`]. Responses should carry [`BotGuard::set_cookie`].
+    pub fn cookie_signal(mut self, signal: CookieSignal) -> Self {
+        self.cookies = Some(signal);
+        self
+    }
+
+    /// Replaces the bundled header orders of automation clients, a request sending its headers in
+    /// one of these orders gets [`Reason::AutomationHeaderOrder`].
+    pub fn header_fingerprints(mut self, fingerprints: HeaderFingerprints) -> Self {
+        self.header_orders = fingerprints;
+        self
+    }
+
+    /// The known header orders, for registering new ones while requests are evaluated.
+    pub fn header_orders(&self) -> &HeaderFingerprints {
+        &self.header_orders
+    }
+
+    /// Replaces the bundled HTTP/2 profiles the [`RequestSnapshot::http2_fingerprint`] is compared
+    /// with, see [`Reason::Http2Automation`] and [`Reason::Http2Mismatch`].
+    pub fn http2_profiles(mut self, profiles: Http2Profiles) -> Self {
+        self.http2_profiles = profiles;
+        self
+    }
+
+    /// The known HTTP/2 profiles, for registering new ones while requests are evaluated.
+    pub fn http2_fingerprints(&self) -> &Http2Profiles {
+        &self.http2_profiles
+    }
+
+    /// Checks the `Accept-Language` header of every request with headers, each issue adds
+    /// [`Reason::Locale`] and its weight to the score. A missing header is then reported by the
+    /// rules instead of as a [`Reason::MissingHeader`] of browsers.
+    pub fn locale_rules(mut self, rules: LocaleRules) -> Self {
+        self.locale = Some(rules);
+        self
+    }
+
+    /// Weighs the [`RequestSnapshot::fingerprint`] of requests carrying one, each signal adds
+    /// [`Reason::Fingerprint`] and the combined weight of the signals counts for the score.
+    pub fn fingerprint_analyzer(mut self, analyzer: FingerprintAnalyzer) -> Self {
+        self.fingerprints = Some(analyzer);
+        self
+    }
+
+    /// Scores requests no pattern matches with a trained model, a score reaching the suspicious
+    /// threshold adds [`Reason::Classified`].
+    #[cfg(feature = "classifier")]
+    pub fn classifier(mut self, classifier: crate::classifier::Classifier) -> Self {
+        self.classifier = Some(classifier);
+        self
+    }
+
+    /// Runs the classifier, fingerprint analyzer, reputation lookup and rate limit only on the
+    /// requests their gate lets through, see [`crate::sampling`].
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Runs the checks in the stages and order of `pipeline`, every stage in the order of
+    /// [`Stage::ALL`] unless set.
+    pub fn pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Allows requests matching `exemptions` before any stage of the pipeline runs, without
+    /// asking the route policy. The verdict has [`Reason::Exempt`] and is human.
+    pub fn exemptions(mut self, exemptions: Exemptions) -> Self {
+        self.exemptions = exemptions;
+        self
+    }
+
+    /// Never blocks requests with a token of a partner, their verdict has [`Reason::Partner`]
+    /// and the tag `partner:<name>`. They are not remembered in the client memory.
+    pub fn partner_tokens(mut self, tokens: PartnerTokens) -> Self {
+        self.partners = Some(tokens);
+        self
+    }
+
+    /// Takes [`RequestSnapshot::client_ip`] as the peer of the connection and replaces it with
+    /// the client address forwarded by trusted proxies before any check runs, so every address
+    /// based signal, the allowlist and the rate limit see the client.
+    pub fn trusted_proxies(mut self, extractor: ClientIpExtractor) -> Self {
+        self.proxies = Some(extractor);
+        self
+    }
+
+    /// Issues a signed [`RequestVerdict::reference`] for every request not allowed, which
+    /// [`ReferenceSigner::decode_reference`] turns back into the decision.
+    pub fn references(mut self, signer: ReferenceSigner) -> Self {
+        self.references = Some(signer);
+        self
+    }
+
+    /// Checks candidate patterns on a sample of the user-agents next to the detector's own, see
+    /// [`crate::canary`]. Their verdicts are only reported, see [`BotGuard::canary_report`].
+    pub fn canary(mut self, canary: Canary) -> Self {
+        self.canary = Some(canary);
+        self
+    }
+
+    /// The overrides, for marking clients while requests are evaluated.
+    pub fn client_overrides(&self) -> Option<&ClientOverrides> {
+        self.overrides.as_ref()
+    }
+
+    /// The partner tokens, for registering and rotating them while requests are evaluated.
+    pub fn partners(&self) -> Option<&PartnerTokens> {
+        self.partners.as_ref()
+    }
+
+    /// The `Set-Cookie` header value for the response to a request without a valid seen-before
+    /// cookie, `None` if it has one or no cookie signal is set.
+    pub fn set_cookie(&self, request: &RequestSnapshot) -> Option<String> {
+        let signal = self.cookies.as_ref()?;
+        match signal.evaluate(request.header_value("cookie").unwrap_or("")) {
+            CookieCheck::Returning(_) => None,
+            _ => Some(signal.set_cookie()),
+        }
+    }
+
+    pub fn detector(&self) -> &BotDetector {
+        &self.detector
+    }
+
+    pub fn policy_engine(&self) -> &PolicyEngine {
+        &self.policy
+    }
+
+    /// Replaces the route policy of a guard that is already in use, see [`crate::admin`].
+    pub fn set_policy(&mut self, policy: PolicyEngine) {
+        self.policy = policy;
+    }
+
+    pub fn set_allowlist(&mut self, allowlist: Allowlist) {
+        self.allowlist = allowlist;
+    }
+
+    pub fn set_sampling(&mut self, sampling: Sampling) {
+        self.sampling = sampling;
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: Pipeline) {
+        self.pipeline = pipeline;
+    }
+
+    pub fn set_exemptions(&mut self, exemptions: Exemptions) {
+        self.exemptions = exemptions;
+    }
+
+    pub fn detector_mut(&mut self) -> &mut BotDetector {
+        &mut self.detector
+    }
+
+    /// Starts checking new candidate patterns, `None` stops the canary. The report of the previous
+    /// candidate is lost.
+    pub fn set_canary(&mut self, canary: Option<Canary>) {
+        self.canary = canary;
+    }
+
+    pub fn canary_report(&self) -> Option<CanaryReport> {
+        self.canary.as_ref().map(Canary::report)
+    }
+
+    /// Replaces the detector's patterns with the canary's once it [`Canary::is_ready`]. Fails and
+    /// keeps the canary running without one or while it diverges too much or has too few samples.
+    pub fn promote_canary(&mut self) -> Result<BundleVersion, BotGuardError> {
+        self.canary.as_ref().ok_or_else(|| BotGuardError::InvalidConfig { line: None, reason: "no canary is running".to_string() })?.check_ready()?;
+        if let Some(canary) = self.canary.take() {
+            canary.promote(&mut self.detector)?;
+        }
+        Ok(self.detector.current_version().clone())
+    }
+
+    /// Runs the stages of the pipeline on the request, see [`crate::pipeline`]. The verdict is
+    /// the strictest of the detector's own verdict and the tier of the combined score, detection
+    /// hooks are called as for [`BotDetector::check_bot_from`].
+    pub fn evaluate(&self, request: &RequestSnapshot) -> RequestVerdict {
+        #[cfg(feature = "tracing")]
+        {
+            let mut buffer = [0; crate::INLINE_USER_AGENT];
+            let normalized_user_agent = self.detector.normalize_user_agent_in(request.effective_user_agent(), &mut buffer);
+            let _span = crate::trace::evaluate_span(&request.method, &normalized_user_agent).entered();
+            let evaluated = self.evaluate_untraced(request);
+            let pattern = evaluated.reasons.iter().find_map(|reason| match reason {
+                Reason::UaPatternMatch { pattern, .. } => Some(pattern.as_str()),
+                _ => None,
+            });
+            crate::trace::decision("evaluate", &normalized_user_agent, pattern, evaluated.category.as_deref(), evaluated.verdict.is_bot(), Some(evaluated.score));
+            evaluated
+        }
+        #[cfg(not(feature = "tracing"))]
+        self.evaluate_untraced(request)
+    }
+
+    fn evaluate_untraced(&self, request: &RequestSnapshot) -> RequestVerdict {
+        let request = &*self.resolve_client(request);
+        if let Some(exemption) = self.exemptions.exempts(request) {
+            return self.exempt(request, exemption);
+        }
+        // counted before any stage can decide, the Behavior stage only holds it against the request
+        let rate_exceeded = request.client_ip.and_then(|ip| self.rate_exceeded(ip));
+        let overridden = self.overrides.as_ref().and_then(|overrides| overrides.get(&request.client_key()?).ok().flatten());
+        let mut evaluated = match overridden {
+            Some(verdict) => self.overridden(request, verdict),
+            None => {
+                let partner = self.partners.as_ref().and_then(|partners| partners.verify_request(request));
+                let mut evaluated = self.analyze(request, partner.is_some(), rate_exceeded);
+                if let Some(partner) = partner {
+                    // analyzed and reported as usual, but never blocked
+                    evaluated.reasons.push(Reason::Partner { partner: partner.clone() });
+                    evaluated.tags.push(format!("partner:{}", partner));
+                    evaluated.action = Action::Allow;
+                    evaluated.shadowed = None;
+                }
+                evaluated
+            }
+        };
+        if let Some(signer) = self.references.as_ref().filter(|_| evaluated.action != Action::Allow) {
+            evaluated.reference = Some(signer.issue(&evaluated, request));
+        }
+
+        evaluated
+    }
+
+    /// Runs the pipeline, `verified` clients have a partner token and are kept out of the client
+    /// memory.
+    fn analyze(&self, request: &RequestSnapshot, verified: bool, rate_exceeded: Option<(u32, Duration)>) -> RequestVerdict {
+        let mut evaluation = Evaluation { score: 0.0, verdict: Verdict::Human, category: None, reasons: Vec::new(), memory: None, rate_exceeded, verified };
+        let mut stages = self.pipeline.stages().peekable();
+        while let Some((stage, short_circuit)) = stages.next() {
+            if let Some(decided) = self.run_stage(stage, request, &mut evaluation) {
+                return decided;
+            }
+            if stages.peek().is_some() && short_circuit.is_some_and(|score| evaluation.score >= score) {
+                evaluation.reasons.push(Reason::ShortCircuited { stage });
+                break;
+            }
+        }
+
+        let Evaluation { score, verdict, category, reasons, memory, verified, .. } = evaluation;
+        let tier = self.detector.options.thresholds.verdict(score);
+        let verdict = if tier.rank() > verdict.rank() { tier } else { verdict };
+        if let Some((memory, client)) = memory.filter(|_| !verified && verdict == Verdict::Bot) {
+            // a store failure only costs the fast path of the next request
+            let _ = memory.remember(&client, score);
+        }
+        self.decide(request, verdict, score, category, reasons, verified)
+    }
+
+    /// The request with the client address behind trusted proxies, IPv4-mapped IPv6 addresses of
+    /// dual-stack listeners as IPv4.
+    fn resolve_client<'a>(&self, request: &'a RequestSnapshot) -> Cow<'a, RequestSnapshot> {
+        let client = self.proxies.as_ref().and_then(|proxies| proxies.extract_request(request));
+        match client.or(request.client_ip).map(|client| client.to_canonical()) {
+            Some(client) if request.client_ip != Some(client) => Cow::Owned(RequestSnapshot { client_ip: Some(client), ..request.clone() }),
+            _ => Cow::Borrowed(request),
+        }
+    }
+
+    fn exempt(&self, request: &RequestSnapshot, exemption: Exemption) -> RequestVerdict {
+        let mut reasons = vec![Reason::Exempt(exemption)];
+        let mut action = Action::Allow;
+        if let Some((limit, per)) = request.client_ip.filter(|_| self.exemptions.is_rate_limited()).and_then(|ip| self.rate_exceeded(ip)) {
+            reasons.push(Reason::RateExceeded { limit, per });
+            action = Action::RateLimit { requests: limit, per };
+        }
+        RequestVerdict { verdict: Verdict::Human, score: 0.0, category: None, reasons, action, shadowed: None, tags: Vec::new(), log: self.exemptions.is_logged(), reference: None }
+    }
+
+    fn overridden(&self, request: &RequestSnapshot, verdict: Verdict) -> RequestVerdict {
+        let reasons = vec![Reason::Overridden { verdict }];
+        if verdict.is_bot() {
+            return self.decide(request, Verdict::Bot, 1.0, None, reasons, false);
+        }
+        RequestVerdict { verdict: Verdict::Human, score: 0.0, category: None, reasons, action: Action::Allow, shadowed: None, tags: Vec::new(), log: true, reference: None }
+    }
+
+    /// The limit the client is over, `None` within the limit, without a rate limit or if the
+    /// limiter's store fails.
+    fn rate_exceeded(&self, ip: IpAddr) -> Option<(u32, Duration)> {
+        let (limiter, limit, per) = self.rate_limit.as_ref()?;
+        limiter.check(&ip.to_string(), *limit, *per).is_ok_and(|decision| !decision.allowed).then_some((*limit, *per))
+    }
+
+    /// Runs the checks of one stage, returns the verdict if the stage decides on its own.
+    fn run_stage<'a>(&'a self, stage: Stage, request: &RequestSnapshot, evaluation: &mut Evaluation<'a>) -> Option<RequestVerdict> {
+        let user_agent = request.effective_user_agent();
+        match stage {
+            Stage::Allowlist => {
+                if self.allowlist.allows_user_agent(user_agent) || request.client_ip.is_some_and(|ip| self.allowlist.allows_ip(ip)) {
+                    let mut reasons = std::mem::take(&mut evaluation.reasons);
+                    reasons.push(Reason::Allowlisted);
+                    return Some(self.decide(request, Verdict::Human, 0.0, None, reasons, evaluation.verified));
+                }
+            }
+            Stage::Memory => {
+                evaluation.memory = self.memory.as_ref().zip(request.client_ip.map(|ip| ip.to_string()));
+                if let Some(score) = evaluation.memory.as_ref().and_then(|(memory, client)| memory.recall(client).ok().flatten()) {
+                    if score >= self.detector.options.thresholds.bot {
+                        let mut reasons = std::mem::take(&mut evaluation.reasons);
+                        reasons.push(Reason::Remembered { score });
+                        return Some(self.decide(request, Verdict::Bot, score, None, reasons, evaluation.verified));
+                    }
+                }
+            }
+            Stage::UserAgent => self.check_user_agent(request, evaluation),
+            Stage::Ip => self.check_ip(request, evaluation),
+            Stage::Headers => self.check_headers(request, evaluation),
+            Stage::Behavior => self.check_behavior(request, evaluation),
+        }
+        None
+    }
+
+    fn check_user_agent(&self, request: &RequestSnapshot, evaluation: &mut Evaluation<'_>) {
+        let user_agent = request.effective_user_agent();
+        let (verdict, detector_score, found) = self.detector.assess(user_agent, request.client_ip);
+        evaluation.verdict = verdict;
+        if let Some(canary) = &self.canary {
+            canary.compare_scored(|| detector_score, &self.detector, user_agent);
+        }
+        if user_agent.trim().is_empty() {
+            evaluation.reasons.push(Reason::EmptyUserAgent);
+        } else if let Some(found) = &found {
+            evaluation.reasons.push(Reason::UaPatternMatch { group: found.group.to_string(), pattern: found.pattern.to_string(), weight: found.weight });
+        }
+        if found.is_none() && !user_agent.trim().is_empty() && detector_score > 0.0 {
+            evaluation.reasons.push(Reason::Heuristic);
+        }
+        evaluation.add(detector_score);
+        #[cfg(feature = "classifier")]
+        if let Some(classifier) = self.classifier.as_ref().filter(|_| found.is_none() && !user_agent.trim().is_empty() && self.sampling.runs(Signal::Classifier, request, evaluation.score)) {
+            let classified = classifier.score_request(request);
+            if classified >= self.detector.options.thresholds.suspicious {
+                evaluation.reasons.push(Reason::Classified { score: classified });
+                evaluation.add(classified);
+            }
+        }
+        evaluation.category = found.map(|found| found.group.to_string());
+        for anomaly in spoof::anomalies(user_agent) {
+            evaluation.reasons.push(Reason::Anomaly(anomaly));
+            evaluation.add(ANOMALY_SCORE);
+        }
+        let issues = structure::structural_issues(user_agent);
+        if !issues.is_empty() {
+            evaluation.add(structure::ua_anomaly_score(user_agent));
+            evaluation.reasons.extend(issues.into_iter().map(Reason::Malformed));
+        }
+    }
+
+    fn check_ip(&self, request: &RequestSnapshot, evaluation: &mut Evaluation<'_>) {
+        let Some(ip) = request.client_ip else { return };
+        if let Some(provider) = self.datacenter_provider(ip) {
+            evaluation.reasons.push(Reason::DatacenterIp { provider });
+            evaluation.add(DATACENTER_SCORE);
+        }
+        let reputation = self.reputation.as_ref().filter(|_| self.sampling.runs(Signal::Reputation, request, evaluation.score));
+        if let Some(entry) = reputation.and_then(|cache| cache.reputation(ip)) {
+            evaluation.add(entry.score);
+            evaluation.reasons.push(Reason::BadReputation { feed: entry.feed, score: entry.score });
+        }
+        let kind = self.anonymizers.as_ref().map_or(AnonymizerKind::None, |lists| lists.check_anonymizer(ip));
+        if kind != AnonymizerKind::None {
+            evaluation.reasons.push(Reason::Anonymizer { kind });
+            evaluation.add(kind.weight());
+        }
+    }
+
+    fn check_headers(&self, request: &RequestSnapshot, evaluation: &mut Evaluation<'_>) {
+        let user_agent = request.effective_user_agent();
+        if let Some(issue) = request.header_value("referer").and_then(|referrer| self.referrers.check(referrer)) {
+            evaluation.reasons.push(Reason::SpamReferrer(issue));
+            evaluation.add(REFERRER_SPAM_SCORE);
+        }
+        let claims_browser = user_agent.get(..8).is_some_and(|prefix| prefix.eq_ignore_ascii_case("mozilla/"));
+        if claims_browser && !request.headers.is_empty() {
+            let missing = BROWSER_HEADERS.iter().filter(|name| request.header_value(name).is_none());
+            for name in missing.filter(|name| self.locale.is_none() || **name != "accept-language") {
+                evaluation.reasons.push(Reason::MissingHeader { name: name.to_string() });
+                evaluation.add(ANOMALY_SCORE);
+            }
+        }
+        if let Some(rules) = self.locale.as_ref().filter(|_| !request.headers.is_empty()) {
+            let issues = rules.check_request(request);
+            if !issues.is_empty() {
+                evaluation.add(rules.score(&issues));
+                evaluation.reasons.extend(issues.into_iter().map(Reason::Locale));
+            }
+        }
+        if let Some(client) = self.header_orders.identify(&HeaderOrderFingerprint::from_request(request)) {
+            evaluation.reasons.push(Reason::AutomationHeaderOrder { client });
+            evaluation.add(HEADER_ORDER_SCORE);
+        }
+        let fingerprint = request.http2_fingerprint.as_deref().and_then(|fingerprint| fingerprint.parse::<Http2Fingerprint>().ok());
+        match fingerprint.and_then(|fingerprint| self.http2_profiles.identify(&fingerprint)) {
+            Some(profile) if profile.kind == ProfileKind::Automation => {
+                evaluation.reasons.push(Reason::Http2Automation { client: profile.client });
+                evaluation.add(HTTP2_AUTOMATION_SCORE);
+            }
+            Some(profile) => {
+                if let Some(claimed) = http2::claimed_browser(user_agent).filter(|claimed| *claimed != profile.client) {
+                    evaluation.reasons.push(Reason::Http2Mismatch { claimed: claimed.to_string(), client: profile.client });
+                    evaluation.add(HTTP2_MISMATCH_SCORE);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn check_behavior(&self, request: &RequestSnapshot, evaluation: &mut Evaluation<'_>) {
+        let analyzer = self.fingerprints.as_ref().filter(|_| self.sampling.runs(Signal::Fingerprint, request, evaluation.score));
+        if let Some(report) = analyzer.and_then(|analyzer| analyzer.evaluate_request(request)) {
+            evaluation.add(report.score);
+            evaluation.reasons.extend(report.signals.into_iter().map(Reason::Fingerprint));
+        }
+        // the gate only decides whether exceeding the limit is held against this request, every
+        // request was counted, or a sampled limit would never be reached
+        if let Some((limit, per)) = evaluation.rate_exceeded.filter(|_| self.sampling.runs(Signal::RateLimit, request, evaluation.score)) {
+            evaluation.reasons.push(Reason::RateExceeded { limit, per });
+            evaluation.add(RATE_EXCEEDED_SCORE);
+        }
+        if let Some(signal) = &self.cookies {
+            let check = signal.evaluate(request.header_value("cookie").unwrap_or(""));
+            if check == CookieCheck::Invalid {
+                evaluation.reasons.push(Reason::InvalidCookie);
+                evaluation.add(COOKIE_SCORE);
+            }
+            let requests = request.client_ip.map_or(0, |ip| signal.observe(ip, check));
+            if signal.is_suspicious(requests) {
+                evaluation.reasons.push(Reason::CookiesIgnored { requests });
+                evaluation.add(COOKIE_SCORE);
+            }
+        }
+    }
+
+    /// Whether evaluating can wait on a [`crate::state::StateStore`], see
+    /// [`crate::nonblocking::AsyncBotGuard::offload`].
+    pub(crate) fn uses_state(&self) -> bool {
+        self.rate_limit.is_some() || self.memory.is_some() || self.overrides.is_some()
+    }
+
+    fn datacenter_provider(&self, ip: IpAddr) -> Option<CloudProvider> {
+        match &self.datacenters {
+            Some(ranges) => ranges.check_datacenter_ip(ip),
+            #[cfg(feature = "datacenter-ranges")]
+            None => crate::datacenter::check_datacenter_ip(ip),
+            #[cfg(not(feature = "datacenter-ranges"))]
+            None => None,
+        }
+    }
+
+    /// The country and autonomous system of the client, as far as they are known.
+    fn locate(&self, request: &RequestSnapshot) -> (Option<String>, Option<u32>) {
+        #[cfg(feature = "geoip")]
+        if let Some(info) = self.geoip.as_ref().zip(request.client_ip).and_then(|(geoip, ip)| geoip.lookup(ip).ok()) {
+            return (request.country.clone().or(info.country), info.asn);
+        }
+        (request.country.clone(), None)
+    }
+
+    fn decide(&self, request: &RequestSnapshot, verdict: Verdict, score: f32, category: Option<String>, mut reasons: Vec<Reason>, verified: bool) -> RequestVerdict {
+        let (country, asn) = self.locate(request);
+        let datacenter = reasons.iter().any(|reason| matches!(reason, Reason::DatacenterIp { .. }));
+        let input = PolicyInput::new(&request.path).category(category.as_deref()).score(score).verified(verified).country(country.as_deref()).asn(asn).datacenter(datacenter);
+        let decision = self.policy.evaluate(&input);
+        let rule = decision.rule.map(|i| &self.policy.rules()[i]);
+        if let Some(rule) = rule.filter(|rule| rule.conditions().iter().any(Condition::is_geo)) {
+            reasons.push(Reason::GeoPolicy { route: rule.route().to_string(), country, asn });
+        }
+        RequestVerdict { verdict, score, category, reasons, action: decision.action, shadowed: decision.shadowed, tags: decision.tags, log: true, reference: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ip::IpNet;
+    use crate::policy::PolicyRule;
+
+    #[test]
+    fn combines_every_signal() {
+        let guard = BotGuard::new(BotDetector::new("[scanners]\n^sqlmap/\n[http-clients]\n0.6 python-requests/"));
+        let evaluate = |ua: &str| guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent(ua));
+
+        let scanner = evaluate("sqlmap/1.7");
+        assert_eq!((scanner.verdict, scanner.score, scanner.category.as_deref()), (Verdict::Bot, 1.0, Some("scanners")));
+        assert_eq!(scanner.reasons, vec![Reason::UaPatternMatch { group: "scanners".to_string(), pattern: "^sqlmap/".to_string(), weight: 1.0 }]);
+
+        assert_eq!(evaluate("python-requests/2.31").verdict, Verdict::Suspicious);
+        let tool = evaluate("acme-uptime/1.0");
+        assert_eq!((tool.verdict, tool.reasons.clone()), (Verdict::Human, vec![Reason::Heuristic]));
+
+        let forged = evaluate("Mozilla/5.0 (Windows NT 10.0) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/250.0 Safari/537.36");
+        assert_eq!(forged.verdict, Verdict::Suspicious);
+        assert_eq!(forged.reasons, vec![Reason::Anomaly(Anomaly::ImpossibleVersion { browser: "Chrome", major: 250 })]);
+
+        let empty = guard.evaluate(&RequestSnapshot::new("GET", "/").header("Accept", "*/*"));
+        assert_eq!((empty.verdict, empty.reasons), (Verdict::Human, vec![Reason::EmptyUserAgent]));
+    }
+
+    #[test]
+    fn allowlist_and_policy() {
+        let allowlist = Allowlist::new(&[], &["10.0.0.0/8".parse::<IpNet>().unwrap()]).unwrap();
+        let policy = PolicyEngine::new(Action::Allow)
+            .rule(crate::policy::PolicyRule::new("/admin/*", Action::Tag("admin".to_string())))
+            .rule(crate::policy::PolicyRule::new("*", Action::Challenge).when(crate::policy::Condition::MinScore(0.5)));
+        let guard = BotGuard::new(BotDetector::new("^curl/")).allowlist(allowlist).policy(policy);
+
+        let internal = guard.evaluate(&RequestSnapshot::new("GET", "/admin/x").user_agent("curl/8.0").client_ip("10.1.2.3".parse().unwrap()));
+        assert_eq!((internal.verdict, internal.action, internal.reasons), (Verdict::Human, Action::Allow, vec![Reason::Allowlisted]));
+        assert_eq!(internal.tags, vec!["admin".to_string()]);
+        // a dual-stack listener reports IPv4 clients as IPv4-mapped IPv6 addresses
+        let dual_stack = guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("curl/8.0").client_ip("::ffff:10.1.2.3".parse().unwrap()));
+        assert_eq!(dual_stack.reasons, vec![Reason::Allowlisted]);
+
+        let external = RequestSnapshot::new("GET", "/admin/x").user_agent("curl/8.0").client_ip("203.0.113.7".parse().unwrap());
+        let enforced = guard.evaluate(&external);
+        assert_eq!((enforced.verdict, enforced.action, enforced.shadowed), (Verdict::Bot, Action::Challenge, None));
+
+        let guard = BotGuard::new(BotDetector::new("^curl/")).policy(guard.policy.clone().shadow(true));
+        let shadowed = guard.evaluate(&external);
+        assert_eq!((shadowed.verdict, shadowed.action, shadowed.shadowed), (Verdict::Bot, Action::Allow, Some(Action::Challenge)));
+    }
+
+    #[test]
+    fn reports_request_level_reasons() {
+        use crate::state::MemoryStore;
+        use std::sync::Arc;
+
+        let ranges = DatacenterRanges::from_lists("[hetzner]\n198.51.100.0/24\n").unwrap();
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let guard = BotGuard::new(BotDetector::new("^curl/")).datacenter_ranges(ranges).rate_limit(limiter, 1, Duration::from_secs(60));
+        let request = RequestSnapshot::new("GET", "/")
+            .header("user-agent", "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0")
+            .header("Accept", "text/html")
+            .client_ip("198.51.100.9".parse().unwrap());
+
+        let first = guard.evaluate(&request);
+        assert_eq!(first.reasons, vec![Reason::DatacenterIp { provider: CloudProvider::Hetzner }, Reason::MissingHeader { name: "accept-language".to_string() }]);
+        assert_eq!(first.verdict, Verdict::Suspicious);
+        let mapped = RequestSnapshot { client_ip: Some("::ffff:198.51.100.9".parse().unwrap()), ..request.clone() };
+        assert_eq!(guard.evaluate(&mapped).reasons[0], Reason::DatacenterIp { provider: CloudProvider::Hetzner });
+
+        let second = guard.evaluate(&request);
+        assert_eq!(second.reasons.iter().map(Reason::code).collect::<Vec<_>>(), ["datacenter_ip", "missing_header", "rate_exceeded"]);
+        assert_eq!(
+            second.reasons[2].to_json(),
+            r#"{"code":"rate_exceeded","limit":1,"per_ms":60000,"detail":"more than 1 requests in 60s"}"#
+        );
+    }
+
+    #[test]
+    fn flags_library_header_orders() {
+        let guard = BotGuard::new(BotDetector::new("^curl/"));
+        let chrome = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0.0.0 Safari/537.36";
+        let script = RequestSnapshot::new("GET", "/").header("Host", "example.com").header("User-Agent", chrome).header("Accept-Encoding", "gzip");
+        let evaluated = guard.evaluate(&script);
+        assert_eq!(evaluated.verdict, Verdict::Suspicious);
+        assert!(evaluated.reasons.contains(&Reason::AutomationHeaderOrder { client: "go-http-client".to_string() }));
+
+        let browser = script.clone().header("Accept", "text/html").header("Accept-Language", "en");
+        assert_eq!(guard.evaluate(&browser).verdict, Verdict::Human);
+        guard.header_orders().register(HeaderOrderFingerprint::from_request(&browser), "acme-scraper");
+        assert_eq!(guard.evaluate(&browser).reasons[0].to_string(), "headers in the order acme-scraper sends them");
+    }
+
+    #[test]
+    fn compares_http2_fingerprints() {
+        let guard = BotGuard::new(BotDetector::new("^curl/"));
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0";
+        let request = RequestSnapshot::new("GET", "/").user_agent(firefox);
+        let evaluate = |fingerprint: &str| guard.evaluate(&request.clone().http2_fingerprint(fingerprint));
+
+        assert_eq!(evaluate("1:65536;2:0;4:131072;5:16384|12517377|0|m,p,a,s").reasons, Vec::new());
+        let go = evaluate("2:0;4:4194304;6:10485760|1073741824|0|m,a,s,p");
+        assert_eq!((go.verdict, go.reasons), (Verdict::Bot, vec![Reason::Http2Automation { client: "go-http-client".to_string() }]));
+        let chrome = evaluate("1:65536;2:0;4:6291456;6:262144|15663105|0|m,a,s,p");
+        assert_eq!(chrome.verdict, Verdict::Suspicious);
+        assert_eq!(chrome.reasons[0].to_json(), r#"{"code":"http2_mismatch","claimed":"firefox","client":"chrome","detail":"firefox user-agent with the HTTP/2 fingerprint of chrome"}"#);
+        assert_eq!(evaluate("garbage").reasons, Vec::new());
+    }
+
+    #[test]
+    fn scores_listed_addresses() {
+        use crate::reputation::{FeedFormat, ReputationFeed};
+
+        let cache = ReputationCache::new();
+        cache.add_feed(ReputationFeed::new("abuse", None, FeedFormat::Csv));
+        cache.load("abuse", "ipAddress,abuseConfidenceScore\n203.0.113.7,90\n198.51.100.4,30\n").unwrap();
+        let guard = BotGuard::new(BotDetector::new("^curl/")).ip_reputation(cache);
+        let request = |ip: &str| RequestSnapshot::new("GET", "/").user_agent("acme-fetcher").client_ip(ip.parse().unwrap());
+
+        let listed = guard.evaluate(&request("203.0.113.7"));
+        assert_eq!(listed.verdict, Verdict::Bot);
+        assert_eq!(listed.reasons[1].to_json(), r#"{"code":"ip_reputation","feed":"abuse","score":0.9,"detail":"address listed by abuse with score 0.9"}"#);
+        assert_eq!(guard.evaluate(&request("198.51.100.4")).verdict, Verdict::Human);
+        assert_eq!(guard.evaluate(&request("192.0.2.1")).reasons, vec![Reason::Heuristic]);
+        assert_eq!(guard.reputation_cache().map(ReputationCache::len), Some(2));
+    }
+
+    #[test]
+    fn scores_anonymizer_addresses() {
+        use crate::anonymizer::AnonymizerFeed;
+
+        let lists = Arc::new(AnonymizerLists::new());
+        lists.add_feed(AnonymizerFeed { url: None, ..AnonymizerFeed::tor_exit_nodes() });
+        lists.add_feed(AnonymizerFeed::new("vpn", AnonymizerKind::Vpn, None));
+        lists.load("tor", "185.220.101.1\n").unwrap();
+        lists.load("vpn", "198.51.100.0/24\n").unwrap();
+        let guard = BotGuard::new(BotDetector::new("^curl/")).anonymizers(Arc::clone(&lists));
+        let request = |ip: &str| RequestSnapshot::new("GET", "/").user_agent("acme-fetcher").client_ip(ip.parse().unwrap());
+
+        let plain = guard.evaluate(&request("192.0.2.1"));
+        let tor = guard.evaluate(&request("185.220.101.1"));
+        assert_eq!(tor.reasons, vec![Reason::Heuristic, Reason::Anonymizer { kind: AnonymizerKind::Tor }]);
+        assert!(plain.score < AnonymizerKind::Tor.weight() && tor.score == AnonymizerKind::Tor.weight(), "{} {}", tor.score, plain.score);
+        assert_eq!(tor.reasons[1].to_json(), r#"{"code":"anonymizer","kind":"tor","detail":"tor address"}"#);
+        let vpn = guard.evaluate(&request("198.51.100.9"));
+        assert_eq!((vpn.score, &vpn.reasons[1]), (plain.score.max(AnonymizerKind::Vpn.weight()), &Reason::Anonymizer { kind: AnonymizerKind::Vpn }));
+    }
+
+    #[test]
+    fn short_circuits_confident_stages() {
+        let pipeline = Pipeline::default().short_circuit(Stage::UserAgent, 0.9);
+        let guard = BotGuard::new(BotDetector::new("[scanners]\n^sqlmap/\n[http-clients]\n0.6 python-requests/")).pipeline(pipeline);
+        let evaluate = |ua: &str| guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent(ua).header("Referer", "http://semalt.com/"));
+
+        let scanner = evaluate("sqlmap/1.7");
+        assert_eq!(scanner.verdict, Verdict::Bot);
+        assert_eq!(scanner.reasons[1], Reason::ShortCircuited { stage: Stage::UserAgent });
+        assert_eq!(scanner.reasons[1].to_json(), r#"{"code":"short_circuited","stage":"user_agent","detail":"confident after the user_agent stage, later stages skipped"}"#);
+        // not confident enough, the referrer is checked too
+        let client = evaluate("python-requests/2.31");
+        assert!(matches!(client.reasons[..], [Reason::UaPatternMatch { .. }, Reason::SpamReferrer(_)]), "{:?}", client.reasons);
+
+        let headers_only = BotGuard::new(BotDetector::new("^sqlmap/")).pipeline(Pipeline::new([Stage::Headers]));
+        let skipped = headers_only.evaluate(&RequestSnapshot::new("GET", "/").user_agent("sqlmap/1.7"));
+        assert_eq!((skipped.verdict, skipped.reasons), (Verdict::Human, Vec::new()));
+    }
+
+    #[test]
+    fn exempts_internal_traffic_before_every_stage() {
+        use crate::exempt::INTERNAL_HEADER;
+        use crate::state::MemoryStore;
+        use std::sync::Arc;
+
+        let exemptions = Exemptions::new().health_checks().tokens(b"secret");
+        let token = exemptions.issue_token("deploy", Duration::from_secs(60)).unwrap();
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let policy = PolicyEngine::new(Action::Block);
+        let guard = BotGuard::new(BotDetector::new("^curl/")).policy(policy).rate_limit(limiter, 1, Duration::from_secs(60)).exemptions(exemptions);
+        let probe = RequestSnapshot::new("GET", "/healthz").user_agent("kube-probe/1.29").client_ip("10.0.0.5".parse().unwrap());
+        for _ in 0..3 {
+            let evaluated = guard.evaluate(&probe);
+            assert_eq!((evaluated.verdict, evaluated.action, evaluated.log), (Verdict::Human, Action::Allow, false));
+        }
+        let deploy = guard.evaluate(&RequestSnapshot::new("POST", "/purge").user_agent("curl/8.4.0").header(INTERNAL_HEADER, &token));
+        assert_eq!(deploy.reasons[0].to_json(), r#"{"code":"exempt","detail":"exempt: internal token of deploy"}"#);
+        assert_eq!(guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("curl/8.4.0")).action, Action::Block);
+
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let counted = BotGuard::new(BotDetector::new("^curl/")).rate_limit(limiter, 1, Duration::from_secs(60)).exemptions(Exemptions::new().health_checks().rate_limited(true).logged(true));
+        assert_eq!(counted.evaluate(&probe).action, Action::Allow);
+        let limited = counted.evaluate(&probe);
+        assert_eq!((limited.verdict, limited.action, limited.log), (Verdict::Human, Action::RateLimit { requests: 1, per: Duration::from_secs(60) }, true));
+    }
+
+    #[test]
+    fn checks_the_user_agent_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let hooks = Arc::new(AtomicUsize::new(0));
+        let mut detector = BotDetector::new("[tools]\n0.6 ^curl/");
+        let counter = hooks.clone();
+        detector.on_detection(move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        let guard = BotGuard::new(detector);
+        let evaluated = guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("curl/8.4.0").client_ip("203.0.113.7".parse().unwrap()));
+        assert_eq!((evaluated.verdict, evaluated.score, evaluated.category.as_deref()), (Verdict::Suspicious, 0.6, Some("tools")));
+        assert_eq!(evaluated.reasons[0], Reason::UaPatternMatch { group: "tools".to_string(), pattern: "^curl/".to_string(), weight: 0.6 });
+        assert_eq!(hooks.load(Ordering::Relaxed), 0);
+
+        let mut detector = guard.detector().clone();
+        detector.block_ip_ttl("203.0.113.7".parse().unwrap(), Duration::from_secs(60));
+        let guard = BotGuard::new(detector);
+        let evaluated = guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("curl/8.4.0").client_ip("203.0.113.7".parse().unwrap()));
+        assert_eq!((evaluated.verdict, evaluated.score), (Verdict::Bot, 0.6));
+        assert_eq!(hooks.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn promotes_the_canary_once_it_agrees() {
+        use crate::canary::Canary;
+
+        let mut guard = BotGuard::new(BotDetector::new("^sqlmap/")).canary(Canary::new(BotDetector::new("^sqlmap/\nnikto")).sample(1.0).min_samples(2));
+        guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("sqlmap/1.7"));
+        assert!(guard.promote_canary().unwrap_err().to_string().contains("0 of 1 samples diverged"));
+        guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("sqlmap/1.8"));
+        assert_eq!(guard.canary_report().map(|report| report.samples), Some(2));
+        guard.promote_canary().unwrap();
+        assert!(guard.detector().check_bot("Nikto/2.5"));
+        assert!(guard.canary_report().is_none() && guard.promote_canary().is_err());
+    }
+
+    #[test]
+    fn applies_overrides_before_the_pipeline() {
+        use crate::state::MemoryStore;
+        use std::sync::Arc;
+
+        let overrides = ClientOverrides::new(Arc::new(MemoryStore::new()));
+        let guard = BotGuard::new(BotDetector::new("^curl/")).policy(PolicyEngine::new(Action::Block)).overrides(overrides.clone());
+        let customer = RequestSnapshot::new("GET", "/checkout").user_agent("curl/8.4.0").client_ip("203.0.113.7".parse().unwrap());
+        assert_eq!(guard.evaluate(&customer).reasons[0].code(), "ua_pattern_match");
+
+        overrides.mark_human(&customer.client_key().unwrap(), Duration::from_secs(60)).unwrap();
+        let unblocked = guard.evaluate(&customer);
+        assert_eq!((unblocked.verdict, unblocked.action), (Verdict::Human, Action::Allow));
+        assert_eq!(unblocked.reasons[0].to_json(), r#"{"code":"overridden","verdict":"human","detail":"marked human by hand"}"#);
+
+        let scraper = RequestSnapshot::new("GET", "/").user_agent("Mozilla/5.0").tls_fingerprint("771,4865-4866");
+        overrides.mark_bot("tls:771,4865-4866", Duration::from_secs(60)).unwrap();
+        let blocked = guard.evaluate(&scraper);
+        assert_eq!((blocked.verdict, blocked.score, blocked.action), (Verdict::Bot, 1.0, Action::Block));
+        assert_eq!(blocked.reasons, vec![Reason::Overridden { verdict: Verdict::Bot }]);
+    }
+
+    #[test]
+    fn allows_and_tags_partner_traffic() {
+        use crate::partners::PARTNER_HEADER;
+
+        let tokens = PartnerTokens::new();
+        tokens.register("uptime-monitor", "0123456789abcdef", None).unwrap();
+        let policy = PolicyEngine::new(Action::Allow).rule(PolicyRule::new("*", Action::Block).when(Condition::MinScore(0.8)));
+        let guard = BotGuard::new(BotDetector::new("^curl/")).policy(policy).partner_tokens(tokens);
+        let check = RequestSnapshot::new("GET", "/status").user_agent("curl/8.4.0");
+
+        let monitored = guard.evaluate(&check.clone().header(PARTNER_HEADER, "0123456789abcdef"));
+        assert_eq!((monitored.verdict, monitored.action, monitored.tags), (Verdict::Bot, Action::Allow, vec!["partner:uptime-monitor".to_string()]));
+        assert_eq!(monitored.reasons[1].to_json(), r#"{"code":"partner","partner":"uptime-monitor","detail":"token of partner uptime-monitor"}"#);
+        assert_eq!(guard.evaluate(&check.clone().header(PARTNER_HEADER, "fedcba9876543210")).action, Action::Block);
+
+        guard.partners().unwrap().revoke("uptime-monitor");
+        assert_eq!(guard.evaluate(&check.header(PARTNER_HEADER, "0123456789abcdef")).action, Action::Block);
+    }
+
+    #[test]
+    fn partners_are_verified_for_the_policy() {
+        use crate::partners::PARTNER_HEADER;
+
+        let tokens = PartnerTokens::new();
+        tokens.register("uptime-monitor", "0123456789abcdef", None).unwrap();
+        let policy = PolicyEngine::new(Action::Allow)
+            .rule(PolicyRule::new("/status", Action::Tag("verified".to_string())).when(Condition::Verified(true)))
+            .rule(PolicyRule::new("/status", Action::Challenge).when(Condition::Verified(false)));
+        let guard = BotGuard::new(BotDetector::new("^curl/")).policy(policy).partner_tokens(tokens);
+        let check = RequestSnapshot::new("GET", "/status").user_agent("curl/8.4.0");
+
+        let monitored = guard.evaluate(&check.clone().header(PARTNER_HEADER, "0123456789abcdef"));
+        assert_eq!(monitored.tags, vec!["verified".to_string(), "partner:uptime-monitor".to_string()]);
+        let unverified = guard.evaluate(&check);
+        assert_eq!((unverified.action, unverified.tags), (Action::Challenge, Vec::<String>::new()));
+    }
+
+    #[test]
+    fn evaluates_the_client_behind_trusted_proxies() {
+        let allowlist = Allowlist::new(&[], &["192.0.2.0/24".parse::<IpNet>().unwrap()]).unwrap();
+        let proxies = ClientIpExtractor::new(&["10.0.0.0/8".parse().unwrap()]);
+        let guard = BotGuard::new(BotDetector::new("^curl/")).allowlist(allowlist).trusted_proxies(proxies);
+        let request = |peer: &str| RequestSnapshot::new("GET", "/").user_agent("curl/8.4.0").header("X-Forwarded-For", "192.0.2.7").client_ip(peer.parse().unwrap());
+        assert_eq!(guard.evaluate(&request("10.0.0.1")).reasons, vec![Reason::Allowlisted]);
+        assert_eq!(guard.evaluate(&request("203.0.113.7")).verdict, Verdict::Bot);
+    }
+
+    #[test]
+    fn skips_gated_signals() {
+        use crate::reputation::{FeedFormat, ReputationFeed};
+        use crate::sampling::SignalGate;
+
+        let cache = ReputationCache::new();
+        cache.add_feed(ReputationFeed::new("abuse", None, FeedFormat::Csv));
+        cache.load("abuse", "ipAddress,abuseConfidenceScore\n203.0.113.7,90\n").unwrap();
+        let sampling = Sampling::new().gate(Signal::Reputation, SignalGate::new().paths(&["/login"]));
+        let guard = BotGuard::new(BotDetector::new("^curl/")).ip_reputation(cache).sampling(sampling);
+        let request = |path: &str| RequestSnapshot::new("POST", path).user_agent("acme-fetcher").client_ip("203.0.113.7".parse().unwrap());
+        assert_eq!(guard.evaluate(&request("/login")).verdict, Verdict::Bot);
+        assert_eq!(guard.evaluate(&request("/search")).verdict, Verdict::Human);
+    }
+
+    #[test]
+    fn counts_requests_the_rate_limit_gate_skips() {
+        use crate::sampling::SignalGate;
+        use crate::state::MemoryStore;
+        use std::sync::Arc;
+
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let sampling = Sampling::new().gate(Signal::RateLimit, SignalGate::new().min_score(0.5));
+        let guard = BotGuard::new(BotDetector::new("^curl/")).rate_limit(limiter, 2, Duration::from_secs(60)).sampling(sampling);
+        let request = |user_agent: &str| RequestSnapshot::new("GET", "/").user_agent(user_agent).client_ip("203.0.113.7".parse().unwrap());
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0";
+        for _ in 0..3 {
+            assert!(guard.evaluate(&request(firefox)).reasons.iter().all(|reason| reason.code() != "rate_exceeded"));
+        }
+        // the skipped requests count towards the limit the first gated one is held to
+        let scripted = guard.evaluate(&request("curl/8.4.0"));
+        assert!(scripted.reasons.contains(&Reason::RateExceeded { limit: 2, per: Duration::from_secs(60) }), "{:?}", scripted.reasons);
+    }
+
+    #[test]
+    fn counts_requests_decided_before_the_behavior_stage() {
+        use crate::state::MemoryStore;
+        use std::sync::Arc;
+
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let allowlist = Allowlist::new(&["^monitor/"], &[]).unwrap();
+        let pipeline = Pipeline::default().short_circuit(Stage::UserAgent, 1.0);
+        let guard = BotGuard::new(BotDetector::new("^curl/")).allowlist(allowlist).pipeline(pipeline).rate_limit(limiter, 2, Duration::from_secs(60));
+        let request = |user_agent: &str| RequestSnapshot::new("GET", "/").user_agent(user_agent).client_ip("203.0.113.7".parse().unwrap());
+        assert!(guard.evaluate(&request("Monitor/1.0")).reasons.contains(&Reason::Allowlisted));
+        assert!(guard.evaluate(&request("curl/8.4.0")).reasons.contains(&Reason::ShortCircuited { stage: Stage::UserAgent }));
+        let firefox = guard.evaluate(&request("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0"));
+        assert!(firefox.reasons.contains(&Reason::RateExceeded { limit: 2, per: Duration::from_secs(60) }), "{:?}", firefox.reasons);
+
+        // counted without the stage holding it against any request
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let guard = BotGuard::new(BotDetector::new("^curl/")).pipeline(Pipeline::new([Stage::UserAgent])).rate_limit(limiter.clone(), 3, Duration::from_secs(60));
+        for _ in 0..3 {
+            assert!(guard.evaluate(&request("curl/8.4.0")).reasons.iter().all(|reason| reason.code() != "rate_exceeded"));
+        }
+        assert!(!limiter.check("203.0.113.7", 3, Duration::from_secs(60)).unwrap().allowed);
+    }
+
+    #[test]
+    fn applies_location_rules() {
+        let policy = PolicyEngine::new(Action::Allow)
+            .rule(PolicyRule::new("/signup", Action::Challenge).when(Condition::NotCountry(vec!["DE".to_string(), "AT".to_string()])))
+            .rule(PolicyRule::new("*", Action::Block).when(Condition::Datacenter(true)));
+        let ranges = DatacenterRanges::from_lists("[hetzner]\n198.51.100.0/24\n").unwrap();
+        let guard = BotGuard::new(BotDetector::new("^curl/")).policy(policy).datacenter_ranges(ranges);
+        let signup = RequestSnapshot::new("GET", "/signup").user_agent("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0");
+
+        let foreign = guard.evaluate(&signup.clone().country("BR"));
+        assert_eq!(foreign.action, Action::Challenge);
+        assert_eq!(
+            foreign.reasons[0].to_json(),
+            r#"{"code":"geo_policy","route":"/signup","country":"BR","asn":null,"detail":"location rule of /signup for BR"}"#
+        );
+        assert_eq!(guard.evaluate(&signup.clone().country("de")).reasons, Vec::new());
+        let cloud = guard.evaluate(&signup.client_ip("198.51.100.4".parse().unwrap()));
+        assert_eq!(cloud.action, Action::Block);
+        assert!(matches!(&cloud.reasons[..], [Reason::DatacenterIp { .. }, Reason::GeoPolicy { route, .. }] if route == "*"));
+    }
+
+    #[cfg(feature = "geoip")]
+    #[test]
+    fn resolves_locations_with_geoip() {
+        let policy = PolicyEngine::new(Action::Allow).rule(PolicyRule::new("*", Action::Block).when(Condition::Country(vec!["DE".to_string()])).when(Condition::NotAsn(vec![64501])));
+        let guard = BotGuard::new(BotDetector::new("^curl/")).policy(policy).geoip(crate::geoip::tests::test_geoip());
+        let request = |ip: &str| RequestSnapshot::new("GET", "/").user_agent("acme-fetcher").client_ip(ip.parse().unwrap());
+
+        let blocked = guard.evaluate(&request("203.0.113.9"));
+        assert_eq!(blocked.action, Action::Block);
+        assert_eq!(blocked.reasons[1], Reason::GeoPolicy { route: "*".to_string(), country: Some("DE".to_string()), asn: Some(64500) });
+        assert_eq!(blocked.reasons[1].to_string(), "location rule of * for DE AS64500");
+        assert_eq!(guard.evaluate(&request("203.0.113.9").country("FR")).action, Action::Allow);
+        assert_eq!(guard.evaluate(&request("198.51.100.1")).action, Action::Allow);
+    }
+
+    #[test]
+    fn weighs_locale_issues() {
+        let guard = BotGuard::new(BotDetector::new("^curl/")).locale_rules(LocaleRules::new().country_weight(0.5));
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0";
+        let request = RequestSnapshot::new("GET", "/").header("User-Agent", firefox).header("Accept", "text/html");
+        let missing = guard.evaluate(&request);
+        assert_eq!((missing.verdict, missing.reasons), (Verdict::Human, vec![Reason::Locale(LocaleIssue::Missing)]));
+
+        let wildcard = guard.evaluate(&request.clone().header("Accept-Language", "*").country("DE"));
+        assert_eq!((wildcard.verdict, wildcard.reasons), (Verdict::Suspicious, vec![Reason::Locale(LocaleIssue::WildcardOnly)]));
+        let foreign = guard.evaluate(&request.clone().header("Accept-Language", "ko-KR,ko;q=0.9").country("DE"));
+        assert_eq!(foreign.reasons[0].to_string(), "Accept-Language ko-KR from DE");
+        assert_eq!(foreign.score, 0.5);
+        assert!(guard.evaluate(&request.clone().header("Accept-Language", "de-DE,de").country("DE")).reasons.is_empty());
+    }
+
+    #[test]
+    fn flags_clients_ignoring_the_cookie() {
+        let guard = BotGuard::new(BotDetector::new("^curl/")).cookie_signal(CookieSignal::new(b"secret").suspicious_after(2));
+        let script = RequestSnapshot::new("GET", "/").user_agent("acme-fetcher").client_ip("203.0.113.7".parse().unwrap());
+        let set_cookie = guard.set_cookie(&script).unwrap();
+        let browser = script.clone().header("Cookie", set_cookie.split(';').next().unwrap());
+        assert_eq!(guard.set_cookie(&browser), None);
+
+        for _ in 0..2 {
+            assert_eq!(guard.evaluate(&script).verdict, Verdict::Human);
+        }
+        let third = guard.evaluate(&script);
+        assert_eq!((third.verdict, third.reasons), (Verdict::Suspicious, vec![Reason::Heuristic, Reason::CookiesIgnored { requests: 3 }]));
+        // returning the cookie resets the streak
+        assert_eq!(guard.evaluate(&browser).reasons, vec![Reason::Heuristic]);
+        assert_eq!(guard.evaluate(&script).verdict, Verdict::Human);
+
+        let forged = guard.evaluate(&script.clone().header("Cookie", "bg_seen=1.2"));
+        assert_eq!(forged.reasons[1].to_json(), r#"{"code":"invalid_cookie","detail":"forged seen-before cookie"}"#);
+    }
+
+    #[test]
+    fn weighs_browser_fingerprints() {
+        let guard = BotGuard::new(BotDetector::new("^curl/")).fingerprint_analyzer(FingerprintAnalyzer::default());
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0";
+        let request = RequestSnapshot::new("GET", "/").user_agent(firefox);
+        assert_eq!(guard.evaluate(&request).reasons, vec![]);
+
+        let payload = FingerprintPayload::from_json(&format!(r#"{{"canvasHash":"ab12","webdriver":true,"plugins":5,"userAgent":"{}"}}"#, firefox)).unwrap();
+        let automated = guard.evaluate(&request.fingerprint(payload));
+        assert_eq!((automated.verdict, automated.score), (Verdict::Bot, 1.0));
+        assert_eq!(automated.reasons[0].to_json(), r#"{"code":"browser_fingerprint","detail":"browser under automation"}"#);
+    }
+
+    #[test]
+    fn fast_paths_remembered_bots() {
+        use crate::state::MemoryStore;
+        use std::sync::Arc;
+
+        let memory = ClientMemory::new(Arc::new(MemoryStore::new()), Duration::from_secs(3600));
+        let guard = BotGuard::new(BotDetector::new("^curl/")).client_memory(memory);
+        let ip = "203.0.113.7".parse().unwrap();
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0";
+        assert_eq!(guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent("curl/8.4.0").client_ip(ip)).verdict, Verdict::Bot);
+
+        let disguised = guard.evaluate(&RequestSnapshot::new("GET", "/").user_agent(firefox).client_ip(ip));
+        assert_eq!(disguised.verdict, Verdict::Bot);
+        assert!(matches!(disguised.reasons[..], [Reason::Remembered { score }] if score > 0.99));
+        let other = RequestSnapshot::new("GET", "/").user_agent(firefox).client_ip("203.0.113.8".parse().unwrap());
+        assert_eq!(guard.evaluate(&other).verdict, Verdict::Human);
+
+        // a TLS fingerprint is shared by every user of a browser build, it is no key of its own
+        let fingerprinted = RequestSnapshot::new("GET", "/").user_agent("curl/8.4.0").tls_fingerprint("t13d1516h2");
+        assert_eq!(guard.evaluate(&fingerprinted).verdict, Verdict::Bot);
+        let same_build = guard.evaluate(&fingerprinted.clone().user_agent(firefox));
+        assert_eq!(same_build.verdict, Verdict::Human);
+        assert!(same_build.reasons.iter().all(|reason| reason.code() != "client_memory"));
+    }
+}
@@ -0,0 +1,557 @@
+// Persisted detection records for reviewing decisions after the fact, e.g. to find the heaviest
+// crawlers of the last day or blocks that may have hit real users.
+//
+// `MemoryVerdictStore` keeps records per process, `FileVerdictStore` appends them to a file with
+// one JSON object per line, which survives restarts and can be inspected with `jq`. With the
+// `sqlite` feature `SqliteVerdictStore` keeps them in a SQLite database. `Report` aggregates the
+// records of a time range, e.g. for a daily cron email.
+//
+// The JSON-lines file has no index, every query reads the whole file, so it suits the records of
+// days rather than months. The SQLite store indexes records by time and answers the queries in
+// SQL.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::events::BotEvent;
+use crate::policy::Action;
+use crate::{json, BotGuardError};
+
+#[cfg(feature = "sqlite")]
+pub use crate::sqlite::SqliteVerdictStore;
+
+/// Records acted on with a score below this are [`VerdictStore::false_positive_candidates`].
+pub const FALSE_POSITIVE_SCORE: f32 = 0.5;
+
+/// One detection decision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectionRecord {
+    pub user_agent: String,
+    pub ip: Option<IpAddr>,
+    /// Bot score between `0.0` (human) and `1.0` (bot).
+    pub score: f32,
+    pub matched_patterns: Vec<String>,
+    pub category: Option<String>,
+    /// What was done with the request.
+    pub action: Action,
+    pub timestamp: SystemTime,
+}
+
+impl DetectionRecord {
+    /// A record of a detection event, scored `1.0` unless a score is known.
+    pub fn from_event(event: &BotEvent, action: Action) -> Self {
+        DetectionRecord {
+            user_agent: event.user_agent.clone(),
+            ip: event.ip,
+            score: 1.0,
+            matched_patterns: event.matched_pattern.iter().cloned().collect(),
+            category: event.category.clone(),
+            action,
+            timestamp: event.timestamp,
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let mut out = String::from("{\"user_agent\":");
+        json::push_str(&mut out, &self.user_agent);
+        out.push_str(",\"ip\":");
+        json::push_opt_str(&mut out, self.ip.map(|ip| ip.to_string()).as_deref());
+        out.push_str(&format!(",\"score\":{},\"matched_patterns\":[", self.score));
+        for (i, pattern) in self.matched_patterns.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            json::push_str(&mut out, pattern);
+        }
+        out.push_str("],\"category\":");
+        json::push_opt_str(&mut out, self.category.as_deref());
+        out.push_str(",\"action\":");
+        json::push_str(&mut out, &action_name(&self.action));
+        let millis = self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        out.push_str(&format!(",\"timestamp\":{}}}", millis));
+        out
+    }
+
+    fn from_json(line: &str) -> Option<Self> {
+        let value = json::parse(line).ok()?;
+        let ip = match value.get("ip")? {
+            json::Value::Null => None,
+            ip => Some(ip.as_str()?.parse().ok()?),
+        };
+        let matched_patterns = value
+            .get("matched_patterns")?
+            .as_array()?
+            .iter()
+            .map(|pattern| pattern.as_str().map(str::to_string))
+            .collect::<Option<Vec<String>>>()?;
+        Some(DetectionRecord {
+            user_agent: value.get("user_agent")?.as_str()?.to_string(),
+            ip,
+            score: value.get("score")?.as_f64()? as f32,
+            matched_patterns,
+            category: value.get("category")?.as_str().map(str::to_string),
+            action: parse_action(value.get("action")?.as_str()?)?,
+            timestamp: UNIX_EPOCH + Duration::from_millis(value.get("timestamp")?.as_f64()? as u64),
+        })
+    }
+}
+
+pub(crate) fn action_name(action: &Action) -> String {
+    match action {
+        Action::Allow => "allow".to_string(),
+        Action::Block => "block".to_string(),
+        Action::Challenge => "challenge".to_string(),
+        Action::Tag(tag) => format!("tag:{}", tag),
+        Action::RateLimit { requests, per } => format!("rate_limit:{}/{}", requests, per.as_millis()),
+        Action::Tarpit { bytes_per_second } => format!("tarpit:{}", bytes_per_second),
+        Action::Decoy => "decoy".to_string(),
+    }
+}
+
+pub(crate) fn parse_action(name: &str) -> Option<Action> {
+    Some(match name {
+        "allow" => Action::Allow,
+        "block" => Action::Block,
+        "challenge" => Action::Challenge,
+        "decoy" => Action::Decoy,
+        _ => {
+            if let Some(tag) = name.strip_prefix("tag:") {
+                Action::Tag(tag.to_string())
+            } else if let Some(rate) = name.strip_prefix("tarpit:") {
+                Action::Tarpit { bytes_per_second: rate.parse().ok()? }
+            } else {
+                let (requests, millis) = name.strip_prefix("rate_limit:")?.split_once('/')?;
+                Action::RateLimit { requests: requests.parse().ok()?, per: Duration::from_millis(millis.parse().ok()?) }
+            }
+        }
+    })
+}
+
+/// A user-agent and the number of its records, see [`VerdictStore::top_bots`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotCount {
+    pub user_agent: String,
+    pub category: Option<String>,
+    pub count: u64,
+}
+
+/// Storage of detection records with the queries used for review.
+pub trait VerdictStore: Send + Sync {
+    fn record(&self, record: &DetectionRecord) -> Result<(), BotGuardError>;
+
+    /// Records made at or after `since`, oldest first.
+    fn records_since(&self, since: SystemTime) -> Result<Vec<DetectionRecord>, BotGuardError>;
+
+    /// User-agents with a bot category recorded since `since`, the most frequent first.
+    fn top_bots(&self, since: SystemTime) -> Result<Vec<BotCount>, BotGuardError> {
+        let mut counts = HashMap::<(String, Option<String>), u64>::new();
+        for record in self.records_since(since)? {
+            if record.category.is_some() {
+                *counts.entry((record.user_agent, record.category)).or_default() += 1;
+            }
+        }
+        let mut top = counts
+            .into_iter()
+            .map(|((user_agent, category), count)| BotCount { user_agent, category, count })
+            .collect::<Vec<BotCount>>();
+        top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.user_agent.cmp(&b.user_agent)));
+        Ok(top)
+    }
+
+    /// The latest record per user-agent that was blocked or challenged although its score is
+    /// below [`FALSE_POSITIVE_SCORE`], i.e. on weak evidence.
+    fn false_positive_candidates(&self) -> Result<Vec<DetectionRecord>, BotGuardError> {
+        let mut latest = HashMap::<String, DetectionRecord>::new();
+        for record in self.records_since(UNIX_EPOCH)? {
+            if matches!(record.action, Action::Block | Action::Challenge) && record.score < FALSE_POSITIVE_SCORE {
+                latest.insert(record.user_agent.clone(), record);
+            }
+        }
+        let mut candidates = latest.into_values().collect::<Vec<DetectionRecord>>();
+        candidates.sort_by_key(|record| std::cmp::Reverse(record.timestamp));
+        Ok(candidates)
+    }
+}
+
+/// Records kept in memory, lost when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryVerdictStore {
+    records: Mutex<Vec<DetectionRecord>>,
+}
+
+impl MemoryVerdictStore {
+    pub fn new() -> Self {
+        MemoryVerdictStore::default()
+    }
+}
+
+impl VerdictStore for MemoryVerdictStore {
+    fn record(&self, record: &DetectionRecord) -> Result<(), BotGuardError> {
+        self.records.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(record.clone());
+        Ok(())
+    }
+
+    fn records_since(&self, since: SystemTime) -> Result<Vec<DetectionRecord>, BotGuardError> {
+        let records = self.records.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        Ok(records.iter().filter(|record| record.timestamp >= since).cloned().collect())
+    }
+}
+
+/// Records appended to a file as JSON lines, without a database.
+///
+/// Lines that cannot be parsed, such as one cut short by a crash, are skipped when reading. Every
+/// query reads the file from the start, there is no index.
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::time::{Duration, SystemTime};
+/// use BotGuardLib::BotDetector;
+/// use BotGuardLib::policy::Action;
+/// use BotGuardLib::review::{DetectionRecord, FileVerdictStore, VerdictStore};
+///
+/// let store = Arc::new(FileVerdictStore::open("/var/lib/botguard/verdicts.jsonl").unwrap());
+/// let mut detector = BotDetector::default();
+/// let recorder = Arc::clone(&store);
+/// detector.on_detection(move |event| {
+///     let _ = recorder.record(&DetectionRecord::from_event(event, Action::Block));
+/// });
+///
+/// let yesterday = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+/// for bot in store.top_bots(yesterday).unwrap().iter().take(10) {
+///     println!("{:>8} {}", bot.count, bot.user_agent);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FileVerdictStore {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl FileVerdictStore {
+    /// Opens or creates the file, existing records are kept.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BotGuardError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new().create(true).append(true).open(&path).map_err(|e| io_error(&path, e))?;
+        // terminate a line cut short by a crash, the next record would be lost with it otherwise
+        let mut last = [b'\n'];
+        let mut reader = File::open(&path).map_err(|e| io_error(&path, e))?;
+        if reader.metadata().map_err(|e| io_error(&path, e))?.len() > 0 {
+            reader.seek(SeekFrom::End(-1)).and_then(|_| reader.read_exact(&mut last)).map_err(|e| io_error(&path, e))?;
+        }
+        if last[0] != b'\n' {
+            file.write_all(b"\n").map_err(|e| io_error(&path, e))?;
+        }
+        Ok(FileVerdictStore { path, file: Mutex::new(file) })
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> BotGuardError {
+    BotGuardError::IoError { path: path.display().to_string(), reason: e.to_string() }
+}
+
+impl VerdictStore for FileVerdictStore {
+    fn record(&self, record: &DetectionRecord) -> Result<(), BotGuardError> {
+        let mut line = record.to_json();
+        line.push('\n');
+        let mut file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // a single write keeps concurrent appenders from interleaving lines
+        file.write_all(line.as_bytes()).map_err(|e| io_error(&self.path, e))
+    }
+
+    fn records_since(&self, since: SystemTime) -> Result<Vec<DetectionRecord>, BotGuardError> {
+        // holding the lock keeps half-written records out of the result
+        let _file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let reader = BufReader::new(File::open(&self.path).map_err(|e| io_error(&self.path, e))?);
+        let mut records = Vec::new();
+        for line in reader.lines() {
+            let line = line.map_err(|e| io_error(&self.path, e))?;
+            if let Some(record) = DetectionRecord::from_json(&line).filter(|record| record.timestamp >= since) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Number of entries in the top lists of a [`Report`].
+const TOP_ENTRIES: usize = 10;
+
+/// Number of [`TrendBucket`]s a [`Report`] splits its range into.
+const TREND_BUCKETS: u32 = 24;
+
+/// Requests within one slice of a [`Report`]'s range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrendBucket {
+    pub start: SystemTime,
+    pub requests: u64,
+    pub bots: u64,
+}
+
+/// Summary of the records in a time range. Records with a category count as bots.
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use BotGuardLib::policy::Action;
+/// use BotGuardLib::review::{DetectionRecord, MemoryVerdictStore, Report, VerdictStore};
+///
+/// let now = SystemTime::now();
+/// let store = MemoryVerdictStore::new();
+/// for (user_agent, category) in [("curl/8.0", Some("http-clients")), ("Mozilla/5.0", None)] {
+///     store.record(&DetectionRecord {
+///         user_agent: user_agent.to_string(),
+///         ip: None,
+///         score: if category.is_some() { 1.0 } else { 0.0 },
+///         matched_patterns: Vec::new(),
+///         category: category.map(str::to_string),
+///         action: Action::Allow,
+///         timestamp: now,
+///     }).unwrap();
+/// }
+///
+/// let report = Report::generate(&store, now - Duration::from_secs(3600)..now + Duration::from_secs(1)).unwrap();
+/// assert_eq!(report.bot_share(), 0.5);
+/// assert_eq!(report.categories, vec![("http-clients".to_string(), 1)]);
+/// println!("{}", report);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub range: Range<SystemTime>,
+    pub requests: u64,
+    pub bots: u64,
+    /// Bot records per category, the largest first.
+    pub categories: Vec<(String, u64)>,
+    /// The most frequent bot user-agents.
+    pub top_user_agents: Vec<(String, u64)>,
+    /// The addresses with the most bot records.
+    pub top_ips: Vec<(IpAddr, u64)>,
+    /// The range split into equal slices, oldest first.
+    pub trend: Vec<TrendBucket>,
+}
+
+impl Report {
+    /// Aggregates the records made within `range`.
+    pub fn generate(store: &dyn VerdictStore, range: Range<SystemTime>) -> Result<Self, BotGuardError> {
+        let span = range.end.duration_since(range.start).unwrap_or_default();
+        let width = (span / TREND_BUCKETS).max(Duration::from_millis(1));
+        let mut trend = (0..TREND_BUCKETS)
+            .map(|i| TrendBucket { start: range.start + width * i, requests: 0, bots: 0 })
+            .collect::<Vec<TrendBucket>>();
+        let (mut requests, mut bots) = (0, 0);
+        let (mut categories, mut user_agents, mut ips) = (HashMap::new(), HashMap::new(), HashMap::new());
+        for record in store.records_since(range.start)? {
+            if record.timestamp >= range.end {
+                continue;
+            }
+            let offset = record.timestamp.duration_since(range.start).unwrap_or_default();
+            let bucket = &mut trend[((offset.as_millis() / width.as_millis()) as usize).min(TREND_BUCKETS as usize - 1)];
+            requests += 1;
+            bucket.requests += 1;
+            let Some(category) = record.category else { continue };
+            bots += 1;
+            bucket.bots += 1;
+            *categories.entry(category).or_default() += 1;
+            *user_agents.entry(record.user_agent).or_default() += 1;
+            if let Some(ip) = record.ip {
+                *ips.entry(ip).or_default() += 1;
+            }
+        }
+        Ok(Report {
+            range,
+            requests,
+            bots,
+            categories: ranked(categories, usize::MAX),
+            top_user_agents: ranked(user_agents, TOP_ENTRIES),
+            top_ips: ranked(ips, TOP_ENTRIES),
+            trend,
+        })
+    }
+
+    /// Fraction of the requests made by bots, `0.0` without requests.
+    pub fn bot_share(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.bots as f64 / self.requests as f64
+        }
+    }
+
+    /// Serializes the report as a JSON object, times are in unix milliseconds.
+    pub fn to_json(&self) -> String {
+        let millis = |time: SystemTime| time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let counts = |out: &mut String, entries: &mut dyn Iterator<Item = (String, u64)>| {
+            out.push('[');
+            for (i, (key, count)) in entries.enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push('[');
+                json::push_str(out, &key);
+                out.push_str(&format!(",{}]", count));
+            }
+            out.push(']');
+        };
+        let mut out = format!(
+            "{{\"start\":{},\"end\":{},\"requests\":{},\"bots\":{},\"bot_share\":{},\"categories\":",
+            millis(self.range.start),
+            millis(self.range.end),
+            self.requests,
+            self.bots,
+            self.bot_share()
+        );
+        counts(&mut out, &mut self.categories.iter().cloned());
+        out.push_str(",\"top_user_agents\":");
+        counts(&mut out, &mut self.top_user_agents.iter().cloned());
+        out.push_str(",\"top_ips\":");
+        counts(&mut out, &mut self.top_ips.iter().map(|(ip, count)| (ip.to_string(), *count)));
+        out.push_str(",\"trend\":[");
+        for (i, bucket) in self.trend.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&format!("{{\"start\":{},\"requests\":{},\"bots\":{}}}", millis(bucket.start), bucket.requests, bucket.bots));
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+fn ranked<K: Hash + Eq + Ord>(counts: HashMap<K, u64>, limit: usize) -> Vec<(K, u64)> {
+    let mut ranked = counts.into_iter().collect::<Vec<(K, u64)>>();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    ranked
+}
+
+/// Renders the report as plain-text tables.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        writeln!(f, "Bot traffic from {} to {} (unix time)", secs(self.range.start), secs(self.range.end))?;
+        writeln!(f, "{:>10} requests, {} by bots ({:.1}%)", self.requests, self.bots, self.bot_share() * 100.0)?;
+        let mut table = |title: &str, rows: Vec<(String, u64)>| -> fmt::Result {
+            if rows.is_empty() {
+                return Ok(());
+            }
+            writeln!(f, "\n{:>10}  {}", "count", title)?;
+            for (key, count) in rows {
+                writeln!(f, "{:>10}  {}", count, key)?;
+            }
+            Ok(())
+        };
+        table("category", self.categories.clone())?;
+        table("user-agent", self.top_user_agents.clone())?;
+        table("address", self.top_ips.iter().map(|(ip, count)| (ip.to_string(), *count)).collect())?;
+        writeln!(f, "\n{:>10}  {:>10}  {:>10}", "start", "requests", "bots")?;
+        for bucket in &self.trend {
+            writeln!(f, "{:>10}  {:>10}  {:>10}", secs(bucket.start), bucket.requests, bucket.bots)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(user_agent: &str, category: Option<&str>, score: f32, action: Action, secs: u64) -> DetectionRecord {
+        DetectionRecord {
+            user_agent: user_agent.to_string(),
+            ip: Some("203.0.113.7".parse().unwrap()),
+            score,
+            matched_patterns: category.map(|_| user_agent.to_lowercase()).into_iter().collect(),
+            category: category.map(str::to_string),
+            action,
+            timestamp: UNIX_EPOCH + Duration::from_secs(secs),
+        }
+    }
+
+    fn fill(store: &dyn VerdictStore) {
+        let records = [
+            record("Googlebot/2.1", Some("search-engines"), 1.0, Action::Allow, 10),
+            record("curl/8.0", Some("http-clients"), 0.9, Action::Block, 20),
+            record("curl/8.0", Some("http-clients"), 0.9, Action::Block, 30),
+            record("Mozilla/5.0 (X11)", None, 0.3, Action::Challenge, 40),
+            record("Mozilla/5.0 (X11)", None, 0.4, Action::Block, 50),
+            record("Mozilla/5.0 (Mac)", None, 0.2, Action::Allow, 60),
+            record("ScrapyBot", Some("scrapers"), 0.7, Action::RateLimit { requests: 10, per: Duration::from_secs(60) }, 70),
+        ];
+        for r in &records {
+            store.record(r).unwrap();
+        }
+    }
+
+    fn check_queries(store: &dyn VerdictStore) {
+        let top = store.top_bots(UNIX_EPOCH + Duration::from_secs(15)).unwrap();
+        assert_eq!(
+            top,
+            vec![
+                BotCount { user_agent: "curl/8.0".to_string(), category: Some("http-clients".to_string()), count: 2 },
+                BotCount { user_agent: "ScrapyBot".to_string(), category: Some("scrapers".to_string()), count: 1 },
+            ]
+        );
+        let candidates = store.false_positive_candidates().unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].user_agent, "Mozilla/5.0 (X11)");
+        assert_eq!(candidates[0].action, Action::Block);
+    }
+
+    #[test]
+    fn memory_store_answers_review_queries() {
+        let store = MemoryVerdictStore::new();
+        fill(&store);
+        check_queries(&store);
+        assert_eq!(store.records_since(UNIX_EPOCH + Duration::from_secs(60)).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reports_aggregate_a_range() {
+        let store = MemoryVerdictStore::new();
+        fill(&store);
+        let report = Report::generate(&store, UNIX_EPOCH + Duration::from_secs(15)..UNIX_EPOCH + Duration::from_secs(63)).unwrap();
+        assert_eq!((report.requests, report.bots), (5, 2));
+        assert_eq!(report.bot_share(), 0.4);
+        assert_eq!(report.categories, vec![("http-clients".to_string(), 2)]);
+        assert_eq!(report.top_ips, vec![("203.0.113.7".parse().unwrap(), 2)]);
+        assert_eq!(report.trend.len(), 24);
+        assert_eq!((report.trend[2].requests, report.trend[2].bots), (1, 1));
+        assert_eq!(report.trend.iter().map(|bucket| bucket.requests).sum::<u64>(), 5);
+
+        let json = report.to_json();
+        assert!(json.starts_with(r#"{"start":15000,"end":63000,"requests":5,"bots":2,"bot_share":0.4,"categories":[["http-clients",2]],"#), "{}", json);
+        assert!(crate::json::parse(&json).is_ok());
+        let text = report.to_string();
+        assert!(text.contains("5 requests, 2 by bots (40.0%)"), "{}", text);
+        assert!(text.contains("         2  curl/8.0\n"), "{}", text);
+    }
+
+    #[test]
+    fn file_store_survives_reopening() {
+        let path = std::env::temp_dir().join(format!("botguard-verdicts-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        fill(&FileVerdictStore::open(&path).unwrap());
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"user_agent\":\"cut sh").unwrap();
+
+        let store = FileVerdictStore::open(&path).unwrap();
+        check_queries(&store);
+        store.record(&record("Wget/1.21", Some("http-clients"), 0.9, Action::Tarpit { bytes_per_second: 8 }, 80)).unwrap();
+        let all = store.records_since(UNIX_EPOCH).unwrap();
+        assert_eq!(all.len(), 8);
+        assert_eq!((all[7].user_agent.as_str(), &all[7].action), ("Wget/1.21", &Action::Tarpit { bytes_per_second: 8 }));
+        assert_eq!(all[6].action, Action::RateLimit { requests: 10, per: Duration::from_secs(60) });
+        assert_eq!(all[0], record("Googlebot/2.1", Some("search-engines"), 1.0, Action::Allow, 10));
+        // a complete last line is left alone
+        let length = std::fs::metadata(&path).unwrap().len();
+        drop(FileVerdictStore::open(&path).unwrap());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), length);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+// robots.txt compliance: crawlers announce that they follow the site's robots.txt, the auditor
+// checks what they actually fetch against it and reports crawlers requesting disallowed paths or
+// coming back faster than their crawl-delay, optionally adding them to the block patterns.
+//
+// Rules follow RFC 9309: the group of the longest matching user-agent token applies, within it
+// the longest matching path rule wins and `allow` wins ties. `*` and a trailing `$` are supported
+// in paths. Only clients with a crawler-like product token or a named group of their own are
+// audited, a browser opening a disallowed page is not a violation.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use crate::{BotDetector, BotGuardError};
+
+/// Group the patterns of promoted violators are added to.
+pub const VIOLATORS_GROUP: &str = "robots-violators";
+
+/// Product token fragments marking a user-agent as a crawler.
+const CRAWLER_TOKENS: &[&str] = &["bot", "crawl", "spider", "slurp", "fetch", "scan", "archiver"];
+
+#[derive(Debug, Clone)]
+struct Rule {
+    allow: bool,
+    /// Length of the path pattern, longer patterns are more specific.
+    specificity: usize,
+    pattern: Regex,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+/// A parsed robots.txt.
+///
+/// ```
+/// use std::time::Duration;
+/// use BotGuardLib::robots::RobotsTxt;
+///
+/// let robots = RobotsTxt::parse("User-agent: *\nDisallow: /private/\nAllow: /private/press\n\nUser-agent: FooBot\nCrawl-delay: 5\nDisallow: /");
+/// assert!(!robots.is_allowed("SomeBot/1.0", "/private/x"));
+/// assert!(robots.is_allowed("SomeBot/1.0", "/private/press/2024"));
+/// assert!(!robots.is_allowed("Mozilla/5.0 (compatible; FooBot/2.1)", "/index.html"));
+/// assert_eq!(robots.crawl_delay("FooBot/2.1"), Some(Duration::from_secs(5)));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RobotsTxt {
+    groups: Vec<Group>,
+}
+
+impl RobotsTxt {
+    /// Unknown lines and invalid values are skipped, like crawlers do.
+    pub fn parse(text: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut in_agents = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push(Group::default());
+                    }
+                    in_agents = true;
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                key @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    let Some(group) = groups.last_mut() else { continue };
+                    // an empty disallow allows everything
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if let Some(pattern) = path_pattern(value) {
+                        group.rules.push(Rule { allow: key == "allow", specificity: value.len(), pattern });
+                    }
+                }
+                "crawl-delay" => {
+                    in_agents = false;
+                    let delay = value.parse::<f64>().ok().filter(|delay| delay.is_finite() && *delay >= 0.0);
+                    if let (Some(group), Some(delay)) = (groups.last_mut(), delay) {
+                        group.crawl_delay = Some(Duration::from_secs_f64(delay));
+                    }
+                }
+                _ => {}
+            }
+        }
+        RobotsTxt { groups }
+    }
+
+    /// Whether the user-agent may fetch the path, anything not disallowed is allowed.
+    pub fn is_allowed(&self, user_agent: &str, path: &str) -> bool {
+        let Some(group) = self.group(user_agent) else { return true };
+        group
+            .rules
+            .iter()
+            .filter(|rule| rule.pattern.is_match(path))
+            .max_by_key(|rule| (rule.specificity, rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+
+    /// The crawl-delay of the group applying to the user-agent.
+    pub fn crawl_delay(&self, user_agent: &str) -> Option<Duration> {
+        self.group(user_agent).and_then(|group| group.crawl_delay)
+    }
+
+    /// The agent token of the most specific group naming the user-agent, `None` for `*`.
+    fn named_agent(&self, user_agent: &str) -> Option<&str> {
+        let user_agent = user_agent.to_ascii_lowercase();
+        self.groups
+            .iter()
+            .flat_map(|group| &group.agents)
+            .filter(|agent| agent.as_str() != "*" && !agent.is_empty() && user_agent.contains(agent.as_str()))
+            .max_by_key(|agent| agent.len())
+            .map(String::as_str)
+    }
+
+    fn group(&self, user_agent: &str) -> Option<Group> {
+        let agent = self.named_agent(user_agent).unwrap_or("*");
+        // groups naming the same agent several times are combined
+        let mut matching = self.groups.iter().filter(|group| group.agents.iter().any(|a| a == agent)).peekable();
+        matching.peek()?;
+        Some(matching.fold(Group::default(), |mut combined, group| {
+            combined.rules.extend(group.rules.iter().cloned());
+            combined.crawl_delay = combined.crawl_delay.or(group.crawl_delay);
+            combined
+        }))
+    }
+}
+
+/// A robots.txt path as an anchored regular expression.
+fn path_pattern(path: &str) -> Option<Regex> {
+    let (path, anchored) = match path.strip_suffix('$') {
+        Some(path) => (path, true),
+        None => (path, false),
+    };
+    let body = path.split('*').map(regex::escape).collect::<Vec<_>>().join(".*");
+    Regex::new(&format!("^{}{}", body, if anchored { "$" } else { "" })).ok()
+}
+
+/// A request breaking the robots.txt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The path is disallowed for the crawler.
+    Disallowed { path: String },
+    /// The request came `elapsed` after the previous one, sooner than the crawl-delay.
+    CrawlDelay { required: Duration, elapsed: Duration },
+}
+
+/// What the auditor saw of one crawler, see [`RobotsAuditor::report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrawlerReport {
+    /// The robots.txt agent naming the crawler, or its product token, lowercase.
+    pub crawler: String,
+    pub requests: u64,
+    pub disallowed: u64,
+    pub crawl_delay_violations: u64,
+    /// The last few disallowed paths.
+    pub recent_paths: Vec<String>,
+}
+
+impl CrawlerReport {
+    pub fn violations(&self) -> u64 {
+        self.disallowed + self.crawl_delay_violations
+    }
+}
+
+/// Disallowed paths kept per crawler.
+const RECENT_PATHS: usize = 10;
+
+#[derive(Debug)]
+struct CrawlerState {
+    report: CrawlerReport,
+    last_request: Option<Instant>,
+}
+
+/// Checks crawler requests against a robots.txt.
+///
+/// ```
+/// use BotGuardLib::robots::{RobotsAuditor, RobotsTxt, Violation};
+/// use BotGuardLib::BotDetector;
+///
+/// let auditor = RobotsAuditor::new(RobotsTxt::parse("User-agent: *\nDisallow: /cart"));
+/// let crawler = "Mozilla/5.0 (compatible; SneakyBot/1.0)";
+/// assert_eq!(auditor.observe(crawler, "/products"), None);
+/// assert_eq!(auditor.observe(crawler, "/cart/checkout"), Some(Violation::Disallowed { path: "/cart/checkout".to_string() }));
+/// // browsers are not audited
+/// assert_eq!(auditor.observe("Mozilla/5.0 (X11; Linux x86_64) Firefox/120.0", "/cart"), None);
+///
+/// let report = auditor.report();
+/// assert_eq!((report[0].crawler.as_str(), report[0].requests, report[0].disallowed), ("sneakybot", 2, 1));
+///
+/// let mut BotDetector = BotDetector::new("");
+/// assert_eq!(auditor.promote(&mut BotDetector, 1).unwrap(), ["sneakybot"]);
+/// assert_eq!(BotDetector.classify(crawler), Some("robots-violators"));
+/// ```
+#[derive(Debug)]
+pub struct RobotsAuditor {
+    robots: RobotsTxt,
+    crawlers: Mutex<HashMap<String, CrawlerState>>,
+    max_crawlers: usize,
+}
+
+impl RobotsAuditor {
+    /// Tracks up to 10,000 crawlers.
+    pub fn new(robots: RobotsTxt) -> Self {
+        RobotsAuditor { robots, crawlers: Mutex::default(), max_crawlers: 10_000 }
+    }
+
+    /// Crawlers beyond the limit are not tracked.
+    pub fn max_crawlers(mut self, max_crawlers: usize) -> Self {
+        self.max_crawlers = max_crawlers;
+        self
+    }
+
+    /// Records a request made now, returns the violation if it broke the robots.txt. A request can
+    /// only break one rule, a disallowed path is reported over a missed crawl-delay.
+    pub fn observe(&self, user_agent: &str, path: &str) -> Option<Violation> {
+        self.observe_at(user_agent, path, Instant::now())
+    }
+
+    fn observe_at(&self, user_agent: &str, path: &str, now: Instant) -> Option<Violation> {
+        let crawler = self.crawler(user_agent)?;
+        let mut crawlers = self.crawlers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if !crawlers.contains_key(&crawler) && crawlers.len() >= self.max_crawlers {
+            return None;
+        }
+        let state = crawlers.entry(crawler.clone()).or_insert_with(|| CrawlerState {
+            report: CrawlerReport { crawler, requests: 0, disallowed: 0, crawl_delay_violations: 0, recent_paths: Vec::new() },
+            last_request: None,
+        });
+        state.report.requests += 1;
+        let previous = state.last_request.replace(now);
+
+        if !self.robots.is_allowed(user_agent, path) {
+            state.report.disallowed += 1;
+            if state.report.recent_paths.len() == RECENT_PATHS {
+                state.report.recent_paths.remove(0);
+            }
+            state.report.recent_paths.push(path.to_string());
+            return Some(Violation::Disallowed { path: path.to_string() });
+        }
+        let required = self.robots.crawl_delay(user_agent)?;
+        let elapsed = now.duration_since(previous?);
+        if elapsed < required {
+            state.report.crawl_delay_violations += 1;
+            return Some(Violation::CrawlDelay { required, elapsed });
+        }
+        None
+    }
+
+    /// Every crawler seen, the ones with the most violations first.
+    pub fn report(&self) -> Vec<CrawlerReport> {
+        let crawlers = self.crawlers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut reports = crawlers.values().map(|state| state.report.clone()).collect::<Vec<_>>();
+        reports.sort_by(|a, b| b.violations().cmp(&a.violations()).then_with(|| a.crawler.cmp(&b.crawler)));
+        reports
+    }
+
+    /// Adds a pattern for every crawler with at least `min_violations` to the [`VIOLATORS_GROUP`]
+    /// of the detector and returns the patterns added. Crawlers the detector already classifies
+    /// are left in their group.
+    pub fn promote(&self, detector: &mut BotDetector, min_violations: u64) -> Result<Vec<String>, BotGuardError> {
+        let patterns = self
+            .report()
+            .into_iter()
+            .filter(|report| report.violations() >= min_violations.max(1))
+            .map(|report| regex::escape(&report.crawler))
+            .filter(|pattern| !detector.contains_pattern(pattern) && detector.classify(pattern).is_none())
+            .collect::<Vec<_>>();
+        if !patterns.is_empty() {
+            detector.append_to_group(VIOLATORS_GROUP, &patterns.iter().map(String::as_str).collect::<Vec<_>>())?;
+        }
+        Ok(patterns)
+    }
+
+    /// The identity of an audited client: the robots.txt agent naming it, or a crawler-like
+    /// product token such as `SneakyBot` in `SneakyBot/1.0`.
+    fn crawler(&self, user_agent: &str) -> Option<String> {
+        if let Some(agent) = self.robots.named_agent(user_agent) {
+            return Some(agent.to_string());
+        }
+        user_agent
+            .split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ';' | ','))
+            .map(|token| token.split('/').next().unwrap_or("").trim_start_matches('+'))
+            .filter(|name| !name.is_empty() && !name.contains(':') && !name.contains('.'))
+            .map(str::to_ascii_lowercase)
+            .find(|name| CRAWLER_TOKENS.iter().any(|token| name.contains(token)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_rfc_9309_precedence() {
+        let robots = RobotsTxt::parse(
+            "# comment\nUser-agent: *\nDisallow: /search\nAllow: /search/about$\nDisallow: /*.pdf$\n\n\
+             User-agent: googlebot\nUser-agent: googlebot-news\nDisallow: /drafts  # no drafts\nDisallow:\n\n\
+             User-agent: googlebot\nCrawl-delay: 1.5\nUnknown: x\n",
+        );
+        assert!(!robots.is_allowed("Bingbot/2.0", "/search?q=x"));
+        assert!(robots.is_allowed("Bingbot/2.0", "/search/about"));
+        assert!(!robots.is_allowed("Bingbot/2.0", "/search/about/team"));
+        assert!(!robots.is_allowed("Bingbot/2.0", "/files/report.pdf"));
+        assert!(robots.is_allowed("Bingbot/2.0", "/files/report.pdf?download"));
+        // googlebot has a group of its own, the `*` rules do not apply
+        assert!(robots.is_allowed("Googlebot/2.1", "/search"));
+        assert!(!robots.is_allowed("Googlebot-News", "/drafts/1"));
+        assert_eq!(robots.crawl_delay("Mozilla/5.0 (compatible; Googlebot/2.1)"), Some(Duration::from_millis(1500)));
+        assert_eq!(robots.named_agent("Googlebot-News/1.0"), Some("googlebot-news"));
+        assert!(RobotsTxt::parse("").is_allowed("AnyBot", "/"));
+    }
+
+    #[test]
+    fn audits_crawl_delay_and_promotes_violators() {
+        let auditor = RobotsAuditor::new(RobotsTxt::parse("User-agent: *\nCrawl-delay: 10\nDisallow: /admin"));
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let ua = "ExampleCrawler/3.0 (+https://crawler.example/info)";
+
+        assert_eq!(auditor.observe_at(ua, "/", at(0)), None);
+        assert_eq!(auditor.observe_at(ua, "/a", at(20)), None);
+        assert_eq!(auditor.observe_at(ua, "/b", at(22)), Some(Violation::CrawlDelay { required: Duration::from_secs(10), elapsed: Duration::from_secs(2) }));
+        assert!(matches!(auditor.observe_at(ua, "/admin", at(60)), Some(Violation::Disallowed { .. })));
+        assert_eq!(auditor.observe_at("Mozilla/5.0 Googlebot/2.1", "/admin", at(60)), Some(Violation::Disallowed { path: "/admin".to_string() }));
+
+        let report = auditor.report();
+        assert_eq!(report.iter().map(|r| (r.crawler.as_str(), r.violations())).collect::<Vec<_>>(), [("examplecrawler", 2), ("googlebot", 1)]);
+        assert_eq!(report[0].recent_paths, ["/admin"]);
+
+        let mut detector = BotDetector::new("[search]\ngooglebot");
+        assert_eq!(auditor.promote(&mut detector, 2).unwrap(), ["examplecrawler"]);
+        assert_eq!(detector.classify(ua), Some(VIOLATORS_GROUP));
+        // already known crawlers keep their group and nothing is added twice
+        assert!(auditor.promote(&mut detector, 1).unwrap().is_empty());
+        assert_eq!(detector.classify("Googlebot/2.1"), Some("search"));
+    }
+}
@@ -0,0 +1,227 @@
+// Conditions for the costly signals of `BotGuard::evaluate`, so a gateway can run them on the
+// requests where they change the outcome instead of on every one: the classifier only on
+// user-agents claiming to be a browser, the fingerprint analyzer only on requests already
+// suspicious, the rate limit only against part of the traffic.
+//
+// A signal without a gate always runs. A gate lets the signal run when every condition it has
+// holds; `min_score` compares with the score of the checks run before, see `crate::pipeline`
+// for their order. Sampling is per request, not per client, so a client skipped once is checked on a
+// later request; the sequence is fixed for a `Sampling`, repeated runs of a test agree.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use regex::{RegexSet, RegexSetBuilder};
+
+use crate::policy::glob_match;
+use crate::request::RequestSnapshot;
+use crate::BotGuardError;
+
+/// A signal [`Sampling`] can skip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    /// The trained model of the `classifier` feature, for user-agents no pattern matches.
+    Classifier,
+    /// [`crate::request::BotGuard::fingerprint_analyzer`].
+    Fingerprint,
+    /// [`crate::request::BotGuard::ip_reputation`].
+    Reputation,
+    /// [`crate::request::BotGuard::rate_limit`]. Every request is counted whatever the gate, so the
+    /// limit holds for clients the gate mostly skips; it only decides whether exceeding the limit
+    /// is held against a request.
+    RateLimit,
+}
+
+impl Signal {
+    pub const ALL: [Signal; 4] = [Signal::Classifier, Signal::Fingerprint, Signal::Reputation, Signal::RateLimit];
+
+    /// The name in configuration files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::Classifier => "classifier",
+            Signal::Fingerprint => "fingerprint",
+            Signal::Reputation => "reputation",
+            Signal::RateLimit => "rate_limit",
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Signal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Signal::ALL.into_iter().find(|signal| signal.name() == s).ok_or_else(|| format!("unknown signal {:?}", s))
+    }
+}
+
+/// When a signal runs.
+#[derive(Debug, Clone)]
+pub struct SignalGate {
+    rate: f64,
+    min_score: Option<f32>,
+    user_agents: Option<RegexSet>,
+    paths: Vec<String>,
+}
+
+impl Default for SignalGate {
+    fn default() -> Self {
+        SignalGate { rate: 1.0, min_score: None, user_agents: None, paths: Vec::new() }
+    }
+}
+
+impl SignalGate {
+    /// Lets every request through until conditions are added.
+    pub fn new() -> Self {
+        SignalGate::default()
+    }
+
+    /// Runs the signal for this share of the requests meeting the other conditions, `0.1` for
+    /// one in ten. Clamped to `0.0..=1.0`.
+    pub fn sample(mut self, rate: f64) -> Self {
+        self.rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Runs the signal once the earlier signals scored the request at least `score`.
+    pub fn min_score(mut self, score: f32) -> Self {
+        self.min_score = Some(score);
+        self
+    }
+
+    /// Runs the signal for user-agents matching one of the case-insensitive regexes, e.g.
+    /// `googlebot` to only look closer at clients claiming to be Google's crawler.
+    pub fn user_agents(mut self, patterns: &[&str]) -> Result<Self, BotGuardError> {
+        let set = RegexSetBuilder::new(patterns)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| BotGuardError::InvalidPattern { pattern: patterns.join("|"), reason: e.to_string() })?;
+        self.user_agents = Some(set);
+        Ok(self)
+    }
+
+    /// Runs the signal for paths matching one of the globs, as in route policies.
+    pub fn paths(mut self, globs: &[&str]) -> Self {
+        self.paths = globs.iter().map(|glob| glob.to_string()).collect();
+        self
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Whether the conditions other than the sample rate hold.
+    pub fn applies(&self, request: &RequestSnapshot, score: f32) -> bool {
+        self.min_score.is_none_or(|min| score >= min)
+            && self.user_agents.as_ref().is_none_or(|set| set.is_match(request.effective_user_agent()))
+            && (self.paths.is_empty() || self.paths.iter().any(|glob| glob_match(glob, &request.path)))
+    }
+}
+
+/// Gates by signal, see [`crate::request::BotGuard::sampling`].
+///
+/// ```
+/// use BotGuardLib::request::RequestSnapshot;
+/// use BotGuardLib::sampling::{Sampling, Signal, SignalGate};
+///
+/// let sampling = Sampling::new().gate(Signal::Fingerprint, SignalGate::new().min_score(0.5));
+/// let request = RequestSnapshot::new("GET", "/").user_agent("curl/8.4.0");
+/// assert!(!sampling.runs(Signal::Fingerprint, &request, 0.2));
+/// assert!(sampling.runs(Signal::Fingerprint, &request, 0.6));
+/// assert!(sampling.runs(Signal::Reputation, &request, 0.0));
+/// ```
+#[derive(Debug, Default)]
+pub struct Sampling {
+    gates: Vec<(Signal, SignalGate)>,
+    requests: AtomicU64,
+}
+
+impl Clone for Sampling {
+    fn clone(&self) -> Self {
+        Sampling { gates: self.gates.clone(), requests: AtomicU64::new(self.requests.load(Ordering::Relaxed)) }
+    }
+}
+
+impl Sampling {
+    /// Runs every signal.
+    pub fn new() -> Self {
+        Sampling::default()
+    }
+
+    /// Replaces the gate of `signal`.
+    pub fn gate(mut self, signal: Signal, gate: SignalGate) -> Self {
+        self.gates.retain(|(gated, _)| *gated != signal);
+        self.gates.push((signal, gate));
+        self
+    }
+
+    pub fn get(&self, signal: Signal) -> Option<&SignalGate> {
+        self.gates.iter().find(|(gated, _)| *gated == signal).map(|(_, gate)| gate)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.gates.is_empty()
+    }
+
+    /// Whether `signal` runs for the request, `score` being what the signals before it found.
+    /// Only requests meeting the conditions count towards the sample.
+    pub fn runs(&self, signal: Signal, request: &RequestSnapshot, score: f32) -> bool {
+        let Some(gate) = self.get(signal) else { return true };
+        if !gate.applies(request, score) {
+            return false;
+        }
+        gate.rate >= 1.0 || (gate.rate > 0.0 && self.draw() < gate.rate)
+    }
+
+    fn draw(&self) -> f64 {
+        uniform(self.requests.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Uniform in `0.0..1.0`, element `n` of the splitmix64 sequence.
+pub(crate) fn uniform(n: u64) -> f64 {
+    let mut x = n.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gates_combine_their_conditions() {
+        let gate = SignalGate::new().user_agents(&["googlebot"]).unwrap().paths(&["/search/*"]).min_score(0.3);
+        let sampling = Sampling::new().gate(Signal::Reputation, gate);
+        let crawler = RequestSnapshot::new("GET", "/search/shoes").user_agent("Mozilla/5.0 (compatible; Googlebot/2.1)");
+        assert!(sampling.runs(Signal::Reputation, &crawler, 0.3));
+        assert!(!sampling.runs(Signal::Reputation, &crawler, 0.1));
+        assert!(!sampling.runs(Signal::Reputation, &RequestSnapshot { path: "/".to_string(), ..crawler.clone() }, 0.3));
+        assert!(!sampling.runs(Signal::Reputation, &RequestSnapshot::new("GET", "/search/shoes").user_agent("curl/8.4.0"), 0.3));
+        assert!(SignalGate::new().user_agents(&["("]).is_err());
+        assert_eq!("rate_limit".parse::<Signal>(), Ok(Signal::RateLimit));
+        assert!("dns".parse::<Signal>().is_err());
+    }
+
+    #[test]
+    fn samples_a_share_of_the_requests() {
+        let sampling = Sampling::new().gate(Signal::Classifier, SignalGate::new().sample(0.25)).gate(Signal::RateLimit, SignalGate::new().sample(0.0));
+        let request = RequestSnapshot::new("GET", "/");
+        let ran = (0..4000).filter(|_| sampling.runs(Signal::Classifier, &request, 0.0)).count();
+        assert!((800..1200).contains(&ran), "{}", ran);
+        assert!(!(0..100).any(|_| sampling.runs(Signal::RateLimit, &request, 1.0)));
+        let replay = sampling.clone();
+        assert_eq!(
+            (0..50).map(|_| sampling.runs(Signal::Classifier, &request, 0.0)).collect::<Vec<_>>(),
+            (0..50).map(|_| replay.runs(Signal::Classifier, &request, 0.0)).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,64 @@
+// Weak signals in user-agents no pattern matched, scored below any confirmed match, and the
+// thresholds turning scores into verdicts.
+
+use crate::Verdict;
+
+/// Score thresholds separating the verdict tiers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    /// Scores from this value on are suspicious.
+    pub suspicious: f32,
+    /// Scores from this value on are bots.
+    pub bot: f32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds { suspicious: 0.5, bot: 0.8 }
+    }
+}
+
+impl Thresholds {
+    /// The tier a score like the one of `BotDetector::score` falls into.
+    pub fn verdict(&self, score: f32) -> Verdict {
+        if score >= self.bot {
+            Verdict::Bot
+        } else if score >= self.suspicious {
+            Verdict::Suspicious
+        } else {
+            Verdict::Human
+        }
+    }
+}
+
+/// Score of a user-agent that looks like a tool, e.g. `foo/1.0` or `Java`.
+const TOOL_LIKE: f32 = 0.4;
+
+/// Score of a user-agent that claims to be a browser but carries none of the usual details.
+const BARE_BROWSER: f32 = 0.3;
+
+/// Scores an already normalized user-agent that no pattern matched.
+pub(crate) fn heuristic(user_agent: &str) -> f32 {
+    let user_agent = user_agent.trim();
+    let claims_browser = user_agent.get(..8).is_some_and(|prefix| prefix.eq_ignore_ascii_case("mozilla/"));
+    if !claims_browser && !user_agent.contains(' ') {
+        TOOL_LIKE
+    } else if claims_browser && !user_agent.contains('(') {
+        BARE_BROWSER
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_weak_signals() {
+        assert_eq!(heuristic("mozilla/5.0 (x11; linux x86_64; rv:120.0) gecko/20100101 firefox/120.0"), 0.0);
+        assert_eq!(heuristic("okhttp/4.9.0"), TOOL_LIKE);
+        assert_eq!(heuristic("Mozilla/5.0"), BARE_BROWSER);
+        assert_eq!(heuristic("dalvik/2.1.0 (linux; u; android 8.0.0)"), 0.0);
+    }
+}
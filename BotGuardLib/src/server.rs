@@ -0,0 +1,700 @@
+// A small HTTP/1.1 service exposing the detector to deployments that cannot embed Rust, run as a
+// sidecar with the `botguard-server` binary.
+//
+// POST /check    {"user_agent": "..", "ip": "..", "referrer": "..", "headers": {"user-agent": ".."}} -> verdict JSON
+// POST /reload   re-reads the pattern file right away, with `Server::admin` only with the admin token
+// GET  /metrics  counters in the Prometheus text format
+// GET  /healthz  liveness probe
+//
+// With `Server::admin` the routes of `admin::AdminHandle` are served under `/admin/` as well, and
+// the checks use the detector of the handle's guard, so an admin reload changes their verdicts.
+//
+// With the `grpc` feature and `Server::grpc` the same checks are answered by the `Check` method
+// of the gRPC service in `proto/botguard.proto` on a second listener, see `grpc`.
+//
+// Every connection is served by its own thread and closed after one response. At most `Server::max_connections` are served at the same time, further
+// ones wait in the listen backlog. A peer address with more connections open than the
+// `ConcurrencyGuard` allows is answered 429 before its request is read, and a client has
+// `Server::request_timeout` to send the whole request, however slowly it trickles in.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::{IpAddr, Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::actions::Response;
+use crate::admin::AdminHandle;
+use crate::concurrency::ConcurrencyGuard;
+use crate::config::{Allowlist, BotGuardConfig};
+use crate::request::BotGuard;
+use crate::source::{self, block_on, FileSource, PatternRefresher};
+use crate::{json, BotDetector, BotGuardError, Verdict};
+
+/// Requests with larger bodies are rejected.
+const MAX_BODY: usize = 64 * 1024;
+/// Requests with a larger head (request line and headers) are rejected.
+const MAX_HEAD: usize = 16 * 1024;
+/// Connections of one peer address served at the same time, unless [`Server::concurrency`] is set.
+const PEER_CONNECTIONS: usize = 32;
+/// Connections served at the same time, unless [`Server::max_connections`] is set.
+const MAX_CONNECTIONS: usize = 512;
+/// Time to send the whole request, unless [`Server::request_timeout`] is set.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+struct Metrics {
+    checks: [AtomicU64; 3],
+    allowlisted: AtomicU64,
+    bad_requests: AtomicU64,
+    concurrency_rejected: AtomicU64,
+    reloads: AtomicU64,
+    reload_failures: AtomicU64,
+}
+
+/// The detection service, see the [module documentation](self).
+///
+/// It speaks HTTP/1.1 and serves the REST routes, with the `grpc` feature [`Server::grpc`] adds
+/// the gRPC `Check` method on a listener of its own.
+///
+/// ```no_run
+/// use std::net::TcpListener;
+/// use std::time::Duration;
+/// use BotGuardLib::server::Server;
+/// use BotGuardLib::source::FileSource;
+/// use BotGuardLib::BotDetector;
+///
+/// let server = Server::new(BotDetector::default())
+///     .reload_from(FileSource::new("/etc/botguard/bots.rgx"), Duration::from_secs(60));
+/// server.serve(TcpListener::bind("127.0.0.1:8080").unwrap()).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Server {
+    /// Only the detector of the guard is used, the guard is what an [`AdminHandle`] administers.
+    guard: Arc<RwLock<BotGuard>>,
+    allowlist: Allowlist,
+    source: Option<(FileSource, Duration)>,
+    concurrency: ConcurrencyGuard,
+    max_connections: usize,
+    /// Connection threads running, at most `max_connections`.
+    connections: (Mutex<usize>, Condvar),
+    request_timeout: Duration,
+    admin: Option<AdminHandle>,
+    #[cfg(feature = "grpc")]
+    grpc: Option<TcpListener>,
+    metrics: Metrics,
+}
+
+impl Server {
+    pub fn new(detector: BotDetector) -> Self {
+        Server {
+            guard: Arc::new(RwLock::new(BotGuard::new(detector))),
+            allowlist: Allowlist::default(),
+            source: None,
+            concurrency: ConcurrencyGuard::new(PEER_CONNECTIONS),
+            max_connections: MAX_CONNECTIONS,
+            connections: (Mutex::new(0), Condvar::new()),
+            request_timeout: REQUEST_TIMEOUT,
+            admin: None,
+            #[cfg(feature = "grpc")]
+            grpc: None,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// A server with the detector and allowlist of a configuration.
+    pub fn from_config(config: &BotGuardConfig) -> Result<Self, BotGuardError> {
+        Ok(Server { allowlist: config.allowlist.clone(), ..Server::new(config.detector()?) })
+    }
+
+    /// Clients on the allowlist are always judged human.
+    pub fn allowlist(mut self, allowlist: Allowlist) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    /// Reloads the patterns from a file every `interval` and on `POST /reload`.
+    pub fn reload_from(mut self, source: FileSource, interval: Duration) -> Self {
+        self.source = Some((source, interval));
+        self
+    }
+
+    /// Limits the connections served at the same time, per peer address and in total, connections
+    /// over the limit are answered 429. Without it a peer address gets 32 connections at a time.
+    pub fn concurrency(mut self, guard: ConcurrencyGuard) -> Self {
+        self.concurrency = guard;
+        self
+    }
+
+    /// Connections served at the same time, each by its own thread, 512 unless set. Further
+    /// connections are only accepted once one of them is closed.
+    pub fn max_connections(mut self, connections: usize) -> Self {
+        self.max_connections = connections.max(1);
+        self
+    }
+
+    /// Time a client has from connecting to sending the end of its request, 10 seconds unless
+    /// set. Slower clients are answered 400 and disconnected.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Serves the admin routes of a guard under `/admin/`. From then on the server checks with
+    /// the detector of that guard instead of the one it was built with, and reloads it from
+    /// [`Server::reload_from`]. Fails if the handle has no [`AdminHandle::token`], the routes
+    /// would be open to anyone reaching the server.
+    ///
+    /// ```
+    /// use std::sync::{Arc, RwLock};
+    /// use BotGuardLib::admin::AdminHandle;
+    /// use BotGuardLib::request::BotGuard;
+    /// use BotGuardLib::server::Server;
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let guard = Arc::new(RwLock::new(BotGuard::new(BotDetector::default())));
+    /// assert!(Server::new(BotDetector::default()).admin(AdminHandle::new(Arc::clone(&guard))).is_err());
+    /// let server = Server::new(BotDetector::default()).admin(AdminHandle::new(guard).token("a long random admin token")).unwrap();
+    /// ```
+    pub fn admin(mut self, admin: AdminHandle) -> Result<Self, BotGuardError> {
+        if !admin.has_token() {
+            return Err(BotGuardError::InvalidConfig { line: None, reason: "the admin routes of the server need a token".to_string() });
+        }
+        self.guard = Arc::clone(admin.guard());
+        self.admin = Some(admin);
+        Ok(self)
+    }
+
+    /// Answers the gRPC `Check` method on a listener of its own as well, see [`crate::grpc`].
+    /// It is served while [`Server::serve`] runs, with the same allowlist, detector and metrics.
+    ///
+    /// ```no_run
+    /// use std::net::TcpListener;
+    /// use BotGuardLib::server::Server;
+    /// use BotGuardLib::BotDetector;
+    ///
+    /// let server = Server::new(BotDetector::default()).grpc(TcpListener::bind("127.0.0.1:50051").unwrap());
+    /// server.serve(TcpListener::bind("127.0.0.1:8080").unwrap()).unwrap();
+    /// ```
+    #[cfg(feature = "grpc")]
+    pub fn grpc(mut self, listener: TcpListener) -> Self {
+        self.grpc = Some(listener);
+        self
+    }
+
+    /// Accepts connections until the listener fails.
+    pub fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let _refresher = match &self.source {
+            Some((source, interval)) => Some(PatternRefresher::spawn_shared(source.clone(), Arc::clone(&self.guard), *interval)?),
+            None => None,
+        };
+        let server = Arc::new(self);
+        // the gRPC endpoint stops when this is dropped
+        #[cfg(feature = "grpc")]
+        let _grpc = match &server.grpc {
+            Some(listener) => Some(crate::grpc::Endpoint::bind(listener)?.spawn(Arc::clone(&server), server.request_timeout)?),
+            None => None,
+        };
+        let server = &*server;
+        thread::scope(|scope| loop {
+            let slot = server.connection_slot();
+            let (stream, _) = listener.accept()?;
+            // a failed spawn only loses this connection
+            let _ = thread::Builder::new().name("botguard-conn".to_string()).spawn_scoped(scope, move || {
+                let _slot = slot;
+                server.serve_connection(stream)
+            });
+        })
+    }
+
+    /// Waits until fewer than `max_connections` connections are served.
+    fn connection_slot(&self) -> ConnectionSlot<'_> {
+        let (running, released) = &self.connections;
+        let mut running = running.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        while *running >= self.max_connections {
+            running = released.wait(running).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        *running += 1;
+        ConnectionSlot(&self.connections)
+    }
+
+    fn serve_connection(&self, stream: TcpStream) {
+        let deadline = Instant::now() + self.request_timeout;
+        let _ = stream.set_write_timeout(Some(Duration::from_secs(10)));
+        let peer = stream.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+        let _permit = match self.concurrency.acquire(&peer) {
+            Ok(permit) => permit,
+            Err(exceeded) => {
+                self.metrics.concurrency_rejected.fetch_add(1, Ordering::Relaxed);
+                let response = Response::new(429, "application/json", error_json(&exceeded.to_string())).header("Retry-After", "1");
+                let _ = response.write_to(&stream);
+                // closing with the request unread would reset the connection before the client
+                // reads the answer
+                let _ = stream.shutdown(Shutdown::Write);
+                let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+                let _ = io::copy(&mut (&stream).take(MAX_HEAD as u64), &mut io::sink());
+                return;
+            }
+        };
+        let (status, content_type, body) = match read_request(&stream, deadline) {
+            Ok(request) => match &self.admin {
+                Some(admin) if request.path.starts_with("/admin/") => {
+                    let _ = admin.handle(&request.method, &request.path, request.authorization.as_deref(), &request.body).write_to(&stream);
+                    return;
+                }
+                // reloading is administration as well once the server is administered
+                Some(admin) if request.path == "/reload" => match admin.authorize(request.authorization.as_deref()) {
+                    Ok(()) => self.handle(&request.method, &request.path, &request.body),
+                    Err(unauthorized) => {
+                        let _ = unauthorized.write_to(&stream);
+                        return;
+                    }
+                },
+                _ => self.handle(&request.method, &request.path, &request.body),
+            },
+            Err(e) => {
+                self.count_bad_request();
+                (400, "application/json", error_json(&e.to_string()))
+            }
+        };
+        let _ = write_response(&stream, status, content_type, &body);
+    }
+
+    /// Answers a request with its status, content type and body.
+    fn handle(&self, method: &str, path: &str, body: &[u8]) -> (u16, &'static str, String) {
+        match (method, path) {
+            ("POST", "/check") => match self.check(body) {
+                Ok(response) => (200, "application/json", response),
+                Err(reason) => {
+                    self.count_bad_request();
+                    (400, "application/json", error_json(&reason))
+                }
+            },
+            ("POST", "/reload") => self.reload(),
+            ("GET", "/metrics") => (200, "text/plain; version=0.0.4", self.metrics()),
+            ("GET", "/healthz") => (200, "text/plain", "ok\n".to_string()),
+            (_, "/check" | "/reload" | "/metrics" | "/healthz") => (405, "application/json", error_json("method not allowed")),
+            _ => (404, "application/json", error_json("not found")),
+        }
+    }
+
+    /// Answers the JSON body of `POST /check`.
+    fn check(&self, body: &[u8]) -> Result<String, String> {
+        let request = json::parse(std::str::from_utf8(body).map_err(|_| "body is not UTF-8".to_string())?)?;
+        let header = |name: &str| match request.get("headers") {
+            Some(json::Value::Object(headers)) => headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .and_then(|(_, value)| value.as_str()),
+            _ => None,
+        };
+        let user_agent = match request.get("user_agent") {
+            Some(value) => value.as_str().ok_or("user_agent must be a string")?,
+            None => header("user-agent").unwrap_or(""),
+        };
+        let referrer = match request.get("referrer") {
+            Some(value) => Some(value.as_str().ok_or("referrer must be a string")?),
+            None => header("referer"),
+        };
+        let ip = match request.get("ip") {
+            Some(value) => {
+                let ip = value.as_str().ok_or("ip must be a string")?;
+                Some(ip.parse::<IpAddr>().map_err(|_| format!("invalid ip {:?}", ip))?)
+            }
+            None => None,
+        };
+
+        let decision = self.decide(&Check { user_agent, ip, referrer });
+        let mut out = String::from("{\"verdict\":");
+        json::push_str(&mut out, verdict_name(decision.verdict));
+        let _ = write!(out, ",\"is_bot\":{},\"allowlisted\":{},\"category\":", decision.verdict.is_bot(), decision.allowlisted);
+        json::push_opt_str(&mut out, decision.category.as_deref());
+        out.push_str(",\"version\":");
+        json::push_str(&mut out, &decision.version);
+        out.push_str(",\"referrer_spam\":");
+        json::push_opt_str(&mut out, decision.referrer_spam.as_deref());
+        out.push('}');
+        Ok(out)
+    }
+
+    /// Decides a check for either endpoint and counts it.
+    pub(crate) fn decide(&self, check: &Check<'_>) -> Decision {
+        let allowlisted = self.allowlist.allows_user_agent(check.user_agent) || check.ip.is_some_and(|ip| self.allowlist.allows_ip(ip));
+        let guard = self.guard.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let detector = guard.detector();
+        let (verdict, category) = if allowlisted {
+            self.metrics.allowlisted.fetch_add(1, Ordering::Relaxed);
+            (Verdict::Human, None)
+        } else {
+            (detector.detect(check.user_agent, check.ip), detector.classify_untraced(check.user_agent))
+        };
+        self.metrics.checks[verdict as usize].fetch_add(1, Ordering::Relaxed);
+        Decision {
+            verdict,
+            allowlisted,
+            category: category.map(str::to_string),
+            version: detector.current_version().version.clone(),
+            referrer_spam: check.referrer.and_then(|referrer| detector.check_referrer(referrer)).map(|issue| issue.to_string()),
+        }
+    }
+
+    /// Counts a check that could not be decided, like one with an invalid address.
+    pub(crate) fn count_bad_request(&self) {
+        self.metrics.bad_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn reload(&self) -> (u16, &'static str, String) {
+        let Some((source, _)) = &self.source else {
+            return (409, "application/json", error_json("no pattern file configured"));
+        };
+        match block_on(source::refresh_shared(source, &*self.guard, None)) {
+            Ok(_) => {
+                self.metrics.reloads.fetch_add(1, Ordering::Relaxed);
+                let guard = self.guard.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+                let detector = guard.detector();
+                let mut out = String::from("{\"version\":");
+                json::push_str(&mut out, &detector.current_version().version);
+                let _ = write!(out, ",\"patterns\":{}}}", detector.len());
+                (200, "application/json", out)
+            }
+            Err(e) => {
+                self.metrics.reload_failures.fetch_add(1, Ordering::Relaxed);
+                (500, "application/json", error_json(&e.to_string()))
+            }
+        }
+    }
+
+    fn metrics(&self) -> String {
+        let guard = self.guard.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let detector = guard.detector();
+        let mut out = String::from("# TYPE botguard_checks_total counter\n");
+        for verdict in [Verdict::Human, Verdict::Suspicious, Verdict::Bot] {
+            let count = self.metrics.checks[verdict as usize].load(Ordering::Relaxed);
+            let _ = writeln!(out, "botguard_checks_total{{verdict=\"{}\"}} {}", verdict_name(verdict), count);
+        }
+        let counters = [
+            ("botguard_allowlisted_total", &self.metrics.allowlisted),
+            ("botguard_bad_requests_total", &self.metrics.bad_requests),
+            ("botguard_concurrency_rejected_total", &self.metrics.concurrency_rejected),
+            ("botguard_reloads_total", &self.metrics.reloads),
+            ("botguard_reload_failures_total", &self.metrics.reload_failures),
+        ];
+        for (name, counter) in counters {
+            let _ = writeln!(out, "# TYPE {} counter\n{} {}", name, name, counter.load(Ordering::Relaxed));
+        }
+        let _ = writeln!(out, "# TYPE botguard_patterns gauge\nbotguard_patterns {}", detector.len());
+        let _ = writeln!(out, "# TYPE botguard_pattern_version gauge\nbotguard_pattern_version{{version=\"{}\"}} 1", detector.current_version().version.replace(['"', '\\', '\n'], "_"));
+        out
+    }
+}
+
+/// What a check asks about, from the body of `POST /check` or a gRPC `CheckRequest`.
+pub(crate) struct Check<'a> {
+    pub user_agent: &'a str,
+    pub ip: Option<IpAddr>,
+    pub referrer: Option<&'a str>,
+}
+
+/// The answer to a [`Check`].
+pub(crate) struct Decision {
+    pub verdict: Verdict,
+    pub allowlisted: bool,
+    pub category: Option<String>,
+    pub version: String,
+    pub referrer_spam: Option<String>,
+}
+
+fn verdict_name(verdict: Verdict) -> &'static str {
+    match verdict {
+        Verdict::Human => "human",
+        Verdict::Suspicious => "suspicious",
+        Verdict::Bot => "bot",
+    }
+}
+
+fn error_json(reason: &str) -> String {
+    let mut out = String::from("{\"error\":");
+    json::push_str(&mut out, reason);
+    out.push('}');
+    out
+}
+
+/// A connection thread counted in [`Server::connection_slot`], released when dropped.
+struct ConnectionSlot<'a>(&'a (Mutex<usize>, Condvar));
+
+impl Drop for ConnectionSlot<'_> {
+    fn drop(&mut self) {
+        let (running, released) = self.0;
+        *running.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) -= 1;
+        released.notify_one();
+    }
+}
+
+/// Reads from a stream until a deadline, however many reads it takes.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timed_out = || io::Error::new(io::ErrorKind::TimedOut, "request not received in time");
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(timed_out());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        match self.stream.read(buf) {
+            // how an expired read timeout is reported differs between platforms
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Err(timed_out()),
+            read => read,
+        }
+    }
+}
+
+/// What the server reads of a request.
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Reads the method, path, `Authorization` header and body of a request sent before the deadline.
+fn read_request(stream: &TcpStream, deadline: Instant) -> io::Result<Request> {
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+    let mut reader = BufReader::new(DeadlineReader { stream, deadline }.take(MAX_HEAD as u64));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else { return Err(invalid("malformed request line")) };
+    let (method, path) = (method.to_string(), target.split('?').next().unwrap_or_default().to_string());
+
+    let (mut content_length, mut authorization) = (0, None);
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("request head too large or incomplete"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| invalid("invalid content-length"))?;
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(invalid("body too large"));
+    }
+    // the head limit no longer applies to the body
+    let buffered = reader.buffer().to_vec();
+    let mut body = buffered[..buffered.len().min(content_length)].to_vec();
+    let mut rest = reader.into_inner().into_inner().take((content_length - body.len()) as u64);
+    rest.read_to_end(&mut body)?;
+    if body.len() != content_length {
+        return Err(invalid("incomplete body"));
+    }
+    Ok(Request { method, path, authorization, body })
+}
+
+fn write_response(stream: &TcpStream, status: u16, content_type: &str, body: &str) -> io::Result<()> {
+    Response::new(status, content_type, body.to_string()).write_to(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    use crate::ip::IpNet;
+
+    #[test]
+    fn answers_checks() {
+        let allowlist = Allowlist::new(&["^kube-probe/"], &["10.0.0.0/8".parse::<IpNet>().unwrap()]).unwrap();
+        let server = Server::new(BotDetector::new("[scrapers]\ncurl/")).allowlist(allowlist);
+        let check = |body: &str| server.handle("POST", "/check", body.as_bytes());
+
+        let (status, _, body) = check(r#"{"user_agent": "curl/8.0", "ip": "203.0.113.7"}"#);
+        assert_eq!(status, 200);
+        assert!(body.starts_with(r#"{"verdict":"bot","is_bot":true,"allowlisted":false,"category":"scrapers","version":""#), "{}", body);
+        assert!(check(r#"{"headers": {"User-Agent": "curl/8.0"}}"#).2.contains(r#""verdict":"bot""#));
+        assert!(check(r#"{"user_agent": "curl/8.0", "ip": "10.1.2.3"}"#).2.contains(r#""allowlisted":true"#));
+        assert!(check(r#"{"user_agent": "Kube-Probe/1.29"}"#).2.contains(r#""verdict":"human""#));
+        let spam = check(r#"{"user_agent": "Mozilla/5.0", "headers": {"Referer": "http://semalt.com/"}}"#).2;
+        assert!(spam.ends_with(r#""referrer_spam":"referrer spam domain semalt.com"}"#), "{}", spam);
+        assert!(body.ends_with(r#""referrer_spam":null}"#), "{}", body);
+        assert_eq!(check(r#"{"user_agent": "x", "ip": "nope"}"#).0, 400);
+        assert_eq!(check("[").0, 400);
+
+        assert_eq!(server.handle("GET", "/check", b"").0, 405);
+        assert_eq!(server.handle("GET", "/nope", b"").0, 404);
+        assert_eq!(server.handle("POST", "/reload", b"").0, 409);
+        let metrics = server.handle("GET", "/metrics", b"").2;
+        assert!(metrics.contains("botguard_checks_total{verdict=\"bot\"} 2\n"), "{}", metrics);
+        assert!(metrics.contains("botguard_allowlisted_total 2\n"));
+        assert!(metrics.contains("botguard_bad_requests_total 2\n"));
+    }
+
+    #[test]
+    fn serves_http_and_reloads_patterns() {
+        let path = std::env::temp_dir().join(format!("botguard-server-{}.rgx", std::process::id()));
+        std::fs::write(&path, "curl/").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new(BotDetector::new("")).reload_from(FileSource::new(&path), Duration::from_secs(3600));
+        thread::spawn(move || server.serve(listener));
+
+        let request = |raw: String| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(raw.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let post = |path: &str, body: &str| request(format!("POST {} HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n{}", path, body.len(), body));
+
+        // the refresher loads the file in the background right away
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !post("/check", r#"{"user_agent":"curl/8.0"}"#).contains(r#""verdict":"bot""#) {
+            assert!(std::time::Instant::now() < deadline, "patterns were not loaded");
+            thread::sleep(Duration::from_millis(10));
+        }
+        std::fs::write(&path, "wget/").unwrap();
+        let reloaded = post("/reload", "");
+        assert!(reloaded.starts_with("HTTP/1.1 200 OK\r\n"), "{}", reloaded);
+        assert!(post("/check", r#"{"user_agent":"curl/8.0"}"#).contains(r#""verdict":"human""#));
+        assert!(request("GET /healthz HTTP/1.1\r\n\r\n".to_string()).ends_with("\r\n\r\nok\n"));
+        assert!(request("garbage\r\n\r\n".to_string()).starts_with("HTTP/1.1 400 Bad Request"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn serves_admin_routes() {
+        use crate::request::BotGuard;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let admin = AdminHandle::new(Arc::new(RwLock::new(BotGuard::new(BotDetector::new("^curl/"))))).token("secret");
+        thread::spawn(move || Server::new(BotDetector::new("")).admin(admin).unwrap().serve(listener));
+        let request = |raw: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(raw.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let shadow = request("POST /admin/shadow HTTP/1.1\r\nAuthorization: Bearer secret\r\nContent-Length: 16\r\n\r\n{\"enabled\":true}");
+        assert!(shadow.ends_with(r#"{"shadow":true,"previous":false}"#), "{}", shadow);
+        assert!(request("GET /admin/stats HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        assert!(request("POST /reload HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        assert!(request("POST /reload HTTP/1.1\r\nAuthorization: Bearer wrong\r\n\r\n").starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        // authorized, but without a pattern file to reload
+        assert!(request("POST /reload HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n").starts_with("HTTP/1.1 409 "));
+        assert!(request("GET /healthz HTTP/1.1\r\n\r\n").ends_with("\r\n\r\nok\n"));
+    }
+
+    #[test]
+    fn checks_with_the_detector_the_admin_reloads() {
+        use crate::request::BotGuard;
+
+        let path = std::env::temp_dir().join(format!("botguard-server-admin-{}.toml", std::process::id()));
+        std::fs::write(&path, "[detector]\ndefault_patterns = false\n\n[groups]\ntools = [\"^curl/\"]\n").unwrap();
+        let admin = AdminHandle::new(Arc::new(RwLock::new(BotGuard::new(BotDetector::new("^wget/"))))).config_path(&path);
+        assert!(Server::new(BotDetector::new("")).admin(admin.clone()).is_err());
+
+        let server = Server::new(BotDetector::new("")).admin(admin.clone().token("secret")).unwrap();
+        let check = |user_agent: &str| server.handle("POST", "/check", format!(r#"{{"user_agent": "{}"}}"#, user_agent).as_bytes()).2;
+        assert!(check("Wget/1.21").contains(r#""verdict":"bot""#));
+        assert!(check("curl/8.0").contains(r#""verdict":"human""#));
+        admin.reload().unwrap();
+        assert!(check("curl/8.0").contains(r#""verdict":"bot""#));
+        assert!(check("Wget/1.21").contains(r#""verdict":"human""#));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_parallel_connections_over_the_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || Server::new(BotDetector::new("")).concurrency(ConcurrencyGuard::new(1)).serve(listener));
+
+        // an idle connection holds the only slot of the address while its request is awaited
+        let idle = TcpStream::connect(addr).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let mut response = String::new();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /healthz HTTP/1.1\r\n\r\n").unwrap();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 429 Too Many Requests\r\n"), "{}", response);
+        assert!(response.contains("\r\nRetry-After: 1\r\n"), "{}", response);
+        assert!(response.ends_with(r#"{"error":"more than 1 parallel requests from the client"}"#), "{}", response);
+
+        drop(idle);
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        loop {
+            let mut response = String::new();
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"GET /healthz HTTP/1.1\r\n\r\n").unwrap();
+            stream.read_to_string(&mut response).unwrap();
+            if response.ends_with("\r\n\r\nok\n") {
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "slot was not released");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn disconnects_clients_sending_too_slowly() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || Server::new(BotDetector::new("")).request_timeout(Duration::from_millis(300)).serve(listener));
+
+        // every byte arrives well within a read timeout, the request as a whole does not
+        let started = std::time::Instant::now();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        thread::spawn(move || {
+            for byte in b"GET /healthz HTTP/1.1\r\nX-Slow: ".iter().chain(b"a".repeat(100).iter()) {
+                if writer.write_all(&[*byte]).is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+        // bytes still trickling in when the server gives up may reset the connection
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        assert!(started.elapsed() < Duration::from_millis(1500), "{:?}", started.elapsed());
+        assert!(response.is_empty() || response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", response);
+    }
+
+    #[test]
+    fn serves_at_most_max_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || Server::new(BotDetector::new("")).max_connections(1).serve(listener));
+
+        // the second connection is only accepted once the first one is closed
+        let idle = TcpStream::connect(addr).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /healthz HTTP/1.1\r\n\r\n").unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let mut response = [0; 1];
+        assert!(stream.read(&mut response).is_err());
+        drop(idle);
+        stream.set_read_timeout(None).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("\r\n\r\nok\n"), "{}", response);
+    }
+}
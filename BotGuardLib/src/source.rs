@@ -0,0 +1,562 @@
+// Where pattern bundles come from, and the background refresher applying them to a shared detector.
+//
+// Sources are async so implementations for S3, Consul or etcd can use their async clients. The
+// refresher drives them on its own thread with a minimal executor, so sources needing a specific
+// runtime should instead be polled from that runtime with `refresh`.
+//
+// Bundles can carry a detached Ed25519 signature over the exact pattern bytes, sources read it as
+// raw 64 bytes, hex or base64 as configured. Detectors built with a verifying key refuse bundles
+// that do not verify.
+
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, JoinHandle, Thread};
+use std::time::{Duration, SystemTime};
+
+use crate::request::BotGuard;
+use crate::{crypto, http, nonblocking, BotDetector, BotGuardError};
+
+/// A set of patterns in the format of [`BotDetector::new`], as fetched from a source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternBundle {
+    pub patterns: String,
+    /// Description of the source, e.g. the file path or URL.
+    pub origin: String,
+    pub fetched_at: SystemTime,
+    /// Detached Ed25519 signature of `patterns`, the raw 64 bytes.
+    pub signature: Option<Vec<u8>>,
+    /// Version given by the source, a hash of the patterns is used if `None`.
+    pub version: Option<String>,
+}
+
+impl PatternBundle {
+    /// An unsigned bundle fetched now.
+    pub fn new(patterns: String, origin: &str) -> Self {
+        PatternBundle { patterns, origin: origin.to_string(), fetched_at: SystemTime::now(), signature: None, version: None }
+    }
+
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    pub fn with_signature(mut self, signature: Vec<u8>) -> Self {
+        self.signature = Some(signature);
+        self
+    }
+}
+
+/// Identifies a set of loaded patterns, see [`BotDetector::current_version`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleVersion {
+    /// The version given by the source, or the first 16 hex digits of the SHA-512 of the patterns.
+    pub version: String,
+    pub loaded_at: SystemTime,
+}
+
+/// How a key or detached signature is written, see [`VerifyingKey::decode`] and
+/// [`FileSource::with_signature_file`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// The bytes as they are.
+    Raw,
+    /// Hex digits in either case.
+    Hex,
+    /// Standard base64, with or without padding.
+    Base64,
+}
+
+impl Encoding {
+    /// Decodes the bytes, surrounding whitespace of text encodings is ignored. Returns `None`
+    /// if they are not valid in this encoding.
+    pub fn decode(self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Encoding::Raw => Some(bytes.to_vec()),
+            Encoding::Hex => std::str::from_utf8(bytes).ok().and_then(crypto::decode_hex),
+            Encoding::Base64 => std::str::from_utf8(bytes).ok().and_then(crypto::decode_base64),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Raw => "raw",
+            Encoding::Hex => "hex",
+            Encoding::Base64 => "base64",
+        })
+    }
+}
+
+impl BundleVersion {
+    pub(crate) fn of(patterns: &str, version: Option<&str>) -> Self {
+        let version = version.map(str::to_string).unwrap_or_else(|| {
+            crypto::sha512(patterns.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+        });
+        BundleVersion { version, loaded_at: SystemTime::now() }
+    }
+}
+
+/// An Ed25519 public key that pattern bundles must be signed with.
+///
+/// ```
+/// use BotGuardLib::source::{Encoding, PatternBundle, VerifyingKey};
+///
+/// let key = VerifyingKey::decode(b"A6EHv/POEL4dcN0Y50vAmWfk1jCbpQ1fHdyGZBJVMbg=", Encoding::Base64).unwrap();
+/// let signature = "09a5286fefbb24d5faf44f5fbb24b8e099324661cb205e0689b2fc85965008f2131f65dbab9eeea516245cead21ba9721e6e2f13668212917844886740e95d07";
+/// let bundle = PatternBundle::new("[search-engines]\ngooglebot\n".to_string(), "example")
+///     .with_signature(Encoding::Hex.decode(signature.as_bytes()).unwrap());
+/// assert!(key.verify(&bundle).is_ok());
+///
+/// let tampered = PatternBundle { patterns: "[search-engines]\n".to_string(), ..bundle };
+/// assert!(key.verify(&tampered).is_err());
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey([u8; 32]);
+
+impl VerifyingKey {
+    /// Returns `None` if the bytes are not a canonically encoded point of the curve, or one of
+    /// small order.
+    pub fn from_bytes(bytes: [u8; 32]) -> Option<Self> {
+        crypto::ed25519_valid_key(&bytes).then_some(VerifyingKey(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Decodes a key written in the given encoding.
+    pub fn decode(key: &[u8], encoding: Encoding) -> Result<Self, String> {
+        let bytes = encoding
+            .decode(key)
+            .and_then(|decoded| <[u8; 32]>::try_from(decoded).ok())
+            .ok_or_else(|| format!("{:?} is not a 32 byte key in {}", String::from_utf8_lossy(key), encoding))?;
+        VerifyingKey::from_bytes(bytes).ok_or_else(|| format!("{:?} is not a valid Ed25519 key", String::from_utf8_lossy(key)))
+    }
+
+    /// Checks the bundle signature.
+    pub fn verify(&self, bundle: &PatternBundle) -> Result<(), BotGuardError> {
+        let rejected = |reason: &str| BotGuardError::SignatureInvalid { origin: bundle.origin.clone(), reason: reason.to_string() };
+        let signature = bundle.signature.as_deref().ok_or_else(|| rejected("the bundle is not signed"))?;
+        let signature = <[u8; 64]>::try_from(signature).map_err(|_| rejected("the signature is not 64 bytes"))?;
+        if !crypto::ed25519_verify(&self.0, bundle.patterns.as_bytes(), &signature) {
+            return Err(rejected("the signature does not match"));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for VerifyingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VerifyingKey({})", self.0.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+    }
+}
+
+/// Fetches the current pattern bundle.
+///
+/// ```
+/// use BotGuardLib::BotGuardError;
+/// use BotGuardLib::source::{PatternBundle, PatternSource};
+///
+/// struct Static;
+///
+/// impl PatternSource for Static {
+///     async fn fetch(&self) -> Result<PatternBundle, BotGuardError> {
+///         Ok(PatternBundle::new("googlebot".to_string(), "static"))
+///     }
+/// }
+/// ```
+pub trait PatternSource {
+    fn fetch(&self) -> impl Future<Output = Result<PatternBundle, BotGuardError>> + Send;
+}
+
+/// Reads the bundle from a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSource {
+    path: PathBuf,
+    signature: Option<(PathBuf, Encoding)>,
+}
+
+impl FileSource {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileSource { path: path.into(), signature: None }
+    }
+
+    /// Also reads the bundle signature from a file written in the given encoding.
+    pub fn with_signature_file<P: Into<PathBuf>>(mut self, path: P, encoding: Encoding) -> Self {
+        self.signature = Some((path.into(), encoding));
+        self
+    }
+}
+
+/// Decodes a signature as fetched, the bundle is rejected if it is not valid in the encoding.
+fn decode_signature(signature: &[u8], encoding: Encoding, origin: &str) -> Result<Vec<u8>, BotGuardError> {
+    encoding.decode(signature).ok_or_else(|| BotGuardError::SignatureInvalid {
+        origin: origin.to_string(),
+        reason: format!("the signature is not valid {}", encoding),
+    })
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, BotGuardError> {
+    std::fs::read(path).map_err(|e| BotGuardError::IoError { path: path.display().to_string(), reason: e.to_string() })
+}
+
+impl PatternSource for FileSource {
+    async fn fetch(&self) -> Result<PatternBundle, BotGuardError> {
+        let origin = self.path.display().to_string();
+        let patterns = String::from_utf8(read_file(&self.path)?)
+            .map_err(|_| BotGuardError::IoError { path: origin.clone(), reason: "file is not UTF-8".to_string() })?;
+        let bundle = PatternBundle::new(patterns, &origin);
+        match &self.signature {
+            Some((path, encoding)) => Ok(bundle.with_signature(decode_signature(&read_file(path)?, *encoding, &origin)?)),
+            None => Ok(bundle),
+        }
+    }
+}
+
+/// Downloads the bundle from an `http://` or `https://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpSource {
+    url: String,
+    signature_url: Option<(String, Encoding)>,
+    timeout: Duration,
+}
+
+impl HttpSource {
+    /// A source with a 30 second timeout.
+    pub fn new(url: &str) -> Self {
+        HttpSource { url: url.to_string(), signature_url: None, timeout: Duration::from_secs(30) }
+    }
+
+    /// Also downloads the bundle signature written in the given encoding, e.g. from `<url>.sig`.
+    pub fn with_signature_url(mut self, url: &str, encoding: Encoding) -> Self {
+        self.signature_url = Some((url.to_string(), encoding));
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl HttpSource {
+    /// Downloads on the shared blocking pool, the executor polling the fetch is not held up by
+    /// the connection.
+    async fn download(&self, url: &str) -> Result<Vec<u8>, BotGuardError> {
+        let (owned, timeout) = (url.to_string(), self.timeout);
+        nonblocking::spawn_blocking(move || http::get(&owned, timeout))
+            .await
+            .map_err(|e| BotGuardError::FetchFailed { url: url.to_string(), reason: e.to_string() })
+    }
+}
+
+impl PatternSource for HttpSource {
+    async fn fetch(&self) -> Result<PatternBundle, BotGuardError> {
+        let patterns = String::from_utf8(self.download(&self.url).await?)
+            .map_err(|_| BotGuardError::FetchFailed { url: self.url.clone(), reason: "body is not UTF-8".to_string() })?;
+        let bundle = PatternBundle::new(patterns, &self.url);
+        match &self.signature_url {
+            Some((url, encoding)) => Ok(bundle.with_signature(decode_signature(&self.download(url).await?, *encoding, &self.url)?)),
+            None => Ok(bundle),
+        }
+    }
+}
+
+/// Reads the bundle from an environment variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvSource {
+    variable: String,
+}
+
+impl EnvSource {
+    pub fn new(variable: &str) -> Self {
+        EnvSource { variable: variable.to_string() }
+    }
+}
+
+impl PatternSource for EnvSource {
+    async fn fetch(&self) -> Result<PatternBundle, BotGuardError> {
+        let origin = format!("${}", self.variable);
+        let patterns = std::env::var(&self.variable)
+            .map_err(|e| BotGuardError::IoError { path: origin.clone(), reason: e.to_string() })?;
+        Ok(PatternBundle::new(patterns, &origin))
+    }
+}
+
+/// Fetches a bundle and reloads the detector with it, see [`BotDetector::reload`].
+///
+/// The write lock is only taken once the bundle is fetched, verified and compiled. Returns `false`
+/// without touching the detector if the patterns are unchanged since `previous`. If the detector
+/// was built with a [`VerifyingKey`], bundles without a valid signature fail with
+/// [`BotGuardError::SignatureInvalid`].
+pub async fn refresh<S: PatternSource>(
+    source: &S,
+    detector: &RwLock<BotDetector>,
+    previous: Option<&PatternBundle>,
+) -> Result<(bool, PatternBundle), BotGuardError> {
+    refresh_shared(source, detector, previous).await
+}
+
+/// A detector shared with the request handlers, on its own or as part of a guard.
+pub(crate) trait SharedDetector: Send + Sync {
+    fn read_detector<R>(&self, read: impl FnOnce(&BotDetector) -> R) -> R;
+    fn write_detector<R>(&self, write: impl FnOnce(&mut BotDetector) -> R) -> R;
+}
+
+impl SharedDetector for RwLock<BotDetector> {
+    fn read_detector<R>(&self, read: impl FnOnce(&BotDetector) -> R) -> R {
+        read(&self.read().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+
+    fn write_detector<R>(&self, write: impl FnOnce(&mut BotDetector) -> R) -> R {
+        write(&mut self.write().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+}
+
+impl SharedDetector for RwLock<BotGuard> {
+    fn read_detector<R>(&self, read: impl FnOnce(&BotDetector) -> R) -> R {
+        read(self.read().unwrap_or_else(|poisoned| poisoned.into_inner()).detector())
+    }
+
+    fn write_detector<R>(&self, write: impl FnOnce(&mut BotDetector) -> R) -> R {
+        write(self.write().unwrap_or_else(|poisoned| poisoned.into_inner()).detector_mut())
+    }
+}
+
+/// [`refresh`] of a detector on its own or of the detector of a guard.
+pub(crate) async fn refresh_shared<S: PatternSource, D: SharedDetector>(
+    source: &S,
+    detector: &D,
+    previous: Option<&PatternBundle>,
+) -> Result<(bool, PatternBundle), BotGuardError> {
+    let bundle = source.fetch().await?;
+    let options = detector.read_detector(|detector| detector.options.clone());
+    if let Some(key) = &options.verifying_key {
+        key.verify(&bundle)?;
+    }
+    if previous.is_some_and(|previous| previous.patterns == bundle.patterns) {
+        return Ok((false, bundle));
+    }
+    // compile outside of the lock so checks are only blocked for the swap
+    let reloaded = BotDetector::from_bundle(&bundle, options)?;
+    detector.write_detector(|detector| detector.install(reloaded));
+    Ok((true, bundle))
+}
+
+/// Outcome of the latest refresh, see [`PatternRefresher::status`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RefreshStatus {
+    /// Number of refreshes that changed the patterns.
+    pub applied: u64,
+    /// The bundle currently applied.
+    pub current: Option<PatternBundle>,
+    /// Error of the latest refresh, cleared by the next successful one.
+    pub last_error: Option<BotGuardError>,
+}
+
+/// Refreshes a shared detector from a source at a fixed interval on a background thread.
+///
+/// The first refresh happens right away. Dropping the refresher stops the thread. Every run also
+/// prunes expired temporary patterns, see [`BotDetector::append_ttl`]. After a
+/// [`BotDetector::rollback`] the refresher only applies bundles whose patterns differ from the
+/// rolled back one.
+///
+/// ```no_run
+/// use std::sync::{Arc, RwLock};
+/// use std::time::Duration;
+/// use BotGuardLib::BotDetector;
+/// use BotGuardLib::source::{HttpSource, PatternRefresher};
+///
+/// let detector = Arc::new(RwLock::new(BotDetector::default()));
+/// let refresher = PatternRefresher::spawn(
+///     HttpSource::new("http://patterns.internal/bots.rgx"),
+///     Arc::clone(&detector),
+///     Duration::from_secs(300),
+/// ).unwrap();
+/// assert!(!detector.read().unwrap().check_bot("Mozilla/5.0"));
+/// ```
+#[derive(Debug)]
+pub struct PatternRefresher {
+    stop: Sender<()>,
+    worker: Option<JoinHandle<()>>,
+    status: Arc<Mutex<RefreshStatus>>,
+}
+
+impl PatternRefresher {
+    pub fn spawn<S>(source: S, detector: Arc<RwLock<BotDetector>>, interval: Duration) -> io::Result<Self>
+    where
+        S: PatternSource + Send + 'static,
+    {
+        PatternRefresher::spawn_shared(source, detector, interval)
+    }
+
+    /// [`PatternRefresher::spawn`] for a detector on its own or for the detector of a guard.
+    pub(crate) fn spawn_shared<S, D>(source: S, detector: Arc<D>, interval: Duration) -> io::Result<Self>
+    where
+        S: PatternSource + Send + 'static,
+        D: SharedDetector + 'static,
+    {
+        let (stop, stopped) = mpsc::channel();
+        let status = Arc::new(Mutex::new(RefreshStatus::default()));
+        let worker_status = Arc::clone(&status);
+        let worker = thread::Builder::new().name("botguard-refresh".to_string()).spawn(move || loop {
+            let current = worker_status.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).current.clone();
+            let result = block_on(refresh_shared(&source, &*detector, current.as_ref()));
+            {
+                let mut status = worker_status.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                match result {
+                    Ok((changed, bundle)) => {
+                        status.applied += u64::from(changed);
+                        status.current = Some(bundle);
+                        status.last_error = None;
+                    }
+                    Err(e) => status.last_error = Some(e),
+                }
+            }
+            detector.write_detector(BotDetector::prune_expired);
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+        })?;
+        Ok(PatternRefresher { stop, worker: Some(worker), status })
+    }
+
+    pub fn status(&self) -> RefreshStatus {
+        self.status.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+}
+
+impl Drop for PatternRefresher {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs a future to completion on the current thread.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serves the bundles in turn, repeating the last one.
+    struct Sequence {
+        bundles: Vec<&'static str>,
+        calls: AtomicUsize,
+    }
+
+    impl PatternSource for Sequence {
+        async fn fetch(&self) -> Result<PatternBundle, BotGuardError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst).min(self.bundles.len() - 1);
+            Ok(PatternBundle::new(self.bundles[call].to_string(), "sequence"))
+        }
+    }
+
+    #[test]
+    fn built_in_sources() {
+        let path = std::env::temp_dir().join(format!("botguard-source-{}.rgx", std::process::id()));
+        std::fs::write(&path, "googlebot\n").unwrap();
+        let bundle = block_on(FileSource::new(&path).fetch()).unwrap();
+        assert_eq!(bundle.patterns, "googlebot\n");
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(block_on(FileSource::new(&path).fetch()), Err(BotGuardError::IoError { .. })));
+
+        assert!(matches!(block_on(EnvSource::new("BOTGUARD_TEST_UNSET_VARIABLE").fetch()), Err(BotGuardError::IoError { .. })));
+        let path_variable = block_on(EnvSource::new("PATH").fetch()).unwrap();
+        assert_eq!(path_variable.origin, "$PATH");
+
+        let unreachable = HttpSource::new("http://127.0.0.1:1/bots.rgx").with_timeout(Duration::from_secs(1));
+        assert!(matches!(block_on(unreachable.fetch()), Err(BotGuardError::FetchFailed { .. })));
+    }
+
+    #[test]
+    fn refresh_reloads_only_changed_bundles() {
+        let detector = RwLock::new(BotDetector::new("googlebot"));
+        let source = Sequence { bundles: vec!["bingbot", "bingbot", "(broken"], calls: AtomicUsize::new(0) };
+
+        let (changed, bundle) = block_on(refresh(&source, &detector, None)).unwrap();
+        assert!(changed);
+        assert!(detector.read().unwrap().check_bot("bingbot"));
+        assert_eq!(detector.read().unwrap().current_version().version, BundleVersion::of("bingbot", None).version);
+        let (changed, _) = block_on(refresh(&source, &detector, Some(&bundle))).unwrap();
+        assert!(!changed);
+        assert!(block_on(refresh(&source, &detector, Some(&bundle))).is_err());
+        assert!(detector.read().unwrap().check_bot("bingbot"));
+    }
+
+    #[test]
+    fn refresh_requires_a_valid_signature() {
+        let key = VerifyingKey::decode(b"03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8", Encoding::Hex).unwrap();
+        let detector = RwLock::new(BotDetector::builder().patterns("bingbot").verifying_key(key).build().unwrap());
+        let dir = std::env::temp_dir().join(format!("botguard-signed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("bots.rgx"), "[search-engines]\ngooglebot\n").unwrap();
+        std::fs::write(dir.join("bots.rgx.sig"), "09a5286fefbb24d5faf44f5fbb24b8e099324661cb205e0689b2fc85965008f2131f65dbab9eeea516245cead21ba9721e6e2f13668212917844886740e95d07\n").unwrap();
+
+        let unsigned = FileSource::new(dir.join("bots.rgx"));
+        let error = block_on(refresh(&unsigned, &detector, None)).unwrap_err();
+        assert!(matches!(error, BotGuardError::SignatureInvalid { ref reason, .. } if reason == "the bundle is not signed"));
+        let base64 = unsigned.clone().with_signature_file(dir.join("bots.rgx.sig"), Encoding::Base64);
+        let error = block_on(refresh(&base64, &detector, None)).unwrap_err();
+        assert!(matches!(error, BotGuardError::SignatureInvalid { ref reason, .. } if reason == "the signature is not 64 bytes"));
+        let signed = unsigned.with_signature_file(dir.join("bots.rgx.sig"), Encoding::Hex);
+        assert!(block_on(refresh(&signed, &detector, None)).unwrap().0);
+        assert_eq!(detector.read().unwrap().classify("Googlebot/2.1"), Some("search-engines"));
+
+        std::fs::write(dir.join("bots.rgx"), "[search-engines]\ngooglebot\n.\n").unwrap();
+        let error = block_on(refresh(&signed, &detector, None)).unwrap_err();
+        assert!(matches!(error, BotGuardError::SignatureInvalid { ref reason, .. } if reason == "the signature does not match"));
+        assert!(!detector.read().unwrap().check_bot("Mozilla/5.0"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(VerifyingKey::decode(b"00", Encoding::Hex).is_err());
+        assert!(VerifyingKey::decode(b"03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8", Encoding::Base64).is_err());
+        assert!(VerifyingKey::from_bytes([0xff; 32]).is_none());
+    }
+
+    #[test]
+    fn refresher_applies_bundles_in_the_background() {
+        let detector = Arc::new(RwLock::new(BotDetector::new("googlebot")));
+        let source = Sequence { bundles: vec!["bingbot", "duckduckbot"], calls: AtomicUsize::new(0) };
+        let refresher = PatternRefresher::spawn(source, Arc::clone(&detector), Duration::from_millis(10)).unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while refresher.status().applied < 2 {
+            assert!(std::time::Instant::now() < deadline, "refresher did not apply both bundles");
+            thread::sleep(Duration::from_millis(5));
+        }
+        drop(refresher);
+        let detector = detector.read().unwrap();
+        assert!(detector.check_bot("DuckDuckBot/1.1"));
+        assert!(!detector.check_bot("Googlebot"));
+    }
+}
@@ -0,0 +1,217 @@
+// Email harvesters and comment spam bots. The harvesters crawl for addresses to sell and the spam
+// bots post links to forms, both are unwanted everywhere, including where crawlers are allowed.
+//
+// Their user-agents are the `SPAM_BOTS_GROUP` of the bundled patterns, which `check_spam_bot`
+// reads as well, so the list is maintained in one place. Spam bots faking a browser user-agent
+// are left to the content: `ContentAnalyzer` scores submitted text by its links and by phrases
+// spam keeps selling, for comment and contact forms where the honeypot alone is not enough.
+
+use std::sync::OnceLock;
+
+use regex::{Regex, RegexSet, RegexSetBuilder};
+
+use crate::{BotGuardError, Verdict};
+
+/// Group of the bundled patterns holding email harvesters and comment spam bots.
+pub const SPAM_BOTS_GROUP: &str = "spam-bots";
+
+/// Phrases of the usual spam payloads, matched case-insensitively as whole words.
+const SPAM_PAYLOADS: &[&str] = &[
+    "viagra",
+    "cialis",
+    "levitra",
+    "xanax",
+    "online casino",
+    "payday loans?",
+    "replica watches",
+    "cheap jerseys",
+    "buy followers",
+    "seo services",
+    "backlinks",
+    "crypto giveaway",
+    "forex signals",
+    "essay writing service",
+];
+
+/// Matches a link, in markup as well as in plain text.
+const LINK: &str = r"(?i)https?://|\bwww\.[a-z0-9-]+\.";
+
+/// Whether the user-agent matches the [`SPAM_BOTS_GROUP`] of the bundled patterns, see
+/// [`crate::BotDetector::check_spam_bot`].
+pub(crate) fn is_spam_bot(user_agent: &str) -> bool {
+    static PATTERNS: OnceLock<RegexSet> = OnceLock::new();
+    PATTERNS
+        .get_or_init(|| {
+            let header = format!("[{}]", SPAM_BOTS_GROUP);
+            let group = include_str!("bot_patterns.rgx").lines().skip_while(|line| line.trim() != header).skip(1).take_while(|line| !line.starts_with('['));
+            RegexSetBuilder::new(group.filter(|line| !line.trim().is_empty())).case_insensitive(true).build().expect("the bundled spam bot patterns are valid")
+        })
+        .is_match(user_agent)
+}
+
+/// Something in submitted text that spam does and people rarely do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpamSignal {
+    /// More links than [`ContentAnalyzer::max_links`].
+    TooManyLinks(usize),
+    /// Links per word above [`ContentAnalyzer::max_link_density`], text that is mostly links.
+    LinkDensity(f64),
+    /// `[url=...]` BBCode, posted by bots that fill every form as if it were a forum.
+    BbCode,
+    /// A phrase of a spam payload, pattern as listed.
+    Payload(String),
+}
+
+impl SpamSignal {
+    fn weight(&self) -> f32 {
+        match self {
+            SpamSignal::TooManyLinks(_) | SpamSignal::LinkDensity(_) => 0.4,
+            SpamSignal::BbCode => 0.5,
+            SpamSignal::Payload(_) => 0.3,
+        }
+    }
+}
+
+/// Outcome of [`ContentAnalyzer::analyze`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentReport {
+    /// Between `0.0` and `1.0`, the sum of the signal weights.
+    pub score: f32,
+    pub links: usize,
+    pub signals: Vec<SpamSignal>,
+}
+
+impl ContentReport {
+    /// A score of `0.8` or more is a bot, `0.4` or more suspicious: one signal alone is
+    /// suspicious at most, an honest comment may carry a few links or mention a casino.
+    pub fn verdict(&self) -> Verdict {
+        match self.score {
+            score if score >= 0.8 => Verdict::Bot,
+            score if score >= 0.4 => Verdict::Suspicious,
+            _ => Verdict::Human,
+        }
+    }
+}
+
+/// Scores the text of form submissions for spam.
+///
+/// ```
+/// use BotGuardLib::spam::{ContentAnalyzer, SpamSignal};
+/// use BotGuardLib::Verdict;
+///
+/// let analyzer = ContentAnalyzer::default();
+/// let comment = "Great post! See also https://en.wikipedia.org/wiki/Rust_(programming_language)";
+/// assert_eq!(analyzer.analyze(comment).verdict(), Verdict::Human);
+///
+/// let spam = "Cheap viagra [url=http://pills.example]here[/url] http://pills.example/buy http://pills.example/now";
+/// let report = analyzer.analyze(spam);
+/// assert_eq!(report.verdict(), Verdict::Bot);
+/// assert!(report.signals.contains(&SpamSignal::BbCode));
+/// assert!(report.signals.contains(&SpamSignal::Payload("viagra".to_string())));
+///
+/// // submitted fields are analyzed together
+/// assert_eq!(analyzer.analyze_form([("name", "Ann"), ("comment", spam)]).links, 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ContentAnalyzer {
+    links: Regex,
+    bbcode: Regex,
+    payloads: RegexSet,
+    patterns: Vec<String>,
+    max_links: usize,
+    max_link_density: f64,
+}
+
+impl Default for ContentAnalyzer {
+    /// The bundled spam payloads, at most 3 links and one link per 5 words.
+    fn default() -> Self {
+        ContentAnalyzer::new(SPAM_PAYLOADS).expect("the bundled spam payloads are valid")
+    }
+}
+
+impl ContentAnalyzer {
+    /// Looks for `payloads`, regexes matched case-insensitively as whole words, instead of the
+    /// bundled ones.
+    pub fn new(payloads: &[&str]) -> Result<Self, BotGuardError> {
+        let invalid = |err: regex::Error| BotGuardError::InvalidConfig { line: None, reason: format!("spam payload: {}", err) };
+        let payloads_set = RegexSetBuilder::new(payloads.iter().map(|payload| format!(r"\b(?:{})\b", payload))).case_insensitive(true).build().map_err(invalid)?;
+        Ok(ContentAnalyzer {
+            links: Regex::new(LINK).map_err(invalid)?,
+            bbcode: Regex::new(r"(?i)\[url[=\]]").map_err(invalid)?,
+            payloads: payloads_set,
+            patterns: payloads.iter().map(|payload| payload.to_string()).collect(),
+            max_links: 3,
+            max_link_density: 0.2,
+        })
+    }
+
+    /// Links allowed in a submission, more are a [`SpamSignal::TooManyLinks`].
+    pub fn max_links(mut self, links: usize) -> Self {
+        self.max_links = links;
+        self
+    }
+
+    /// Links per word allowed in a submission with more than one link.
+    pub fn max_link_density(mut self, density: f64) -> Self {
+        self.max_link_density = density;
+        self
+    }
+
+    pub fn analyze(&self, text: &str) -> ContentReport {
+        let links = self.links.find_iter(text).count();
+        let words = text.split_whitespace().count().max(1);
+        let mut signals = Vec::new();
+        if links > self.max_links {
+            signals.push(SpamSignal::TooManyLinks(links));
+        }
+        let density = links as f64 / words as f64;
+        if links > 1 && density > self.max_link_density {
+            signals.push(SpamSignal::LinkDensity(density));
+        }
+        if self.bbcode.is_match(text) {
+            signals.push(SpamSignal::BbCode);
+        }
+        signals.extend(self.payloads.matches(text).iter().map(|i| SpamSignal::Payload(self.patterns[i].clone())));
+        let score = signals.iter().map(SpamSignal::weight).sum::<f32>().min(1.0);
+        ContentReport { score, links, signals }
+    }
+
+    /// Analyzes the values of the submitted fields as one text.
+    pub fn analyze_form<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(&self, fields: I) -> ContentReport {
+        let text = fields.into_iter().map(|(_, value)| value).collect::<Vec<_>>().join("\n");
+        self.analyze(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_harvesters_and_spam_bots() {
+        for user_agent in ["EmailCollector/1.0", "Mozilla/4.0 (compatible; Advanced Email Extractor v2.xx)", "EmailSiphon", "XRumer 7.0", "ScrapeBox"] {
+            assert!(is_spam_bot(user_agent), "{}", user_agent);
+        }
+        for user_agent in ["Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:127.0) Gecko/20100101 Thunderbird/115.0", "Googlebot/2.1", "curl/8.4.0"] {
+            assert!(!is_spam_bot(user_agent), "{}", user_agent);
+        }
+    }
+
+    #[test]
+    fn scores_links_and_payloads() {
+        let analyzer = ContentAnalyzer::default();
+        let links = "http://a.example http://b.example www.c-d.example <a href=\"http://e.example\">e</a>";
+        let report = analyzer.analyze(links);
+        assert_eq!(report.links, 4);
+        assert_eq!(report.signals[0], SpamSignal::TooManyLinks(4));
+        assert!(matches!(report.signals[1], SpamSignal::LinkDensity(_)));
+        assert_eq!(report.verdict(), Verdict::Bot);
+
+        // whole words only, one payload alone is not a bot
+        assert_eq!(analyzer.analyze("I ordered the replica watches for a film set").verdict(), Verdict::Human);
+        assert!(analyzer.analyze("Payday LOANS approved today").signals.contains(&SpamSignal::Payload("payday loans?".to_string())));
+        assert!(analyzer.analyze("the backlinksbuilder tool").signals.is_empty());
+        assert_eq!(ContentAnalyzer::default().max_links(10).max_link_density(1.0).analyze(links).score, 0.0);
+        assert!(ContentAnalyzer::new(&["("]).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+// Where a pattern matched, in the user-agent as it was passed in rather than the lowercased and
+// folded form the patterns run on, see `BotDetector::find`.
+//
+// Only the winning pattern is compiled again on its own to read its span and capture groups, the
+// combined regexes of the groups never report captures of the patterns inside them.
+
+use regex::RegexBuilder;
+
+use crate::BotDetector;
+
+/// A part of the user-agent as it was passed to [`BotDetector::find`], in byte offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span<'u> {
+    pub start: usize,
+    pub end: usize,
+    /// `&user_agent[start..end]`.
+    pub text: &'u str,
+}
+
+/// A capture group of the matching pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture<'u> {
+    /// The name of a `(?P<name>...)` group.
+    pub name: Option<String>,
+    /// `None` if the group did not take part in the match, like a branch of `(a)|(b)`.
+    pub span: Option<Span<'u>>,
+}
+
+/// The pattern [`BotDetector::find_match`] reports, with where it matched the user-agent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a, 'u> {
+    pub group: &'a str,
+    pub pattern: &'a str,
+    pub weight: f32,
+    /// The leftmost match of the pattern, without the delimiters of [`crate::MatchMode::Token`].
+    pub span: Span<'u>,
+    /// The capture groups of the pattern in order.
+    pub captures: Vec<Capture<'u>>,
+}
+
+impl<'u> Match<'_, 'u> {
+    /// The capture group of that name, if it took part in the match.
+    pub fn capture(&self, name: &str) -> Option<Span<'u>> {
+        self.captures.iter().find(|capture| capture.name.as_deref() == Some(name)).and_then(|capture| capture.span)
+    }
+}
+
+pub(crate) fn find<'a, 'u>(detector: &'a BotDetector, user_agent: &'u str) -> Option<Match<'a, 'u>> {
+    let truncated = detector.truncate_user_agent(user_agent);
+    let (folded, offsets) = detector.options.normalization.apply_with_offsets(truncated);
+    // lowercasing ASCII keeps every byte where it is
+    let normalized = match detector.options.case_sensitive {
+        true => folded.into_owned(),
+        false => folded.to_ascii_lowercase(),
+    };
+    let found = detector.best_match(&normalized)?;
+    let regex = RegexBuilder::new(&detector.options.match_mode.wrap(0, found.pattern))
+        .case_insensitive(!detector.options.case_sensitive)
+        .size_limit(detector.options.limits.pattern_size_limit)
+        .nest_limit(detector.options.limits.nest_limit.saturating_add(1))
+        .build()
+        .ok()?;
+    let captures = regex.captures(&normalized)?;
+    let span = |start: usize, end: usize| {
+        let (start, end) = match &offsets {
+            None => (start, end),
+            Some(offsets) if start == end => {
+                let at = offsets.get(start).map_or(truncated.len(), |range| range.0);
+                (at, at)
+            }
+            Some(offsets) => (offsets[start].0, offsets[end - 1].1),
+        };
+        Span { start, end, text: &user_agent[start..end] }
+    };
+    let matched = captures.name("__bg0")?;
+    Some(Match {
+        group: found.group,
+        pattern: found.pattern,
+        weight: found.weight,
+        span: span(matched.start(), matched.end()),
+        // 0 is the whole match and 1 the wrapping group
+        captures: regex
+            .capture_names()
+            .zip(captures.iter())
+            .skip(2)
+            .map(|(name, group)| Capture { name: name.map(str::to_string), span: group.map(|group| span(group.start(), group.end())) })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize::Normalization;
+    use crate::{BotDetector, MatchMode};
+
+    #[test]
+    fn spans_point_into_the_original_user_agent() {
+        let detector = BotDetector::new("[tools]\n0.6 curl/(?P<version>\\d+)\\.(\\d+)|wget/(\\d+)\n[crawlers]\ngooglebot");
+        let user_agent = "Agent CURL/8.4 (x)";
+        let found = detector.find(user_agent).unwrap();
+        assert_eq!((found.group, found.weight, found.span.start, found.span.end, found.span.text), ("tools", 0.6, 6, 14, "CURL/8.4"));
+        assert_eq!(found.capture("version").map(|span| span.text), Some("8"));
+        let spans = found.captures.iter().map(|capture| capture.span.map(|span| span.text)).collect::<Vec<_>>();
+        assert_eq!(spans, vec![Some("8"), Some("4"), None]);
+        assert_eq!(detector.find("Mozilla/5.0 (X11)"), None);
+
+        let token = BotDetector::builder().patterns("googlebot").match_mode(MatchMode::Token).build().unwrap();
+        let found = token.find("Mozilla/5.0 (compatible; Googlebot/2.1)").unwrap();
+        assert_eq!((found.span.start, found.span.text), (25, "Googlebot"));
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn spans_map_folded_characters_back() {
+        let detector = BotDetector::builder().patterns("google bot/\\d").normalization(Normalization::all()).build().unwrap();
+        let user_agent = "  x Ｇооgle\u{200b} \t Bot/2";
+        let found = detector.find(user_agent).unwrap();
+        assert_eq!(found.span.text, "Ｇооgle\u{200b} \t Bot/2");
+        assert_eq!(found.span.start, 4);
+        assert_eq!(found.span.end, user_agent.len());
+    }
+}
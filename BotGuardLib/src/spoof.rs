@@ -0,0 +1,151 @@
+// Consistency rules for user-agents claiming to be a real browser. Bots copying a browser
+// user-agent often edit it by hand or assemble it from parts, which leaves combinations no
+// released browser ever sent.
+
+use std::fmt;
+
+/// Highest major versions considered plausible, a little ahead of the current releases.
+const MAX_CHROME: u32 = 160;
+const MAX_FIREFOX: u32 = 160;
+const MAX_SAFARI: u32 = 30;
+
+/// `Windows NT` versions that exist.
+const WINDOWS_NT_VERSIONS: &[&str] = &["4.0", "5.0", "5.01", "5.1", "5.2", "6.0", "6.1", "6.2", "6.3", "10.0"];
+
+/// First Chrome major version released after `Mozilla/4.0` went out of use.
+const MODERN_CHROME: u32 = 10;
+
+/// An inconsistency found by [`anomalies`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    /// A browser version beyond any release, like `Chrome/200`.
+    ImpossibleVersion { browser: &'static str, major: u32 },
+    /// A `Windows NT` version that was never released.
+    UnknownWindowsVersion(String),
+    /// `Mozilla/4.0` together with a browser that always sends `Mozilla/5.0`.
+    LegacyMozillaToken { browser: &'static str },
+    /// Tokens of a mobile platform together with those of a desktop one.
+    MixedPlatforms { mobile: &'static str, desktop: &'static str },
+    /// A Chromium based browser without the `AppleWebKit/` token it always sends.
+    MissingEngineToken { browser: &'static str },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::ImpossibleVersion { browser, major } => write!(f, "{} {} was never released", browser, major),
+            Anomaly::UnknownWindowsVersion(version) => write!(f, "Windows NT {} does not exist", version),
+            Anomaly::LegacyMozillaToken { browser } => write!(f, "{} never sends Mozilla/4.0", browser),
+            Anomaly::MixedPlatforms { mobile, desktop } => write!(f, "{} and {} tokens in one user-agent", mobile, desktop),
+            Anomaly::MissingEngineToken { browser } => write!(f, "{} without AppleWebKit", browser),
+        }
+    }
+}
+
+/// Checks a user-agent claiming to be a browser against what real browsers send.
+///
+/// User-agents without a `Mozilla/` prefix make no such claim and never have anomalies.
+///
+/// ```
+/// use BotGuardLib::spoof::{anomalies, Anomaly};
+///
+/// let genuine = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+/// assert!(anomalies(genuine).is_empty());
+///
+/// let forged = "Mozilla/4.0 (Windows NT 7.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/200.0.0.0 Safari/537.36";
+/// assert_eq!(
+///     anomalies(forged),
+///     vec![
+///         Anomaly::ImpossibleVersion { browser: "Chrome", major: 200 },
+///         Anomaly::UnknownWindowsVersion("7.0".to_string()),
+///         Anomaly::LegacyMozillaToken { browser: "Chrome" },
+///     ]
+/// );
+/// ```
+pub fn anomalies(user_agent: &str) -> Vec<Anomaly> {
+    let ua = user_agent.to_ascii_lowercase();
+    let mut found = Vec::new();
+    if !ua.starts_with("mozilla/") {
+        return found;
+    }
+
+    let chrome = major(&ua, "chrome/").or_else(|| major(&ua, "crios/"));
+    let firefox = major(&ua, "firefox/").or_else(|| major(&ua, "fxios/"));
+    let safari = if chrome.is_none() && ua.contains("safari/") { major(&ua, "version/") } else { None };
+    let edge = major(&ua, "edg/");
+    let browsers = [("Chrome", chrome, MAX_CHROME), ("Edge", edge, MAX_CHROME), ("Firefox", firefox, MAX_FIREFOX), ("Safari", safari, MAX_SAFARI)];
+    for (browser, version, max) in browsers {
+        if let Some(major) = version.filter(|&major| major > max) {
+            found.push(Anomaly::ImpossibleVersion { browser, major });
+        }
+    }
+
+    if let Some(rest) = ua.split_once("windows nt ").map(|(_, rest)| rest) {
+        let version = rest.split([';', ')', ' ']).next().unwrap_or_default();
+        if !WINDOWS_NT_VERSIONS.contains(&version) {
+            found.push(Anomaly::UnknownWindowsVersion(version.to_string()));
+        }
+    }
+
+    if ua.starts_with("mozilla/4.0") {
+        let modern = [("Chrome", chrome.filter(|&major| major >= MODERN_CHROME)), ("Edge", edge), ("Firefox", firefox)];
+        if let Some((browser, _)) = modern.into_iter().find(|(_, version)| version.is_some()) {
+            found.push(Anomaly::LegacyMozillaToken { browser });
+        }
+    }
+
+    let mobile = [("Android", "android"), ("iPhone", "iphone")].into_iter().find(|(_, token)| ua.contains(token));
+    let desktop = [("Windows NT", "windows nt"), ("Macintosh", "macintosh"), ("X11", "x11")].into_iter().find(|(_, token)| ua.contains(token));
+    if let (Some((mobile, _)), Some((desktop, _))) = (mobile, desktop) {
+        found.push(Anomaly::MixedPlatforms { mobile, desktop });
+    }
+
+    if (major(&ua, "chrome/").is_some() || edge.is_some()) && !ua.contains("applewebkit/") {
+        found.push(Anomaly::MissingEngineToken { browser: if edge.is_some() { "Edge" } else { "Chrome" } });
+    }
+    found
+}
+
+/// Major version following `token` in a lowercased user-agent.
+fn major(ua: &str, token: &str) -> Option<u32> {
+    let start = ua.find(token)? + token.len();
+    let digits = ua[start..].bytes().take_while(u8::is_ascii_digit).count();
+    ua[start..start + digits].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn genuine_browsers_are_consistent() {
+        let genuine = [
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Safari/605.1.15",
+            "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0",
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) CriOS/122.0.6261.89 Mobile/15E148 Safari/604.1",
+            "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0.0.0 Mobile Safari/537.36",
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0.0.0 Safari/537.36 Edg/122.0.2365.66",
+            "Mozilla/4.0 (compatible; MSIE 8.0; Windows NT 6.1; Trident/4.0)",
+            "curl/8.0 (Chrome/999)",
+        ];
+        for ua in genuine {
+            assert_eq!(anomalies(ua), Vec::new(), "{}", ua);
+        }
+    }
+
+    #[test]
+    fn forged_browsers_are_reported() {
+        assert_eq!(
+            anomalies("Mozilla/5.0 (Linux; Android 13; Windows NT 10.0) Chrome/120.0 Safari/537.36"),
+            vec![
+                Anomaly::MixedPlatforms { mobile: "Android", desktop: "Windows NT" },
+                Anomaly::MissingEngineToken { browser: "Chrome" },
+            ]
+        );
+        assert_eq!(
+            anomalies("Mozilla/4.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/999.0"),
+            vec![Anomaly::ImpossibleVersion { browser: "Firefox", major: 999 }, Anomaly::LegacyMozillaToken { browser: "Firefox" }]
+        );
+        assert_eq!(anomalies("Mozilla/5.0 (Windows NT 11.0)")[0].to_string(), "Windows NT 11.0 does not exist");
+    }
+}
@@ -0,0 +1,230 @@
+// SQLite backend of `review::VerdictStore`, with SQLite compiled into the crate by `rusqlite`.
+//
+// Records go to a `detections` table indexed by time, and the review queries run as SQL, so
+// unlike `FileVerdictStore` a query over the last hour does not read the records of months. The
+// database is opened in WAL mode, `sqlite3` or any other reader can look at it while it is
+// written.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, Row};
+
+use crate::review::{action_name, parse_action, BotCount, DetectionRecord, VerdictStore, FALSE_POSITIVE_SCORE};
+use crate::{json, BotGuardError};
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    CREATE TABLE IF NOT EXISTS detections (
+        id INTEGER PRIMARY KEY,
+        timestamp INTEGER NOT NULL,
+        user_agent TEXT NOT NULL,
+        ip TEXT,
+        score REAL NOT NULL,
+        matched_patterns TEXT NOT NULL,
+        category TEXT,
+        action TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS detections_timestamp ON detections (timestamp);
+";
+
+const COLUMNS: &str = "timestamp, user_agent, ip, score, matched_patterns, category, action";
+
+/// Records kept in a SQLite database, see [`crate::review`].
+///
+/// Times are stored as unix milliseconds and matched patterns as a JSON array.
+///
+/// ```no_run
+/// use std::time::{Duration, SystemTime};
+/// use BotGuardLib::review::{SqliteVerdictStore, VerdictStore};
+///
+/// let store = SqliteVerdictStore::open("/var/lib/botguard/verdicts.db").unwrap();
+/// let yesterday = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+/// for bot in store.top_bots(yesterday).unwrap().iter().take(10) {
+///     println!("{:>8} {}", bot.count, bot.user_agent);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SqliteVerdictStore {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteVerdictStore {
+    /// Opens or creates the database, existing records are kept.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BotGuardError> {
+        let path = path.as_ref().to_path_buf();
+        let connection = Connection::open(&path).map_err(|e| sqlite_error(&path, e))?;
+        connection.busy_timeout(Duration::from_secs(5)).map_err(|e| sqlite_error(&path, e))?;
+        connection.execute_batch(SCHEMA).map_err(|e| sqlite_error(&path, e))?;
+        Ok(SqliteVerdictStore { path, connection: Mutex::new(connection) })
+    }
+
+    /// Runs a query returning records.
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<DetectionRecord>, BotGuardError> {
+        let connection = self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let error = |e| sqlite_error(&self.path, e);
+        let mut statement = connection.prepare_cached(sql).map_err(error)?;
+        let rows = statement.query_map(params, from_row).map_err(error)?;
+        rows.map(|row| row.map_err(error)?.ok_or_else(|| invalid_row(&self.path))).collect()
+    }
+}
+
+fn sqlite_error(path: &Path, e: rusqlite::Error) -> BotGuardError {
+    BotGuardError::IoError { path: path.display().to_string(), reason: e.to_string() }
+}
+
+fn invalid_row(path: &Path) -> BotGuardError {
+    BotGuardError::IoError { path: path.display().to_string(), reason: "invalid detection record".to_string() }
+}
+
+fn millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+/// Reads the [`COLUMNS`] of a row, `None` if they do not hold a record.
+fn from_row(row: &Row<'_>) -> rusqlite::Result<Option<DetectionRecord>> {
+    let ip = match row.get::<_, Option<String>>(2)? {
+        Some(ip) => match ip.parse() {
+            Ok(ip) => Some(ip),
+            Err(_) => return Ok(None),
+        },
+        None => None,
+    };
+    let patterns = json::parse(&row.get::<_, String>(4)?).ok();
+    let matched_patterns = patterns
+        .as_ref()
+        .and_then(|patterns| patterns.as_array())
+        .and_then(|patterns| patterns.iter().map(|pattern| pattern.as_str().map(str::to_string)).collect::<Option<Vec<String>>>());
+    let (Some(matched_patterns), Some(action)) = (matched_patterns, parse_action(&row.get::<_, String>(6)?)) else {
+        return Ok(None);
+    };
+    Ok(Some(DetectionRecord {
+        user_agent: row.get(1)?,
+        ip,
+        score: row.get::<_, f64>(3)? as f32,
+        matched_patterns,
+        category: row.get(5)?,
+        action,
+        timestamp: UNIX_EPOCH + Duration::from_millis(row.get::<_, i64>(0)?.max(0) as u64),
+    }))
+}
+
+impl VerdictStore for SqliteVerdictStore {
+    fn record(&self, record: &DetectionRecord) -> Result<(), BotGuardError> {
+        let mut patterns = String::from("[");
+        for (i, pattern) in record.matched_patterns.iter().enumerate() {
+            if i > 0 {
+                patterns.push(',');
+            }
+            json::push_str(&mut patterns, pattern);
+        }
+        patterns.push(']');
+        let connection = self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        connection
+            .prepare_cached(&format!("INSERT INTO detections ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)", COLUMNS))
+            .and_then(|mut statement| {
+                statement.execute(params![
+                    millis(record.timestamp),
+                    record.user_agent,
+                    record.ip.map(|ip| ip.to_string()),
+                    f64::from(record.score),
+                    patterns,
+                    record.category,
+                    action_name(&record.action),
+                ])
+            })
+            .map(drop)
+            .map_err(|e| sqlite_error(&self.path, e))
+    }
+
+    fn records_since(&self, since: SystemTime) -> Result<Vec<DetectionRecord>, BotGuardError> {
+        self.query(&format!("SELECT {} FROM detections WHERE timestamp >= ?1 ORDER BY timestamp, id", COLUMNS), [millis(since)])
+    }
+
+    fn top_bots(&self, since: SystemTime) -> Result<Vec<BotCount>, BotGuardError> {
+        let connection = self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let error = |e| sqlite_error(&self.path, e);
+        let mut statement = connection
+            .prepare_cached(
+                "SELECT user_agent, category, COUNT(*) AS count FROM detections
+                 WHERE timestamp >= ?1 AND category IS NOT NULL
+                 GROUP BY user_agent, category ORDER BY count DESC, user_agent, category",
+            )
+            .map_err(error)?;
+        let rows = statement
+            .query_map([millis(since)], |row| Ok(BotCount { user_agent: row.get(0)?, category: row.get(1)?, count: row.get::<_, i64>(2)? as u64 }))
+            .map_err(error)?;
+        rows.map(|row| row.map_err(error)).collect()
+    }
+
+    fn false_positive_candidates(&self) -> Result<Vec<DetectionRecord>, BotGuardError> {
+        let sql = format!(
+            "SELECT {} FROM detections WHERE id IN (
+                 SELECT MAX(id) FROM detections
+                 WHERE action IN ('block', 'challenge') AND score < ?1 GROUP BY user_agent
+             ) ORDER BY timestamp DESC, id DESC",
+            COLUMNS
+        );
+        self.query(&sql, [f64::from(FALSE_POSITIVE_SCORE)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Action;
+
+    fn record(user_agent: &str, category: Option<&str>, score: f32, action: Action, secs: u64) -> DetectionRecord {
+        DetectionRecord {
+            user_agent: user_agent.to_string(),
+            ip: Some("2001:db8::7".parse().unwrap()),
+            score,
+            matched_patterns: category.map(|_| format!("^{}\"", user_agent.to_lowercase())).into_iter().collect(),
+            category: category.map(str::to_string),
+            action,
+            timestamp: UNIX_EPOCH + Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn sqlite_store_answers_review_queries() {
+        let path = std::env::temp_dir().join(format!("botguard-verdicts-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let records = [
+            record("Googlebot/2.1", Some("search-engines"), 1.0, Action::Allow, 10),
+            record("curl/8.0", Some("http-clients"), 0.9, Action::Block, 20),
+            record("curl/8.0", Some("http-clients"), 0.9, Action::Block, 30),
+            record("Mozilla/5.0 (X11)", None, 0.3, Action::Challenge, 40),
+            record("Mozilla/5.0 (X11)", None, 0.4, Action::Block, 50),
+            record("Mozilla/5.0 (Mac)", None, 0.2, Action::Allow, 60),
+            record("ScrapyBot", Some("scrapers"), 0.7, Action::RateLimit { requests: 10, per: Duration::from_secs(60) }, 70),
+        ];
+        let store = SqliteVerdictStore::open(&path).unwrap();
+        for r in &records {
+            store.record(r).unwrap();
+        }
+        drop(store);
+
+        let store = SqliteVerdictStore::open(&path).unwrap();
+        assert_eq!(store.records_since(UNIX_EPOCH).unwrap(), records);
+        assert_eq!(store.records_since(UNIX_EPOCH + Duration::from_secs(60)).unwrap().len(), 2);
+        assert_eq!(
+            store.top_bots(UNIX_EPOCH + Duration::from_secs(15)).unwrap(),
+            vec![
+                BotCount { user_agent: "curl/8.0".to_string(), category: Some("http-clients".to_string()), count: 2 },
+                BotCount { user_agent: "ScrapyBot".to_string(), category: Some("scrapers".to_string()), count: 1 },
+            ]
+        );
+        assert_eq!(store.false_positive_candidates().unwrap(), vec![records[4].clone()]);
+
+        store.record(&record("Mozilla/5.0 (Mac)", None, 0.1, Action::Challenge, 80)).unwrap();
+        let candidates = store.false_positive_candidates().unwrap();
+        assert_eq!(candidates.iter().map(|record| record.user_agent.as_str()).collect::<Vec<_>>(), ["Mozilla/5.0 (Mac)", "Mozilla/5.0 (X11)"]);
+        drop(store);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
@@ -0,0 +1,748 @@
+// State shared by the instances of a deployment: request counters for rate limiting and crawl
+// budgets, session tracking, cached verdicts, the scores of known bots, verdicts overridden by
+// support and redeemed nonces.
+//
+// Every component works on a `StateStore`. `MemoryStore` keeps state per process, `RedisStore`
+// (feature `redis`) shares it between instances behind a load balancer, and `FallbackStore` uses
+// a local store while the shared one is unreachable.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "redis")]
+pub use crate::redis::RedisStore;
+use crate::{crypto, BotGuardError, Verdict};
+
+/// Key-value storage with expiring entries.
+pub trait StateStore: Send + Sync {
+    /// Adds one to the counter at `key` and returns the new value. A new counter expires after
+    /// `ttl`, incrementing does not extend it.
+    fn increment(&self, key: &str, ttl: Duration) -> Result<u64, BotGuardError>;
+
+    fn get(&self, key: &str) -> Result<Option<String>, BotGuardError>;
+
+    /// Stores a value that expires after `ttl`, replacing any previous value and expiry.
+    fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), BotGuardError>;
+}
+
+/// Number of writes between sweeps of expired entries.
+const SWEEP_INTERVAL: u32 = 1024;
+
+/// A store local to the process.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<MemoryEntries>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryEntries {
+    values: HashMap<String, (String, Instant)>,
+    writes: u32,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Number of entries, including expired ones not swept yet.
+    pub fn len(&self) -> usize {
+        self.lock().values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemoryEntries> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl MemoryEntries {
+    fn live(&mut self, key: &str, now: Instant) -> Option<&mut (String, Instant)> {
+        self.values.get_mut(key).filter(|(_, expires)| *expires > now)
+    }
+
+    fn written(&mut self, now: Instant) {
+        self.writes += 1;
+        if self.writes >= SWEEP_INTERVAL {
+            self.writes = 0;
+            self.values.retain(|_, (_, expires)| *expires > now);
+        }
+    }
+}
+
+impl StateStore for MemoryStore {
+    fn increment(&self, key: &str, ttl: Duration) -> Result<u64, BotGuardError> {
+        let now = Instant::now();
+        let mut entries = self.lock();
+        let count = match entries.live(key, now) {
+            Some((value, _)) => {
+                let count = value.parse::<u64>().unwrap_or(0) + 1;
+                *value = count.to_string();
+                count
+            }
+            None => {
+                entries.values.insert(key.to_string(), ("1".to_string(), now + ttl));
+                1
+            }
+        };
+        entries.written(now);
+        Ok(count)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, BotGuardError> {
+        Ok(self.lock().live(key, Instant::now()).map(|(value, _)| value.clone()))
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), BotGuardError> {
+        let now = Instant::now();
+        let mut entries = self.lock();
+        entries.values.insert(key.to_string(), (value.to_string(), now + ttl));
+        entries.written(now);
+        Ok(())
+    }
+}
+
+/// Uses `primary` and switches to a local [`MemoryStore`] when it fails.
+///
+/// After a failure the primary store is left alone for `retry_after` before it is tried again.
+/// Counts kept locally meanwhile are not merged back.
+#[derive(Debug)]
+pub struct FallbackStore<S> {
+    primary: S,
+    fallback: MemoryStore,
+    retry_after: Duration,
+    failed_at: Mutex<Option<Instant>>,
+}
+
+impl<S: StateStore> FallbackStore<S> {
+    /// Retries the primary store 5 seconds after a failure.
+    pub fn new(primary: S) -> Self {
+        FallbackStore { primary, fallback: MemoryStore::new(), retry_after: Duration::from_secs(5), failed_at: Mutex::new(None) }
+    }
+
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Whether the primary store failed within the retry interval.
+    pub fn is_degraded(&self) -> bool {
+        self.failed_at
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .is_some_and(|failed_at| failed_at.elapsed() < self.retry_after)
+    }
+
+    fn run<T>(&self, primary: impl FnOnce(&S) -> Result<T, BotGuardError>, fallback: impl FnOnce(&MemoryStore) -> Result<T, BotGuardError>) -> Result<T, BotGuardError> {
+        if !self.is_degraded() {
+            match primary(&self.primary) {
+                Ok(value) => return Ok(value),
+                Err(_) => *self.failed_at.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Instant::now()),
+            }
+        }
+        fallback(&self.fallback)
+    }
+}
+
+impl<S: StateStore> StateStore for FallbackStore<S> {
+    fn increment(&self, key: &str, ttl: Duration) -> Result<u64, BotGuardError> {
+        self.run(|store| store.increment(key, ttl), |store| store.increment(key, ttl))
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, BotGuardError> {
+        self.run(|store| store.get(key), |store| store.get(key))
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), BotGuardError> {
+        self.run(|store| store.set(key, value, ttl), |store| store.set(key, value, ttl))
+    }
+}
+
+/// Outcome of [`RateLimiter::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    /// Requests counted in the current window, this one included.
+    pub count: u64,
+    /// Time until the window resets.
+    pub reset_after: Duration,
+}
+
+/// Fixed-window rate limiting, e.g. for [`crate::policy::Action::RateLimit`].
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use BotGuardLib::state::{MemoryStore, RateLimiter};
+///
+/// let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+/// let minute = Duration::from_secs(60);
+/// assert!(limiter.check("203.0.113.7", 2, minute).unwrap().allowed);
+/// assert!(limiter.check("203.0.113.7", 2, minute).unwrap().allowed);
+/// assert!(!limiter.check("203.0.113.7", 2, minute).unwrap().allowed);
+/// ```
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn StateStore>,
+    prefix: String,
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter").field("prefix", &self.prefix).finish_non_exhaustive()
+    }
+}
+
+impl RateLimiter {
+    /// Keys are stored under the `botguard:rate:` prefix.
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        RateLimiter { store, prefix: "botguard:rate:".to_string() }
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Counts a request of `key`, allowed while at most `requests` were made in the window.
+    ///
+    /// Windows are aligned to the unix epoch so every instance uses the same ones.
+    pub fn check(&self, key: &str, requests: u32, per: Duration) -> Result<RateLimitDecision, BotGuardError> {
+        let window = per.as_millis().max(1);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let reset_after = Duration::from_millis((window - now % window) as u64);
+        let count = self.store.increment(&format!("{}{}:{}", self.prefix, key, now / window), reset_after)?;
+        Ok(RateLimitDecision { allowed: count <= u64::from(requests), count, reset_after })
+    }
+}
+
+/// Outcome of [`CrawlBudget::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrawlDecision {
+    Allow,
+    /// The crawler came back sooner than the minimum interval, it may retry after this long.
+    Delay(Duration),
+    /// The crawler used up its budget for the window, which resets after `retry_after`.
+    Reject { retry_after: Duration },
+}
+
+impl CrawlDecision {
+    /// The value for a `Retry-After` header on a 429 response, `None` for [`CrawlDecision::Allow`].
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            CrawlDecision::Allow => None,
+            CrawlDecision::Delay(after) | CrawlDecision::Reject { retry_after: after } => Some(*after),
+        }
+    }
+}
+
+/// Request budgets for crawlers that should be slowed down rather than blocked, e.g. verified
+/// search engines, keyed by the crawler's identity like `googlebot`.
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use BotGuardLib::state::{CrawlBudget, CrawlDecision, MemoryStore};
+///
+/// let budget = CrawlBudget::new(Arc::new(MemoryStore::new()), 120).budget("bingbot", 1).min_interval(Duration::from_secs(2));
+/// assert_eq!(budget.check("Googlebot").unwrap(), CrawlDecision::Allow);
+/// assert!(matches!(budget.check("googlebot").unwrap(), CrawlDecision::Delay(_)));
+///
+/// assert_eq!(budget.check("bingbot").unwrap(), CrawlDecision::Allow);
+/// let decision = budget.check("bingbot").unwrap();
+/// assert!(decision.retry_after().is_some_and(|after| after <= Duration::from_secs(60)));
+/// ```
+#[derive(Clone)]
+pub struct CrawlBudget {
+    limiter: RateLimiter,
+    store: Arc<dyn StateStore>,
+    requests: u32,
+    window: Duration,
+    budgets: HashMap<String, u32>,
+    min_interval: Option<Duration>,
+}
+
+impl fmt::Debug for CrawlBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CrawlBudget")
+            .field("requests", &self.requests)
+            .field("window", &self.window)
+            .field("budgets", &self.budgets)
+            .field("min_interval", &self.min_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CrawlBudget {
+    /// Allows every crawler `requests` per minute, keys are stored under `botguard:crawl:`.
+    pub fn new(store: Arc<dyn StateStore>, requests: u32) -> Self {
+        CrawlBudget {
+            limiter: RateLimiter::new(Arc::clone(&store)).with_prefix("botguard:crawl:"),
+            store,
+            requests,
+            window: Duration::from_secs(60),
+            budgets: HashMap::new(),
+            min_interval: None,
+        }
+    }
+
+    /// A budget of its own for one crawler.
+    pub fn budget(mut self, crawler: &str, requests: u32) -> Self {
+        self.budgets.insert(crawler.to_ascii_lowercase(), requests);
+        self
+    }
+
+    /// The window budgets are counted in, a minute by default.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// The shortest time between two requests of a crawler, like a robots.txt crawl-delay.
+    pub fn min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = Some(min_interval);
+        self
+    }
+
+    /// Decides about a request of the crawler. Delayed requests do not count against the budget.
+    pub fn check(&self, crawler: &str) -> Result<CrawlDecision, BotGuardError> {
+        let crawler = crawler.to_ascii_lowercase();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let last_key = format!("botguard:crawl-last:{}", crawler);
+        if let Some(min_interval) = self.min_interval {
+            let last = self.store.get(&last_key)?.and_then(|millis| millis.parse::<u64>().ok()).map(Duration::from_millis);
+            if let Some(elapsed) = last.and_then(|last| now.checked_sub(last)) {
+                if elapsed < min_interval {
+                    return Ok(CrawlDecision::Delay(min_interval - elapsed));
+                }
+            }
+        }
+        let requests = self.budgets.get(&crawler).copied().unwrap_or(self.requests);
+        let decision = self.limiter.check(&crawler, requests, self.window)?;
+        if !decision.allowed {
+            return Ok(CrawlDecision::Reject { retry_after: decision.reset_after });
+        }
+        if let Some(min_interval) = self.min_interval {
+            self.store.set(&last_key, &now.as_millis().to_string(), min_interval)?;
+        }
+        Ok(CrawlDecision::Allow)
+    }
+}
+
+/// Counts the requests of sessions, a session is forgotten `ttl` after its first request.
+#[derive(Clone)]
+pub struct SessionTracker {
+    store: Arc<dyn StateStore>,
+    ttl: Duration,
+}
+
+impl SessionTracker {
+    pub fn new(store: Arc<dyn StateStore>, ttl: Duration) -> Self {
+        SessionTracker { store, ttl }
+    }
+
+    /// Records a request and returns the number of requests of the session so far.
+    pub fn record(&self, session: &str) -> Result<u64, BotGuardError> {
+        self.store.increment(&format!("botguard:session:{}", session), self.ttl)
+    }
+
+    pub fn requests(&self, session: &str) -> Result<u64, BotGuardError> {
+        let count = self.store.get(&format!("botguard:session:{}", session))?;
+        Ok(count.and_then(|count| count.parse().ok()).unwrap_or(0))
+    }
+}
+
+/// Verdicts by user-agent, keyed by a hash so user-agents are not stored.
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use BotGuardLib::state::{MemoryStore, VerdictCache};
+/// use BotGuardLib::{BotDetector, Verdict};
+///
+/// let cache = VerdictCache::new(Arc::new(MemoryStore::new()), Duration::from_secs(300));
+/// let detector = BotDetector::default();
+/// let verdict = cache.get_or_check("curl/8.4.0", |ua| detector.check(ua)).unwrap();
+/// assert_eq!(verdict, Verdict::Bot);
+/// assert_eq!(cache.get("curl/8.4.0").unwrap(), Some(Verdict::Bot));
+/// ```
+#[derive(Clone)]
+pub struct VerdictCache {
+    store: Arc<dyn StateStore>,
+    ttl: Duration,
+}
+
+impl VerdictCache {
+    pub fn new(store: Arc<dyn StateStore>, ttl: Duration) -> Self {
+        VerdictCache { store, ttl }
+    }
+
+    fn key(user_agent: &str) -> String {
+        let hash = crypto::sha512(user_agent.as_bytes())[..16].iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        format!("botguard:verdict:{}", hash)
+    }
+
+    pub fn get(&self, user_agent: &str) -> Result<Option<Verdict>, BotGuardError> {
+        Ok(self.store.get(&VerdictCache::key(user_agent))?.and_then(|value| match value.as_str() {
+            "human" => Some(Verdict::Human),
+            "suspicious" => Some(Verdict::Suspicious),
+            "bot" => Some(Verdict::Bot),
+            _ => None,
+        }))
+    }
+
+    pub fn insert(&self, user_agent: &str, verdict: Verdict) -> Result<(), BotGuardError> {
+        let value = match verdict {
+            Verdict::Human => "human",
+            Verdict::Suspicious => "suspicious",
+            Verdict::Bot => "bot",
+        };
+        self.store.set(&VerdictCache::key(user_agent), value, self.ttl)
+    }
+
+    /// Returns the cached verdict, or runs `check` and caches its verdict.
+    pub fn get_or_check(&self, user_agent: &str, check: impl FnOnce(&str) -> Verdict) -> Result<Verdict, BotGuardError> {
+        if let Some(verdict) = self.get(user_agent)? {
+            return Ok(verdict);
+        }
+        let verdict = check(user_agent);
+        self.insert(user_agent, verdict)?;
+        Ok(verdict)
+    }
+}
+
+/// The scores of clients confirmed as bots, decaying by half every `half_life` and forgotten
+/// after the TTL, eight half-lives unless set. See [`crate::request::BotGuard::client_memory`].
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use BotGuardLib::state::{ClientMemory, MemoryStore};
+///
+/// let memory = ClientMemory::new(Arc::new(MemoryStore::new()), Duration::from_secs(3600));
+/// assert_eq!(memory.recall("203.0.113.7").unwrap(), None);
+/// memory.remember("203.0.113.7", 0.9).unwrap();
+/// let score = memory.recall("203.0.113.7").unwrap().unwrap();
+/// assert!(score > 0.89 && score <= 0.9);
+/// ```
+#[derive(Clone)]
+pub struct ClientMemory {
+    store: Arc<dyn StateStore>,
+    half_life: Duration,
+    ttl: Duration,
+}
+
+impl fmt::Debug for ClientMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientMemory").field("half_life", &self.half_life).field("ttl", &self.ttl).finish_non_exhaustive()
+    }
+}
+
+impl ClientMemory {
+    /// Keys are stored under the `botguard:client:` prefix.
+    pub fn new(store: Arc<dyn StateStore>, half_life: Duration) -> Self {
+        ClientMemory { store, half_life, ttl: half_life * 8 }
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn key(client: &str) -> String {
+        format!("botguard:client:{}", client)
+    }
+
+    /// Stores the score of a client, unless its remembered score is still higher. The TTL starts
+    /// over.
+    pub fn remember(&self, client: &str, score: f32) -> Result<(), BotGuardError> {
+        let now = now_millis();
+        let score = self.decayed(client, now)?.map_or(score, |remembered| remembered.max(score));
+        self.store.set(&ClientMemory::key(client), &format!("{}@{}", score, now), self.ttl)
+    }
+
+    /// The decayed score of a client, `None` if it is not remembered.
+    pub fn recall(&self, client: &str) -> Result<Option<f32>, BotGuardError> {
+        self.decayed(client, now_millis())
+    }
+
+    fn decayed(&self, client: &str, now: u128) -> Result<Option<f32>, BotGuardError> {
+        let value = self.store.get(&ClientMemory::key(client))?;
+        let Some((score, at)) = value.as_deref().and_then(|value| value.split_once('@')) else {
+            return Ok(None);
+        };
+        let (Ok(score), Ok(at)) = (score.parse::<f32>(), at.parse::<u128>()) else {
+            return Ok(None);
+        };
+        let half_lives = now.saturating_sub(at) as f64 / self.half_life.as_millis().max(1) as f64;
+        Ok(Some(score * 0.5f64.powf(half_lives) as f32))
+    }
+}
+
+/// Verdicts set by hand for clients the checks misjudge, e.g. to unblock a customer right away
+/// while the patterns are fixed. Keyed by address or else `tls:<fingerprint>`, see
+/// [`crate::request::RequestSnapshot::client_key`], and kept in the store so every instance
+/// sees them. An override of a fingerprint applies to every client of that browser build. See
+/// [`crate::request::BotGuard::overrides`].
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use BotGuardLib::state::{ClientOverrides, MemoryStore};
+/// use BotGuardLib::Verdict;
+///
+/// let overrides = ClientOverrides::new(Arc::new(MemoryStore::new()));
+/// overrides.mark_human("203.0.113.7", Duration::from_secs(86400)).unwrap();
+/// assert_eq!(overrides.get("203.0.113.7").unwrap(), Some(Verdict::Human));
+/// overrides.clear("203.0.113.7").unwrap();
+/// assert_eq!(overrides.get("203.0.113.7").unwrap(), None);
+/// ```
+#[derive(Clone)]
+pub struct ClientOverrides {
+    store: Arc<dyn StateStore>,
+}
+
+impl fmt::Debug for ClientOverrides {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientOverrides").finish_non_exhaustive()
+    }
+}
+
+impl ClientOverrides {
+    /// Keys are stored under the `botguard:override:` prefix.
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        ClientOverrides { store }
+    }
+
+    fn key(client: &str) -> String {
+        format!("botguard:override:{}", client)
+    }
+
+    /// Judges the client human for `ttl`, replacing any earlier override.
+    pub fn mark_human(&self, client: &str, ttl: Duration) -> Result<(), BotGuardError> {
+        self.store.set(&ClientOverrides::key(client), "human", ttl)
+    }
+
+    /// Judges the client a bot for `ttl`, replacing any earlier override.
+    pub fn mark_bot(&self, client: &str, ttl: Duration) -> Result<(), BotGuardError> {
+        self.store.set(&ClientOverrides::key(client), "bot", ttl)
+    }
+
+    /// Removes the override of the client, its requests are evaluated again.
+    pub fn clear(&self, client: &str) -> Result<(), BotGuardError> {
+        self.store.set(&ClientOverrides::key(client), "", Duration::ZERO)
+    }
+
+    /// The verdict set for the client, `None` without an unexpired override.
+    pub fn get(&self, client: &str) -> Result<Option<Verdict>, BotGuardError> {
+        Ok(self.store.get(&ClientOverrides::key(client))?.and_then(|value| match value.as_str() {
+            "human" => Some(Verdict::Human),
+            "bot" => Some(Verdict::Bot),
+            _ => None,
+        }))
+    }
+}
+
+/// Counts of [`NonceStore::redeem`] calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NonceStats {
+    pub redeemed: u64,
+    /// Nonces presented again while still remembered.
+    pub replays: u64,
+}
+
+/// Single-use tokens: a solved challenge or a fingerprint payload is only accepted the first
+/// time its nonce is presented. Nonces are remembered for the TTL, which has to outlast the
+/// tokens they protect, in a [`MemoryStore`] per process or a `RedisStore` for every instance.
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use BotGuardLib::state::{MemoryStore, NonceStore};
+///
+/// let nonces = NonceStore::new(Arc::new(MemoryStore::new()), Duration::from_secs(600));
+/// assert!(nonces.redeem("1718000000000.9f2c41d07be8a3e5").unwrap());
+/// assert!(!nonces.redeem("1718000000000.9f2c41d07be8a3e5").unwrap());
+/// assert_eq!((nonces.stats().redeemed, nonces.stats().replays), (1, 1));
+/// ```
+#[derive(Clone)]
+pub struct NonceStore {
+    store: Arc<dyn StateStore>,
+    ttl: Duration,
+    redeemed: Arc<AtomicU64>,
+    replays: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for NonceStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NonceStore").field("ttl", &self.ttl).field("stats", &self.stats()).finish_non_exhaustive()
+    }
+}
+
+impl NonceStore {
+    /// Keys are stored under the `botguard:nonce:` prefix, clones share their statistics.
+    pub fn new(store: Arc<dyn StateStore>, ttl: Duration) -> Self {
+        NonceStore { store, ttl, redeemed: Arc::default(), replays: Arc::default() }
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn key(nonce: &str) -> String {
+        let hash = crypto::sha512(nonce.as_bytes())[..16].iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        format!("botguard:nonce:{}", hash)
+    }
+
+    /// Whether this is the first time the nonce is presented within the TTL. Two instances
+    /// redeeming the same nonce at once cannot both succeed, the store counts atomically.
+    pub fn redeem(&self, nonce: &str) -> Result<bool, BotGuardError> {
+        let first = self.store.increment(&NonceStore::key(nonce), self.ttl)? == 1;
+        match first {
+            true => self.redeemed.fetch_add(1, Ordering::Relaxed),
+            false => self.replays.fetch_add(1, Ordering::Relaxed),
+        };
+        Ok(first)
+    }
+
+    /// Counts of this process.
+    pub fn stats(&self) -> NonceStats {
+        NonceStats { redeemed: self.redeemed.load(Ordering::Relaxed), replays: self.replays.load(Ordering::Relaxed) }
+    }
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Fails while `down` is set.
+    struct Flaky {
+        store: MemoryStore,
+        down: AtomicBool,
+    }
+
+    impl Flaky {
+        fn check(&self) -> Result<(), BotGuardError> {
+            match self.down.load(Ordering::SeqCst) {
+                true => Err(BotGuardError::StateUnavailable { backend: "flaky".to_string(), reason: "down".to_string() }),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl StateStore for Flaky {
+        fn increment(&self, key: &str, ttl: Duration) -> Result<u64, BotGuardError> {
+            self.check().and_then(|()| self.store.increment(key, ttl))
+        }
+
+        fn get(&self, key: &str) -> Result<Option<String>, BotGuardError> {
+            self.check().and_then(|()| self.store.get(key))
+        }
+
+        fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), BotGuardError> {
+            self.check().and_then(|()| self.store.set(key, value, ttl))
+        }
+    }
+
+    #[test]
+    fn memory_entries_expire() {
+        let store = MemoryStore::new();
+        assert_eq!(store.increment("a", Duration::from_millis(30)).unwrap(), 1);
+        assert_eq!(store.increment("a", Duration::from_secs(60)).unwrap(), 2);
+        store.set("b", "x", Duration::from_secs(60)).unwrap();
+        assert_eq!(store.get("b").unwrap().as_deref(), Some("x"));
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(store.get("a").unwrap(), None);
+        assert_eq!(store.increment("a", Duration::from_secs(60)).unwrap(), 1);
+
+        for i in 0..SWEEP_INTERVAL {
+            store.set(&format!("short{}", i), "", Duration::ZERO).unwrap();
+        }
+        assert!(store.len() < SWEEP_INTERVAL as usize);
+    }
+
+    #[test]
+    fn falls_back_while_the_primary_is_down() {
+        let store = FallbackStore::new(Flaky { store: MemoryStore::new(), down: AtomicBool::new(false) }).retry_after(Duration::from_millis(30));
+        let tracker = SessionTracker::new(Arc::new(store), Duration::from_secs(60));
+        assert_eq!(tracker.record("s1").unwrap(), 1);
+
+        let store = FallbackStore::new(Flaky { store: MemoryStore::new(), down: AtomicBool::new(true) }).retry_after(Duration::from_millis(30));
+        assert_eq!(store.increment("k", Duration::from_secs(60)).unwrap(), 1);
+        assert!(store.is_degraded());
+        store.primary.down.store(false, Ordering::SeqCst);
+        assert_eq!(store.increment("k", Duration::from_secs(60)).unwrap(), 2);
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(store.increment("k", Duration::from_secs(60)).unwrap(), 1);
+        assert!(!store.is_degraded());
+    }
+
+    #[test]
+    fn rate_limits_and_caches_verdicts() {
+        let store: Arc<dyn StateStore> = Arc::new(MemoryStore::new());
+        let limiter = RateLimiter::new(Arc::clone(&store)).with_prefix("test:");
+        // a century long window, so no window boundary falls between the checks
+        let century = Duration::from_secs(100 * 365 * 86400);
+        let decisions = (0..3).map(|_| limiter.check("client", 2, century).unwrap()).collect::<Vec<_>>();
+        assert_eq!(decisions.iter().map(|d| (d.allowed, d.count)).collect::<Vec<_>>(), vec![(true, 1), (true, 2), (false, 3)]);
+        assert!(decisions[2].reset_after <= century);
+        assert!(limiter.check("other", 2, century).unwrap().allowed);
+
+        let cache = VerdictCache::new(store, Duration::from_secs(60));
+        assert_eq!(cache.get("Mozilla/5.0").unwrap(), None);
+        assert_eq!(cache.get_or_check("Mozilla/5.0", |_| Verdict::Suspicious).unwrap(), Verdict::Suspicious);
+        assert_eq!(cache.get_or_check("Mozilla/5.0", |_| unreachable!()).unwrap(), Verdict::Suspicious);
+    }
+
+    #[test]
+    fn client_scores_decay() {
+        let store: Arc<dyn StateStore> = Arc::new(MemoryStore::new());
+        let memory = ClientMemory::new(Arc::clone(&store), Duration::from_secs(60));
+        let minute_ago = now_millis() - 60_000;
+        store.set("botguard:client:a", &format!("0.8@{}", minute_ago), Duration::from_secs(60)).unwrap();
+        let decayed = memory.recall("a").unwrap().unwrap();
+        assert!((decayed - 0.4).abs() < 0.01, "{}", decayed);
+        memory.remember("a", 0.3).unwrap();
+        assert!(memory.recall("a").unwrap().unwrap() > 0.39);
+        memory.remember("a", 0.9).unwrap();
+        assert!(memory.recall("a").unwrap().unwrap() > 0.89);
+
+        store.set("botguard:client:b", "garbage", Duration::from_secs(60)).unwrap();
+        assert_eq!(memory.recall("b").unwrap(), None);
+        let forgetful = ClientMemory::new(store, Duration::from_secs(60)).ttl(Duration::from_millis(20));
+        forgetful.remember("c", 1.0).unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(forgetful.recall("c").unwrap(), None);
+    }
+
+    #[test]
+    fn enforces_crawl_budgets() {
+        let century = Duration::from_secs(100 * 365 * 86400);
+        let budget = CrawlBudget::new(Arc::new(MemoryStore::new()), 3).window(century).budget("YandexBot", 1);
+        let decisions = (0..4).map(|_| budget.check("googlebot").unwrap()).collect::<Vec<_>>();
+        assert_eq!(&decisions[..3], [CrawlDecision::Allow; 3]);
+        assert!(matches!(decisions[3], CrawlDecision::Reject { retry_after } if retry_after > Duration::from_secs(86400)));
+        assert_eq!(budget.check("yandexbot").unwrap(), CrawlDecision::Allow);
+        assert!(matches!(budget.check("yandexbot").unwrap(), CrawlDecision::Reject { .. }));
+        assert_eq!(CrawlDecision::Allow.retry_after(), None);
+
+        let spaced = CrawlBudget::new(Arc::new(MemoryStore::new()), 10).window(century).min_interval(Duration::from_millis(50));
+        assert_eq!(spaced.check("bingbot").unwrap(), CrawlDecision::Allow);
+        let CrawlDecision::Delay(wait) = spaced.check("bingbot").unwrap() else { panic!() };
+        assert!(wait <= Duration::from_millis(50));
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(spaced.check("bingbot").unwrap(), CrawlDecision::Allow);
+    }
+}
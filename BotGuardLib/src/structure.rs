@@ -0,0 +1,190 @@
+// Structural checks of user-agents, independent of the loaded patterns: random blobs generated to
+// defeat lists, strings no client would send for their length, and browser tokens assembled
+// without the frame every browser puts around them.
+
+use std::fmt;
+
+/// User-agents shorter than this, in bytes, are too short for any real client.
+const MIN_LEN: usize = 5;
+
+/// User-agents longer than this are padded, browsers with many extensions stay far below.
+const MAX_LEN: usize = 768;
+
+/// Runs of hexadecimal digits from this length on look generated.
+const HEX_RUN: usize = 16;
+
+/// Other alphanumeric tokens from this length on are checked for their entropy.
+const RANDOM_TOKEN: usize = 24;
+
+/// Bits per character from which an alphanumeric token looks random, English words and version
+/// strings stay well below.
+const RANDOM_ENTROPY: f64 = 4.0;
+
+/// Tokens only browsers send, always after a `Mozilla/` prefix.
+const BROWSER_TOKENS: &[&str] = &["applewebkit/", "gecko/", "chrome/", "safari/", "firefox/"];
+
+/// A structural oddity found by [`structural_issues`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StructuralIssue {
+    /// Fewer bytes than any real client sends.
+    TooShort { len: usize },
+    /// More bytes than any browser sends.
+    TooLong { len: usize },
+    /// A long run of hex digits or a high entropy token, like a hash or random padding.
+    RandomToken(String),
+    /// A browser token like `AppleWebKit/` without the `Mozilla/` prefix.
+    BrowserTokenWithoutMozilla(String),
+    /// More opening than closing parentheses or the other way round.
+    UnbalancedParentheses,
+}
+
+impl StructuralIssue {
+    /// Contribution of the issue to [`ua_anomaly_score`].
+    pub fn weight(&self) -> f32 {
+        match self {
+            StructuralIssue::TooShort { .. } => 0.5,
+            StructuralIssue::TooLong { .. } => 0.4,
+            StructuralIssue::RandomToken(_) => 0.5,
+            StructuralIssue::BrowserTokenWithoutMozilla(_) => 0.6,
+            StructuralIssue::UnbalancedParentheses => 0.4,
+        }
+    }
+}
+
+impl fmt::Display for StructuralIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StructuralIssue::TooShort { len } => write!(f, "user-agent of only {} bytes", len),
+            StructuralIssue::TooLong { len } => write!(f, "user-agent of {} bytes", len),
+            StructuralIssue::RandomToken(token) => write!(f, "random looking token {}", token),
+            StructuralIssue::BrowserTokenWithoutMozilla(token) => write!(f, "{} without Mozilla/ prefix", token),
+            StructuralIssue::UnbalancedParentheses => f.write_str("unbalanced parentheses"),
+        }
+    }
+}
+
+/// Finds structural oddities in a user-agent, in the order of [`StructuralIssue`]'s variants.
+/// Empty user-agents have none, they are left to the [`crate::EmptyUaPolicy`].
+///
+/// ```
+/// use BotGuardLib::structure::{structural_issues, StructuralIssue};
+///
+/// let chrome = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+/// assert!(structural_issues(chrome).is_empty());
+/// assert_eq!(
+///     structural_issues("AppleWebKit/537.36 (KHTML, like Gecko 9f86d081884c7d659a2feaa0c55ad015"),
+///     vec![
+///         StructuralIssue::RandomToken("9f86d081884c7d659a2feaa0c55ad015".to_string()),
+///         StructuralIssue::BrowserTokenWithoutMozilla("AppleWebKit/".to_string()),
+///         StructuralIssue::UnbalancedParentheses,
+///     ]
+/// );
+/// ```
+pub fn structural_issues(user_agent: &str) -> Vec<StructuralIssue> {
+    let user_agent = user_agent.trim();
+    let mut found = Vec::new();
+    if user_agent.is_empty() {
+        return found;
+    }
+    if user_agent.len() < MIN_LEN {
+        found.push(StructuralIssue::TooShort { len: user_agent.len() });
+    }
+    if user_agent.len() > MAX_LEN {
+        found.push(StructuralIssue::TooLong { len: user_agent.len() });
+    }
+    if let Some(token) = user_agent.split(|c: char| !c.is_ascii_alphanumeric()).find(|token| is_random(token)) {
+        found.push(StructuralIssue::RandomToken(token.to_string()));
+    }
+    let lowercase = user_agent.to_ascii_lowercase();
+    if !lowercase.starts_with("mozilla/") {
+        let browser_token = BROWSER_TOKENS.iter().filter_map(|token| lowercase.find(token)).min();
+        if let Some(start) = browser_token {
+            let token = &user_agent[start..];
+            found.push(StructuralIssue::BrowserTokenWithoutMozilla(token[..=token.find('/').unwrap_or_default()].to_string()));
+        }
+    }
+    let mut depth = 0i32;
+    for c in user_agent.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth < 0 {
+            break;
+        }
+    }
+    if depth != 0 {
+        found.push(StructuralIssue::UnbalancedParentheses);
+    }
+    found
+}
+
+/// How odd the structure of a user-agent is, between `0.0` and `1.0`, without consulting any
+/// pattern. Issues add up like independent chances, two of weight `0.5` score `0.75`.
+///
+/// ```
+/// use BotGuardLib::ua_anomaly_score;
+///
+/// assert_eq!(ua_anomaly_score("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0"), 0.0);
+/// assert_eq!(ua_anomaly_score("x"), 0.5);
+/// assert!(ua_anomaly_score("Chrome/120.0 (Windows NT 10.0 a3f7c9e2b14d6f80e5a2c7b9") > 0.8);
+/// ```
+pub fn ua_anomaly_score(user_agent: &str) -> f32 {
+    let human = structural_issues(user_agent).iter().fold(1.0, |human, issue| human * (1.0 - issue.weight()));
+    1.0 - human
+}
+
+/// Whether an alphanumeric token looks generated rather than written.
+fn is_random(token: &str) -> bool {
+    let has_digit = token.bytes().any(|b| b.is_ascii_digit());
+    let has_letter = token.bytes().any(|b| b.is_ascii_alphabetic());
+    if !has_digit || !has_letter {
+        return false;
+    }
+    if token.len() >= HEX_RUN && token.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return true;
+    }
+    token.len() >= RANDOM_TOKEN && entropy(token) >= RANDOM_ENTROPY
+}
+
+/// Shannon entropy of the bytes of a token, in bits per byte.
+fn entropy(token: &str) -> f64 {
+    let mut counts = [0u32; 256];
+    for b in token.bytes() {
+        counts[usize::from(b)] += 1;
+    }
+    let len = token.len() as f64;
+    counts.iter().filter(|&&count| count > 0).map(|&count| f64::from(count) / len).map(|p| -p * p.log2()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn real_clients_are_well_formed() {
+        let clients = [
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Mobile/15E148 Safari/604.1",
+            "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)",
+            "Dalvik/2.1.0 (Linux; U; Android 8.0.0; SM-G930F Build/R16NW)",
+            "curl/8.4.0",
+            "okhttp/4.9.0",
+            "",
+        ];
+        for user_agent in clients {
+            assert_eq!(structural_issues(user_agent), Vec::new(), "{}", user_agent);
+        }
+    }
+
+    #[test]
+    fn odd_structures_are_scored() {
+        let padded = format!("Mozilla/5.0 ({})", "x; ".repeat(300));
+        assert_eq!(structural_issues(&padded), vec![StructuralIssue::TooLong { len: padded.len() }]);
+        assert_eq!(structural_issues("Mozilla/5.0 (X11))"), vec![StructuralIssue::UnbalancedParentheses]);
+        assert_eq!(structural_issues("kR8vQz2LmX9pT4wY7nB3cF6hJ1dG5sAe"), vec![StructuralIssue::RandomToken("kR8vQz2LmX9pT4wY7nB3cF6hJ1dG5sAe".to_string())]);
+        assert!(structural_issues("Perl/5.36.0 libwww-perl/6.72 AbCdEfGhIjKlMnOpQrStUvWxYz").is_empty());
+        assert!((ua_anomaly_score("Safari/605.1.15") - 0.6).abs() < 1e-6);
+        assert_eq!(StructuralIssue::UnbalancedParentheses.to_string(), "unbalanced parentheses");
+    }
+}
@@ -0,0 +1,321 @@
+// Candidate patterns learned from the user-agents of blocked or high-scoring requests that no
+// pattern matches yet, e.g. bots caught by their address or by the heuristic score, for an
+// operator to review instead of curating the lists by hand.
+//
+// User-agents are split into product tokens (`scrapy/` of `Scrapy/2.11`), words and the hosts of
+// contact URLs. Tokens shared by many observed user-agents become candidates, each estimated
+// against a corpus of human user-agents for how many real users it would hit. Nothing is added
+// to a detector unless a suggestion is accepted.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::events::BotEvent;
+use crate::policy::Action;
+use crate::review::DetectionRecord;
+use crate::{BotDetector, BotGuardError};
+
+/// Group accepted suggestions are appended to.
+pub const SUGGESTED_GROUP: &str = "suggested";
+
+/// A candidate pattern, see [`PatternSuggester::suggest`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    /// A literal pattern, escaped for use as a regex.
+    pub pattern: String,
+    /// Observed requests with a user-agent containing the pattern.
+    pub events: u64,
+    /// A few of these user-agents.
+    pub samples: Vec<String>,
+    /// Share of the human corpus the pattern matches, `0.0` without a corpus.
+    pub collateral: f64,
+}
+
+#[derive(Debug, Default)]
+struct Observed {
+    /// Requests per user-agent, lowercased.
+    counts: HashMap<String, u64>,
+    rejected: HashSet<String>,
+}
+
+/// Collects user-agents of bot detections and proposes literal patterns for them.
+///
+/// ```
+/// use BotGuardLib::suggest::PatternSuggester;
+/// use BotGuardLib::BotDetector;
+///
+/// let suggester = PatternSuggester::new()
+///     .min_events(3)
+///     .human_corpus("Mozilla/5.0 (Windows NT 10.0; Win64; x64) Chrome/120.0\nMozilla/5.0 (compatible; MSIE 10.0; Windows NT 6.2)");
+/// for version in ["1.2", "1.3", "1.3"] {
+///     suggester.observe(&format!("Mozilla/5.0 (compatible; ShadowCrawler/{}; +https://shadow.example)", version), 0.9);
+/// }
+/// suggester.observe("Mozilla/5.0 (Windows NT 10.0; Win64; x64) Firefox/120.0", 0.1);
+///
+/// let mut BotDetector = BotDetector::new("googlebot");
+/// let suggestions = suggester.suggest(&BotDetector);
+/// assert_eq!(suggestions.len(), 1);
+/// assert_eq!((suggestions[0].pattern.as_str(), suggestions[0].events), ("shadowcrawler/", 3));
+///
+/// suggester.accept(&mut BotDetector, &suggestions[0].pattern).unwrap();
+/// assert!(BotDetector.check_bot("ShadowCrawler/2.0"));
+/// assert!(suggester.suggest(&BotDetector).is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct PatternSuggester {
+    observed: Arc<Mutex<Observed>>,
+    human: Arc<Vec<String>>,
+    min_score: f32,
+    min_events: u64,
+    max_collateral: f64,
+    capacity: usize,
+}
+
+impl Default for PatternSuggester {
+    fn default() -> Self {
+        PatternSuggester::new()
+    }
+}
+
+impl PatternSuggester {
+    /// Observes scores of at least `0.8` and suggests tokens of at least 5 requests hitting no
+    /// human user-agent, keeping up to 10,000 distinct user-agents.
+    pub fn new() -> Self {
+        PatternSuggester {
+            observed: Arc::default(),
+            human: Arc::default(),
+            min_score: 0.8,
+            min_events: 5,
+            max_collateral: 0.0,
+            capacity: 10_000,
+        }
+    }
+
+    /// Lowest score of the user-agents taken into account by [`PatternSuggester::observe`].
+    pub fn min_score(mut self, score: f32) -> Self {
+        self.min_score = score;
+        self
+    }
+
+    /// Fewest observed requests a suggestion has to cover.
+    pub fn min_events(mut self, events: u64) -> Self {
+        self.min_events = events.max(1);
+        self
+    }
+
+    /// Largest share of the human corpus a suggestion may match.
+    pub fn max_collateral(mut self, share: f64) -> Self {
+        self.max_collateral = share;
+        self
+    }
+
+    /// Most distinct user-agents kept, user-agents seen for the first time are ignored beyond.
+    pub fn capacity(mut self, user_agents: usize) -> Self {
+        self.capacity = user_agents;
+        self
+    }
+
+    /// Human user-agents to estimate the collateral with, one per line. Blank lines and lines
+    /// starting with `#` are skipped.
+    pub fn human_corpus(mut self, corpus: &str) -> Self {
+        let lines = corpus.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+        Arc::make_mut(&mut self.human).extend(lines.map(str::to_ascii_lowercase));
+        self
+    }
+
+    /// Adds the browser user-agents of the bundled corpus, see [`crate::tester::evaluate`].
+    #[cfg(feature = "corpus")]
+    pub fn bundled_corpus(self) -> Self {
+        let mut human = false;
+        let mut lines = String::new();
+        for line in crate::tester::_CORPUS.lines().map(str::trim) {
+            match line {
+                "[human]" => human = true,
+                _ if line.starts_with('[') && line.ends_with(']') => human = false,
+                _ if human => {
+                    lines.push_str(line);
+                    lines.push('\n');
+                }
+                _ => {}
+            }
+        }
+        self.human_corpus(&lines)
+    }
+
+    /// Records a request with its bot score, ignored below the [`PatternSuggester::min_score`].
+    pub fn observe(&self, user_agent: &str, score: f32) {
+        if score < self.min_score {
+            return;
+        }
+        let mut observed = self.observed();
+        let user_agent = user_agent.trim().to_ascii_lowercase();
+        if user_agent.is_empty() || (observed.counts.len() >= self.capacity && !observed.counts.contains_key(&user_agent)) {
+            return;
+        }
+        *observed.counts.entry(user_agent).or_default() += 1;
+    }
+
+    /// Records a detection event, which has the score of a bot.
+    pub fn observe_event(&self, event: &BotEvent) {
+        self.observe(&event.user_agent, 1.0);
+    }
+
+    /// Records a reviewed decision, blocked requests count whatever their score.
+    pub fn observe_record(&self, record: &DetectionRecord) {
+        let score = match record.action {
+            Action::Block => 1.0,
+            _ => record.score,
+        };
+        self.observe(&record.user_agent, score);
+    }
+
+    /// A hook for [`BotDetector::on_detection`] observing every event.
+    pub fn hook(&self) -> impl FnMut(&BotEvent) + Send + 'static {
+        let suggester = self.clone();
+        move |event| suggester.observe_event(event)
+    }
+
+    /// Number of distinct user-agents observed.
+    pub fn len(&self) -> usize {
+        self.observed().counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Candidate patterns for the observed user-agents the detector does not match, those
+    /// covering the most requests first. Among equals those with less collateral win, then
+    /// product tokens over contact hosts over words.
+    ///
+    /// A candidate is left out if the candidates before it already cover all of its user-agents,
+    /// the version-less `scrapy/` and the contact host `scrapy.org` are rarely both needed.
+    pub fn suggest(&self, detector: &BotDetector) -> Vec<Suggestion> {
+        let observed = self.observed();
+        let unmatched = observed.counts.iter().filter(|(user_agent, _)| detector.find_match(user_agent).is_none()).collect::<Vec<_>>();
+        let mut covering: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, (user_agent, _)) in unmatched.iter().enumerate() {
+            for token in tokens(user_agent) {
+                covering.entry(token).or_default().push(i);
+            }
+        }
+        let mut candidates = covering
+            .into_iter()
+            .filter(|(token, _)| !observed.rejected.contains(token))
+            .filter_map(|(token, covered)| {
+                let events = covered.iter().map(|&i| *unmatched[i].1).sum::<u64>();
+                if events < self.min_events {
+                    return None;
+                }
+                let collateral = self.collateral(&token);
+                (collateral <= self.max_collateral).then_some((token, covered, events, collateral))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| b.2.cmp(&a.2).then(a.3.total_cmp(&b.3)).then(kind(&a.0).cmp(&kind(&b.0))).then_with(|| a.0.cmp(&b.0)));
+
+        let mut covered_before = HashSet::new();
+        let mut suggestions = Vec::new();
+        for (token, covered, events, collateral) in candidates {
+            if covered.iter().all(|i| covered_before.contains(i)) {
+                continue;
+            }
+            covered_before.extend(covered.iter().copied());
+            let mut samples = covered.iter().map(|&i| unmatched[i].0.clone()).collect::<Vec<_>>();
+            samples.sort();
+            samples.truncate(3);
+            suggestions.push(Suggestion { pattern: regex::escape(&token), events, samples, collateral });
+        }
+        suggestions
+    }
+
+    /// Appends a suggested pattern to the [`SUGGESTED_GROUP`] of the detector and forgets the
+    /// user-agents it matches. Fails like [`BotDetector::append_to_group`].
+    pub fn accept(&self, detector: &mut BotDetector, pattern: &str) -> Result<(), BotGuardError> {
+        detector.append_to_group(SUGGESTED_GROUP, &[pattern])?;
+        self.observed().counts.retain(|user_agent, _| detector.find_match(user_agent).is_none());
+        Ok(())
+    }
+
+    /// Never suggests the pattern again.
+    pub fn reject(&self, pattern: &str) {
+        let token = pattern.replace('\\', "").to_ascii_lowercase();
+        self.observed().rejected.insert(token);
+    }
+
+    fn collateral(&self, token: &str) -> f64 {
+        if self.human.is_empty() {
+            return 0.0;
+        }
+        let hits = self.human.iter().filter(|user_agent| user_agent.contains(token)).count();
+        hits as f64 / self.human.len() as f64
+    }
+
+    fn observed(&self) -> MutexGuard<'_, Observed> {
+        self.observed.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The product tokens, words and contact hosts of a lowercased user-agent with at least three
+/// letters, each once.
+fn tokens(user_agent: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    for piece in user_agent.split(|c: char| c.is_whitespace() || ";()[],\"".contains(c)) {
+        let piece = piece.trim_start_matches('+');
+        let token = match piece.split_once("://") {
+            Some((_, rest)) => rest.split('/').next().unwrap_or_default().trim_start_matches("www.").to_string(),
+            None => match piece.split_once('/') {
+                Some((name, _)) => format!("{}/", name),
+                None => piece.to_string(),
+            },
+        };
+        if token.chars().filter(char::is_ascii_alphabetic).count() >= 3 && !found.contains(&token) {
+            found.push(token);
+        }
+    }
+    found
+}
+
+/// Product tokens before hosts before words.
+fn kind(token: &str) -> u8 {
+    match token {
+        _ if token.ends_with('/') => 0,
+        _ if token.contains('.') => 1,
+        _ => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_of_user_agents() {
+        let tokens = tokens("mozilla/5.0 (compatible; scrapy/2.11; +https://www.scrapy.org/bot) x64 v2");
+        assert_eq!(tokens, vec!["mozilla/", "compatible", "scrapy/", "scrapy.org"]);
+    }
+
+    #[test]
+    fn collateral_and_rejections_filter_candidates() {
+        let suggester = PatternSuggester::new()
+            .min_events(2)
+            .min_score(0.5)
+            .human_corpus("# browsers\nMozilla/5.0 (X11; Linux x86_64) Firefox/120.0\nMozilla/5.0 (Macintosh) Safari/605.1.15");
+        suggester.observe("Mozilla/5.0 (X11; Linux x86_64) DataHarvester/3.1", 0.9);
+        suggester.observe("Mozilla/5.0 (X11; Linux x86_64) DataHarvester/3.2", 0.6);
+        suggester.observe("Mozilla/5.0 (X11; Linux x86_64) Firefox/121.0", 0.2);
+        suggester.observe("Googlebot/2.1", 1.0);
+        suggester.observe("Googlebot/2.1", 1.0);
+        assert_eq!(suggester.len(), 3);
+
+        let detector = BotDetector::new("googlebot");
+        let patterns = suggester.suggest(&detector).into_iter().map(|suggestion| suggestion.pattern).collect::<Vec<_>>();
+        assert_eq!(patterns, vec!["dataharvester/"]);
+        suggester.reject("DataHarvester/");
+        assert!(suggester.suggest(&detector).is_empty());
+
+        let lenient = suggester.clone().max_collateral(0.5);
+        let suggestions = lenient.suggest(&detector);
+        assert_eq!(suggestions.iter().map(|suggestion| suggestion.pattern.as_str()).collect::<Vec<_>>(), vec!["linux"]);
+        assert_eq!(suggestions[0].collateral, 0.5);
+    }
+}
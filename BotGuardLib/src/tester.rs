@@ -0,0 +1,423 @@
+// Validation of a pattern set against labeled user-agents, e.g. in CI before a list update is
+// deployed: how many bots it catches, how many humans it flags, and which patterns do the work.
+// `compare` needs no labels: it runs two pattern sets over the same user-agents and lists where
+// and why their verdicts differ, for reviewing a rule change before it ships.
+
+use std::fmt;
+
+use crate::{BotDetector, BotGuardError, Verdict};
+
+#[cfg(feature = "corpus")]
+pub(crate) const _CORPUS: &str = include_str!("ua_corpus.txt");
+
+/// What a sample user-agent is known to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Label {
+    Bot,
+    Human,
+}
+
+/// The samples matched by one pattern, as indices into [`TestReport::samples`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternHits {
+    pub group: String,
+    pub pattern: String,
+    pub samples: Vec<usize>,
+}
+
+/// Outcome of [`PatternTester::run`]. A sample counts as flagged if its verdict is not
+/// [`Verdict::Human`], so low weight patterns only count once the score reaches the suspicious
+/// threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestReport {
+    pub samples: Vec<(String, Label)>,
+    /// Whether each sample was flagged, in the order of `samples`.
+    pub flagged: Vec<bool>,
+    /// Patterns of the enabled groups that matched at least one sample, sorted within a group.
+    pub hits: Vec<PatternHits>,
+    /// Patterns of the enabled groups that matched no sample at all, as `(group, pattern)`.
+    pub unmatched: Vec<(String, String)>,
+}
+
+impl TestReport {
+    /// Bots that were flagged.
+    pub fn true_positives(&self) -> usize {
+        self.count(Label::Bot, true)
+    }
+
+    /// Humans that were flagged.
+    pub fn false_positives(&self) -> usize {
+        self.count(Label::Human, true)
+    }
+
+    /// Bots that were not flagged.
+    pub fn false_negatives(&self) -> usize {
+        self.count(Label::Bot, false)
+    }
+
+    /// Share of the flagged samples that are bots, `1.0` if nothing was flagged.
+    pub fn precision(&self) -> f64 {
+        ratio(self.true_positives(), self.true_positives() + self.false_positives())
+    }
+
+    /// Share of the bots that were flagged, `1.0` if there are no bot samples.
+    pub fn recall(&self) -> f64 {
+        ratio(self.true_positives(), self.true_positives() + self.false_negatives())
+    }
+
+    /// The human samples that were flagged.
+    pub fn false_positive_samples(&self) -> impl Iterator<Item = &str> {
+        self.samples_where(Label::Human, true)
+    }
+
+    /// The bot samples that were not flagged.
+    pub fn false_negative_samples(&self) -> impl Iterator<Item = &str> {
+        self.samples_where(Label::Bot, false)
+    }
+
+    fn count(&self, label: Label, flagged: bool) -> usize {
+        self.samples_where(label, flagged).count()
+    }
+
+    fn samples_where(&self, label: Label, flagged: bool) -> impl Iterator<Item = &str> {
+        self.samples
+            .iter()
+            .zip(&self.flagged)
+            .filter(move |((_, l), f)| *l == label && **f == flagged)
+            .map(|((user_agent, _), _)| user_agent.as_str())
+    }
+}
+
+fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        1.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} samples, precision {:.3}, recall {:.3}", self.samples.len(), self.precision(), self.recall())?;
+        for user_agent in self.false_positive_samples() {
+            writeln!(f, "false positive: {}", user_agent)?;
+        }
+        for user_agent in self.false_negative_samples() {
+            writeln!(f, "false negative: {}", user_agent)?;
+        }
+        for (group, pattern) in &self.unmatched {
+            writeln!(f, "never matched: [{}] {}", group, pattern)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a detector over labeled user-agents.
+///
+/// ```
+/// use BotGuardLib::tester::{Label, PatternTester};
+/// use BotGuardLib::BotDetector;
+///
+/// let BotDetector = BotDetector::new("[crawlers]\ngooglebot\nbingbot\n[tools]\n^curl/\nmozilla");
+/// let report = PatternTester::new(&BotDetector)
+///     .corpus(Label::Bot, "# crawlers\nGooglebot/2.1 (+http://www.google.com/bot.html)\ncurl/8.4.0\nScrapy/2.11\n")
+///     .sample("Mozilla/5.0 (Windows NT 10.0; Win64; x64) Firefox/120.0", Label::Human)
+///     .run()
+///     .unwrap();
+///
+/// assert_eq!((report.true_positives(), report.false_positives(), report.false_negatives()), (2, 1, 1));
+/// assert_eq!(report.unmatched, vec![("crawlers".to_string(), "bingbot".to_string())]);
+/// assert_eq!(report.false_positive_samples().collect::<Vec<_>>(), ["Mozilla/5.0 (Windows NT 10.0; Win64; x64) Firefox/120.0"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PatternTester<'a> {
+    detector: &'a BotDetector,
+    samples: Vec<(String, Label)>,
+}
+
+impl<'a> PatternTester<'a> {
+    pub fn new(detector: &'a BotDetector) -> Self {
+        PatternTester { detector, samples: Vec::new() }
+    }
+
+    pub fn sample(mut self, user_agent: &str, label: Label) -> Self {
+        self.samples.push((user_agent.to_string(), label));
+        self
+    }
+
+    /// Adds one sample per line, blank lines and lines starting with `#` are skipped.
+    pub fn corpus(mut self, label: Label, corpus: &str) -> Self {
+        let lines = corpus.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+        self.samples.extend(lines.map(|line| (line.to_string(), label)));
+        self
+    }
+
+    /// Adds the samples listed under `[bot]` and `[human]` headers, lines outside of these
+    /// sections are skipped like comments.
+    pub fn labeled_corpus(mut self, corpus: &str) -> Self {
+        let mut label = None;
+        for line in corpus.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            match line {
+                "[bot]" => label = Some(Label::Bot),
+                "[human]" => label = Some(Label::Human),
+                _ if line.starts_with('[') && line.ends_with(']') => label = None,
+                _ => self.samples.extend(label.map(|label| (line.to_string(), label))),
+            }
+        }
+        self
+    }
+
+    /// Adds the bundled corpus of about 4,350 bot and 4,650 browser user-agents collected from
+    /// traffic, see [`evaluate`].
+    #[cfg(feature = "corpus")]
+    pub fn bundled_corpus(self) -> Self {
+        self.labeled_corpus(_CORPUS)
+    }
+
+    /// Classifies every sample and records which patterns of the enabled groups matched it.
+    ///
+    /// Detection hooks are not called.
+    pub fn run(&self) -> Result<TestReport, BotGuardError> {
+        let detector = self.detector;
+        let normalized = self.samples.iter().map(|(user_agent, _)| detector.normalize_user_agent(user_agent)).collect::<Vec<_>>();
+        let flagged = self
+            .samples
+            .iter()
+            .map(|(user_agent, _)| verdict(detector, user_agent) != Verdict::Human)
+            .collect();
+
+        let mut hits = Vec::new();
+        let mut unmatched = Vec::new();
+        for group in detector.groups.iter().filter(|group| group.is_enabled()) {
+            let set = group.pattern_set()?;
+            let mut samples = vec![Vec::new(); group.compiled_patterns().len()];
+            for (i, user_agent) in normalized.iter().enumerate() {
+                for pattern in set.matches(user_agent).iter() {
+                    samples[pattern].push(i);
+                }
+            }
+            let mut patterns = group.compiled_patterns().into_iter().zip(samples).collect::<Vec<_>>();
+            patterns.sort_by_key(|(pattern, _)| *pattern);
+            for (pattern, samples) in patterns {
+                if samples.is_empty() {
+                    unmatched.push((group.name().to_string(), pattern.to_string()));
+                } else {
+                    hits.push(PatternHits { group: group.name().to_string(), pattern: pattern.to_string(), samples });
+                }
+            }
+        }
+        Ok(TestReport { samples: self.samples.clone(), flagged, hits, unmatched })
+    }
+}
+
+/// Runs the detector over the bundled corpus, e.g. to compare a custom pattern set with the
+/// bundled one.
+///
+/// The corpus holds real user-agents from the test fixtures of the `isbot` crate, with the
+/// source of every section in the header of `src/ua_corpus.txt`. Its bots are a long list of
+/// crawlers, many of them rare, so recall is lower than on the traffic of a typical site, where
+/// a few well-known bots make most requests. Give [`PatternTester::labeled_corpus`] user-agents
+/// from your own logs to measure that.
+///
+/// ```
+/// use BotGuardLib::{tester, BotDetector};
+///
+/// let report = tester::evaluate(&BotDetector::default()).unwrap();
+/// assert!(report.precision() > 0.99);
+/// let only_crawlers = tester::evaluate(&BotDetector::new("googlebot\nbingbot")).unwrap();
+/// assert!(only_crawlers.recall() < report.recall());
+/// ```
+#[cfg(feature = "corpus")]
+pub fn evaluate(detector: &BotDetector) -> Result<TestReport, BotGuardError> {
+    PatternTester::new(detector).bundled_corpus().run()
+}
+
+/// The verdict by score alone, without calling detection hooks or recording a decision.
+pub(crate) fn verdict(detector: &BotDetector, user_agent: &str) -> Verdict {
+    detector.options.thresholds.verdict(detector.score_untraced(user_agent))
+}
+
+/// A user-agent two detectors judge differently, see [`compare`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerdictChange {
+    pub user_agent: String,
+    pub before: Verdict,
+    pub after: Verdict,
+    /// The pattern of the first detector that matched, as `(group, pattern)`.
+    pub before_match: Option<(String, String)>,
+    /// The pattern of the second detector that matched, as `(group, pattern)`.
+    pub after_match: Option<(String, String)>,
+}
+
+impl VerdictChange {
+    /// The pattern behind the change, the match of the stricter verdict if it has one.
+    pub fn cause(&self) -> Option<(&str, &str)> {
+        let (stricter, other) = if self.after.rank() > self.before.rank() { (&self.after_match, &self.before_match) } else { (&self.before_match, &self.after_match) };
+        stricter.as_ref().or(other.as_ref()).map(|(group, pattern)| (group.as_str(), pattern.as_str()))
+    }
+}
+
+/// Outcome of [`compare`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonReport {
+    pub samples: usize,
+    /// The samples with different verdicts, in corpus order.
+    pub changes: Vec<VerdictChange>,
+    /// Samples by verdict of the first and the second detector, indexed by verdict rank.
+    counts: [[usize; 3]; 3],
+}
+
+impl ComparisonReport {
+    /// Share of the samples both detectors agree on, `1.0` without samples.
+    pub fn agreement(&self) -> f64 {
+        ratio(self.samples - self.changes.len(), self.samples)
+    }
+
+    /// Samples the first detector judged `before` and the second one `after`.
+    pub fn count(&self, before: Verdict, after: Verdict) -> usize {
+        self.counts[before.rank() as usize][after.rank() as usize]
+    }
+
+    /// Samples only the second detector flags, as suspicious or bot.
+    pub fn newly_flagged(&self) -> usize {
+        self.changes.iter().filter(|change| change.before == Verdict::Human).count()
+    }
+
+    /// Samples only the first detector flags.
+    pub fn no_longer_flagged(&self) -> usize {
+        self.changes.iter().filter(|change| change.after == Verdict::Human).count()
+    }
+
+    /// The patterns behind the changes as `(group, pattern, changes)`, most changes first.
+    pub fn causes(&self) -> Vec<(&str, &str, usize)> {
+        let mut causes: Vec<(&str, &str, usize)> = Vec::new();
+        for (group, pattern) in self.changes.iter().filter_map(VerdictChange::cause) {
+            match causes.iter_mut().find(|(g, p, _)| *g == group && *p == pattern) {
+                Some((_, _, count)) => *count += 1,
+                None => causes.push((group, pattern, 1)),
+            }
+        }
+        causes.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)).then(a.1.cmp(b.1)));
+        causes
+    }
+}
+
+impl fmt::Display for ComparisonReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} samples, agreement {:.3}, {} newly flagged, {} no longer flagged", self.samples, self.agreement(), self.newly_flagged(), self.no_longer_flagged())?;
+        for change in &self.changes {
+            write!(f, "{:?} -> {:?}: {}", change.before, change.after, change.user_agent)?;
+            match change.cause() {
+                Some((group, pattern)) => writeln!(f, " ([{}] {})", group, pattern)?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs two detectors over the user-agents of `corpus`, one per line. Blank lines, comments
+/// starting with `#` and section headers like `[bot]` are skipped, so a labeled corpus works as
+/// well. Detection hooks are not called. See [`BotDetector::compare`].
+///
+/// ```
+/// use BotGuardLib::{tester, BotDetector, Verdict};
+///
+/// let current = BotDetector::new("[tools]\n^curl/\n[crawlers]\ngooglebot");
+/// let proposed = BotDetector::new("[tools]\n^curl/\n^wget/\n[crawlers]\nbot");
+/// let report = tester::compare(&current, &proposed, "curl/8.4.0\nWget/1.21\nGooglebot/2.1\nMozilla/5.0 (X11; Linux x86_64; rv:127.0) Gecko/20100101 Firefox/127.0");
+///
+/// assert_eq!((report.samples, report.agreement()), (4, 0.75));
+/// assert_eq!(report.changes[0].user_agent, "Wget/1.21");
+/// assert_eq!(report.count(Verdict::Human, Verdict::Bot), 1);
+/// assert_eq!(report.causes(), vec![("tools", "^wget/", 1)]);
+/// ```
+pub fn compare(before: &BotDetector, after: &BotDetector, corpus: &str) -> ComparisonReport {
+    let mut report = ComparisonReport { samples: 0, changes: Vec::new(), counts: [[0; 3]; 3] };
+    let is_header = |line: &str| line.starts_with('[') && line.ends_with(']');
+    let lines = corpus.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#') && !is_header(line));
+    for user_agent in lines {
+        let (old, new) = (verdict(before, user_agent), verdict(after, user_agent));
+        report.samples += 1;
+        report.counts[old.rank() as usize][new.rank() as usize] += 1;
+        if old != new {
+            let matched = |detector: &BotDetector| detector.find_match(user_agent).map(|found| (found.group.to_string(), found.pattern.to_string()));
+            report.changes.push(VerdictChange { user_agent: user_agent.to_string(), before: old, after: new, before_match: matched(before), after_match: matched(after) });
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_pattern_hits() {
+        let detector = BotDetector::new("[a]\nbot\n^curl/\n0.2 python-requests/\n[b]\nbot/\nnever-seen");
+        let report = PatternTester::new(&detector)
+            .sample("SomeBot/1.0", Label::Bot)
+            .sample("curl/8.0", Label::Bot)
+            .sample("python-requests/2.31", Label::Bot)
+            .sample("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0", Label::Human)
+            .run()
+            .unwrap();
+
+        assert_eq!(report.flagged, vec![true, true, false, false]);
+        let hits = report.hits.iter().map(|hit| (hit.group.as_str(), hit.pattern.as_str(), hit.samples.clone())).collect::<Vec<_>>();
+        assert_eq!(hits, vec![("a", "^curl/", vec![1]), ("a", "bot", vec![0]), ("a", "python-requests/", vec![2]), ("b", "bot/", vec![0])]);
+        assert_eq!(report.unmatched, vec![("b".to_string(), "never-seen".to_string())]);
+        assert_eq!((report.precision(), report.recall()), (1.0, 2.0 / 3.0));
+        assert_eq!(report.false_negative_samples().collect::<Vec<_>>(), ["python-requests/2.31"]);
+        assert!(report.to_string().starts_with("4 samples, precision 1.000, recall 0.667\nfalse negative: python-requests/2.31\n"));
+    }
+
+    #[test]
+    fn skips_disabled_groups() {
+        let mut detector = BotDetector::new("[a]\nfoo\n[b]\nbar");
+        detector.disable_group("b");
+        let report = PatternTester::new(&detector).sample("bar", Label::Human).sample("foo", Label::Bot).run().unwrap();
+        assert_eq!((report.false_positives(), report.true_positives()), (0, 1));
+        assert!(report.unmatched.is_empty());
+        assert_eq!(PatternTester::new(&detector).run().unwrap().precision(), 1.0);
+    }
+
+    #[test]
+    fn reads_labeled_sections() {
+        let detector = BotDetector::new("");
+        let tester = PatternTester::new(&detector).labeled_corpus("skipped\n[bot]\ncurl/8.0\n# comment\n[other]\nskipped\n[human]\nMozilla/5.0");
+        assert_eq!(tester.samples, vec![("curl/8.0".to_string(), Label::Bot), ("Mozilla/5.0".to_string(), Label::Human)]);
+    }
+
+    #[test]
+    fn compares_verdicts_and_their_causes() {
+        let before = BotDetector::new("[scanners]\n^sqlmap/\nnikto\n[tools]\n0.6 python-requests/");
+        let after = BotDetector::new("[scanners]\n^sqlmap/\n[tools]\npython-requests/\nhttpx");
+        let report = compare(&before, &after, "# scanners\n[bot]\nsqlmap/1.7\nNikto/2.5\npython-requests/2.31\npython-httpx/0.27\n\nhttpx-client/1.0");
+        assert_eq!(report.samples, 5);
+        let changes = report.changes.iter().map(|change| (change.user_agent.as_str(), change.before, change.after, change.cause())).collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("Nikto/2.5", Verdict::Bot, Verdict::Human, Some(("scanners", "nikto"))),
+                ("python-requests/2.31", Verdict::Suspicious, Verdict::Bot, Some(("tools", "python-requests/"))),
+                ("python-httpx/0.27", Verdict::Human, Verdict::Bot, Some(("tools", "httpx"))),
+                ("httpx-client/1.0", Verdict::Human, Verdict::Bot, Some(("tools", "httpx"))),
+            ]
+        );
+        assert_eq!(report.causes(), vec![("tools", "httpx", 2), ("scanners", "nikto", 1), ("tools", "python-requests/", 1)]);
+        assert_eq!((report.newly_flagged(), report.no_longer_flagged(), report.count(Verdict::Bot, Verdict::Bot)), (2, 1, 1));
+        assert!(report.to_string().starts_with("5 samples, agreement 0.200, 2 newly flagged, 1 no longer flagged\nBot -> Human: Nikto/2.5 ([scanners] nikto)\n"));
+        assert_eq!(compare(&before, &before, "").agreement(), 1.0);
+    }
+
+    #[cfg(feature = "corpus")]
+    #[test]
+    fn bundled_patterns_against_bundled_corpus() {
+        let report = evaluate(&BotDetector::default()).unwrap();
+        assert!(report.samples.len() > 8000);
+        // the bundled patterns flag no browser but miss much of the long tail of crawlers
+        assert!(report.precision() > 0.99, "{}", report);
+        assert!(report.recall() > 0.35, "{}", report);
+    }
+}
@@ -0,0 +1,266 @@
+// Generators and invariants for property tests and fuzzing of a detector, by this crate and by
+// applications testing their own pattern lists.
+//
+// The generators are deterministic for a seed, so a failing case found by a property test
+// framework or a long randomized loop is reproduced from its seed alone. The invariants return a
+// `Violation` instead of panicking, for fuzz targets that want to report the input themselves.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::tester::Label;
+use crate::{BotDetector, Verdict};
+
+/// Random user-agents, the same seed gives the same sequence.
+///
+/// ```
+/// use BotGuardLib::testing::UaGenerator;
+/// use BotGuardLib::BotDetector;
+///
+/// let BotDetector = BotDetector::default();
+/// let mut generator = UaGenerator::new(7);
+/// for _ in 0..100 {
+///     assert!(BotDetector.check_bot(&generator.bot()));
+///     assert!(!BotDetector.check_bot(&generator.human()));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct UaGenerator {
+    state: u64,
+}
+
+const PLATFORMS: [&str; 6] = [
+    "Windows NT 10.0; Win64; x64",
+    "Windows NT 6.1; WOW64",
+    "Macintosh; Intel Mac OS X 10_15_7",
+    "X11; Linux x86_64",
+    "X11; Ubuntu; Linux x86_64",
+    "Linux; Android 14; Pixel 8",
+];
+
+const CRAWLERS: [&str; 8] = ["Googlebot", "bingbot", "YandexBot", "AhrefsBot", "SemrushBot", "DuckDuckBot", "Applebot", "facebookexternalhit"];
+
+const TOOLS: [&str; 6] = ["curl", "Wget", "python-requests", "Scrapy", "Go-http-client", "HeadlessChrome"];
+
+impl UaGenerator {
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves zero
+        UaGenerator { state: seed ^ 0x2545_f491_4f6c_dd1d }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        // xorshift
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Below `n`, `0` for `0`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n.max(1)
+    }
+
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.below(choices.len() as u64) as usize]
+    }
+
+    /// A current Chrome, Firefox, Safari or Edge on a desktop or phone platform.
+    pub fn human(&mut self) -> String {
+        let platform = self.pick(&PLATFORMS);
+        let major = 100 + self.below(30);
+        match self.below(4) {
+            0 => format!("Mozilla/5.0 ({}) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/{}.0.{}.{} Safari/537.36", platform, major, self.below(7000), self.below(200)),
+            1 => format!("Mozilla/5.0 ({}; rv:{}.0) Gecko/20100101 Firefox/{}.0", platform, major, major),
+            2 => format!("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/{}.{} Safari/605.1.15", 14 + self.below(4), self.below(6)),
+            _ => format!("Mozilla/5.0 ({}) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/{}.0.0.0 Safari/537.36 Edg/{}.0.{}.{}", platform, major, major, self.below(3000), self.below(100)),
+        }
+    }
+
+    /// A well-known crawler or HTTP tool, bare or wrapped in a browser-like user-agent.
+    pub fn bot(&mut self) -> String {
+        let version = format!("{}.{}", 1 + self.below(9), self.below(20));
+        match self.below(3) {
+            0 => format!("Mozilla/5.0 (compatible; {}/{}; +https://example.com/bot.html)", self.pick(&CRAWLERS), version),
+            1 => format!("{}/{}", self.pick(&TOOLS), version),
+            _ => format!("Mozilla/5.0 ({}) AppleWebKit/537.36 (KHTML, like Gecko; compatible; {}/{}) Chrome/120.0 Safari/537.36", self.pick(&PLATFORMS), self.pick(&CRAWLERS), version),
+        }
+    }
+
+    /// `len` user-agents, a bot for every `bot_ratio` of them on average.
+    pub fn labeled(&mut self, len: usize, bot_ratio: f64) -> Vec<(String, Label)> {
+        (0..len)
+            .map(|_| {
+                let is_bot = ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < bot_ratio;
+                if is_bot {
+                    (self.bot(), Label::Bot)
+                } else {
+                    (self.human(), Label::Human)
+                }
+            })
+            .collect()
+    }
+
+    /// The user-agent with the case of some letters flipped, a space doubled or a comment
+    /// appended, edits real clients make and a case-insensitive detector should mostly ignore.
+    pub fn mutate(&mut self, user_agent: &str) -> String {
+        let mut mutated = user_agent
+            .chars()
+            .map(|c| match self.below(8) {
+                0 if c.is_ascii_lowercase() => c.to_ascii_uppercase(),
+                0 if c.is_ascii_uppercase() => c.to_ascii_lowercase(),
+                _ => c,
+            })
+            .collect::<String>();
+        match self.below(3) {
+            0 => mutated = mutated.replacen(' ', "  ", 1),
+            1 => mutated.push_str(&format!(" (build {})", self.below(10_000))),
+            _ => {}
+        }
+        mutated
+    }
+
+    /// Up to `max_len` characters of printable ASCII, control characters and some multi-byte
+    /// letters, for inputs no real client sends.
+    pub fn arbitrary(&mut self, max_len: usize) -> String {
+        let len = self.below(max_len as u64 + 1) as usize;
+        (0..len)
+            .map(|_| match self.below(20) {
+                0 => char::from(self.below(32) as u8),
+                1 => ['é', 'ß', 'К', 'ｂ', '😀', '\u{200b}'][self.below(6) as usize],
+                _ => char::from(32 + self.below(95) as u8),
+            })
+            .collect()
+    }
+}
+
+/// An invariant that did not hold, and for which user-agent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub invariant: &'static str,
+    pub user_agent: String,
+    pub detail: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} violated for {:?}: {}", self.invariant, self.user_agent, self.detail)
+    }
+}
+
+impl std::error::Error for Violation {}
+
+fn violation(invariant: &'static str, user_agent: &str, detail: String) -> Result<(), Violation> {
+    Err(Violation { invariant, user_agent: user_agent.to_string(), detail })
+}
+
+/// The entry points of the detector agree with each other: the score is between `0.0` and
+/// `1.0`, [`BotDetector::check_bot`] follows [`BotDetector::check`], a user-agent has a group
+/// exactly when it has a match, and a match scores its weight.
+pub fn consistent_results(detector: &BotDetector, user_agent: &str) -> Result<(), Violation> {
+    let (verdict, score) = (detector.check(user_agent), detector.score(user_agent));
+    if !(0.0..=1.0).contains(&score) {
+        return violation("consistent_results", user_agent, format!("score {} out of range", score));
+    }
+    if detector.check_bot(user_agent) != (verdict == Verdict::Bot) {
+        return violation("consistent_results", user_agent, format!("check_bot disagrees with the verdict {:?}", verdict));
+    }
+    let found = detector.find_match(user_agent);
+    if detector.classify(user_agent).is_some() != found.is_some() {
+        return violation("consistent_results", user_agent, format!("classify disagrees with find_match {:?}", found));
+    }
+    match found {
+        Some(found) if found.weight != score => violation("consistent_results", user_agent, format!("score {} differs from the weight of {:?}", score, found)),
+        _ => Ok(()),
+    }
+}
+
+/// Changing the case of ASCII letters does not change the verdict, for detectors that are not
+/// case-sensitive.
+pub fn case_insensitive(detector: &BotDetector, user_agent: &str) -> Result<(), Violation> {
+    let verdict = detector.check(user_agent);
+    for changed in [user_agent.to_ascii_lowercase(), user_agent.to_ascii_uppercase()] {
+        let changed_verdict = detector.check(&changed);
+        if changed_verdict != verdict {
+            return violation("case_insensitive", user_agent, format!("{:?} for {:?} but {:?} as sent", changed_verdict, changed, verdict));
+        }
+    }
+    Ok(())
+}
+
+/// Appending patterns and removing them again gives every sample its original verdict. Patterns
+/// the detector already contains are left out, removing them would remove the original.
+pub fn append_remove_restores<'a>(detector: &BotDetector, patterns: &[&str], samples: impl IntoIterator<Item = &'a str>) -> Result<(), Violation> {
+    let patterns = patterns.iter().copied().filter(|pattern| !detector.contains_pattern(pattern)).collect::<Vec<_>>();
+    let mut edited = detector.clone();
+    if edited.append(&patterns).is_err() {
+        // invalid patterns leave the detector unchanged
+        return Ok(());
+    }
+    edited.remove(&patterns);
+    for sample in samples {
+        let (before, after) = (detector.check(sample), edited.check(sample));
+        if before != after {
+            return violation("append_remove_restores", sample, format!("{:?} before appending {:?}, {:?} after removing them", before, patterns, after));
+        }
+    }
+    Ok(())
+}
+
+/// Runs every invariant that holds for any detector on arbitrary bytes, for fuzz targets:
+///
+/// ```
+/// use BotGuardLib::testing::exercise;
+/// use BotGuardLib::BotDetector;
+///
+/// let BotDetector = BotDetector::default();
+/// // fuzz_target!(|data: &[u8]| exercise(&BotDetector, data).unwrap());
+/// exercise(&BotDetector, b"Mozilla/5.0 \xff\xfe (compatible; Googlebot/2.1)").unwrap();
+/// ```
+pub fn exercise(detector: &BotDetector, input: &[u8]) -> Result<(), Violation> {
+    let user_agent: Cow<'_, str> = String::from_utf8_lossy(input);
+    consistent_results(detector, &user_agent)?;
+    let _ = (detector.check_http_client(&user_agent), detector.find(&user_agent));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generators_are_deterministic_and_labeled() {
+        let (mut a, mut b) = (UaGenerator::new(42), UaGenerator::new(42));
+        assert_eq!(a.labeled(20, 0.5), b.labeled(20, 0.5));
+        assert_ne!(UaGenerator::new(1).human(), UaGenerator::new(2).human());
+        let detector = BotDetector::default();
+        let samples = UaGenerator::new(3).labeled(500, 0.3);
+        assert!(samples.iter().all(|(user_agent, label)| detector.check_bot(user_agent) == (*label == Label::Bot)));
+        let bots = samples.iter().filter(|(_, label)| *label == Label::Bot).count();
+        assert!((100..200).contains(&bots), "{}", bots);
+        assert!(UaGenerator::new(4).arbitrary(16).chars().count() <= 16);
+    }
+
+    #[test]
+    fn invariants_hold_for_generated_user_agents() {
+        let detector = BotDetector::default();
+        let mut generator = UaGenerator::new(9);
+        for _ in 0..300 {
+            let user_agent = match generator.below(3) {
+                0 => generator.bot(),
+                1 => generator.human(),
+                _ => generator.arbitrary(64),
+            };
+            let mutated = generator.mutate(&user_agent);
+            consistent_results(&detector, &mutated).unwrap();
+            case_insensitive(&detector, &mutated).unwrap();
+            exercise(&detector, mutated.as_bytes()).unwrap();
+        }
+        let samples = (0..50).map(|_| generator.human()).collect::<Vec<_>>();
+        append_remove_restores(&detector, &["^mozilla/", "googlebot"], samples.iter().map(String::as_str)).unwrap();
+
+        let sensitive = BotDetector::builder().case_sensitive(true).patterns("^Scanner/").build().unwrap();
+        let error = case_insensitive(&sensitive, "Scanner/1.0").unwrap_err();
+        assert_eq!(error.invariant, "case_insensitive");
+    }
+}
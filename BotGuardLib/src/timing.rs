@@ -0,0 +1,172 @@
+// Timing checks: a page embeds a signed token with the time it was served, the form submission or
+// API call made from it sends the token back. Scripts posting faster than any human could read
+// and fill in the page give themselves away.
+//
+// Tokens are `<served unix millis>.<HMAC-SHA-512 prefix>` over the served time and a context such
+// as the session or the route, so nothing is stored on the server.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::request::RequestSnapshot;
+use crate::{crypto, Verdict};
+
+/// Header carrying the token on API calls, e.g. copied from the [`PageTimer::meta_tag`] by a script.
+pub const TIMING_HEADER: &str = "X-BotGuard-Served";
+
+/// Hex digits of the MAC in tokens.
+const MAC_DIGITS: usize = 32;
+
+/// Outcome of [`PageTimer::verify`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingCheck {
+    /// The request came this long after the page was served.
+    Passed(Duration),
+    /// The request came this soon after the page was served, below [`PageTimer::human_floor`].
+    TooFast(Duration),
+    /// No token was sent.
+    Missing,
+    /// The token was not issued for this context or was tampered with.
+    Invalid,
+    /// The token is older than [`PageTimer::max_age`].
+    Expired,
+}
+
+impl TimingCheck {
+    /// Too fast requests and forged tokens are bots, missing or expired tokens suspicious.
+    pub fn verdict(self) -> Verdict {
+        match self {
+            TimingCheck::Passed(_) => Verdict::Human,
+            TimingCheck::Missing | TimingCheck::Expired => Verdict::Suspicious,
+            TimingCheck::TooFast(_) | TimingCheck::Invalid => Verdict::Bot,
+        }
+    }
+}
+
+/// Issues and verifies page served tokens.
+///
+/// ```
+/// use std::time::Duration;
+/// use BotGuardLib::request::RequestSnapshot;
+/// use BotGuardLib::timing::{PageTimer, TimingCheck, TIMING_HEADER};
+///
+/// let timer = PageTimer::new(b"a long random server secret").human_floor(Duration::from_millis(1500));
+/// let token = timer.issue("checkout");
+/// assert!(timer.meta_tag("checkout").starts_with("<meta name=\"botguard-served\" content=\""));
+///
+/// let call = RequestSnapshot::new("POST", "/api/checkout").header(TIMING_HEADER, &token);
+/// assert!(matches!(timer.verify_request("checkout", &call), TimingCheck::TooFast(_)));
+/// assert_eq!(timer.verify("search", &token), TimingCheck::Invalid);
+/// assert_eq!(timer.verify("checkout", ""), TimingCheck::Missing);
+/// ```
+#[derive(Clone)]
+pub struct PageTimer {
+    secret: Vec<u8>,
+    human_floor: Duration,
+    max_age: Duration,
+}
+
+impl fmt::Debug for PageTimer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PageTimer").field("human_floor", &self.human_floor).field("max_age", &self.max_age).finish_non_exhaustive()
+    }
+}
+
+impl PageTimer {
+    /// Requests within 2 seconds of serving the page are too fast, tokens expire after a day.
+    /// Every instance verifying the tokens needs the same secret.
+    pub fn new(secret: &[u8]) -> Self {
+        PageTimer { secret: secret.to_vec(), human_floor: Duration::from_secs(2), max_age: Duration::from_secs(24 * 60 * 60) }
+    }
+
+    /// The shortest time a human needs between loading the page and the request.
+    pub fn human_floor(mut self, human_floor: Duration) -> Self {
+        self.human_floor = human_floor;
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// A token for a page served now, only valid for the same `context` like a session or route.
+    pub fn issue(&self, context: &str) -> String {
+        self.issue_at(context, SystemTime::now())
+    }
+
+    pub(crate) fn issue_at(&self, context: &str, served: SystemTime) -> String {
+        let served = served.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        format!("{}.{}", served, self.mac(context, &served.to_string()))
+    }
+
+    /// A token as a `<meta name="botguard-served">` tag for the page head.
+    pub fn meta_tag(&self, context: &str) -> String {
+        format!("<meta name=\"botguard-served\" content=\"{}\">", self.issue(context))
+    }
+
+    /// Checks a token sent back for the context, an empty token is [`TimingCheck::Missing`].
+    pub fn verify(&self, context: &str, token: &str) -> TimingCheck {
+        self.verify_at(context, token, SystemTime::now())
+    }
+
+    /// Checks the token of the [`TIMING_HEADER`].
+    pub fn verify_request(&self, context: &str, request: &RequestSnapshot) -> TimingCheck {
+        self.verify(context, request.header_value(TIMING_HEADER).unwrap_or(""))
+    }
+
+    pub(crate) fn verify_at(&self, context: &str, token: &str, now: SystemTime) -> TimingCheck {
+        let token = token.trim();
+        if token.is_empty() {
+            return TimingCheck::Missing;
+        }
+        let Some((served, mac)) = token.split_once('.') else { return TimingCheck::Invalid };
+        let valid = crypto::mac_eq(mac.as_bytes(), self.mac(context, served).as_bytes());
+        let Some(served) = served.parse::<u64>().ok().filter(|_| valid) else { return TimingCheck::Invalid };
+        let elapsed = now.duration_since(UNIX_EPOCH + Duration::from_millis(served)).unwrap_or_default();
+        if elapsed > self.max_age {
+            TimingCheck::Expired
+        } else if elapsed < self.human_floor {
+            TimingCheck::TooFast(elapsed)
+        } else {
+            TimingCheck::Passed(elapsed)
+        }
+    }
+
+    fn mac(&self, context: &str, served: &str) -> String {
+        let message = format!("served\n{}\n{}", context, served);
+        crypto::hex(&crypto::hmac_sha512(&self.secret, message.as_bytes()))[..MAC_DIGITS].to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_time_since_serving() {
+        let timer = PageTimer::new(b"secret").human_floor(Duration::from_millis(800)).max_age(Duration::from_secs(600));
+        let served = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let token = timer.issue_at("session-1", served);
+        let after = |millis: u64| timer.verify_at("session-1", &token, served + Duration::from_millis(millis));
+
+        assert_eq!(after(5_000), TimingCheck::Passed(Duration::from_secs(5)));
+        assert_eq!(after(799), TimingCheck::TooFast(Duration::from_millis(799)));
+        assert_eq!(after(600_001), TimingCheck::Expired);
+        assert_eq!(timer.verify_at("session-1", &token, served - Duration::from_secs(1)), TimingCheck::TooFast(Duration::ZERO));
+    }
+
+    #[test]
+    fn rejects_forged_tokens() {
+        let timer = PageTimer::new(b"secret");
+        let served = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let token = timer.issue_at("a", served);
+        let (_, mac) = token.split_once('.').unwrap();
+        let later = served + Duration::from_secs(60);
+        assert_eq!(timer.verify_at("a", &format!("1600000000000.{}", mac), later), TimingCheck::Invalid);
+        assert_eq!(PageTimer::new(b"other").verify_at("a", &token, later), TimingCheck::Invalid);
+        assert_eq!(timer.verify_at("a", "no-dot", later), TimingCheck::Invalid);
+        assert_eq!(timer.verify_at("a", "  ", later), TimingCheck::Missing);
+        assert_eq!(TimingCheck::Invalid.verdict(), Verdict::Bot);
+    }
+}
@@ -0,0 +1,389 @@
+// Parser for the subset of TOML used by configuration files: tables, arrays of tables, strings,
+// integers, floats, booleans and (multi-line) arrays. Inline tables and dates are not supported.
+
+/// A parsed value, table entries keep their order.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+impl Value {
+    #[cfg(test)]
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Table(entries) => entries.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_table(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Table(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+/// A parse error with the 1-based line it occurred on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Error {
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Parses a document into its root table.
+pub(crate) fn parse(input: &str) -> Result<Value, Error> {
+    let mut parser = Parser { chars: input.chars().collect(), pos: 0, line: 1 };
+    let mut root = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    loop {
+        parser.skip_blank_lines();
+        match parser.peek() {
+            None => return Ok(Value::Table(root)),
+            Some('[') => {
+                parser.pos += 1;
+                let array = parser.eat('[');
+                let path = parser.key_path()?;
+                if !parser.eat(']') || (array && !parser.eat(']')) {
+                    return Err(parser.error("unterminated table header"));
+                }
+                parser.end_of_line()?;
+                let line = parser.line;
+                open_table(&mut root, &path, array).map_err(|reason| Error { line, reason })?;
+                current = path;
+            }
+            Some(_) => {
+                let path = parser.key_path()?;
+                parser.skip_spaces();
+                if !parser.eat('=') {
+                    return Err(parser.error("expected '='"));
+                }
+                let value = parser.value()?;
+                parser.end_of_line()?;
+                let line = parser.line;
+                let table = table_mut(&mut root, &current).map_err(|reason| Error { line, reason })?;
+                insert(table, &path, value).map_err(|reason| Error { line, reason })?;
+            }
+        }
+    }
+}
+
+/// Returns the table at `path`, following the last element of arrays of tables.
+fn table_mut<'a>(root: &'a mut Vec<(String, Value)>, path: &[String]) -> Result<&'a mut Vec<(String, Value)>, String> {
+    let mut table = root;
+    for key in path {
+        let value = table
+            .iter_mut()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+            .ok_or_else(|| format!("table {:?} is not defined", key))?;
+        table = match value {
+            Value::Table(entries) => entries,
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Table(entries)) => entries,
+                _ => return Err(format!("{:?} is not a table", key)),
+            },
+            _ => return Err(format!("{:?} is not a table", key)),
+        };
+    }
+    Ok(table)
+}
+
+fn open_table(root: &mut Vec<(String, Value)>, path: &[String], array: bool) -> Result<(), String> {
+    let (last, parents) = path.split_last().ok_or("empty table name")?;
+    let mut table = root;
+    for key in parents {
+        if !table.iter().any(|(name, _)| name == key) {
+            table.push((key.clone(), Value::Table(Vec::new())));
+        }
+        table = table_mut(table, std::slice::from_ref(key))?;
+    }
+    match (table.iter_mut().find(|(name, _)| name == last), array) {
+        (None, false) => table.push((last.clone(), Value::Table(Vec::new()))),
+        (None, true) => table.push((last.clone(), Value::Array(vec![Value::Table(Vec::new())]))),
+        (Some((_, Value::Array(items))), true) => items.push(Value::Table(Vec::new())),
+        (Some(_), _) => return Err(format!("table {:?} is defined twice", last)),
+    }
+    Ok(())
+}
+
+fn insert(table: &mut Vec<(String, Value)>, path: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = path.split_last().ok_or("empty key")?;
+    let mut table = table;
+    for key in parents {
+        if !table.iter().any(|(name, _)| name == key) {
+            table.push((key.clone(), Value::Table(Vec::new())));
+        }
+        table = table_mut(table, std::slice::from_ref(key))?;
+    }
+    if table.iter().any(|(name, _)| name == last) {
+        return Err(format!("key {:?} is defined twice", last));
+    }
+    table.push((last.clone(), value));
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, reason: &str) -> Error {
+        Error { line: self.line, reason: reason.to_string() }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_spaces();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Skips whitespace, comments and newlines, also inside arrays.
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') => {
+                    self.pos += 1;
+                    self.line += 1;
+                }
+                Some('\r') => self.pos += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), Error> {
+        self.skip_spaces();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n') | Some('\r') => Ok(()),
+            Some(_) => Err(self.error("unexpected characters after the value")),
+        }
+    }
+
+    /// Parses `a.b."c d"`.
+    fn key_path(&mut self) -> Result<Vec<String>, Error> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_spaces();
+            let key = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err(self.error("expected a key"));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            path.push(key);
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(path);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => Err(self.error("inline tables are not supported")),
+            Some(_) => self.scalar(),
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    fn scalar(&mut self) -> Result<Value, Error> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_')) {
+            self.pos += 1;
+        }
+        let word = self.chars[start..self.pos].iter().filter(|&&c| c != '_').collect::<String>();
+        match word.as_str() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            word => word
+                .parse::<i64>()
+                .map(Value::Integer)
+                .or_else(|_| word.parse::<f64>().map(Value::Float))
+                .map_err(|_| self.error(&format!("invalid value {:?}", word))),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some(c @ ('u' | 'U')) => {
+                            let len = if c == 'u' { 4 } else { 8 };
+                            let digits = self.chars.get(self.pos + 1..self.pos + 1 + len).ok_or_else(|| self.error("invalid escape"))?;
+                            let code = u32::from_str_radix(&digits.iter().collect::<String>(), 16).map_err(|_| self.error("invalid escape"))?;
+                            self.pos += len;
+                            char::from_u32(code).ok_or_else(|| self.error("invalid escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    self.pos += 1;
+                    out.push(escaped);
+                }
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some(c) => {
+                    self.pos += 1;
+                    out.push(c);
+                }
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let start = self.pos;
+        while !matches!(self.peek(), None | Some('\'') | Some('\n')) {
+            self.pos += 1;
+        }
+        if self.peek() != Some('\'') {
+            return Err(self.error("unterminated string"));
+        }
+        self.pos += 1;
+        Ok(self.chars[start..self.pos - 1].iter().collect())
+    }
+
+    fn array(&mut self) -> Result<Value, Error> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_documents() {
+        let document = parse(
+            r#"
+# comment
+title = "bot \"guard\"é"
+[detector]
+case_sensitive = false # trailing comment
+max_input_len = 2_048
+ratio = 0.5
+
+[[route]]
+path = '/api/*'
+methods = [
+    "GET", # inline
+    "POST",
+]
+
+[[route]]
+path = "/blog/*"
+nested.key = 1
+"#,
+        )
+        .unwrap();
+        assert_eq!(document.get("title"), Some(&Value::String("bot \"guard\"é".to_string())));
+        let detector = document.get("detector").unwrap();
+        assert_eq!(detector.get("case_sensitive"), Some(&Value::Bool(false)));
+        assert_eq!(detector.get("max_input_len"), Some(&Value::Integer(2048)));
+        assert_eq!(detector.get("ratio"), Some(&Value::Float(0.5)));
+        let routes = match document.get("route") {
+            Some(Value::Array(routes)) => routes,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].get("methods"), Some(&Value::Array(vec![Value::String("GET".into()), Value::String("POST".into())])));
+        assert_eq!(routes[1].get("nested").and_then(|n| n.get("key")), Some(&Value::Integer(1)));
+    }
+
+    #[test]
+    fn reports_error_lines() {
+        assert_eq!(parse("a = 1\nb = \n").unwrap_err().line, 2);
+        assert_eq!(parse("a = 1\na = 2").unwrap_err(), Error { line: 2, reason: "key \"a\" is defined twice".to_string() });
+        assert_eq!(parse("[t]\n[t]").unwrap_err().line, 2);
+        assert!(parse("a = \"open").is_err());
+        assert!(parse("a = 1 2").is_err());
+        assert!(parse("a = {b = 1}").is_err());
+        assert!(parse("[t\n").is_err());
+    }
+}
@@ -0,0 +1,31 @@
+// Detection decisions as `tracing` events, enabled with the `tracing` feature.
+//
+// `check_bot` and its variants, `classify`, `score` and `BotGuard::evaluate` each record one
+// `DEBUG` event with the target `BotGuardLib::trace` per decision. `evaluate` also opens a
+// `botguard.evaluate` span around the pipeline, so the decision joins the trace of the request
+// it was made for. The raw user-agent is never recorded, only its hash.
+
+/// Stable 64-bit FNV-1a hash of a user-agent, identical across runs and platforms.
+pub fn ua_hash(user_agent: &str) -> u64 {
+    user_agent.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Records a decision on an already normalized user-agent, the `decision` names the API.
+pub(crate) fn decision(decision: &'static str, normalized_user_agent: &str, pattern: Option<&str>, category: Option<&str>, is_bot: bool, score: Option<f32>) {
+    tracing::debug!(
+        decision,
+        ua_hash = %format_args!("{:016x}", ua_hash(normalized_user_agent)),
+        pattern,
+        category,
+        is_bot,
+        score,
+        "bot detection decision"
+    );
+}
+
+/// The span of one [`crate::request::BotGuard::evaluate`] call.
+pub(crate) fn evaluate_span(method: &str, normalized_user_agent: &str) -> tracing::Span {
+    tracing::debug_span!("botguard.evaluate", method, ua_hash = %format_args!("{:016x}", ua_hash(normalized_user_agent)))
+}
@@ -0,0 +1,260 @@
+// Per-address request patterns: bursts over sliding windows of one second, ten seconds and a
+// minute, and request intervals too regular for a human, like a script sleeping in a loop.
+//
+// The tracker keeps the timestamps of the last minute for a bounded number of addresses, split
+// over shards with their own lock so concurrent requests rarely wait on each other.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+const SHARDS: usize = 16;
+
+/// The longest sliding window, older requests are forgotten.
+const HORIZON: Duration = Duration::from_secs(60);
+
+/// Requests needed before intervals are judged periodic.
+const PERIODIC_SAMPLES: usize = 8;
+
+/// Largest relative deviation of the intervals from their mean still considered periodic.
+const PERIODIC_JITTER: f64 = 0.05;
+
+/// Share of a full table forgotten at once, so the scan for the oldest entries runs once per
+/// that many new addresses rather than for every one.
+const EVICTED_SHARE: usize = 16;
+
+/// Unusual traffic of one address, see [`TrafficTracker::anomaly`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyKind {
+    /// More than the allowed requests within `window`.
+    Burst { window: Duration, count: usize },
+    /// The last requests came at this interval, with almost no jitter.
+    Periodic { interval: Duration },
+}
+
+/// Most requests an address may make per window before it is reported as a burst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BurstLimits {
+    pub per_second: usize,
+    pub per_ten_seconds: usize,
+    pub per_minute: usize,
+}
+
+impl Default for BurstLimits {
+    /// 10 per second, 50 per ten seconds and 200 per minute.
+    fn default() -> Self {
+        BurstLimits { per_second: 10, per_ten_seconds: 50, per_minute: 200 }
+    }
+}
+
+/// Sliding window request history of client addresses.
+///
+/// ```
+/// use std::net::IpAddr;
+/// use BotGuardLib::traffic::{AnomalyKind, BurstLimits, TrafficTracker};
+///
+/// let tracker = TrafficTracker::new().limits(BurstLimits { per_second: 3, per_ten_seconds: 20, per_minute: 60 });
+/// let ip: IpAddr = "203.0.113.7".parse().unwrap();
+/// for _ in 0..4 {
+///     tracker.observe(ip);
+/// }
+/// assert!(matches!(tracker.anomaly(ip), Some(AnomalyKind::Burst { count: 4, .. })));
+/// assert_eq!(tracker.anomaly("198.51.100.1".parse().unwrap()), None);
+/// ```
+#[derive(Debug)]
+pub struct TrafficTracker {
+    shards: Vec<Mutex<HashMap<IpAddr, VecDeque<Instant>>>>,
+    limits: BurstLimits,
+    max_clients: usize,
+}
+
+impl Default for TrafficTracker {
+    fn default() -> Self {
+        TrafficTracker::new()
+    }
+}
+
+impl TrafficTracker {
+    /// Tracks up to 100,000 addresses with the default [`BurstLimits`].
+    pub fn new() -> Self {
+        TrafficTracker { shards: (0..SHARDS).map(|_| Mutex::default()).collect(), limits: BurstLimits::default(), max_clients: 100_000 }
+    }
+
+    pub fn limits(mut self, limits: BurstLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// When full, the addresses seen least recently are dropped to make room for new ones, a
+    /// sixteenth of them at a time.
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients.max(SHARDS);
+        self
+    }
+
+    /// Records a request of the address made now.
+    pub fn observe(&self, ip: IpAddr) {
+        self.observe_at(ip, Instant::now());
+    }
+
+    /// Whether the requests of the address form a burst or a periodic pattern, bursts are
+    /// reported for the shortest window exceeding its limit.
+    pub fn anomaly(&self, ip: IpAddr) -> Option<AnomalyKind> {
+        self.anomaly_at(ip, Instant::now())
+    }
+
+    /// Number of addresses with requests in the last minute, or not yet swept.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| lock(shard).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn observe_at(&self, ip: IpAddr, now: Instant) {
+        let mut shard = lock(self.shard(ip));
+        if !shard.contains_key(&ip) && shard.len() >= self.max_clients / SHARDS {
+            shard.retain(|_, history| history.back().is_some_and(|last| now.duration_since(*last) < HORIZON));
+            if shard.len() >= self.max_clients / SHARDS {
+                evict_oldest(&mut shard, |history| history.back().copied());
+            }
+        }
+        let history = shard.entry(ip).or_default();
+        prune(history, now);
+        history.push_back(now);
+        // one more than the minute limit is all a burst check needs
+        if history.len() > self.limits.per_minute + 1 {
+            history.pop_front();
+        }
+    }
+
+    fn anomaly_at(&self, ip: IpAddr, now: Instant) -> Option<AnomalyKind> {
+        let shard = lock(self.shard(ip));
+        let history = shard.get(&ip)?;
+        let windows = [
+            (Duration::from_secs(1), self.limits.per_second),
+            (Duration::from_secs(10), self.limits.per_ten_seconds),
+            (HORIZON, self.limits.per_minute),
+        ];
+        for (window, limit) in windows {
+            let count = history.iter().rev().take_while(|at| now.duration_since(**at) < window).count();
+            if count > limit {
+                return Some(AnomalyKind::Burst { window, count });
+            }
+        }
+        periodic(history)
+    }
+
+    fn shard(&self, ip: IpAddr) -> &Mutex<HashMap<IpAddr, VecDeque<Instant>>> {
+        let mut hasher = DefaultHasher::new();
+        ip.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+}
+
+/// Removes the [`EVICTED_SHARE`] of the entries with the smallest `seen`, at least one.
+pub(crate) fn evict_oldest<V, T: Ord>(map: &mut HashMap<IpAddr, V>, seen: impl Fn(&V) -> T) {
+    let count = (map.len() / EVICTED_SHARE).max(1).min(map.len());
+    if count == 0 {
+        return;
+    }
+    let mut oldest = map.iter().map(|(ip, value)| (seen(value), *ip)).collect::<Vec<_>>();
+    oldest.select_nth_unstable_by(count - 1, |a, b| a.0.cmp(&b.0));
+    for (_, ip) in &oldest[..count] {
+        map.remove(ip);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn prune(history: &mut VecDeque<Instant>, now: Instant) {
+    while history.front().is_some_and(|first| now.duration_since(*first) >= HORIZON) {
+        history.pop_front();
+    }
+}
+
+/// The mean interval of the last requests if none deviates from it by more than the jitter.
+fn periodic(history: &VecDeque<Instant>) -> Option<AnomalyKind> {
+    if history.len() < PERIODIC_SAMPLES + 1 {
+        return None;
+    }
+    let recent = history.iter().skip(history.len() - PERIODIC_SAMPLES - 1).collect::<Vec<_>>();
+    let intervals = recent.windows(2).map(|pair| pair[1].duration_since(*pair[0]).as_secs_f64()).collect::<Vec<_>>();
+    let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+    let regular = mean > 0.0 && intervals.iter().all(|interval| (interval - mean).abs() <= mean * PERIODIC_JITTER);
+    regular.then(|| AnomalyKind::Periodic { interval: Duration::from_secs_f64(mean) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 0, 2, last])
+    }
+
+    #[test]
+    fn detects_bursts_and_periodic_clients() {
+        let tracker = TrafficTracker::new().limits(BurstLimits { per_second: 5, per_ten_seconds: 8, per_minute: 100 });
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+
+        // six requests within half a second
+        for i in 0..6 {
+            tracker.observe_at(ip(1), at(i * 100));
+        }
+        assert_eq!(tracker.anomaly_at(ip(1), at(500)), Some(AnomalyKind::Burst { window: Duration::from_secs(1), count: 6 }));
+        // spread over ten seconds only the ten second limit is exceeded
+        assert_eq!(tracker.anomaly_at(ip(1), at(9_000)), None);
+        for i in 0..4 {
+            tracker.observe_at(ip(1), at(2_000 + i * 1_500));
+        }
+        assert_eq!(tracker.anomaly_at(ip(1), at(9_000)), Some(AnomalyKind::Burst { window: Duration::from_secs(10), count: 10 }));
+
+        for i in 0..9 {
+            tracker.observe_at(ip(2), at(i * 3_000));
+        }
+        assert_eq!(tracker.anomaly_at(ip(2), at(30_000)), Some(AnomalyKind::Periodic { interval: Duration::from_secs(3) }));
+        for (i, jitter) in [0, 2_100, 3_900, 7_400, 8_800, 12_900, 14_300, 18_000, 19_100].into_iter().enumerate() {
+            tracker.observe_at(ip(3), at(i as u64 * 100 + jitter));
+        }
+        assert_eq!(tracker.anomaly_at(ip(3), at(30_000)), None);
+    }
+
+    #[test]
+    fn stays_within_bounds() {
+        let tracker = TrafficTracker::new().max_clients(32).limits(BurstLimits { per_second: 1, per_ten_seconds: 2, per_minute: 3 });
+        let start = Instant::now();
+        for i in 0..=255 {
+            tracker.observe_at(ip(i), start + Duration::from_millis(u64::from(i)));
+        }
+        assert!(tracker.len() <= 32, "{}", tracker.len());
+        assert!(tracker.anomaly_at(ip(255), start + Duration::from_secs(1)).is_none());
+
+        for i in 0..10 {
+            tracker.observe_at(ip(255), start + Duration::from_secs(i));
+        }
+        assert_eq!(lock(tracker.shard(ip(255)))[&ip(255)].len(), 4);
+        // everything is forgotten after a minute
+        tracker.observe_at(ip(255), start + Duration::from_secs(100));
+        assert_eq!(lock(tracker.shard(ip(255)))[&ip(255)].len(), 1);
+    }
+
+    #[test]
+    fn evicts_the_oldest_in_batches() {
+        let mut seen = (0..=40).map(|i| (ip(i), 40 - u32::from(i))).collect::<HashMap<_, _>>();
+        evict_oldest(&mut seen, |seen| *seen);
+        assert_eq!(seen.len(), 39);
+        assert!(!seen.contains_key(&ip(40)) && !seen.contains_key(&ip(39)));
+        let mut single = HashMap::from([(ip(1), 1)]);
+        evict_oldest(&mut single, |seen| *seen);
+        assert!(single.is_empty());
+        evict_oldest(&mut single, |seen| *seen);
+    }
+}
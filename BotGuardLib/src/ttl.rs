@@ -0,0 +1,109 @@
+// Patterns and addresses blocked for a while, like a scraper for the next 24 hours, see
+// `BotDetector::append_ttl`.
+//
+// Temporary patterns are compiled into a group of their own and stay in its regex until they are
+// pruned, which every edit of the detector does first, as does every run of a
+// `source::PatternRefresher`. Pruning only recompiles that group. Addresses are compared with their
+// expiry on each check, so they never outlive it, IPv4-mapped IPv6 addresses as IPv4.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+/// Group the patterns appended with [`crate::BotDetector::append_ttl`] are compiled into.
+pub const TEMPORARY_GROUP: &str = "temporary";
+
+/// When the temporary patterns and blocked addresses of a detector expire.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Expiries {
+    patterns: HashMap<String, Instant>,
+    ips: HashMap<IpAddr, Instant>,
+}
+
+impl Expiries {
+    pub(crate) fn set_pattern(&mut self, pattern: String, expires: Instant) {
+        self.patterns.insert(pattern, expires);
+    }
+
+    pub(crate) fn pattern(&self, pattern: &str) -> Option<Instant> {
+        self.patterns.get(pattern).copied()
+    }
+
+    pub(crate) fn forget_patterns(&mut self, patterns: &[String]) {
+        for pattern in patterns {
+            self.patterns.remove(pattern);
+        }
+    }
+
+    pub(crate) fn block_ip(&mut self, ip: IpAddr, expires: Instant) {
+        self.ips.insert(ip.to_canonical(), expires);
+    }
+
+    pub(crate) fn unblock_ip(&mut self, ip: IpAddr) -> bool {
+        self.ips.remove(&ip.to_canonical()).is_some()
+    }
+
+    pub(crate) fn is_blocked(&self, ip: IpAddr, now: Instant) -> bool {
+        self.ips.get(&ip.to_canonical()).is_some_and(|expires| now < *expires)
+    }
+
+    /// Forgets everything expired by `now`, returns the expired patterns and the number of
+    /// expired addresses.
+    pub(crate) fn take_expired(&mut self, now: Instant) -> (Vec<String>, usize) {
+        let mut expired = Vec::new();
+        self.patterns.retain(|pattern, expires| {
+            if now < *expires {
+                return true;
+            }
+            expired.push(pattern.clone());
+            false
+        });
+        let ips = self.ips.len();
+        self.ips.retain(|_, expires| now < *expires);
+        (expired, ips - self.ips.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::BotDetector;
+
+    use super::*;
+
+    #[test]
+    fn temporary_patterns_are_pruned_once_expired() {
+        let mut detector = BotDetector::new("googlebot");
+        detector.append_ttl("^scrapy/", Duration::from_secs(3600)).unwrap();
+        detector.append_ttl("^badbot/", Duration::ZERO).unwrap();
+        assert!(detector.check_bot("Scrapy/2.11") && detector.pattern_expiry("^Scrapy/").is_some());
+        assert_eq!(detector.classify("badbot/1.0"), Some(TEMPORARY_GROUP));
+
+        assert_eq!(detector.prune_expired(), 1);
+        assert!(!detector.check_bot("badbot/1.0") && detector.pattern_expiry("^badbot/").is_none());
+        assert_eq!(detector.group_patterns(TEMPORARY_GROUP).unwrap().collect::<Vec<_>>(), vec!["^scrapy/"]);
+        assert!(detector.append_ttl("(unclosed", Duration::from_secs(60)).is_err());
+
+        detector.reload("bingbot").unwrap();
+        assert!(detector.check_bot("Scrapy/2.11") && !detector.check_bot("Googlebot/2.1"));
+        assert!(detector.rollback());
+        assert!(detector.check_bot("Scrapy/2.11") && detector.check_bot("Googlebot/2.1"));
+    }
+
+    #[test]
+    fn blocked_addresses_expire_on_their_own() {
+        let mut detector = BotDetector::new("googlebot");
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        detector.block_ip_ttl(ip, Duration::from_secs(3600));
+        assert!(detector.check_bot_from("Mozilla/5.0 (X11; Linux x86_64)", ip));
+        assert!(!detector.check_bot_from("Mozilla/5.0 (X11; Linux x86_64)", "198.51.100.1".parse().unwrap()));
+        assert!(!detector.check_bot("Mozilla/5.0 (X11; Linux x86_64)"));
+        assert!(detector.is_ip_blocked("::ffff:203.0.113.7".parse().unwrap()));
+        assert!(detector.unblock_ip(ip) && !detector.is_ip_blocked(ip));
+
+        detector.block_ip_ttl(ip, Duration::ZERO);
+        assert!(!detector.is_ip_blocked(ip));
+        assert_eq!(detector.prune_expired(), 1);
+    }
+}
@@ -0,0 +1,222 @@
+// A small user-agent parser and the contextual rules built on the parsed fields, e.g. flagging
+// outdated browsers or HTTP libraries regardless of the pattern groups.
+//
+// The parser only knows the families that matter for bot detection: the major browsers and
+// the common HTTP libraries. Everything else parses as the `Other` family.
+
+use crate::Verdict;
+
+/// HTTP libraries and SDKs, as the lowercase product token and the family name.
+const LIBRARIES: &[(&str, &str)] = &[
+    ("okhttp", "okhttp"),
+    ("axios", "axios"),
+    ("python-urllib", "Python-urllib"),
+    ("python-requests", "python-requests"),
+    ("aiohttp", "aiohttp"),
+    ("python-httpx", "httpx"),
+    ("go-http-client", "Go-http-client"),
+    ("java", "Java"),
+    ("apache-httpclient", "Apache-HttpClient"),
+    ("curl", "curl"),
+    ("wget", "Wget"),
+    ("libwww-perl", "libwww-perl"),
+    ("node-fetch", "node-fetch"),
+    ("undici", "undici"),
+    ("guzzlehttp", "Guzzle"),
+    ("dart", "Dart"),
+];
+
+/// Browsers in the order they are recognized, tokens of derived browsers come before the ones
+/// they copy, e.g. Edge sends `Chrome/` too.
+const BROWSERS: &[(&str, &str)] = &[
+    ("edg/", "Edge"),
+    ("edga/", "Edge"),
+    ("edgios/", "Edge"),
+    ("opr/", "Opera"),
+    ("samsungbrowser/", "Samsung Internet"),
+    ("firefox/", "Firefox"),
+    ("fxios/", "Firefox"),
+    ("crios/", "Chrome"),
+    ("chrome/", "Chrome"),
+    ("msie ", "IE"),
+];
+
+/// Family of user-agents the parser does not recognize.
+pub const OTHER_FAMILY: &str = "Other";
+
+/// The fields of a parsed user-agent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserAgent {
+    /// Browser or library name like `Chrome` or `okhttp`, [`OTHER_FAMILY`] if unknown.
+    pub family: &'static str,
+    pub major: Option<u32>,
+    /// Operating system like `Windows`, `macOS`, `iOS`, `Android`, `ChromeOS` or `Linux`.
+    pub os: Option<&'static str>,
+    /// Whether the family is an HTTP library rather than a browser.
+    pub library: bool,
+}
+
+impl UserAgent {
+    /// ```
+    /// use BotGuardLib::useragent::UserAgent;
+    ///
+    /// let parsed = UserAgent::parse("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0.0.0 Safari/537.36");
+    /// assert_eq!((parsed.family, parsed.major, parsed.os, parsed.library), ("Chrome", Some(122), Some("Windows"), false));
+    /// assert_eq!(UserAgent::parse("okhttp/4.9.0").family, "okhttp");
+    /// ```
+    pub fn parse(user_agent: &str) -> Self {
+        let ua = user_agent.to_ascii_lowercase();
+        let os = [
+            ("windows", "Windows"),
+            ("iphone", "iOS"),
+            ("ipad", "iOS"),
+            ("macintosh", "macOS"),
+            ("android", "Android"),
+            ("cros ", "ChromeOS"),
+            ("linux", "Linux"),
+        ]
+        .into_iter()
+        .find(|(token, _)| ua.contains(token))
+        .map(|(_, os)| os);
+
+        let product = ua.split(['/', ' ']).next().unwrap_or_default();
+        if let Some(&(_, family)) = LIBRARIES.iter().find(|(token, _)| product == *token) {
+            return UserAgent { family, major: major_after(&ua, product.len() + 1), os, library: true };
+        }
+        if !ua.starts_with("mozilla/") {
+            return UserAgent { family: OTHER_FAMILY, major: None, os, library: false };
+        }
+        let browser = BROWSERS.iter().find_map(|&(token, family)| ua.find(token).map(|at| (family, major_after(&ua, at + token.len()))));
+        let (family, major) = match browser {
+            Some(browser) => browser,
+            None if ua.contains("trident/") => ("IE", ua.find("rv:").and_then(|at| major_after(&ua, at + 3))),
+            None if ua.contains("safari/") => ("Safari", ua.find("version/").and_then(|at| major_after(&ua, at + 8))),
+            None => (OTHER_FAMILY, None),
+        };
+        UserAgent { family, major, os, library: false }
+    }
+}
+
+fn major_after(ua: &str, start: usize) -> Option<u32> {
+    let rest = ua.get(start..)?;
+    rest[..rest.bytes().take_while(u8::is_ascii_digit).count()].parse().ok()
+}
+
+/// A condition on a parsed user-agent, see [`ContextRules`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContextRule {
+    /// A browser of this family with a major version below `min_major`, e.g. a release 20
+    /// versions behind the current one.
+    Outdated { family: String, min_major: u32 },
+    /// A user-agent claiming to be a browser whose family is not recognized.
+    UnknownFamily,
+    /// A known HTTP library like okhttp, axios or python-urllib.
+    Library,
+}
+
+impl ContextRule {
+    fn holds(&self, parsed: &UserAgent, user_agent: &str) -> bool {
+        match self {
+            ContextRule::Outdated { family, min_major } => {
+                parsed.family.eq_ignore_ascii_case(family) && parsed.major.is_some_and(|major| major < *min_major)
+            }
+            ContextRule::UnknownFamily => {
+                parsed.family == OTHER_FAMILY && user_agent.get(..8).is_some_and(|prefix| prefix.eq_ignore_ascii_case("mozilla/"))
+            }
+            ContextRule::Library => parsed.library,
+        }
+    }
+}
+
+/// Rules on the parsed user-agent that raise the verdict of a check, see
+/// [`crate::BotDetectorBuilder::context_rules`].
+///
+/// ```
+/// use BotGuardLib::useragent::{ContextRule, ContextRules};
+/// use BotGuardLib::{BotDetector, Verdict};
+///
+/// let rules = ContextRules::new()
+///     .rule(ContextRule::Outdated { family: "Chrome".to_string(), min_major: 100 }, Verdict::Suspicious)
+///     .rule(ContextRule::Library, Verdict::Bot);
+/// let BotDetector = BotDetector::builder().patterns("").context_rules(rules).build().unwrap();
+///
+/// assert_eq!(BotDetector.check("axios/1.6.7"), Verdict::Bot);
+/// let checked = BotDetector.check_parsed("Mozilla/5.0 (Windows NT 6.1) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/49.0.2623.112 Safari/537.36");
+/// assert_eq!(checked.verdict, Verdict::Suspicious);
+/// assert_eq!(checked.user_agent.major, Some(49));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContextRules {
+    rules: Vec<(ContextRule, Verdict)>,
+}
+
+impl ContextRules {
+    pub fn new() -> Self {
+        ContextRules::default()
+    }
+
+    /// Adds a rule giving matching user-agents at least this verdict.
+    pub fn rule(mut self, rule: ContextRule, verdict: Verdict) -> Self {
+        self.rules.push((rule, verdict));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The strictest verdict of the rules holding for the user-agent, `None` if none holds.
+    pub fn evaluate(&self, parsed: &UserAgent, user_agent: &str) -> Option<Verdict> {
+        self.rules
+            .iter()
+            .filter(|(rule, _)| rule.holds(parsed, user_agent))
+            .map(|&(_, verdict)| verdict)
+            .max_by_key(|verdict| verdict.rank())
+    }
+}
+
+/// The verdict of [`crate::BotDetector::check_parsed`] with the user-agent it was based on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedVerdict {
+    pub verdict: Verdict,
+    pub user_agent: UserAgent,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_browsers_and_libraries() {
+        let cases = [
+            ("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Safari/605.1.15", "Safari", Some(17), Some("macOS")),
+            ("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0", "Firefox", Some(120), Some("Linux")),
+            ("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/122.0.0.0 Safari/537.36 Edg/122.0.2365.66", "Edge", Some(122), Some("Windows")),
+            ("Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) CriOS/122.0.6261.89 Mobile/15E148 Safari/604.1", "Chrome", Some(122), Some("iOS")),
+            ("Mozilla/5.0 (Windows NT 6.1; Trident/7.0; rv:11.0) like Gecko", "IE", Some(11), Some("Windows")),
+            ("Python-urllib/3.11", "Python-urllib", Some(3), None),
+            ("Go-http-client/1.1", "Go-http-client", Some(1), None),
+            ("Mozilla/5.0 (compatible; Unknown/1.0)", OTHER_FAMILY, None, None),
+            ("SomeApp/2.0 (Linux; Android 14)", OTHER_FAMILY, None, Some("Android")),
+        ];
+        for (ua, family, major, os) in cases {
+            let parsed = UserAgent::parse(ua);
+            assert_eq!((parsed.family, parsed.major, parsed.os), (family, major, os), "{}", ua);
+        }
+        assert!(UserAgent::parse("Java/17.0.2").library);
+    }
+
+    #[test]
+    fn strictest_rule_wins() {
+        let rules = ContextRules::new()
+            .rule(ContextRule::UnknownFamily, Verdict::Suspicious)
+            .rule(ContextRule::Library, Verdict::Bot)
+            .rule(ContextRule::Outdated { family: "firefox".to_string(), min_major: 100 }, Verdict::Suspicious);
+        let evaluate = |ua: &str| rules.evaluate(&UserAgent::parse(ua), ua);
+        assert_eq!(evaluate("Mozilla/5.0 (compatible; Unknown/1.0)"), Some(Verdict::Suspicious));
+        assert_eq!(evaluate("SomeApp/2.0"), None);
+        assert_eq!(evaluate("curl/8.0"), Some(Verdict::Bot));
+        assert_eq!(evaluate("Mozilla/5.0 (X11; rv:52.0) Gecko/20100101 Firefox/52.0"), Some(Verdict::Suspicious));
+        assert_eq!(evaluate("Mozilla/5.0 (X11; rv:120.0) Gecko/20100101 Firefox/120.0"), None);
+    }
+}
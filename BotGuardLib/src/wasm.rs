@@ -0,0 +1,138 @@
+// Raw WebAssembly exports for edge runtimes such as Cloudflare Workers and Fastly Compute.
+//
+// Build with `cargo rustc --release --lib --crate-type cdylib --target wasm32-unknown-unknown
+// --no-default-features --features wasm,include-default-BotDetector` and load `BotGuardLib.wasm` with
+// `bindings/wasm/botguard.mjs`. The exports take UTF-8 strings as pointer and length into the
+// module memory, allocated with `botguard_wasm_alloc`, so no wasm-bindgen glue is needed.
+//
+// The module holds a single detector, created with the bundled patterns on first use. Everything
+// touching files, sockets or threads (configuration files, feeds, the refresher, webhooks) returns
+// an error on `wasm32-unknown-unknown`, fetch patterns in JavaScript and pass them to
+// `botguard_wasm_load` instead. `regex-lite` is not an option, the bot database needs `RegexSet`.
+
+use std::sync::Mutex;
+
+use crate::{BotDetector, Verdict};
+
+static DETECTOR: Mutex<Option<BotDetector>> = Mutex::new(None);
+
+fn with_detector<T>(f: impl FnOnce(&mut BotDetector) -> T) -> T {
+    let mut detector = DETECTOR.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(detector.get_or_insert_with(BotDetector::default))
+}
+
+/// # Safety
+///
+/// `ptr` must point to `len` readable bytes, or `len` must be 0.
+unsafe fn read_str<'a>(ptr: *const u8, len: usize) -> Option<&'a str> {
+    if len == 0 {
+        return Some("");
+    }
+    std::str::from_utf8(std::slice::from_raw_parts(ptr, len)).ok()
+}
+
+/// Allocates `len` bytes for passing a string in, free them with [`botguard_wasm_dealloc`].
+#[no_mangle]
+pub extern "C" fn botguard_wasm_alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// # Safety
+///
+/// `ptr` must come from [`botguard_wasm_alloc`] called with the same `len`.
+#[no_mangle]
+pub unsafe extern "C" fn botguard_wasm_dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// Replaces the patterns, in the format of [`BotDetector::new`]. Returns 0 on success and -1 if
+/// the patterns are invalid, the previous ones stay in place then.
+///
+/// # Safety
+///
+/// `ptr` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn botguard_wasm_load(ptr: *const u8, len: usize) -> i32 {
+    match read_str(ptr, len) {
+        Some(patterns) => with_detector(|detector| detector.reload(patterns)).map_or(-1, |()| 0),
+        None => -1,
+    }
+}
+
+/// Returns 0 for humans, 1 for suspicious clients and 2 for bots, see [`BotDetector::check`].
+/// User-agents that are not UTF-8 count as suspicious.
+///
+/// # Safety
+///
+/// `ptr` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn botguard_wasm_check(ptr: *const u8, len: usize) -> u32 {
+    let verdict = match read_str(ptr, len) {
+        Some(user_agent) => with_detector(|detector| detector.check(user_agent)),
+        None => Verdict::Suspicious,
+    };
+    match verdict {
+        Verdict::Human => 0,
+        Verdict::Suspicious => 1,
+        Verdict::Bot => 2,
+    }
+}
+
+/// Writes the group of the matching pattern to `out`, see [`BotDetector::classify`].
+///
+/// Returns the length of the name, or -1 if the user-agent is no bot. Names longer than
+/// `out_len` are cut, group names are ASCII.
+///
+/// # Safety
+///
+/// `ptr` must point to `len` readable bytes and `out` to `out_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn botguard_wasm_classify(ptr: *const u8, len: usize, out: *mut u8, out_len: usize) -> i32 {
+    let Some(user_agent) = read_str(ptr, len) else { return -1 };
+    with_detector(|detector| match detector.classify(user_agent) {
+        Some(group) => {
+            let written = group.len().min(out_len);
+            std::ptr::copy_nonoverlapping(group.as_ptr(), out, written);
+            written as i32
+        }
+        None => -1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_check_classify_and_load() {
+        let check = |ua: &str| unsafe { botguard_wasm_check(ua.as_ptr(), ua.len()) };
+        let mut out = [0u8; 32];
+        let classify = |ua: &str, out: &mut [u8]| unsafe { botguard_wasm_classify(ua.as_ptr(), ua.len(), out.as_mut_ptr(), out.len()) };
+
+        let patterns = "[edge-test]\nedgebot";
+        assert_eq!(unsafe { botguard_wasm_load(patterns.as_ptr(), patterns.len()) }, 0);
+        assert_eq!(check("EdgeBot/1.0"), 2);
+        assert_eq!(check("Mozilla/5.0"), 0);
+        assert_eq!(unsafe { botguard_wasm_check([0xff].as_ptr(), 1) }, 1);
+        assert_eq!(classify("EdgeBot/1.0", &mut out), 9);
+        assert_eq!(&out[..9], b"edge-test");
+        assert_eq!(classify("EdgeBot/1.0", &mut out[..4]), 4);
+        assert_eq!(classify("Mozilla/5.0", &mut out), -1);
+
+        assert_eq!(unsafe { botguard_wasm_load("(open".as_ptr(), 5) }, -1);
+        assert_eq!(check("EdgeBot/1.0"), 2);
+    }
+
+    #[test]
+    fn allocates_buffers() {
+        let ptr = botguard_wasm_alloc(16);
+        unsafe {
+            std::ptr::copy_nonoverlapping(b"curl/8.0".as_ptr(), ptr, 8);
+            assert_eq!(read_str(ptr, 8), Some("curl/8.0"));
+            botguard_wasm_dealloc(ptr, 16);
+        }
+    }
+}